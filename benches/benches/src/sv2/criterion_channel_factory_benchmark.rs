@@ -0,0 +1,124 @@
+use binary_sv2::{Sv2Option, U256};
+use criterion::{black_box, Criterion};
+use mining_sv2::{
+    ExtendedExtranonce, NewExtendedMiningJob, SetNewPrevHash, SubmitSharesStandard, Target,
+};
+use roles_logic_sv2::{
+    channel_logic::channel_factory::{ExtendedChannelKind, ProxyExtendedChannelFactory},
+    parsers::Mining,
+    utils::{GroupId, Mutex},
+};
+use std::{convert::TryInto, sync::Arc};
+
+// Coinbase with the extranonce pushed in the script sig
+fn coinbase(extranonce_len: u8) -> (Vec<u8>, Vec<u8>) {
+    let mut prefix = vec![1, 0, 0, 0, 1];
+    prefix.extend_from_slice(&[0; 32]);
+    prefix.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, extranonce_len + 1, extranonce_len]);
+    let mut suffix = vec![0xff, 0xff, 0xff, 0xff, 1];
+    suffix.extend_from_slice(&5_000_000_000_u64.to_le_bytes());
+    suffix.extend_from_slice(&[1, 0x51, 0, 0, 0, 0]);
+    (prefix, suffix)
+}
+
+fn extended_job(job_id: u32, future: bool) -> NewExtendedMiningJob<'static> {
+    let (prefix, suffix) = coinbase(16);
+    // A block with ~4000 transactions
+    let merkle_path: Vec<U256> = (0..12_u8).map(|i| [i; 32].into()).collect();
+    NewExtendedMiningJob {
+        channel_id: 1,
+        job_id,
+        min_ntime: Sv2Option::new(if future { None } else { Some(0) }),
+        version: 0x2000_0000,
+        version_rolling_allowed: true,
+        merkle_path: merkle_path.into(),
+        coinbase_tx_prefix: prefix.try_into().unwrap(),
+        coinbase_tx_suffix: suffix.try_into().unwrap(),
+    }
+}
+
+// A proxy factory with `channels` HOM channels, from 1 to `channels`, and an active job
+fn factory_with_hom_channels(channels: u32) -> ProxyExtendedChannelFactory {
+    let upstream_target: Target = [255; 32].into();
+    let mut factory = ProxyExtendedChannelFactory::new(
+        Arc::new(Mutex::new(GroupId::new())),
+        ExtendedExtranonce::new(0..0, 0..8, 8..16),
+        None,
+        1.0,
+        ExtendedChannelKind::Proxy { upstream_target },
+        None,
+        "".to_string(),
+        1,
+    );
+    for id in 1..=channels {
+        factory
+            .add_standard_channel(id, 1_000_000_000.0, true, id)
+            .unwrap();
+    }
+    factory
+        .on_new_extended_mining_job(extended_job(1, true))
+        .unwrap();
+    factory
+        .on_new_prev_hash(SetNewPrevHash {
+            channel_id: 1,
+            job_id: 1,
+            prev_hash: [0; 32].into(),
+            min_ntime: 0,
+            nbits: 0x1d00ffff,
+        })
+        .unwrap();
+    factory
+}
+
+fn channel_factory_new_extended_job(c: &mut Criterion) {
+    for channels in [1, 100, 1000] {
+        let name = format!("channel_factory_new_extended_job_{}_hom_channels", channels);
+        c.bench_function(&name, |b| {
+            let mut factory = factory_with_hom_channels(channels);
+            let mut job_id = 1;
+            b.iter(|| {
+                job_id += 1;
+                black_box(factory.on_new_extended_mining_job(extended_job(job_id, false)))
+            });
+        });
+    }
+}
+
+fn share(job_id: u32) -> SubmitSharesStandard {
+    SubmitSharesStandard {
+        channel_id: 1,
+        sequence_number: 0,
+        job_id,
+        nonce: 0,
+        ntime: 0,
+        version: 0x2000_0000,
+    }
+}
+
+fn channel_factory_submit_standard_share(c: &mut Criterion) {
+    let mut factory = factory_with_hom_channels(1);
+    let jobs = factory
+        .on_new_extended_mining_job(extended_job(2, false))
+        .unwrap();
+    let job_id = match &jobs[&1] {
+        Mining::NewMiningJob(job) => job.job_id,
+        _ => panic!(),
+    };
+    c.bench_function("channel_factory_submit_standard_share", |b| {
+        b.iter(|| black_box(factory.on_submit_shares_standard(share(job_id))));
+    });
+    // The job id is not a standard job of the channel, the merkle root is computed from the
+    // coinbase as for a channel that is not header only
+    c.bench_function("channel_factory_submit_standard_share_not_cached", |b| {
+        b.iter(|| black_box(factory.on_submit_shares_standard(share(u32::MAX))));
+    });
+}
+
+fn main() {
+    let mut criterion = Criterion::default()
+        .sample_size(100)
+        .measurement_time(std::time::Duration::from_secs(5));
+    channel_factory_new_extended_job(&mut criterion);
+    channel_factory_submit_standard_share(&mut criterion);
+    criterion.final_summary();
+}
@@ -0,0 +1,292 @@
+use binary_sv2::{from_bytes, to_bytes, Seq064K, ShortTxId, Signature, Sv2Option, U256};
+use codec_sv2::{
+    Encoder, Error, HandshakeRole, Initiator, NoiseEncoder, Responder, StandardDecoder,
+    StandardEitherFrame, StandardNoiseDecoder, StandardSv2Frame, State,
+};
+use criterion::{black_box, BatchSize, Criterion, Throughput};
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use roles_logic_sv2::{
+    job_declaration_sv2::DeclareMiningJob,
+    mining_sv2::{NewExtendedMiningJob, SubmitSharesExtended},
+    parsers::{JobDeclaration, Mining, PoolMessages},
+};
+use std::convert::{TryFrom, TryInto};
+
+pub type Message = PoolMessages<'static>;
+pub type StdFrame = StandardSv2Frame<Message>;
+pub type EitherFrame = StandardEitherFrame<Message>;
+
+const AUTHORITY_PUBLIC_K: &str = "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72";
+const AUTHORITY_PRIVATE_K: &str = "mkDLTBBRxdBv998612qipDYoTK3YUrqLe8uWw7gu3iXbSrn2n";
+
+fn new_extended_mining_job() -> NewExtendedMiningJob<'static> {
+    // 12 levels of merkle path, a block with ~4000 transactions
+    let merkle_path: Vec<U256> = (0..12_u8).map(|i| [i; 32].into()).collect();
+    NewExtendedMiningJob {
+        channel_id: 1,
+        job_id: 1,
+        min_ntime: Sv2Option::new(Some(1_700_000_000)),
+        version: 0x2000_0000,
+        version_rolling_allowed: true,
+        merkle_path: merkle_path.into(),
+        coinbase_tx_prefix: vec![1; 43].try_into().unwrap(),
+        coinbase_tx_suffix: vec![2; 110].try_into().unwrap(),
+    }
+}
+
+fn submit_shares_extended() -> SubmitSharesExtended<'static> {
+    SubmitSharesExtended {
+        channel_id: 1,
+        sequence_number: 42,
+        job_id: 1,
+        nonce: 0xdead_beef,
+        ntime: 1_700_000_000,
+        version: 0x2000_0000,
+        extranonce: vec![3; 16].try_into().unwrap(),
+    }
+}
+
+fn declare_mining_job() -> DeclareMiningJob<'static> {
+    let short_ids: Vec<ShortTxId> = (0..2000_u32)
+        .map(|i| {
+            let mut id = i.to_le_bytes().to_vec();
+            id.extend_from_slice(&[0, 0]);
+            id.try_into().unwrap()
+        })
+        .collect();
+    DeclareMiningJob {
+        request_id: 1,
+        mining_job_token: vec![4; 32].try_into().unwrap(),
+        version: 0x2000_0000,
+        coinbase_prefix: vec![1; 43].try_into().unwrap(),
+        coinbase_suffix: vec![2; 110].try_into().unwrap(),
+        tx_short_hash_nonce: 0x0123_4567_89ab_cdef,
+        tx_short_hash_list: Seq064K::new(short_ids).unwrap(),
+        tx_hash_list_hash: [5; 32].into(),
+        excess_data: vec![].try_into().unwrap(),
+    }
+}
+
+fn messages() -> Vec<(&'static str, Message)> {
+    vec![
+        (
+            "new_extended_mining_job",
+            PoolMessages::Mining(Mining::NewExtendedMiningJob(new_extended_mining_job())),
+        ),
+        (
+            "submit_shares_extended",
+            PoolMessages::Mining(Mining::SubmitSharesExtended(submit_shares_extended())),
+        ),
+        (
+            "declare_mining_job",
+            PoolMessages::JobDeclaration(JobDeclaration::DeclareMiningJob(declare_mining_job())),
+        ),
+    ]
+}
+
+fn sv2_codec_to_bytes_from_bytes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sv2_codec");
+    let job = new_extended_mining_job();
+    let mut job_bytes = to_bytes(job.clone()).unwrap();
+    group.throughput(Throughput::Bytes(job_bytes.len() as u64));
+    group.bench_function("new_extended_mining_job_to_bytes", |b| {
+        b.iter(|| black_box(to_bytes(job.clone())));
+    });
+    group.bench_function("new_extended_mining_job_from_bytes", |b| {
+        b.iter(|| black_box(from_bytes::<NewExtendedMiningJob>(&mut job_bytes[..]).is_ok()));
+    });
+
+    let share = submit_shares_extended();
+    let mut share_bytes = to_bytes(share.clone()).unwrap();
+    group.throughput(Throughput::Bytes(share_bytes.len() as u64));
+    group.bench_function("submit_shares_extended_to_bytes", |b| {
+        b.iter(|| black_box(to_bytes(share.clone())));
+    });
+    group.bench_function("submit_shares_extended_from_bytes", |b| {
+        b.iter(|| black_box(from_bytes::<SubmitSharesExtended>(&mut share_bytes[..]).is_ok()));
+    });
+
+    let declare = declare_mining_job();
+    let mut declare_bytes = to_bytes(declare.clone()).unwrap();
+    group.throughput(Throughput::Bytes(declare_bytes.len() as u64));
+    group.bench_function("declare_mining_job_to_bytes", |b| {
+        b.iter(|| black_box(to_bytes(declare.clone())));
+    });
+    group.bench_function("declare_mining_job_from_bytes", |b| {
+        b.iter(|| black_box(from_bytes::<DeclareMiningJob>(&mut declare_bytes[..]).is_ok()));
+    });
+    group.finish();
+}
+
+// Sequences of fixed size primitives, where the codec time is mostly the copy of each element
+fn sv2_fixed_size_primitives(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sv2_primitives");
+    let u256s: Vec<U256> = (0..1000_u32).map(|i| [i as u8; 32].into()).collect();
+    let u256s = Seq064K::new(u256s).unwrap();
+    let mut u256_bytes = to_bytes(u256s.clone()).unwrap();
+    group.throughput(Throughput::Bytes(u256_bytes.len() as u64));
+    group.bench_function("u256_seq_to_bytes", |b| {
+        b.iter(|| black_box(to_bytes(u256s.clone())));
+    });
+    group.bench_function("u256_seq_from_bytes", |b| {
+        b.iter(|| black_box(from_bytes::<Seq064K<U256>>(&mut u256_bytes[..]).is_ok()));
+    });
+
+    let signatures: Vec<Signature> = (0..1000_u32)
+        .map(|i| vec![i as u8; 64].try_into().unwrap())
+        .collect();
+    let signatures = Seq064K::new(signatures).unwrap();
+    let mut signature_bytes = to_bytes(signatures.clone()).unwrap();
+    group.throughput(Throughput::Bytes(signature_bytes.len() as u64));
+    group.bench_function("signature_seq_to_bytes", |b| {
+        b.iter(|| black_box(to_bytes(signatures.clone())));
+    });
+    group.bench_function("signature_seq_from_bytes", |b| {
+        b.iter(|| black_box(from_bytes::<Seq064K<Signature>>(&mut signature_bytes[..]).is_ok()));
+    });
+    group.finish();
+}
+
+fn frame(message: Message) -> StdFrame {
+    message.try_into().unwrap()
+}
+
+// Copies `bytes` in the decoder as a socket would and returns the frame once complete
+fn decode(decoder: &mut StandardDecoder<Message>, bytes: &[u8]) -> StdFrame {
+    let mut read = 0;
+    loop {
+        let writable = decoder.writable();
+        let len = writable.len();
+        writable.copy_from_slice(&bytes[read..read + len]);
+        read += len;
+        match decoder.next_frame() {
+            Ok(frame) => return frame,
+            Err(Error::MissingBytes(_)) => (),
+            Err(e) => panic!("{:?}", e),
+        }
+    }
+}
+
+fn sv2_frame_parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sv2_frame");
+    for (name, message) in messages() {
+        let mut encoder = Encoder::<Message>::new();
+        let bytes = encoder.encode(frame(message)).unwrap().to_vec();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_function(format!("{}_parse", name), |b| {
+            let mut decoder = StandardDecoder::<Message>::new();
+            b.iter(|| {
+                let mut frame = decode(&mut decoder, &bytes);
+                let message_type = frame.get_header().unwrap().msg_type();
+                black_box(PoolMessages::try_from((message_type, frame.payload())).is_ok())
+            });
+        });
+    }
+    group.finish();
+}
+
+// Initiator and responder states after the handshake
+fn noise_states() -> (State, State) {
+    let public_key: Secp256k1PublicKey = AUTHORITY_PUBLIC_K.to_string().try_into().unwrap();
+    let secret_key: Secp256k1SecretKey = AUTHORITY_PRIVATE_K.to_string().try_into().unwrap();
+    let initiator = Initiator::from_raw_k(public_key.into_bytes()).unwrap();
+    let responder = Responder::from_authority_kp(
+        &public_key.into_bytes(),
+        &secret_key.into_bytes(),
+        std::time::Duration::from_secs(3600),
+    )
+    .unwrap();
+    let mut initiator = State::initialized(HandshakeRole::Initiator(initiator));
+    let mut responder = State::initialized(HandshakeRole::Responder(responder));
+    let first_message = initiator.step_0().unwrap();
+    let (second_message, responder) = responder
+        .step_1(
+            first_message
+                .get_payload_when_handshaking()
+                .try_into()
+                .unwrap(),
+        )
+        .unwrap();
+    let initiator = initiator
+        .step_2(
+            second_message
+                .get_payload_when_handshaking()
+                .try_into()
+                .unwrap(),
+        )
+        .unwrap();
+    match (initiator, responder) {
+        (State::Transport(initiator), State::Transport(responder)) => (
+            State::with_transport_mode(initiator),
+            State::with_transport_mode(responder),
+        ),
+        _ => panic!("Handshake not completed"),
+    }
+}
+
+fn sv2_noise_encrypt_decrypt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sv2_noise");
+    for (name, message) in messages() {
+        let (mut initiator, _) = noise_states();
+        let frame = frame(message);
+        let mut encoder = NoiseEncoder::<Message>::new();
+        let len = encoder
+            .encode(EitherFrame::from(frame.clone()), &mut initiator)
+            .unwrap()
+            .as_slice()
+            .len();
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_function(format!("{}_encrypt", name), |b| {
+            b.iter(|| {
+                black_box(
+                    encoder
+                        .encode(EitherFrame::from(frame.clone()), &mut initiator)
+                        .is_ok(),
+                )
+            });
+        });
+
+        // The frames are decrypted in the same order they are encrypted, as the nonces of the two
+        // sides must match
+        let (mut initiator, mut responder) = noise_states();
+        let mut decoder = StandardNoiseDecoder::<Message>::new();
+        group.bench_function(format!("{}_decrypt", name), |b| {
+            b.iter_batched(
+                || {
+                    encoder
+                        .encode(EitherFrame::from(frame.clone()), &mut initiator)
+                        .unwrap()
+                        .as_slice()
+                        .to_vec()
+                },
+                |bytes| {
+                    let mut read = 0;
+                    loop {
+                        let writable = decoder.writable();
+                        let len = writable.len();
+                        writable.copy_from_slice(&bytes[read..read + len]);
+                        read += len;
+                        match decoder.next_frame(&mut responder) {
+                            Ok(frame) => break black_box(frame),
+                            Err(Error::MissingBytes(_)) => (),
+                            Err(e) => panic!("{:?}", e),
+                        }
+                    }
+                },
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn main() {
+    let mut criterion = Criterion::default()
+        .sample_size(100)
+        .measurement_time(std::time::Duration::from_secs(5));
+    sv2_codec_to_bytes_from_bytes(&mut criterion);
+    sv2_fixed_size_primitives(&mut criterion);
+    sv2_frame_parsing(&mut criterion);
+    sv2_noise_encrypt_decrypt(&mut criterion);
+    criterion.final_summary();
+}
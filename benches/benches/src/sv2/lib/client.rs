@@ -7,7 +7,9 @@ use async_std::channel::unbounded;
 use binary_sv2::u256_from_int;
 use codec_sv2::{buffer_sv2::Slice, StandardEitherFrame, StandardSv2Frame};
 use roles_logic_sv2::{
-    common_messages_sv2::{Protocol, SetupConnection, SetupConnectionSuccess},
+    common_messages_sv2::{
+        DeviceInfo, Endpoint, Protocol, SetupConnection, SetupConnectionSuccess,
+    },
     common_properties::{IsMiningUpstream, IsUpstream},
     errors::Error,
     handlers::{
@@ -53,23 +55,14 @@ use std::convert::TryInto;
 
 impl SetupConnectionHandler {
     pub fn get_setup_connection_message(address: SocketAddr) -> SetupConnection<'static> {
-        let endpoint_host = address.ip().to_string().into_bytes().try_into().unwrap();
-        let vendor = String::new().try_into().unwrap();
-        let hardware_version = String::new().try_into().unwrap();
-        let firmware = String::new().try_into().unwrap();
-        let device_id = String::new().try_into().unwrap();
-        SetupConnection {
-            protocol: Protocol::MiningProtocol,
-            min_version: 2,
-            max_version: 2,
-            flags: 0b0000_0000_0000_0000_0000_0000_0000_0001,
-            endpoint_host,
-            endpoint_port: address.port(),
-            vendor,
-            hardware_version,
-            firmware,
-            device_id,
-        }
+        let endpoint = Endpoint::new(&address.ip().to_string(), address.port()).unwrap();
+        let mut setup_connection = SetupConnection::for_protocol(
+            Protocol::MiningProtocol,
+            DeviceInfo::default(),
+            endpoint,
+        );
+        setup_connection.flags = 0b0000_0000_0000_0000_0000_0000_0000_0001;
+        setup_connection
     }
 }
 
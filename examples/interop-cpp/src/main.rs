@@ -13,7 +13,9 @@ mod main_ {
 #[cfg(not(feature = "with_serde"))]
 mod main_ {
     use codec_sv2::{Encoder, StandardDecoder, StandardSv2Frame};
-    use common_messages_sv2::{Protocol, SetupConnection, SetupConnectionError};
+    use common_messages_sv2::{
+        DeviceInfoBuilder, Endpoint, Protocol, SetupConnection, SetupConnectionError,
+    };
     use const_sv2::{
         CHANNEL_BIT_SETUP_CONNECTION, MESSAGE_TYPE_SETUP_CONNECTION,
         MESSAGE_TYPE_SETUP_CONNECTION_ERROR,
@@ -87,18 +89,17 @@ mod main_ {
     pub fn main() -> Result<(), std::io::Error> {
         let mut encoder = Encoder::<SetupConnection>::new();
 
-        let setup_connection = SetupConnection {
-            protocol: Protocol::TemplateDistributionProtocol,
-            min_version: 2,
-            max_version: 2,
-            flags: 0,
-            endpoint_host: "0.0.0.0".to_string().into_bytes().try_into().unwrap(),
-            endpoint_port: 8081,
-            vendor: "Bitmain".to_string().into_bytes().try_into().unwrap(),
-            hardware_version: "901".to_string().into_bytes().try_into().unwrap(),
-            firmware: "abcX".to_string().into_bytes().try_into().unwrap(),
-            device_id: "89567".to_string().into_bytes().try_into().unwrap(),
-        };
+        let device_info = DeviceInfoBuilder::new("Bitmain")
+            .hardware_version("901")
+            .firmware("abcX")
+            .device_id("89567")
+            .build()
+            .unwrap();
+        let setup_connection = SetupConnection::for_protocol(
+            Protocol::TemplateDistributionProtocol,
+            device_info,
+            Endpoint::new("0.0.0.0", 8081).unwrap(),
+        );
 
         let setup_connection = StandardSv2Frame::from_message(
             setup_connection,
@@ -40,7 +40,9 @@
 pub mod error;
 pub mod json_rpc;
 pub mod methods;
+pub mod session;
 pub mod utils;
+pub mod validation;
 
 use std::convert::{TryFrom, TryInto};
 use tracing::debug;
@@ -49,7 +51,9 @@ use tracing::debug;
 use error::Error;
 pub use json_rpc::Message;
 pub use methods::{client_to_server, server_to_client, Method, MethodError, ParsingMethodError};
+pub use session::{Sv1ClientSession, Sv1ServerSession};
 use utils::{Extranonce, HexU32Be};
+pub use validation::{Policy, PolicyTable};
 
 /// json_rpc Response are not handled cause stratum v1 does not have any request from a server to a
 /// client
@@ -71,12 +75,40 @@ pub trait IsServer<'a> {
     {
         // Server shoudln't receive json_rpc responses
         if msg.is_response() {
-            Err(Error::InvalidJsonRpcMessageKind)
-        } else {
-            self.handle_request(msg.try_into()?)
+            return Err(Error::InvalidJsonRpcMessageKind);
+        }
+        let mut validated = false;
+        if let Some(policies) = self.policy_table() {
+            if let Err(response) = policies.validate(&msg) {
+                return Ok(Some(response));
+            }
+            if let Message::StandardRequest(request) = &msg {
+                validated = policies.policy(&request.method) != Policy::Off;
+            }
+        }
+        match msg.try_into() {
+            Ok(request) => self.handle_request(request),
+            // A request with params of the right types can still fail to parse, e.g. when the
+            // extranonce is too long
+            Err(MethodError::ParsingMethodError((e, Message::StandardRequest(request))))
+                if validated =>
+            {
+                Ok(Some(validation::error_response(
+                    request.id,
+                    validation::INVALID_PARAMS,
+                    format!("Invalid params: {:?}", e),
+                )))
+            }
+            Err(e) => Err(e.into()),
         }
     }
 
+    /// Checks of the received requests, the invalid ones are answered with a JSON-RPC error. With
+    /// `None` nothing is checked and a request that can not be parsed is an error.
+    fn policy_table(&self) -> Option<&PolicyTable> {
+        None
+    }
+
     /// Call the right handler according with the called method
     fn handle_request(
         &mut self,
@@ -0,0 +1,373 @@
+//! Optional bookkeeping of a stratum v1 session.
+//!
+//! [`IsServer`] and [`IsClient`] leave to the implementor the state of the session: subscriptions,
+//! extranonces, authorized workers, version rolling, last job and difficulty. [`Sv1ServerSession`]
+//! and [`Sv1ClientSession`] track it, so that an implementor can store one of them and delegate
+//! the getters and the setters of the traits to it.
+//!
+//! [`IsServer`]: crate::IsServer
+//! [`IsClient`]: crate::IsClient
+use crate::{
+    client_to_server,
+    error::Error,
+    server_to_client,
+    utils::{Extranonce, HexU32Be},
+    ClientStatus,
+};
+use std::collections::HashMap;
+
+/// State of the session of a server with one client
+#[derive(Debug, Clone, Default)]
+pub struct Sv1ServerSession<'a> {
+    subscriptions: Vec<(String, String)>,
+    extranonce1: Option<Extranonce<'a>>,
+    extranonce2_size: usize,
+    authorized_workers: Vec<String>,
+    version_rolling_mask: Option<HexU32Be>,
+    version_rolling_min_bit: Option<HexU32Be>,
+    last_job_id: Option<String>,
+    difficulty: Option<f64>,
+    extranonce_subscribed: bool,
+}
+
+impl<'a> Sv1ServerSession<'a> {
+    /// A session where the client will get `extranonce1` and has to roll `extranonce2_size`
+    /// bytes
+    pub fn new(extranonce1: Extranonce<'a>, extranonce2_size: usize) -> Self {
+        Self {
+            extranonce1: Some(extranonce1),
+            extranonce2_size,
+            ..Default::default()
+        }
+    }
+
+    /// Records the subscriptions sent in the `mining.subscribe` response
+    pub fn subscribe(&mut self, subscriptions: Vec<(String, String)>) {
+        self.subscriptions = subscriptions;
+    }
+
+    pub fn subscriptions(&self) -> &[(String, String)] {
+        &self.subscriptions
+    }
+
+    pub fn is_subscribed(&self) -> bool {
+        !self.subscriptions.is_empty()
+    }
+
+    pub fn extranonce1(&self) -> Option<&Extranonce<'a>> {
+        self.extranonce1.as_ref()
+    }
+
+    pub fn extranonce2_size(&self) -> usize {
+        self.extranonce2_size
+    }
+
+    /// Changes the extranonces, e.g. before sending `mining.set_extranonce`
+    pub fn set_extranonce(&mut self, extranonce1: Extranonce<'a>, extranonce2_size: usize) {
+        self.extranonce1 = Some(extranonce1);
+        self.extranonce2_size = extranonce2_size;
+    }
+
+    /// The client sent `mining.extranonce.subscribe`, it accepts `mining.set_extranonce`
+    pub fn extranonce_subscribe(&mut self) {
+        self.extranonce_subscribed = true;
+    }
+
+    pub fn is_extranonce_subscribed(&self) -> bool {
+        self.extranonce_subscribed
+    }
+
+    pub fn authorize(&mut self, worker: &str) {
+        if !self.is_authorized(worker) {
+            self.authorized_workers.push(worker.to_string());
+        }
+    }
+
+    pub fn is_authorized(&self, worker: &str) -> bool {
+        self.authorized_workers.iter().any(|w| w == worker)
+    }
+
+    pub fn authorized_workers(&self) -> &[String] {
+        &self.authorized_workers
+    }
+
+    pub fn set_version_rolling_mask(&mut self, mask: Option<HexU32Be>) {
+        self.version_rolling_mask = mask;
+    }
+
+    pub fn version_rolling_mask(&self) -> Option<HexU32Be> {
+        self.version_rolling_mask.clone()
+    }
+
+    pub fn set_version_rolling_min_bit(&mut self, min_bit: Option<HexU32Be>) {
+        self.version_rolling_min_bit = min_bit;
+    }
+
+    pub fn version_rolling_min_bit(&self) -> Option<HexU32Be> {
+        self.version_rolling_min_bit.clone()
+    }
+
+    /// Records the job sent in a `mining.notify`, only shares for this job are valid
+    pub fn notify(&mut self, notify: &server_to_client::Notify) {
+        self.last_job_id = Some(notify.job_id.clone());
+    }
+
+    pub fn last_job_id(&self) -> Option<&str> {
+        self.last_job_id.as_deref()
+    }
+
+    /// Records the difficulty sent in a `mining.set_difficulty`
+    pub fn set_difficulty(&mut self, difficulty: f64) {
+        self.difficulty = Some(difficulty);
+    }
+
+    pub fn difficulty(&self) -> Option<f64> {
+        self.difficulty
+    }
+
+    /// Checks that the share is for the last job, from an authorized worker, with an extranonce2
+    /// of the right size and version bits inside the negotiated mask
+    #[allow(clippy::result_large_err)]
+    pub fn check_submit(&self, submit: &client_to_server::Submit) -> Result<(), Error<'static>> {
+        if !self.is_authorized(&submit.user_name) {
+            return Err(Error::UnauthorizedClient(submit.user_name.clone()));
+        }
+        let valid_version_bits = match (&submit.version_bits, &self.version_rolling_mask) {
+            (Some(bits), Some(mask)) => mask.check_mask(bits),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        if submit.extra_nonce2.len() != self.extranonce2_size
+            || self.last_job_id.as_deref() != Some(submit.job_id.as_str())
+            || !valid_version_bits
+        {
+            return Err(Error::InvalidSubmission);
+        }
+        Ok(())
+    }
+}
+
+/// State of the session of a client with a server
+#[derive(Debug, Clone)]
+pub struct Sv1ClientSession<'a> {
+    status: ClientStatus,
+    subscriptions: Vec<(String, String)>,
+    extranonce1: Option<Extranonce<'a>>,
+    extranonce2_size: usize,
+    authorized_workers: Vec<String>,
+    // id of the `mining.authorize` requests waiting for a response -> worker
+    pending_authorize: HashMap<u64, String>,
+    // id of the `mining.submit` requests waiting for a response
+    pending_submit: Vec<u64>,
+    version_rolling_mask: Option<HexU32Be>,
+    version_rolling_min_bit: Option<HexU32Be>,
+    last_notify: Option<server_to_client::Notify<'a>>,
+    difficulty: Option<f64>,
+}
+
+impl<'a> Default for Sv1ClientSession<'a> {
+    fn default() -> Self {
+        Self {
+            status: ClientStatus::Init,
+            subscriptions: vec![],
+            extranonce1: None,
+            extranonce2_size: 0,
+            authorized_workers: vec![],
+            pending_authorize: HashMap::new(),
+            pending_submit: vec![],
+            version_rolling_mask: None,
+            version_rolling_min_bit: None,
+            last_notify: None,
+            difficulty: None,
+        }
+    }
+}
+
+impl<'a> Sv1ClientSession<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn status(&self) -> ClientStatus {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: ClientStatus) {
+        self.status = status;
+    }
+
+    /// Records the `mining.subscribe` response and moves to [`ClientStatus::Subscribed`]
+    pub fn subscribed(&mut self, subscribe: &server_to_client::Subscribe<'a>) {
+        self.subscriptions = subscribe.subscriptions.clone();
+        self.extranonce1 = Some(subscribe.extra_nonce1.clone());
+        self.extranonce2_size = subscribe.extra_nonce2_size;
+        self.status = ClientStatus::Subscribed;
+    }
+
+    pub fn subscriptions(&self) -> &[(String, String)] {
+        &self.subscriptions
+    }
+
+    pub fn extranonce1(&self) -> Option<&Extranonce<'a>> {
+        self.extranonce1.as_ref()
+    }
+
+    pub fn extranonce2_size(&self) -> usize {
+        self.extranonce2_size
+    }
+
+    /// Records a `mining.set_extranonce`
+    pub fn set_extranonce(&mut self, extranonce1: Extranonce<'a>, extranonce2_size: usize) {
+        self.extranonce1 = Some(extranonce1);
+        self.extranonce2_size = extranonce2_size;
+    }
+
+    /// Records a `mining.authorize` request, the worker is authorized when the response comes
+    pub fn authorize_sent(&mut self, id: u64, worker: String) {
+        self.pending_authorize.insert(id, worker);
+    }
+
+    /// Records a `mining.submit` request
+    pub fn submit_sent(&mut self, id: u64) {
+        self.pending_submit.push(id);
+    }
+
+    /// If `id` is the one of a pending `mining.authorize` returns the worker, the request is no
+    /// more pending
+    pub fn id_is_authorize(&mut self, id: u64) -> Option<String> {
+        self.pending_authorize.remove(&id)
+    }
+
+    /// Whether `id` is the one of a pending `mining.submit`, the request is no more pending
+    pub fn id_is_submit(&mut self, id: u64) -> bool {
+        match self.pending_submit.iter().position(|i| *i == id) {
+            Some(index) => {
+                self.pending_submit.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn authorize(&mut self, worker: String) {
+        if !self.is_authorized(&worker) {
+            self.authorized_workers.push(worker);
+        }
+    }
+
+    pub fn is_authorized(&self, worker: &str) -> bool {
+        self.authorized_workers.iter().any(|w| w == worker)
+    }
+
+    pub fn authorized_workers(&self) -> &[String] {
+        &self.authorized_workers
+    }
+
+    pub fn set_version_rolling_mask(&mut self, mask: Option<HexU32Be>) {
+        self.version_rolling_mask = mask;
+    }
+
+    pub fn version_rolling_mask(&self) -> Option<HexU32Be> {
+        self.version_rolling_mask.clone()
+    }
+
+    pub fn set_version_rolling_min_bit(&mut self, min_bit: Option<HexU32Be>) {
+        self.version_rolling_min_bit = min_bit;
+    }
+
+    pub fn version_rolling_min_bit(&self) -> Option<HexU32Be> {
+        self.version_rolling_min_bit.clone()
+    }
+
+    /// Records a `mining.notify`, the shares are submitted for its job
+    pub fn notify(&mut self, notify: server_to_client::Notify<'a>) {
+        self.last_notify = Some(notify);
+    }
+
+    pub fn last_notify(&self) -> Option<&server_to_client::Notify<'a>> {
+        self.last_notify.as_ref()
+    }
+
+    /// Records a `mining.set_difficulty`
+    pub fn set_difficulty(&mut self, difficulty: f64) {
+        self.difficulty = Some(difficulty);
+    }
+
+    pub fn difficulty(&self) -> Option<f64> {
+        self.difficulty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn submit(
+        user_name: &str,
+        job_id: &str,
+        extra_nonce2: Vec<u8>,
+    ) -> client_to_server::Submit<'static> {
+        client_to_server::Submit {
+            user_name: user_name.to_string(),
+            job_id: job_id.to_string(),
+            extra_nonce2: extra_nonce2.try_into().unwrap(),
+            time: HexU32Be(0),
+            nonce: HexU32Be(0),
+            version_bits: None,
+            id: 1,
+        }
+    }
+
+    #[test]
+    fn test_server_session_checks_the_shares() {
+        let mut session = Sv1ServerSession::new(vec![0, 0, 0, 1].try_into().unwrap(), 4);
+        session.authorize("worker");
+        session.authorize("worker");
+        assert_eq!(session.authorized_workers().len(), 1);
+        session.last_job_id = Some("2".to_string());
+
+        assert!(session
+            .check_submit(&submit("worker", "2", vec![0; 4]))
+            .is_ok());
+        assert!(matches!(
+            session.check_submit(&submit("other", "2", vec![0; 4])),
+            Err(Error::UnauthorizedClient(_))
+        ));
+        // stale job and wrong extranonce2 size
+        assert!(session
+            .check_submit(&submit("worker", "1", vec![0; 4]))
+            .is_err());
+        assert!(session
+            .check_submit(&submit("worker", "2", vec![0; 3]))
+            .is_err());
+
+        let mut rolled = submit("worker", "2", vec![0; 4]);
+        rolled.version_bits = Some(HexU32Be(0x0000_2000));
+        assert!(session.check_submit(&rolled).is_err());
+        session.set_version_rolling_mask(Some(HexU32Be(0x1fff_e000)));
+        assert!(session.check_submit(&rolled).is_ok());
+    }
+
+    #[test]
+    fn test_client_session_pairs_the_responses() {
+        let mut session = Sv1ClientSession::new();
+        assert_eq!(session.status(), ClientStatus::Init);
+        session.subscribed(&server_to_client::Subscribe {
+            id: 0,
+            extra_nonce1: vec![1, 2].try_into().unwrap(),
+            extra_nonce2_size: 8,
+            subscriptions: vec![("mining.notify".to_string(), "1".to_string())],
+        });
+        assert_eq!(session.status(), ClientStatus::Subscribed);
+        assert_eq!(session.extranonce2_size(), 8);
+
+        session.authorize_sent(1, "worker".to_string());
+        session.submit_sent(2);
+        assert!(!session.id_is_submit(1));
+        assert_eq!(session.id_is_authorize(1), Some("worker".to_string()));
+        assert_eq!(session.id_is_authorize(1), None);
+        assert!(session.id_is_submit(2));
+        assert!(!session.id_is_submit(2));
+    }
+}
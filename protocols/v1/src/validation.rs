@@ -0,0 +1,358 @@
+//! Validation of the requests that a server receives from its clients.
+//!
+//! Without validation a request with the wrong params fails to parse and the server drops it, the
+//! client never gets a response. A [`PolicyTable`] checks the params count and types of each known
+//! method and returns the JSON-RPC error response to send back to the client instead. How strict
+//! the checks are is chosen per method with a [`Policy`].
+use crate::json_rpc::{JsonRpcError, Message, Response, StandardRequest};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The method does not exist or is not available
+pub const METHOD_NOT_FOUND: i32 = -32601;
+/// Invalid method parameters
+pub const INVALID_PARAMS: i32 = -32602;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Policy {
+    /// Nothing is checked, a request that can not be parsed is dropped
+    #[default]
+    Off,
+    /// The required params must be there with the right type, more params are ignored
+    Lenient,
+    /// As `Lenient`, and there can not be more params than the ones known for the method
+    Strict,
+}
+
+/// Expected type of a param
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Param {
+    String,
+    /// Hex encoded bytes
+    Hex,
+    /// Hex encoded bytes or null
+    HexOrNull,
+    /// A u32, as an hex string or as a number
+    U32,
+    Number,
+    Array,
+    Object,
+    Any,
+}
+
+impl Param {
+    fn check(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Param::String, Value::String(_)) => true,
+            (Param::Hex, Value::String(s)) => hex::decode(s).is_ok(),
+            (Param::HexOrNull, Value::Null) => true,
+            (Param::HexOrNull, Value::String(s)) => hex::decode(s).is_ok(),
+            (Param::U32, Value::String(s)) => s.len() <= 8 && u32::from_str_radix(s, 16).is_ok(),
+            (Param::U32, Value::Number(n)) => matches!(n.as_u64(), Some(n) if n <= u32::MAX as u64),
+            (Param::Number, Value::Number(_)) => true,
+            (Param::Array, Value::Array(_)) => true,
+            (Param::Object, Value::Object(_)) => true,
+            (Param::Any, _) => true,
+            _ => false,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Param::String => "a string",
+            Param::Hex => "an hex string",
+            Param::HexOrNull => "an hex string or null",
+            Param::U32 => "an hex u32 or a number",
+            Param::Number => "a number",
+            Param::Array => "an array",
+            Param::Object => "an object",
+            Param::Any => "any value",
+        }
+    }
+}
+
+/// Params of a method: the required ones and then the optional ones
+struct MethodSpec {
+    method: &'static str,
+    required: &'static [Param],
+    optional: &'static [Param],
+}
+
+const METHODS: &[MethodSpec] = &[
+    // bosminer sends 4 params, the last two are not used
+    MethodSpec {
+        method: "mining.subscribe",
+        required: &[],
+        optional: &[Param::String, Param::HexOrNull, Param::Any, Param::Any],
+    },
+    MethodSpec {
+        method: "mining.authorize",
+        required: &[Param::String, Param::String],
+        optional: &[],
+    },
+    // the last optional param is the version bits of BIP 310
+    MethodSpec {
+        method: "mining.submit",
+        required: &[
+            Param::String,
+            Param::String,
+            Param::Hex,
+            Param::U32,
+            Param::U32,
+        ],
+        optional: &[Param::U32],
+    },
+    MethodSpec {
+        method: "mining.configure",
+        required: &[Param::Array],
+        optional: &[Param::Object],
+    },
+    MethodSpec {
+        method: "mining.extranonce.subscribe",
+        required: &[],
+        optional: &[],
+    },
+    MethodSpec {
+        method: "mining.suggest_difficulty",
+        required: &[Param::Number],
+        optional: &[],
+    },
+];
+
+/// The [`Policy`] of each method. By default nothing is checked, as without a table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PolicyTable {
+    /// Policy of the known methods that are not in `methods`
+    pub default: Policy,
+    /// Policy of the methods that are not known, with `Off` they are dropped as before, otherwise
+    /// they are answered with a method not found error
+    pub unknown_methods: Policy,
+    /// Policy of single methods, e.g. `mining.submit`
+    pub methods: BTreeMap<String, Policy>,
+}
+
+impl PolicyTable {
+    /// Every known method has `policy`, the unknown ones are answered with an error unless `policy`
+    /// is `Off`
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            default: policy,
+            unknown_methods: policy,
+            methods: BTreeMap::new(),
+        }
+    }
+
+    pub fn strict() -> Self {
+        Self::new(Policy::Strict)
+    }
+
+    pub fn lenient() -> Self {
+        Self::new(Policy::Lenient)
+    }
+
+    pub fn with_policy(mut self, method: &str, policy: Policy) -> Self {
+        self.methods.insert(method.to_string(), policy);
+        self
+    }
+
+    pub fn with_unknown_methods(mut self, policy: Policy) -> Self {
+        self.unknown_methods = policy;
+        self
+    }
+
+    pub fn policy(&self, method: &str) -> Policy {
+        match self.methods.get(method) {
+            Some(policy) => *policy,
+            None if METHODS.iter().any(|m| m.method == method) => self.default,
+            None => self.unknown_methods,
+        }
+    }
+
+    /// Checks a message received by a server. Only the requests are checked, notifications and
+    /// responses have no id to answer to. Returns the error response for the client if the
+    /// request is not valid.
+    pub fn validate(&self, message: &Message) -> Result<(), Response> {
+        match message {
+            Message::StandardRequest(request) => self.validate_request(request),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn validate_request(&self, request: &StandardRequest) -> Result<(), Response> {
+        let policy = self.policy(&request.method);
+        if policy == Policy::Off {
+            return Ok(());
+        }
+        let spec = match METHODS.iter().find(|m| m.method == request.method) {
+            Some(spec) => spec,
+            None => {
+                return Err(error_response(
+                    request.id,
+                    METHOD_NOT_FOUND,
+                    format!("Method not found: {}", request.method),
+                ))
+            }
+        };
+        check_params(spec, policy, &request.params)
+            .map_err(|message| error_response(request.id, INVALID_PARAMS, message))
+    }
+}
+
+fn check_params(spec: &MethodSpec, policy: Policy, params: &Value) -> Result<(), String> {
+    let params = match params {
+        Value::Array(params) => params,
+        // some clients send no params at all to the methods without params
+        Value::Null if spec.required.is_empty() => return Ok(()),
+        _ => return Err(format!("{} params must be an array", spec.method)),
+    };
+    if params.len() < spec.required.len() {
+        return Err(format!(
+            "{} expects at least {} params, got {}",
+            spec.method,
+            spec.required.len(),
+            params.len()
+        ));
+    }
+    let max = spec.required.len() + spec.optional.len();
+    if policy == Policy::Strict && params.len() > max {
+        return Err(format!(
+            "{} expects at most {} params, got {}",
+            spec.method,
+            max,
+            params.len()
+        ));
+    }
+    let expected = spec.required.iter().chain(spec.optional.iter());
+    for (index, (param, value)) in expected.zip(params.iter()).enumerate() {
+        if !param.check(value) {
+            return Err(format!(
+                "{} param {} must be {}",
+                spec.method,
+                index,
+                param.name()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The response to a request that can not be handled
+pub fn error_response(id: u64, code: i32, message: String) -> Response {
+    Response {
+        id,
+        error: Some(JsonRpcError {
+            code,
+            message,
+            data: None,
+        }),
+        result: Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(method: &str, params: Value) -> Message {
+        Message::StandardRequest(StandardRequest {
+            id: 7,
+            method: method.to_string(),
+            params,
+        })
+    }
+
+    fn error_code(result: Result<(), Response>) -> Option<i32> {
+        result.err().map(|r| {
+            assert_eq!(r.id, 7);
+            r.error.unwrap().code
+        })
+    }
+
+    #[test]
+    fn test_policies() {
+        let submit = json!(["worker", "1", "00000001", "5f5e1000", "00000000", "1fffe000", 1]);
+        let table = PolicyTable::default();
+        assert!(table
+            .validate(&request("mining.submit", submit.clone()))
+            .is_ok());
+        assert!(table
+            .validate(&request("mining.unknown", json!([])))
+            .is_ok());
+
+        let table = PolicyTable::lenient();
+        assert!(table
+            .validate(&request("mining.submit", submit.clone()))
+            .is_ok());
+        assert_eq!(
+            error_code(table.validate(&request("mining.unknown", json!([])))),
+            Some(METHOD_NOT_FOUND)
+        );
+
+        let table: PolicyTable = serde_json::from_value(json!({
+            "default": "strict",
+            "methods": { "mining.submit": "lenient" },
+        }))
+        .unwrap();
+        assert_eq!(
+            table,
+            PolicyTable::strict()
+                .with_unknown_methods(Policy::Off)
+                .with_policy("mining.submit", Policy::Lenient)
+        );
+        assert!(table.validate(&request("mining.submit", submit)).is_ok());
+        assert_eq!(
+            error_code(table.validate(&request("mining.authorize", json!(["a", "b", "c"])))),
+            Some(INVALID_PARAMS)
+        );
+    }
+
+    #[test]
+    fn test_params() {
+        let table = PolicyTable::strict();
+        let check =
+            |method: &str, params: Value| error_code(table.validate(&request(method, params)));
+        assert_eq!(check("mining.authorize", json!(["worker", "x"])), None);
+        assert_eq!(
+            check("mining.authorize", json!(["worker"])),
+            Some(INVALID_PARAMS)
+        );
+        assert_eq!(
+            check("mining.authorize", json!("worker")),
+            Some(INVALID_PARAMS)
+        );
+        assert_eq!(
+            check("mining.subscribe", json!(["cgminer", null, "x", null])),
+            None
+        );
+        assert_eq!(check("mining.subscribe", json!([])), None);
+        assert_eq!(check("mining.extranonce.subscribe", Value::Null), None);
+        assert_eq!(
+            check(
+                "mining.submit",
+                json!(["w", "1", "0001", 1_600_000_000_u32, 42])
+            ),
+            None
+        );
+        assert_eq!(
+            check("mining.submit", json!(["w", "1", "zz", "5f5e1000", "0"])),
+            Some(INVALID_PARAMS)
+        );
+        assert_eq!(
+            check(
+                "mining.submit",
+                json!(["w", "1", "0001", "5f5e1000", "100000000"])
+            ),
+            Some(INVALID_PARAMS)
+        );
+        // notifications are not checked, there is no id to answer to
+        let notification = Message::Notification(crate::json_rpc::Notification {
+            method: "mining.submit".to_string(),
+            params: json!([]),
+        });
+        assert!(table.validate(&notification).is_ok());
+    }
+}
@@ -0,0 +1,143 @@
+//! Helpers for the conformance tests of the subprotocols messages.
+//!
+//! The subprotocols run their conformance tests with and without `with_serde` against the same
+//! hex vectors, so both backends have to encode every message to the same bytes and to decode the
+//! bytes encoded by the other one. The vectors are in the `test-vectors/` corpus of the
+//! subprotocols, see [`test_vector`].
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+/// Encodes `message` with the backend selected by `with_serde`, panics on error
+#[cfg(not(feature = "with_serde"))]
+pub fn encode<T: crate::Serialize + crate::GetSize + Clone>(message: &T) -> Vec<u8> {
+    crate::to_bytes(message.clone()).unwrap()
+}
+
+/// Encodes `message` with the backend selected by `with_serde`, panics on error
+#[cfg(feature = "with_serde")]
+pub fn encode<T: crate::Serialize>(message: &T) -> Vec<u8> {
+    crate::to_bytes(message).unwrap()
+}
+
+/// Panics if `hex` is not made of pairs of hex digits, whitespaces are ignored
+pub fn from_hex(hex: &str) -> Vec<u8> {
+    let digits: Vec<u8> = hex
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).expect("invalid hex digit") as u8)
+        .collect();
+    let pairs = digits.chunks_exact(2);
+    assert!(pairs.remainder().is_empty(), "odd number of hex digits");
+    pairs.map(|d| (d[0] << 4) | d[1]).collect()
+}
+
+/// `len` times `byte`, as any of the bytes types
+pub fn bytes<T>(len: usize, byte: u8) -> T
+where
+    Vec<u8>: TryInto<T>,
+    <Vec<u8> as TryInto<T>>::Error: core::fmt::Debug,
+{
+    vec![byte; len].try_into().unwrap()
+}
+
+/// Asserts that `$value`, a `$message`, encodes to `$hex` and that `$hex` decodes to a
+/// `$message` that encodes to `$hex` again.
+#[macro_export]
+macro_rules! assert_conformance {
+    ($message:ident = $value:expr, $hex:expr) => {{
+        let expected = $crate::conformance::from_hex($hex);
+        let message: $message = $value;
+        assert_eq!(
+            $crate::conformance::encode(&message),
+            expected,
+            "{} is not encoded as expected",
+            stringify!($message)
+        );
+        let mut bytes = expected.clone();
+        let decoded: $message = $crate::from_bytes(&mut bytes[..])
+            .unwrap_or_else(|e| panic!("{} can not be decoded: {:?}", stringify!($message), e));
+        assert_eq!(
+            $crate::conformance::encode(&decoded),
+            expected,
+            "{} is not decoded as expected",
+            stringify!($message)
+        );
+    }};
+}
+
+/// A vector of the `test-vectors/` corpus, the bytes are hex encoded
+#[derive(Debug, Clone, Copy)]
+pub struct TestVector<'a> {
+    pub message_type: u8,
+    pub channel_msg: bool,
+    pub payload: &'a str,
+    pub frame: &'a str,
+}
+
+impl<'a> TestVector<'a> {
+    /// The frame expected for the vector: header with no extension and then the payload
+    pub fn expected_frame(&self) -> Vec<u8> {
+        let payload = from_hex(self.payload);
+        let extension_type: u16 = if self.channel_msg { 0x8000 } else { 0 };
+        let mut frame = extension_type.to_le_bytes().to_vec();
+        frame.push(self.message_type);
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes()[..3]);
+        frame.extend(payload);
+        frame
+    }
+}
+
+/// Looks up the vector of `message` in `corpus`, the content of one of the JSON files of
+/// `test-vectors/`. The files are formatted with one key per line, this is not a JSON parser.
+pub fn test_vector<'a>(corpus: &'a str, message: &str) -> TestVector<'a> {
+    let start = corpus
+        .find(&format!("\"message\": \"{}\",", message))
+        .unwrap_or_else(|| panic!("no test vector for {}", message));
+    let entry = &corpus[start + 1..];
+    let entry = &entry[..entry.find("\"message\": ").unwrap_or(entry.len())];
+    let value = |key: &str| {
+        let key = format!("\"{}\": ", key);
+        let value = &entry[entry
+            .find(&key)
+            .unwrap_or_else(|| panic!("{} of {} is missing", key, message))
+            + key.len()..];
+        value[..value.find('\n').unwrap_or(value.len())]
+            .trim_end_matches(',')
+            .trim_matches('"')
+    };
+    TestVector {
+        message_type: value("message_type").parse().unwrap(),
+        channel_msg: value("channel_msg").parse().unwrap(),
+        payload: value("payload"),
+        frame: value("frame"),
+    }
+}
+
+/// Asserts the conformance of `$value` against the vector of `$message` in `$corpus`, see
+/// [`assert_conformance`], and checks the header of the vector against the `MessageType` of
+/// `$message`. The calling crate has to depend on `const_sv2`.
+#[macro_export]
+macro_rules! assert_test_vector {
+    ($corpus:expr, $message:ident = $value:expr) => {{
+        let vector = $crate::conformance::test_vector($corpus, stringify!($message));
+        assert_eq!(
+            vector.message_type,
+            <$message as const_sv2::MessageType>::MESSAGE_TYPE,
+            "wrong message_type for {}",
+            stringify!($message)
+        );
+        assert_eq!(
+            vector.channel_msg,
+            <$message as const_sv2::MessageType>::CHANNEL_BIT,
+            "wrong channel_msg for {}",
+            stringify!($message)
+        );
+        assert_eq!(
+            $crate::conformance::from_hex(vector.frame),
+            vector.expected_frame(),
+            "wrong frame for {}",
+            stringify!($message)
+        );
+        $crate::assert_conformance!($message = $value, vector.payload);
+    }};
+}
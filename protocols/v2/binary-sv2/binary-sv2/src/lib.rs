@@ -19,6 +19,12 @@ pub use binary_codec_sv2::{self, Decodable as Deserialize, Encodable as Serializ
 #[cfg(not(feature = "with_serde"))]
 pub use derive_codec_sv2::{Decodable as Deserialize, Encodable as Serialize};
 
+#[doc(hidden)]
+pub mod conformance;
+mod u256;
+
+pub use u256::{FixedPointError, U256Ext, DIFFICULTY_1_TARGET, FRACTION_BITS};
+
 pub fn clone_message<T: Serialize>(_: T) -> T {
     todo!()
 }
@@ -146,6 +152,23 @@ mod test {
         }
     }
 
+    mod test_str0255 {
+        use super::*;
+        use core::convert::{TryFrom, TryInto};
+
+        #[test]
+        fn test_str0255_as_str() {
+            let s: Str0255 = "user.worker1".try_into().unwrap();
+            assert_eq!(s.as_str().unwrap(), "user.worker1");
+
+            let invalid: Str0255 = vec![0x75, 0xff, 0xfe].try_into().unwrap();
+            assert!(invalid.as_str().is_err());
+
+            let too_long = "a".repeat(256);
+            assert!(Str0255::try_from(too_long.as_str()).is_err());
+        }
+    }
+
     mod test_u256 {
         use super::*;
         use core::convert::TryInto;
@@ -174,6 +197,45 @@ mod test {
         }
     }
 
+    mod test_fixed_and_variable_layout {
+        use super::*;
+        use core::convert::TryInto;
+
+        #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+        struct Test<'decoder> {
+            #[cfg_attr(feature = "with_serde", serde(borrow))]
+            a: U256<'decoder>,
+            #[cfg_attr(feature = "with_serde", serde(borrow))]
+            b: B064K<'decoder>,
+            #[cfg_attr(feature = "with_serde", serde(borrow))]
+            c: Signature<'decoder>,
+        }
+
+        #[test]
+        fn test_fixed_and_variable_layout() {
+            let a: U256 = [1_u8; 32].into();
+            let b: B064K = vec![2_u8; 300].try_into().unwrap();
+            let c: Signature = vec![3_u8; 64].try_into().unwrap();
+            let expected = Test { a, b, c };
+
+            #[cfg(not(feature = "with_serde"))]
+            let mut bytes = to_bytes(expected.clone()).unwrap();
+            #[cfg(feature = "with_serde")]
+            let mut bytes = to_bytes(&expected.clone()).unwrap();
+
+            // fixed size fields have no header, the header of B064K is the length as u16 le
+            assert_eq!(bytes.len(), 32 + 2 + 300 + 64);
+            assert_eq!(&bytes[..32], &[1; 32][..]);
+            assert_eq!(&bytes[32..34], &300_u16.to_le_bytes()[..]);
+            assert_eq!(&bytes[34..334], &[2; 300][..]);
+            assert_eq!(&bytes[334..], &[3; 64][..]);
+
+            let deserialized: Test = from_bytes(&mut bytes[..]).unwrap();
+
+            assert_eq!(deserialized, expected);
+        }
+    }
+
     mod test_signature {
         use super::*;
         use core::convert::TryInto;
@@ -319,6 +381,31 @@ mod test {
 
             assert_eq!(bytes, bytes_2);
         }
+
+        #[cfg(not(feature = "with_serde"))]
+        #[test]
+        fn test_seq0255_u256_strict() {
+            let val: Vec<U256> = vec![[6; 32].into(), [5; 32].into()];
+            let test = Test {
+                a: Seq0255::new(val).unwrap(),
+            };
+            let mut bytes = to_bytes(test.clone()).unwrap();
+            let deserialized: Test = from_bytes_strict(&mut bytes[..]).unwrap();
+            assert_eq!(deserialized, test);
+
+            // The header declares one element but the frame has two
+            let mut bytes = to_bytes(test).unwrap();
+            bytes[0] = 1;
+            assert!(from_bytes::<Test>(&mut bytes[..]).is_ok());
+            assert!(matches!(
+                from_bytes_strict::<Test>(&mut bytes[..]),
+                Err(Error::TrailingBytes(32))
+            ));
+
+            // The header declares three elements, the frame is truncated
+            bytes[0] = 3;
+            assert!(from_bytes_strict::<Test>(&mut bytes[..]).is_err());
+        }
     }
 
     mod test_0255_bool {
@@ -783,4 +870,175 @@ mod test {
             assert_eq!(bytes, bytes_2);
         }
     }
+    #[cfg(not(feature = "with_serde"))]
+    mod test_from_bytes_ref {
+        use super::*;
+        use core::convert::TryInto;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            a: U32AsRef<'decoder>,
+            b: U256<'decoder>,
+            c: B0255<'decoder>,
+            d: Seq0255<'decoder, U256<'decoder>>,
+            e: Sv2Option<'decoder, u32>,
+            f: bool,
+        }
+
+        fn test_message() -> Test<'static> {
+            let u256: U256 = vec![7; 32].try_into().unwrap();
+            Test {
+                a: 42_u32.into(),
+                b: u256.clone(),
+                c: vec![1, 2, 3].try_into().unwrap(),
+                d: Seq0255::new(vec![u256.clone(), u256]).unwrap(),
+                e: Sv2Option::new(Some(5)),
+                f: true,
+            }
+        }
+
+        #[test]
+        fn test_from_bytes_ref() {
+            let expected = test_message();
+            let mut bytes = to_bytes(expected.clone()).unwrap();
+            let bytes_ref = bytes.clone();
+
+            let deserialized: Test = from_bytes(&mut bytes[..]).unwrap();
+            let deserialized_ref: Test = from_bytes_ref(&bytes_ref[..]).unwrap();
+
+            assert_eq!(deserialized_ref, expected);
+            assert_eq!(deserialized_ref, deserialized);
+            assert_eq!(to_bytes(deserialized_ref).unwrap(), bytes_ref);
+        }
+
+        #[test]
+        fn test_from_bytes_ref_too_short() {
+            let bytes = to_bytes(test_message()).unwrap();
+            let res: Result<Test, _> = from_bytes_ref(&bytes[..bytes.len() - 1]);
+            assert!(res.is_err());
+        }
+
+        #[test]
+        fn test_u32_as_ref_inner_as_mut() {
+            let mut bytes = to_bytes(test_message()).unwrap();
+            let bytes_ref = bytes.clone();
+
+            let mut deserialized: Test = from_bytes(&mut bytes[..]).unwrap();
+            deserialized
+                .a
+                .inner_as_mut()
+                .copy_from_slice(&43_u32.to_le_bytes());
+            drop(deserialized);
+            assert_eq!(&bytes[..4], &43_u32.to_le_bytes());
+
+            let mut deserialized_ref: Test = from_bytes_ref(&bytes_ref[..]).unwrap();
+            deserialized_ref
+                .a
+                .inner_as_mut()
+                .copy_from_slice(&43_u32.to_le_bytes());
+            assert_eq!(deserialized_ref.a.as_u32(), 43);
+            assert_eq!(&bytes_ref[..4], &42_u32.to_le_bytes());
+        }
+    }
+    #[cfg(not(feature = "with_serde"))]
+    mod test_to_uninit {
+        use super::*;
+        use core::{convert::TryInto, mem::MaybeUninit};
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            a: u32,
+            b: U24,
+            c: B016M<'decoder>,
+            d: Seq064K<'decoder, B0255<'decoder>>,
+            e: bool,
+        }
+
+        fn test_message() -> Test<'static> {
+            let b0255: B0255 = vec![9; 40].try_into().unwrap();
+            Test {
+                a: 42,
+                b: 7_u32.try_into().unwrap(),
+                c: vec![5; 70_000].try_into().unwrap(),
+                d: Seq064K::new(vec![b0255.clone(), b0255]).unwrap(),
+                e: true,
+            }
+        }
+
+        #[test]
+        fn test_to_uninit() {
+            let message = test_message();
+            let size = message.get_size();
+            let mut expected = vec![0; size];
+            Encodable::to_bytes(message.clone(), &mut expected).unwrap();
+
+            let mut buffer = vec![MaybeUninit::uninit(); size + 10];
+            let encoded = to_uninit(message.clone(), &mut buffer).unwrap();
+            assert_eq!(encoded, &expected[..]);
+
+            let deserialized: Test = from_bytes(encoded).unwrap();
+            assert_eq!(deserialized, message);
+            assert_eq!(to_bytes(message).unwrap(), expected);
+        }
+
+        #[test]
+        fn test_to_uninit_too_short() {
+            let message = test_message();
+            let mut buffer = vec![MaybeUninit::uninit(); message.get_size() - 1];
+            assert!(matches!(
+                to_uninit(message, &mut buffer),
+                Err(Error::WriteError(_, _))
+            ));
+
+            let mut buffer = [MaybeUninit::uninit(); 6];
+            let mut dst = UninitBuffer::new(&mut buffer);
+            dst.init_zeroed(2).unwrap().copy_from_slice(&[1, 2]);
+            dst.extend_from_slice(&[3, 4, 5]).unwrap();
+            assert_eq!(dst.filled(), &[1, 2, 3, 4, 5]);
+            assert_eq!(dst.remaining(), 1);
+            assert!(dst.extend_from_slice(&[6, 7]).is_err());
+            assert_eq!(dst.into_filled(), &[1, 2, 3, 4, 5]);
+        }
+    }
+    #[cfg(not(feature = "with_serde"))]
+    mod test_arrays_and_tuples {
+        use super::*;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            a: [u8; 32],
+            b: (u32, u32),
+            c: B0255<'decoder>,
+            d: (u8, bool, [u8; 4]),
+        }
+
+        #[test]
+        fn test_arrays_and_tuples() {
+            let expected = Test {
+                a: [7; 32],
+                b: (1, 2),
+                c: vec![3; 10].try_into().unwrap(),
+                d: (4, true, [5; 4]),
+            };
+            let mut bytes = to_bytes(expected.clone()).unwrap();
+
+            // Laid out like a U256 followed by two u32
+            assert_eq!(bytes.len(), expected.get_size());
+            assert_eq!(&bytes[..32], &[7; 32][..]);
+            assert_eq!(&bytes[32..36], &1_u32.to_le_bytes());
+            assert_eq!(&bytes[36..40], &2_u32.to_le_bytes());
+            assert_eq!(&bytes[51..], &[4, 1, 5, 5, 5, 5]);
+
+            let deserialized: Test = from_bytes_ref(&bytes[..]).unwrap();
+            assert_eq!(deserialized, expected);
+            let deserialized: Test = from_bytes(&mut bytes[..]).unwrap();
+            assert_eq!(deserialized, expected);
+
+            let u256: U256 = expected.a.into();
+            let array: [u8; 32] = from_bytes(&mut to_bytes(u256).unwrap()[..]).unwrap();
+            assert_eq!(array, expected.a);
+            let tuple: (u32, u32) = from_bytes(&mut bytes[32..40]).unwrap();
+            assert_eq!(tuple, (1, 2));
+        }
+    }
 }
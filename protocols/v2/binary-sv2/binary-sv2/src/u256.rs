@@ -0,0 +1,509 @@
+//! Arithmetic on [`U256`], the little endian 256 bits unsigned integers used for the targets and
+//! the hashes.
+use crate::U256;
+use core::cmp::Ordering;
+
+/// Little endian 64 bits limbs
+fn to_limbs(value: &U256<'_>) -> [u64; 4] {
+    let bytes = value.inner_as_ref();
+    let mut limbs = [0_u64; 4];
+    for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks_exact(8)) {
+        let mut le = [0_u8; 8];
+        le.copy_from_slice(chunk);
+        *limb = u64::from_le_bytes(le);
+    }
+    limbs
+}
+
+fn from_limbs(limbs: [u64; 4]) -> U256<'static> {
+    let mut bytes = [0_u8; 32];
+    for (chunk, limb) in bytes.chunks_exact_mut(8).zip(limbs.iter()) {
+        chunk.copy_from_slice(&limb.to_le_bytes());
+    }
+    bytes.into()
+}
+
+const MAX: [u64; 4] = [u64::MAX; 4];
+
+fn is_zero(value: [u64; 4]) -> bool {
+    value == [0; 4]
+}
+
+/// Number of significant bits
+fn bits(value: [u64; 4]) -> usize {
+    match value.iter().rposition(|limb| *limb != 0) {
+        Some(i) => 64 * i + 64 - value[i].leading_zeros() as usize,
+        None => 0,
+    }
+}
+
+fn cmp_limbs(a: [u64; 4], b: [u64; 4]) -> Ordering {
+    a.iter().rev().cmp(b.iter().rev())
+}
+
+/// `value << shift`, the bits above 2^256 are lost
+fn shl(value: [u64; 4], shift: usize) -> [u64; 4] {
+    let mut result = [0_u64; 4];
+    let (limbs, bits) = (shift / 64, shift % 64);
+    for i in limbs..4 {
+        result[i] = value[i - limbs] << bits;
+        if bits != 0 && i > limbs {
+            result[i] |= value[i - limbs - 1] >> (64 - bits);
+        }
+    }
+    result
+}
+
+fn shr(value: [u64; 4], shift: usize) -> [u64; 4] {
+    let mut result = [0_u64; 4];
+    let (limbs, bits) = (shift / 64, shift % 64);
+    for i in 0..4_usize.saturating_sub(limbs) {
+        result[i] = value[i + limbs] >> bits;
+        if bits != 0 && i + limbs + 1 < 4 {
+            result[i] |= value[i + limbs + 1] << (64 - bits);
+        }
+    }
+    result
+}
+
+/// `a + b` modulo 2^256
+fn wrapping_add(a: [u64; 4], b: [u64; 4]) -> [u64; 4] {
+    let mut result = [0_u64; 4];
+    let mut carry = false;
+    for i in 0..4 {
+        let (sum, overflow_1) = a[i].overflowing_add(b[i]);
+        let (sum, overflow_2) = sum.overflowing_add(carry as u64);
+        result[i] = sum;
+        carry = overflow_1 || overflow_2;
+    }
+    result
+}
+
+/// `a - b` modulo 2^256
+fn wrapping_sub(a: [u64; 4], b: [u64; 4]) -> [u64; 4] {
+    let mut result = [0_u64; 4];
+    let mut borrow = false;
+    for i in 0..4 {
+        let (difference, overflow_1) = a[i].overflowing_sub(b[i]);
+        let (difference, overflow_2) = difference.overflowing_sub(borrow as u64);
+        result[i] = difference;
+        borrow = overflow_1 || overflow_2;
+    }
+    result
+}
+
+/// Quotient and remainder of the long division of `dividend` by a non zero `divisor`
+fn div_rem(dividend: [u64; 4], divisor: [u64; 4]) -> ([u64; 4], [u64; 4]) {
+    let mut quotient = [0_u64; 4];
+    let mut remainder = [0_u64; 4];
+    for i in (0..bits(dividend)).rev() {
+        // the remainder is lower than the divisor, if the shift overflows it is greater
+        let overflow = remainder[3] >> 63 == 1;
+        remainder = shl(remainder, 1);
+        remainder[0] |= (dividend[i / 64] >> (i % 64)) & 1;
+        if overflow || cmp_limbs(remainder, divisor) != Ordering::Less {
+            remainder = wrapping_sub(remainder, divisor);
+            quotient[i / 64] |= 1 << (i % 64);
+        }
+    }
+    (quotient, remainder)
+}
+
+/// Fractional bits of the fixed point hashrates and difficulties
+pub const FRACTION_BITS: usize = 32;
+
+/// 0x00000000ffff0000000000000000000000000000000000000000000000000000, little endian
+pub const DIFFICULTY_1_TARGET: [u8; 32] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0,
+    0, 0,
+];
+
+/// Invalid input of a fixed point conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixedPointError {
+    NegativeInput,
+    DivisionByZero,
+    NonFiniteInput,
+}
+
+/// Exact fixed point value of `value`, saturated at 2^256 - 1
+fn to_fixed(value: f64) -> Result<[u64; 4], FixedPointError> {
+    if !value.is_finite() {
+        return Err(FixedPointError::NonFiniteInput);
+    }
+    if value < 0.0 {
+        return Err(FixedPointError::NegativeInput);
+    }
+    if value == 0.0 {
+        return Ok([0; 4]);
+    }
+    // value = mantissa * 2^exponent
+    let raw = value.to_bits();
+    let exponent = ((raw >> 52) & 0x7ff) as i32;
+    let mantissa = raw & ((1 << 52) - 1);
+    let (mantissa, exponent) = match exponent {
+        0 => (mantissa, -1074),
+        _ => (mantissa | (1 << 52), exponent - 1075),
+    };
+    let mantissa = [mantissa, 0, 0, 0];
+    let shift = exponent + FRACTION_BITS as i32;
+    if shift >= 0 {
+        if bits(mantissa) + shift as usize > 256 {
+            Ok(MAX)
+        } else {
+            Ok(shl(mantissa, shift as usize))
+        }
+    } else if -shift >= 64 {
+        Ok([0; 4])
+    } else {
+        Ok(shr(mantissa, (-shift) as usize))
+    }
+}
+
+fn from_fixed(value: [u64; 4]) -> f64 {
+    let value = value
+        .iter()
+        .rev()
+        .fold(0_f64, |acc, limb| acc * 18_446_744_073_709_551_616.0 + *limb as f64);
+    value / (1_u64 << FRACTION_BITS) as f64
+}
+
+/// [`DIFFICULTY_1_TARGET`] with [`FRACTION_BITS`] fractional bits
+fn difficulty_1_fixed() -> [u64; 4] {
+    shl(to_limbs(&DIFFICULTY_1_TARGET.into()), FRACTION_BITS)
+}
+
+pub trait U256Ext {
+    /// Compares two `U256` as little endian numbers, a hash meets a target if it is not
+    /// `Ordering::Greater`
+    fn cmp_as_le_number(&self, other: &U256<'_>) -> Ordering;
+
+    /// Target encoded in the `nbits` field of a block header. `None` if it is negative or does not
+    /// fit in 256 bits.
+    fn from_compact(nbits: u32) -> Option<U256<'static>>;
+
+    /// `nbits` encoding, loses the bits below the 3 most significant bytes
+    fn to_compact(&self) -> u32;
+
+    /// Product, `2^256 - 1` if it overflows
+    fn saturating_mul_u64(&self, rhs: u64) -> U256<'static>;
+
+    /// Quotient, `2^256 - 1` if `rhs` is zero
+    fn saturating_div_u64(&self, rhs: u64) -> U256<'static>;
+
+    /// Target for which a miner with `hashrate` hashes per second finds on average
+    /// `shares_per_minute` shares per minute: `2^256 / (hashrate * 60 / shares_per_minute) - 1`,
+    /// `2^256 - 1` if the miner does less than one hash per share.
+    fn from_hashrate(
+        hashrate: f64,
+        shares_per_minute: f64,
+    ) -> Result<U256<'static>, FixedPointError>;
+
+    /// Target of `difficulty`, [`DIFFICULTY_1_TARGET`] divided by it. Difficulties below the
+    /// fixed point precision get the target of difficulty `2^-FRACTION_BITS`.
+    fn from_difficulty(difficulty: f64) -> Result<U256<'static>, FixedPointError>;
+
+    /// Difficulty of the target, [`DIFFICULTY_1_TARGET`] divided by it
+    fn difficulty(&self) -> Result<f64, FixedPointError>;
+}
+
+impl<'a> U256Ext for U256<'a> {
+    fn cmp_as_le_number(&self, other: &U256<'_>) -> Ordering {
+        self.inner_as_ref()
+            .iter()
+            .rev()
+            .cmp(other.inner_as_ref().iter().rev())
+    }
+
+    fn from_compact(nbits: u32) -> Option<U256<'static>> {
+        let exponent = (nbits >> 24) as usize;
+        let mantissa = nbits & 0x007f_ffff;
+        if mantissa == 0 {
+            return Some([0; 32].into());
+        }
+        if nbits & 0x0080_0000 != 0 {
+            return None;
+        }
+        if exponent > 34
+            || (mantissa > 0xff && exponent > 33)
+            || (mantissa > 0xffff && exponent > 32)
+        {
+            return None;
+        }
+        let mut bytes = [0_u8; 32];
+        let mantissa = mantissa.to_le_bytes();
+        // the mantissa is the 3 bytes below the byte `exponent`
+        for (i, byte) in mantissa[..3].iter().enumerate() {
+            if let Some(position) = (exponent + i).checked_sub(3) {
+                if position < 32 {
+                    bytes[position] = *byte;
+                }
+            }
+        }
+        Some(bytes.into())
+    }
+
+    fn to_compact(&self) -> u32 {
+        let bytes = self.inner_as_ref();
+        let mut size = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+        let mut mantissa = (0..3).fold(0_u32, |acc, i| {
+            let byte = match (size + i).checked_sub(3) {
+                Some(position) => bytes[position],
+                None => 0,
+            };
+            acc | ((byte as u32) << (8 * i))
+        });
+        // the most significant bit of the mantissa is the sign
+        if mantissa & 0x0080_0000 != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+        mantissa | ((size as u32) << 24)
+    }
+
+    fn saturating_mul_u64(&self, rhs: u64) -> U256<'static> {
+        let mut limbs = to_limbs(self);
+        let mut carry = 0_u128;
+        for limb in limbs.iter_mut() {
+            let product = *limb as u128 * rhs as u128 + carry;
+            *limb = product as u64;
+            carry = product >> 64;
+        }
+        if carry != 0 {
+            return [255; 32].into();
+        }
+        from_limbs(limbs)
+    }
+
+    fn saturating_div_u64(&self, rhs: u64) -> U256<'static> {
+        if rhs == 0 {
+            return [255; 32].into();
+        }
+        let mut limbs = to_limbs(self);
+        let mut remainder = 0_u128;
+        for limb in limbs.iter_mut().rev() {
+            let dividend = (remainder << 64) | *limb as u128;
+            *limb = (dividend / rhs as u128) as u64;
+            remainder = dividend % rhs as u128;
+        }
+        from_limbs(limbs)
+    }
+
+    fn from_hashrate(
+        hashrate: f64,
+        shares_per_minute: f64,
+    ) -> Result<U256<'static>, FixedPointError> {
+        if shares_per_minute == 0.0 {
+            return Err(FixedPointError::DivisionByZero);
+        }
+        to_fixed(shares_per_minute)?;
+        let hashes_per_share = to_fixed(hashrate * 60.0 / shares_per_minute)?;
+        // less than one hash per share, every hash is a share
+        if cmp_limbs(hashes_per_share, shl([1, 0, 0, 0], FRACTION_BITS)) != Ordering::Greater {
+            return Ok(from_limbs(MAX));
+        }
+        // 2^256 = q * h + r + 1, with h scaled by 2^FRACTION_BITS the target plus one is
+        // (q << FRACTION_BITS) + ((r + 1) << FRACTION_BITS) / h
+        let (quotient, remainder) = div_rem(MAX, hashes_per_share);
+        let remainder = wrapping_add(remainder, [1, 0, 0, 0]);
+        let mut target_plus_one = shl(quotient, FRACTION_BITS);
+        // the remainder is negligible if it does not fit
+        if bits(remainder) + FRACTION_BITS <= 256 {
+            let (fraction, _) = div_rem(shl(remainder, FRACTION_BITS), hashes_per_share);
+            target_plus_one = wrapping_add(target_plus_one, fraction);
+        }
+        Ok(from_limbs(wrapping_sub(target_plus_one, [1, 0, 0, 0])))
+    }
+
+    fn from_difficulty(difficulty: f64) -> Result<U256<'static>, FixedPointError> {
+        if difficulty == 0.0 {
+            return Err(FixedPointError::DivisionByZero);
+        }
+        let difficulty = to_fixed(difficulty)?;
+        // lower than the fixed point precision
+        if is_zero(difficulty) {
+            return Ok(from_limbs(difficulty_1_fixed()));
+        }
+        Ok(from_limbs(div_rem(difficulty_1_fixed(), difficulty).0))
+    }
+
+    fn difficulty(&self) -> Result<f64, FixedPointError> {
+        let target = to_limbs(self);
+        if is_zero(target) {
+            return Err(FixedPointError::DivisionByZero);
+        }
+        Ok(from_fixed(div_rem(difficulty_1_fixed(), target).0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn from_be(be: [u8; 32]) -> U256<'static> {
+        let mut le = be;
+        le.reverse();
+        le.into()
+    }
+
+    fn from_u64(value: u64) -> U256<'static> {
+        let mut le = [0_u8; 32];
+        le[..8].copy_from_slice(&value.to_le_bytes());
+        le.into()
+    }
+
+    #[test]
+    fn test_cmp_as_le_number() {
+        let mut low = [0_u8; 32];
+        low[0] = 0xff;
+        let mut high = [0_u8; 32];
+        high[31] = 0x01;
+        let (low, high): (U256, U256) = (low.into(), high.into());
+        assert_eq!(low.cmp_as_le_number(&high), Ordering::Less);
+        assert_eq!(high.cmp_as_le_number(&low), Ordering::Greater);
+        assert_eq!(high.cmp_as_le_number(&high.clone()), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_compact_vectors() {
+        // difficulty 1
+        let mut be = [0_u8; 32];
+        be[4] = 0xff;
+        be[5] = 0xff;
+        assert_eq!(U256::from_compact(0x1d00ffff), Some(from_be(be)));
+        assert_eq!(from_be(be).to_compact(), 0x1d00ffff);
+
+        let mut be = [0_u8; 32];
+        be[5..8].copy_from_slice(&[0x04, 0x04, 0xcb]);
+        assert_eq!(U256::from_compact(0x1b0404cb), Some(from_be(be)));
+        assert_eq!(from_be(be).to_compact(), 0x1b0404cb);
+
+        // (nbits, value, compact of value) from bitcoin core arith_uint256 tests
+        let vectors = [
+            (0x00123456, 0, 0),
+            (0x01003456, 0, 0),
+            (0x02000056, 0, 0),
+            (0x01123456, 0x12, 0x01120000),
+            (0x02123456, 0x1234, 0x02123400),
+            (0x03123456, 0x123456, 0x03123456),
+            (0x04123456, 0x12345600, 0x04123456),
+            (0x05009234, 0x92340000, 0x05009234),
+        ];
+        for (nbits, value, compact) in vectors {
+            let target = U256::from_compact(nbits).unwrap();
+            assert_eq!(target, from_u64(value));
+            assert_eq!(target.to_compact(), compact);
+        }
+
+        let mut be = [0_u8; 32];
+        be[..3].copy_from_slice(&[0x12, 0x34, 0x56]);
+        assert_eq!(U256::from_compact(0x20123456), Some(from_be(be)));
+        assert_eq!(from_be(be).to_compact(), 0x20123456);
+    }
+
+    #[test]
+    fn test_invalid_compact() {
+        // negative
+        assert_eq!(U256::from_compact(0x04923456), None);
+        assert_eq!(U256::from_compact(0x01fedcba), None);
+        // overflow
+        assert_eq!(U256::from_compact(0xff123456), None);
+        assert_eq!(U256::from_compact(0x21010000), None);
+    }
+
+    #[test]
+    fn test_saturating_mul_div() {
+        let value = from_u64(u64::MAX);
+        let product = value.saturating_mul_u64(u64::MAX);
+        // (2^64 - 1)^2 = 2^128 - 2^65 + 1
+        let mut le = [0_u8; 32];
+        le[0] = 1;
+        le[8] = 0xfe;
+        le[9..16].copy_from_slice(&[0xff; 7]);
+        assert_eq!(product, U256::from(le));
+        assert_eq!(product.saturating_div_u64(u64::MAX), value);
+
+        let max: U256 = [255; 32].into();
+        assert_eq!(max.saturating_mul_u64(2), max);
+        assert_eq!(max.saturating_mul_u64(1), max);
+        assert_eq!(from_u64(7).saturating_div_u64(0), max);
+        assert_eq!(from_u64(7).saturating_div_u64(2), from_u64(3));
+    }
+
+    #[test]
+    fn test_div_rem() {
+        let dividend = [3, 5, 7, u64::MAX];
+        for divisor in [1, 7, u64::MAX] {
+            let (quotient, remainder) = div_rem(dividend, [divisor, 0, 0, 0]);
+            assert!(remainder[0] < divisor && remainder[1..] == [0; 3]);
+            let product = to_limbs(&from_limbs(quotient).saturating_mul_u64(divisor));
+            assert_eq!(wrapping_add(product, remainder), dividend);
+        }
+        assert_eq!(div_rem(dividend, MAX), ([0; 4], dividend));
+        assert_eq!(div_rem(MAX, MAX), ([1, 0, 0, 0], [0; 4]));
+        assert_eq!(div_rem(MAX, [0, 0, 0, 1 << 63]), ([1, 0, 0, 0], shr(MAX, 1)));
+        assert_eq!(shr(shl(dividend, 100), 100), [3, 5, 7, 0]);
+        assert_eq!(shl(shr(dividend, 100), 100), [0, 0, 7, u64::MAX]);
+    }
+
+    #[test]
+    fn test_difficulty_vectors() {
+        let difficulty_1: U256 = DIFFICULTY_1_TARGET.into();
+        assert_eq!(difficulty_1.difficulty(), Ok(1.0));
+        assert_eq!(U256::from_difficulty(1.0), Ok(difficulty_1.clone()));
+
+        // nBits 0x1b0404cb, https://en.bitcoin.it/wiki/Difficulty
+        let difficulty = U256::from_compact(0x1b0404cb).unwrap().difficulty().unwrap();
+        assert!((difficulty - 16307.420938523983).abs() < 1e-8);
+
+        // difficulties lower than 1 are exact
+        let mut be = [0_u8; 32];
+        be[3..5].copy_from_slice(&[0x01, 0xff]);
+        be[5] = 0xfe;
+        assert_eq!(from_be(be).difficulty(), Ok(0.5));
+        assert_eq!(U256::from_difficulty(0.5), Ok(from_be(be)));
+
+        // large power of two difficulties are exact shifts of the difficulty 1 target
+        let mut le = [0_u8; 32];
+        le[13..15].copy_from_slice(&[0xf0, 0xff]);
+        le[15] = 0x0f;
+        assert_eq!(U256::from_difficulty(2_f64.powi(100)), Ok(U256::from(le)));
+        assert_eq!(U256::from(le).difficulty(), Ok(2_f64.powi(100)));
+
+        // 2^32 hashes per share
+        let mut be = [255_u8; 32];
+        be[..4].copy_from_slice(&[0; 4]);
+        assert_eq!(U256::from_hashrate(2_f64.powi(32), 60.0), Ok(from_be(be)));
+        assert_eq!(U256::from_hashrate(2_f64.powi(33), 120.0), Ok(from_be(be)));
+        // a miner slower than a hash per share gets the highest target
+        assert_eq!(U256::from_hashrate(0.5, 60.0), Ok(U256::from([255_u8; 32])));
+    }
+
+    #[test]
+    fn test_invalid_fixed_point_inputs() {
+        assert_eq!(
+            U256::from_hashrate(1e12, 0.0),
+            Err(FixedPointError::DivisionByZero)
+        );
+        assert_eq!(
+            U256::from_hashrate(-1.0, 6.0),
+            Err(FixedPointError::NegativeInput)
+        );
+        assert_eq!(
+            U256::from_hashrate(f64::NAN, 6.0),
+            Err(FixedPointError::NonFiniteInput)
+        );
+        assert_eq!(
+            U256::from_difficulty(0.0),
+            Err(FixedPointError::DivisionByZero)
+        );
+        assert_eq!(
+            U256::from_difficulty(f64::INFINITY),
+            Err(FixedPointError::NonFiniteInput)
+        );
+        assert_eq!(
+            U256::from([0_u8; 32]).difficulty(),
+            Err(FixedPointError::DivisionByZero)
+        );
+    }
+}
@@ -5,7 +5,7 @@ use crate::{
     },
     Error,
 };
-use alloc::vec::Vec;
+use alloc::{borrow::Cow, vec::Vec};
 use core::convert::TryFrom;
 #[cfg(not(feature = "no_std"))]
 use std::io::{Cursor, Read};
@@ -34,6 +34,25 @@ pub trait Decodable<'a>: Sized {
         Self::from_decoded_fields(fields)
     }
 
+    /// Decodes borrowing `data` immutably, for data in shared buffers. The decoded values can not
+    /// update `data` in place: `U32AsRef::inner_as_mut` copies the value first.
+    fn from_bytes_ref(data: &'a [u8]) -> Result<Self, Error> {
+        let structure = Self::get_structure(data)?;
+        let mut fields = Vec::new();
+        let mut tail = data;
+
+        for field in structure {
+            let field_size = field.size_hint_(tail, 0)?;
+            if field_size > tail.len() {
+                return Err(Error::DecodableConversionError);
+            }
+            let (head, t) = tail.split_at(field_size);
+            tail = t;
+            fields.push(field.decode_ref(head)?);
+        }
+        Self::from_decoded_fields(fields)
+    }
+
     #[cfg(not(feature = "no_std"))]
     fn from_reader(reader: &mut impl Read) -> Result<Self, Error> {
         let mut data = Vec::new();
@@ -69,6 +88,8 @@ pub enum PrimitiveMarker {
     B0255,
     B064K,
     B016M,
+    /// `[u8; N]`, encoded like the fixed size `Inner` of `N` bytes
+    FixedBytes(usize),
 }
 
 /// Passed to a decoder to define the structure of the data to be decoded
@@ -99,6 +120,7 @@ pub enum DecodablePrimitive<'a> {
     B0255(B0255<'a>),
     B064K(B064K<'a>),
     B016M(B016M<'a>),
+    FixedBytes(Cow<'a, [u8]>),
 }
 
 /// Used to contrustuct messages is returned by the decoder
@@ -131,6 +153,7 @@ impl SizeHint for PrimitiveMarker {
             Self::B0255 => B0255::size_hint(data, offset),
             Self::B064K => B064K::size_hint(data, offset),
             Self::B016M => B016M::size_hint(data, offset),
+            Self::FixedBytes(size) => Ok(*size),
         }
     }
 }
@@ -217,9 +240,9 @@ impl PrimitiveMarker {
                 DecodablePrimitive::Signature(Signature::from_bytes_unchecked(&mut data[offset..]))
             }
             Self::U32 => DecodablePrimitive::U32(u32::from_bytes_unchecked(&mut data[offset..])),
-            Self::U32AsRef => {
-                DecodablePrimitive::U32AsRef(U32AsRef::from_bytes_unchecked(&mut data[offset..]))
-            }
+            Self::U32AsRef => DecodablePrimitive::U32AsRef(U32AsRef::from_bytes_mut_unchecked(
+                &mut data[offset..],
+            )),
             Self::F32 => DecodablePrimitive::F32(f32::from_bytes_unchecked(&mut data[offset..])),
             Self::U64 => DecodablePrimitive::U64(u64::from_bytes_unchecked(&mut data[offset..])),
             Self::B032 => DecodablePrimitive::B032(B032::from_bytes_unchecked(&mut data[offset..])),
@@ -232,6 +255,35 @@ impl PrimitiveMarker {
             Self::B016M => {
                 DecodablePrimitive::B016M(B016M::from_bytes_unchecked(&mut data[offset..]))
             }
+            Self::FixedBytes(_) => self.decode_ref(data, offset),
+        }
+    }
+
+    fn decode_ref<'a>(&self, data: &'a [u8], offset: usize) -> DecodablePrimitive<'a> {
+        let data = &data[offset..];
+        match self {
+            Self::U8 => DecodablePrimitive::U8(u8::from_bytes_ref_unchecked(data)),
+            Self::U16 => DecodablePrimitive::U16(u16::from_bytes_ref_unchecked(data)),
+            Self::Bool => DecodablePrimitive::Bool(bool::from_bytes_ref_unchecked(data)),
+            Self::U24 => DecodablePrimitive::U24(U24::from_bytes_ref_unchecked(data)),
+            Self::U256 => DecodablePrimitive::U256(U256::from_bytes_ref_unchecked(data)),
+            Self::ShortTxId => {
+                DecodablePrimitive::ShortTxId(ShortTxId::from_bytes_ref_unchecked(data))
+            }
+            Self::Signature => {
+                DecodablePrimitive::Signature(Signature::from_bytes_ref_unchecked(data))
+            }
+            Self::U32 => DecodablePrimitive::U32(u32::from_bytes_ref_unchecked(data)),
+            Self::U32AsRef => {
+                DecodablePrimitive::U32AsRef(U32AsRef::from_bytes_ref_unchecked(data))
+            }
+            Self::F32 => DecodablePrimitive::F32(f32::from_bytes_ref_unchecked(data)),
+            Self::U64 => DecodablePrimitive::U64(u64::from_bytes_ref_unchecked(data)),
+            Self::B032 => DecodablePrimitive::B032(B032::from_bytes_ref_unchecked(data)),
+            Self::B0255 => DecodablePrimitive::B0255(B0255::from_bytes_ref_unchecked(data)),
+            Self::B064K => DecodablePrimitive::B064K(B064K::from_bytes_ref_unchecked(data)),
+            Self::B016M => DecodablePrimitive::B016M(B016M::from_bytes_ref_unchecked(data)),
+            Self::FixedBytes(size) => DecodablePrimitive::FixedBytes(Cow::Borrowed(&data[..*size])),
         }
     }
 
@@ -261,6 +313,11 @@ impl PrimitiveMarker {
             Self::B0255 => Ok(DecodablePrimitive::B0255(B0255::from_reader_(reader)?)),
             Self::B064K => Ok(DecodablePrimitive::B064K(B064K::from_reader_(reader)?)),
             Self::B016M => Ok(DecodablePrimitive::B016M(B016M::from_reader_(reader)?)),
+            Self::FixedBytes(size) => {
+                let mut bytes = vec![0; *size];
+                reader.read_exact(&mut bytes)?;
+                Ok(DecodablePrimitive::FixedBytes(Cow::Owned(bytes)))
+            }
         }
     }
 }
@@ -283,6 +340,7 @@ impl<'a> GetSize for DecodablePrimitive<'a> {
             DecodablePrimitive::B0255(v) => v.get_size(),
             DecodablePrimitive::B064K(v) => v.get_size(),
             DecodablePrimitive::B016M(v) => v.get_size(),
+            DecodablePrimitive::FixedBytes(v) => v.len(),
         }
     }
 }
@@ -305,6 +363,23 @@ impl FieldMarker {
         }
     }
 
+    pub(crate) fn decode_ref<'a>(&self, data: &'a [u8]) -> Result<DecodableField<'a>, Error> {
+        match self {
+            Self::Primitive(p) => Ok(DecodableField::Primitive(p.decode_ref(data, 0))),
+            Self::Struct(ps) => {
+                let mut decodeds = Vec::new();
+                let mut tail = data;
+                for p in ps {
+                    let field_size = p.size_hint_(tail, 0)?;
+                    let (head, t) = tail.split_at(field_size);
+                    tail = t;
+                    decodeds.push(p.decode_ref(head)?);
+                }
+                Ok(DecodableField::Struct(decodeds))
+            }
+        }
+    }
+
     #[allow(clippy::wrong_self_convention)]
     #[cfg(not(feature = "no_std"))]
     #[allow(clippy::wrong_self_convention)]
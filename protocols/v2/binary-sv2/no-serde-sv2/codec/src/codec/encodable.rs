@@ -1,5 +1,5 @@
 use crate::{
-    codec::GetSize,
+    codec::{uninit::UninitBuffer, GetSize},
     datatypes::{
         ShortTxId, Signature, Sv2DataType, U32AsRef, B016M, B0255, B032, B064K, U24, U256,
     },
@@ -13,6 +13,10 @@ pub trait Encodable {
     #[allow(clippy::wrong_self_convention)]
     fn to_bytes(self, dst: &mut [u8]) -> Result<usize, Error>;
 
+    /// Like `to_bytes` but appends the encoded bytes to `dst` without zero filling them first
+    #[allow(clippy::wrong_self_convention)]
+    fn to_uninit(self, dst: &mut UninitBuffer) -> Result<usize, Error>;
+
     #[cfg(not(feature = "no_std"))]
     #[allow(clippy::wrong_self_convention)]
     fn to_writer(self, dst: &mut impl Write) -> Result<(), E>;
@@ -26,6 +30,12 @@ impl<'a, T: Into<EncodableField<'a>>> Encodable for T {
         encoded_field.encode(dst, 0)
     }
 
+    #[allow(clippy::wrong_self_convention)]
+    fn to_uninit(self, dst: &mut UninitBuffer) -> Result<usize, Error> {
+        let encoded_field = self.into();
+        encoded_field.encode_uninit(dst)
+    }
+
     #[cfg(not(feature = "no_std"))]
     #[allow(clippy::wrong_self_convention, unconditional_recursion)]
     fn to_writer(self, dst: &mut impl Write) -> Result<(), E> {
@@ -52,6 +62,8 @@ pub enum EncodablePrimitive<'a> {
     B0255(B0255<'a>),
     B064K(B064K<'a>),
     B016M(B016M<'a>),
+    /// `[u8; N]`, encoded like the fixed size `Inner` of `N` bytes
+    FixedBytes(Vec<u8>),
 }
 
 impl<'a> EncodablePrimitive<'a> {
@@ -73,6 +85,38 @@ impl<'a> EncodablePrimitive<'a> {
             Self::B0255(v) => v.to_slice(dst),
             Self::B064K(v) => v.to_slice(dst),
             Self::B016M(v) => v.to_slice(dst),
+            Self::FixedBytes(v) => match dst.get_mut(..v.len()) {
+                Some(dst) => {
+                    dst.copy_from_slice(v);
+                    Ok(v.len())
+                }
+                None => Err(Error::WriteError(v.len(), dst.len())),
+            },
+        }
+    }
+
+    fn encode_uninit(&self, dst: &mut UninitBuffer) -> Result<usize, Error> {
+        match self {
+            Self::U8(v) => v.to_uninit_slice(dst),
+            Self::OwnedU8(v) => v.to_uninit_slice(dst),
+            Self::U16(v) => v.to_uninit_slice(dst),
+            Self::Bool(v) => v.to_uninit_slice(dst),
+            Self::U24(v) => v.to_uninit_slice(dst),
+            Self::U256(v) => v.to_uninit_slice(dst),
+            Self::ShortTxId(v) => v.to_uninit_slice(dst),
+            Self::Signature(v) => v.to_uninit_slice(dst),
+            Self::U32(v) => v.to_uninit_slice(dst),
+            Self::U32AsRef(v) => v.to_uninit_slice(dst),
+            Self::F32(v) => v.to_uninit_slice(dst),
+            Self::U64(v) => v.to_uninit_slice(dst),
+            Self::B032(v) => v.to_uninit_slice(dst),
+            Self::B0255(v) => v.to_uninit_slice(dst),
+            Self::B064K(v) => v.to_uninit_slice(dst),
+            Self::B016M(v) => v.to_uninit_slice(dst),
+            Self::FixedBytes(v) => {
+                dst.extend_from_slice(v)?;
+                Ok(v.len())
+            }
         }
     }
 
@@ -95,6 +139,7 @@ impl<'a> EncodablePrimitive<'a> {
             Self::B0255(v) => v.to_writer_(writer),
             Self::B064K(v) => v.to_writer_(writer),
             Self::B016M(v) => v.to_writer_(writer),
+            Self::FixedBytes(v) => writer.write_all(v),
         }
     }
 }
@@ -118,6 +163,7 @@ impl<'a> GetSize for EncodablePrimitive<'a> {
             Self::B0255(v) => v.get_size(),
             Self::B064K(v) => v.get_size(),
             Self::B016M(v) => v.get_size(),
+            Self::FixedBytes(v) => v.len(),
         }
     }
 }
@@ -145,6 +191,20 @@ impl<'a> EncodableField<'a> {
         }
     }
 
+    /// Appends the encoded field to `dst`, see [`UninitBuffer`]
+    pub fn encode_uninit(&self, dst: &mut UninitBuffer) -> Result<usize, Error> {
+        match self {
+            Self::Primitive(p) => p.encode_uninit(dst),
+            Self::Struct(ps) => {
+                let mut result = 0;
+                for p in ps {
+                    result += p.encode_uninit(dst)?;
+                }
+                Ok(result)
+            }
+        }
+    }
+
     #[cfg(not(feature = "no_std"))]
     pub fn to_writer(&self, writer: &mut impl Write) -> Result<(), E> {
         match self {
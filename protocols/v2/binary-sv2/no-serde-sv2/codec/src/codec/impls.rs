@@ -4,6 +4,7 @@ use crate::{
             Decodable, DecodableField, DecodablePrimitive, FieldMarker, GetMarker, PrimitiveMarker,
         },
         encodable::{EncodableField, EncodablePrimitive},
+        Fixed,
     },
     datatypes::*,
     Error,
@@ -849,3 +850,85 @@ impl<'a> From<U32AsRef<'a>> for FieldMarker {
         FieldMarker::Primitive(PrimitiveMarker::U32AsRef)
     }
 }
+
+// IMPL CODEC FOR FIXED SIZE BYTE ARRAYS
+
+impl<const N: usize> GetMarker for [u8; N] {
+    fn get_marker() -> FieldMarker {
+        FieldMarker::Primitive(PrimitiveMarker::FixedBytes(N))
+    }
+}
+impl<'a, const N: usize> Decodable<'a> for [u8; N] {
+    fn get_structure(_: &[u8]) -> Result<Vec<FieldMarker>, Error> {
+        Ok(vec![PrimitiveMarker::FixedBytes(N).into()])
+    }
+
+    fn from_decoded_fields(mut data: Vec<DecodableField<'a>>) -> Result<Self, Error> {
+        data.pop().ok_or(Error::NoDecodableFieldPassed)?.try_into()
+    }
+}
+impl<'a, const N: usize> TryFrom<DecodablePrimitive<'a>> for [u8; N] {
+    type Error = Error;
+
+    fn try_from(value: DecodablePrimitive<'a>) -> Result<Self, Self::Error> {
+        match value {
+            DecodablePrimitive::FixedBytes(val) => val
+                .as_ref()
+                .try_into()
+                .map_err(|_| Error::PrimitiveConversionError),
+            _ => Err(Error::PrimitiveConversionError),
+        }
+    }
+}
+impl<'a, const N: usize> TryFrom<DecodableField<'a>> for [u8; N] {
+    type Error = Error;
+
+    fn try_from(value: DecodableField<'a>) -> Result<Self, Self::Error> {
+        match value {
+            DecodableField::Primitive(p) => p.try_into(),
+            _ => Err(Error::DecodableConversionError),
+        }
+    }
+}
+impl<'a, const N: usize> From<[u8; N]> for EncodableField<'a> {
+    fn from(v: [u8; N]) -> Self {
+        EncodableField::Primitive(EncodablePrimitive::FixedBytes(v.to_vec()))
+    }
+}
+impl<const N: usize> From<[u8; N]> for FieldMarker {
+    fn from(_: [u8; N]) -> Self {
+        FieldMarker::Primitive(PrimitiveMarker::FixedBytes(N))
+    }
+}
+
+// IMPL CODEC FOR TUPLES OF FIXED SIZE PRIMITIVES
+
+macro_rules! impl_codec_for_tuple {
+    ($($t:ident),+) => {
+        impl<'a, $($t: Decodable<'a> + Fixed),+> Decodable<'a> for ($($t,)+) {
+            fn get_structure(data: &[u8]) -> Result<Vec<FieldMarker>, Error> {
+                // The elements have a fixed size so their structure does not depend on `data`
+                Ok(vec![$($t::get_structure(data)?.try_into()?),+])
+            }
+
+            fn from_decoded_fields(data: Vec<DecodableField<'a>>) -> Result<Self, Error> {
+                let mut data = data.into_iter();
+                Ok(($(
+                    $t::from_decoded_fields(data.next().ok_or(Error::NoDecodableFieldPassed)?.into())?,
+                )+))
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<'a, $($t: Into<EncodableField<'a>> + Fixed),+> From<($($t,)+)> for EncodableField<'a> {
+            fn from(v: ($($t,)+)) -> Self {
+                let ($($t,)+) = v;
+                EncodableField::Struct(vec![$($t.into()),+])
+            }
+        }
+    };
+}
+
+impl_codec_for_tuple!(A, B);
+impl_codec_for_tuple!(A, B, C);
+impl_codec_for_tuple!(A, B, C, D);
@@ -8,6 +8,7 @@ use crate::Error;
 pub mod decodable;
 pub mod encodable;
 mod impls;
+pub mod uninit;
 #[cfg(feature = "with_buffer_pool")]
 use buffer_sv2::Slice;
 
@@ -49,16 +50,19 @@ pub trait Variable {
 
 impl<T: Fixed> SizeHint for T {
     /// Total size of the encoded data type compreensive of the header when present
+    #[inline]
     fn size_hint(_data: &[u8], _offset: usize) -> Result<usize, Error> {
         Ok(Self::SIZE)
     }
 
+    #[inline]
     fn size_hint_(&self, _: &[u8], _offset: usize) -> Result<usize, Error> {
         Ok(Self::SIZE)
     }
 }
 
 impl<T: Fixed> GetSize for T {
+    #[inline]
     fn get_size(&self) -> usize {
         Self::SIZE
     }
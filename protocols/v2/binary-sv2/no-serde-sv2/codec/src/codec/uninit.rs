@@ -0,0 +1,81 @@
+//! Encoding into uninitialized memory.
+//!
+//! [`crate::to_bytes`] and [`crate::Encodable::to_bytes`] need an initialized destination, which
+//! for a large message means zero filling megabytes that are overwritten right after.
+//! [`UninitBuffer`] wraps a `&mut [MaybeUninit<u8>]` and keeps track of how many bytes have been
+//! written, the bytes of the variable length fields are copied in it without being initialized
+//! first.
+use crate::Error;
+use core::mem::MaybeUninit;
+
+/// A `&mut [MaybeUninit<u8>]` whose first [`UninitBuffer::len`] bytes are initialized
+#[derive(Debug)]
+pub struct UninitBuffer<'a> {
+    buf: &'a mut [MaybeUninit<u8>],
+    filled: usize,
+}
+
+impl<'a> UninitBuffer<'a> {
+    pub fn new(buf: &'a mut [MaybeUninit<u8>]) -> Self {
+        Self { buf, filled: 0 }
+    }
+
+    /// Number of bytes written
+    pub fn len(&self) -> usize {
+        self.filled
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filled == 0
+    }
+
+    /// Number of bytes that can still be written
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.filled
+    }
+
+    /// The bytes written so far
+    pub fn filled(&self) -> &[u8] {
+        // Safe the first `filled` bytes have been initialized by `extend_from_slice` or
+        // `init_zeroed`
+        unsafe { &*(&self.buf[..self.filled] as *const [MaybeUninit<u8>] as *const [u8]) }
+    }
+
+    /// Consumes the buffer returning the bytes written
+    pub fn into_filled(self) -> &'a mut [u8] {
+        let filled = self.filled;
+        // Safe see `filled`
+        unsafe { &mut *(&mut self.buf[..filled] as *mut [MaybeUninit<u8>] as *mut [u8]) }
+    }
+
+    /// Appends `src`, it is copied without initializing the destination first
+    pub fn extend_from_slice(&mut self, src: &[u8]) -> Result<(), Error> {
+        let dst = self.reserve(src.len())?;
+        // Safe `MaybeUninit<u8>` has the same layout as `u8` and `dst` is as long as `src`
+        let src = unsafe { &*(src as *const [u8] as *const [MaybeUninit<u8>]) };
+        dst.copy_from_slice(src);
+        self.filled += src.len();
+        Ok(())
+    }
+
+    /// Appends `len` zeroed bytes and returns them to be written. Used for the fixed size fields,
+    /// that are too small for the zero filling to matter.
+    pub fn init_zeroed(&mut self, len: usize) -> Result<&mut [u8], Error> {
+        let start = self.filled;
+        for byte in self.reserve(len)? {
+            *byte = MaybeUninit::new(0);
+        }
+        self.filled += len;
+        // Safe the bytes have been zeroed above
+        Ok(unsafe {
+            &mut *(&mut self.buf[start..start + len] as *mut [MaybeUninit<u8>] as *mut [u8])
+        })
+    }
+
+    fn reserve(&mut self, len: usize) -> Result<&mut [MaybeUninit<u8>], Error> {
+        if len > self.remaining() {
+            return Err(Error::WriteError(len, self.remaining()));
+        }
+        Ok(&mut self.buf[self.filled..self.filled + len])
+    }
+}
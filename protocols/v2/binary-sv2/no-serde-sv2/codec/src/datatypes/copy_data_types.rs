@@ -23,7 +23,8 @@ impl Fixed for bool {
 // impact on meaning. This allows future use of other
 // bits as flag bits.
 impl<'a> Sv2DataType<'a> for bool {
-    fn from_bytes_unchecked(data: &'a mut [u8]) -> Self {
+    #[inline]
+    fn from_bytes_ref_unchecked(data: &'a [u8]) -> Self {
         match data
             .first()
             .map(|x: &u8| x << 7)
@@ -38,21 +39,22 @@ impl<'a> Sv2DataType<'a> for bool {
         }
     }
 
-    fn from_vec_(mut data: Vec<u8>) -> Result<Self, Error> {
-        Self::from_bytes_(&mut data)
+    fn from_vec_(data: Vec<u8>) -> Result<Self, Error> {
+        Self::from_bytes_ref_(&data)
     }
 
-    fn from_vec_unchecked(mut data: Vec<u8>) -> Self {
-        Self::from_bytes_unchecked(&mut data)
+    fn from_vec_unchecked(data: Vec<u8>) -> Self {
+        Self::from_bytes_ref_unchecked(&data)
     }
 
     #[cfg(not(feature = "no_std"))]
     fn from_reader_(reader: &mut impl Read) -> Result<Self, Error> {
         let mut dst = [0_u8; Self::SIZE];
         reader.read_exact(&mut dst)?;
-        Self::from_bytes_(&mut dst)
+        Self::from_bytes_ref_(&dst)
     }
 
+    #[inline]
     fn to_slice_unchecked(&'a self, dst: &mut [u8]) {
         match self {
             true => dst[0] = 1,
@@ -90,7 +92,8 @@ impl Fixed for u64 {
 macro_rules! impl_sv2_for_unsigned {
     ($a:ty) => {
         impl<'a> Sv2DataType<'a> for $a {
-            fn from_bytes_unchecked(data: &'a mut [u8]) -> Self {
+            #[inline]
+            fn from_bytes_ref_unchecked(data: &'a [u8]) -> Self {
                 // unchecked function is fine to panic
                 let a: &[u8; Self::SIZE] = data[0..Self::SIZE].try_into().expect(
                     "Try to decode a copy data type from a buffer that do not have enough bytes",
@@ -98,21 +101,22 @@ macro_rules! impl_sv2_for_unsigned {
                 Self::from_le_bytes(*a)
             }
 
-            fn from_vec_(mut data: Vec<u8>) -> Result<Self, Error> {
-                Self::from_bytes_(&mut data)
+            fn from_vec_(data: Vec<u8>) -> Result<Self, Error> {
+                Self::from_bytes_ref_(&data)
             }
 
-            fn from_vec_unchecked(mut data: Vec<u8>) -> Self {
-                Self::from_bytes_unchecked(&mut data)
+            fn from_vec_unchecked(data: Vec<u8>) -> Self {
+                Self::from_bytes_ref_unchecked(&data)
             }
 
             #[cfg(not(feature = "no_std"))]
             fn from_reader_(reader: &mut impl Read) -> Result<Self, Error> {
                 let mut dst = [0_u8; Self::SIZE];
                 reader.read_exact(&mut dst)?;
-                Ok(Self::from_bytes_unchecked(&mut dst))
+                Ok(Self::from_bytes_ref_unchecked(&dst))
             }
 
+            #[inline]
             fn to_slice_unchecked(&'a self, dst: &mut [u8]) {
                 let dst = &mut dst[0..Self::SIZE];
                 let src = self.to_le_bytes();
@@ -140,6 +144,25 @@ impl Fixed for f32 {
 
 impl_sv2_for_unsigned!(f32);
 
+// Impl fixed size byte arrays and tuples of fixed size primitives, they are encoded as their
+// elements one after the other
+
+impl<const N: usize> Fixed for [u8; N] {
+    const SIZE: usize = N;
+}
+
+macro_rules! impl_fixed_for_tuple {
+    ($($t:ident),+) => {
+        impl<$($t: Fixed),+> Fixed for ($($t,)+) {
+            const SIZE: usize = 0 $(+ $t::SIZE)+;
+        }
+    };
+}
+
+impl_fixed_for_tuple!(A, B);
+impl_fixed_for_tuple!(A, B, C);
+impl_fixed_for_tuple!(A, B, C, D);
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct U24(pub(crate) u32);
@@ -149,11 +172,13 @@ impl Fixed for U24 {
 }
 
 impl U24 {
+    #[inline]
     fn from_le_bytes(b: [u8; Self::SIZE]) -> Self {
         let inner = u32::from_le_bytes([b[0], b[1], b[2], 0]);
         Self(inner)
     }
 
+    #[inline]
     fn to_le_bytes(self) -> [u8; Self::SIZE] {
         let b = self.0.to_le_bytes();
         [b[0], b[1], b[2]]
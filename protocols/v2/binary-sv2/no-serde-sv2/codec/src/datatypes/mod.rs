@@ -1,5 +1,5 @@
 use crate::{
-    codec::{GetSize, SizeHint},
+    codec::{uninit::UninitBuffer, GetSize, SizeHint},
     Error,
 };
 mod non_copy_data_types;
@@ -18,12 +18,25 @@ use core::convert::TryInto;
 use std::io::{Error as E, Read, Write};
 
 pub trait Sv2DataType<'a>: Sized + SizeHint + GetSize + TryInto<FieldMarker> {
+    #[inline]
     fn from_bytes_(data: &'a mut [u8]) -> Result<Self, Error> {
         Self::size_hint(data, 0)?;
         Ok(Self::from_bytes_unchecked(data))
     }
 
-    fn from_bytes_unchecked(data: &'a mut [u8]) -> Self;
+    #[inline]
+    fn from_bytes_unchecked(data: &'a mut [u8]) -> Self {
+        Self::from_bytes_ref_unchecked(data)
+    }
+
+    /// Like `from_bytes_` but borrows `data` immutably, the decoded value can not update `data`
+    #[inline]
+    fn from_bytes_ref_(data: &'a [u8]) -> Result<Self, Error> {
+        Self::size_hint(data, 0)?;
+        Ok(Self::from_bytes_ref_unchecked(data))
+    }
+
+    fn from_bytes_ref_unchecked(data: &'a [u8]) -> Self;
 
     fn from_vec_(data: Vec<u8>) -> Result<Self, Error>;
 
@@ -32,6 +45,7 @@ pub trait Sv2DataType<'a>: Sized + SizeHint + GetSize + TryInto<FieldMarker> {
     #[cfg(not(feature = "no_std"))]
     fn from_reader_(reader: &mut impl Read) -> Result<Self, Error>;
 
+    #[inline]
     fn to_slice(&'a self, dst: &mut [u8]) -> Result<usize, Error> {
         if dst.len() >= self.get_size() {
             self.to_slice_unchecked(dst);
@@ -43,6 +57,17 @@ pub trait Sv2DataType<'a>: Sized + SizeHint + GetSize + TryInto<FieldMarker> {
 
     fn to_slice_unchecked(&'a self, dst: &mut [u8]);
 
+    /// Like `to_slice` but appends `self` to a buffer that does not need to be initialized
+    #[inline]
+    fn to_uninit_slice(&'a self, dst: &mut UninitBuffer) -> Result<usize, Error> {
+        let size = self.get_size();
+        if dst.remaining() < size {
+            return Err(Error::WriteError(size, dst.remaining()));
+        }
+        self.to_slice_unchecked(dst.init_zeroed(size)?);
+        Ok(size)
+    }
+
     #[cfg(not(feature = "no_std"))]
     fn to_writer_(&self, writer: &mut impl Write) -> Result<(), E>;
 }
@@ -1,12 +1,14 @@
 use super::IntoOwned;
 use crate::{
-    codec::{GetSize, SizeHint},
+    codec::{uninit::UninitBuffer, GetSize, SizeHint},
     datatypes::Sv2DataType,
     Error,
 };
 
 use alloc::vec::Vec;
 use core::convert::{TryFrom, TryInto};
+#[cfg(feature = "prop_test")]
+use quickcheck::{Arbitrary, Gen};
 #[cfg(not(feature = "no_std"))]
 use std::io::{Error as E, Read, Write};
 
@@ -19,28 +21,33 @@ pub enum Inner<
     const HEADERSIZE: usize,
     const MAXSIZE: usize,
 > {
-    Ref(&'a mut [u8]),
+    Ref(&'a [u8]),
+    /// Only used by [`super::U32AsRef`], updated in place when the proxies rewrite the request ids
+    /// of the messages they relay
+    RefMut(&'a mut [u8]),
     Owned(Vec<u8>),
 }
 
 // TODO add test for that and implement it also with serde!!!!
 impl<'a, const SIZE: usize> Inner<'a, true, SIZE, 0, 0> {
     pub fn to_vec(&self) -> Vec<u8> {
-        match self {
-            Inner::Ref(ref_) => ref_.to_vec(),
-            Inner::Owned(v) => v.clone(),
-        }
+        self.as_ref().to_vec()
     }
+    #[inline]
     pub fn inner_as_ref(&self) -> &[u8] {
-        match self {
-            Inner::Ref(ref_) => ref_,
-            Inner::Owned(v) => v,
-        }
+        self.as_ref()
     }
+    /// An immutably borrowed value is copied first, the bytes it was decoded from are not updated
+    #[inline]
     pub fn inner_as_mut(&mut self) -> &mut [u8] {
+        if let Inner::Ref(ref_) = self {
+            *self = Inner::Owned(ref_.to_vec());
+        }
         match self {
-            Inner::Ref(ref_) => ref_,
+            Inner::RefMut(ref_) => ref_,
             Inner::Owned(v) => v,
+            // Replaced above
+            Inner::Ref(_) => unreachable!(),
         }
     }
 }
@@ -49,16 +56,11 @@ impl<'a, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
     Inner<'a, false, SIZE, HEADERSIZE, MAXSIZE>
 {
     pub fn to_vec(&self) -> Vec<u8> {
-        match self {
-            Inner::Ref(ref_) => ref_[..].to_vec(),
-            Inner::Owned(v) => v[..].to_vec(),
-        }
+        self.as_ref().to_vec()
     }
+    #[inline]
     pub fn inner_as_ref(&self) -> &[u8] {
-        match self {
-            Inner::Ref(ref_) => &ref_[..],
-            Inner::Owned(v) => &v[..],
-        }
+        self.as_ref()
     }
 }
 
@@ -66,12 +68,7 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
     PartialEq for Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
 {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Inner::Ref(b), Inner::Owned(a)) => *b == &a[..],
-            (Inner::Owned(b), Inner::Ref(a)) => *a == &b[..],
-            (Inner::Owned(b), Inner::Owned(a)) => b == a,
-            (Inner::Ref(b), Inner::Ref(a)) => b == a,
-        }
+        self.as_ref() == other.as_ref()
     }
 }
 
@@ -83,6 +80,7 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
 impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
     Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
 {
+    #[inline]
     fn expected_length(data: &[u8]) -> Result<usize, Error> {
         let expected_length = match ISFIXED {
             true => Self::expected_length_fixed(),
@@ -95,10 +93,12 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
         }
     }
 
+    #[inline]
     fn expected_length_fixed() -> usize {
         SIZE
     }
 
+    #[inline]
     fn expected_length_variable(data: &[u8]) -> Result<usize, Error> {
         if data.len() >= HEADERSIZE {
             let size = match HEADERSIZE {
@@ -141,19 +141,17 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
     }
 
     pub fn len(&self) -> usize {
-        match (self, ISFIXED) {
-            (Inner::Ref(data), false) => data.len(),
-            (Inner::Owned(data), false) => data.len(),
-            (_, true) => 1,
+        match ISFIXED {
+            false => self.as_ref().len(),
+            true => 1,
         }
     }
 
-    fn get_header(&self) -> Vec<u8> {
-        if HEADERSIZE == 0 {
-            Vec::new()
-        } else {
-            let len = self.len();
-            len.to_le_bytes().into()
+    // Writes the header in `dst` without allocating it, `dst` must be at least HEADERSIZE long
+    #[inline]
+    fn write_header(data_len: usize, dst: &mut [u8]) {
+        if HEADERSIZE != 0 {
+            dst[..HEADERSIZE].copy_from_slice(&(data_len as u32).to_le_bytes()[..HEADERSIZE]);
         }
     }
 }
@@ -164,6 +162,16 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
     type Error = Error;
 
     fn try_from(value: &'a mut [u8]) -> Result<Self, Self::Error> {
+        Self::try_from(&*value)
+    }
+}
+
+impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
+    TryFrom<&'a [u8]> for Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
+{
+    type Error = Error;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
         if ISFIXED && value.len() == SIZE {
             Ok(Self::Ref(value))
         } else if ISFIXED {
@@ -225,17 +233,16 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
 impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
     GetSize for Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
 {
+    #[inline]
     fn get_size(&self) -> usize {
-        match self {
-            Inner::Ref(data) => data.len() + HEADERSIZE,
-            Inner::Owned(data) => data.len() + HEADERSIZE,
-        }
+        self.as_ref().len() + HEADERSIZE
     }
 }
 
 impl<'a, const ISFIXED: bool, const HEADERSIZE: usize, const SIZE: usize, const MAXSIZE: usize>
     SizeHint for Inner<'a, ISFIXED, HEADERSIZE, SIZE, MAXSIZE>
 {
+    #[inline]
     fn size_hint(data: &[u8], offset: usize) -> Result<usize, Error> {
         if offset >= data.len() {
             return Err(Error::ReadError(data.len(), offset));
@@ -243,6 +250,7 @@ impl<'a, const ISFIXED: bool, const HEADERSIZE: usize, const SIZE: usize, const
         Self::expected_length(&data[offset..])
     }
 
+    #[inline]
     fn size_hint_(&self, data: &[u8], offset: usize) -> Result<usize, Error> {
         if offset >= data.len() {
             return Err(Error::ReadError(data.len(), offset));
@@ -257,11 +265,12 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
 where
     Self: TryInto<FieldMarker>,
 {
-    fn from_bytes_unchecked(data: &'a mut [u8]) -> Self {
+    #[inline]
+    fn from_bytes_ref_unchecked(data: &'a [u8]) -> Self {
         if ISFIXED {
-            Self::Ref(data)
+            Self::Ref(&data[..SIZE])
         } else {
-            Self::Ref(&mut data[HEADERSIZE..])
+            Self::Ref(&data[HEADERSIZE..])
         }
     }
 
@@ -284,33 +293,39 @@ where
         Ok(Self::from_vec_unchecked(dst))
     }
 
+    #[inline]
     fn to_slice_unchecked(&'a self, dst: &mut [u8]) {
+        let data = self.as_ref();
+        if ISFIXED {
+            // SIZE is known at compile time so the copy is a single checked memcpy
+            dst[..SIZE].copy_from_slice(&data[..SIZE]);
+        } else {
+            let (header, payload) = dst.split_at_mut(HEADERSIZE);
+            Self::write_header(data.len(), header);
+            payload[..data.len()].copy_from_slice(data);
+        }
+    }
+
+    /// The data is copied in `dst` without being zero filled first, only the header is
+    #[inline]
+    fn to_uninit_slice(&'a self, dst: &mut UninitBuffer) -> Result<usize, Error> {
         let size = self.get_size();
-        let header = self.get_header();
-        dst[0..HEADERSIZE].copy_from_slice(&header[..HEADERSIZE]);
-        match self {
-            Inner::Ref(data) => {
-                let dst = &mut dst[0..size];
-                dst[HEADERSIZE..].copy_from_slice(data);
-            }
-            Inner::Owned(data) => {
-                let dst = &mut dst[0..size];
-                dst[HEADERSIZE..].copy_from_slice(data);
-            }
+        if dst.remaining() < size {
+            return Err(Error::WriteError(size, dst.remaining()));
+        }
+        let data = self.as_ref();
+        if ISFIXED {
+            dst.extend_from_slice(&data[..SIZE])?;
+        } else {
+            Self::write_header(data.len(), dst.init_zeroed(HEADERSIZE)?);
+            dst.extend_from_slice(data)?;
         }
+        Ok(size)
     }
 
     #[cfg(not(feature = "no_std"))]
     fn to_writer_(&self, writer: &mut impl Write) -> Result<(), E> {
-        match self {
-            Inner::Ref(data) => {
-                writer.write_all(data)?;
-            }
-            Inner::Owned(data) => {
-                writer.write_all(data)?;
-            }
-        };
-        Ok(())
+        writer.write_all(self.as_ref())
     }
 }
 
@@ -319,10 +334,8 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
 {
     fn into_owned(self) -> Self {
         match self {
-            Inner::Ref(data) => {
-                let v: Vec<u8> = data.into();
-                Self::Owned(v)
-            }
+            Inner::Ref(data) => Self::Owned(data.into()),
+            Inner::RefMut(data) => Self::Owned(data.to_vec()),
             Inner::Owned(_) => self,
         }
     }
@@ -333,12 +346,8 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
 {
     pub fn into_static(self) -> Inner<'static, ISFIXED, SIZE, HEADERSIZE, MAXSIZE> {
         match self {
-            Inner::Ref(data) => {
-                let mut v = Vec::with_capacity(data.len());
-                v.extend_from_slice(data);
-                Inner::Owned(v)
-            }
             Inner::Owned(data) => Inner::Owned(data),
+            borrowed => Inner::Owned(borrowed.as_ref().to_vec()),
         }
     }
 }
@@ -347,24 +356,33 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
     Clone for Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
 {
     fn clone(&self) -> Inner<'static, ISFIXED, SIZE, HEADERSIZE, MAXSIZE> {
-        match self {
-            Inner::Ref(data) => {
-                let mut v = Vec::with_capacity(data.len());
-                v.extend_from_slice(data);
-                Inner::Owned(v)
-            }
-            Inner::Owned(data) => Inner::Owned(data.clone()),
-        }
+        Inner::Owned(self.as_ref().to_vec())
     }
 }
 
 impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
     AsRef<[u8]> for Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
 {
+    #[inline]
     fn as_ref(&self) -> &[u8] {
         match self {
-            Inner::Ref(r) => &r[..],
-            Inner::Owned(r) => &r[..],
+            Inner::Ref(r) => r,
+            Inner::RefMut(r) => r,
+            Inner::Owned(r) => r,
         }
     }
 }
+
+#[cfg(feature = "prop_test")]
+impl<const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
+    Arbitrary for Inner<'static, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
+{
+    fn arbitrary(g: &mut Gen) -> Self {
+        let len = if ISFIXED {
+            SIZE
+        } else {
+            usize::arbitrary(g) % (MAXSIZE.min(g.size()) + 1)
+        };
+        Inner::Owned((0..len).map(|_| u8::arbitrary(g)).collect())
+    }
+}
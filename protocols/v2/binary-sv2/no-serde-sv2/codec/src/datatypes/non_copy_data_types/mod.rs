@@ -26,8 +26,8 @@ pub type Str0255<'a> = Inner<'a, false, 1, 1, 255>;
 pub type B064K<'a> = Inner<'a, false, 1, 2, { u16::MAX as usize }>;
 pub type B016M<'a> = Inner<'a, false, 1, 3, { 2_usize.pow(24) - 1 }>;
 
-impl<'decoder> From<[u8; 32]> for U256<'decoder> {
-    fn from(v: [u8; 32]) -> Self {
+impl<'decoder, const N: usize> From<[u8; N]> for Inner<'decoder, true, N, 0, 0> {
+    fn from(v: [u8; N]) -> Self {
         Inner::Owned(v.into())
     }
 }
@@ -61,7 +61,28 @@ impl<'a> TryFrom<String> for Str0255<'a> {
     }
 }
 
+impl<'a> TryFrom<&str> for Str0255<'a> {
+    type Error = crate::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.as_bytes().to_vec().try_into()
+    }
+}
+
+impl<'a> Str0255<'a> {
+    /// Errors with `Error::InvalidUtf8String` if the bytes are not valid UTF-8
+    pub fn as_str(&self) -> Result<&str, crate::Error> {
+        core::str::from_utf8(self.inner_as_ref()).map_err(|_| crate::Error::InvalidUtf8String)
+    }
+}
+
 impl<'a> U32AsRef<'a> {
+    /// Borrows `data` mutably, so that `inner_as_mut` updates the bytes the value is decoded from
+    #[inline]
+    pub fn from_bytes_mut_unchecked(data: &'a mut [u8]) -> Self {
+        Inner::RefMut(&mut data[..4])
+    }
+
     pub fn as_u32(&self) -> u32 {
         let inner = self.inner_as_ref();
         u32::from_le_bytes([inner[0], inner[1], inner[2], inner[3]])
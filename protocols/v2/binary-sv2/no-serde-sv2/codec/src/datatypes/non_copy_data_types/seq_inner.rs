@@ -8,6 +8,8 @@ use crate::{
     Error,
 };
 use core::marker::PhantomData;
+#[cfg(feature = "prop_test")]
+use quickcheck::{Arbitrary, Gen};
 
 // TODO add test for that and implement it also with serde!!!!
 impl<'a, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
@@ -192,6 +194,24 @@ macro_rules! impl_codec_for_sequence {
                 Ok(Self(inner, PhantomData))
             }
 
+            fn from_bytes_ref(data: &'a [u8]) -> Result<Self, Error> {
+                let len = Self::expected_len(data)?;
+
+                let mut inner = Vec::new();
+                let mut tail = &data[Self::HEADERSIZE..];
+
+                for _ in 0..len {
+                    let element_size = T::size_hint(tail, 0)?;
+                    if element_size > tail.len() {
+                        return Err(Error::OutOfBound);
+                    }
+                    let (head, t) = tail.split_at(element_size);
+                    tail = t;
+                    inner.push(T::from_bytes_ref_unchecked(head));
+                }
+                Ok(Self(inner, PhantomData))
+            }
+
             #[cfg(not(feature = "no_std"))]
             fn from_reader(reader: &mut impl Read) -> Result<Self, Error> {
                 let mut header = vec![0; Self::HEADERSIZE];
@@ -220,7 +240,7 @@ macro_rules! impl_into_encodable_field_for_seq {
             fn from(v: Seq064K<'a, $a>) -> Self {
                 let inner_len = v.0.len() as u16;
                 let mut as_encodable: Vec<EncodableField> =
-                    Vec::with_capacity((inner_len + 2) as usize);
+                    Vec::with_capacity(inner_len as usize + 2);
                 as_encodable.push(EncodableField::Primitive(EncodablePrimitive::OwnedU8(
                     inner_len.to_le_bytes()[0],
                 )));
@@ -238,7 +258,7 @@ macro_rules! impl_into_encodable_field_for_seq {
             fn from(v: Seq0255<$a>) -> Self {
                 let inner_len = v.0.len() as u8;
                 let mut as_encodable: Vec<EncodableField> =
-                    Vec::with_capacity((inner_len + 1) as usize);
+                    Vec::with_capacity(inner_len as usize + 1);
                 as_encodable.push(EncodableField::Primitive(EncodablePrimitive::OwnedU8(
                     inner_len,
                 )));
@@ -253,7 +273,7 @@ macro_rules! impl_into_encodable_field_for_seq {
             fn from(v: Sv2Option<$a>) -> Self {
                 let inner_len = v.0.len() as u8;
                 let mut as_encodable: Vec<EncodableField> =
-                    Vec::with_capacity((inner_len + 1) as usize);
+                    Vec::with_capacity(inner_len as usize + 1);
                 as_encodable.push(EncodableField::Primitive(EncodablePrimitive::OwnedU8(
                     inner_len,
                 )));
@@ -303,6 +323,29 @@ impl<'a, T> core::convert::TryFrom<Seq064K<'a, T>> for Vec<T> {
     }
 }
 
+#[cfg(feature = "prop_test")]
+impl<T: Arbitrary> Arbitrary for Seq0255<'static, T> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let len = usize::arbitrary(g) % (g.size().min(255) + 1);
+        Seq0255((0..len).map(|_| T::arbitrary(g)).collect(), PhantomData)
+    }
+}
+
+#[cfg(feature = "prop_test")]
+impl<T: Arbitrary> Arbitrary for Seq064K<'static, T> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let len = usize::arbitrary(g) % (g.size().min(65535) + 1);
+        Seq064K((0..len).map(|_| T::arbitrary(g)).collect(), PhantomData)
+    }
+}
+
+#[cfg(feature = "prop_test")]
+impl<T: Arbitrary> Arbitrary for Sv2Option<'static, T> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Sv2Option::new(Option::arbitrary(g))
+    }
+}
+
 impl<'a, T> From<Vec<T>> for Seq0255<'a, T> {
     fn from(v: Vec<T>) -> Self {
         Seq0255(v, PhantomData)
@@ -35,15 +35,19 @@ pub use datatypes::{
 pub use crate::codec::{
     decodable::{Decodable, GetMarker},
     encodable::{Encodable, EncodableField},
+    uninit::UninitBuffer,
     Fixed, GetSize, SizeHint,
 };
 
 use alloc::vec::Vec;
+use core::mem::MaybeUninit;
 
 #[allow(clippy::wrong_self_convention)]
 pub fn to_bytes<T: Encodable + GetSize>(src: T) -> Result<Vec<u8>, Error> {
-    let mut result = vec![0_u8; src.get_size()];
-    src.to_bytes(&mut result)?;
+    let mut result = Vec::with_capacity(src.get_size());
+    let written = to_uninit(src, result.spare_capacity_mut())?.len();
+    // Safe the first `written` bytes have been initialized by `to_uninit`
+    unsafe { result.set_len(written) };
     Ok(result)
 }
 
@@ -53,10 +57,56 @@ pub fn to_writer<T: Encodable>(src: T, dst: &mut [u8]) -> Result<(), Error> {
     Ok(())
 }
 
+/// Encodes `src` in `dst` without zero filling it first, returns the encoded bytes. Used for the
+/// large messages, whose zero filling is measurable.
+#[allow(clippy::wrong_self_convention)]
+pub fn to_uninit<T: Encodable>(src: T, dst: &mut [MaybeUninit<u8>]) -> Result<&mut [u8], Error> {
+    let mut buffer = UninitBuffer::new(dst);
+    src.to_uninit(&mut buffer)?;
+    Ok(buffer.into_filled())
+}
+
 pub fn from_bytes<'a, T: Decodable<'a>>(data: &'a mut [u8]) -> Result<T, Error> {
     T::from_bytes(data)
 }
 
+/// Like [`from_bytes`] but borrows `data` immutably, see [`Decodable::from_bytes_ref`]
+pub fn from_bytes_ref<'a, T: Decodable<'a>>(data: &'a [u8]) -> Result<T, Error> {
+    T::from_bytes_ref(data)
+}
+
+/// Like [`from_bytes`] but fails with [`Error::TrailingBytes`] if the decoded value does not use
+/// all of `data`. A sequence decodes the number of elements declared in its header, a count that
+/// is too small for the bytes of the frame is only detected this way.
+pub fn from_bytes_strict<'a, T: Decodable<'a> + GetSize>(data: &'a mut [u8]) -> Result<T, Error> {
+    let len = data.len();
+    let decoded = T::from_bytes(data)?;
+    match len.checked_sub(decoded.get_size()) {
+        Some(0) => Ok(decoded),
+        Some(trailing) => Err(Error::TrailingBytes(trailing)),
+        // The decoded value can not be bigger than the bytes it has been decoded from
+        None => Err(Error::OutOfBound),
+    }
+}
+
+/// Encodes `message`, decodes the encoded bytes and encodes the decoded message again. True if
+/// both encodings are equal and as long as `message.get_size()`. Used by the round trip tests of
+/// the subprotocols messages.
+#[cfg(feature = "prop_test")]
+pub fn round_trip<T: Encodable + Decodable<'static> + GetSize>(message: T) -> bool {
+    let size = message.get_size();
+    let encoded = match to_bytes(message) {
+        Ok(encoded) => encoded,
+        Err(_) => return false,
+    };
+    // the decoded message borrows the bytes for as long as it lives
+    let bytes: &'static mut [u8] = alloc::boxed::Box::leak(encoded.clone().into_boxed_slice());
+    match from_bytes::<T>(bytes).map(to_bytes) {
+        Ok(Ok(decoded)) => encoded.len() == size && decoded == encoded,
+        _ => false,
+    }
+}
+
 pub mod decodable {
     pub use crate::codec::decodable::{Decodable, DecodableField, FieldMarker};
     //pub use crate::codec::decodable::PrimitiveMarker;
@@ -103,6 +153,10 @@ pub enum Error {
     ValueIsNotAValidProtocol(u8),
     UnknownMessageType(u8),
     Sv2OptionHaveMoreThenOneElement(u8),
+    /// A `Str0255` that is not valid UTF-8
+    InvalidUtf8String,
+    /// Number of bytes left after decoding with [`from_bytes_strict`]
+    TrailingBytes(usize),
 }
 
 #[cfg(not(feature = "no_std"))]
@@ -151,6 +205,10 @@ pub enum CError {
     ValueIsNotAValidProtocol(u8),
     UnknownMessageType(u8),
     Sv2OptionHaveMoreThenOneElement(u8),
+    /// A `Str0255` that is not valid UTF-8
+    InvalidUtf8String,
+    /// Number of bytes left after decoding with [`from_bytes_strict`]
+    TrailingBytes(usize),
 }
 
 impl From<Error> for CError {
@@ -187,6 +245,8 @@ impl From<Error> for CError {
             Error::ValueIsNotAValidProtocol(u) => CError::ValueIsNotAValidProtocol(u),
             Error::UnknownMessageType(u) => CError::UnknownMessageType(u),
             Error::Sv2OptionHaveMoreThenOneElement(u) => CError::Sv2OptionHaveMoreThenOneElement(u),
+            Error::InvalidUtf8String => CError::InvalidUtf8String,
+            Error::TrailingBytes(u) => CError::TrailingBytes(u),
         }
     }
 }
@@ -221,6 +281,8 @@ impl Drop for CError {
             Self::ValueIsNotAValidProtocol(_) => (),
             Self::UnknownMessageType(_) => (),
             Self::Sv2OptionHaveMoreThenOneElement(_) => (),
+            Self::InvalidUtf8String => (),
+            Self::TrailingBytes(_) => (),
         };
     }
 }
@@ -354,11 +416,7 @@ impl<'a, const A: bool, const B: usize, const C: usize, const D: usize>
 {
     fn from(v: datatypes::Inner<'a, A, B, C, D>) -> Self {
         let (ptr, len, cap): (*mut u8, usize, usize) = match v {
-            datatypes::Inner::Ref(inner) => {
-                // Data is copied in a vector that then will be forgetted from the allocator,
-                // cause the owner of the data is going to be dropped by rust
-                let mut inner: Vec<u8> = inner.into();
-
+            datatypes::Inner::Owned(mut inner) => {
                 // Get the length, first, then the pointer (doing it the other way around
                 // **currently** doesn't cause UB, but it may be unsound due to unclear (to me, at
                 // least) guarantees of the std lib)
@@ -369,7 +427,11 @@ impl<'a, const A: bool, const B: usize, const C: usize, const D: usize>
 
                 (ptr, len, cap)
             }
-            datatypes::Inner::Owned(mut inner) => {
+            borrowed => {
+                // Data is copied in a vector that then will be forgetted from the allocator,
+                // cause the owner of the data is going to be dropped by rust
+                let mut inner: Vec<u8> = borrowed.as_ref().to_vec();
+
                 // Get the length, first, then the pointer (doing it the other way around
                 // **currently** doesn't cause UB, but it may be unsound due to unclear (to me, at
                 // least) guarantees of the std lib)
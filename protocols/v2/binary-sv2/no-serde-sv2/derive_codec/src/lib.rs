@@ -85,6 +85,11 @@ fn parse_struct_fields(group: Vec<TokenTree>) -> Vec<ParsedField> {
             (TokenTree::Ident(i), ParserState::Type) => {
                 field_.type_ = i.to_string();
             }
+            // Arrays and tuples, e.g. `[u8; 32]` or `(u32, u32)`, are qualified so that the generated
+            // `<[u8; 32]>::get_structure` is a valid path
+            (TokenTree::Group(g), ParserState::Type) => {
+                field_.type_ = format!("<{}>", g);
+            }
             (TokenTree::Ident(i), ParserState::Generics(_)) => {
                 field_.generics = format!("{}{}", field_.generics, i);
             }
@@ -269,8 +274,19 @@ pub fn decodable(item: TokenStream) -> TokenStream {
         derive_fields.push_str(&field)
     }
 
+    // into_static takes the fields out of self, as_static has to clone them
     let mut derive_static_fields = String::new();
+    let mut derive_static_fields_cloned = String::new();
     for f in parsed_struct.fields.clone() {
+        let field = format!(
+            "
+            {}: self.{}{},
+            ",
+            f.name,
+            f.name,
+            f.as_static(),
+        );
+        derive_static_fields.push_str(&field);
         let field = format!(
             "
             {}: self.{}.clone(){},
@@ -279,7 +295,7 @@ pub fn decodable(item: TokenStream) -> TokenStream {
             f.name,
             f.as_static(),
         );
-        derive_static_fields.push_str(&field)
+        derive_static_fields_cloned.push_str(&field)
     }
 
     let mut derive_decoded_fields = String::new();
@@ -360,14 +376,14 @@ pub fn decodable(item: TokenStream) -> TokenStream {
         get_static_generics(&parsed_struct.generics),
         parsed_struct.name,
         derive_static_fields,
-        // impl into_static
+        // impl as_static
         impl_generics,
         parsed_struct.name,
         parsed_struct.generics,
         parsed_struct.name,
         get_static_generics(&parsed_struct.generics),
         parsed_struct.name,
-        derive_static_fields,
+        derive_static_fields_cloned,
 
     );
 
@@ -133,9 +133,9 @@ impl<'de> Deserializer<'de> {
 
     #[inline]
     fn parse_u64(&mut self) -> Result<u64> {
-        let u32_ = self.get_slice(8)?;
+        let u64_ = self.get_slice(8)?;
         Ok(u64::from_le_bytes([
-            u32_[0], u32_[1], u32_[2], u32_[3], u32_[0], u32_[1], u32_[2], u32_[3],
+            u64_[0], u64_[1], u64_[2], u64_[3], u64_[4], u64_[5], u64_[6], u64_[7],
         ]))
     }
 
@@ -280,6 +280,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
             "Seq_0255_U32" => visitor.visit_borrowed_bytes(self.parse_seq0255(4)?),
             "Seq_0255_Signature" => visitor.visit_borrowed_bytes(self.parse_seq0255(64)?),
             "Seq_064K_U256" => visitor.visit_borrowed_bytes(self.parse_seq064k(32)?),
+            "Seq_064K_ShortTxId" => visitor.visit_borrowed_bytes(self.parse_seq064k(6)?),
             "Seq_064K_Bool" => visitor.visit_borrowed_bytes(self.parse_seq064k(1)?),
             "Seq_064K_U16" => visitor.visit_borrowed_bytes(self.parse_seq064k(2)?),
             "Seq_064K_U24" => visitor.visit_borrowed_bytes(self.parse_seq064k(3)?),
@@ -196,6 +196,12 @@ impl<'a> B016M<'a> {
             Inner::Owned(inner) => B016M(Inner::Owned(inner)),
         }
     }
+    pub fn inner_as_ref(&self) -> &[u8] {
+        match &self.0 {
+            Inner::Ref(slice) => slice,
+            Inner::Owned(inner) => inner.as_slice(),
+        }
+    }
     pub fn to_vec(self) -> Vec<u8> {
         match self.0 {
             Inner::Ref(v) => v.to_vec(),
@@ -209,6 +209,10 @@ impl<'a> B0255<'a> {
             Inner::Owned(inner) => inner.to_vec(),
         }
     }
+    /// Errors with `Error::InvalidUtf8` if the bytes are not valid UTF-8
+    pub fn as_str(&self) -> Result<&str, crate::Error> {
+        core::str::from_utf8(self.as_ref()).map_err(|_| crate::Error::InvalidUtf8)
+    }
 }
 
 impl<'a> TryFrom<alloc::string::String> for B0255<'a> {
@@ -218,3 +222,11 @@ impl<'a> TryFrom<alloc::string::String> for B0255<'a> {
         value.into_bytes().try_into()
     }
 }
+
+impl<'a> TryFrom<&'a str> for B0255<'a> {
+    type Error = crate::Error;
+
+    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
+        value.as_bytes().try_into()
+    }
+}
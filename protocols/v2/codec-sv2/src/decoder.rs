@@ -36,6 +36,8 @@ use core::marker::PhantomData;
 use framing_sv2::framing::HandShakeFrame;
 #[cfg(feature = "noise_sv2")]
 use framing_sv2::header::{NOISE_HEADER_ENCRYPTED_SIZE, NOISE_HEADER_SIZE};
+#[cfg(feature = "frame_trace")]
+use framing_sv2::trace::FrameTrace;
 use framing_sv2::{
     framing::{Frame, Sv2Frame},
     header::Header,
@@ -47,9 +49,13 @@ use noise_sv2::NoiseCodec;
 use crate::error::Error;
 use crate::error::Result;
 
-use crate::Error::MissingBytes;
 #[cfg(feature = "noise_sv2")]
 use crate::State;
+use crate::{
+    diagnostics::{header_bytes, DecoderDiagnostics},
+    pool::BufferPoolConfig,
+    Error::MissingBytes,
+};
 
 #[cfg(not(feature = "with_buffer_pool"))]
 use buffer_sv2::{Buffer as IsBuffer, BufferFromSystemMemory as Buffer};
@@ -124,6 +130,15 @@ pub struct WithNoise<B: IsBuffer, T: Serialize + binary_sv2::GetSize> {
     //
     // Stores the decrypted data until it is ready to be processed and converted into a Sv2 frame.
     sv2_buffer: B,
+
+    // Maximum frame size and behaviour when the buffers are full.
+    pool_config: BufferPoolConfig,
+
+    // Frame counts and last errors, if enabled.
+    diagnostics: Option<DecoderDiagnostics>,
+
+    // Decrypted header of the frame being decoded, only kept when the diagnostics are enabled.
+    last_header: Option<[u8; SV2_FRAME_HEADER_SIZE]>,
 }
 
 #[cfg(feature = "noise_sv2")]
@@ -138,8 +153,44 @@ impl<'a, T: Serialize + GetSize + Deserialize<'a>, B: IsBuffer + AeadBuffer> Wit
     /// `writable`, read another chunk from the incoming message stream, and then call `next_frame`
     /// again. This process should be repeated until `next_frame` returns `Ok`, indicating that the
     /// full message has been received, and the decoding and decryption of the frame can proceed.
+    ///
+    /// With the `frame_trace` feature the Sv2 frames carry the time they were decrypted and a new
+    /// trace id, see [`Sv2Frame::trace`].
+    ///
+    /// The frame or the error is recorded in the diagnostics, if enabled.
     #[inline]
     pub fn next_frame(&mut self, state: &mut State) -> Result<Frame<T, B::Slice>> {
+        if self.diagnostics.is_none() {
+            return self.decode_frame(state);
+        }
+        let result = self.decode_frame(state);
+        if let Some(diagnostics) = &mut self.diagnostics {
+            match &result {
+                Ok(Frame::Sv2(frame)) => {
+                    diagnostics.on_frame(frame.get_header());
+                    self.last_header = None;
+                }
+                Ok(Frame::HandShake(_)) => (),
+                Err(MissingBytes(_)) => (),
+                Err(e) => diagnostics.on_error(e, self.last_header.take()),
+            }
+        }
+        result
+    }
+
+    /// Counts the decoded frames by message type and keeps the last `max_errors` decode errors,
+    /// see [`WithNoise::diagnostics`].
+    pub fn enable_diagnostics(&mut self, max_errors: usize) {
+        self.diagnostics = Some(DecoderDiagnostics::new(max_errors));
+    }
+
+    /// Frame counts and last decode errors, `None` if not enabled.
+    pub fn diagnostics(&self) -> Option<&DecoderDiagnostics> {
+        self.diagnostics.as_ref()
+    }
+
+    #[inline]
+    fn decode_frame(&mut self, state: &mut State) -> Result<Frame<T, B::Slice>> {
         match state {
             State::HandShake(_) => unreachable!(),
             State::NotInitialized(msg_len) => {
@@ -248,14 +299,24 @@ impl<'a, T: Serialize + GetSize + Deserialize<'a>, B: IsBuffer + AeadBuffer> Wit
                 decrypted_header.copy_from_slice(src.as_ref());
                 self.sv2_buffer.as_ref();
                 noise_codec.decrypt(&mut self.sv2_buffer)?;
+                if self.diagnostics.is_some() {
+                    self.last_header =
+                        header_bytes(self.sv2_buffer.get_data_by_ref(SV2_FRAME_HEADER_SIZE));
+                }
                 let header =
                     Header::from_bytes(self.sv2_buffer.get_data_by_ref(SV2_FRAME_HEADER_SIZE))?;
+                if let Err(e) = self.pool_config.check_size(Header::SIZE + header.len()) {
+                    self.sv2_buffer.get_data_owned();
+                    self.missing_noise_b = NOISE_HEADER_ENCRYPTED_SIZE;
+                    return Err(e);
+                }
                 self.missing_noise_b = header.encrypted_len();
                 Err(Error::MissingBytes(header.encrypted_len()))
             }
             // HERE THE SV2 PAYLOAD IS READY TO BE DECRYPTED
             _ => {
                 // DECRYPT THE PAYLOAD IN CHUNKS
+                let exhausted = self.noise_buffer.is_exhausted();
                 let encrypted_payload = self.noise_buffer.get_data_owned();
                 let encrypted_payload_len = encrypted_payload.as_ref().len();
                 let mut start = 0;
@@ -277,8 +338,12 @@ impl<'a, T: Serialize + GetSize + Deserialize<'a>, B: IsBuffer + AeadBuffer> Wit
                     decrypted_len += self.sv2_buffer.as_ref().len();
                 }
                 self.sv2_buffer.danger_set_start(0);
+                let exhausted = exhausted || self.sv2_buffer.is_exhausted();
                 let src = self.sv2_buffer.get_data_owned();
+                self.pool_config.check_exhausted(exhausted)?;
                 let frame = Sv2Frame::<T, B::Slice>::from_bytes_unchecked(src);
+                #[cfg(feature = "frame_trace")]
+                let frame = frame.with_trace(FrameTrace::now());
                 Ok(frame.into())
             }
         }
@@ -292,11 +357,34 @@ impl<T: Serialize + binary_sv2::GetSize> WithNoise<Buffer, T> {
     /// Initializes the decoder with default buffer sizes and sets the number of missing bytes to
     /// 0.
     pub fn new() -> Self {
+        Self::with_pool_config(BufferPoolConfig::default())
+    }
+
+    /// Creates a new [`WithNoise`] decoder with buffers sized as in `config`.
+    ///
+    /// A frame bigger than `config.max_slice_size` fails with [`crate::Error::FrameTooLarge`] and
+    /// the connection can not be used anymore.
+    pub fn with_pool_config(config: BufferPoolConfig) -> Self {
         Self {
             frame: PhantomData,
             missing_noise_b: 0,
-            noise_buffer: Buffer::new(2_usize.pow(16) * 5),
-            sv2_buffer: Buffer::new(2_usize.pow(16) * 5),
+            noise_buffer: Buffer::new(config.capacity),
+            sv2_buffer: Buffer::new(config.capacity),
+            pool_config: config,
+            diagnostics: None,
+            last_header: None,
+        }
+    }
+
+    /// Counters of the frames that were decoded in the buffer pools and of the ones that were
+    /// allocated in system memory.
+    #[cfg(feature = "with_buffer_pool")]
+    pub fn pool_stats(&self) -> buffer_sv2::PoolStats {
+        let noise = self.noise_buffer.stats();
+        let sv2 = self.sv2_buffer.stats();
+        buffer_sv2::PoolStats {
+            hits: noise.hits + sv2.hits,
+            misses: noise.misses + sv2.misses,
         }
     }
 }
@@ -333,6 +421,16 @@ pub struct WithoutNoise<B: IsBuffer, T: Serialize + binary_sv2::GetSize> {
     // necessary bytes until a full frame is available. Once the full encoded frame has been
     // received, the buffer's contents are processed and decoded into an Sv2 frame.
     buffer: B,
+
+    // Every frame must end with a CRC, which is checked, see [`framing_sv2::crc`]. When not set
+    // the CRC bit of the extension type is left to the protocol.
+    crc_required: bool,
+
+    // Maximum frame size and behaviour when the buffer is full.
+    pool_config: BufferPoolConfig,
+
+    // Frame counts and last errors, if enabled.
+    diagnostics: Option<DecoderDiagnostics>,
 }
 
 impl<T: Serialize + binary_sv2::GetSize, B: IsBuffer> WithoutNoise<B, T> {
@@ -348,8 +446,43 @@ impl<T: Serialize + binary_sv2::GetSize, B: IsBuffer> WithoutNoise<B, T> {
     /// `writable`, read another chunk from the incoming message stream, and then call `next_frame`
     /// again. This process should be repeated until `next_frame` returns `Ok`, indicating that the
     /// full message has been received, and the frame can be fully decoded.
+    ///
+    /// A frame with a wrong CRC, or without CRC when it is required, is an error.
+    ///
+    /// With the `frame_trace` feature the frame carries the time it was completed and a new trace
+    /// id, see [`Sv2Frame::trace`].
+    ///
+    /// The frame or the error is recorded in the diagnostics, if enabled.
     #[inline]
     pub fn next_frame(&mut self) -> Result<Sv2Frame<T, B::Slice>> {
+        if self.diagnostics.is_none() {
+            return self.decode_frame();
+        }
+        let len = self.buffer.len();
+        let header = header_bytes(self.buffer.get_data_by_ref(len));
+        let result = self.decode_frame();
+        if let Some(diagnostics) = &mut self.diagnostics {
+            match &result {
+                Ok(frame) => diagnostics.on_frame(frame.get_header()),
+                Err(e) => diagnostics.on_error(e, header),
+            }
+        }
+        result
+    }
+
+    /// Counts the decoded frames by message type and keeps the last `max_errors` decode errors,
+    /// see [`WithoutNoise::diagnostics`].
+    pub fn enable_diagnostics(&mut self, max_errors: usize) {
+        self.diagnostics = Some(DecoderDiagnostics::new(max_errors));
+    }
+
+    /// Frame counts and last decode errors, `None` if not enabled.
+    pub fn diagnostics(&self) -> Option<&DecoderDiagnostics> {
+        self.diagnostics.as_ref()
+    }
+
+    #[inline]
+    fn decode_frame(&mut self) -> Result<Sv2Frame<T, B::Slice>> {
         let len = self.buffer.len();
         let src = self.buffer.get_data_by_ref(len);
         let hint = Sv2Frame::<T, B::Slice>::size_hint(src) as usize;
@@ -357,11 +490,26 @@ impl<T: Serialize + binary_sv2::GetSize, B: IsBuffer> WithoutNoise<B, T> {
         match hint {
             0 => {
                 self.missing_b = Header::SIZE;
+                let exhausted = self.buffer.is_exhausted();
                 let src = self.buffer.get_data_owned();
-                let frame = Sv2Frame::<T, B::Slice>::from_bytes_unchecked(src);
+                self.pool_config.check_exhausted(exhausted)?;
+                let mut frame = Sv2Frame::<T, B::Slice>::from_bytes_unchecked(src);
+                if self.crc_required {
+                    frame.check_crc()?;
+                }
+                #[cfg(feature = "frame_trace")]
+                let frame = frame.with_trace(FrameTrace::now());
                 Ok(frame)
             }
             _ => {
+                // The header is complete, the hint is the length of the payload
+                if len == Header::SIZE {
+                    if let Err(e) = self.pool_config.check_size(Header::SIZE + hint) {
+                        self.missing_b = Header::SIZE;
+                        self.buffer.get_data_owned();
+                        return Err(e);
+                    }
+                }
                 self.missing_b = hint;
                 Err(MissingBytes(self.missing_b))
             }
@@ -385,10 +533,37 @@ impl<T: Serialize + binary_sv2::GetSize> WithoutNoise<Buffer, T> {
     /// Initializes the decoder with a default buffer size and sets the number of missing bytes to
     /// the size of the header.
     pub fn new() -> Self {
+        Self::with_pool_config(BufferPoolConfig::default())
+    }
+
+    /// Creates a new [`WithoutNoise`] with a buffer sized as in `config`.
+    ///
+    /// A frame bigger than `config.max_slice_size` fails with [`crate::Error::FrameTooLarge`] and
+    /// the connection can not be used anymore.
+    pub fn with_pool_config(config: BufferPoolConfig) -> Self {
         Self {
             frame: PhantomData,
             missing_b: Header::SIZE,
-            buffer: Buffer::new(2_usize.pow(16) * 5),
+            buffer: Buffer::new(config.capacity),
+            crc_required: false,
+            pool_config: config,
+            diagnostics: None,
+        }
+    }
+
+    /// Counters of the frames that were decoded in the buffer pool and of the ones that were
+    /// allocated in system memory.
+    #[cfg(feature = "with_buffer_pool")]
+    pub fn pool_stats(&self) -> buffer_sv2::PoolStats {
+        self.buffer.stats()
+    }
+
+    /// Creates a new [`WithoutNoise`] that rejects the frames without CRC, for the plaintext
+    /// connections where both ends send it.
+    pub fn with_crc() -> Self {
+        Self {
+            crc_required: true,
+            ..Self::new()
         }
     }
 }
@@ -414,4 +589,127 @@ mod tests {
         let expect = [0u8; Header::SIZE];
         assert_eq!(actual, expect);
     }
+
+    fn decode(decoder: &mut StandardDecoder<TestMessage>, bytes: &[u8]) -> Result<()> {
+        let mut bytes = bytes;
+        loop {
+            let writable = decoder.writable();
+            let (chunk, rest) = bytes.split_at(writable.len());
+            writable.copy_from_slice(chunk);
+            bytes = rest;
+            match decoder.next_frame() {
+                Err(MissingBytes(_)) => continue,
+                result => return result.map(|_| ()),
+            }
+        }
+    }
+
+    #[test]
+    fn unencrypted_frame_larger_than_max_slice_size() {
+        let mut decoder = StandardDecoder::<TestMessage>::with_pool_config(BufferPoolConfig {
+            max_slice_size: Header::SIZE + 4,
+            ..Default::default()
+        });
+        let small = [0x00, 0x00, 0x1f, 0x04, 0x00, 0x00, 1, 2, 3, 4];
+        assert!(decode(&mut decoder, &small).is_ok());
+
+        let large = [0x00, 0x00, 0x1f, 0x05, 0x00, 0x00];
+        assert_eq!(
+            decode(&mut decoder, &large).unwrap_err(),
+            crate::Error::FrameTooLarge(Header::SIZE + 5)
+        );
+        // the decoder waits for a new header
+        assert_eq!(decoder.writable().len(), Header::SIZE);
+    }
+
+    #[test]
+    fn unencrypted_frames_with_crc() {
+        let frame = Sv2Frame::from_message(TestMessage {}, 0x1f, 0, false).unwrap();
+        let encoded = crate::Encoder::<TestMessage>::with_crc()
+            .encode(frame)
+            .unwrap()
+            .to_vec();
+        assert_eq!(encoded.len(), Header::SIZE + const_sv2::SV2_FRAME_CRC_SIZE);
+        assert!(decode(&mut StandardDecoder::with_crc(), &encoded).is_ok());
+
+        let mut corrupted = encoded.clone();
+        corrupted[2] = 0x20;
+        assert!(matches!(
+            decode(&mut StandardDecoder::with_crc(), &corrupted),
+            Err(crate::Error::FramingSv2Error(
+                framing_sv2::Error::InvalidCrc
+            ))
+        ));
+        // A decoder that did not enable the CRC leaves the bit and the bytes to the protocol
+        assert!(decode(&mut StandardDecoder::new(), &corrupted).is_ok());
+
+        let frame = Sv2Frame::from_message(TestMessage {}, 0x1f, 0, false).unwrap();
+        let encoded = crate::Encoder::<TestMessage>::new()
+            .encode(frame)
+            .unwrap()
+            .to_vec();
+        assert!(decode(&mut StandardDecoder::new(), &encoded).is_ok());
+        assert!(matches!(
+            decode(&mut StandardDecoder::with_crc(), &encoded),
+            Err(crate::Error::FramingSv2Error(
+                framing_sv2::Error::MissingCrc
+            ))
+        ));
+    }
+
+    #[test]
+    fn unencrypted_diagnostics() {
+        let mut decoder = StandardDecoder::<TestMessage>::with_crc();
+        assert!(decoder.diagnostics().is_none());
+        decoder.enable_diagnostics(4);
+        let frame = Sv2Frame::from_message(TestMessage {}, 0x1f, 0, false).unwrap();
+        let encoded = crate::Encoder::<TestMessage>::with_crc()
+            .encode(frame)
+            .unwrap()
+            .to_vec();
+        assert!(decode(&mut decoder, &encoded).is_ok());
+        assert!(decode(&mut decoder, &encoded).is_ok());
+        let mut corrupted = encoded.clone();
+        corrupted[2] = 0x20;
+        assert!(decode(&mut decoder, &corrupted).is_err());
+
+        let diagnostics = decoder.diagnostics().unwrap();
+        assert_eq!(diagnostics.frame_count(0x1f), 2);
+        assert_eq!(diagnostics.total_frames(), 2);
+        let errors: Vec<_> = diagnostics.last_errors().collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].frames_before, 2);
+        assert_eq!(errors[0].header.unwrap()[..], corrupted[..Header::SIZE]);
+    }
+
+    #[cfg(feature = "frame_trace")]
+    #[test]
+    fn unencrypted_frames_are_traced() {
+        let frame = Sv2Frame::from_message(TestMessage {}, 0x1f, 0, false).unwrap();
+        assert!(frame.trace().is_none());
+        let encoded = crate::Encoder::<TestMessage>::new()
+            .encode(frame)
+            .unwrap()
+            .to_vec();
+
+        let mut decoder = StandardDecoder::<TestMessage>::new();
+        let mut traces = Vec::new();
+        for _ in 0..2 {
+            let mut bytes = &encoded[..];
+            let frame = loop {
+                let writable = decoder.writable();
+                let (chunk, rest) = bytes.split_at(writable.len());
+                writable.copy_from_slice(chunk);
+                bytes = rest;
+                match decoder.next_frame() {
+                    Err(MissingBytes(_)) => continue,
+                    result => break result.unwrap(),
+                }
+            };
+            // The trace is kept when the payload type changes
+            traces.push(frame.map(|_| ()).trace().unwrap());
+        }
+        assert!(traces[1].id() > traces[0].id());
+        assert!(traces[1].received_at() >= traces[0].received_at());
+    }
 }
@@ -0,0 +1,132 @@
+// # Decoder Diagnostics
+//
+// Optional bookkeeping of a decoder, meant to be attached to bug reports when a role does not
+// understand the frames of another implementation.
+//
+// When enabled on a decoder, [`DecoderDiagnostics`] counts the decoded frames by message type and
+// keeps the last decode errors together with the header of the offending frame. Missing bytes are
+// not errors and are not recorded.
+
+use crate::Error;
+use alloc::{collections::VecDeque, string::String};
+use framing_sv2::header::Header;
+
+/// Number of decode errors kept when no other limit is given.
+pub const DEFAULT_MAX_DECODE_ERRORS: usize = 16;
+
+/// A frame that could not be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeErrorRecord {
+    /// Number of frames successfully decoded before this error.
+    pub frames_before: u64,
+    /// The error, as displayed.
+    pub error: String,
+    /// Raw header of the frame, decrypted for Noise connections, if it has been received.
+    pub header: Option<[u8; Header::SIZE]>,
+}
+
+/// Frame counts by message type and last decode errors of a decoder.
+#[derive(Debug, Clone)]
+pub struct DecoderDiagnostics {
+    frames: [u64; 256],
+    total_frames: u64,
+    errors: VecDeque<DecodeErrorRecord>,
+    max_errors: usize,
+}
+
+impl Default for DecoderDiagnostics {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_DECODE_ERRORS)
+    }
+}
+
+impl DecoderDiagnostics {
+    /// Keeps the last `max_errors` decode errors.
+    pub fn new(max_errors: usize) -> Self {
+        Self {
+            frames: [0; 256],
+            total_frames: 0,
+            errors: VecDeque::with_capacity(max_errors),
+            max_errors,
+        }
+    }
+
+    /// Number of decoded frames with message type `msg_type`.
+    pub fn frame_count(&self, msg_type: u8) -> u64 {
+        self.frames[msg_type as usize]
+    }
+
+    /// Message types decoded at least once, with their number of frames.
+    pub fn frame_counts(&self) -> impl Iterator<Item = (u8, u64)> + '_ {
+        self.frames
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(msg_type, count)| (msg_type as u8, *count))
+    }
+
+    /// Number of decoded frames, handshake frames excluded.
+    pub fn total_frames(&self) -> u64 {
+        self.total_frames
+    }
+
+    /// Last decode errors, the oldest first.
+    pub fn last_errors(&self) -> impl Iterator<Item = &DecodeErrorRecord> {
+        self.errors.iter()
+    }
+
+    pub(crate) fn on_frame(&mut self, header: Option<Header>) {
+        if let Some(header) = header {
+            self.frames[header.msg_type() as usize] += 1;
+            self.total_frames += 1;
+        }
+    }
+
+    pub(crate) fn on_error(&mut self, error: &Error, header: Option<[u8; Header::SIZE]>) {
+        if matches!(error, Error::MissingBytes(_)) || self.max_errors == 0 {
+            return;
+        }
+        if self.errors.len() == self.max_errors {
+            self.errors.pop_front();
+        }
+        self.errors.push_back(DecodeErrorRecord {
+            frames_before: self.total_frames,
+            error: alloc::format!("{}", error),
+            header,
+        });
+    }
+}
+
+// The header at the start of `bytes`, if it is complete.
+pub(crate) fn header_bytes(bytes: &[u8]) -> Option<[u8; Header::SIZE]> {
+    let mut header = [0; Header::SIZE];
+    header.copy_from_slice(bytes.get(..Header::SIZE)?);
+    Some(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_errors_ring_buffer() {
+        let mut diagnostics = DecoderDiagnostics::new(2);
+        diagnostics.on_error(&Error::MissingBytes(6), None);
+        assert_eq!(diagnostics.last_errors().count(), 0);
+
+        let header = [0, 0, 0x1f, 0, 0, 0];
+        diagnostics.on_frame(Header::from_bytes(&header).ok());
+        diagnostics.on_error(&Error::FrameTooLarge(1), None);
+        diagnostics.on_error(&Error::FrameTooLarge(2), Some(header));
+        diagnostics.on_error(&Error::FrameTooLarge(3), Some(header));
+        let errors: Vec<_> = diagnostics.last_errors().collect();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].error, Error::FrameTooLarge(2).to_string());
+        assert_eq!(errors[1].header, Some(header));
+        assert_eq!(errors[1].frames_before, 1);
+        assert_eq!(
+            diagnostics.frame_counts().collect::<Vec<_>>(),
+            vec![(0x1f, 1)]
+        );
+    }
+}
@@ -24,7 +24,9 @@
 use alloc::vec::Vec;
 use binary_sv2::{GetSize, Serialize};
 #[allow(unused_imports)]
-pub use const_sv2::{AEAD_MAC_LEN, SV2_FRAME_CHUNK_SIZE, SV2_FRAME_HEADER_SIZE};
+pub use const_sv2::{
+    AEAD_MAC_LEN, SV2_FRAME_CHUNK_SIZE, SV2_FRAME_CRC_SIZE, SV2_FRAME_HEADER_SIZE,
+};
 #[cfg(feature = "noise_sv2")]
 use core::convert::TryInto;
 use core::marker::PhantomData;
@@ -38,7 +40,7 @@ pub use framing_sv2::header::NOISE_HEADER_ENCRYPTED_SIZE;
 use tracing::error;
 
 #[cfg(feature = "noise_sv2")]
-use crate::{Error, Result, State};
+use crate::{pool::BufferPoolConfig, Error, Result, State};
 
 #[cfg(feature = "noise_sv2")]
 #[cfg(not(feature = "with_buffer_pool"))]
@@ -108,6 +110,9 @@ pub struct NoiseEncoder<T: Serialize + binary_sv2::GetSize> {
     // ensuring that the encoder can handle different message types correctly during the encoding
     // process.
     frame: PhantomData<T>,
+
+    // Maximum frame size and behaviour when the buffers are full.
+    pool_config: BufferPoolConfig,
 }
 
 // A Sv2 frame that will be encoded and optionally encrypted using the Noise protocol.
@@ -140,6 +145,7 @@ impl<T: Serialize + GetSize> NoiseEncoder<T> {
         match state {
             State::Transport(noise_codec) => {
                 let len = item.encoded_length();
+                self.pool_config.check_size(len)?;
                 let writable = self.sv2_buffer.get_writable(len);
 
                 // ENCODE THE SV2 FRAME
@@ -181,10 +187,13 @@ impl<T: Serialize + GetSize> NoiseEncoder<T> {
             State::NotInitialized(_) => self.while_handshaking(item)?,
         };
 
+        let exhausted = self.sv2_buffer.is_exhausted() || self.noise_buffer.is_exhausted();
         // Clear sv2_buffer
         self.sv2_buffer.get_data_owned();
         // Return noise_buffer
-        Ok(self.noise_buffer.get_data_owned())
+        let encoded = self.noise_buffer.get_data_owned();
+        self.pool_config.check_exhausted(exhausted)?;
+        Ok(encoded)
     }
 
     // Encodes Sv2 frames during the handshake phase of the Noise protocol.
@@ -220,13 +229,35 @@ impl<T: Serialize + binary_sv2::GetSize> NoiseEncoder<T> {
     /// Creates a new `NoiseEncoder` with default buffer sizes.
     pub fn new() -> Self {
         #[cfg(not(feature = "with_buffer_pool"))]
-        let size = 512;
+        let capacity = 512;
         #[cfg(feature = "with_buffer_pool")]
-        let size = 2_usize.pow(16) * 5;
+        let capacity = crate::DEFAULT_POOL_CAPACITY;
+        Self::with_pool_config(BufferPoolConfig {
+            capacity,
+            ..Default::default()
+        })
+    }
+
+    /// Creates a new `NoiseEncoder` with buffers sized as in `config`. A frame bigger than
+    /// `config.max_slice_size` fails with [`Error::FrameTooLarge`].
+    pub fn with_pool_config(config: BufferPoolConfig) -> Self {
         Self {
-            sv2_buffer: Buffer::new(size),
-            noise_buffer: Buffer::new(size),
+            sv2_buffer: Buffer::new(config.capacity),
+            noise_buffer: Buffer::new(config.capacity),
             frame: core::marker::PhantomData,
+            pool_config: config,
+        }
+    }
+
+    /// Counters of the frames that were encoded in the buffer pools and of the ones that were
+    /// allocated in system memory.
+    #[cfg(feature = "with_buffer_pool")]
+    pub fn pool_stats(&self) -> buffer_sv2::PoolStats {
+        let noise = self.noise_buffer.stats();
+        let sv2 = self.sv2_buffer.stats();
+        buffer_sv2::PoolStats {
+            hits: noise.hits + sv2.hits,
+            misses: noise.misses + sv2.misses,
         }
     }
 }
@@ -259,6 +290,9 @@ pub struct Encoder<T> {
     // ensuring that the encoder can handle different message types correctly during the encoding
     // process.
     frame: PhantomData<T>,
+
+    // Appends a CRC to the frames that do not have one, see [`framing_sv2::crc`].
+    crc: bool,
 }
 
 impl<T: Serialize + GetSize> Encoder<T> {
@@ -273,10 +307,28 @@ impl<T: Serialize + GetSize> Encoder<T> {
         item: Sv2Frame<T, Slice>,
     ) -> core::result::Result<&[u8], crate::Error> {
         let len = item.encoded_length();
+        let append_crc = self.crc && !item.has_crc();
 
-        self.buffer.resize(len, 0);
-
-        item.serialize(&mut self.buffer)?;
+        self.buffer.clear();
+        self.buffer.reserve(len + SV2_FRAME_CRC_SIZE);
+        // The frame is written in the spare capacity of the buffer without zero filling it first
+        #[cfg(not(feature = "with_serde"))]
+        {
+            let written = item
+                .serialize_uninit(self.buffer.spare_capacity_mut())?
+                .len();
+            // Safe the first `written` bytes have been initialized by `serialize_uninit`
+            unsafe { self.buffer.set_len(written) };
+        }
+        #[cfg(feature = "with_serde")]
+        {
+            self.buffer.resize(len, 0);
+            item.serialize(&mut self.buffer)?;
+        }
+        if append_crc {
+            self.buffer.extend_from_slice(&[0; SV2_FRAME_CRC_SIZE]);
+            framing_sv2::crc::append_crc(&mut self.buffer)?;
+        }
 
         Ok(&self.buffer[..])
     }
@@ -286,6 +338,16 @@ impl<T: Serialize + GetSize> Encoder<T> {
         Self {
             buffer: Vec::with_capacity(512),
             frame: core::marker::PhantomData,
+            crc: false,
+        }
+    }
+
+    /// Creates a new `Encoder` that ends every frame with a CRC, for the plaintext connections
+    /// where both ends check it.
+    pub fn with_crc() -> Self {
+        Self {
+            crc: true,
+            ..Self::new()
         }
     }
 }
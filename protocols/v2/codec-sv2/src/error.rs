@@ -36,6 +36,13 @@ pub enum Error {
     /// Framing Sv2 error.
     FramingSv2Error(framing_sv2::Error),
 
+    /// The frame, of the given size, is bigger than the configured maximum slice size.
+    FrameTooLarge(usize),
+
+    /// The Noise handshake did not complete before its deadline.
+    #[cfg(feature = "noise_sv2")]
+    HandshakeTimeout,
+
     /// Invalid step for initiator in the Noise protocol.
     #[cfg(feature = "noise_sv2")]
     InvalidStepForInitiator,
@@ -55,6 +62,9 @@ pub enum Error {
     #[cfg(feature = "noise_sv2")]
     NotInHandShakeState,
 
+    /// The buffer pool is full and its exhaustion policy does not allow to allocate.
+    PoolExhausted,
+
     /// Unexpected state in the Noise protocol.
     UnexpectedNoiseState,
 }
@@ -68,6 +78,9 @@ impl fmt::Display for Error {
             BinarySv2Error(e) => write!(f, "Binary Sv2 Error: `{:?}`", e),
             FramingError(e) => write!(f, "Framing error in codec: `{:?}`", e),
             FramingSv2Error(e) => write!(f, "Framing Sv2 Error: `{:?}`", e),
+            FrameTooLarge(len) => write!(f, "Frame of `{}` bytes is too large", len),
+            #[cfg(feature = "noise_sv2")]
+            HandshakeTimeout => write!(f, "Noise handshake timed out"),
             #[cfg(feature = "noise_sv2")]
             InvalidStepForInitiator => write!(
                 f,
@@ -86,6 +99,7 @@ impl fmt::Display for Error {
                 f,
                 "This operation can be executed only during the noise handshake"
             ),
+            PoolExhausted => write!(f, "Buffer pool is exhausted"),
             UnexpectedNoiseState => {
                 write!(f, "Noise state is incorrect")
             }
@@ -138,6 +152,12 @@ pub enum CError {
     /// Framing Sv2 error.
     FramingSv2Error,
 
+    /// The frame, of the given size, is bigger than the configured maximum slice size.
+    FrameTooLarge(usize),
+
+    /// The Noise handshake did not complete before its deadline.
+    HandshakeTimeout,
+
     /// Invalid step for initiator in the Noise protocol.
     InvalidStepForInitiator,
 
@@ -153,6 +173,9 @@ pub enum CError {
     /// Noise protocol is not in the expected handshake state.
     NotInHandShakeState,
 
+    /// The buffer pool is full and its exhaustion policy does not allow to allocate.
+    PoolExhausted,
+
     /// Unexpected state in the Noise protocol.
     UnexpectedNoiseState,
 }
@@ -175,6 +198,9 @@ impl From<Error> for CError {
             Error::BinarySv2Error(_) => CError::BinarySv2Error,
             Error::FramingSv2Error(_) => CError::FramingSv2Error,
             Error::FramingError(_) => CError::FramingError,
+            Error::FrameTooLarge(len) => CError::FrameTooLarge(len),
+            #[cfg(feature = "noise_sv2")]
+            Error::HandshakeTimeout => CError::HandshakeTimeout,
             #[cfg(feature = "noise_sv2")]
             Error::InvalidStepForInitiator => CError::InvalidStepForInitiator,
             #[cfg(feature = "noise_sv2")]
@@ -184,6 +210,7 @@ impl From<Error> for CError {
             Error::NoiseSv2Error(_) => CError::NoiseSv2Error,
             #[cfg(feature = "noise_sv2")]
             Error::NotInHandShakeState => CError::NotInHandShakeState,
+            Error::PoolExhausted => CError::PoolExhausted,
             Error::UnexpectedNoiseState => CError::UnexpectedNoiseState,
         }
     }
@@ -196,11 +223,14 @@ impl Drop for CError {
             CError::BinarySv2Error => (),
             CError::FramingError => (),
             CError::FramingSv2Error => (),
+            CError::FrameTooLarge(_) => (),
+            CError::HandshakeTimeout => (),
             CError::InvalidStepForInitiator => (),
             CError::InvalidStepForResponder => (),
             CError::MissingBytes(_) => (),
             CError::NoiseSv2Error => (),
             CError::NotInHandShakeState => (),
+            CError::PoolExhausted => (),
             CError::UnexpectedNoiseState => (),
         };
     }
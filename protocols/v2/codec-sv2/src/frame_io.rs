@@ -0,0 +1,171 @@
+//! Async helpers to read and write standard Sv2 frames.
+//!
+//! [`read_frame`] and [`write_frame`] work with any reader or writer implementing the
+//! `futures-io` traits, so they do not depend on a runtime (`async-std` streams implement them,
+//! `tokio` ones through `tokio-util::compat`). [`read_frame`] reads the header first and then the
+//! payload it announces, a frame bigger than the max slice size of the decoder fails with
+//! [`Error::FrameTooLarge`] before its payload is read.
+use crate::{Encoder, Error, StandardDecoder, StandardSv2Frame};
+use binary_sv2::{GetSize, Serialize};
+use core::{fmt, future::poll_fn, pin::Pin};
+use futures_io::{AsyncRead, AsyncWrite};
+use std::io;
+
+#[derive(Debug)]
+pub enum FrameIoError {
+    /// The reader or the writer failed, `UnexpectedEof` if the connection was closed
+    Io(io::Error),
+    Codec(Error),
+}
+
+impl fmt::Display for FrameIoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameIoError::Io(e) => write!(f, "IO error: `{}`", e),
+            FrameIoError::Codec(e) => write!(f, "Codec error: `{}`", e),
+        }
+    }
+}
+
+impl From<io::Error> for FrameIoError {
+    fn from(e: io::Error) -> Self {
+        FrameIoError::Io(e)
+    }
+}
+
+impl From<Error> for FrameIoError {
+    fn from(e: Error) -> Self {
+        FrameIoError::Codec(e)
+    }
+}
+
+/// Reads the next frame from `reader`. The partial frames are kept in `decoder`, use the same
+/// decoder for all the frames of a connection.
+pub async fn read_frame<R, T>(
+    reader: &mut R,
+    decoder: &mut StandardDecoder<T>,
+) -> Result<StandardSv2Frame<T>, FrameIoError>
+where
+    R: AsyncRead + Unpin,
+    T: Serialize + GetSize,
+{
+    loop {
+        read_exact(reader, decoder.writable()).await?;
+        match decoder.next_frame() {
+            Ok(frame) => return Ok(frame),
+            Err(Error::MissingBytes(_)) => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Encodes `frame` with `encoder` and writes it to `writer`
+pub async fn write_frame<W, T>(
+    writer: &mut W,
+    encoder: &mut Encoder<T>,
+    frame: StandardSv2Frame<T>,
+) -> Result<(), FrameIoError>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize + GetSize,
+{
+    let bytes = encoder.encode(frame)?;
+    write_all(writer, bytes).await?;
+    poll_fn(|cx| Pin::new(&mut *writer).poll_flush(cx)).await?;
+    Ok(())
+}
+
+async fn read_exact<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> io::Result<()> {
+    let mut read = 0;
+    while read < buf.len() {
+        let n = poll_fn(|cx| Pin::new(&mut *reader).poll_read(cx, &mut buf[read..])).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        read += n;
+    }
+    Ok(())
+}
+
+async fn write_all<W: AsyncWrite + Unpin>(writer: &mut W, buf: &[u8]) -> io::Result<()> {
+    let mut written = 0;
+    while written < buf.len() {
+        let n = poll_fn(|cx| Pin::new(&mut *writer).poll_write(cx, &buf[written..])).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        written += n;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BufferPoolConfig;
+    use binary_sv2::{binary_codec_sv2, Deserialize, Serialize};
+    use core::convert::TryInto;
+    use framing_sv2::header::Header;
+    use futures::{executor::block_on, io::Cursor};
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct TestMessage {
+        a: u32,
+        b: u64,
+    }
+
+    fn frame(a: u32) -> StandardSv2Frame<TestMessage> {
+        StandardSv2Frame::from_message(TestMessage { a, b: 7 }, 0x10, 0, false).unwrap()
+    }
+
+    #[test]
+    fn test_write_and_read_frames() {
+        block_on(async {
+            let mut writer = Cursor::new(Vec::new());
+            let mut encoder = Encoder::new();
+            write_frame(&mut writer, &mut encoder, frame(1))
+                .await
+                .unwrap();
+            write_frame(&mut writer, &mut encoder, frame(2))
+                .await
+                .unwrap();
+
+            let mut reader = Cursor::new(writer.into_inner());
+            let mut decoder = StandardDecoder::<TestMessage>::new();
+            for expected in 1..=2 {
+                let mut frame = read_frame(&mut reader, &mut decoder).await.unwrap();
+                let header = frame.get_header().unwrap();
+                assert_eq!(header.msg_type(), 0x10);
+                let message: TestMessage = binary_sv2::from_bytes(frame.payload()).unwrap();
+                assert_eq!(message.a, expected);
+            }
+            // The connection is closed
+            assert!(matches!(
+                read_frame(&mut reader, &mut decoder).await,
+                Err(FrameIoError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+            ));
+        })
+    }
+
+    #[test]
+    fn test_read_frame_too_large() {
+        block_on(async {
+            let mut writer = Cursor::new(Vec::new());
+            write_frame(&mut writer, &mut Encoder::new(), frame(1))
+                .await
+                .unwrap();
+
+            let mut reader = Cursor::new(writer.into_inner());
+            let mut decoder = StandardDecoder::<TestMessage>::with_pool_config(BufferPoolConfig {
+                max_slice_size: Header::SIZE + 4,
+                ..BufferPoolConfig::default()
+            });
+            assert!(matches!(
+                read_frame(&mut reader, &mut decoder).await,
+                Err(FrameIoError::Codec(Error::FrameTooLarge(_)))
+            ));
+            // Only the header has been read
+            assert_eq!(reader.position(), Header::SIZE as u64);
+        })
+    }
+}
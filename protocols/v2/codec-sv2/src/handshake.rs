@@ -0,0 +1,136 @@
+//! Noise handshake with a deadline.
+//!
+//! A peer that stops answering in the middle of the handshake would otherwise hold the
+//! connection forever. [`HandshakeDriver`] runs the steps of [`State`] and fails them with
+//! [`Error::HandshakeTimeout`] once the handshake took longer than its timeout. While waiting
+//! for a handshake message the caller bounds the wait with [`HandshakeDriver::remaining`].
+use crate::{Error, HandShakeFrame, HandshakeRole, State};
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct HandshakeDriver {
+    state: State,
+    deadline: Option<Instant>,
+}
+
+impl HandshakeDriver {
+    /// Starts the handshake as `role`, it expires after `timeout` if set
+    pub fn new(role: HandshakeRole, timeout: Option<Duration>) -> Self {
+        Self {
+            state: State::initialized(role),
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+        }
+    }
+
+    /// Time left before the handshake expires, `None` if it never expires
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Fails with [`Error::HandshakeTimeout`] if the handshake expired
+    pub fn check_deadline(&self) -> Result<(), Error> {
+        match self.remaining() {
+            Some(remaining) if remaining.is_zero() => Err(Error::HandshakeTimeout),
+            _ => Ok(()),
+        }
+    }
+
+    /// [`State::step_0`], if the handshake did not expire
+    pub fn step_0(&mut self) -> Result<HandShakeFrame, Error> {
+        self.check_deadline()?;
+        self.state.step_0()
+    }
+
+    /// [`State::step_1`], if the handshake did not expire
+    pub fn step_1(
+        &mut self,
+        re_pub: [u8; const_sv2::RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE],
+    ) -> Result<(HandShakeFrame, State), Error> {
+        self.check_deadline()?;
+        self.state.step_1(re_pub)
+    }
+
+    /// [`State::step_2`], if the handshake did not expire
+    pub fn step_2(
+        &mut self,
+        message: [u8; const_sv2::INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE],
+    ) -> Result<State, Error> {
+        self.check_deadline()?;
+        self.state.step_2(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Initiator, Responder};
+    use core::convert::TryInto;
+
+    fn roles() -> (HandshakeRole, HandshakeRole) {
+        let public_key: key_utils::Secp256k1PublicKey =
+            "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72"
+                .to_string()
+                .try_into()
+                .unwrap();
+        let secret_key: key_utils::Secp256k1SecretKey =
+            "mkDLTBBRxdBv998612qipDYoTK3YUrqLe8uWw7gu3iXbSrn2n"
+                .to_string()
+                .try_into()
+                .unwrap();
+        let initiator = Initiator::from_raw_k(public_key.into_bytes()).unwrap();
+        let responder = Responder::from_authority_kp(
+            &public_key.into_bytes(),
+            &secret_key.into_bytes(),
+            Duration::from_secs(3600),
+        )
+        .unwrap();
+        (
+            HandshakeRole::Initiator(initiator),
+            HandshakeRole::Responder(responder),
+        )
+    }
+
+    #[test]
+    fn handshake_completes_before_the_deadline() {
+        let (initiator, responder) = roles();
+        let mut initiator = HandshakeDriver::new(initiator, Some(Duration::from_secs(60)));
+        let mut responder = HandshakeDriver::new(responder, None);
+        assert_eq!(responder.remaining(), None);
+
+        let first = initiator.step_0().unwrap().get_payload_when_handshaking();
+        let (second, _) = responder.step_1(first.try_into().unwrap()).unwrap();
+        let second = second.get_payload_when_handshaking();
+        assert!(matches!(
+            initiator.step_2(second.try_into().unwrap()),
+            Ok(State::Transport(_))
+        ));
+    }
+
+    #[test]
+    fn rejected_handshake_fails() {
+        let (initiator, responder) = roles();
+        let responder = match responder {
+            HandshakeRole::Responder(mut responder) => {
+                responder.set_pre_handshake_hook(Box::new(|_| false));
+                HandshakeRole::Responder(responder)
+            }
+            HandshakeRole::Initiator(_) => unreachable!(),
+        };
+        let mut initiator = HandshakeDriver::new(initiator, None);
+        let mut responder = HandshakeDriver::new(responder, None);
+        let first = initiator.step_0().unwrap().get_payload_when_handshaking();
+        assert_eq!(
+            responder.step_1(first.try_into().unwrap()).unwrap_err(),
+            Error::NoiseSv2Error(noise_sv2::Error::HandshakeRejected)
+        );
+    }
+
+    #[test]
+    fn expired_handshake_fails() {
+        let (initiator, _) = roles();
+        let mut initiator = HandshakeDriver::new(initiator, Some(Duration::ZERO));
+        assert_eq!(initiator.remaining(), Some(Duration::ZERO));
+        assert_eq!(initiator.step_0().unwrap_err(), Error::HandshakeTimeout);
+    }
+}
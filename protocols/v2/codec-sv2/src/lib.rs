@@ -21,6 +21,10 @@
 //!
 //! - `noise_sv2`: Enables support for Noise protocol encryption and decryption.
 //! - `with_buffer_pool`: Enables buffer pooling for more efficient memory management.
+//! - `frame_trace`: the decoded frames carry a receive timestamp and a trace id, to measure the
+//!   processing latency of each message, see `framing_sv2::trace`.
+//! - `async_io`: Enables [`read_frame`] and [`write_frame`], to read and write standard Sv2 frames
+//!   on any `futures-io` reader or writer.
 //! - `with_serde`: builds [`binary_sv2`] and [`buffer_sv2`] crates with `serde`-based encoding and
 //!   decoding. Note that this feature flag is only used for the Message Generator, and deprecated
 //!   for any other kind of usage. It will likely be fully deprecated in the future.
@@ -42,12 +46,23 @@ extern crate alloc;
 use alloc::boxed::Box;
 
 mod decoder;
+mod diagnostics;
 mod encoder;
 pub mod error;
+#[cfg(all(feature = "async_io", not(feature = "no_std")))]
+mod frame_io;
+#[cfg(all(feature = "noise_sv2", not(feature = "no_std")))]
+mod handshake;
+mod pool;
 
 pub use error::{CError, Error, Result};
 
+#[cfg(feature = "with_buffer_pool")]
+pub use buffer_sv2::PoolStats;
+pub use pool::{BufferPoolConfig, ExhaustionPolicy, DEFAULT_POOL_CAPACITY};
+
 pub use decoder::{StandardEitherFrame, StandardSv2Frame};
+pub use diagnostics::{DecodeErrorRecord, DecoderDiagnostics, DEFAULT_MAX_DECODE_ERRORS};
 
 pub use decoder::StandardDecoder;
 #[cfg(feature = "noise_sv2")]
@@ -56,6 +71,11 @@ pub use decoder::StandardNoiseDecoder;
 pub use encoder::Encoder;
 #[cfg(feature = "noise_sv2")]
 pub use encoder::NoiseEncoder;
+#[cfg(all(feature = "async_io", not(feature = "no_std")))]
+pub use frame_io::{read_frame, write_frame, FrameIoError};
+
+#[cfg(all(feature = "noise_sv2", not(feature = "no_std")))]
+pub use handshake::HandshakeDriver;
 
 #[cfg(feature = "noise_sv2")]
 pub use framing_sv2::framing::HandShakeFrame;
@@ -151,7 +171,9 @@ impl State {
     ///
     /// The responder receives the public key from the initiator, generates a response message
     /// containing the handshake frame, and prepares the [`NoiseCodec`] for transitioning the
-    /// initiator state to transport mode in `step_2`.
+    /// initiator state to transport mode in `step_2`. Fails with
+    /// [`noise_sv2::Error::HandshakeRejected`] if the pre-handshake hook of the responder rejects
+    /// the initiator.
     ///
     /// nb: Returns a new state [`State::Transport`] but does not update the current state
     /// (`self`). The caller is responsible for updating the state, allowing for more flexible
@@ -163,6 +185,8 @@ impl State {
         match self {
             Self::HandShake(h) => match h {
                 HandshakeRole::Responder(r) => {
+                    // Tells a rejected handshake from a failed one
+                    r.admit(&re_pub)?;
                     let (message, codec) = r.step_1(re_pub)?;
                     Ok((h2f(message), Self::Transport(codec)))
                 }
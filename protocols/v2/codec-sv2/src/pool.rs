@@ -0,0 +1,58 @@
+// # Buffer Pool Configuration
+//
+// Sizing of the buffers of the decoders and of the [`crate::NoiseEncoder`]. With the
+// `with_buffer_pool` feature the buffers are [`buffer_sv2::BufferPool`]s: the frames are written
+// in a preallocated memory of `capacity` bytes and are only allocated in system memory when the
+// pool is full. The [`ExhaustionPolicy`] tells whether this is allowed.
+
+/// Size of the buffers when not configured.
+pub const DEFAULT_POOL_CAPACITY: usize = 2_usize.pow(16) * 5;
+
+/// What the decoders and the encoders do with a frame that does not fit in their buffer pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExhaustionPolicy {
+    /// The frame is allocated in system memory.
+    Alloc,
+    /// The frame is dropped with [`crate::Error::PoolExhausted`], so that the caller can slow
+    /// down until the slices in use are dropped, or close the connection.
+    Error,
+}
+
+/// Sizing of the buffers of a decoder or of an encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferPoolConfig {
+    /// Size in bytes of each buffer.
+    pub capacity: usize,
+    /// Size of the largest frame, a bigger frame fails with [`crate::Error::FrameTooLarge`].
+    pub max_slice_size: usize,
+    /// What to do when the pool is full.
+    pub exhaustion: ExhaustionPolicy,
+}
+
+impl Default for BufferPoolConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_POOL_CAPACITY,
+            max_slice_size: usize::MAX,
+            exhaustion: ExhaustionPolicy::Alloc,
+        }
+    }
+}
+
+impl BufferPoolConfig {
+    // Fails if the policy does not allow the frame to be allocated out of the pool.
+    pub(crate) fn check_exhausted(&self, exhausted: bool) -> crate::Result<()> {
+        match (exhausted, self.exhaustion) {
+            (true, ExhaustionPolicy::Error) => Err(crate::Error::PoolExhausted),
+            _ => Ok(()),
+        }
+    }
+
+    // Fails if a frame of `len` bytes is bigger than `max_slice_size`.
+    pub(crate) fn check_size(&self, len: usize) -> crate::Result<()> {
+        match len > self.max_slice_size {
+            true => Err(crate::Error::FrameTooLarge(len)),
+            false => Ok(()),
+        }
+    }
+}
@@ -42,9 +42,16 @@
 /// extensions.
 pub const EXTENSION_TYPE_NO_EXTENSION: u16 = 0;
 
+/// Bit of the extension_type set on the frames that end with a CRC32 of their header and
+/// payload. Only used on plaintext connections, when both ends are configured to use it.
+pub const EXTENSION_TYPE_FRAME_CRC: u16 = 0b0100_0000_0000_0000;
+
 /// Size of the SV2 frame header in bytes.
 pub const SV2_FRAME_HEADER_SIZE: usize = 6;
 
+/// Size of the CRC32 at the end of the frames with [`EXTENSION_TYPE_FRAME_CRC`] set.
+pub const SV2_FRAME_CRC_SIZE: usize = 4;
+
 // It's not used anywhere.
 // Refactoring: deprecate it.
 pub const SV2_FRAME_HEADER_LEN_OFFSET: usize = 3;
@@ -248,3 +255,16 @@ pub const CHANNEL_BIT_SUBMIT_SHARES_STANDARD: bool = true;
 pub const CHANNEL_BIT_SUBMIT_SHARES_SUCCESS: bool = true;
 pub const CHANNEL_BIT_UPDATE_CHANNEL: bool = true;
 pub const CHANNEL_BIT_UPDATE_CHANNEL_ERROR: bool = true;
+
+/// Implemented by the message structs of the subprotocols, gives the values of the frame header
+/// fields for the message.
+pub trait MessageType {
+    const MESSAGE_TYPE: u8;
+    const CHANNEL_BIT: bool;
+    const EXTENSION_TYPE: u16 = EXTENSION_TYPE_NO_EXTENSION;
+}
+
+/// Message type of `T`, e.g. `message_type_of::<SetTarget>()`
+pub const fn message_type_of<T: MessageType>() -> u8 {
+    T::MESSAGE_TYPE
+}
@@ -0,0 +1,117 @@
+//! CRC32 of the frames sent over plaintext connections.
+//!
+//! Noise already authenticates every frame, a plaintext connection has nothing that detects a
+//! corrupted frame. When both ends enable it, every frame has the
+//! [`const_sv2::EXTENSION_TYPE_FRAME_CRC`] bit set in the extension type and ends with the CRC32
+//! (IEEE) of the header and of the payload. The CRC is counted in `msg_length`, so a receiver
+//! finds the end of the frame as usual.
+use crate::{header::Header, Error};
+use const_sv2::SV2_FRAME_CRC_SIZE;
+
+const POLYNOMIAL: u32 = 0xedb8_8320;
+
+const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC32 (IEEE) of `data`
+pub fn crc32(data: &[u8]) -> u32 {
+    let crc = data.iter().fold(u32::MAX, |crc, b| {
+        TABLE[((crc ^ *b as u32) & 0xff) as usize] ^ (crc >> 8)
+    });
+    !crc
+}
+
+/// Writes the CRC of `frame` in its last [`SV2_FRAME_CRC_SIZE`] bytes, the header must already
+/// count them
+pub(crate) fn write_crc(frame: &mut [u8]) {
+    let end = frame.len() - SV2_FRAME_CRC_SIZE;
+    let crc = crc32(&frame[..end]);
+    frame[end..].copy_from_slice(&crc.to_le_bytes());
+}
+
+/// Adds the CRC to a serialized frame that does not have one. `frame` is the serialized frame
+/// followed by [`SV2_FRAME_CRC_SIZE`] spare bytes.
+pub fn append_crc(frame: &mut [u8]) -> Result<(), Error> {
+    let mut header = Header::from_bytes(frame)?;
+    if header.has_crc() || frame.len() != Header::SIZE + header.len() + SV2_FRAME_CRC_SIZE {
+        return Err(Error::InvalidCrc);
+    }
+    header = Header::from_len(
+        (header.len() + SV2_FRAME_CRC_SIZE) as u32,
+        header.msg_type(),
+        header.ext_type() | const_sv2::EXTENSION_TYPE_FRAME_CRC,
+    )
+    .ok_or(Error::InvalidCrc)?;
+    frame[..Header::SIZE].copy_from_slice(&header.to_raw_bytes());
+    write_crc(frame);
+    Ok(())
+}
+
+/// Checks the CRC of a serialized frame, the frames without one are accepted unless `required`
+pub fn check_crc(frame: &[u8], required: bool) -> Result<(), Error> {
+    let header = Header::from_bytes(frame)?;
+    if !header.has_crc() {
+        return match required {
+            true => Err(Error::MissingCrc),
+            false => Ok(()),
+        };
+    }
+    if header.len() < SV2_FRAME_CRC_SIZE || frame.len() != Header::SIZE + header.len() {
+        return Err(Error::InvalidCrc);
+    }
+    let end = frame.len() - SV2_FRAME_CRC_SIZE;
+    let mut expected = [0; SV2_FRAME_CRC_SIZE];
+    expected.copy_from_slice(&frame[end..]);
+    match crc32(&frame[..end]) == u32::from_le_bytes(expected) {
+        true => Ok(()),
+        false => Err(Error::InvalidCrc),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn test_append_and_check_crc() {
+        let mut frame = vec![0x00, 0x00, 0x1f, 0x02, 0x00, 0x00, 0xaa, 0xbb, 0, 0, 0, 0];
+        assert_eq!(check_crc(&frame[..8], false), Ok(()));
+        assert_eq!(check_crc(&frame[..8], true), Err(Error::MissingCrc));
+
+        append_crc(&mut frame).unwrap();
+        assert_eq!(
+            &frame[..Header::SIZE],
+            &[0x00, 0x40, 0x1f, 0x06, 0x00, 0x00]
+        );
+        assert_eq!(check_crc(&frame, true), Ok(()));
+        // a frame that already has a crc can not get another one
+        assert_eq!(append_crc(&mut frame), Err(Error::InvalidCrc));
+
+        frame[7] ^= 0x01;
+        assert_eq!(check_crc(&frame, true), Err(Error::InvalidCrc));
+    }
+}
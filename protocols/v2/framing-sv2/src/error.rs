@@ -9,6 +9,10 @@ pub enum Error {
     ExpectedHandshakeFrame,
     ExpectedSv2Frame,
     UnexpectedHeaderLength(isize),
+    /// The CRC at the end of the frame does not match its content
+    InvalidCrc,
+    /// The frame has no CRC but the connection requires it
+    MissingCrc,
 }
 
 impl fmt::Display for Error {
@@ -32,6 +36,12 @@ impl fmt::Display for Error {
                     const_sv2::SV2_FRAME_HEADER_SIZE
                 )
             }
+            InvalidCrc => {
+                write!(f, "Invalid frame CRC")
+            }
+            MissingCrc => {
+                write!(f, "Expected a frame with a CRC")
+            }
         }
     }
 }
@@ -1,7 +1,14 @@
-use crate::{header::Header, Error};
+#[cfg(feature = "frame_trace")]
+use crate::trace::FrameTrace;
+use crate::{crc, header::Header, Error};
 use alloc::vec::Vec;
+#[cfg(not(feature = "with_serde"))]
+use binary_sv2::UninitBuffer;
 use binary_sv2::{to_writer, GetSize, Serialize};
+use const_sv2::{EXTENSION_TYPE_FRAME_CRC, SV2_FRAME_CRC_SIZE};
 use core::convert::TryFrom;
+#[cfg(not(feature = "with_serde"))]
+use core::mem::MaybeUninit;
 
 #[cfg(not(feature = "with_buffer_pool"))]
 type Slice = Vec<u8>;
@@ -45,6 +52,13 @@ pub struct Sv2Frame<T, B> {
     payload: Option<T>,
     /// Serialized header + payload
     serialized: Option<B>,
+    /// The frame ends with a CRC. Only set by [`Sv2Frame::from_message_with_crc`] and by
+    /// [`Sv2Frame::check_crc`], the [`const_sv2::EXTENSION_TYPE_FRAME_CRC`] bit of the frames of
+    /// a connection that did not enable the CRC is left to the protocol.
+    crc: bool,
+    /// Set by the decoder that received the frame
+    #[cfg(feature = "frame_trace")]
+    trace: Option<FrameTrace>,
 }
 
 impl<T: Serialize + GetSize, B: AsMut<[u8]> + AsRef<[u8]>> Sv2Frame<T, B> {
@@ -66,6 +80,9 @@ impl<T: Serialize + GetSize, B: AsMut<[u8]> + AsRef<[u8]>> Sv2Frame<T, B> {
             #[cfg(feature = "with_serde")]
             to_writer(&payload, &mut dst.as_mut()[Header::SIZE..])
                 .map_err(Error::BinarySv2Error)?;
+            if self.crc {
+                crc::write_crc(&mut dst[..Header::SIZE + self.header.len()]);
+            }
             Ok(())
         } else {
             // Sv2Frame always has a payload or a serialized payload
@@ -73,14 +90,49 @@ impl<T: Serialize + GetSize, B: AsMut<[u8]> + AsRef<[u8]>> Sv2Frame<T, B> {
         }
     }
 
+    /// Like [`Sv2Frame::serialize`] but `dst` does not need to be initialized, returns the
+    /// [`Sv2Frame::encoded_length`] bytes of the frame written at the start of `dst`.
+    #[cfg(not(feature = "with_serde"))]
+    #[inline]
+    pub fn serialize_uninit(self, dst: &mut [MaybeUninit<u8>]) -> Result<&mut [u8], Error> {
+        let mut dst = UninitBuffer::new(dst);
+        if let Some(serialized) = self.serialized {
+            dst.extend_from_slice(serialized.as_ref())
+                .map_err(Error::BinarySv2Error)?;
+            Ok(dst.into_filled())
+        } else if let Some(payload) = self.payload {
+            self.header
+                .to_uninit(&mut dst)
+                .map_err(Error::BinarySv2Error)?;
+            payload.to_uninit(&mut dst).map_err(Error::BinarySv2Error)?;
+            if self.crc {
+                dst.init_zeroed(SV2_FRAME_CRC_SIZE)
+                    .map_err(Error::BinarySv2Error)?;
+            }
+            let frame = dst.into_filled();
+            if self.crc {
+                crc::write_crc(frame);
+            }
+            Ok(frame)
+        } else {
+            // Sv2Frame always has a payload or a serialized payload
+            panic!("Impossible state")
+        }
+    }
+
     /// `self` can be either serialized (`self.serialized` is `Some()`) or
     /// deserialized (`self.serialized` is `None`, `self.payload` is `Some()`).
     /// This function is only intended as a fast way to get a reference to an
     /// already serialized payload. If the frame has not yet been
     /// serialized, this function should never be used (it will panic).
+    /// The CRC at the end of the frame, if any, is not part of the payload.
     pub fn payload(&mut self) -> &mut [u8] {
+        let end = match self.crc {
+            true => Header::SIZE + self.header.len() - SV2_FRAME_CRC_SIZE,
+            false => Header::SIZE + self.header.len(),
+        };
         if let Some(serialized) = self.serialized.as_mut() {
-            &mut serialized.as_mut()[Header::SIZE..]
+            &mut serialized.as_mut()[Header::SIZE..end]
         } else {
             // panic here is the expected behaviour
             panic!("Sv2Frame is not yet serialized.")
@@ -115,6 +167,9 @@ impl<T: Serialize + GetSize, B: AsMut<[u8]> + AsRef<[u8]>> Sv2Frame<T, B> {
             header,
             payload: None,
             serialized: Some(bytes),
+            crc: false,
+            #[cfg(feature = "frame_trace")]
+            trace: None,
         }
     }
 
@@ -151,7 +206,10 @@ impl<T: Serialize + GetSize, B: AsMut<[u8]> + AsRef<[u8]>> Sv2Frame<T, B> {
         if let Some(serialized) = self.serialized.as_ref() {
             serialized.as_ref().len()
         } else if let Some(payload) = self.payload.as_ref() {
-            payload.get_size() + Header::SIZE
+            match self.crc {
+                true => payload.get_size() + Header::SIZE + SV2_FRAME_CRC_SIZE,
+                false => payload.get_size() + Header::SIZE,
+            }
         } else {
             // Sv2Frame always has a payload or a serialized payload
             panic!("Impossible state")
@@ -172,6 +230,75 @@ impl<T: Serialize + GetSize, B: AsMut<[u8]> + AsRef<[u8]>> Sv2Frame<T, B> {
             header,
             payload: Some(message),
             serialized: None,
+            crc: false,
+            #[cfg(feature = "frame_trace")]
+            trace: None,
+        })
+    }
+
+    /// Like [`Sv2Frame::from_message`] but the serialized frame ends with a CRC, see
+    /// [`crate::crc`]
+    pub fn from_message_with_crc(
+        message: T,
+        message_type: u8,
+        extension_type: u16,
+        channel_msg: bool,
+    ) -> Option<Self> {
+        let extension_type =
+            update_extension_type(extension_type, channel_msg) | EXTENSION_TYPE_FRAME_CRC;
+        let len = (message.get_size() + SV2_FRAME_CRC_SIZE) as u32;
+        Header::from_len(len, message_type, extension_type).map(|header| Self {
+            header,
+            payload: Some(message),
+            serialized: None,
+            crc: true,
+            #[cfg(feature = "frame_trace")]
+            trace: None,
+        })
+    }
+
+    /// Checks the CRC at the end of a serialized frame, only called by the decoders of the
+    /// connections that enabled the CRC. A frame without CRC is an error, a frame that is not
+    /// serialized is always accepted.
+    pub fn check_crc(&mut self) -> Result<(), Error> {
+        if let Some(serialized) = self.serialized.as_ref() {
+            crc::check_crc(serialized.as_ref(), true)?;
+            self.crc = true;
+        }
+        Ok(())
+    }
+
+    /// The frame ends with a CRC, see [`Sv2Frame::check_crc`]
+    pub fn has_crc(&self) -> bool {
+        self.crc
+    }
+}
+
+impl<T, B: AsMut<[u8]>> Sv2Frame<T, B> {
+    /// Lets `fun` modify the header, the payload is left as it is. If the frame is serialized the
+    /// header bytes are rewritten in place, so a relayed frame does not have to be decoded and
+    /// encoded again. The payload length can not be changed.
+    pub fn with_header_mut<R>(&mut self, fun: impl FnOnce(&mut Header) -> R) -> R {
+        let msg_length = self.header.len();
+        let ret = fun(&mut self.header);
+        debug_assert_eq!(msg_length, self.header.len());
+        if let Some(serialized) = self.serialized.as_mut() {
+            serialized.as_mut()[..Header::SIZE].copy_from_slice(&self.header.to_raw_bytes());
+        }
+        ret
+    }
+
+    /// Sets the message type and the extension type, the channel bit is the most significant bit
+    /// of `extension_type`. See [`Sv2Frame::with_header_mut`]. The CRC bit of a frame that ends
+    /// with a CRC is kept, as it tells the receiver to check it.
+    pub fn retag(&mut self, message_type: u8, extension_type: u16) {
+        let crc = self.crc;
+        self.with_header_mut(|header| {
+            header.set_msg_type(message_type);
+            match crc {
+                true => header.set_ext_type(extension_type | EXTENSION_TYPE_FRAME_CRC),
+                false => header.set_ext_type(extension_type),
+            }
         })
     }
 }
@@ -187,10 +314,26 @@ impl<A, B> Sv2Frame<A, B> {
             header,
             payload,
             serialized,
+            crc: self.crc,
+            #[cfg(feature = "frame_trace")]
+            trace: self.trace,
         }
     }
 }
 
+#[cfg(feature = "frame_trace")]
+impl<T, B> Sv2Frame<T, B> {
+    /// Receive timestamp and trace id, `None` if the frame was not received by a decoder
+    pub fn trace(&self) -> Option<FrameTrace> {
+        self.trace
+    }
+
+    pub fn with_trace(mut self, trace: FrameTrace) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+}
+
 impl<T, B> TryFrom<Frame<T, B>> for Sv2Frame<T, B> {
     type Error = Error;
 
@@ -269,6 +412,8 @@ fn update_extension_type(extension_type: u16, channel_msg: bool) -> u16 {
     }
 }
 
+#[cfg(test)]
+use alloc::vec;
 #[cfg(test)]
 use binary_sv2::binary_codec_sv2;
 
@@ -281,3 +426,95 @@ fn test_size_hint() {
     let h = Sv2Frame::<T, Vec<u8>>::size_hint(&[0, 128, 30, 46, 0, 0][..]);
     assert!(h == 46);
 }
+
+#[test]
+fn test_retag_serialized_frame() {
+    let payload = [0x15, 0, 0, 0, 0xff];
+    let mut bytes = vec![0x00, 0x00, 0x1f, payload.len() as u8, 0, 0];
+    bytes.extend_from_slice(&payload);
+    let mut frame = Sv2Frame::<T, Vec<u8>>::from_bytes(bytes).unwrap();
+
+    frame.retag(0x20, 0x8001);
+    let header = frame.get_header().unwrap();
+    assert_eq!((header.msg_type(), header.ext_type()), (0x20, 0x8001));
+    assert_eq!(frame.payload(), &payload[..]);
+
+    let mut dst = vec![0; frame.encoded_length()];
+    frame.serialize(&mut dst).unwrap();
+    assert_eq!(&dst[..Header::SIZE], &[0x01, 0x80, 0x20, 0x05, 0x00, 0x00]);
+    assert_eq!(&dst[Header::SIZE..], &payload[..]);
+}
+
+#[test]
+fn test_with_header_mut_on_message() {
+    let mut frame = Sv2Frame::<T, Vec<u8>>::from_message(T {}, 0x1f, 0, false).unwrap();
+    let channel_msg = frame.with_header_mut(|header| {
+        header.set_channel_msg(true);
+        header.ext_type()
+    });
+    assert_eq!(channel_msg, 0x8000);
+
+    let mut dst = vec![0; frame.encoded_length()];
+    frame.serialize(&mut dst).unwrap();
+    assert_eq!(dst, [0x00, 0x80, 0x1f, 0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn test_frame_with_crc() {
+    let mut frame = Sv2Frame::<T, Vec<u8>>::from_message_with_crc(T {}, 0x1f, 0, true).unwrap();
+    assert!(frame.has_crc());
+    assert!(frame.get_header().unwrap().has_crc());
+    assert_eq!(frame.encoded_length(), Header::SIZE + SV2_FRAME_CRC_SIZE);
+    frame.retag(0x20, 0x8000);
+    assert!(frame.get_header().unwrap().has_crc());
+
+    let mut dst = vec![0; frame.encoded_length()];
+    frame.serialize(&mut dst).unwrap();
+    assert_eq!(&dst[..Header::SIZE], &[0x00, 0xc0, 0x20, 0x04, 0x00, 0x00]);
+
+    // Not checked, the bit is left to the protocol and the CRC is part of the payload
+    let mut frame = Sv2Frame::<T, Vec<u8>>::from_bytes(dst.clone()).unwrap();
+    assert!(!frame.has_crc());
+    assert_eq!(frame.payload().len(), SV2_FRAME_CRC_SIZE);
+    frame.retag(0x20, 0x8000);
+    assert!(!frame.get_header().unwrap().has_crc());
+
+    let mut frame = Sv2Frame::<T, Vec<u8>>::from_bytes(dst.clone()).unwrap();
+    assert_eq!(frame.check_crc(), Ok(()));
+    assert!(frame.payload().is_empty());
+
+    dst[2] = 0x21;
+    let mut frame = Sv2Frame::<T, Vec<u8>>::from_bytes(dst).unwrap();
+    assert_eq!(frame.check_crc(), Err(Error::InvalidCrc));
+
+    // The extension type of a frame without CRC is not changed
+    let mut frame = Sv2Frame::<T, Vec<u8>>::from_message(T {}, 0x1f, 0, false).unwrap();
+    frame.retag(0x1f, 0x4001);
+    assert_eq!(frame.get_header().unwrap().ext_type(), 0x4001);
+}
+
+#[test]
+#[cfg(not(feature = "with_serde"))]
+fn test_serialize_uninit() {
+    use core::mem::MaybeUninit;
+
+    for crc in [false, true] {
+        let frame = || match crc {
+            true => Sv2Frame::<T, Vec<u8>>::from_message_with_crc(T {}, 0x1f, 0, true).unwrap(),
+            false => Sv2Frame::<T, Vec<u8>>::from_message(T {}, 0x1f, 0, true).unwrap(),
+        };
+        let mut expected = vec![0; frame().encoded_length()];
+        frame().serialize(&mut expected).unwrap();
+
+        let mut dst = [MaybeUninit::uninit(); 16];
+        assert_eq!(frame().serialize_uninit(&mut dst).unwrap(), &expected[..]);
+
+        // Already serialized frames are copied as they are
+        let frame = Sv2Frame::<T, Vec<u8>>::from_bytes(expected.clone()).unwrap();
+        assert_eq!(frame.serialize_uninit(&mut dst).unwrap(), &expected[..]);
+
+        let frame = Sv2Frame::<T, Vec<u8>>::from_bytes(expected.clone()).unwrap();
+        let mut dst = [MaybeUninit::uninit(); Header::SIZE - 1];
+        assert!(frame.serialize_uninit(&mut dst).is_err());
+    }
+}
@@ -54,7 +54,7 @@ impl Header {
     /// Get the payload length
     #[allow(clippy::len_without_is_empty)]
     #[inline]
-    pub(crate) fn len(&self) -> usize {
+    pub fn len(&self) -> usize {
         let inner: u32 = self.msg_length.into();
         inner as usize
     }
@@ -79,6 +79,40 @@ impl Header {
         self.extension_type
     }
 
+    /// Set the `Header` message type.
+    pub fn set_msg_type(&mut self, msg_type: u8) {
+        self.msg_type = msg_type;
+    }
+
+    /// Set the `Header` extension type, the most significant bit is the channel bit.
+    pub fn set_ext_type(&mut self, extension_type: u16) {
+        self.extension_type = extension_type;
+    }
+
+    /// Set or clear the channel bit of the `Header` extension type.
+    pub fn set_channel_msg(&mut self, channel_msg: bool) {
+        const CHANNEL_BIT: u16 = 0b1000_0000_0000_0000;
+        if channel_msg {
+            self.extension_type |= CHANNEL_BIT;
+        } else {
+            self.extension_type &= !CHANNEL_BIT;
+        }
+    }
+
+    /// Serialize the `Header`, as it is at the start of a frame
+    pub fn to_raw_bytes(&self) -> [u8; Self::SIZE] {
+        let extension_type = self.extension_type.to_le_bytes();
+        let msg_length = (self.len() as u32).to_le_bytes();
+        [
+            extension_type[0],
+            extension_type[1],
+            self.msg_type,
+            msg_length[0],
+            msg_length[1],
+            msg_length[2],
+        ]
+    }
+
     /// Check if `Header` represents a channel message
     ///
     /// A header can represent a channel message if the MSB(Most Significant Bit) is set.
@@ -87,6 +121,12 @@ impl Header {
         self.extension_type & CHANNEL_MSG_MASK == self.extension_type
     }
 
+    /// Check if the CRC bit of the extension type is set. It only means that the frame ends with
+    /// a CRC on the connections that enabled it, see [`crate::crc`].
+    pub fn has_crc(&self) -> bool {
+        self.extension_type & const_sv2::EXTENSION_TYPE_FRAME_CRC != 0
+    }
+
     /// Calculate the length of the encrypted `Header`
     pub fn encrypted_len(&self) -> usize {
         let len = self.len();
@@ -113,6 +153,20 @@ mod tests {
         assert_eq!(header.msg_length, 0x060504_u32.try_into().unwrap());
     }
 
+    #[test]
+    fn test_header_to_raw_bytes() {
+        let bytes = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+        let mut header = Header::from_bytes(&bytes).unwrap();
+        assert_eq!(header.to_raw_bytes(), bytes);
+
+        header.set_msg_type(0x15);
+        header.set_ext_type(0x0001);
+        header.set_channel_msg(true);
+        assert_eq!(header.to_raw_bytes(), [0x01, 0x80, 0x15, 0x04, 0x05, 0x06]);
+        header.set_channel_msg(false);
+        assert_eq!(header.ext_type(), 0x0001);
+    }
+
     #[test]
     fn test_header_from_len() {
         let header = Header::from_len(0x1234, 0x56, 0x789a).unwrap();
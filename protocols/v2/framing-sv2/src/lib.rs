@@ -19,6 +19,8 @@
 //!   decoding.
 //! - `with_buffer_pool`: uses `buffer_sv2` to provide a more efficient allocation method for
 //!   `non_std` environments. Please refer to `buffer_sv2` crate docs for more context.
+//! - `frame_trace`: the decoded frames carry a receive timestamp and a trace id, see [`trace`].
+//!   Requires `std`.
 //!
 //! The `with_serde` feature flag is only used for the Message Generator, and deprecated for any
 //! other kind of usage. It will likely be fully deprecated in the future.
@@ -26,6 +28,8 @@
 #![no_std]
 
 extern crate alloc;
+#[cfg(feature = "frame_trace")]
+extern crate std;
 
 /// SV2 framing types
 pub mod framing;
@@ -35,4 +39,11 @@ pub mod error;
 
 /// SV2 framing header
 pub mod header;
+
+/// CRC of the frames sent over plaintext connections
+pub mod crc;
+
+/// Receive timestamp and trace id of the decoded frames
+#[cfg(feature = "frame_trace")]
+pub mod trace;
 pub use error::Error;
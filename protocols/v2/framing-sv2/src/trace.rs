@@ -0,0 +1,50 @@
+//! Receive timestamp and trace id of the decoded frames, to measure how long a role takes to
+//! process a message.
+//!
+//! The decoders of `codec_sv2` set a [`FrameTrace`] on every frame they decode when the
+//! `frame_trace` feature is enabled. It is kept when the frame is mapped to another payload type,
+//! so a role can log [`FrameTrace::elapsed`] once it is done with the message, or carry the trace
+//! id along with the messages derived from it.
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// Ids of the frames decoded by the process
+static NEXT_TRACE_ID: AtomicU64 = AtomicU64::new(1);
+
+/// When a frame was received and its trace id, unique and increasing in the process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameTrace {
+    id: u64,
+    received_at: Instant,
+}
+
+impl FrameTrace {
+    /// Trace of a frame received now, with the next trace id
+    pub fn now() -> Self {
+        Self {
+            id: NEXT_TRACE_ID.fetch_add(1, Ordering::Relaxed),
+            received_at: Instant::now(),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn received_at(&self) -> Instant {
+        self.received_at
+    }
+
+    /// Time since the frame was received
+    pub fn elapsed(&self) -> Duration {
+        self.received_at.elapsed()
+    }
+}
+
+#[test]
+fn test_trace_ids_increase() {
+    let first = FrameTrace::now();
+    let second = FrameTrace::now();
+    assert!(second.id() > first.id());
+    assert!(second.received_at() >= first.received_at());
+}
@@ -33,7 +33,7 @@
 // within the Noise protocol, ensuring secure data handling, key management, and nonce tracking
 // throughout the communication session.
 
-use std::ptr;
+use zeroize::Zeroize;
 
 use crate::aed_cipher::AeadCipher;
 use aes_gcm::Aes256Gcm;
@@ -221,22 +221,8 @@ impl GenericCipher {
     // no longer needed.
     pub fn erase_k(&mut self) {
         match self {
-            GenericCipher::ChaCha20Poly1305(c) => {
-                if let Some(k) = c.k.as_mut() {
-                    for b in k {
-                        unsafe { ptr::write_volatile(b, 0) };
-                    }
-                    c.k = None;
-                }
-            }
-            GenericCipher::Aes256Gcm(c) => {
-                if let Some(k) = c.k.as_mut() {
-                    for b in k {
-                        unsafe { ptr::write_volatile(b, 0) };
-                    }
-                    c.k = None;
-                }
-            }
+            GenericCipher::ChaCha20Poly1305(c) => c.k.zeroize(),
+            GenericCipher::Aes256Gcm(c) => c.k.zeroize(),
         }
     }
 
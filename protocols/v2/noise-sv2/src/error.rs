@@ -42,6 +42,9 @@ pub enum Error {
 
     /// A message has an incorrect or unexpected length.
     InvalidMessageLength,
+
+    /// The pre-handshake hook of the responder rejected the handshake.
+    HandshakeRejected,
 }
 
 impl From<AesGcm> for Error {
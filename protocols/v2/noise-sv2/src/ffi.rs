@@ -0,0 +1,412 @@
+// # C API
+//
+// Exposes the handshake and the transport encryption to C, e.g. for mining firmware.
+//
+// The [`Initiator`], [`Responder`] and [`NoiseCodec`] are opaque pointers allocated by Rust, they
+// must be released with the matching `free_noise_*` function. The handshake messages are written
+// to and read from buffers owned by the caller, of the sizes defined in `const_sv2`. Encryption and
+// decryption happen in place in a [`NoiseBuffer`] owned by the caller, so that no memory is
+// allocated for each frame.
+
+use crate::{Initiator, NoiseCodec, Responder};
+use aes_gcm::aead::{Buffer, Error as AeadError};
+use const_sv2::{ELLSWIFT_ENCODING_SIZE, INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE};
+use core::{ptr, slice, time::Duration};
+use zeroize::Zeroize;
+
+/// Buffer owned by the C side, laid out like `CVec`: the first `len` bytes of `data` are used, out
+/// of `capacity`.
+///
+/// Encryption grows the content by the size of the MAC (`AEAD_MAC_LEN`), so `capacity` must be at
+/// least `len + AEAD_MAC_LEN` to encrypt a buffer.
+#[repr(C)]
+#[derive(Debug)]
+pub struct NoiseBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+/// Outcome of the C API functions.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseResult {
+    Ok,
+    /// A pointer argument is null.
+    NullPointer,
+    /// The handshake message is not valid or the responder certificate can not be verified.
+    InvalidHandshakeMessage,
+    /// The buffer is too small to hold the encrypted message.
+    BufferTooSmall,
+    /// Encryption or decryption failed, e.g. the message has been tampered with.
+    AeadError,
+}
+
+// Implements `Buffer` on the memory of a `NoiseBuffer`, the content can not grow past `capacity`.
+struct SliceBuffer<'a> {
+    data: &'a mut [u8],
+    len: usize,
+}
+
+impl AsRef<[u8]> for SliceBuffer<'_> {
+    fn as_ref(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl AsMut<[u8]> for SliceBuffer<'_> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.data[..self.len]
+    }
+}
+
+impl Buffer for SliceBuffer<'_> {
+    fn extend_from_slice(&mut self, other: &[u8]) -> Result<(), AeadError> {
+        let end = self.len + other.len();
+        if end > self.data.len() {
+            return Err(AeadError);
+        }
+        self.data[self.len..end].copy_from_slice(other);
+        self.len = end;
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+}
+
+/// Creates an initiator, `authority_public_key` is the 32 bytes x-only public key of the
+/// responder authority, or null to not verify the responder certificate.
+///
+/// Returns null if the key is not valid.
+///
+/// # Safety
+///
+/// `authority_public_key` must be null or point to 32 readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn new_noise_initiator(authority_public_key: *const u8) -> *mut Initiator {
+    let initiator = if authority_public_key.is_null() {
+        Initiator::without_pk()
+    } else {
+        let mut key = [0; 32];
+        key.copy_from_slice(slice::from_raw_parts(authority_public_key, 32));
+        Initiator::from_raw_k(key)
+    };
+    match initiator {
+        Ok(initiator) => Box::into_raw(initiator),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Creates a responder from its authority key pair, the certificates it sends are valid for
+/// `cert_validity_sec` seconds.
+///
+/// Returns null if the key pair is not valid.
+///
+/// # Safety
+///
+/// `public_key` and `private_key` must point to 32 readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn new_noise_responder(
+    public_key: *const u8,
+    private_key: *const u8,
+    cert_validity_sec: u32,
+) -> *mut Responder {
+    if public_key.is_null() || private_key.is_null() {
+        return ptr::null_mut();
+    }
+    let mut public = [0; 32];
+    public.copy_from_slice(slice::from_raw_parts(public_key, 32));
+    let mut private = [0; 32];
+    private.copy_from_slice(slice::from_raw_parts(private_key, 32));
+    let responder = Responder::from_authority_kp(
+        &public,
+        &private,
+        Duration::from_secs(cert_validity_sec.into()),
+    );
+    private.zeroize();
+    match responder {
+        Ok(responder) => Box::into_raw(responder),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Writes the first handshake message, to be sent to the responder, to `message_out`.
+///
+/// # Safety
+///
+/// `initiator` must come from [`new_noise_initiator`] and `message_out` must point to
+/// `ELLSWIFT_ENCODING_SIZE` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn noise_initiator_step_0(
+    initiator: *mut Initiator,
+    message_out: *mut u8,
+) -> NoiseResult {
+    let initiator = match initiator.as_mut() {
+        Some(initiator) if !message_out.is_null() => initiator,
+        _ => return NoiseResult::NullPointer,
+    };
+    match initiator.step_0() {
+        Ok(message) => {
+            slice::from_raw_parts_mut(message_out, ELLSWIFT_ENCODING_SIZE)
+                .copy_from_slice(&message);
+            NoiseResult::Ok
+        }
+        Err(_) => NoiseResult::AeadError,
+    }
+}
+
+/// Reads the first handshake message of the initiator from `message_in`, writes the answer to
+/// `message_out` and the codec for the rest of the connection to `codec_out`.
+///
+/// # Safety
+///
+/// `responder` must come from [`new_noise_responder`], `message_in` must point to
+/// `ELLSWIFT_ENCODING_SIZE` readable bytes, `message_out` to
+/// `INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE` writable bytes and `codec_out` to a writable
+/// pointer.
+#[no_mangle]
+pub unsafe extern "C" fn noise_responder_step_1(
+    responder: *mut Responder,
+    message_in: *const u8,
+    message_out: *mut u8,
+    codec_out: *mut *mut NoiseCodec,
+) -> NoiseResult {
+    let responder = match responder.as_mut() {
+        Some(responder)
+            if !message_in.is_null() && !message_out.is_null() && !codec_out.is_null() =>
+        {
+            responder
+        }
+        _ => return NoiseResult::NullPointer,
+    };
+    let mut message = [0; ELLSWIFT_ENCODING_SIZE];
+    message.copy_from_slice(slice::from_raw_parts(message_in, ELLSWIFT_ENCODING_SIZE));
+    match responder.step_1(message) {
+        Ok((response, codec)) => {
+            slice::from_raw_parts_mut(message_out, INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE)
+                .copy_from_slice(&response);
+            *codec_out = Box::into_raw(Box::new(codec));
+            NoiseResult::Ok
+        }
+        Err(_) => NoiseResult::InvalidHandshakeMessage,
+    }
+}
+
+/// Reads the answer of the responder from `message_in` and writes the codec for the rest of the
+/// connection to `codec_out`.
+///
+/// # Safety
+///
+/// `initiator` must come from [`new_noise_initiator`], `message_in` must point to
+/// `INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE` readable bytes and `codec_out` to a writable
+/// pointer.
+#[no_mangle]
+pub unsafe extern "C" fn noise_initiator_step_2(
+    initiator: *mut Initiator,
+    message_in: *const u8,
+    codec_out: *mut *mut NoiseCodec,
+) -> NoiseResult {
+    let initiator = match initiator.as_mut() {
+        Some(initiator) if !message_in.is_null() && !codec_out.is_null() => initiator,
+        _ => return NoiseResult::NullPointer,
+    };
+    let mut message = [0; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE];
+    message.copy_from_slice(slice::from_raw_parts(
+        message_in,
+        INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
+    ));
+    match initiator.step_2(message) {
+        Ok(codec) => {
+            *codec_out = Box::into_raw(Box::new(codec));
+            NoiseResult::Ok
+        }
+        Err(_) => NoiseResult::InvalidHandshakeMessage,
+    }
+}
+
+/// Encrypts the content of `buffer` in place, `buffer.len` grows by `AEAD_MAC_LEN`.
+///
+/// # Safety
+///
+/// `codec` must come from a handshake step and `buffer.data` must point to `buffer.capacity`
+/// writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn noise_encrypt(
+    codec: *mut NoiseCodec,
+    buffer: *mut NoiseBuffer,
+) -> NoiseResult {
+    let (codec, buffer) = match (codec.as_mut(), buffer.as_mut()) {
+        (Some(codec), Some(buffer)) if !buffer.data.is_null() => (codec, buffer),
+        _ => return NoiseResult::NullPointer,
+    };
+    if buffer.len + const_sv2::AEAD_MAC_LEN > buffer.capacity {
+        return NoiseResult::BufferTooSmall;
+    }
+    let mut slice_buffer = SliceBuffer {
+        data: slice::from_raw_parts_mut(buffer.data, buffer.capacity),
+        len: buffer.len,
+    };
+    match codec.encrypt(&mut slice_buffer) {
+        Ok(()) => {
+            buffer.len = slice_buffer.len;
+            NoiseResult::Ok
+        }
+        Err(_) => NoiseResult::AeadError,
+    }
+}
+
+/// Decrypts the content of `buffer` in place, `buffer.len` shrinks by `AEAD_MAC_LEN`.
+///
+/// # Safety
+///
+/// `codec` must come from a handshake step and `buffer.data` must point to `buffer.capacity`
+/// writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn noise_decrypt(
+    codec: *mut NoiseCodec,
+    buffer: *mut NoiseBuffer,
+) -> NoiseResult {
+    let (codec, buffer) = match (codec.as_mut(), buffer.as_mut()) {
+        (Some(codec), Some(buffer)) if !buffer.data.is_null() => (codec, buffer),
+        _ => return NoiseResult::NullPointer,
+    };
+    if buffer.len > buffer.capacity {
+        return NoiseResult::BufferTooSmall;
+    }
+    let mut slice_buffer = SliceBuffer {
+        data: slice::from_raw_parts_mut(buffer.data, buffer.capacity),
+        len: buffer.len,
+    };
+    match codec.decrypt(&mut slice_buffer) {
+        Ok(()) => {
+            buffer.len = slice_buffer.len;
+            NoiseResult::Ok
+        }
+        Err(_) => NoiseResult::AeadError,
+    }
+}
+
+/// # Safety
+///
+/// `initiator` must be null or come from [`new_noise_initiator`] and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn free_noise_initiator(initiator: *mut Initiator) {
+    if !initiator.is_null() {
+        drop(Box::from_raw(initiator));
+    }
+}
+
+/// # Safety
+///
+/// `responder` must be null or come from [`new_noise_responder`] and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn free_noise_responder(responder: *mut Responder) {
+    if !responder.is_null() {
+        drop(Box::from_raw(responder));
+    }
+}
+
+/// # Safety
+///
+/// `codec` must be null or come from a handshake step and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn free_noise_codec(codec: *mut NoiseCodec) {
+    if !codec.is_null() {
+        drop(Box::from_raw(codec));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::{Keypair, Secp256k1};
+
+    #[test]
+    fn test_c_handshake_and_transport() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::new(&secp, &mut rand::thread_rng());
+        let public = keypair.x_only_public_key().0.serialize();
+        let private = keypair.secret_bytes();
+        unsafe {
+            let initiator = new_noise_initiator(public.as_ptr());
+            let responder = new_noise_responder(public.as_ptr(), private.as_ptr(), 3600);
+            assert!(!initiator.is_null() && !responder.is_null());
+
+            let mut first = [0; ELLSWIFT_ENCODING_SIZE];
+            assert_eq!(
+                noise_initiator_step_0(initiator, first.as_mut_ptr()),
+                NoiseResult::Ok
+            );
+            let mut second = [0; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE];
+            let mut responder_codec = ptr::null_mut();
+            assert_eq!(
+                noise_responder_step_1(
+                    responder,
+                    first.as_ptr(),
+                    second.as_mut_ptr(),
+                    &mut responder_codec
+                ),
+                NoiseResult::Ok
+            );
+            let mut initiator_codec = ptr::null_mut();
+            assert_eq!(
+                noise_initiator_step_2(initiator, second.as_ptr(), &mut initiator_codec),
+                NoiseResult::Ok
+            );
+
+            let message = b"hello";
+            let mut data = [0; 5 + const_sv2::AEAD_MAC_LEN];
+            data[..5].copy_from_slice(message);
+            let mut buffer = NoiseBuffer {
+                data: data.as_mut_ptr(),
+                len: 5,
+                capacity: data.len(),
+            };
+            assert_eq!(noise_encrypt(initiator_codec, &mut buffer), NoiseResult::Ok);
+            assert_eq!(buffer.len, data.len());
+            assert_eq!(noise_decrypt(responder_codec, &mut buffer), NoiseResult::Ok);
+            assert_eq!(buffer.len, 5);
+            assert_eq!(&data[..5], message);
+
+            // No room for the MAC
+            buffer.capacity = 5;
+            assert_eq!(
+                noise_encrypt(initiator_codec, &mut buffer),
+                NoiseResult::BufferTooSmall
+            );
+
+            free_noise_codec(initiator_codec);
+            free_noise_codec(responder_codec);
+            free_noise_initiator(initiator);
+            free_noise_responder(responder);
+        }
+    }
+
+    #[test]
+    fn test_c_invalid_arguments() {
+        let private = [1; 32];
+        unsafe {
+            // The public key does not match the private key
+            assert!(new_noise_responder([2; 32].as_ptr(), private.as_ptr(), 3600).is_null());
+            assert_eq!(
+                noise_initiator_step_0(ptr::null_mut(), [0; 64].as_mut_ptr()),
+                NoiseResult::NullPointer
+            );
+            let initiator = new_noise_initiator(ptr::null());
+            assert!(!initiator.is_null());
+            let mut codec = ptr::null_mut();
+            assert_eq!(
+                noise_initiator_step_2(
+                    initiator,
+                    [0; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE].as_ptr(),
+                    &mut codec
+                ),
+                NoiseResult::InvalidHandshakeMessage
+            );
+            assert!(codec.is_null());
+            free_noise_initiator(initiator);
+        }
+    }
+}
@@ -37,6 +37,7 @@ use secp256k1::{
     hashes::{sha256::Hash as Sha256Hash, Hash},
     rand, Keypair, Secp256k1, SecretKey, XOnlyPublicKey,
 };
+use zeroize::Zeroize;
 
 // Represents the operations needed during a Noise protocol handshake.
 //
@@ -100,13 +101,20 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
     // using the [`Secp256k1`] elliptic curve. If the generated public key does not match the
     // expected parity, a new key pair is generated to ensure consistency.
     fn generate_key() -> Keypair {
+        Self::generate_key_with_rng(&mut rand::thread_rng())
+    }
+
+    // Generates a new cryptographic key pair using the provided random number generator.
+    //
+    // Same as [`HandshakeOp::generate_key`], a seeded `rng` always generates the same key pair.
+    fn generate_key_with_rng<R: rand::Rng + ?Sized>(rng: &mut R) -> Keypair {
         let secp = Secp256k1::new();
-        let (secret_key, _) = secp.generate_keypair(&mut rand::thread_rng());
+        let (secret_key, _) = secp.generate_keypair(rng);
         let kp = Keypair::from_secret_key(&secp, &secret_key);
         if kp.x_only_public_key().1 == crate::PARITY {
             kp
         } else {
-            Self::generate_key()
+            Self::generate_key_with_rng(rng)
         }
     }
 
@@ -136,13 +144,19 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
         let mut to_hash = Vec::with_capacity(64 + data.len());
         to_hash.extend_from_slice(&ipad);
         to_hash.extend_from_slice(data);
-        let temp = Sha256Hash::hash(&to_hash).to_byte_array();
+        let mut temp = Sha256Hash::hash(&to_hash).to_byte_array();
 
         to_hash.clear();
         to_hash.extend_from_slice(&opad);
         to_hash.extend_from_slice(&temp);
 
-        Sha256Hash::hash(&to_hash).to_byte_array()
+        let hash = Sha256Hash::hash(&to_hash).to_byte_array();
+        // the pads and the inner hash are derived from the key
+        ipad.zeroize();
+        opad.zeroize();
+        to_hash.zeroize();
+        temp.zeroize();
+        hash
     }
 
     // Derives two new keys using the HKDF (HMAC-based Key Derivation Function) process.
@@ -160,9 +174,10 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
     //    specific byte sequence (`0x02`).
     // 4. Returns both outputs.
     fn hkdf_2(chaining_key: &[u8; 32], input_key_material: &[u8]) -> ([u8; 32], [u8; 32]) {
-        let temp_key = Self::hmac_hash(chaining_key, input_key_material);
+        let mut temp_key = Self::hmac_hash(chaining_key, input_key_material);
         let out_1 = Self::hmac_hash(&temp_key, &[0x1]);
         let out_2 = Self::hmac_hash(&temp_key, &[&out_1[..], &[0x2][..]].concat());
+        temp_key.zeroize();
         (out_1, out_2)
     }
 
@@ -170,10 +185,11 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
         chaining_key: &[u8; 32],
         input_key_material: &[u8],
     ) -> ([u8; 32], [u8; 32], [u8; 32]) {
-        let temp_key = Self::hmac_hash(chaining_key, input_key_material);
+        let mut temp_key = Self::hmac_hash(chaining_key, input_key_material);
         let out_1 = Self::hmac_hash(&temp_key, &[0x1]);
         let out_2 = Self::hmac_hash(&temp_key, &[&out_1[..], &[0x2][..]].concat());
         let out_3 = Self::hmac_hash(&temp_key, &[&out_2[..], &[0x3][..]].concat());
+        temp_key.zeroize();
         (out_1, out_2, out_3)
     }
 
@@ -186,9 +202,10 @@ pub trait HandshakeOp<Cipher: AeadCipher>: CipherState<Cipher> {
     // use in the next step of the handshake.
     fn mix_key(&mut self, input_key_material: &[u8]) {
         let ck = self.get_ck();
-        let (ck, temp_k) = Self::hkdf_2(ck, input_key_material);
+        let (ck, mut temp_k) = Self::hkdf_2(ck, input_key_material);
         self.set_ck(ck);
         self.initialize_key(temp_k);
+        temp_k.zeroize();
     }
 
     // Encrypts the provided plaintext and updates the hash `h` value.
@@ -34,7 +34,7 @@
 // The [`Drop`] trait is implemented to automatically trigger secure erasure when the [`Initiator`]
 // instance goes out of scope, preventing potential misuse or leakage of cryptographic material.
 
-use std::{convert::TryInto, ptr};
+use std::convert::TryInto;
 
 use crate::{
     cipher_state::{Cipher, CipherState, GenericCipher},
@@ -52,8 +52,9 @@ use const_sv2::{
 };
 use secp256k1::{
     ellswift::{ElligatorSwift, ElligatorSwiftParty},
-    Keypair, PublicKey, XOnlyPublicKey,
+    rand, Keypair, PublicKey, XOnlyPublicKey,
 };
+use zeroize::Zeroize;
 
 /// Manages the initiator's role in the Noise NX handshake, handling key exchange, encryption, and
 /// handshake state. It securely generates and manages cryptographic keys, performs Diffie-Hellman
@@ -165,13 +166,25 @@ impl Initiator {
     /// responder during the handshake. The initial initiator state is instantiated with the
     /// ephemeral key pair and handshake hash.
     pub fn new(pk: Option<XOnlyPublicKey>) -> Box<Self> {
+        Self::new_with_rng(pk, &mut rand::thread_rng())
+    }
+
+    /// Creates a new [`Initiator`] instance like [`Initiator::new`], generating the ephemeral key
+    /// pair with `rng`.
+    ///
+    /// A seeded `rng` makes the handshake reproducible, this is meant for tests: a production
+    /// initiator must use a cryptographically secure `rng` that is never reused.
+    pub fn new_with_rng<R: rand::Rng + ?Sized>(
+        pk: Option<XOnlyPublicKey>,
+        rng: &mut R,
+    ) -> Box<Self> {
         let mut self_ = Self {
             handshake_cipher: None,
             k: None,
             n: 0,
             ck: [0; 32],
             h: [0; 32],
-            e: Self::generate_key(),
+            e: Self::generate_key_with_rng(rng),
             responder_authority_pk: pk,
             c1: None,
             c2: None,
@@ -193,6 +206,17 @@ impl Initiator {
         Ok(Self::new(Some(pk)))
     }
 
+    /// Creates a new [`Initiator`] instance like [`Initiator::from_raw_k`], generating the
+    /// ephemeral key pair with `rng`.
+    pub fn from_raw_k_with_rng<R: rand::Rng + ?Sized>(
+        key: [u8; 32],
+        rng: &mut R,
+    ) -> Result<Box<Self>, Error> {
+        let pk =
+            secp256k1::XOnlyPublicKey::from_slice(&key).map_err(|_| Error::InvalidRawPublicKey)?;
+        Ok(Self::new_with_rng(Some(pk), rng))
+    }
+
     /// Creates a new [`Initiator`] without requiring the responder's authority public key.
     /// This function initializes the [`Initiator`] with a default empty state and is intended
     /// for use when both the initiator and responder are within the same network. In this case,
@@ -202,6 +226,12 @@ impl Initiator {
         Ok(Self::new(None))
     }
 
+    /// Creates a new [`Initiator`] instance like [`Initiator::without_pk`], generating the
+    /// ephemeral key pair with `rng`.
+    pub fn without_pk_with_rng<R: rand::Rng + ?Sized>(rng: &mut R) -> Result<Box<Self>, Error> {
+        Ok(Self::new_with_rng(None, rng))
+    }
+
     /// Executes the initial step of the Noise NX protocol handshake.
     ///
     /// This step involves generating an ephemeral keypair and encoding the public key using
@@ -244,6 +274,21 @@ impl Initiator {
     pub fn step_2(
         &mut self,
         message: [u8; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE],
+    ) -> Result<NoiseCodec, Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+        self.step_2_with_now(message, now)
+    }
+
+    /// Processes the second step of the handshake like [`Initiator::step_2`], checking the
+    /// responder certificate against `now` (seconds since the Unix epoch) instead of the current
+    /// time. This is meant for tests with a fixed certificate.
+    pub fn step_2_with_now(
+        &mut self,
+        message: [u8; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE],
+        now: u32,
     ) -> Result<NoiseCodec, Error> {
         // 2. interprets first 64 bytes as ElligatorSwift encoding of x-coordinate of public key
         // from this is derived the 32-bytes remote ephemeral public key `re.public_key`
@@ -258,7 +303,7 @@ impl Initiator {
         let elligatorswift_ours_ephemeral = ElligatorSwift::from_pubkey(self.e.public_key());
         let elligatorswift_theirs_ephemeral =
             ElligatorSwift::from_array(elliswift_theirs_ephemeral_serialized);
-        let ecdh_ephemeral: [u8; 32] = ElligatorSwift::shared_secret(
+        let mut ecdh_ephemeral: [u8; 32] = ElligatorSwift::shared_secret(
             elligatorswift_ours_ephemeral,
             elligatorswift_theirs_ephemeral,
             e_private_key,
@@ -267,6 +312,7 @@ impl Initiator {
         )
         .to_secret_bytes();
         self.mix_key(&ecdh_ephemeral);
+        ecdh_ephemeral.zeroize();
 
         // 5. decrypts next 80 bytes with `DecryptAndHash()` and stores the results as
         // `rs.public_key` which is **server's static public key** (note that 64 bytes is the
@@ -282,7 +328,7 @@ impl Initiator {
             .expect("slice with incorrect length");
         let elligatorswift_theirs_static =
             ElligatorSwift::from_array(elligatorswift_theirs_static_serialized);
-        let ecdh_static: [u8; 32] = ElligatorSwift::shared_secret(
+        let mut ecdh_static: [u8; 32] = ElligatorSwift::shared_secret(
             elligatorswift_ours_ephemeral,
             elligatorswift_theirs_static,
             e_private_key,
@@ -291,6 +337,7 @@ impl Initiator {
         )
         .to_secret_bytes();
         self.mix_key(&ecdh_static);
+        ecdh_static.zeroize();
 
         // Decrypt and verify the SignatureNoiseMessage
         let mut to_decrypt = message[ELLSWIFT_ENCODING_SIZE + ENCRYPTED_ELLSWIFT_ENCODING_SIZE
@@ -308,12 +355,14 @@ impl Initiator {
             .0
             .serialize();
         let rs_pk_xonly = XOnlyPublicKey::from_slice(&rs_pub_key).unwrap();
-        if signature_message.verify(&rs_pk_xonly, &self.responder_authority_pk) {
-            let (temp_k1, temp_k2) = Self::hkdf_2(self.get_ck(), &[]);
+        if signature_message.verify(&rs_pk_xonly, &self.responder_authority_pk, now) {
+            let (mut temp_k1, mut temp_k2) = Self::hkdf_2(self.get_ck(), &[]);
             let c1 = ChaCha20Poly1305::new(&temp_k1.into());
             let c2 = ChaCha20Poly1305::new(&temp_k2.into());
             let c1: Cipher<ChaCha20Poly1305> = Cipher::from_key_and_cipher(temp_k1, c1);
             let c2: Cipher<ChaCha20Poly1305> = Cipher::from_key_and_cipher(temp_k2, c2);
+            temp_k1.zeroize();
+            temp_k2.zeroize();
             self.c1 = None;
             self.c2 = None;
             let mut encryptor = GenericCipher::ChaCha20Poly1305(c1);
@@ -337,17 +386,9 @@ impl Initiator {
     // and session ciphers with zeros. This method is typically
     // called when the [`Initiator`] instance is no longer needed or before deallocation.
     fn erase(&mut self) {
-        if let Some(k) = self.k.as_mut() {
-            for b in k {
-                unsafe { ptr::write_volatile(b, 0) };
-            }
-        }
-        for mut b in self.ck {
-            unsafe { ptr::write_volatile(&mut b, 0) };
-        }
-        for mut b in self.h {
-            unsafe { ptr::write_volatile(&mut b, 0) };
-        }
+        self.k.zeroize();
+        self.ck.zeroize();
+        self.h.zeroize();
         if let Some(c1) = self.c1.as_mut() {
             c1.erase_k()
         }
@@ -38,8 +38,10 @@ use cipher_state::GenericCipher;
 mod aed_cipher;
 mod cipher_state;
 mod error;
+pub mod ffi;
 mod handshake;
 mod initiator;
+mod rate_limit;
 mod responder;
 mod signature_message;
 #[cfg(test)]
@@ -58,6 +60,8 @@ const PARITY: secp256k1::Parity = secp256k1::Parity::Even;
 /// Manages the encryption and decryption of messages between two parties, the [`Initiator`] and
 /// [`Responder`], using the Noise protocol. A symmetric cipher is used for both encrypting
 /// outgoing messages and decrypting incoming messages.
+///
+/// The session keys are wiped from memory when the codec is dropped.
 pub struct NoiseCodec {
     // Cipher to encrypt outgoing messages.
     encryptor: GenericCipher,
@@ -86,4 +90,5 @@ impl NoiseCodec {
 
 pub use error::Error;
 pub use initiator::Initiator;
-pub use responder::Responder;
+pub use rate_limit::HandshakeRateLimiter;
+pub use responder::{PreHandshakeHook, Responder};
@@ -0,0 +1,103 @@
+// # Handshake Rate Limiting
+//
+// Every handshake costs the responder elliptic curve operations: generating its key pairs, the
+// two ECDH and the signature of the certificate. A peer opening connections in a loop can keep a
+// listener busy with them, [`HandshakeRateLimiter`] lets the listener refuse the peers that start
+// too many handshakes before doing any of these operations.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+// Number of peers tracked before the buckets that are full again are dropped.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Token bucket per peer IP address.
+///
+/// A peer can start `burst` handshakes at once, then `rate` handshakes per second. The handshakes
+/// over the limit are refused, they do not consume tokens.
+#[derive(Debug, Clone)]
+pub struct HandshakeRateLimiter {
+    rate: f64,
+    burst: f64,
+    // Tokens left and time of the last refill of every peer.
+    peers: HashMap<IpAddr, (f64, Instant)>,
+}
+
+impl HandshakeRateLimiter {
+    /// Creates a limiter refilling `rate` tokens per second, up to `burst` tokens per peer.
+    pub fn new(rate: f64, burst: u32) -> Self {
+        Self {
+            rate,
+            burst: burst as f64,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Takes a token of `peer`, returns `false` if the handshake must be refused.
+    pub fn allow(&mut self, peer: IpAddr) -> bool {
+        self.allow_at(peer, Instant::now())
+    }
+
+    /// Like [`HandshakeRateLimiter::allow`] at the time `now`.
+    pub fn allow_at(&mut self, peer: IpAddr, now: Instant) -> bool {
+        if self.peers.len() >= PRUNE_THRESHOLD {
+            self.prune(now);
+        }
+        let (rate, burst) = (self.rate, self.burst);
+        let (tokens, last) = self.peers.entry(peer).or_insert((burst, now));
+        let elapsed = now.saturating_duration_since(*last);
+        *tokens = (*tokens + elapsed.as_secs_f64() * rate).min(burst);
+        *last = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Drops the peers whose bucket is full again, they are the same as the peers never seen.
+    fn prune(&mut self, now: Instant) {
+        let (rate, burst) = (self.rate, self.burst);
+        self.peers.retain(|_, (tokens, last)| {
+            let elapsed: Duration = now.saturating_duration_since(*last);
+            *tokens + elapsed.as_secs_f64() * rate < burst
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_refills() {
+        let mut limiter = HandshakeRateLimiter::new(1.0, 2);
+        let peer: IpAddr = [10, 0, 0, 1].into();
+        let other: IpAddr = [10, 0, 0, 2].into();
+        let now = Instant::now();
+        assert!(limiter.allow_at(peer, now));
+        assert!(limiter.allow_at(peer, now));
+        assert!(!limiter.allow_at(peer, now));
+        // Other peers have their own bucket
+        assert!(limiter.allow_at(other, now));
+        assert!(!limiter.allow_at(peer, now + Duration::from_millis(500)));
+        assert!(limiter.allow_at(peer, now + Duration::from_secs(1)));
+        assert!(!limiter.allow_at(peer, now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_rate_limiter_prunes_full_buckets() {
+        let mut limiter = HandshakeRateLimiter::new(1.0, 1);
+        let now = Instant::now();
+        for i in 0..PRUNE_THRESHOLD as u32 {
+            assert!(limiter.allow_at(IpAddr::from(i.to_be_bytes()), now));
+        }
+        let later = now + Duration::from_secs(2);
+        assert!(limiter.allow_at([10, 0, 0, 1].into(), later));
+        assert_eq!(limiter.peers.len(), 1);
+    }
+}
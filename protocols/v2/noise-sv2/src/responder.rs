@@ -34,7 +34,7 @@
 // The [`Drop`] trait is implemented to automatically trigger secure erasure when the [`Responder`]
 // instance goes out of scope, preventing potential misuse or leakage of cryptographic material.
 
-use std::{ptr, time::Duration};
+use std::time::Duration;
 
 use crate::{
     cipher_state::{Cipher, CipherState, GenericCipher},
@@ -49,10 +49,18 @@ use const_sv2::{
     ELLSWIFT_ENCODING_SIZE, ENCRYPTED_ELLSWIFT_ENCODING_SIZE,
     ENCRYPTED_SIGNATURE_NOISE_MESSAGE_SIZE, INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
 };
-use secp256k1::{ellswift::ElligatorSwift, Keypair, Secp256k1, SecretKey};
+use secp256k1::{ellswift::ElligatorSwift, rand, Keypair, Secp256k1, SecretKey};
+use zeroize::Zeroize;
 
 const VERSION: u16 = 0;
 
+/// Called by [`Responder::admit`] with the initiator's ephemeral public key before any elliptic
+/// curve operation, the handshake is rejected if it returns `false`.
+///
+/// A listener can check here what it knows about the peer, e.g. a token bucket per IP address
+/// captured by the closure, see [`crate::HandshakeRateLimiter`].
+pub type PreHandshakeHook = Box<dyn FnMut(&[u8; ELLSWIFT_ENCODING_SIZE]) -> bool + Send + Sync>;
+
 /// Represents the state and operations of the responder in the Noise NX protocol handshake.
 /// It handles cryptographic key exchanges, manages handshake state, and securely establishes
 /// a connection with the initiator. The responder manages key generation, Diffie-Hellman exchanges,
@@ -95,6 +103,10 @@ pub struct Responder {
     c2: Option<GenericCipher>,
     // Validity duration of the responder's certificate, in seconds.
     cert_validity: u32,
+    // Checks the initiator's first message before the handshake does any expensive operation.
+    pre_handshake: Option<PreHandshakeHook>,
+    // Set once `pre_handshake` accepted the handshake, so it is called only once.
+    admitted: bool,
 }
 
 impl std::fmt::Debug for Responder {
@@ -172,18 +184,33 @@ impl Responder {
     /// prepares the handshake state. The authority keypair and certificate validity period are
     /// also configured.
     pub fn new(a: Keypair, cert_validity: u32) -> Box<Self> {
+        Self::new_with_rng(a, cert_validity, &mut rand::thread_rng())
+    }
+
+    /// Creates a new [`Responder`] instance like [`Responder::new`], generating the ephemeral and
+    /// static key pairs with `rng`.
+    ///
+    /// A seeded `rng` makes the handshake reproducible, this is meant for tests: a production
+    /// responder must use a cryptographically secure `rng` that is never reused.
+    pub fn new_with_rng<R: rand::Rng + ?Sized>(
+        a: Keypair,
+        cert_validity: u32,
+        rng: &mut R,
+    ) -> Box<Self> {
         let mut self_ = Self {
             handshake_cipher: None,
             k: None,
             n: 0,
             ck: [0; 32],
             h: [0; 32],
-            e: Self::generate_key(),
-            s: Self::generate_key(),
+            e: Self::generate_key_with_rng(rng),
+            s: Self::generate_key_with_rng(rng),
             a,
             c1: None,
             c2: None,
             cert_validity,
+            pre_handshake: None,
+            admitted: false,
         };
         Self::initialize_self(&mut self_);
         Box::new(self_)
@@ -199,18 +226,56 @@ impl Responder {
         public: &[u8; 32],
         private: &[u8; 32],
         cert_validity: Duration,
+    ) -> Result<Box<Self>, Error> {
+        Self::from_authority_kp_with_rng(public, private, cert_validity, &mut rand::thread_rng())
+    }
+
+    /// Creates a new [`Responder`] instance like [`Responder::from_authority_kp`], generating the
+    /// ephemeral and static key pairs with `rng`.
+    pub fn from_authority_kp_with_rng<R: rand::Rng + ?Sized>(
+        public: &[u8; 32],
+        private: &[u8; 32],
+        cert_validity: Duration,
+        rng: &mut R,
     ) -> Result<Box<Self>, Error> {
         let secp = Secp256k1::new();
         let secret = SecretKey::from_slice(private).map_err(|_| Error::InvalidRawPrivateKey)?;
         let kp = Keypair::from_secret_key(&secp, &secret);
         let pub_ = kp.x_only_public_key().0.serialize();
         if public == &pub_[..] {
-            Ok(Self::new(kp, cert_validity.as_secs() as u32))
+            Ok(Self::new_with_rng(kp, cert_validity.as_secs() as u32, rng))
         } else {
             Err(Error::InvalidRawPublicKey)
         }
     }
 
+    /// Sets the hook checking the initiator's first message, see [`PreHandshakeHook`].
+    pub fn set_pre_handshake_hook(&mut self, hook: PreHandshakeHook) {
+        self.pre_handshake = Some(hook);
+        self.admitted = false;
+    }
+
+    /// Runs the pre-handshake hook on the initiator's first message, fails with
+    /// [`Error::HandshakeRejected`] if the hook rejects it.
+    ///
+    /// [`Responder::step_1`] calls it before any elliptic curve operation, callers that want to
+    /// tell a rejection from a failed handshake call it first. The hook is called only once
+    /// it accepted the handshake.
+    pub fn admit(
+        &mut self,
+        elligatorswift_theirs_ephemeral_serialized: &[u8; ELLSWIFT_ENCODING_SIZE],
+    ) -> Result<(), Error> {
+        if !self.admitted {
+            if let Some(hook) = self.pre_handshake.as_mut() {
+                if !hook(elligatorswift_theirs_ephemeral_serialized) {
+                    return Err(Error::HandshakeRejected);
+                }
+            }
+            self.admitted = true;
+        }
+        Ok(())
+    }
+
     /// Processes the first step of the Noise NX protocol handshake for the responder.
     ///
     /// This function manages the responder's side of the handshake after receiving the initiator's
@@ -225,11 +290,36 @@ impl Responder {
     /// secure transmission of subsequent messages.
     ///
     /// On failure, the method returns an error if there is an issue during encryption, decryption,
-    /// or any other step of the handshake process.
+    /// or any other step of the handshake process, or if the pre-handshake hook rejected it.
     pub fn step_1(
         &mut self,
         elligatorswift_theirs_ephemeral_serialized: [u8; ELLSWIFT_ENCODING_SIZE],
     ) -> Result<([u8; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE], NoiseCodec), aes_gcm::Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+        self.step_1_with_now_rng(
+            elligatorswift_theirs_ephemeral_serialized,
+            now,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    /// Processes the first step of the handshake like [`Responder::step_1`], with the certificate
+    /// valid from `now` (seconds since the Unix epoch) and signed with randomness from `rng`.
+    ///
+    /// Together with the constructors taking an `rng`, this makes the response message
+    /// reproducible, this is meant for tests.
+    pub fn step_1_with_now_rng<R: rand::Rng + ?Sized>(
+        &mut self,
+        elligatorswift_theirs_ephemeral_serialized: [u8; ELLSWIFT_ENCODING_SIZE],
+        now: u32,
+        rng: &mut R,
+    ) -> Result<([u8; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE], NoiseCodec), aes_gcm::Error> {
+        self.admit(&elligatorswift_theirs_ephemeral_serialized)
+            .map_err(|_| aes_gcm::Error)?;
+
         // 4.5.1.2 Responder
         Self::mix_hash(self, &elligatorswift_theirs_ephemeral_serialized[..]);
         Self::decrypt_and_hash(self, &mut vec![])?;
@@ -252,7 +342,7 @@ impl Responder {
         let e_private_key = keypair.secret_key();
         let elligatorswift_theirs_ephemeral =
             ElligatorSwift::from_array(elligatorswift_theirs_ephemeral_serialized);
-        let ecdh_ephemeral = ElligatorSwift::shared_secret(
+        let mut ecdh_ephemeral = ElligatorSwift::shared_secret(
             elligatorswift_theirs_ephemeral,
             elligatorswitf_ours_ephemeral,
             e_private_key,
@@ -261,6 +351,7 @@ impl Responder {
         )
         .to_secret_bytes();
         Self::mix_key(self, &ecdh_ephemeral);
+        ecdh_ephemeral.zeroize();
 
         // 5. appends `EncryptAndHash(s.public_key)` (64 bytes encrypted elligatorswift  public key,
         //    16 bytes MAC)
@@ -277,7 +368,7 @@ impl Responder {
 
         // 6. calls `MixKey(ECDH(s.private_key, re.public_key))`
         let s_private_key = self.s.secret_key();
-        let ecdh_static = ElligatorSwift::shared_secret(
+        let mut ecdh_static = ElligatorSwift::shared_secret(
             elligatorswift_theirs_ephemeral,
             elligatorswift_ours_static,
             s_private_key,
@@ -286,15 +377,12 @@ impl Responder {
         )
         .to_secret_bytes();
         Self::mix_key(self, &ecdh_static[..]);
+        ecdh_static.zeroize();
 
         // 7. appends `EncryptAndHash(SIGNATURE_NOISE_MESSAGE)` to the buffer
-        let valid_from = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        let not_valid_after = valid_from as u32 + self.cert_validity;
-        let signature_noise_message =
-            self.get_signature(VERSION, valid_from as u32, not_valid_after);
+        let valid_from = now;
+        let not_valid_after = valid_from + self.cert_validity;
+        let signature_noise_message = self.get_signature(VERSION, valid_from, not_valid_after, rng);
         let mut signature_part = Vec::with_capacity(ENCRYPTED_SIGNATURE_NOISE_MESSAGE_SIZE);
         signature_part.extend_from_slice(&signature_noise_message[..]);
         Self::encrypt_and_hash(self, &mut signature_part)?;
@@ -306,11 +394,13 @@ impl Responder {
         // 9. return pair of CipherState objects, the first for encrypting transport messages from
         //    initiator to responder, and the second for messages in the other direction:
         let ck = Self::get_ck(self);
-        let (temp_k1, temp_k2) = Self::hkdf_2(ck, &[]);
+        let (mut temp_k1, mut temp_k2) = Self::hkdf_2(ck, &[]);
         let c1 = ChaCha20Poly1305::new(&temp_k1.into());
         let c2 = ChaCha20Poly1305::new(&temp_k2.into());
         let c1: Cipher<ChaCha20Poly1305> = Cipher::from_key_and_cipher(temp_k1, c1);
         let c2: Cipher<ChaCha20Poly1305> = Cipher::from_key_and_cipher(temp_k2, c2);
+        temp_k1.zeroize();
+        temp_k2.zeroize();
         let to_send = out;
         self.c1 = None;
         self.c2 = None;
@@ -331,7 +421,13 @@ impl Responder {
     // certificate validity period, and a cryptographic signature. The signature is created using
     // the responder's static public key and authority keypair, ensuring that the responder's
     // identity and certificate validity are cryptographically verifiable.
-    fn get_signature(&self, version: u16, valid_from: u32, not_valid_after: u32) -> [u8; 74] {
+    fn get_signature<R: rand::Rng + ?Sized>(
+        &self,
+        version: u16,
+        valid_from: u32,
+        not_valid_after: u32,
+        rng: &mut R,
+    ) -> [u8; 74] {
         let mut ret = [0; 74];
         let version = version.to_le_bytes();
         let valid_from = valid_from.to_le_bytes();
@@ -346,7 +442,7 @@ impl Responder {
         ret[7] = not_valid_after[1];
         ret[8] = not_valid_after[2];
         ret[9] = not_valid_after[3];
-        SignatureNoiseMessage::sign(&mut ret, &self.s.x_only_public_key().0, &self.a);
+        SignatureNoiseMessage::sign(&mut ret, &self.s.x_only_public_key().0, &self.a, rng);
         ret
     }
 
@@ -357,17 +453,9 @@ impl Responder {
     // and session ciphers with zeros. This function is typically
     // called when the [`Responder`] instance is no longer needed or before deallocation.
     fn erase(&mut self) {
-        if let Some(k) = self.k.as_mut() {
-            for b in k {
-                unsafe { ptr::write_volatile(b, 0) };
-            }
-        }
-        for mut b in self.ck {
-            unsafe { ptr::write_volatile(&mut b, 0) };
-        }
-        for mut b in self.h {
-            unsafe { ptr::write_volatile(&mut b, 0) };
-        }
+        self.k.zeroize();
+        self.ck.zeroize();
+        self.h.zeroize();
         if let Some(c1) = self.c1.as_mut() {
             c1.erase_k()
         }
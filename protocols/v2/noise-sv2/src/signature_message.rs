@@ -21,8 +21,10 @@
 // public key and optional authority key, while ensuring the message falls within the specified
 // validity period.
 
-use secp256k1::{hashes::sha256, schnorr::Signature, Keypair, Message, Secp256k1, XOnlyPublicKey};
-use std::{convert::TryInto, time::SystemTime};
+use secp256k1::{
+    hashes::sha256, rand, schnorr::Signature, Keypair, Message, Secp256k1, XOnlyPublicKey,
+};
+use std::convert::TryInto;
 
 /// `SignatureNoiseMessage` represents a signed message used in the Noise NX protocol
 /// for authentication during the handshake process. It encapsulates the necessary
@@ -64,18 +66,19 @@ impl From<[u8; 74]> for SignatureNoiseMessage {
 
 impl SignatureNoiseMessage {
     // Verifies the [`SignatureNoiseMessage`] against the provided public key and an optional
-    // authority public key. The verification checks that the message is currently valid
-    // (i.e., within the `valid_from` and `not_valid_after` time window) and that the signature
-    // is correctly signed by the authority.
+    // authority public key. The verification checks that the message is valid at `now` (seconds
+    // since the Unix epoch, i.e., within the `valid_from` and `not_valid_after` time window) and
+    // that the signature is correctly signed by the authority.
     //
     // If an authority public key is not provided, the function assumes that the signature
     // is already valid without further verification.
-    pub fn verify(self, pk: &XOnlyPublicKey, authority_pk: &Option<XOnlyPublicKey>) -> bool {
+    pub fn verify(
+        self,
+        pk: &XOnlyPublicKey,
+        authority_pk: &Option<XOnlyPublicKey>,
+        now: u32,
+    ) -> bool {
         if let Some(authority_pk) = authority_pk {
-            let now = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as u32;
             if self.valid_from <= now && self.not_valid_after >= now {
                 let secp = Secp256k1::verification_only();
                 let (m, s) = self.split();
@@ -100,11 +103,21 @@ impl SignatureNoiseMessage {
     // Creates a Schnorr signature for the message, combining the version, validity period, and
     // the static public key of the server (`static_pk`). The resulting signature is then written
     // into the provided message buffer (`msg`).
-    pub fn sign(msg: &mut [u8; 74], static_pk: &XOnlyPublicKey, kp: &Keypair) {
+    //
+    // The auxiliary randomness of the Schnorr signature is drawn from `rng`, a seeded `rng` always
+    // produces the same signature.
+    pub fn sign<R: rand::Rng + ?Sized>(
+        msg: &mut [u8; 74],
+        static_pk: &XOnlyPublicKey,
+        kp: &Keypair,
+        rng: &mut R,
+    ) {
         let secp = Secp256k1::signing_only();
         let m = [&msg[0..10], &static_pk.serialize()].concat();
         let m = Message::from_hashed_data::<sha256::Hash>(&m);
-        let signature = secp.sign_schnorr(&m, kp);
+        let mut aux_rand = [0; 32];
+        rng.fill_bytes(&mut aux_rand);
+        let signature = secp.sign_schnorr_with_aux_rand(&m, kp, &aux_rand);
         for (i, b) in signature.as_ref().iter().enumerate() {
             msg[10 + i] = *b;
         }
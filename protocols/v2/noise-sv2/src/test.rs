@@ -16,3 +16,144 @@ fn test_1() {
 
     assert!(message == "ciao".as_bytes().to_vec());
 }
+
+#[test]
+fn test_pre_handshake_hook() {
+    use crate::{Error, HandshakeRateLimiter};
+    use std::net::IpAddr;
+
+    let key_pair = Responder::generate_key();
+    let peer: IpAddr = [10, 0, 0, 1].into();
+    let limiter = std::sync::Arc::new(std::sync::Mutex::new(HandshakeRateLimiter::new(0.0, 1)));
+    let responder = |limiter: std::sync::Arc<std::sync::Mutex<HandshakeRateLimiter>>| {
+        let mut responder = Responder::new(key_pair, 31449600);
+        responder.set_pre_handshake_hook(Box::new(move |_| limiter.lock().unwrap().allow(peer)));
+        responder
+    };
+
+    let mut initiator = Initiator::new(Some(key_pair.public_key().into()));
+    let first_message = initiator.step_0().unwrap();
+    let mut first = responder(limiter.clone());
+    assert_eq!(first.admit(&first_message), Ok(()));
+    // The hook accepted the handshake, it is not called again by step_1
+    let (second_message, _) = first.step_1(first_message).unwrap();
+    assert!(initiator.step_2(second_message).is_ok());
+
+    let mut initiator = Initiator::new(Some(key_pair.public_key().into()));
+    let first_message = initiator.step_0().unwrap();
+    let mut second = responder(limiter);
+    assert_eq!(second.admit(&first_message), Err(Error::HandshakeRejected));
+    assert!(second.step_1(first_message).is_err());
+}
+
+#[test]
+fn test_erase_k() {
+    use crate::cipher_state::{Cipher, CipherState, GenericCipher};
+    use aes_gcm::{Aes256Gcm, KeyInit};
+
+    let key = [7; 32];
+    let cipher = Cipher::from_key_and_cipher(key, Aes256Gcm::new(&key.into()));
+    let mut cipher = GenericCipher::Aes256Gcm(cipher);
+    assert_eq!(CipherState::<Aes256Gcm>::get_k(&mut cipher), &Some(key));
+    cipher.erase_k();
+    assert_eq!(CipherState::<Aes256Gcm>::get_k(&mut cipher), &None);
+}
+
+// Test vectors of a handshake with seeded random number generators and a fixed certificate time,
+// followed by a transport message in each direction. Other implementations of the protocol can be
+// checked against them, they must not change unless the handshake changes.
+mod vectors {
+    use super::*;
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+    use secp256k1::{Keypair, Secp256k1, SecretKey};
+
+    const AUTHORITY_SECRET_KEY: &str =
+        "0101010101010101010101010101010101010101010101010101010101010101";
+    const INITIATOR_SEED: [u8; 32] = [1; 32];
+    const RESPONDER_SEED: [u8; 32] = [2; 32];
+    const NOW: u32 = 1_700_000_000;
+    const CERT_VALIDITY: u32 = 3600;
+
+    const INITIATOR_MESSAGE: &str = concat!(
+        "bf755a0c055771d46627a3936009cec5df8b8332670872aaabd97be18c5b82bf",
+        "4caee5f2d0be31f283ecff0771eee34fee61add33675a42a41ba0a046aa6c060",
+    );
+    const RESPONDER_MESSAGE: &str = concat!(
+        "fb6d140df68aa006c9184112611f64e3e4ce51df8ebc3b01ff26cd9935eb2d4f",
+        "6bd5a4e2ef0988a838bf1fc5c8a45bc7450a356e4fb21817569650ce9e68b9d4",
+        "dd1993c8a1e1694a941820ff6b0b4fb6563a4c1d4c9da5727d75fb074d83e558",
+        "c8c64753545b671a8c2e032484d720b9bc37c2740e150992f8db13ce9a00e62e",
+        "5e1427cf2be0df8982894f3d38e4cfc5da25558f37d67d6ae459db238b626afd",
+        "ac6d21aa022a9955256564e9e4f8fce7237d298f6e4c41deebb149b605c55be9",
+        "320eec18188a5d6786560628e57f6e1d4b6c623737ecf7be178796c51020088e",
+        "7c80d90242b5517ea503",
+    );
+    const TRANSPORT_PLAINTEXT: &str = "ciao";
+    const INITIATOR_TRANSPORT_CIPHERTEXT: &str = "a41172bd51c361279b702ca2317a1734f815742e";
+    const RESPONDER_TRANSPORT_CIPHERTEXT: &str = "65647fbd11284574989794a53278462686714d38";
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn authority() -> Keypair {
+        let secret = SecretKey::from_slice(&from_hex(AUTHORITY_SECRET_KEY)).unwrap();
+        Keypair::from_secret_key(&Secp256k1::new(), &secret)
+    }
+
+    // Handshake messages and transport ciphertexts, in the order of the constants above
+    fn run() -> [String; 4] {
+        let authority = authority();
+        let mut initiator = Initiator::new_with_rng(
+            Some(authority.x_only_public_key().0),
+            &mut ChaCha20Rng::from_seed(INITIATOR_SEED),
+        );
+        let mut responder_rng = ChaCha20Rng::from_seed(RESPONDER_SEED);
+        let mut responder = Responder::new_with_rng(authority, CERT_VALIDITY, &mut responder_rng);
+
+        let first_message = initiator.step_0().unwrap();
+        let (second_message, mut codec_responder) = responder
+            .step_1_with_now_rng(first_message, NOW, &mut responder_rng)
+            .unwrap();
+        let mut codec_initiator = initiator.step_2_with_now(second_message, NOW).unwrap();
+
+        let mut from_initiator = TRANSPORT_PLAINTEXT.as_bytes().to_vec();
+        codec_initiator.encrypt(&mut from_initiator).unwrap();
+        let mut from_responder = TRANSPORT_PLAINTEXT.as_bytes().to_vec();
+        codec_responder.encrypt(&mut from_responder).unwrap();
+        let vectors = [
+            to_hex(&first_message),
+            to_hex(&second_message),
+            to_hex(&from_initiator),
+            to_hex(&from_responder),
+        ];
+
+        codec_responder.decrypt(&mut from_initiator).unwrap();
+        codec_initiator.decrypt(&mut from_responder).unwrap();
+        assert_eq!(from_initiator, TRANSPORT_PLAINTEXT.as_bytes());
+        assert_eq!(from_responder, TRANSPORT_PLAINTEXT.as_bytes());
+        vectors
+    }
+
+    #[test]
+    fn handshake_is_reproducible() {
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn handshake_matches_test_vectors() {
+        let [initiator_message, responder_message, initiator_ciphertext, responder_ciphertext] =
+            run();
+        assert_eq!(initiator_message, INITIATOR_MESSAGE);
+        assert_eq!(responder_message, RESPONDER_MESSAGE);
+        assert_eq!(initiator_ciphertext, INITIATOR_TRANSPORT_CIPHERTEXT);
+        assert_eq!(responder_ciphertext, RESPONDER_TRANSPORT_CIPHERTEXT);
+    }
+}
@@ -1,22 +1,32 @@
-use super::extended_to_standard_job;
+use super::{standard_jobs::StandardJobs, template_pipeline::JobBatch};
 use crate::{
     common_properties::StandardChannel,
     job_creator::{self, JobsCreators},
     parsers::Mining,
+    protocol_errors::IntoProtocolError,
+    share_validation::{NtimeLimits, INVALID_EXTRANONCE_SIZE, INVALID_NTIME, INVALID_VERSION},
+    token_manager::{DeclaredJobToken, DeclaredJobTokens},
     utils::{GroupId, Id, Mutex},
     Error,
 };
 
 use mining_sv2::{
-    ExtendedExtranonce, NewExtendedMiningJob, NewMiningJob, OpenExtendedMiningChannelSuccess,
-    OpenMiningChannelError, OpenStandardMiningChannelSuccess, SetCustomMiningJob,
-    SetCustomMiningJobSuccess, SetNewPrevHash, SubmitSharesError, SubmitSharesExtended,
-    SubmitSharesStandard, Target,
+    CloseChannel, ExtendedExtranonce, Extranonce, NewExtendedMiningJob, NewMiningJob,
+    OpenExtendedMiningChannelSuccess, OpenMiningChannelError, OpenStandardMiningChannelSuccess,
+    SetCustomMiningJob, SetCustomMiningJobError, SetCustomMiningJobSuccess, SetGroupChannel,
+    SetNewPrevHash, SubmitSharesError, SubmitSharesExtended, SubmitSharesStandard, Target,
 };
 
 use nohash_hasher::BuildNoHashHasher;
-use std::{collections::HashMap, convert::TryInto, sync::Arc};
-use template_distribution_sv2::{NewTemplate, SetNewPrevHash as SetNewPrevHashFromTp};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use template_distribution_sv2::{
+    NewTemplate, SetNewPrevHash as SetNewPrevHashFromTp, SubmitSolution,
+};
 
 use tracing::{debug, error, info, trace, warn};
 
@@ -27,6 +37,7 @@ use stratum_common::{
         hashes::{hex::ToHex, sha256d::Hash, Hash as Hash_},
         TxOut,
     },
+    secp256k1::XOnlyPublicKey,
 };
 
 /// A stripped type of `SetCustomMiningJob` without the (`channel_id, `request_id` and `token`)
@@ -48,6 +59,15 @@ pub struct PartialSetCustomMiningJob {
     pub future_job: bool,
 }
 
+/// Reason code used in the `CloseChannel` messages returned by `close_idle_channels`
+pub const IDLE_CHANNEL_REASON_CODE: &str = "idle-channel";
+
+/// Error codes of the `SetCustomMiningJob.Error` messages returned by
+/// `PoolChannelFactory::on_new_set_custom_mining_job`
+pub const INVALID_CHANNEL_ID: &str = "invalid-channel-id";
+pub const INVALID_MINING_JOB_TOKEN: &str = "invalid-mining-job-token";
+pub const INVALID_COINBASE_TX_OUTPUTS: &str = "invalid-job-param-value-coinbase_tx_outputs";
+
 /// Represent the action that needs to be done when a new share is received.
 #[derive(Debug, Clone)]
 pub enum OnNewShare {
@@ -215,6 +235,22 @@ struct ChannelFactory {
     job_ids: Id,
     channel_to_group_id: HashMap<u32, u32, BuildNoHashHasher<u32>>,
     future_templates: HashMap<u32, NewTemplate<'static>, BuildNoHashHasher<u32>>,
+    // channel_id -> last time we received something for the channel
+    last_activity: HashMap<u32, Instant, BuildNoHashHasher<u32>>,
+    // hash of the last share checked, little endian
+    last_share_hash: Option<[u8; 32]>,
+    // standard jobs of the HOM channels, for the extended jobs still valid
+    standard_jobs: StandardJobs,
+    // the ntime of the shares is not checked if None
+    ntime_limits: Option<NtimeLimits>,
+    // when the last SetNewPrevHash has been received
+    last_prev_hash_received: Option<Instant>,
+    // extranonces of the closed standard channels, reused before asking new ones to extranonces
+    freed_extranonces: Vec<mining_sv2::Extranonce>,
+    // channel_id -> extranonce given by extranonces to the extended channel
+    extended_extranonces: HashMap<u32, mining_sv2::Extranonce, BuildNoHashHasher<u32>>,
+    // extranonces of the closed extended channels, reused before asking new ones to extranonces
+    freed_extended_extranonces: Vec<mining_sv2::Extranonce>,
 }
 
 impl ChannelFactory {
@@ -243,6 +279,8 @@ impl ChannelFactory {
     /// `OpenExtendedMiningChannelSuccess` if the channel is successfully opened. Then we add
     /// the `NewExtendedMiningJob` and `SetNewPrevHash` messages if the relevant data is
     /// available. If the channel opening fails, we return `OpenExtenedMiningChannelError`.
+    /// The channel gets `min_extranonce_size` of the extranonce bytes reserved for the
+    /// downstreams, the ones that it does not use are zeroed and added to its extranonce prefix.
     pub fn new_extended_channel(
         &mut self,
         request_id: u32,
@@ -273,21 +311,40 @@ impl ChannelFactory {
                     return Err(e);
                 }
             };
+            let prefix_len = self.extranonces.get_prefix_len()
+                + (max_extranonce_size - min_extranonce_size) as usize;
             let extranonce = self
-                .extranonces
-                .next_extended(max_extranonce_size as usize)
-                .unwrap();
-            let extranonce_prefix = extranonce
-                .into_prefix(self.extranonces.get_prefix_len())
-                .unwrap();
+                .freed_extended_extranonces
+                .pop()
+                .or_else(|| self.extranonces.next_extended(min_extranonce_size as usize));
+            let extranonce_prefix = match extranonce.clone().and_then(|extranonce| {
+                Extranonce::from_vec_with_len(extranonce.to_vec(), prefix_len)
+                    .into_prefix(prefix_len)
+            }) {
+                Some(extranonce_prefix) => extranonce_prefix,
+                None => {
+                    error!(
+                        "No extranonce left for the channel. Request id: {:?}",
+                        request_id
+                    );
+                    self.channel_to_group_id.remove(&channel_id);
+                    return Ok(vec![Mining::OpenMiningChannelError(
+                        Error::ExtranonceSpaceEnded.open_mining_channel_error(request_id),
+                    )]);
+                }
+            };
             let success = OpenExtendedMiningChannelSuccess {
                 request_id,
                 channel_id,
                 target,
-                extranonce_size: max_extranonce_size,
+                extranonce_size: min_extranonce_size,
                 extranonce_prefix,
             };
             self.extended_channels.insert(channel_id, success.clone());
+            // Safe unwrap the prefix has been built from the extranonce above
+            self.extended_extranonces
+                .insert(channel_id, extranonce.unwrap());
+            self.on_channel_activity(channel_id);
             let mut result = vec![Mining::OpenExtendedMiningChannelSuccess(success)];
             if let Some((job, _)) = &self.last_valid_job {
                 let mut job = job.clone();
@@ -333,6 +390,7 @@ impl ChannelFactory {
             extranonce_prefix,
         };
         self.extended_channels.insert(channel_id, success.clone());
+        self.on_channel_activity(channel_id);
         Some(())
     }
     /// Called when an `OpenStandardChannel` message is received for a header only mining channel.
@@ -362,8 +420,9 @@ impl ChannelFactory {
             }
         };
         let extranonce = self
-            .extranonces
-            .next_standard()
+            .freed_extranonces
+            .pop()
+            .or_else(|| self.extranonces.next_standard())
             .ok_or(Error::ExtranonceSpaceEnded)?;
         let standard_channel = StandardChannel {
             channel_id,
@@ -386,6 +445,7 @@ impl ChannelFactory {
         ));
         self.prepare_standard_jobs_and_p_hash(&mut result, channel_id)?;
         self.channel_to_group_id.insert(channel_id, hom_group_id);
+        self.on_channel_activity(channel_id);
         Ok(result)
     }
 
@@ -417,8 +477,9 @@ impl ChannelFactory {
             }
         };
         let extranonce = self
-            .extranonces
-            .next_standard()
+            .freed_extranonces
+            .pop()
+            .or_else(|| self.extranonces.next_standard())
             .ok_or(Error::ExtranonceSpaceEnded)?;
         let standard_channel = StandardChannel {
             channel_id,
@@ -441,6 +502,7 @@ impl ChannelFactory {
         ));
         self.prepare_jobs_and_p_hash(&mut result, complete_id);
         self.channel_to_group_id.insert(channel_id, group_id);
+        self.on_channel_activity(channel_id);
         Ok(result)
     }
 
@@ -457,35 +519,26 @@ impl ChannelFactory {
             .standard_channels_for_hom_downstreams
             .get(&channel_id)
             .unwrap();
-        // OPTIMIZATION this could be memoized somewhere cause is very likely that we will receive a
-        // lot od OpenStandardMiningChannel requests consequtevely
-        let job_id = self.job_ids.next();
-        let future_jobs: Option<Vec<NewMiningJob<'static>>> = self
-            .future_jobs
-            .iter()
-            .map(|j| {
-                extended_to_standard_job(
-                    &j.0,
-                    &standard_channel.extranonce.clone().to_vec()[..],
-                    standard_channel.channel_id,
-                    Some(job_id),
-                )
-            })
-            .collect();
-
-        // OPTIMIZATION the extranonce is cloned so many time but maybe is avoidable?
+        let extranonce = standard_channel.extranonce.clone().to_vec();
         let last_valid_job = match &self.last_valid_job {
-            Some((j, _)) => Some(
-                extended_to_standard_job(
-                    j,
-                    &standard_channel.extranonce.clone().to_vec(),
-                    standard_channel.channel_id,
-                    Some(self.job_ids.next()),
-                )
-                .ok_or(Error::ImpossibleToCalculateMerkleRoot)?,
-            ),
+            Some((j, _)) => Some(self.standard_jobs.get_or_compute(
+                j,
+                channel_id,
+                &extranonce,
+                &mut self.job_ids,
+            )?),
             None => None,
         };
+        let mut future_jobs: Vec<NewMiningJob<'static>> =
+            Vec::with_capacity(self.future_jobs.len());
+        for (job, _) in &self.future_jobs {
+            future_jobs.push(self.standard_jobs.get_or_compute(
+                job,
+                channel_id,
+                &extranonce,
+                &mut self.job_ids,
+            )?);
+        }
 
         // This is the same thing of just check if there is a prev hash add it to result. If there
         // is last_job add it to result and add each future job to result.
@@ -500,8 +553,6 @@ impl ChannelFactory {
             // If we have only future jobs we need to send them all after the
             // SetupConnectionSuccess message
             (None, None, false) => {
-                // Safe unwrap cause we check that self.future_jobs is not empty
-                let mut future_jobs = future_jobs.unwrap();
                 while let Some(job) = future_jobs.pop() {
                     result.push(Mining::NewMiningJob(job));
                 }
@@ -535,9 +586,6 @@ impl ChannelFactory {
                 result.push(Mining::NewMiningJob(job));
                 result.push(Mining::SetNewPrevHash(prev_h.clone()));
 
-                // Safe unwrap cause we check that self.future_jobs is not empty
-                let mut future_jobs = future_jobs.unwrap();
-
                 while let Some(job) = future_jobs.pop() {
                     result.push(Mining::NewMiningJob(job));
                 }
@@ -648,12 +696,12 @@ impl ChannelFactory {
     /// job queue, we move the future job into the valid job slot and store the prev hash as the
     /// current prev hash to be referenced.
     fn on_new_prev_hash(&mut self, m: StagedPhash) -> Result<(), Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
         while let Some(mut job) = self.future_jobs.pop() {
             if job.0.job_id == m.job_id {
-                let now = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs() as u32;
                 job.0.set_no_future(now);
                 self.last_valid_job = Some(job);
                 break;
@@ -661,7 +709,10 @@ impl ChannelFactory {
             self.last_valid_job = None;
         }
         self.future_jobs = vec![];
+        let valid_job = self.last_valid_job.as_ref().map(|(job, _)| job.job_id);
+        self.standard_jobs.on_new_prev_hash(valid_job, now);
         self.last_prev_hash_ = Some(crate::utils::u256_to_block_hash(m.prev_hash.clone()));
+        self.last_prev_hash_received = Some(Instant::now());
         let mut ids = vec![];
         for complete_id in self.standard_channels_for_non_hom_downstreams.keys() {
             let group_id = GroupId::into_group_id(*complete_id);
@@ -703,6 +754,11 @@ impl ChannelFactory {
                         ids.push(group_id)
                     }
                 }
+                // The standard jobs of the previous valid job are replaced by the ones of this job
+                let mut valid_jobs: Vec<u32> =
+                    self.future_jobs.iter().map(|j| j.0.job_id).collect();
+                valid_jobs.push(m.job_id);
+                self.standard_jobs.retain(&valid_jobs);
                 self.last_valid_job = Some((m, ids));
                 if let Some((_p_hash, _)) = &self.last_prev_hash {
                     Ok(result)
@@ -724,15 +780,12 @@ impl ChannelFactory {
         m: &NewExtendedMiningJob<'static>,
     ) -> Result<(), Error> {
         for (id, channel) in &self.standard_channels_for_hom_downstreams {
-            let job_id = self.job_ids.next();
-            let mut standard_job = extended_to_standard_job(
+            let standard_job = self.standard_jobs.get_or_compute(
                 m,
-                &channel.extranonce.clone().to_vec()[..],
                 *id,
-                Some(job_id),
-            )
-            .unwrap();
-            standard_job.channel_id = *id;
+                &channel.extranonce.clone().to_vec()[..],
+                &mut self.job_ids,
+            )?;
             let standard_job = Mining::NewMiningJob(standard_job);
             result.insert(*id, standard_job);
         }
@@ -752,6 +805,52 @@ impl ChannelFactory {
         Ok(())
     }
 
+    // When a new prev hash is activated we use this function to prepare the `SetNewPrevHash` of
+    // each channel, with the job id the channel knows the activated job by. A HOM channel that has
+    // no standard job for the activated job receives it before the prev hash.
+    fn prepare_p_hash_for_downstream(&mut self) -> Result<JobBatch, Error> {
+        let (prev_hash, job) = match (&self.last_prev_hash, &self.last_valid_job) {
+            (Some((prev_hash, _)), Some((job, _))) if job.job_id == prev_hash.job_id => {
+                (prev_hash.clone(), job.clone())
+            }
+            _ => return Err(Error::NoValidJob),
+        };
+        let mut result: JobBatch = HashMap::with_hasher(BuildNoHashHasher::default());
+        for (id, channel) in &self.standard_channels_for_hom_downstreams {
+            let messages = result.entry(*id).or_default();
+            let job_id = match self.standard_jobs.job_id(*id, job.job_id) {
+                Some(job_id) => job_id,
+                None => {
+                    let mut standard_job = self.standard_jobs.get_or_compute(
+                        &job,
+                        *id,
+                        &channel.extranonce.clone().to_vec()[..],
+                        &mut self.job_ids,
+                    )?;
+                    standard_job.set_future();
+                    let job_id = standard_job.job_id;
+                    messages.push(Mining::NewMiningJob(standard_job));
+                    job_id
+                }
+            };
+            let prev_hash = prev_hash.into_set_p_hash(*id, Some(job_id));
+            messages.push(Mining::SetNewPrevHash(prev_hash));
+        }
+        for complete_id in self.standard_channels_for_non_hom_downstreams.keys() {
+            let group_id = GroupId::into_group_id(*complete_id);
+            result.entry(group_id).or_insert_with(|| {
+                vec![Mining::SetNewPrevHash(
+                    prev_hash.into_set_p_hash(group_id, None),
+                )]
+            });
+        }
+        for id in self.extended_channels.keys() {
+            let prev_hash = prev_hash.into_set_p_hash(*id, None);
+            result.insert(*id, vec![Mining::SetNewPrevHash(prev_hash)]);
+        }
+        Ok(result)
+    }
+
     // If there is job creator, bitcoin_target is retrieved from there. If not, it is set to 0.
     // If there is a job creator we pass the correct template id. If not, we pass `None`
     // allow comparison chain because clippy wants to make job management assertion into a match
@@ -771,6 +870,12 @@ impl ChannelFactory {
         bits: u32,
     ) -> Result<OnNewShare, Error> {
         debug!("Checking target for share {:?}", m);
+        if let Some(error) = self.check_ntime(&m) {
+            return Ok(OnNewShare::SendErrorDownstream(error));
+        }
+        if let Some(error) = self.check_extranonce_size(&m) {
+            return Ok(OnNewShare::SendErrorDownstream(error));
+        }
         let upstream_target = match &self.kind {
             ExtendedChannelKind::Pool => Target::new(0, 0),
             ExtendedChannelKind::Proxy {
@@ -800,16 +905,28 @@ impl ChannelFactory {
             "On checking target coinbase suffix is: {:?}",
             coinbase_tx_suffix
         );
-        // Safe unwrap a sha256 can always be converted into [u8;32]
-        let merkle_root: [u8; 32] = crate::utils::merkle_root_from_path(
-            coinbase_tx_prefix,
-            coinbase_tx_suffix,
-            &extranonce[..],
-            &merkle_path[..],
-        )
-        .ok_or(Error::InvalidCoinbase)?
-        .try_into()
-        .unwrap();
+        // The merkle root of a share of an HOM channel has already been computed for its standard
+        // job, as long as the job is the one the share is checked against
+        let cached_merkle_root = match (&m, &self.last_valid_job) {
+            (Share::Standard((share, _)), Some((job, _))) => {
+                self.standard_jobs
+                    .merkle_root(share.channel_id, share.job_id, job.job_id)
+            }
+            _ => None,
+        };
+        let merkle_root: [u8; 32] = match cached_merkle_root {
+            Some(merkle_root) => merkle_root,
+            // Safe unwrap a sha256 can always be converted into [u8;32]
+            None => crate::utils::merkle_root_from_path(
+                coinbase_tx_prefix,
+                coinbase_tx_suffix,
+                &extranonce[..],
+                &merkle_path[..],
+            )
+            .ok_or(Error::InvalidCoinbase)?
+            .try_into()
+            .unwrap(),
+        };
         let version = match &m {
             Share::Extended(share) => share.version as i32,
             Share::Standard(share) => share.0.version as i32,
@@ -843,7 +960,12 @@ impl ChannelFactory {
             hash.reverse();
             debug!("Hash           : {:?}", hash.to_vec().to_hex());
         }
+        self.last_share_hash = Some(hash);
         let hash: Target = hash.into();
+        // Only a valid share counts as activity of the channel
+        if hash <= bitcoin_target || hash <= upstream_target || hash <= downstream_target {
+            self.on_channel_activity(m.get_channel_id());
+        }
 
         if hash <= bitcoin_target {
             let mut print_hash = hash_.as_hash().into_inner();
@@ -907,6 +1029,72 @@ impl ChannelFactory {
             Ok(OnNewShare::SendErrorDownstream(error))
         }
     }
+    /// Returns the error to send downstream when the ntime of the share is not within the
+    /// `ntime_limits` of the last `SetNewPrevHash`
+    fn check_ntime(&self, m: &Share) -> Option<SubmitSharesError<'static>> {
+        let limits = self.ntime_limits?;
+        let (prev_hash, _) = self.last_prev_hash.as_ref()?;
+        let elapsed = self
+            .last_prev_hash_received
+            .map(|received| received.elapsed())
+            .unwrap_or_default();
+        let e = limits
+            .check(m.get_n_time(), prev_hash.min_ntime, elapsed)
+            .err()?;
+        warn!("Share rejected on channel {}: {}", m.get_channel_id(), e);
+        Some(SubmitSharesError {
+            channel_id: m.get_channel_id(),
+            sequence_number: m.get_sequence_number(),
+            // Infallible unwrap we already know the len of the error code (is a static string)
+            error_code: INVALID_NTIME.to_string().try_into().unwrap(),
+        })
+    }
+    /// Returns the error to send downstream when the share rolled version bits that `job` does not
+    /// allow to roll. Downstreams of standard channels can always roll the BIP320 bits.
+    fn check_version_rolling(
+        &self,
+        m: &Share,
+        job: &NewExtendedMiningJob,
+    ) -> Option<SubmitSharesError<'static>> {
+        let e = match m {
+            Share::Extended(share) => {
+                share.check_version_rolling(job.version, job.version_rolling_allowed)
+            }
+            Share::Standard((share, _)) => share.check_version_rolling(job.version, true),
+        }
+        .err()?;
+        warn!("Share rejected on channel {}: {:?}", m.get_channel_id(), e);
+        Some(SubmitSharesError {
+            channel_id: m.get_channel_id(),
+            sequence_number: m.get_sequence_number(),
+            // Infallible unwrap we already know the len of the error code (is a static string)
+            error_code: INVALID_VERSION.to_string().try_into().unwrap(),
+        })
+    }
+    /// The extranonce of an extended share must be as long as the `extranonce_size` of its
+    /// channel, otherwise the coinbase built from it is not the one the downstream mined
+    fn check_extranonce_size(&self, m: &Share) -> Option<SubmitSharesError<'static>> {
+        let share = match m {
+            Share::Extended(share) => share,
+            Share::Standard(_) => return None,
+        };
+        let channel = self.extended_channels.get(&share.channel_id)?;
+        if share.extranonce.len() == channel.extranonce_size as usize {
+            return None;
+        }
+        warn!(
+            "Share rejected on channel {}: extranonce of {} bytes, expected {}",
+            share.channel_id,
+            share.extranonce.len(),
+            channel.extranonce_size
+        );
+        Some(SubmitSharesError {
+            channel_id: share.channel_id,
+            sequence_number: share.sequence_number,
+            // Infallible unwrap we already know the len of the error code (is a static string)
+            error_code: INVALID_EXTRANONCE_SIZE.to_string().try_into().unwrap(),
+        })
+    }
     /// Returns the downstream target and extranonce for the channel
     fn get_channel_specific_mining_info(&self, m: &Share) -> Option<(mining_sv2::Target, Vec<u8>)> {
         match m {
@@ -917,13 +1105,6 @@ impl ChannelFactory {
                 let extranonce = [&extranonce_prefix[..], &share.extranonce.to_vec()[..]]
                     .concat()
                     .to_vec();
-                if extranonce.len() != self.extranonces.get_len() {
-                    error!(
-                        "Extranonce is not of the right len expected {} actual {}",
-                        self.extranonces.get_len(),
-                        extranonce.len()
-                    );
-                }
                 Some((dowstream_target, extranonce))
             }
             Share::Standard((share, group_id)) => match &self.kind {
@@ -960,12 +1141,143 @@ impl ChannelFactory {
             },
         }
     }
-    /// updates the downstream target for the given channel_id
+    /// updates the downstream target for the given channel_id, either an extended channel or a
+    /// standard channel of an HOM downstream
     fn update_target_for_channel(&mut self, channel_id: u32, new_target: Target) -> Option<bool> {
-        let channel = self.extended_channels.get_mut(&channel_id)?;
-        channel.target = new_target.into();
+        if let Some(channel) = self.extended_channels.get_mut(&channel_id) {
+            channel.target = new_target.into();
+            return Some(true);
+        }
+        let channel = self
+            .standard_channels_for_hom_downstreams
+            .get_mut(&channel_id)?;
+        channel.target = new_target;
         Some(true)
     }
+
+    /// Marks the channel as active now. It is a no-op for channels that this factory do not know.
+    fn on_channel_activity(&mut self, channel_id: u32) {
+        if self.channel_to_group_id.contains_key(&channel_id)
+            || self.extended_channels.contains_key(&channel_id)
+        {
+            self.last_activity.insert(channel_id, Instant::now());
+        }
+    }
+
+    /// Returns the ids of the channels that did not have any activity for at least `max_idle`,
+    /// together with how long they have been idle.
+    fn idle_channels(&self, max_idle: Duration) -> Vec<(u32, Duration)> {
+        let now = Instant::now();
+        let mut idle: Vec<(u32, Duration)> = self
+            .last_activity
+            .iter()
+            .map(|(id, last)| (*id, now.saturating_duration_since(*last)))
+            .filter(|(_, elapsed)| *elapsed >= max_idle)
+            .collect();
+        idle.sort_by_key(|(id, _)| *id);
+        idle
+    }
+
+    /// Removes every state related to `channel_id` and frees its extranonce, so that it can be
+    /// given to the next opened channel. Returns false if the channel was not there.
+    fn remove_channel(&mut self, channel_id: u32) -> bool {
+        self.last_activity.remove(&channel_id);
+        self.standard_jobs.remove_channel(channel_id);
+        let mut removed = self.extended_channels.remove(&channel_id).is_some();
+        if let Some(extranonce) = self.extended_extranonces.remove(&channel_id) {
+            self.freed_extended_extranonces.push(extranonce);
+        }
+        if let Some(group_id) = self.channel_to_group_id.remove(&channel_id) {
+            let complete_id = GroupId::into_complete_id(group_id, channel_id);
+            let standard_channel = self
+                .standard_channels_for_non_hom_downstreams
+                .remove(&complete_id)
+                .or_else(|| {
+                    self.standard_channels_for_hom_downstreams
+                        .remove(&channel_id)
+                });
+            if let Some(standard_channel) = standard_channel {
+                self.freed_extranonces.push(standard_channel.extranonce);
+                removed = true;
+            }
+        }
+        removed
+    }
+
+    /// Moves the standard channels of non HOM downstreams in `channel_ids` to the group
+    /// `group_channel_id`. Returns the `SetGroupChannel` to send downstream, followed by the jobs
+    /// and the prev hash that the group did not receive yet. Nothing is moved if one of the
+    /// channels can not be moved.
+    fn set_group_channel(
+        &mut self,
+        group_channel_id: u32,
+        channel_ids: Vec<u32>,
+    ) -> Result<Vec<Mining<'static>>, Error> {
+        // group 0 is the one of the HOM downstreams and of the extended channels
+        if group_channel_id == 0 {
+            return Err(Error::GroupIdNotFound);
+        }
+        for channel_id in &channel_ids {
+            let group_id = self
+                .channel_to_group_id
+                .get(channel_id)
+                .ok_or(Error::NotFoundChannelId)?;
+            let complete_id = GroupId::into_complete_id(*group_id, *channel_id);
+            if !self
+                .standard_channels_for_non_hom_downstreams
+                .contains_key(&complete_id)
+            {
+                return Err(Error::ChannelCanNotBeGrouped(*channel_id));
+            }
+        }
+        for channel_id in &channel_ids {
+            // Safe unwraps, the channels have been checked above
+            let group_id = self
+                .channel_to_group_id
+                .insert(*channel_id, group_channel_id);
+            let complete_id = GroupId::into_complete_id(group_id.unwrap(), *channel_id);
+            let mut channel = self
+                .standard_channels_for_non_hom_downstreams
+                .remove(&complete_id)
+                .unwrap();
+            channel.group_id = group_channel_id;
+            self.standard_channels_for_non_hom_downstreams.insert(
+                GroupId::into_complete_id(group_channel_id, *channel_id),
+                channel,
+            );
+        }
+        let mut result = vec![Mining::SetGroupChannel(SetGroupChannel {
+            group_channel_id,
+            channel_ids: channel_ids.clone().into(),
+        })];
+        if let Some(channel_id) = channel_ids.first() {
+            let complete_id = GroupId::into_complete_id(group_channel_id, *channel_id);
+            self.prepare_jobs_and_p_hash(&mut result, complete_id);
+        }
+        Ok(result)
+    }
+
+    /// Removes all the channels that have been idle for at least `max_idle` and returns a
+    /// `CloseChannel` for each one of them, so that the caller can notify the other side.
+    fn close_idle_channels(&mut self, max_idle: Duration) -> Vec<CloseChannel<'static>> {
+        let mut result = vec![];
+        for (channel_id, elapsed) in self.idle_channels(max_idle) {
+            if self.remove_channel(channel_id) {
+                info!(
+                    "Closing channel {} idle since {} seconds",
+                    channel_id,
+                    elapsed.as_secs()
+                );
+                result.push(CloseChannel {
+                    channel_id,
+                    // Infallible unwrap we already know the len of the reason code (is a static
+                    // string)
+                    reason_code: IDLE_CHANNEL_REASON_CODE.to_string().try_into().unwrap(),
+                });
+            }
+        }
+        result
+    }
 }
 
 /// Used by a pool to in order to manage all downstream channel. It add job creation capabilities
@@ -978,6 +1290,8 @@ pub struct PoolChannelFactory {
     pool_signature: String,
     // extedned_channel_id -> SetCustomMiningJob
     negotiated_jobs: HashMap<u32, SetCustomMiningJob<'static>, BuildNoHashHasher<u32>>,
+    // Custom jobs are refused until the public key of the JDS is set
+    declared_job_tokens: Option<DeclaredJobTokens>,
 }
 
 impl PoolChannelFactory {
@@ -1009,6 +1323,14 @@ impl PoolChannelFactory {
             job_ids: Id::new(),
             channel_to_group_id: HashMap::with_hasher(BuildNoHashHasher::default()),
             future_templates: HashMap::with_hasher(BuildNoHashHasher::default()),
+            last_activity: HashMap::with_hasher(BuildNoHashHasher::default()),
+            last_share_hash: None,
+            standard_jobs: StandardJobs::new(),
+            ntime_limits: None,
+            last_prev_hash_received: None,
+            freed_extranonces: Vec::new(),
+            extended_extranonces: HashMap::with_hasher(BuildNoHashHasher::default()),
+            freed_extended_extranonces: Vec::new(),
         };
 
         Self {
@@ -1017,8 +1339,15 @@ impl PoolChannelFactory {
             pool_coinbase_outputs,
             pool_signature,
             negotiated_jobs: HashMap::with_hasher(BuildNoHashHasher::default()),
+            declared_job_tokens: None,
         }
     }
+
+    /// Sets the authority public key of the JDS, that signs the tokens of the custom jobs. Custom
+    /// jobs are refused if it is not set.
+    pub fn set_jds_public_key(&mut self, public_key: XOnlyPublicKey) {
+        self.declared_job_tokens = Some(DeclaredJobTokens::new(public_key));
+    }
     /// Calls [`ChannelFactory::add_standard_channel`]
     pub fn add_standard_channel(
         &mut self,
@@ -1087,6 +1416,11 @@ impl PoolChannelFactory {
         )?;
         self.inner.on_new_extended_mining_job(new_job)
     }
+    /// The `SetNewPrevHash` (and any missing standard job) to send to each channel after
+    /// [`PoolChannelFactory::on_new_prev_hash_from_tp`]
+    pub(crate) fn prev_hash_messages(&mut self) -> Result<JobBatch, Error> {
+        self.inner.prepare_p_hash_for_downstream()
+    }
     /// Called when a `SubmitSharesStandard` message is received from the downstream. We check the
     /// shares against the channel's respective target and return `OnNewShare` to let us know if
     /// and where the shares should be relayed
@@ -1119,8 +1453,12 @@ impl PoolChannelFactory {
                     .ok_or(Error::ShareDoNotMatchAnyJob)?
                     .0
                     .nbits;
+                let share = Share::Standard((m, *g_id));
+                if let Some(error) = self.inner.check_version_rolling(&share, &referenced_job) {
+                    return Ok(OnNewShare::SendErrorDownstream(error));
+                }
                 self.inner.check_target(
-                    Share::Standard((m, *g_id)),
+                    share,
                     target,
                     Some(template_id),
                     0,
@@ -1166,8 +1504,12 @@ impl PoolChannelFactory {
                     .unwrap();
             let prev_blockhash = crate::utils::u256_to_block_hash(referenced_job.prev_hash.clone());
             let bits = referenced_job.nbits;
+            let share = Share::Extended(m.into_static());
+            if let Some(error) = self.inner.check_version_rolling(&share, &extended_job) {
+                return Ok(OnNewShare::SendErrorDownstream(error));
+            }
             self.inner.check_target(
-                Share::Extended(m.into_static()),
+                share,
                 target,
                 None,
                 0,
@@ -1184,6 +1526,19 @@ impl PoolChannelFactory {
                 .clone()
                 .ok_or(Error::ShareDoNotMatchAnyJob)?
                 .0;
+            if referenced_job.job_id != m.job_id {
+                let error = SubmitSharesError {
+                    channel_id: m.channel_id,
+                    sequence_number: m.sequence_number,
+                    // Infallible unwrap we already know the len of the error code (is a
+                    // static string)
+                    error_code: SubmitSharesError::invalid_job_id_error_code()
+                        .to_string()
+                        .try_into()
+                        .unwrap(),
+                };
+                return Ok(OnNewShare::SendErrorDownstream(error));
+            }
             let merkle_path = referenced_job.merkle_path.to_vec();
             let template_id = self
                 .job_creator
@@ -1200,8 +1555,12 @@ impl PoolChannelFactory {
                 .ok_or(Error::ShareDoNotMatchAnyJob)?
                 .0
                 .nbits;
+            let share = Share::Extended(m.into_static());
+            if let Some(error) = self.inner.check_version_rolling(&share, &referenced_job) {
+                return Ok(OnNewShare::SendErrorDownstream(error));
+            }
             self.inner.check_target(
-                Share::Extended(m.into_static()),
+                share,
                 target,
                 Some(template_id),
                 0,
@@ -1213,10 +1572,46 @@ impl PoolChannelFactory {
             )
         }
     }
+    /// Builds the `SubmitSolution` of a share for which [`OnNewShare::ShareMeetBitcoinTarget`] has
+    /// been returned, see [`crate::utils::submit_solution_from_share`]. `extranonce` is the full
+    /// extranonce returned with the share. Must be called before the factory receives a new job or
+    /// prev hash, the solution is built from the last ones.
+    pub fn submit_solution(
+        &self,
+        share: &Share,
+        extranonce: &[u8],
+        template_id: u64,
+    ) -> Result<SubmitSolution<'static>, Error> {
+        let job = &self
+            .inner
+            .last_valid_job
+            .as_ref()
+            .ok_or(Error::ShareDoNotMatchAnyJob)?
+            .0;
+        let prev_hash = self
+            .inner
+            .last_prev_hash_
+            .ok_or(Error::ShareDoNotMatchAnyJob)?;
+        let nbits = self
+            .inner
+            .last_prev_hash
+            .as_ref()
+            .ok_or(Error::ShareDoNotMatchAnyJob)?
+            .0
+            .nbits;
+        crate::utils::submit_solution_from_share(
+            template_id,
+            job,
+            share,
+            extranonce,
+            prev_hash,
+            nbits,
+        )
+    }
+
     /// Utility function to return a new group id
     pub fn new_group_id(&mut self) -> u32 {
-        let new_id = self.inner.ids.safe_lock(|ids| ids.new_group_id()).unwrap();
-        new_id
+        self.inner.ids.super_safe_lock(|ids| ids.new_group_id())
     }
     /// Utility function to return a new standard channel id
     pub fn new_standard_id_for_hom(&mut self) -> u32 {
@@ -1239,30 +1634,90 @@ impl PoolChannelFactory {
             .extranonce_from_downstream_extranonce(ext)
     }
     /// Called when a new custom mining job arrives
+    /// Records the custom job of an extended channel, shares of the channel are then checked
+    /// against it. The job is refused with the `SetCustomMiningJob.Error` to send back if it is
+    /// not valid, see [`PoolChannelFactory::check_set_custom_mining_job`].
     pub fn on_new_set_custom_mining_job(
         &mut self,
         set_custom_mining_job: SetCustomMiningJob<'static>,
-    ) -> SetCustomMiningJobSuccess {
-        if self.check_set_custom_mining_job(&set_custom_mining_job) {
-            self.negotiated_jobs.insert(
-                set_custom_mining_job.channel_id,
-                set_custom_mining_job.clone(),
-            );
-            SetCustomMiningJobSuccess {
-                channel_id: set_custom_mining_job.channel_id,
-                request_id: set_custom_mining_job.request_id,
-                job_id: self.inner.job_ids.next(),
+    ) -> Result<SetCustomMiningJobSuccess, SetCustomMiningJobError<'static>> {
+        let token = match self.check_set_custom_mining_job(&set_custom_mining_job) {
+            Ok(token) => token,
+            Err(error_code) => {
+                warn!(
+                    "Refusing custom job of channel {}: {}",
+                    set_custom_mining_job.channel_id, error_code
+                );
+                return Err(SetCustomMiningJobError {
+                    channel_id: set_custom_mining_job.channel_id,
+                    request_id: set_custom_mining_job.request_id,
+                    // Infallible unwrap the error codes are short static strings
+                    error_code: error_code.to_string().try_into().unwrap(),
+                });
             }
-        } else {
-            todo!()
+        };
+        if let Some(tokens) = self.declared_job_tokens.as_mut() {
+            tokens.use_token(token);
         }
+        let request_id = set_custom_mining_job.request_id;
+        let channel_id = set_custom_mining_job.channel_id;
+        self.negotiated_jobs
+            .insert(channel_id, set_custom_mining_job);
+        Ok(SetCustomMiningJobSuccess {
+            channel_id,
+            request_id,
+            job_id: self.inner.job_ids.next(),
+        })
     }
 
+    /// Returns the error code of the `SetCustomMiningJob.Error` if the job is not valid:
+    /// * the channel must be an extended channel of this factory
+    /// * the token must have been signed by the JDS, see [`DeclaredJobTokens`], the JDS signs it
+    ///   only when it accepts the `DeclareMiningJob` of the job. A token is used by a single job.
+    /// * the coinbase must pay every pool output at least its value. The first pool output is
+    ///   paid with the value of the template, as done by the job creator, so it must be paid at
+    ///   least `coinbase_tx_value_remaining`.
     fn check_set_custom_mining_job(
         &self,
-        _set_custom_mining_job: &SetCustomMiningJob<'static>,
-    ) -> bool {
-        true
+        set_custom_mining_job: &SetCustomMiningJob<'static>,
+    ) -> Result<DeclaredJobToken, &'static str> {
+        if !self
+            .inner
+            .extended_channels
+            .contains_key(&set_custom_mining_job.channel_id)
+        {
+            return Err(INVALID_CHANNEL_ID);
+        }
+        let tokens = self
+            .declared_job_tokens
+            .as_ref()
+            .ok_or(INVALID_MINING_JOB_TOKEN)?;
+        let token = tokens
+            .check(set_custom_mining_job.token.inner_as_ref())
+            .map_err(|e| {
+                warn!("Invalid token in SetCustomMiningJob: {}", e);
+                INVALID_MINING_JOB_TOKEN
+            })?;
+        let outputs = job_creator::tx_outputs_to_costum_scripts(
+            set_custom_mining_job.coinbase_tx_outputs.inner_as_ref(),
+        );
+        let pays_pool = self
+            .pool_coinbase_outputs
+            .iter()
+            .enumerate()
+            .all(|(i, pool_output)| {
+                let value = match i {
+                    0 => set_custom_mining_job.coinbase_tx_value_remaining,
+                    _ => pool_output.value,
+                };
+                outputs.iter().any(|output| {
+                    output.script_pubkey == pool_output.script_pubkey && output.value >= value
+                })
+            });
+        if !pays_pool {
+            return Err(INVALID_COINBASE_TX_OUTPUTS);
+        }
+        Ok(token)
     }
 
     pub fn get_extended_channels_ids(&self) -> Vec<u32> {
@@ -1286,6 +1741,44 @@ impl PoolChannelFactory {
     pub fn set_target(&mut self, new_target: &mut Target) {
         self.inner.kind.set_target(new_target);
     }
+
+    /// Calls [`ChannelFactory::on_channel_activity`]
+    /// Roles should call it for any message received for `channel_id` that is not a share (shares
+    /// are tracked by the factory itself).
+    pub fn on_channel_activity(&mut self, channel_id: u32) {
+        self.inner.on_channel_activity(channel_id)
+    }
+    /// Calls [`ChannelFactory::idle_channels`]
+    pub fn idle_channels(&self, max_idle: Duration) -> Vec<(u32, Duration)> {
+        self.inner.idle_channels(max_idle)
+    }
+    /// Calls [`ChannelFactory::close_idle_channels`]
+    pub fn close_idle_channels(&mut self, max_idle: Duration) -> Vec<CloseChannel<'static>> {
+        self.inner.close_idle_channels(max_idle)
+    }
+    /// Calls [`ChannelFactory::remove_channel`]
+    /// Used when a `CloseChannel` is received from downstream.
+    pub fn remove_channel(&mut self, channel_id: u32) -> bool {
+        self.inner.remove_channel(channel_id)
+    }
+    /// Calls [`ChannelFactory::set_group_channel`]
+    pub fn set_group_channel(
+        &mut self,
+        group_channel_id: u32,
+        channel_ids: Vec<u32>,
+    ) -> Result<Vec<Mining<'static>>, Error> {
+        self.inner.set_group_channel(group_channel_id, channel_ids)
+    }
+    /// Hash (little endian) of the last share checked against the targets, used by pools to keep
+    /// track of the best shares
+    pub fn last_share_hash(&self) -> Option<[u8; 32]> {
+        self.inner.last_share_hash
+    }
+    /// Shares whose ntime is not within `limits` are rejected with an `invalid-ntime` error, the
+    /// ntime is not checked if None
+    pub fn set_ntime_limits(&mut self, limits: Option<NtimeLimits>) {
+        self.inner.ntime_limits = limits;
+    }
 }
 
 /// Used by proxies that want to open extended channls with upstream. If the proxy has job
@@ -1344,6 +1837,14 @@ impl ProxyExtendedChannelFactory {
             job_ids: Id::new(),
             channel_to_group_id: HashMap::with_hasher(BuildNoHashHasher::default()),
             future_templates: HashMap::with_hasher(BuildNoHashHasher::default()),
+            last_activity: HashMap::with_hasher(BuildNoHashHasher::default()),
+            last_share_hash: None,
+            standard_jobs: StandardJobs::new(),
+            ntime_limits: None,
+            last_prev_hash_received: None,
+            freed_extranonces: Vec::new(),
+            extended_extranonces: HashMap::with_hasher(BuildNoHashHasher::default()),
+            freed_extended_extranonces: Vec::new(),
         };
         ProxyExtendedChannelFactory {
             inner,
@@ -1519,6 +2020,10 @@ impl ProxyExtendedChannelFactory {
             };
             return Ok(OnNewShare::SendErrorDownstream(error));
         }
+        let m = Share::Extended(m);
+        if let Some(error) = self.inner.check_version_rolling(&m, &referenced_job) {
+            return Ok(OnNewShare::SendErrorDownstream(error));
+        }
 
         if let Some(job_creator) = self.job_creator.as_mut() {
             let template_id = job_creator
@@ -1537,7 +2042,7 @@ impl ProxyExtendedChannelFactory {
                 .0
                 .nbits;
             self.inner.check_target(
-                Share::Extended(m),
+                m,
                 bitcoin_target,
                 Some(template_id),
                 self.extended_channel_id,
@@ -1563,7 +2068,7 @@ impl ProxyExtendedChannelFactory {
                 .0
                 .nbits;
             self.inner.check_target(
-                Share::Extended(m),
+                m,
                 bitcoin_target.into(),
                 None,
                 self.extended_channel_id,
@@ -1599,6 +2104,10 @@ impl ProxyExtendedChannelFactory {
             .0;
         match self.inner.channel_to_group_id.get(&m.channel_id) {
             Some(g_id) => {
+                let share = Share::Standard((m, *g_id));
+                if let Some(error) = self.inner.check_version_rolling(&share, &referenced_job) {
+                    return Ok(OnNewShare::SendErrorDownstream(error));
+                }
                 if let Some(job_creator) = self.job_creator.as_mut() {
                     let template_id = job_creator
                         .get_template_id_from_job(
@@ -1618,7 +2127,7 @@ impl ProxyExtendedChannelFactory {
                         .0
                         .nbits;
                     self.inner.check_target(
-                        Share::Standard((m, *g_id)),
+                        share,
                         bitcoin_target,
                         Some(template_id),
                         self.extended_channel_id,
@@ -1644,7 +2153,7 @@ impl ProxyExtendedChannelFactory {
                     // if there is not job_creator is not proxy duty to check if target is below or
                     // above bitcoin target so we set bitcoin_target = 0.
                     self.inner.check_target(
-                        Share::Standard((m, *g_id)),
+                        share,
                         bitcoin_target.into(),
                         None,
                         self.extended_channel_id,
@@ -1722,6 +2231,11 @@ impl ProxyExtendedChannelFactory {
     pub fn channel_extranonce2_size(&self) -> usize {
         self.inner.extranonces.get_len() - self.inner.extranonces.get_range0_len()
     }
+    /// Extranonce bytes reserved for the downstreams, the most that an extended channel opened
+    /// with [`ProxyExtendedChannelFactory::new_extended_channel`] can get
+    pub fn downstream_extranonce_size(&self) -> usize {
+        self.inner.extranonces.get_range2_len()
+    }
 
     // Only used when the proxy is using Job Declaration
     pub fn update_pool_outputs(&mut self, outs: Vec<TxOut>) {
@@ -1745,6 +2259,38 @@ impl ProxyExtendedChannelFactory {
     ) -> Option<bool> {
         self.inner.update_target_for_channel(channel_id, new_target)
     }
+    /// Calls [`ChannelFactory::on_channel_activity`]
+    /// Roles should call it for any message received for `channel_id` that is not a share (shares
+    /// are tracked by the factory itself).
+    pub fn on_channel_activity(&mut self, channel_id: u32) {
+        self.inner.on_channel_activity(channel_id)
+    }
+    /// Calls [`ChannelFactory::idle_channels`]
+    pub fn idle_channels(&self, max_idle: Duration) -> Vec<(u32, Duration)> {
+        self.inner.idle_channels(max_idle)
+    }
+    /// Calls [`ChannelFactory::close_idle_channels`]
+    pub fn close_idle_channels(&mut self, max_idle: Duration) -> Vec<CloseChannel<'static>> {
+        self.inner.close_idle_channels(max_idle)
+    }
+    /// Calls [`ChannelFactory::remove_channel`]
+    /// Used when a `CloseChannel` is received from downstream.
+    pub fn remove_channel(&mut self, channel_id: u32) -> bool {
+        self.inner.remove_channel(channel_id)
+    }
+    /// Calls [`ChannelFactory::set_group_channel`]
+    pub fn set_group_channel(
+        &mut self,
+        group_channel_id: u32,
+        channel_ids: Vec<u32>,
+    ) -> Result<Vec<Mining<'static>>, Error> {
+        self.inner.set_group_channel(group_channel_id, channel_ids)
+    }
+    /// Shares whose ntime is not within `limits` are rejected with an `invalid-ntime` error, the
+    /// ntime is not checked if None
+    pub fn set_ntime_limits(&mut self, limits: Option<NtimeLimits>) {
+        self.inner.ntime_limits = limits;
+    }
 }
 
 /// Used by proxies for tracking upstream targets.
@@ -1978,6 +2524,16 @@ mod test {
             version: 1,
         };
 
+        // The share rolls ntime 11296 seconds after the min_ntime of the prev hash
+        channel.set_ntime_limits(Some(NtimeLimits::default()));
+        match channel.on_submit_shares_standard(share.clone()).unwrap() {
+            OnNewShare::SendErrorDownstream(e) => {
+                assert_eq!(e.error_code.to_vec(), INVALID_NTIME.as_bytes())
+            }
+            _ => panic!(),
+        };
+        channel.set_ntime_limits(Some(NtimeLimits::new(11296, u32::MAX)));
+
         // "Send" the Share to channel
         match channel.on_submit_shares_standard(share).unwrap() {
             OnNewShare::SendErrorDownstream(e) => panic!(
@@ -1991,4 +2547,482 @@ mod test {
             OnNewShare::ShareMeetDownstreamTarget => panic!(),
         };
     }
+
+    #[test]
+    fn test_close_idle_channels() {
+        let extranonces = ExtendedExtranonce::new(0..0, 0..8, 8..16);
+        let ids = Arc::new(Mutex::new(GroupId::new()));
+        let mut channel = PoolChannelFactory::new(
+            ids,
+            extranonces,
+            JobsCreators::new(16),
+            1.0,
+            ExtendedChannelKind::Pool,
+            vec![],
+            "".to_string(),
+        );
+        let result = channel.new_extended_channel(1, 100_000_000.0, 8).unwrap();
+        let (channel_id, extranonce_prefix) = match &result[0] {
+            Mining::OpenExtendedMiningChannelSuccess(success) => {
+                (success.channel_id, success.extranonce_prefix.to_vec())
+            }
+            _ => panic!(),
+        };
+
+        assert!(channel.idle_channels(Duration::from_secs(3600)).is_empty());
+        assert!(channel
+            .close_idle_channels(Duration::from_secs(3600))
+            .is_empty());
+        assert_eq!(channel.get_extended_channels_ids(), vec![channel_id]);
+
+        let idle = channel.idle_channels(Duration::from_secs(0));
+        assert_eq!(idle.len(), 1);
+        assert_eq!(idle[0].0, channel_id);
+
+        let closed = channel.close_idle_channels(Duration::from_secs(0));
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].channel_id, channel_id);
+        assert_eq!(
+            closed[0].reason_code.to_vec(),
+            IDLE_CHANNEL_REASON_CODE.as_bytes().to_vec()
+        );
+        assert!(channel.get_extended_channels_ids().is_empty());
+        assert!(channel.idle_channels(Duration::from_secs(0)).is_empty());
+        assert!(!channel.remove_channel(channel_id));
+
+        // The extranonces of the closed channels are given to the next opened ones
+        let result = channel.new_extended_channel(2, 100_000_000.0, 8).unwrap();
+        match &result[0] {
+            Mining::OpenExtendedMiningChannelSuccess(success) => {
+                assert_ne!(success.channel_id, channel_id);
+                assert_eq!(success.extranonce_prefix.to_vec(), extranonce_prefix);
+            }
+            _ => panic!(),
+        };
+        let group_id = channel.new_group_id();
+        let result = channel
+            .add_standard_channel(3, 100_000_000.0, false, group_id)
+            .unwrap();
+        let (channel_id, extranonce_prefix) = match &result[0] {
+            Mining::OpenStandardMiningChannelSuccess(success) => {
+                (success.channel_id, success.extranonce_prefix.to_vec())
+            }
+            _ => panic!(),
+        };
+        assert!(channel.remove_channel(channel_id));
+        let result = channel
+            .add_standard_channel(4, 100_000_000.0, false, group_id)
+            .unwrap();
+        match &result[0] {
+            Mining::OpenStandardMiningChannelSuccess(success) => {
+                assert_eq!(success.extranonce_prefix.to_vec(), extranonce_prefix)
+            }
+            _ => panic!(),
+        };
+    }
+
+    #[test]
+    fn test_set_group_channel() {
+        let extranonces = ExtendedExtranonce::new(0..0, 0..8, 8..16);
+        let ids = Arc::new(Mutex::new(GroupId::new()));
+        let mut factory = PoolChannelFactory::new(
+            ids,
+            extranonces,
+            JobsCreators::new(16),
+            1.0,
+            ExtendedChannelKind::Pool,
+            vec![],
+            "".to_string(),
+        );
+        let group_id = factory.new_group_id();
+        let result = factory
+            .add_standard_channel(1, 100_000_000.0, false, group_id)
+            .unwrap();
+        let channel_id = match &result[0] {
+            Mining::OpenStandardMiningChannelSuccess(success) => success.channel_id,
+            _ => panic!(),
+        };
+        let result = factory.new_extended_channel(2, 100_000_000.0, 8).unwrap();
+        let extended_id = match &result[0] {
+            Mining::OpenExtendedMiningChannelSuccess(success) => success.channel_id,
+            _ => panic!(),
+        };
+
+        let new_group_id = factory.new_group_id();
+        assert!(matches!(
+            factory.set_group_channel(new_group_id, vec![channel_id, extended_id]),
+            Err(Error::ChannelCanNotBeGrouped(id)) if id == extended_id
+        ));
+        assert!(matches!(
+            factory.set_group_channel(new_group_id, vec![channel_id + 100]),
+            Err(Error::NotFoundChannelId)
+        ));
+        assert!(matches!(
+            factory.set_group_channel(0, vec![channel_id]),
+            Err(Error::GroupIdNotFound)
+        ));
+        // nothing has been moved by the failed attempts
+        assert_eq!(factory.inner.channel_to_group_id[&channel_id], group_id);
+
+        let result = factory
+            .set_group_channel(new_group_id, vec![channel_id])
+            .unwrap();
+        match &result[0] {
+            Mining::SetGroupChannel(m) => {
+                assert_eq!(m.group_channel_id, new_group_id);
+                assert_eq!(m.channel_ids.clone().into_inner(), vec![channel_id]);
+            }
+            _ => panic!(),
+        }
+        assert_eq!(factory.inner.channel_to_group_id[&channel_id], new_group_id);
+        let complete_id = GroupId::into_complete_id(new_group_id, channel_id);
+        assert_eq!(
+            factory.inner.standard_channels_for_non_hom_downstreams[&complete_id].group_id,
+            new_group_id
+        );
+        // the channel is closed from its new group
+        assert!(factory.remove_channel(channel_id));
+    }
+
+    #[test]
+    fn test_set_custom_mining_job_checks() {
+        let pool_output = TxOut {
+            value: 0,
+            script_pubkey: decode_hex(COINBASE_OUTPUT).unwrap().into(),
+        };
+        let mut factory = PoolChannelFactory::new(
+            Arc::new(Mutex::new(GroupId::new())),
+            ExtendedExtranonce::new(0..0, 0..8, 8..16),
+            JobsCreators::new(16),
+            1.0,
+            ExtendedChannelKind::Pool,
+            vec![pool_output.clone()],
+            "".to_string(),
+        );
+        let channel_id = match &factory.new_extended_channel(1, 100_000_000.0, 8).unwrap()[0] {
+            Mining::OpenExtendedMiningChannelSuccess(success) => success.channel_id,
+            _ => panic!(),
+        };
+        let job = |channel_id: u32, token: Vec<u8>, outputs: Vec<TxOut>| SetCustomMiningJob {
+            channel_id,
+            request_id: 7,
+            token: token.try_into().unwrap(),
+            version: VERSION,
+            prev_hash: [0; 32].into(),
+            min_ntime: 0,
+            nbits: PREV_HEADER_NBITS,
+            coinbase_tx_version: 1,
+            coinbase_prefix: vec![].try_into().unwrap(),
+            coinbase_tx_input_n_sequence: u32::MAX,
+            coinbase_tx_value_remaining: BLOCK_REWARD,
+            coinbase_tx_outputs: outputs
+                .iter()
+                .flat_map(bitcoin::consensus::serialize)
+                .collect::<Vec<u8>>()
+                .try_into()
+                .unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: get_merkle_path(),
+            extranonce_size: 16,
+        };
+        let error_code = |result: Result<SetCustomMiningJobSuccess, SetCustomMiningJobError>| {
+            let error = result.unwrap_err();
+            assert_eq!(error.request_id, 7);
+            String::from_utf8(error.error_code.to_vec()).unwrap()
+        };
+
+        let secp = stratum_common::secp256k1::Secp256k1::new();
+        let jds_key = stratum_common::secp256k1::SecretKey::from_slice(&[7; 32]).unwrap();
+        let other_key = stratum_common::secp256k1::SecretKey::from_slice(&[8; 32]).unwrap();
+        let token = DeclaredJobToken::new(1).sign(&jds_key).to_vec();
+        let paid_pool_output = TxOut {
+            value: BLOCK_REWARD,
+            script_pubkey: pool_output.script_pubkey.clone(),
+        };
+        let other_output = TxOut {
+            value: 0,
+            script_pubkey: vec![0x51].into(),
+        };
+
+        // The public key of the JDS is not set
+        assert_eq!(
+            error_code(factory.on_new_set_custom_mining_job(job(
+                channel_id,
+                token.clone(),
+                vec![paid_pool_output.clone()]
+            ))),
+            INVALID_MINING_JOB_TOKEN
+        );
+        factory.set_jds_public_key(jds_key.x_only_public_key(&secp).0);
+
+        assert_eq!(
+            error_code(factory.on_new_set_custom_mining_job(job(
+                channel_id + 1,
+                token.clone(),
+                vec![paid_pool_output.clone()]
+            ))),
+            INVALID_CHANNEL_ID
+        );
+        for invalid_token in [
+            vec![],
+            vec![1],
+            DeclaredJobToken::new(1).sign(&other_key).to_vec(),
+        ] {
+            assert_eq!(
+                error_code(factory.on_new_set_custom_mining_job(job(
+                    channel_id,
+                    invalid_token,
+                    vec![paid_pool_output.clone()]
+                ))),
+                INVALID_MINING_JOB_TOKEN
+            );
+        }
+        assert_eq!(
+            error_code(factory.on_new_set_custom_mining_job(job(
+                channel_id,
+                token.clone(),
+                vec![other_output.clone()]
+            ))),
+            INVALID_COINBASE_TX_OUTPUTS
+        );
+        // The pool script is paid less than the value of the template
+        assert_eq!(
+            error_code(factory.on_new_set_custom_mining_job(job(
+                channel_id,
+                token.clone(),
+                vec![other_output.clone(), pool_output]
+            ))),
+            INVALID_COINBASE_TX_OUTPUTS
+        );
+        assert!(factory.negotiated_jobs.is_empty());
+
+        let success = factory
+            .on_new_set_custom_mining_job(job(
+                channel_id,
+                token.clone(),
+                vec![other_output, paid_pool_output.clone()],
+            ))
+            .unwrap();
+        assert_eq!(success.channel_id, channel_id);
+        assert_eq!(success.request_id, 7);
+        assert!(factory.negotiated_jobs.contains_key(&channel_id));
+
+        // A token is used by a single job
+        assert_eq!(
+            error_code(factory.on_new_set_custom_mining_job(job(
+                channel_id,
+                token,
+                vec![paid_pool_output]
+            ))),
+            INVALID_MINING_JOB_TOKEN
+        );
+    }
+
+    #[test]
+    fn test_update_target_for_hom_standard_channel() {
+        let mut factory = ProxyExtendedChannelFactory::new(
+            Arc::new(Mutex::new(GroupId::new())),
+            ExtendedExtranonce::new(0..0, 0..8, 8..16),
+            None,
+            1.0,
+            ExtendedChannelKind::Proxy {
+                upstream_target: [255; 32].into(),
+            },
+            None,
+            "".to_string(),
+            1,
+        );
+        factory
+            .add_standard_channel(1, 100_000_000.0, true, 2)
+            .unwrap();
+        let target: Target = [1; 32].into();
+        assert_eq!(
+            factory.update_target_for_channel(2, target.clone()),
+            Some(true)
+        );
+        assert_eq!(
+            factory.inner.standard_channels_for_hom_downstreams[&2].target,
+            target
+        );
+        assert_eq!(factory.update_target_for_channel(3, target), None);
+        assert!(factory.remove_channel(2));
+        assert!(factory
+            .inner
+            .standard_channels_for_hom_downstreams
+            .is_empty());
+    }
+
+    #[test]
+    fn test_extended_channels_for_aggregating_proxy() {
+        let (prefix, _, _) = get_coinbase();
+        let out = TxOut {
+            value: BLOCK_REWARD,
+            script_pubkey: decode_hex(COINBASE_OUTPUT).unwrap().into(),
+        };
+        // Same extranonce space as the pool role: 16 bytes to tell the channels apart and 16
+        // bytes for the downstreams
+        let extranonces = ExtendedExtranonce::new(0..0, 0..16, 16..32);
+        let ids = Arc::new(Mutex::new(GroupId::new()));
+        let mut channel = PoolChannelFactory::new(
+            ids,
+            extranonces,
+            JobsCreators::new(32),
+            1.0,
+            ExtendedChannelKind::Pool,
+            vec![out],
+            "".to_string(),
+        );
+        let mut new_template = NewTemplate {
+            template_id: 10,
+            future_template: true,
+            version: VERSION,
+            coinbase_tx_version: 1,
+            coinbase_prefix: prefix.try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: 5_000_000_000,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: get_coinbase_outputs(),
+            coinbase_tx_locktime: 0,
+            merkle_path: get_merkle_path(),
+        };
+        channel.on_new_template(&mut new_template).unwrap();
+        let mut p_hash = decode_hex(PREV_HASH).unwrap();
+        p_hash.reverse();
+        let prev_hash = SetNewPrevHashFromTp {
+            template_id: 10,
+            prev_hash: p_hash.try_into().unwrap(),
+            header_timestamp: PREV_HEADER_TIMESTAMP,
+            n_bits: PREV_HEADER_NBITS,
+            target: nbit_to_target(PREV_HEADER_NBITS),
+        };
+        channel.on_new_prev_hash_from_tp(&prev_hash).unwrap();
+
+        // A proxy aggregating the hashrate of its downstreams opens one extended channel per
+        // upstream connection, asking for the extranonce bytes it needs for them
+        let mut opened = vec![];
+        for (request_id, min_extranonce_size) in [(1, 8), (2, 16)] {
+            let messages = channel
+                .new_extended_channel(request_id, 0.0, min_extranonce_size)
+                .unwrap();
+            let success = match &messages[0] {
+                Mining::OpenExtendedMiningChannelSuccess(success) => success.clone(),
+                _ => panic!(),
+            };
+            assert_eq!(success.request_id, request_id);
+            assert_eq!(success.extranonce_size, min_extranonce_size);
+            assert_eq!(
+                success.extranonce_prefix.len() + success.extranonce_size as usize,
+                32
+            );
+            let job_id = match &messages[1] {
+                Mining::NewExtendedMiningJob(job) => job.job_id,
+                _ => panic!(),
+            };
+            match &messages[2] {
+                Mining::SetNewPrevHash(p_hash) => {
+                    assert_eq!(p_hash.channel_id, success.channel_id);
+                    assert_eq!(p_hash.job_id, job_id);
+                }
+                _ => panic!(),
+            }
+            opened.push((success, job_id));
+        }
+        assert_ne!(
+            opened[0].0.extranonce_prefix.to_vec(),
+            opened[1].0.extranonce_prefix.to_vec()
+        );
+        match &channel.new_extended_channel(3, 0.0, 17).unwrap()[0] {
+            Mining::OpenMiningChannelError(e) => assert_eq!(e.request_id, 3),
+            _ => panic!(),
+        }
+
+        // The proxy rolls the extranonce of the channel for each of its downstreams
+        let (success, job_id) = &opened[0];
+        for (sequence_number, extranonce) in [[0; 8], [1; 8], [255; 8]].iter().enumerate() {
+            let share = SubmitSharesExtended {
+                channel_id: success.channel_id,
+                sequence_number: sequence_number as u32,
+                job_id: *job_id,
+                nonce: 0,
+                ntime: PREV_HEADER_TIMESTAMP,
+                version: VERSION,
+                extranonce: extranonce.to_vec().try_into().unwrap(),
+            };
+            assert!(matches!(
+                channel.on_submit_shares_extended(share).unwrap(),
+                OnNewShare::ShareMeetDownstreamTarget
+            ));
+        }
+
+        // A share of a job that is not the last one is stale
+        let share = SubmitSharesExtended {
+            channel_id: success.channel_id,
+            sequence_number: 3,
+            job_id: *job_id + 1,
+            nonce: 0,
+            ntime: PREV_HEADER_TIMESTAMP,
+            version: VERSION,
+            extranonce: vec![1; 8].try_into().unwrap(),
+        };
+        match channel.on_submit_shares_extended(share).unwrap() {
+            OnNewShare::SendErrorDownstream(e) => {
+                assert_eq!(e.sequence_number, 3);
+                assert_eq!(
+                    e.error_code.to_vec(),
+                    SubmitSharesError::invalid_job_id_error_code().as_bytes()
+                )
+            }
+            _ => panic!(),
+        }
+
+        // Only the BIP320 bits of the version can be rolled
+        let share = SubmitSharesExtended {
+            channel_id: success.channel_id,
+            sequence_number: 3,
+            job_id: *job_id,
+            nonce: 0,
+            ntime: PREV_HEADER_TIMESTAMP,
+            version: VERSION ^ 0x0000_0002,
+            extranonce: vec![1; 8].try_into().unwrap(),
+        };
+        match channel.on_submit_shares_extended(share).unwrap() {
+            OnNewShare::SendErrorDownstream(e) => {
+                assert_eq!(e.sequence_number, 3);
+                assert_eq!(e.error_code.to_vec(), INVALID_VERSION.as_bytes())
+            }
+            _ => panic!(),
+        }
+
+        // An extranonce that is not of the size negotiated for the channel is rejected, and the
+        // rejected share is not activity of the channel
+        let last_activity = channel.inner.last_activity[&success.channel_id];
+        let share = SubmitSharesExtended {
+            channel_id: success.channel_id,
+            sequence_number: 3,
+            job_id: *job_id,
+            nonce: 0,
+            ntime: PREV_HEADER_TIMESTAMP,
+            version: VERSION,
+            extranonce: vec![1; 16].try_into().unwrap(),
+        };
+        match channel.on_submit_shares_extended(share.clone()).unwrap() {
+            OnNewShare::SendErrorDownstream(e) => {
+                assert_eq!(e.sequence_number, 3);
+                assert_eq!(e.error_code.to_vec(), INVALID_EXTRANONCE_SIZE.as_bytes())
+            }
+            _ => panic!(),
+        }
+        assert_eq!(
+            channel.inner.last_activity[&success.channel_id],
+            last_activity
+        );
+        let share = SubmitSharesExtended {
+            channel_id: u32::MAX,
+            ..share
+        };
+        assert!(matches!(
+            channel.on_submit_shares_extended(share),
+            Err(Error::ShareDoNotMatchAnyChannel)
+        ));
+    }
 }
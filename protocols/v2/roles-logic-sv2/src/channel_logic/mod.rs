@@ -1,5 +1,7 @@
 pub mod channel_factory;
 pub mod proxy_group_channel;
+pub mod standard_jobs;
+pub mod template_pipeline;
 
 use mining_sv2::{NewExtendedMiningJob, NewMiningJob};
 use std::convert::TryInto;
@@ -1,7 +1,8 @@
 use crate::{common_properties::StandardChannel, parsers::Mining, Error};
 
 use mining_sv2::{
-    NewExtendedMiningJob, NewMiningJob, OpenStandardMiningChannelSuccess, SetNewPrevHash,
+    NewExtendedMiningJob, NewMiningJob, OpenStandardMiningChannelSuccess, SetGroupChannel,
+    SetNewPrevHash,
 };
 
 use super::extended_to_standard_job;
@@ -73,6 +74,36 @@ impl GroupChannels {
     pub fn ids(&self) -> Vec<u32> {
         self.channels.keys().copied().collect()
     }
+    /// Called when a `SetGroupChannel` is received. The channels are moved to the new group, that
+    /// starts with the jobs of the group of the first moved channel if it does not exist yet.
+    /// Channels that are not in any group are ignored.
+    pub fn on_set_group_channel(&mut self, m: &SetGroupChannel) {
+        let group_id = m.group_channel_id;
+        for channel_id in m.channel_ids.clone().into_inner() {
+            let from = match self.group_of(channel_id) {
+                Some(from) if from != group_id => from,
+                _ => continue,
+            };
+            // Safe unwrap, the channel has just been found in `from`
+            let group = self.channels.get_mut(&from).unwrap();
+            let mut channel = group.hom_downstreams.remove(&channel_id).unwrap();
+            channel.group_id = group_id;
+            if !self.channels.contains_key(&group_id) {
+                let new_group = self.channels[&from].without_downstreams();
+                self.channels.insert(group_id, new_group);
+            }
+            if let Some(group) = self.channels.get_mut(&group_id) {
+                group.hom_downstreams.insert(channel_id, channel);
+            }
+        }
+    }
+    /// Id of the group of `channel_id`
+    pub fn group_of(&self, channel_id: u32) -> Option<u32> {
+        self.channels
+            .iter()
+            .find(|(_, group)| group.hom_downstreams.contains_key(&channel_id))
+            .map(|(id, _)| *id)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -94,6 +125,13 @@ impl GroupChannel {
             last_received_job: None,
         }
     }
+    /// A group with the same jobs and without channels
+    fn without_downstreams(&self) -> Self {
+        Self {
+            hom_downstreams: HashMap::with_hasher(BuildNoHashHasher::default()),
+            ..self.clone()
+        }
+    }
     /// Called when a channel is successfully opened for header only mining on standard channels.
     /// Here we store the new channel, and update state for jobs and return relevant SV2 messages
     /// (NewMiningJob and SNPH)
@@ -204,7 +242,7 @@ impl GroupChannel {
 mod test {
     use super::*;
     use binary_sv2::B064K;
-    use std::convert::TryFrom;
+    use std::convert::{TryFrom, TryInto};
 
     #[test]
     fn group_channel_new_prev_hash_ordering_test() {
@@ -239,4 +277,30 @@ mod test {
 
         assert_eq!(group_channel.last_valid_job.unwrap().version, 1);
     }
+
+    #[test]
+    fn test_set_group_channel() {
+        let mut groups = GroupChannels::new();
+        for channel_id in [1, 2] {
+            groups
+                .on_channel_success_for_hom_downtream(&OpenStandardMiningChannelSuccess {
+                    request_id: channel_id.into(),
+                    channel_id,
+                    target: [0; 32].into(),
+                    extranonce_prefix: vec![0; 8].try_into().unwrap(),
+                    group_channel_id: 10,
+                })
+                .unwrap();
+        }
+        groups.on_set_group_channel(&SetGroupChannel {
+            group_channel_id: 20,
+            channel_ids: vec![2, 3].into(),
+        });
+        assert_eq!(groups.group_of(1), Some(10));
+        assert_eq!(groups.group_of(2), Some(20));
+        assert_eq!(groups.group_of(3), None);
+        let mut ids = groups.ids();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![10, 20]);
+    }
 }
@@ -0,0 +1,181 @@
+//! Standard jobs of the channels opened by header only downstreams.
+//!
+//! A downstream that sets `REQUIRES_STANDARD_JOBS` can not compute the merkle root, so for every
+//! extended job the channel factory computes a standard job for each one of its channels, with the
+//! extranonce of the channel. [`StandardJobs`] keeps them for as long as the extended job they come
+//! from is valid, so the shares submitted on them are checked against the merkle root already
+//! computed instead of hashing the coinbase again.
+use super::extended_to_standard_job;
+use crate::{utils::Id, Error};
+use mining_sv2::{NewExtendedMiningJob, NewMiningJob};
+use nohash_hasher::BuildNoHashHasher;
+use std::{collections::HashMap, convert::TryInto};
+
+#[derive(Debug, Clone)]
+struct StandardJob {
+    extended_job_id: u32,
+    job: NewMiningJob<'static>,
+}
+
+/// Standard jobs of each header only channel, with the id of the extended job they come from
+#[derive(Debug, Default)]
+pub struct StandardJobs {
+    channels: HashMap<u32, Vec<StandardJob>, BuildNoHashHasher<u32>>,
+}
+
+impl StandardJobs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the standard job of `channel_id` for `extended`, computing it with a new id from
+    /// `job_ids` only the first time
+    pub fn get_or_compute(
+        &mut self,
+        extended: &NewExtendedMiningJob,
+        channel_id: u32,
+        extranonce: &[u8],
+        job_ids: &mut Id,
+    ) -> Result<NewMiningJob<'static>, Error> {
+        let jobs = self.channels.entry(channel_id).or_default();
+        if let Some(cached) = jobs.iter().find(|j| j.extended_job_id == extended.job_id) {
+            return Ok(cached.job.clone());
+        }
+        let job = extended_to_standard_job(extended, extranonce, channel_id, Some(job_ids.next()))
+            .ok_or(Error::ImpossibleToCalculateMerkleRoot)?;
+        jobs.push(StandardJob {
+            extended_job_id: extended.job_id,
+            job: job.clone(),
+        });
+        Ok(job)
+    }
+
+    pub fn get(&self, channel_id: u32, job_id: u32) -> Option<&NewMiningJob<'static>> {
+        self.find(channel_id, job_id).map(|j| &j.job)
+    }
+
+    /// Merkle root of the standard job `job_id` of `channel_id`, only if it comes from the extended
+    /// job `extended_job_id`
+    pub fn merkle_root(
+        &self,
+        channel_id: u32,
+        job_id: u32,
+        extended_job_id: u32,
+    ) -> Option<[u8; 32]> {
+        match self.find(channel_id, job_id) {
+            Some(j) if j.extended_job_id == extended_job_id => {
+                j.job.merkle_root.inner_as_ref().try_into().ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Id of the standard job of `channel_id` that comes from the extended job `extended_job_id`
+    pub fn job_id(&self, channel_id: u32, extended_job_id: u32) -> Option<u32> {
+        self.channels
+            .get(&channel_id)?
+            .iter()
+            .find(|j| j.extended_job_id == extended_job_id)
+            .map(|j| j.job.job_id)
+    }
+
+    fn find(&self, channel_id: u32, job_id: u32) -> Option<&StandardJob> {
+        self.channels
+            .get(&channel_id)?
+            .iter()
+            .find(|j| j.job.job_id == job_id)
+    }
+
+    /// Drops the jobs of the extended jobs that are not in `extended_job_ids`
+    pub fn retain(&mut self, extended_job_ids: &[u32]) {
+        for jobs in self.channels.values_mut() {
+            jobs.retain(|j| extended_job_ids.contains(&j.extended_job_id));
+        }
+    }
+
+    /// On a new prev hash only the jobs of the activated extended job are still valid, and they
+    /// are no more future jobs
+    pub fn on_new_prev_hash(&mut self, extended_job_id: Option<u32>, min_ntime: u32) {
+        match extended_job_id {
+            Some(id) => {
+                self.retain(&[id]);
+                for job in self.channels.values_mut().flatten() {
+                    job.job.set_no_future(min_ntime);
+                }
+            }
+            None => self.retain(&[]),
+        }
+    }
+
+    pub fn remove_channel(&mut self, channel_id: u32) {
+        self.channels.remove(&channel_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binary_sv2::{Sv2Option, U256};
+
+    // Coinbase with the 8 bytes of extranonce pushed in the script sig
+    fn coinbase() -> (Vec<u8>, Vec<u8>) {
+        let mut prefix = vec![1, 0, 0, 0, 1];
+        prefix.extend_from_slice(&[0; 32]);
+        prefix.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 9, 8]);
+        let mut suffix = vec![0xff, 0xff, 0xff, 0xff, 1];
+        suffix.extend_from_slice(&5_000_000_000_u64.to_le_bytes());
+        suffix.extend_from_slice(&[1, 0x51, 0, 0, 0, 0]);
+        (prefix, suffix)
+    }
+
+    fn extended(job_id: u32) -> NewExtendedMiningJob<'static> {
+        let path: U256 = [job_id as u8; 32].into();
+        let (prefix, suffix) = coinbase();
+        NewExtendedMiningJob {
+            channel_id: 1,
+            job_id,
+            min_ntime: Sv2Option::new(None),
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: vec![path].try_into().unwrap(),
+            coinbase_tx_prefix: prefix.try_into().unwrap(),
+            coinbase_tx_suffix: suffix.try_into().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_standard_jobs() {
+        let mut jobs = StandardJobs::new();
+        let mut ids = Id::new();
+        let first = jobs
+            .get_or_compute(&extended(10), 2, &[0; 8], &mut ids)
+            .unwrap();
+        // computed once per channel and extended job
+        let again = jobs
+            .get_or_compute(&extended(10), 2, &[0; 8], &mut ids)
+            .unwrap();
+        assert_eq!(first.job_id, again.job_id);
+        let other_channel = jobs
+            .get_or_compute(&extended(10), 3, &[1; 8], &mut ids)
+            .unwrap();
+        assert_ne!(first.job_id, other_channel.job_id);
+        assert_ne!(first.merkle_root, other_channel.merkle_root);
+        let next = jobs
+            .get_or_compute(&extended(11), 2, &[0; 8], &mut ids)
+            .unwrap();
+
+        let root: [u8; 32] = first.merkle_root.inner_as_ref().try_into().unwrap();
+        assert_eq!(jobs.merkle_root(2, first.job_id, 10), Some(root));
+        assert_eq!(jobs.merkle_root(2, first.job_id, 11), None);
+        assert_eq!(jobs.merkle_root(3, first.job_id, 10), None);
+        assert_eq!(jobs.job_id(3, 10), Some(other_channel.job_id));
+        assert_eq!(jobs.job_id(3, 11), None);
+
+        jobs.on_new_prev_hash(Some(11), 42);
+        assert!(jobs.get(2, first.job_id).is_none());
+        assert!(jobs.get(3, other_channel.job_id).is_none());
+        assert!(!jobs.get(2, next.job_id).unwrap().is_future());
+        jobs.remove_channel(2);
+        assert!(jobs.get(2, next.job_id).is_none());
+    }
+}
@@ -0,0 +1,171 @@
+//! From the templates of a Template Provider to the messages of every downstream channel.
+//!
+//! A pool receives `NewTemplate` and `SetNewPrevHash` from its Template Provider and has to send
+//! to each open channel the job built from the template and then the prev hash that activates it.
+//! [`TemplateToJobPipeline`] drives the [`PoolChannelFactory`] for both messages and returns a
+//! [`JobBatch`] with the messages of each channel, in the order they must be sent:
+//! - header only channels get a `NewMiningJob` with their own job id, and a `SetNewPrevHash` that
+//!   refers to it
+//! - group channels and extended channels get the `NewExtendedMiningJob` and a `SetNewPrevHash`
+//!   with the id of the extended job
+use super::channel_factory::PoolChannelFactory;
+use crate::{parsers::Mining, utils::Mutex, Error};
+use nohash_hasher::BuildNoHashHasher;
+use std::{collections::HashMap, sync::Arc};
+use template_distribution_sv2::{NewTemplate, SetNewPrevHash as SetNewPrevHashFromTp};
+
+/// Messages to send to each channel, by channel id. Group channels are keyed by group id.
+pub type JobBatch = HashMap<u32, Vec<Mining<'static>>, BuildNoHashHasher<u32>>;
+
+/// Builds the jobs and the prev hashes of all the channels of a pool
+#[derive(Debug, Clone)]
+pub struct TemplateToJobPipeline {
+    factory: Arc<Mutex<PoolChannelFactory>>,
+}
+
+impl TemplateToJobPipeline {
+    pub fn new(factory: Arc<Mutex<PoolChannelFactory>>) -> Self {
+        Self { factory }
+    }
+
+    pub fn factory(&self) -> Arc<Mutex<PoolChannelFactory>> {
+        self.factory.clone()
+    }
+
+    /// Builds the job of every open channel for `m`, future or not
+    pub fn on_new_template(&self, m: &mut NewTemplate<'static>) -> Result<JobBatch, Error> {
+        let jobs = self
+            .factory
+            .safe_lock(|f| f.on_new_template(m))
+            .map_err(|e| Error::PoisonLock(e.to_string()))??;
+        Ok(jobs
+            .into_iter()
+            .map(|(channel_id, job)| (channel_id, vec![job]))
+            .collect())
+    }
+
+    /// Activates the job of the template of `m` and builds the `SetNewPrevHash` of every open
+    /// channel. Fails with [`Error::NoValidJob`] if no job has been built for the template.
+    pub fn on_new_prev_hash(&self, m: &SetNewPrevHashFromTp<'static>) -> Result<JobBatch, Error> {
+        self.factory
+            .safe_lock(|f| {
+                f.on_new_prev_hash_from_tp(m)?;
+                f.prev_hash_messages()
+            })
+            .map_err(|e| Error::PoisonLock(e.to_string()))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        channel_logic::channel_factory::ExtendedChannelKind, job_creator::JobsCreators,
+        utils::GroupId,
+    };
+    use binary_sv2::U256;
+    use mining_sv2::ExtendedExtranonce;
+    use std::convert::TryInto;
+    use stratum_common::bitcoin::TxOut;
+
+    fn pipeline() -> TemplateToJobPipeline {
+        let out = TxOut {
+            value: 5_000_000_000,
+            script_pubkey: vec![0x51].into(),
+        };
+        let factory = PoolChannelFactory::new(
+            Arc::new(Mutex::new(GroupId::new())),
+            ExtendedExtranonce::new(0..0, 0..8, 8..16),
+            JobsCreators::new(16),
+            1.0,
+            ExtendedChannelKind::Pool,
+            vec![out],
+            "".to_string(),
+        );
+        TemplateToJobPipeline::new(Arc::new(Mutex::new(factory)))
+    }
+
+    fn template(template_id: u64) -> NewTemplate<'static> {
+        let path: U256 = [template_id as u8; 32].into();
+        NewTemplate {
+            template_id,
+            future_template: true,
+            version: 0x2000_0000,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![3, 1, 0, 0, 0].try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: 5_000_000_000,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: vec![path].try_into().unwrap(),
+        }
+    }
+
+    fn prev_hash(template_id: u64) -> SetNewPrevHashFromTp<'static> {
+        SetNewPrevHashFromTp {
+            template_id,
+            prev_hash: [1; 32].into(),
+            header_timestamp: 1_700_000_000,
+            n_bits: 0x1d00ffff,
+            target: [255; 32].into(),
+        }
+    }
+
+    #[test]
+    fn test_template_to_job_pipeline() {
+        let pipeline = pipeline();
+        let (hom_id, extended_id) = pipeline
+            .factory()
+            .safe_lock(|f| {
+                let hom_id = f.new_standard_id_for_hom();
+                f.add_standard_channel(1, 100_000_000.0, true, hom_id)
+                    .unwrap();
+                let extended = f.new_extended_channel(2, 100_000_000.0, 8).unwrap();
+                match &extended[0] {
+                    Mining::OpenExtendedMiningChannelSuccess(m) => (hom_id, m.channel_id),
+                    _ => panic!(),
+                }
+            })
+            .unwrap();
+
+        let mut jobs = pipeline.on_new_template(&mut template(1)).unwrap();
+        let standard_job_id = match jobs.remove(&hom_id).unwrap().as_slice() {
+            [Mining::NewMiningJob(job)] => {
+                assert!(job.is_future());
+                job.job_id
+            }
+            _ => panic!(),
+        };
+        let extended_job_id = match jobs.remove(&extended_id).unwrap().as_slice() {
+            [Mining::NewExtendedMiningJob(job)] => job.job_id,
+            _ => panic!(),
+        };
+        assert!(jobs.is_empty());
+
+        // Each channel activates the job by the id it received it with
+        let mut prev_hashes = pipeline.on_new_prev_hash(&prev_hash(1)).unwrap();
+        match prev_hashes.remove(&hom_id).unwrap().as_slice() {
+            [Mining::SetNewPrevHash(m)] => {
+                assert_eq!(m.channel_id, hom_id);
+                assert_eq!(m.job_id, standard_job_id);
+            }
+            _ => panic!(),
+        }
+        match prev_hashes.remove(&extended_id).unwrap().as_slice() {
+            [Mining::SetNewPrevHash(m)] => {
+                assert_eq!(m.channel_id, extended_id);
+                assert_eq!(m.job_id, extended_job_id);
+                assert_eq!(m.min_ntime, 1_700_000_000);
+            }
+            _ => panic!(),
+        }
+        assert!(prev_hashes.is_empty());
+
+        // No job for the template of the prev hash
+        assert!(matches!(
+            pipeline.on_new_prev_hash(&prev_hash(2)),
+            Err(Error::NoValidJob)
+        ));
+    }
+}
@@ -1,6 +1,7 @@
 //! Traits that implements very basic properties that every implementation should use
-use crate::selectors::{
-    DownstreamMiningSelector, DownstreamSelector, NullDownstreamMiningSelector,
+use crate::{
+    parsers::Mining,
+    selectors::{DownstreamMiningSelector, DownstreamSelector, NullDownstreamMiningSelector},
 };
 use common_messages_sv2::{has_requires_std_job, Protocol, SetupConnection};
 use mining_sv2::{Extranonce, Target};
@@ -15,6 +16,33 @@ pub struct CommonDownstreamData {
     pub version_rolling: bool,
 }
 
+impl CommonDownstreamData {
+    /// False if the flags negotiated by the downstream do not allow it to send `message`
+    pub fn can_send(&self, message: &Mining) -> bool {
+        match message {
+            Mining::OpenExtendedMiningChannel(_) | Mining::SubmitSharesExtended(_) => {
+                !self.header_only
+            }
+            Mining::SetCustomMiningJob(_) => !self.header_only && self.work_selection,
+            _ => true,
+        }
+    }
+
+    /// False if the flags negotiated by the downstream do not allow it to receive `message`, e.g.
+    /// an extended job for a downstream that requires standard jobs
+    pub fn can_receive(&self, message: &Mining) -> bool {
+        match message {
+            Mining::OpenExtendedMiningChannelSuccess(_)
+            | Mining::NewExtendedMiningJob(_)
+            | Mining::SetGroupChannel(_) => !self.header_only,
+            Mining::SetCustomMiningJobSuccess(_) | Mining::SetCustomMiningJobError(_) => {
+                !self.header_only && self.work_selection
+            }
+            _ => true,
+        }
+    }
+}
+
 /// SetupConnection sugared
 #[derive(Debug, Copy, Clone)]
 pub struct PairSettings {
@@ -218,6 +246,57 @@ mod tests {
         assert_eq!(expect, actual);
     }
 
+    #[test]
+    fn filters_messages_by_negotiated_flags() {
+        use core::convert::TryInto;
+        use mining_sv2::{
+            OpenExtendedMiningChannel, SetCustomMiningJobError, SubmitSharesStandard,
+        };
+
+        let open_extended = Mining::OpenExtendedMiningChannel(OpenExtendedMiningChannel {
+            request_id: 0,
+            user_identity: "user".to_string().try_into().unwrap(),
+            nominal_hash_rate: 0.0,
+            max_target: [0xff; 32].into(),
+            min_extranonce_size: 0,
+        });
+        let submit_standard = Mining::SubmitSharesStandard(SubmitSharesStandard {
+            channel_id: 0,
+            sequence_number: 0,
+            job_id: 0,
+            nonce: 0,
+            ntime: 0,
+            version: 0,
+        });
+        let custom_job_error = Mining::SetCustomMiningJobError(SetCustomMiningJobError {
+            channel_id: 0,
+            request_id: 0,
+            error_code: "invalid-job".to_string().try_into().unwrap(),
+        });
+
+        let header_only = CommonDownstreamData {
+            header_only: true,
+            work_selection: false,
+            version_rolling: false,
+        };
+        assert!(!header_only.can_send(&open_extended));
+        assert!(header_only.can_send(&submit_standard));
+        assert!(!header_only.can_receive(&custom_job_error));
+
+        let extended = CommonDownstreamData {
+            header_only: false,
+            ..header_only
+        };
+        assert!(extended.can_send(&open_extended));
+        assert!(!extended.can_receive(&custom_job_error));
+
+        let work_selection = CommonDownstreamData {
+            work_selection: true,
+            ..extended
+        };
+        assert!(work_selection.can_receive(&custom_job_error));
+    }
+
     #[test]
     fn removes_id_from_request_id_mapper() {
         let mut request_id_mapper = RequestIdMapper::new();
@@ -0,0 +1,305 @@
+//! Server side of the `DeclareMiningJob` -> `ProvideMissingTransactions` ->
+//! `ProvideMissingTransactionsSuccess` round trip.
+//!
+//! A JDS receives the transactions of a declared job as a list of short ids, it resolves the ones
+//! that are in its mempool and asks the JDC for the others with a `ProvideMissingTransactions`. The
+//! [`DeclaredJobAssembler`] keeps track of what is still missing, merges the transactions received
+//! in `ProvideMissingTransactionsSuccess` in the declared job and tells when the JDC took too long
+//! to answer.
+use crate::{utils::get_short_hash, Error};
+use job_declaration_sv2::{
+    DeclareMiningJob, ProvideMissingTransactions, ProvideMissingTransactionsSuccess,
+};
+use std::{
+    convert::TryInto,
+    io::Cursor,
+    time::{Duration, Instant},
+};
+use stratum_common::bitcoin::{consensus::Decodable, Transaction, Txid};
+
+/// Default time a JDC has to answer a `ProvideMissingTransactions`
+pub const DEFAULT_MISSING_TRANSACTIONS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A transaction of a declared job
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeclaredTransaction {
+    /// The transaction has been found in the JDS mempool
+    InMempool(Txid),
+    /// The transaction has been provided by the JDC with `ProvideMissingTransactionsSuccess`
+    Provided(Transaction),
+    /// The transaction is not known yet
+    Missing,
+}
+
+impl DeclaredTransaction {
+    pub fn txid(&self) -> Option<Txid> {
+        match self {
+            DeclaredTransaction::InMempool(id) => Some(*id),
+            DeclaredTransaction::Provided(tx) => Some(tx.txid()),
+            DeclaredTransaction::Missing => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DeclaredJobAssembler {
+    declared_job: DeclareMiningJob<'static>,
+    transactions: Vec<DeclaredTransaction>,
+    // positions (not including the coinbase) of the transactions that have been requested
+    requested: Vec<u16>,
+    requested_at: Option<Instant>,
+    timeout: Duration,
+}
+
+impl DeclaredJobAssembler {
+    /// Resolves the short ids of `declared_job` with `lookup`, that must return the txid of the
+    /// transaction with the given short id if it is in the mempool (short ids must be computed
+    /// with `declared_job.tx_short_hash_nonce`).
+    pub fn new<F>(declared_job: DeclareMiningJob<'static>, timeout: Duration, mut lookup: F) -> Self
+    where
+        F: FnMut(&[u8; 6]) -> Option<Txid>,
+    {
+        let transactions = declared_job
+            .tx_short_hash_list
+            .inner_as_ref()
+            .iter()
+            .map(|short_id| {
+                // Infallible unwrap ShortTxId are always 6 bytes long
+                let short_id: [u8; 6] = short_id.to_vec().try_into().unwrap();
+                match lookup(&short_id) {
+                    Some(txid) => DeclaredTransaction::InMempool(txid),
+                    None => DeclaredTransaction::Missing,
+                }
+            })
+            .collect();
+        Self {
+            declared_job,
+            transactions,
+            requested: Vec::new(),
+            requested_at: None,
+            timeout,
+        }
+    }
+
+    pub fn declared_job(&self) -> &DeclareMiningJob<'static> {
+        &self.declared_job
+    }
+
+    pub fn transactions(&self) -> &[DeclaredTransaction] {
+        &self.transactions
+    }
+
+    /// Positions of the transactions that are still missing, 0-indexed not including the coinbase
+    pub fn missing_transactions(&self) -> Vec<u16> {
+        self.transactions
+            .iter()
+            .enumerate()
+            .filter(|(_, tx)| **tx == DeclaredTransaction::Missing)
+            .map(|(i, _)| i as u16)
+            .collect()
+    }
+
+    /// True when every transaction of the declared job is known
+    pub fn is_complete(&self) -> bool {
+        !self.transactions.contains(&DeclaredTransaction::Missing)
+    }
+
+    /// Transactions received from the JDC, they need to be added to the JDS mempool
+    pub fn provided_transactions(&self) -> Vec<Transaction> {
+        self.transactions
+            .iter()
+            .filter_map(|tx| match tx {
+                DeclaredTransaction::Provided(tx) => Some(tx.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the `ProvideMissingTransactions` that must be sent to the JDC, or `None` if every
+    /// transaction is known. Starts the timeout.
+    pub fn provide_missing_transactions(&mut self) -> Option<ProvideMissingTransactions<'static>> {
+        let missing = self.missing_transactions();
+        if missing.is_empty() {
+            return None;
+        }
+        self.requested = missing.clone();
+        self.requested_at = Some(Instant::now());
+        Some(ProvideMissingTransactions {
+            request_id: self.declared_job.request_id,
+            unknown_tx_position_list: missing.into(),
+        })
+    }
+
+    /// Merges the transactions received from the JDC in the declared job. Returns
+    /// `Error::UnknownRequestId` for responses to an old `DeclareMiningJob`, that must be ignored.
+    pub fn on_provide_missing_transactions_success(
+        &mut self,
+        m: ProvideMissingTransactionsSuccess,
+    ) -> Result<(), Error> {
+        if self.requested_at.is_none() || m.request_id != self.declared_job.request_id {
+            return Err(Error::UnknownRequestId(m.request_id));
+        }
+        let transaction_list = m.transaction_list.inner_as_ref();
+        if transaction_list.len() != self.requested.len() {
+            return Err(Error::InvalidMissingTransactions(m.request_id));
+        }
+        let nonce = self.declared_job.tx_short_hash_nonce;
+        let mut provided = Vec::with_capacity(transaction_list.len());
+        for (tx, position) in transaction_list.iter().zip(self.requested.iter()) {
+            let mut cursor = Cursor::new(tx);
+            let tx = Transaction::consensus_decode_from_finite_reader(&mut cursor)
+                .map_err(|e| Error::TxDecodingError(e.to_string()))?;
+            let declared_short_id =
+                &self.declared_job.tx_short_hash_list.inner_as_ref()[*position as usize];
+            if get_short_hash(tx.txid(), nonce).inner_as_ref() != *declared_short_id {
+                return Err(Error::InvalidMissingTransactions(m.request_id));
+            }
+            provided.push((*position as usize, tx));
+        }
+        for (position, tx) in provided {
+            self.transactions[position] = DeclaredTransaction::Provided(tx);
+        }
+        self.requested.clear();
+        self.requested_at = None;
+        Ok(())
+    }
+
+    /// Returns an error if the JDC did not answer to `ProvideMissingTransactions` in time
+    pub fn check_timeout(&self) -> Result<(), Error> {
+        match self.requested_at {
+            Some(requested_at) if requested_at.elapsed() > self.timeout => Err(
+                Error::ProvideMissingTransactionsTimeout(self.declared_job.request_id),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binary_sv2::{Seq064K, B016M};
+    use stratum_common::bitcoin::{consensus::serialize, PackedLockTime, TxOut};
+
+    const NONCE: u64 = 42;
+
+    fn transaction(value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: PackedLockTime(0),
+            input: vec![],
+            output: vec![TxOut {
+                value,
+                script_pubkey: vec![].into(),
+            }],
+        }
+    }
+
+    fn declared_job(txs: &[Transaction]) -> DeclareMiningJob<'static> {
+        let short_ids: Vec<_> = txs
+            .iter()
+            .map(|tx| get_short_hash(tx.txid(), NONCE))
+            .collect();
+        DeclareMiningJob {
+            request_id: 7,
+            mining_job_token: vec![0; 4].try_into().unwrap(),
+            version: 0x2000_0000,
+            coinbase_prefix: vec![].try_into().unwrap(),
+            coinbase_suffix: vec![].try_into().unwrap(),
+            tx_short_hash_nonce: NONCE,
+            tx_short_hash_list: short_ids.into(),
+            tx_hash_list_hash: [0; 32].into(),
+            excess_data: vec![].try_into().unwrap(),
+        }
+    }
+
+    fn success(request_id: u32, txs: &[Transaction]) -> ProvideMissingTransactionsSuccess<'static> {
+        let txs: Vec<B016M> = txs
+            .iter()
+            .map(|tx| serialize(tx).try_into().unwrap())
+            .collect();
+        ProvideMissingTransactionsSuccess {
+            request_id,
+            transaction_list: Seq064K::new(txs).unwrap(),
+        }
+    }
+
+    fn assembler(txs: &[Transaction], in_mempool: &[Transaction]) -> DeclaredJobAssembler {
+        let mempool: Vec<([u8; 6], Txid)> = in_mempool
+            .iter()
+            .map(|tx| {
+                let short_id = get_short_hash(tx.txid(), NONCE)
+                    .to_vec()
+                    .try_into()
+                    .unwrap();
+                (short_id, tx.txid())
+            })
+            .collect();
+        DeclaredJobAssembler::new(
+            declared_job(txs),
+            DEFAULT_MISSING_TRANSACTIONS_TIMEOUT,
+            |short_id| {
+                mempool
+                    .iter()
+                    .find(|(id, _)| id == short_id)
+                    .map(|(_, txid)| *txid)
+            },
+        )
+    }
+
+    #[test]
+    fn test_assemble_declared_job() {
+        let txs: Vec<_> = (0..4).map(transaction).collect();
+        let mut assembler = assembler(&txs, &[txs[0].clone(), txs[2].clone()]);
+        assert!(!assembler.is_complete());
+        let request = assembler.provide_missing_transactions().unwrap();
+        assert_eq!(request.request_id, 7);
+        assert_eq!(request.unknown_tx_position_list.into_inner(), vec![1, 3]);
+
+        // Response to an old job
+        assert!(matches!(
+            assembler.on_provide_missing_transactions_success(success(6, &[])),
+            Err(Error::UnknownRequestId(6))
+        ));
+        // Wrong transactions
+        assert!(matches!(
+            assembler.on_provide_missing_transactions_success(success(
+                7,
+                &[txs[3].clone(), txs[1].clone()]
+            )),
+            Err(Error::InvalidMissingTransactions(7))
+        ));
+        assert!(!assembler.is_complete());
+
+        assembler
+            .on_provide_missing_transactions_success(success(7, &[txs[1].clone(), txs[3].clone()]))
+            .unwrap();
+        assert!(assembler.is_complete());
+        assert!(assembler.provide_missing_transactions().is_none());
+        assert_eq!(
+            assembler.provided_transactions(),
+            vec![txs[1].clone(), txs[3].clone()]
+        );
+        let txids: Vec<_> = assembler
+            .transactions()
+            .iter()
+            .map(|tx| tx.txid().unwrap())
+            .collect();
+        let expected: Vec<_> = txs.iter().map(|tx| tx.txid()).collect();
+        assert_eq!(txids, expected);
+    }
+
+    #[test]
+    fn test_missing_transactions_timeout() {
+        let txs: Vec<_> = (0..2).map(transaction).collect();
+        let mut assembler = assembler(&txs, &[]);
+        assembler.timeout = Duration::from_millis(0);
+        assert!(assembler.check_timeout().is_ok());
+        assembler.provide_missing_transactions().unwrap();
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(matches!(
+            assembler.check_timeout(),
+            Err(Error::ProvideMissingTransactionsTimeout(7))
+        ));
+    }
+}
@@ -0,0 +1,164 @@
+//! Conversions between hashrate, target and difficulty.
+//!
+//! The targets are little endian `U256`, as in the mining messages. The conversions are done on
+//! 256 bits integers: hashrates and difficulties are turned into fixed point numbers with
+//! [`FRACTION_BITS`] fractional bits, so that the precision does not depend on their magnitude
+//! like it does with floats.
+//!
+//! A hash is lower or equal to a target `t` with probability `(t + 1) / 2^256`, so on average a
+//! share is found every `2^256 / (t + 1)` hashes. The difficulty of a target is the target of
+//! difficulty 1 ([`DIFFICULTY_1_TARGET`]) divided by it.
+//!
+//! The conversions are implemented by [`U256Ext`], the functions below map its errors.
+use crate::{errors::Error, utils::InputError};
+pub use binary_sv2::{DIFFICULTY_1_TARGET, FRACTION_BITS};
+use binary_sv2::{FixedPointError, U256Ext, U256};
+
+fn to_input_error(error: FixedPointError) -> InputError {
+    match error {
+        FixedPointError::NegativeInput => InputError::NegativeInput,
+        FixedPointError::DivisionByZero => InputError::DivisionByZero,
+        FixedPointError::NonFiniteInput => InputError::NonFiniteInput,
+    }
+}
+
+/// Target for which a miner with `hashrate` hashes per second finds on average
+/// `shares_per_minute` shares per minute: `2^256 / (hashrate * 60 / shares_per_minute) - 1`
+pub fn hashrate_to_target(hashrate: f64, shares_per_minute: f64) -> Result<U256<'static>, Error> {
+    U256::from_hashrate(hashrate, shares_per_minute)
+        .map_err(|e| Error::TargetError(to_input_error(e)))
+}
+
+/// Difficulty of `target`, its ratio to [`DIFFICULTY_1_TARGET`]
+pub fn target_to_difficulty(target: &U256<'_>) -> Result<f64, Error> {
+    target
+        .difficulty()
+        .map_err(|e| Error::DifficultyError(to_input_error(e)))
+}
+
+/// Target of `difficulty`, [`DIFFICULTY_1_TARGET`] divided by it
+pub fn difficulty_to_target(difficulty: f64) -> Result<U256<'static>, Error> {
+    U256::from_difficulty(difficulty).map_err(|e| Error::DifficultyError(to_input_error(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    fn target_from_be(mut be: [u8; 32]) -> U256<'static> {
+        be.reverse();
+        be.into()
+    }
+
+    fn to_f64(target: &U256<'_>) -> f64 {
+        target
+            .inner_as_ref()
+            .iter()
+            .rev()
+            .fold(0_f64, |acc, byte| acc * 256.0 + *byte as f64)
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= a.abs().max(b.abs()) * 1e-12
+    }
+
+    #[test]
+    fn test_known_vectors() {
+        let difficulty_1: U256<'static> = DIFFICULTY_1_TARGET.into();
+        assert_eq!(target_to_difficulty(&difficulty_1).unwrap(), 1.0);
+        assert_eq!(difficulty_to_target(1.0).unwrap(), difficulty_1);
+
+        // nBits 0x1b0404cb, https://en.bitcoin.it/wiki/Difficulty
+        let mut be = [0_u8; 32];
+        be[5..8].copy_from_slice(&[0x04, 0x04, 0xcb]);
+        let target = target_from_be(be);
+        assert!(close(
+            target_to_difficulty(&target).unwrap(),
+            16307.420938523983
+        ));
+
+        // difficulties lower than 1 are exact
+        let mut be = [0_u8; 32];
+        be[3..5].copy_from_slice(&[0x01, 0xff]);
+        be[5] = 0xfe;
+        let target = target_from_be(be);
+        assert_eq!(target_to_difficulty(&target).unwrap(), 0.5);
+        assert_eq!(difficulty_to_target(0.5).unwrap(), target);
+
+        // 2^32 hashes per share
+        let mut be = [255_u8; 32];
+        be[..4].copy_from_slice(&[0; 4]);
+        let expected = target_from_be(be);
+        assert_eq!(
+            hashrate_to_target(2_f64.powi(32), 60.0).unwrap(),
+            expected.clone()
+        );
+        assert_eq!(hashrate_to_target(2_f64.powi(33), 120.0).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        assert!(matches!(
+            hashrate_to_target(1e12, 0.0),
+            Err(Error::TargetError(InputError::DivisionByZero))
+        ));
+        assert!(matches!(
+            hashrate_to_target(-1.0, 6.0),
+            Err(Error::TargetError(InputError::NegativeInput))
+        ));
+        assert!(matches!(
+            hashrate_to_target(f64::NAN, 6.0),
+            Err(Error::TargetError(InputError::NonFiniteInput))
+        ));
+        assert!(matches!(
+            difficulty_to_target(0.0),
+            Err(Error::DifficultyError(InputError::DivisionByZero))
+        ));
+        assert!(matches!(
+            difficulty_to_target(f64::INFINITY),
+            Err(Error::DifficultyError(InputError::NonFiniteInput))
+        ));
+        assert!(matches!(
+            target_to_difficulty(&[0_u8; 32].into()),
+            Err(Error::DifficultyError(InputError::DivisionByZero))
+        ));
+        // a miner slower than a hash per share gets the highest target
+        assert_eq!(
+            hashrate_to_target(0.5, 60.0).unwrap(),
+            U256::from([255_u8; 32])
+        );
+    }
+
+    #[quickcheck]
+    fn test_difficulty_round_trip(difficulty: u64, fraction: u32) -> bool {
+        let difficulty = (difficulty >> 11) as f64 + fraction as f64 / 2_f64.powi(32);
+        if difficulty == 0.0 {
+            return true;
+        }
+        let target = difficulty_to_target(difficulty).unwrap();
+        close(target_to_difficulty(&target).unwrap(), difficulty)
+    }
+
+    #[quickcheck]
+    fn test_hashrate_to_target_matches_expected_shares(
+        hashrate: u64,
+        shares_per_minute: u8,
+    ) -> bool {
+        let hashrate = (hashrate >> 1) as f64 + 1e6;
+        let shares_per_minute = shares_per_minute as f64 + 1.0;
+        let target = hashrate_to_target(hashrate, shares_per_minute).unwrap();
+        // shares per minute = hashrate * 60 * (t + 1) / 2^256
+        let target_plus_one = (to_f64(&target) + 1.0) / 2_f64.powi(256);
+        close(hashrate * 60.0 * target_plus_one, shares_per_minute)
+    }
+
+    #[quickcheck]
+    fn test_higher_hashrate_lower_target(a: u32, b: u32) -> bool {
+        let (low, high) = (a.min(b) as f64 * 1e3, a.max(b) as f64 * 1e3 + 1e3);
+        hashrate_to_target(high, 6.0)
+            .unwrap()
+            .cmp_as_le_number(&hashrate_to_target(low, 6.0).unwrap())
+            != core::cmp::Ordering::Greater
+    }
+}
@@ -59,8 +59,45 @@ pub enum Error {
     JobNotUpdated(u32, u32),
     TargetError(InputError),
     HashrateError(InputError),
+    DifficultyError(InputError),
     LogicErrorMessage(std::boxed::Box<AllMessages<'static>>),
     JDSMissingTransactions,
+    TransactionDataNotRequested(u64),
+    // (coinbase outputs size, reserved size)
+    CoinbaseOutputsTooBig(usize, usize),
+    // (template id, max coinbase size)
+    TemplateCoinbaseTooBig(u64, usize),
+    InvalidMissingTransactions(u32),
+    ProvideMissingTransactionsTimeout(u32),
+    ShortTxIdCollision([u8; 6]),
+    JobTokenRateLimited(String),
+    InvalidJobToken(u32),
+    ExpiredJobToken(u32),
+    UsedJobToken(u32),
+    InvalidJobTokenLen(usize),
+    /// (channel id, last acknowledged, received) a `SubmitShares.Success` acknowledges a submit
+    /// sent before the one acknowledged by the previous success
+    SequenceNumberNotMonotonic(u32, u32, u32),
+    /// (channel id, sequence number) a response is for a submit that is not waiting for one
+    UnknownSequenceNumber(u32, u32),
+    /// (channel id, acknowledged, sent) a `SubmitShares.Success` acknowledges more submits than
+    /// the ones waiting for a response
+    TooManySubmitsAcknowledged(u32, u32, u32),
+    /// Only the standard channels of non HOM downstreams can be moved to a group
+    ChannelCanNotBeGrouped(u32),
+    /// (message type) the message is not allowed by the flags negotiated in `SetupConnection`
+    MessageNotAllowedByFlags(u8),
+    /// (request id) the response arrived after the timeout of the request
+    RequestTimedOut(u32),
+    /// (template id) the header rebuilt from a share that met the bitcoin target does not meet it
+    InvalidSolution(u64),
+    /// The message type is not part of any subprotocol
+    UnknownMessageType(u8),
+    /// (message type, min version, negotiated version) the message has been introduced by a
+    /// version of the protocol later than the one negotiated
+    UnsupportedForNegotiatedVersion(u8, u16, u16),
+    /// (peer) the message has not been sent, the connection with the peer is closed
+    ConnectionClosed(String),
 }
 
 impl From<BinarySv2Error> for Error {
@@ -90,7 +127,9 @@ impl Display for Error {
                 f,
                 "A channel was attempted to be added to an Upstream, but no groups are specified"
             ),
-            UnexpectedMessage(type_) => write!(f, "Error: Unexpected message received. Recv m type: {:x}", type_),
+            UnexpectedMessage(type_) => write!(f, "Error: Unexpected message received. Recv m type: {:x} ({})", type_, crate::parsers::message_type_name(*type_).unwrap_or("unknown")),
+            RequestTimedOut(id) => write!(f, "Request {} timed out before receiving a response", id),
+            InvalidSolution(id) => write!(f, "The solution of template {} does not meet the bitcoin target", id),
             NoGroupIdOnExtendedChannel => write!(f, "Extended channels do not have group IDs"),
             NoPairableUpstream(a) => {
                 write!(f, "No pairable upstream node: {:?}", a)
@@ -151,8 +190,28 @@ impl Display for Error {
             JobNotUpdated(ds_job_id, us_job_id) => write!(f, "Channel Factory did not update job: Downstream job id = {}, Upstream job id = {}", ds_job_id, us_job_id),
             TargetError(e) => write!(f, "Impossible to get Target: {:?}", e),
             HashrateError(e) => write!(f, "Impossible to get Hashrate: {:?}", e),
+            DifficultyError(e) => write!(f, "Impossible to get Difficulty: {:?}", e),
             LogicErrorMessage(e) => write!(f, "Message is well formatted but can not be handled: {:?}", e),
             JDSMissingTransactions => write!(f, "JD server cannot propagate the block: missing transactions"),
+            TransactionDataNotRequested(id) => write!(f, "Received transaction data for template {} that has not been requested", id),
+            CoinbaseOutputsTooBig(size, reserved) => write!(f, "Coinbase outputs are {} bytes but only {} bytes have been reserved", size, reserved),
+            TemplateCoinbaseTooBig(id, size) => write!(f, "Template {} does not leave enough space for the coinbase outputs, coinbase could be {} bytes", id, size),
+            InvalidMissingTransactions(id) => write!(f, "ProvideMissingTransactionsSuccess for job {} do not contain the requested transactions", id),
+            ProvideMissingTransactionsTimeout(id) => write!(f, "Timeout waiting for the missing transactions of job {}", id),
+            ShortTxIdCollision(id) => write!(f, "More than one transaction with short id {:?}", id),
+            JobTokenRateLimited(user) => write!(f, "Too many mining job tokens allocated by {}", user),
+            InvalidJobToken(token) => write!(f, "Mining job token {} has not been allocated", token),
+            ExpiredJobToken(token) => write!(f, "Mining job token {} is expired", token),
+            UsedJobToken(token) => write!(f, "Mining job token {} has already been used", token),
+            InvalidJobTokenLen(len) => write!(f, "Invalid mining job token of {} bytes", len),
+            SequenceNumberNotMonotonic(channel_id, last, received) => write!(f, "Channel {} acknowledged the sequence number {} after {}", channel_id, received, last),
+            UnknownSequenceNumber(channel_id, sequence_number) => write!(f, "Channel {} received a response for the sequence number {} that is not waiting for one", channel_id, sequence_number),
+            TooManySubmitsAcknowledged(channel_id, acknowledged, sent) => write!(f, "Channel {} acknowledged {} submits but only {} were waiting for a response", channel_id, acknowledged, sent),
+            ChannelCanNotBeGrouped(channel_id) => write!(f, "Channel {} is not a standard channel of a non HOM downstream, it can not be moved to a group", channel_id),
+            UnknownMessageType(type_) => write!(f, "Unknown message type {:x}", type_),
+            UnsupportedForNegotiatedVersion(type_, min, negotiated) => write!(f, "Message type {:x} ({}) requires protocol version {} but version {} has been negotiated", type_, crate::parsers::message_type_name(*type_).unwrap_or("unknown"), min, negotiated),
+            MessageNotAllowedByFlags(type_) => write!(f, "Message type {:x} ({}) is not allowed by the flags negotiated in SetupConnection", type_, crate::parsers::message_type_name(*type_).unwrap_or("unknown")),
+            ConnectionClosed(peer) => write!(f, "Message not sent, the connection with the {} is closed", peer),
         }
     }
 }
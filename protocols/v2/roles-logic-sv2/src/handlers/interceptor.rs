@@ -0,0 +1,149 @@
+//! Lets the proxies observe and rewrite the mining messages that they relay.
+//!
+//! The `handle_message_mining` dispatchers of [`super::mining::ParseDownstreamMiningMessages`] and
+//! [`super::mining::ParseUpstreamMiningMessages`] call the [`Interceptor`] returned by
+//! `get_interceptor` with every decoded message, before it is handled. A message replaced with
+//! [`Intercept::Modify`] is handled in place of the received one, and when the handler relays the
+//! received message the replacement is relayed instead.
+use super::SendTo_;
+use crate::{errors::Error, parsers::Mining};
+use std::fmt::Debug;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Received from the upstream
+    FromUpstream,
+    /// Received from the downstream
+    FromDownstream,
+}
+
+#[derive(Debug, Clone)]
+pub enum Intercept {
+    /// Handle the message as received
+    Pass,
+    /// Handle this message instead of the received one
+    Modify(Mining<'static>),
+    /// Neither handle nor relay the message
+    Drop,
+}
+
+pub trait Interceptor: Send + Sync + Debug {
+    fn intercept(&self, direction: Direction, message: &Mining<'_>) -> Intercept;
+}
+
+/// Returns the message to handle and, if it was modified, its copy to relay. `None` if the
+/// message is dropped.
+#[allow(clippy::type_complexity)]
+pub(crate) fn intercept<'a>(
+    interceptor: Option<&dyn Interceptor>,
+    direction: Direction,
+    message: Result<Mining<'a>, Error>,
+) -> Option<(Result<Mining<'a>, Error>, Option<Mining<'static>>)> {
+    match (interceptor, message) {
+        (Some(interceptor), Ok(message)) => match interceptor.intercept(direction, &message) {
+            Intercept::Pass => Some((Ok(message), None)),
+            Intercept::Modify(modified) => Some((Ok(modified.clone()), Some(modified))),
+            Intercept::Drop => None,
+        },
+        (_, message) => Some((message, None)),
+    }
+}
+
+/// Relays `modified` where `send_to` relays the received message
+pub(crate) fn relay_modified<Remote>(
+    send_to: SendTo_<Mining<'static>, Remote>,
+    modified: &Mining<'static>,
+) -> SendTo_<Mining<'static>, Remote> {
+    match send_to {
+        SendTo_::RelaySameMessageToRemote(remote) => {
+            SendTo_::RelayNewMessageToRemote(remote, modified.clone())
+        }
+        SendTo_::Multiple(sends_to) => SendTo_::Multiple(
+            sends_to
+                .into_iter()
+                .map(|send_to| relay_modified(send_to, modified))
+                .collect(),
+        ),
+        send_to => send_to,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Mutex;
+    use mining_sv2::SetTarget;
+    use std::sync::Arc;
+
+    #[derive(Debug)]
+    struct Fixed(Intercept);
+
+    impl Interceptor for Fixed {
+        fn intercept(&self, _direction: Direction, _message: &Mining<'_>) -> Intercept {
+            self.0.clone()
+        }
+    }
+
+    fn set_target(channel_id: u32, max_target: u8) -> Mining<'static> {
+        Mining::SetTarget(SetTarget {
+            channel_id,
+            maximum_target: [max_target; 32].into(),
+        })
+    }
+
+    fn channel_id(message: &Mining<'_>) -> u32 {
+        match message {
+            Mining::SetTarget(m) => m.channel_id,
+            m => panic!("unexpected message {:?}", m),
+        }
+    }
+
+    #[test]
+    fn modified_message_is_handled_and_relayed() {
+        let interceptor = Fixed(Intercept::Modify(set_target(2, 0xff)));
+        let (handled, relayed) = intercept(
+            Some(&interceptor),
+            Direction::FromUpstream,
+            Ok(set_target(1, 0)),
+        )
+        .unwrap();
+        assert_eq!(channel_id(&handled.unwrap()), 2);
+        let relayed = relayed.unwrap();
+
+        let remote = Arc::new(Mutex::new(()));
+        let send_to = SendTo_::Multiple(vec![
+            SendTo_::RelaySameMessageToRemote(remote.clone()),
+            SendTo_::None(None),
+        ]);
+        match relay_modified(send_to, &relayed) {
+            SendTo_::Multiple(sends_to) => match &sends_to[..] {
+                [SendTo_::RelayNewMessageToRemote(_, m), SendTo_::None(None)] => {
+                    assert_eq!(channel_id(m), 2)
+                }
+                s => panic!("unexpected {:?}", s),
+            },
+            s => panic!("unexpected {:?}", s),
+        }
+    }
+
+    #[test]
+    fn dropped_and_passed_messages() {
+        let drop = Fixed(Intercept::Drop);
+        assert!(intercept(Some(&drop), Direction::FromDownstream, Ok(set_target(1, 0))).is_none());
+
+        let pass = Fixed(Intercept::Pass);
+        let (handled, relayed) =
+            intercept(Some(&pass), Direction::FromDownstream, Ok(set_target(1, 0))).unwrap();
+        assert_eq!(channel_id(&handled.unwrap()), 1);
+        assert!(relayed.is_none());
+
+        // Messages that can not be decoded are not intercepted
+        let (handled, _) = intercept(
+            Some(&drop),
+            Direction::FromDownstream,
+            Err(Error::UnexpectedMessage(0)),
+        )
+        .unwrap();
+        assert!(handled.is_err());
+    }
+}
@@ -1,4 +1,9 @@
-use crate::{common_properties::RequestIdMapper, errors::Error, parsers::Mining};
+use crate::{
+    common_properties::{CommonDownstreamData, RequestIdMapper},
+    errors::Error,
+    parsers::{IsSv2Message, Mining},
+    protocol_errors::IntoProtocolError,
+};
 use core::convert::TryInto;
 use mining_sv2::{
     CloseChannel, NewExtendedMiningJob, NewMiningJob, OpenExtendedMiningChannel,
@@ -15,15 +20,56 @@ use crate::{
     selectors::DownstreamMiningSelector,
 };
 
-use super::SendTo_;
+use super::{
+    interceptor::{intercept, relay_modified, Direction, Interceptor},
+    SendTo_,
+};
 
 use crate::utils::Mutex;
 use const_sv2::*;
 use std::{fmt::Debug as D, sync::Arc};
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 
 pub type SendTo<Remote> = SendTo_<Mining<'static>, Remote>;
 
+/// Answer to a message that the flags negotiated by the downstream do not allow it to send, the
+/// message is not handled so no channel state is changed
+fn not_allowed_by_flags<Remote>(message: &Mining) -> SendTo<Remote> {
+    let error = Error::MessageNotAllowedByFlags(message.message_type());
+    warn!("{}", error);
+    match message {
+        Mining::OpenExtendedMiningChannel(m) => SendTo::Respond(Mining::OpenMiningChannelError(
+            error.open_mining_channel_error(m.get_request_id_as_u32()),
+        )),
+        Mining::SubmitSharesExtended(m) => SendTo::Respond(Mining::SubmitSharesError(
+            error.submit_shares_error(m.channel_id, m.sequence_number),
+        )),
+        // The downstream can not receive a SetCustomMiningJobError either
+        _ => SendTo::None(None),
+    }
+}
+
+/// Drops the messages of `send_to` that the flags negotiated by the downstream do not allow it to
+/// receive, the handlers are not expected to send them
+fn filter_responses<Remote>(
+    send_to: SendTo<Remote>,
+    downstream_mining_data: &CommonDownstreamData,
+) -> SendTo<Remote> {
+    match send_to {
+        SendTo_::Respond(m) if !downstream_mining_data.can_receive(&m) => {
+            error!("{}", Error::MessageNotAllowedByFlags(m.message_type()));
+            SendTo::None(None)
+        }
+        SendTo_::Multiple(sends) => SendTo_::Multiple(
+            sends
+                .into_iter()
+                .map(|s| filter_responses(s, downstream_mining_data))
+                .collect(),
+        ),
+        send_to => send_to,
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SupportedChannelTypes {
     Standard,
@@ -43,6 +89,11 @@ pub trait ParseDownstreamMiningMessages<
 {
     fn get_channel_type(&self) -> SupportedChannelTypes;
 
+    /// See [`Interceptor`]
+    fn get_interceptor(&self) -> Option<Arc<dyn Interceptor>> {
+        None
+    }
+
     /// Used to parse and route SV2 mining messages from the downstream based on `message_type` and
     /// `payload`
     fn handle_message_mining(
@@ -54,12 +105,26 @@ pub trait ParseDownstreamMiningMessages<
     where
         Self: IsMiningDownstream + Sized,
     {
-        match Self::handle_message_mining_deserialized(
-            self_mutex,
+        let (interceptor, downstream_mining_data) = self_mutex
+            .safe_lock(|self_| (self_.get_interceptor(), self_.get_downstream_mining_data()))
+            .map_err(|e| crate::Error::PoisonLock(e.to_string()))?;
+        let (message, modified) = match intercept(
+            interceptor.as_deref(),
+            Direction::FromDownstream,
             (message_type, payload).try_into(),
-            routing_logic,
         ) {
+            Some(intercepted) => intercepted,
+            None => return Ok(SendTo::None(None)),
+        };
+        match Self::handle_message_mining_deserialized(self_mutex, message, routing_logic) {
             Err(Error::UnexpectedMessage(0)) => Err(Error::UnexpectedMessage(message_type)),
+            Ok(send_to) => {
+                let send_to = filter_responses(send_to, &downstream_mining_data);
+                match &modified {
+                    Some(modified) => Ok(relay_modified(send_to, modified)),
+                    None => Ok(send_to),
+                }
+            }
             result => result,
         }
     }
@@ -82,6 +147,11 @@ pub trait ParseDownstreamMiningMessages<
                 )
             })
             .map_err(|e| crate::Error::PoisonLock(e.to_string()))?;
+        if let Ok(message) = &message {
+            if !downstream_mining_data.can_send(message) {
+                return Ok(not_allowed_by_flags(message));
+            }
+        }
         match message {
             Ok(Mining::OpenStandardMiningChannel(mut m)) => {
                 info!(
@@ -260,6 +330,10 @@ pub trait ParseDownstreamMiningMessages<
                     _ => Err(Error::UnexpectedMessage(MESSAGE_TYPE_SET_CUSTOM_MINING_JOB)),
                 }
             }
+            // Only sent by the upstream
+            Ok(Mining::SetGroupChannel(_)) => {
+                Err(Error::UnexpectedMessage(MESSAGE_TYPE_SET_GROUP_CHANNEL))
+            }
             Ok(_) => Err(Error::UnexpectedMessage(0)),
             Err(e) => Err(e),
         }
@@ -314,6 +388,11 @@ pub trait ParseUpstreamMiningMessages<
         None
     }
 
+    /// See [`Interceptor`]
+    fn get_interceptor(&self) -> Option<Arc<dyn Interceptor>> {
+        None
+    }
+
     /// Used to parse and route SV2 mining messages from the upstream based on `message_type` and
     /// `payload` The implementor of DownstreamMining needs to pass a RequestIdMapper if needing
     /// to change the req id. Proxies likely would want to update a downstream req id to a new
@@ -324,12 +403,23 @@ pub trait ParseUpstreamMiningMessages<
         payload: &mut [u8],
         routing_logic: MiningRoutingLogic<Down, Self, Selector, Router>,
     ) -> Result<SendTo<Down>, Error> {
-        match Self::handle_message_mining_deserialized(
-            self_mutex,
+        let interceptor = self_mutex
+            .safe_lock(|self_| self_.get_interceptor())
+            .map_err(|e| crate::Error::PoisonLock(e.to_string()))?;
+        let (message, modified) = match intercept(
+            interceptor.as_deref(),
+            Direction::FromUpstream,
             (message_type, payload).try_into(),
-            routing_logic,
         ) {
+            Some(intercepted) => intercepted,
+            None => return Ok(SendTo::None(None)),
+        };
+        match Self::handle_message_mining_deserialized(self_mutex, message, routing_logic) {
             Err(Error::UnexpectedMessage(0)) => Err(Error::UnexpectedMessage(message_type)),
+            Ok(send_to) => match &modified {
+                Some(modified) => Ok(relay_modified(send_to, modified)),
+                None => Ok(send_to),
+            },
             result => result,
         }
     }
@@ -735,3 +825,46 @@ pub trait ParseUpstreamMiningMessages<
         Ok(SendTo::None(None))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::TryInto;
+
+    #[test]
+    fn test_not_allowed_by_flags_is_answered() {
+        let open_extended = Mining::OpenExtendedMiningChannel(OpenExtendedMiningChannel {
+            request_id: 7,
+            user_identity: "user".to_string().try_into().unwrap(),
+            nominal_hash_rate: 0.0,
+            max_target: [0xff; 32].into(),
+            min_extranonce_size: 0,
+        });
+        match not_allowed_by_flags::<()>(&open_extended) {
+            SendTo::Respond(Mining::OpenMiningChannelError(m)) => {
+                assert_eq!(m.request_id, 7);
+                assert_eq!(
+                    m.error_code.as_ref(),
+                    crate::protocol_errors::UNSUPPORTED_CHANNEL_TYPE.as_bytes()
+                );
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let submit_extended = Mining::SubmitSharesExtended(SubmitSharesExtended {
+            channel_id: 1,
+            sequence_number: 2,
+            job_id: 0,
+            nonce: 0,
+            ntime: 0,
+            version: 0,
+            extranonce: vec![0; 8].try_into().unwrap(),
+        });
+        match not_allowed_by_flags::<()>(&submit_extended) {
+            SendTo::Respond(Mining::SubmitSharesError(m)) => {
+                assert_eq!((m.channel_id, m.sequence_number), (1, 2));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -22,6 +22,7 @@
 //! A `Result<SendTo_, Error>` is returned and it is the duty of the implementer to send the
 //! message.
 pub mod common;
+pub mod interceptor;
 pub mod job_declaration;
 pub mod mining;
 pub mod template_distribution;
@@ -29,7 +29,6 @@ where
         self_: Arc<Mutex<Self>>,
         message: Result<TemplateDistribution<'_>, Error>,
     ) -> Result<SendTo, Error> {
-        // Is ok to unwrap a safe_lock result
         match message {
             Ok(TemplateDistribution::NewTemplate(m)) => {
                 info!(
@@ -111,7 +110,6 @@ where
         self_: Arc<Mutex<Self>>,
         message: Result<TemplateDistribution<'_>, Error>,
     ) -> Result<SendTo, Error> {
-        // Is ok to unwrap a safe_lock result
         match message {
             Ok(TemplateDistribution::CoinbaseOutputDataSize(m)) => self_
                 .safe_lock(|x| x.handle_coinbase_out_data_size(m))
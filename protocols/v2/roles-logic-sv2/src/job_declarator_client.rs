@@ -0,0 +1,529 @@
+//! Client side of the Job Declaration protocol.
+//!
+//! A JDC allocates mining job tokens with `AllocateMiningJobToken`, declares the jobs built on its
+//! templates with `DeclareMiningJob` and, once the JDS accepted a job with
+//! `DeclareMiningJobSuccess`, sends it to the pool with `SetCustomMiningJob`. The job of a future
+//! template is sent when the `SetNewPrevHash` of the template is received. The
+//! [`JobDeclaratorClient`] runs this flow and sends its messages with the [`JobDeclaratorSender`]
+//! of the role, so that it does not depend on how the role talks to the JDS and to the pool.
+use crate::{
+    handlers::{job_declaration::ParseServerJobDeclarationMessages, SendTo_},
+    parsers::JobDeclaration,
+    utils::{hash_lists_tuple, Id},
+    Error,
+};
+use binary_sv2::{Seq064K, B016M, B0255, B064K};
+use job_declaration_sv2::{
+    AllocateMiningJobToken, AllocateMiningJobTokenSuccess, DeclareMiningJob, DeclareMiningJobError,
+    DeclareMiningJobSuccess, IdentifyTransactions, IdentifyTransactionsSuccess,
+    ProvideMissingTransactions, ProvideMissingTransactionsSuccess, SubmitSolutionJd,
+};
+use mining_sv2::{SetCustomMiningJob, SubmitSharesExtended};
+use nohash_hasher::BuildNoHashHasher;
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryInto,
+};
+use stratum_common::bitcoin::{util::psbt::serialize::Deserialize, Transaction};
+use template_distribution_sv2::{NewTemplate, SetNewPrevHash};
+use tracing::{debug, warn};
+
+pub type SendTo = SendTo_<JobDeclaration<'static>, ()>;
+
+/// Tokens kept allocated when not configured, one for the current template and one for the next
+pub const DEFAULT_MIN_TOKENS: usize = 2;
+
+// Declared jobs kept to answer `ProvideMissingTransactions` and `DeclareMiningJobSuccess`, more
+// information in https://github.com/stratum-mining/stratum/pull/904#discussion_r1609469048
+const DECLARED_JOBS_WINDOW: usize = 2;
+
+/// Sends the messages of a [`JobDeclaratorClient`], implemented by the role on top of its
+/// connections
+pub trait JobDeclaratorSender {
+    /// Sends `message` to the JDS
+    fn send_to_jds(&mut self, message: JobDeclaration<'static>) -> Result<(), Error>;
+
+    /// Sends to the pool the job accepted by the JDS for `template_id`. The prev hash, the nbits
+    /// and the min ntime of the job are the ones of the `SetNewPrevHash` of the template, the
+    /// channel id and the request id are left to the sender.
+    fn send_to_pool(
+        &mut self,
+        job: SetCustomMiningJob<'static>,
+        template_id: u64,
+    ) -> Result<(), Error>;
+}
+
+#[derive(Debug, Clone)]
+struct DeclaredJob {
+    declare_job: DeclareMiningJob<'static>,
+    template: NewTemplate<'static>,
+    coinbase_pool_output: Vec<u8>,
+    tx_list: Seq064K<'static, B016M<'static>>,
+}
+
+#[derive(Debug)]
+pub struct JobDeclaratorClient<S> {
+    sender: S,
+    user_identifier: String,
+    min_tokens: usize,
+    tokens: Vec<AllocateMiningJobTokenSuccess<'static>>,
+    // `AllocateMiningJobToken` sent and not answered yet
+    requested_tokens: usize,
+    req_ids: Id,
+    // (request id, job) of the last declared jobs, oldest first
+    declared_jobs: VecDeque<(u32, DeclaredJob)>,
+    // Jobs of the future templates accepted by the JDS, sent to the pool with the SetNewPrevHash
+    // of the template
+    future_jobs: HashMap<u64, SetCustomMiningJob<'static>, BuildNoHashHasher<u64>>,
+    last_set_new_prev_hash: Option<SetNewPrevHash<'static>>,
+    coinbase_tx_prefix: B064K<'static>,
+    coinbase_tx_suffix: B064K<'static>,
+}
+
+impl<S: JobDeclaratorSender> JobDeclaratorClient<S> {
+    /// Tokens are allocated for `user_identifier`, [`JobDeclaratorClient::allocate_tokens`] keeps
+    /// `min_tokens` of them allocated
+    pub fn new(sender: S, user_identifier: String, min_tokens: usize) -> Self {
+        Self {
+            sender,
+            user_identifier,
+            min_tokens,
+            tokens: Vec::new(),
+            requested_tokens: 0,
+            req_ids: Id::new(),
+            declared_jobs: VecDeque::with_capacity(DECLARED_JOBS_WINDOW),
+            future_jobs: HashMap::with_hasher(BuildNoHashHasher::default()),
+            last_set_new_prev_hash: None,
+            coinbase_tx_prefix: B064K::Owned(Vec::new()),
+            coinbase_tx_suffix: B064K::Owned(Vec::new()),
+        }
+    }
+
+    pub fn sender(&mut self) -> &mut S {
+        &mut self.sender
+    }
+
+    /// Sends the `AllocateMiningJobToken` needed to have `min_tokens` tokens once answered
+    pub fn allocate_tokens(&mut self) -> Result<(), Error> {
+        while self.tokens.len() + self.requested_tokens < self.min_tokens {
+            let message = AllocateMiningJobToken {
+                user_identifier: self.user_identifier.clone().try_into()?,
+                request_id: self.req_ids.next(),
+            };
+            self.sender
+                .send_to_jds(JobDeclaration::AllocateMiningJobToken(message))?;
+            self.requested_tokens += 1;
+        }
+        Ok(())
+    }
+
+    /// Takes the last allocated token and allocates a new one. `None` if no token has been
+    /// allocated yet, the caller tries again once an `AllocateMiningJobTokenSuccess` is received.
+    pub fn take_token(&mut self) -> Result<Option<AllocateMiningJobTokenSuccess<'static>>, Error> {
+        let token = self.tokens.pop();
+        self.allocate_tokens()?;
+        Ok(token)
+    }
+
+    /// Sets the coinbase prefix and suffix of the jobs declared from now on
+    pub fn set_coinbase(&mut self, prefix: B064K<'static>, suffix: B064K<'static>) {
+        self.coinbase_tx_prefix = prefix;
+        self.coinbase_tx_suffix = suffix;
+    }
+
+    /// Declares the job of `template` with `token` and returns its request id.
+    /// `coinbase_pool_output` are the outputs of the pool, they come before the outputs of the
+    /// template in the coinbase of the job sent to the pool.
+    pub fn declare_job(
+        &mut self,
+        template: NewTemplate<'static>,
+        token: B0255<'static>,
+        tx_list: Seq064K<'static, B016M<'static>>,
+        excess_data: B064K<'static>,
+        coinbase_pool_output: Vec<u8>,
+    ) -> Result<u32, Error> {
+        let request_id = self.req_ids.next();
+        // TODO: create right nonce
+        let tx_short_hash_nonce = 0;
+        let mut transactions = Vec::new();
+        for tx in tx_list.to_vec() {
+            let tx =
+                Transaction::deserialize(&tx).map_err(|e| Error::TxDecodingError(e.to_string()))?;
+            transactions.push(tx);
+        }
+        let (tx_short_hash_list, tx_hash_list_hash) =
+            hash_lists_tuple(transactions, tx_short_hash_nonce);
+        let declare_job = DeclareMiningJob {
+            request_id,
+            mining_job_token: token,
+            version: template.version,
+            coinbase_prefix: self.coinbase_tx_prefix.clone(),
+            coinbase_suffix: self.coinbase_tx_suffix.clone(),
+            tx_short_hash_nonce,
+            tx_short_hash_list,
+            tx_hash_list_hash,
+            excess_data,
+        };
+        if self.declared_jobs.len() == DECLARED_JOBS_WINDOW {
+            self.declared_jobs.pop_front();
+        }
+        self.declared_jobs.push_back((
+            request_id,
+            DeclaredJob {
+                declare_job: declare_job.clone(),
+                template,
+                coinbase_pool_output,
+                tx_list,
+            },
+        ));
+        self.sender
+            .send_to_jds(JobDeclaration::DeclareMiningJob(declare_job))?;
+        Ok(request_id)
+    }
+
+    /// Sends to the pool the job of the template activated by `m` if it has already been accepted
+    /// by the JDS, the jobs of the other future templates are dropped
+    pub fn on_set_new_prev_hash(&mut self, m: SetNewPrevHash<'static>) -> Result<(), Error> {
+        let job = self.future_jobs.remove(&m.template_id);
+        self.future_jobs.clear();
+        self.last_set_new_prev_hash = Some(m);
+        match job {
+            Some(job) => self.activate(job),
+            None => {
+                debug!("No job accepted yet for the new prev hash");
+                Ok(())
+            }
+        }
+    }
+
+    /// Sends `solution`, a share that meets the bitcoin target, to the JDS
+    pub fn on_solution(&mut self, solution: SubmitSharesExtended<'static>) -> Result<(), Error> {
+        let prev_hash = self
+            .last_set_new_prev_hash
+            .as_ref()
+            .ok_or(Error::JobIsNotFutureButPrevHashNotPresent)?;
+        let solution = SubmitSolutionJd {
+            extranonce: solution.extranonce,
+            prev_hash: prev_hash.prev_hash.clone(),
+            ntime: solution.ntime,
+            nonce: solution.nonce,
+            nbits: prev_hash.n_bits,
+            version: solution.version,
+        };
+        self.sender
+            .send_to_jds(JobDeclaration::SubmitSolution(solution))
+    }
+
+    // Sets the prev hash of `job` and sends it to the pool
+    fn activate(&mut self, mut job: SetCustomMiningJob<'static>) -> Result<(), Error> {
+        let prev_hash = self
+            .last_set_new_prev_hash
+            .as_ref()
+            .ok_or(Error::JobIsNotFutureButPrevHashNotPresent)?;
+        job.prev_hash = prev_hash.prev_hash.clone();
+        job.nbits = prev_hash.n_bits;
+        job.min_ntime = prev_hash.header_timestamp;
+        let template_id = prev_hash.template_id;
+        self.sender.send_to_pool(job, template_id)
+    }
+
+    fn declared_job(&self, request_id: u32) -> Result<&DeclaredJob, Error> {
+        self.declared_jobs
+            .iter()
+            .find(|(id, _)| *id == request_id)
+            .map(|(_, job)| job)
+            .ok_or(Error::UnknownRequestId(request_id))
+    }
+}
+
+// Everything but the prev hash is known when the JDS accepts the job, the job of a future
+// template is built then and only activated by the SetNewPrevHash
+fn custom_job(
+    declared: &DeclaredJob,
+    token: B0255<'static>,
+) -> Result<SetCustomMiningJob<'static>, Error> {
+    let template = &declared.template;
+    let mut coinbase_tx_outputs = declared.coinbase_pool_output.clone();
+    coinbase_tx_outputs.extend_from_slice(&template.coinbase_tx_outputs.to_vec());
+    Ok(SetCustomMiningJob {
+        channel_id: 0,
+        request_id: 0,
+        token,
+        version: declared.declare_job.version,
+        prev_hash: [0; 32].into(),
+        min_ntime: 0,
+        nbits: 0,
+        coinbase_tx_version: template.coinbase_tx_version,
+        coinbase_prefix: template.coinbase_prefix.clone(),
+        coinbase_tx_input_n_sequence: template.coinbase_tx_input_sequence,
+        coinbase_tx_value_remaining: template.coinbase_tx_value_remaining,
+        coinbase_tx_outputs: coinbase_tx_outputs.try_into()?,
+        coinbase_tx_locktime: template.coinbase_tx_locktime,
+        merkle_path: template.merkle_path.clone(),
+        extranonce_size: 0,
+    })
+}
+
+impl<S: JobDeclaratorSender> ParseServerJobDeclarationMessages for JobDeclaratorClient<S> {
+    fn handle_allocate_mining_job_token_success(
+        &mut self,
+        message: AllocateMiningJobTokenSuccess,
+    ) -> Result<SendTo, Error> {
+        self.requested_tokens = self.requested_tokens.saturating_sub(1);
+        self.tokens.push(message.into_static());
+        Ok(SendTo::None(None))
+    }
+
+    fn handle_declare_mining_job_success(
+        &mut self,
+        message: DeclareMiningJobSuccess,
+    ) -> Result<SendTo, Error> {
+        let message = message.into_static();
+        let declared = self.declared_job(message.request_id)?;
+        let template_id = declared.template.template_id;
+        let is_future = declared.template.future_template;
+        let job = custom_job(declared, message.new_mining_job_token)?;
+        // The SetNewPrevHash of a future template can arrive before the JDS accepted its job
+        let activated = self
+            .last_set_new_prev_hash
+            .as_ref()
+            .map(|p| p.template_id == template_id)
+            .unwrap_or(false);
+        if is_future && !activated {
+            self.future_jobs.insert(template_id, job);
+        } else {
+            self.activate(job)?;
+        }
+        Ok(SendTo::None(None))
+    }
+
+    fn handle_declare_mining_job_error(
+        &mut self,
+        message: DeclareMiningJobError,
+    ) -> Result<SendTo, Error> {
+        warn!("Job is not verified: {:?}", message);
+        Ok(SendTo::None(Some(JobDeclaration::DeclareMiningJobError(
+            message.into_static(),
+        ))))
+    }
+
+    fn handle_identify_transactions(
+        &mut self,
+        message: IdentifyTransactions,
+    ) -> Result<SendTo, Error> {
+        let message = IdentifyTransactionsSuccess {
+            request_id: message.request_id,
+            tx_data_hashes: Vec::new().into(),
+        };
+        self.sender
+            .send_to_jds(JobDeclaration::IdentifyTransactionsSuccess(message))?;
+        Ok(SendTo::None(None))
+    }
+
+    fn handle_provide_missing_transactions(
+        &mut self,
+        message: ProvideMissingTransactions,
+    ) -> Result<SendTo, Error> {
+        let tx_list = self
+            .declared_job(message.request_id)?
+            .tx_list
+            .clone()
+            .into_inner();
+        let missing_transactions: Vec<B016M<'static>> = message
+            .unknown_tx_position_list
+            .into_inner()
+            .iter()
+            .filter_map(|&pos| tx_list.get(pos as usize).cloned())
+            .collect();
+        let message = ProvideMissingTransactionsSuccess {
+            request_id: message.request_id,
+            transaction_list: Seq064K::new(missing_transactions)?,
+        };
+        self.sender
+            .send_to_jds(JobDeclaration::ProvideMissingTransactionsSuccess(message))?;
+        Ok(SendTo::None(None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Mutex;
+    use binary_sv2::{Seq0255, U256};
+    use std::sync::Arc;
+
+    #[derive(Debug, Default)]
+    struct RecordingSender {
+        jds: Vec<JobDeclaration<'static>>,
+        pool: Vec<(SetCustomMiningJob<'static>, u64)>,
+    }
+
+    impl JobDeclaratorSender for RecordingSender {
+        fn send_to_jds(&mut self, message: JobDeclaration<'static>) -> Result<(), Error> {
+            self.jds.push(message);
+            Ok(())
+        }
+
+        fn send_to_pool(
+            &mut self,
+            job: SetCustomMiningJob<'static>,
+            template_id: u64,
+        ) -> Result<(), Error> {
+            self.pool.push((job, template_id));
+            Ok(())
+        }
+    }
+
+    fn template(template_id: u64, future_template: bool) -> NewTemplate<'static> {
+        NewTemplate {
+            template_id,
+            future_template,
+            version: 0x2000_0000,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![3, 1, 0, 0].try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: 5_000_000_000,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: Seq0255::new(Vec::<U256>::new()).unwrap(),
+        }
+    }
+
+    fn prev_hash(template_id: u64) -> SetNewPrevHash<'static> {
+        SetNewPrevHash {
+            template_id,
+            prev_hash: [template_id as u8; 32].into(),
+            header_timestamp: 1_700_000_000,
+            n_bits: 0x1d00ffff,
+            target: [0xff; 32].into(),
+        }
+    }
+
+    fn token(request_id: u32) -> AllocateMiningJobTokenSuccess<'static> {
+        AllocateMiningJobTokenSuccess {
+            request_id,
+            mining_job_token: vec![request_id as u8; 4].try_into().unwrap(),
+            coinbase_output_max_additional_size: 100,
+            coinbase_output: vec![].try_into().unwrap(),
+            async_mining_allowed: true,
+        }
+    }
+
+    fn declare(
+        client: &Arc<Mutex<JobDeclaratorClient<RecordingSender>>>,
+        template_id: u64,
+        future: bool,
+    ) -> u32 {
+        client
+            .safe_lock(|c| {
+                let token = c.take_token().unwrap().unwrap();
+                c.declare_job(
+                    template(template_id, future),
+                    token.mining_job_token,
+                    Seq064K::new(Vec::new()).unwrap(),
+                    vec![].try_into().unwrap(),
+                    vec![],
+                )
+                .unwrap()
+            })
+            .unwrap()
+    }
+
+    fn accept(client: &Arc<Mutex<JobDeclaratorClient<RecordingSender>>>, request_id: u32) {
+        let message = JobDeclaration::DeclareMiningJobSuccess(DeclareMiningJobSuccess {
+            request_id,
+            new_mining_job_token: vec![0xaa; 4].try_into().unwrap(),
+        });
+        JobDeclaratorClient::handle_message_job_declaration_deserialized(
+            client.clone(),
+            Ok(message),
+        )
+        .unwrap();
+    }
+
+    fn pool_jobs(client: &Arc<Mutex<JobDeclaratorClient<RecordingSender>>>) -> Vec<(u64, u32)> {
+        client
+            .safe_lock(|c| {
+                c.sender
+                    .pool
+                    .iter()
+                    .map(|(job, template_id)| (*template_id, job.nbits))
+                    .collect()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_job_declarator_client_flow() {
+        let client = Arc::new(Mutex::new(JobDeclaratorClient::new(
+            RecordingSender::default(),
+            "user".to_string(),
+            DEFAULT_MIN_TOKENS,
+        )));
+        client.safe_lock(|c| c.allocate_tokens().unwrap()).unwrap();
+        let allocations = client.safe_lock(|c| c.sender.jds.len()).unwrap();
+        assert_eq!(allocations, 2);
+        assert!(client
+            .safe_lock(|c| c.take_token().unwrap().is_none())
+            .unwrap());
+        for id in 0..2 {
+            JobDeclaratorClient::handle_message_job_declaration_deserialized(
+                client.clone(),
+                Ok(JobDeclaration::AllocateMiningJobTokenSuccess(token(id))),
+            )
+            .unwrap();
+        }
+
+        // The job of a future template waits for its SetNewPrevHash
+        let future = declare(&client, 1, true);
+        accept(&client, future);
+        assert!(pool_jobs(&client).is_empty());
+        client
+            .safe_lock(|c| c.on_set_new_prev_hash(prev_hash(1)).unwrap())
+            .unwrap();
+        assert_eq!(pool_jobs(&client), vec![(1, 0x1d00ffff)]);
+
+        // The job of a template that is not future is sent as soon as it is accepted
+        let current = declare(&client, 2, false);
+        accept(&client, current);
+        assert_eq!(pool_jobs(&client).len(), 2);
+        let job = client.safe_lock(|c| c.sender.pool[1].0.clone()).unwrap();
+        assert_eq!(job.prev_hash, prev_hash(1).prev_hash);
+        assert_eq!(job.token.inner_as_ref(), &[0xaa; 4]);
+
+        // The answer to a request that has not been sent is an error
+        let unknown = JobDeclaration::DeclareMiningJobSuccess(DeclareMiningJobSuccess {
+            request_id: 100,
+            new_mining_job_token: vec![].try_into().unwrap(),
+        });
+        assert!(matches!(
+            JobDeclaratorClient::handle_message_job_declaration_deserialized(
+                client.clone(),
+                Ok(unknown)
+            ),
+            Err(Error::UnknownRequestId(100))
+        ));
+    }
+
+    #[test]
+    fn test_future_job_accepted_after_prev_hash() {
+        let client = Arc::new(Mutex::new(JobDeclaratorClient::new(
+            RecordingSender::default(),
+            "user".to_string(),
+            1,
+        )));
+        JobDeclaratorClient::handle_message_job_declaration_deserialized(
+            client.clone(),
+            Ok(JobDeclaration::AllocateMiningJobTokenSuccess(token(0))),
+        )
+        .unwrap();
+        let future = declare(&client, 7, true);
+        client
+            .safe_lock(|c| c.on_set_new_prev_hash(prev_hash(7)).unwrap())
+            .unwrap();
+        assert!(pool_jobs(&client).is_empty());
+        accept(&client, future);
+        assert_eq!(pool_jobs(&client), vec![(7, 0x1d00ffff)]);
+    }
+}
@@ -157,8 +157,7 @@ impl GroupChannelJobDispatcher {
                 .or_insert_with(|| HashMap::with_hasher(BuildNoHashHasher::default()));
         }
 
-        // Is fine to unwrap a safe_lock result
-        let standard_job_id = self.ids.safe_lock(|ids| ids.next()).unwrap();
+        let standard_job_id = self.ids.super_safe_lock(|ids| ids.next());
 
         let extranonce: Vec<u8> = channel.extranonce.clone().into();
         let new_mining_job_message = extended_to_standard_job_for_group_channel(
@@ -1,12 +1,25 @@
 //! Provides all relevant types, traits and functions to implement a valid SV2 role.
 //!
 //! - For channel and job management, see [`channel_logic`], which utilizes [`job_creator`] and
-//!   [`job_dispatcher`]
+//!   [`job_dispatcher`]. A pool turns the templates of its Template Provider into the messages of
+//!   each channel with [`channel_logic::template_pipeline::TemplateToJobPipeline`]
 //! - For message handling, the traits in [`handlers`] should be implemented
 //! - For basic traits every implementation should use, see [`common_properties`]
 //! - Routers in [`routing_logic`] are used by the traits in `handlers` to decide which
 //!   downstream/upstream to relay/send by using [`selectors`]
+//! - [`upstream_selection`] has the strategies that pick the upstream of a new downstream
 //! - For serializing/deserializing messages, see [`parsers`]
+//! - [`declared_job_assembler`] resolves the transactions of a job declared by a JDC
+//! - [`job_declarator_client`] allocates the tokens, declares the jobs of a JDC and sends the
+//!   accepted ones to the pool
+//! - [`template_store`] caches the templates received from a Template Provider and their
+//!   transaction data
+//! - [`pending_requests`] pairs the requests sent with their responses by request id
+//! - [`protocol_errors`] turns an [`Error`] into the error message of the request that caused it
+//! - [`token_manager`] issues, validates and rate limits the mining job tokens
+//! - [`share_accounting`] batches the `SubmitShares.Success` upstream and checks their sequence
+//!   numbers downstream
+//! - [`share_validation`] checks the `ntime` of the shares
 //! - see [`utils`] for helpers such as safe locking, target and merkle root calculations
 //!
 //!```txt
@@ -36,13 +49,29 @@
 //! ```
 pub mod channel_logic;
 pub mod common_properties;
+// The serde sequences can not be borrowed as slices
+#[cfg(not(feature = "with_serde"))]
+pub mod declared_job_assembler;
+pub mod difficulty;
 pub mod errors;
 pub mod handlers;
 pub mod job_creator;
+// The serde sequences can not be borrowed as slices
+#[cfg(not(feature = "with_serde"))]
+pub mod job_declarator_client;
 pub mod job_dispatcher;
+#[cfg(feature = "lock_diagnostics")]
+pub mod lock_diagnostics;
 pub mod parsers;
+pub mod pending_requests;
+pub mod protocol_errors;
 pub mod routing_logic;
 pub mod selectors;
+pub mod share_accounting;
+pub mod share_validation;
+pub mod template_store;
+pub mod token_manager;
+pub mod upstream_selection;
 pub mod utils;
 pub use common_messages_sv2;
 pub use errors::Error;
@@ -0,0 +1,201 @@
+//! Deadlock diagnostics for [`crate::utils::Mutex`], enabled with the `lock_diagnostics` feature.
+//!
+//! Every thread records the locks that it holds. Taking a lock while holding another records the
+//! order of the two, taking them later in the opposite order can deadlock and is logged. A lock
+//! that is not acquired within the deadlock timeout is logged with the place where it is held.
+//! Waiting for a lock polls it, so this is meant for debugging and not for production.
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    panic::Location,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, OnceLock, PoisonError, TryLockError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{error, warn};
+
+/// Default time after which a lock that is not acquired is reported as a possible deadlock
+pub const DEFAULT_DEADLOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+static DEADLOCK_TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_DEADLOCK_TIMEOUT.as_millis() as u64);
+
+type LockId = usize;
+type Caller = &'static Location<'static>;
+
+thread_local! {
+    // locks held by this thread, in the order they were taken
+    static HELD: RefCell<Vec<(LockId, Caller)>> = const { RefCell::new(Vec::new()) };
+}
+
+// (first, second) -> where second was taken while holding first
+fn orders() -> MutexGuard<'static, HashMap<(LockId, LockId), Caller>> {
+    static ORDERS: OnceLock<Mutex<HashMap<(LockId, LockId), Caller>>> = OnceLock::new();
+    ORDERS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+// lock -> where it is held
+fn holders() -> MutexGuard<'static, HashMap<LockId, Caller>> {
+    static HOLDERS: OnceLock<Mutex<HashMap<LockId, Caller>>> = OnceLock::new();
+    HOLDERS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+fn id<T: ?Sized>(mutex: &Mutex<T>) -> LockId {
+    mutex as *const Mutex<T> as *const () as LockId
+}
+
+/// Sets the time after which a lock that is not acquired is reported as a possible deadlock
+pub fn set_deadlock_timeout(timeout: Duration) {
+    DEADLOCK_TIMEOUT_MS.store(timeout.as_millis() as u64, Ordering::Relaxed);
+}
+
+pub fn deadlock_timeout() -> Duration {
+    Duration::from_millis(DEADLOCK_TIMEOUT_MS.load(Ordering::Relaxed))
+}
+
+/// Released when dropped, after the guard of the lock
+pub(crate) struct Held(LockId);
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        // the thread local may be gone if the lock is released while the thread exits
+        let _ = HELD.try_with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(index) = held.iter().rposition(|(id, _)| *id == self.0) {
+                held.remove(index);
+            }
+        });
+        holders().remove(&self.0);
+    }
+}
+
+fn check_order(id: LockId, caller: Caller) {
+    HELD.with(|held| {
+        let mut orders = orders();
+        for (other, other_caller) in held.borrow().iter() {
+            if *other == id {
+                error!(
+                    "Deadlock: lock taken at {} is already held by the same thread, taken at {}",
+                    caller, other_caller
+                );
+                continue;
+            }
+            if let Some(reversed) = orders.get(&(id, *other)) {
+                warn!(
+                    "Possible deadlock: lock taken at {} while holding the lock taken at {}, \
+                     they were taken in the opposite order at {}",
+                    caller, other_caller, reversed
+                );
+            }
+            orders.entry((*other, id)).or_insert(caller);
+        }
+    });
+}
+
+pub(crate) fn lock<'a, T: ?Sized>(
+    mutex: &'a Mutex<T>,
+    caller: Caller,
+) -> (MutexGuard<'a, T>, Held) {
+    let id = id(mutex);
+    check_order(id, caller);
+    let started = Instant::now();
+    let mut reported = false;
+    let guard = loop {
+        match mutex.try_lock() {
+            Ok(guard) => break guard,
+            Err(TryLockError::Poisoned(e)) => break e.into_inner(),
+            Err(TryLockError::WouldBlock) => {
+                if !reported && started.elapsed() >= deadlock_timeout() {
+                    reported = true;
+                    match holders().get(&id) {
+                        Some(holder) => error!(
+                            "Possible deadlock: lock taken at {} not acquired after {:?}, it is \
+                             held at {}",
+                            caller,
+                            deadlock_timeout(),
+                            holder
+                        ),
+                        None => error!(
+                            "Possible deadlock: lock taken at {} not acquired after {:?}",
+                            caller,
+                            deadlock_timeout()
+                        ),
+                    }
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    };
+    if reported {
+        warn!(
+            "Lock taken at {} acquired after {:?}",
+            caller,
+            started.elapsed()
+        );
+    }
+    HELD.with(|held| held.borrow_mut().push((id, caller)));
+    holders().insert(id, caller);
+    (guard, Held(id))
+}
+
+/// Removes a dropped lock, its address can be reused by another lock
+pub(crate) fn forget<T: ?Sized>(mutex: &Mutex<T>) {
+    let id = id(mutex);
+    orders().retain(|(first, second), _| *first != id && *second != id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Mutex as SafeMutex;
+
+    #[test]
+    fn records_lock_order() {
+        let a = SafeMutex::new(0u8);
+        let b = SafeMutex::new(0u8);
+        a.super_safe_lock(|_| b.super_safe_lock(|_| ()));
+        // opposite order, reported but does not deadlock on a single thread
+        b.super_safe_lock(|_| a.super_safe_lock(|_| ()));
+
+        // the locks are private, take their ids from the recorded orders
+        let orders: Vec<(LockId, LockId)> = orders().keys().copied().collect();
+        let inversions = orders
+            .iter()
+            .filter(|(first, second)| orders.contains(&(*second, *first)))
+            .count();
+        assert!(inversions >= 2);
+        HELD.with(|held| assert!(held.borrow().is_empty()));
+
+        drop(a);
+        drop(b);
+    }
+
+    #[test]
+    fn lock_waits_past_the_timeout() {
+        set_deadlock_timeout(Duration::from_millis(10));
+        let m = std::sync::Arc::new(SafeMutex::new(0u8));
+        let m_ = m.clone();
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let holder = thread::spawn(move || {
+            m_.super_safe_lock(|v| {
+                locked_tx.send(()).unwrap();
+                thread::sleep(Duration::from_millis(50));
+                *v = 1;
+            })
+        });
+        locked_rx.recv().unwrap();
+        assert_eq!(m.super_safe_lock(|v| *v), 1);
+        holder.join().unwrap();
+        set_deadlock_timeout(DEFAULT_DEADLOCK_TIMEOUT);
+    }
+}
@@ -11,7 +11,7 @@ use binary_sv2::Serialize;
 
 use binary_sv2::GetSize;
 
-use binary_sv2::{from_bytes, Deserialize};
+use binary_sv2::{from_bytes, Deserialize, Str0255};
 
 use framing_sv2::framing::Sv2Frame;
 
@@ -60,7 +60,7 @@ use const_sv2::{
 };
 
 use common_messages_sv2::{
-    ChannelEndpointChanged, SetupConnection, SetupConnectionError, SetupConnectionSuccess,
+    ChannelEndpointChanged, Protocol, SetupConnection, SetupConnectionError, SetupConnectionSuccess,
 };
 
 use template_distribution_sv2::{
@@ -225,11 +225,439 @@ impl<'a> Mining<'a> {
     }
 }
 
+#[cfg(not(feature = "with_serde"))]
+impl<'a> CommonMessages<'a> {
+    pub fn into_static(self) -> CommonMessages<'static> {
+        match self {
+            CommonMessages::ChannelEndpointChanged(m) => {
+                CommonMessages::ChannelEndpointChanged(m.into_static())
+            }
+            CommonMessages::SetupConnection(m) => CommonMessages::SetupConnection(m.into_static()),
+            CommonMessages::SetupConnectionError(m) => {
+                CommonMessages::SetupConnectionError(m.into_static())
+            }
+            CommonMessages::SetupConnectionSuccess(m) => {
+                CommonMessages::SetupConnectionSuccess(m.into_static())
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "with_serde"))]
+impl<'a> TemplateDistribution<'a> {
+    pub fn into_static(self) -> TemplateDistribution<'static> {
+        match self {
+            TemplateDistribution::CoinbaseOutputDataSize(m) => {
+                TemplateDistribution::CoinbaseOutputDataSize(m.into_static())
+            }
+            TemplateDistribution::NewTemplate(m) => {
+                TemplateDistribution::NewTemplate(m.into_static())
+            }
+            TemplateDistribution::RequestTransactionData(m) => {
+                TemplateDistribution::RequestTransactionData(m.into_static())
+            }
+            TemplateDistribution::RequestTransactionDataError(m) => {
+                TemplateDistribution::RequestTransactionDataError(m.into_static())
+            }
+            TemplateDistribution::RequestTransactionDataSuccess(m) => {
+                TemplateDistribution::RequestTransactionDataSuccess(m.into_static())
+            }
+            TemplateDistribution::SetNewPrevHash(m) => {
+                TemplateDistribution::SetNewPrevHash(m.into_static())
+            }
+            TemplateDistribution::SubmitSolution(m) => {
+                TemplateDistribution::SubmitSolution(m.into_static())
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "with_serde"))]
+impl<'a> JobDeclaration<'a> {
+    pub fn into_static(self) -> JobDeclaration<'static> {
+        match self {
+            JobDeclaration::AllocateMiningJobToken(m) => {
+                JobDeclaration::AllocateMiningJobToken(m.into_static())
+            }
+            JobDeclaration::AllocateMiningJobTokenSuccess(m) => {
+                JobDeclaration::AllocateMiningJobTokenSuccess(m.into_static())
+            }
+            JobDeclaration::DeclareMiningJob(m) => {
+                JobDeclaration::DeclareMiningJob(m.into_static())
+            }
+            JobDeclaration::DeclareMiningJobError(m) => {
+                JobDeclaration::DeclareMiningJobError(m.into_static())
+            }
+            JobDeclaration::DeclareMiningJobSuccess(m) => {
+                JobDeclaration::DeclareMiningJobSuccess(m.into_static())
+            }
+            JobDeclaration::IdentifyTransactions(m) => {
+                JobDeclaration::IdentifyTransactions(m.into_static())
+            }
+            JobDeclaration::IdentifyTransactionsSuccess(m) => {
+                JobDeclaration::IdentifyTransactionsSuccess(m.into_static())
+            }
+            JobDeclaration::ProvideMissingTransactions(m) => {
+                JobDeclaration::ProvideMissingTransactions(m.into_static())
+            }
+            JobDeclaration::ProvideMissingTransactionsSuccess(m) => {
+                JobDeclaration::ProvideMissingTransactionsSuccess(m.into_static())
+            }
+            JobDeclaration::SubmitSolution(m) => JobDeclaration::SubmitSolution(m.into_static()),
+        }
+    }
+}
+
 pub trait IsSv2Message {
     fn message_type(&self) -> u8;
     fn channel_bit(&self) -> bool;
 }
 
+/// Messages with fields that the spec defines as strings, e.g. `user_identity` or the error codes.
+///
+/// `Str0255` is decoded as plain bytes, with the `strict_strings` feature the parsers also reject
+/// the messages with strings that are not valid UTF-8.
+pub trait HasStrings {
+    fn strings(&self) -> Vec<&Str0255<'_>>;
+
+    /// Errors if any of the strings is not valid UTF-8
+    fn check_strings(&self) -> Result<(), Error> {
+        for string in self.strings() {
+            string.as_str()?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> HasStrings for CommonMessages<'a> {
+    fn strings(&self) -> Vec<&Str0255<'_>> {
+        match self {
+            CommonMessages::SetupConnection(m) => vec![
+                &m.endpoint_host,
+                &m.vendor,
+                &m.hardware_version,
+                &m.firmware,
+                &m.device_id,
+            ],
+            CommonMessages::SetupConnectionError(m) => vec![&m.error_code],
+            _ => vec![],
+        }
+    }
+}
+
+impl<'a> HasStrings for TemplateDistribution<'a> {
+    fn strings(&self) -> Vec<&Str0255<'_>> {
+        match self {
+            TemplateDistribution::RequestTransactionDataError(m) => vec![&m.error_code],
+            _ => vec![],
+        }
+    }
+}
+
+impl<'a> HasStrings for JobDeclaration<'a> {
+    fn strings(&self) -> Vec<&Str0255<'_>> {
+        match self {
+            JobDeclaration::AllocateMiningJobToken(m) => vec![&m.user_identifier],
+            JobDeclaration::DeclareMiningJobError(m) => vec![&m.error_code],
+            _ => vec![],
+        }
+    }
+}
+
+impl<'a> HasStrings for Mining<'a> {
+    fn strings(&self) -> Vec<&Str0255<'_>> {
+        match self {
+            Mining::OpenStandardMiningChannel(m) => vec![&m.user_identity],
+            Mining::OpenExtendedMiningChannel(m) => vec![&m.user_identity],
+            Mining::OpenMiningChannelError(m) => vec![&m.error_code],
+            Mining::UpdateChannelError(m) => vec![&m.error_code],
+            Mining::SubmitSharesError(m) => vec![&m.error_code],
+            Mining::SetCustomMiningJobError(m) => vec![&m.error_code],
+            Mining::CloseChannel(m) => vec![&m.reason_code],
+            Mining::Reconnect(m) => vec![&m.new_host],
+            _ => vec![],
+        }
+    }
+}
+
+/// Name of the message with type `message_type` in any of the subprotocols, for logging
+pub fn message_type_name(message_type: u8) -> Option<&'static str> {
+    common_messages_sv2::message_type_name(message_type)
+        .or_else(|| mining_sv2::message_type_name(message_type))
+        .or_else(|| job_declaration_sv2::message_type_name(message_type))
+        .or_else(|| template_distribution_sv2::message_type_name(message_type))
+}
+
+/// Version of the protocols in which the messages below have been introduced
+const SV2_VERSION: u16 = 2;
+
+/// Subprotocol and first protocol version of a message type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageTypeEntry {
+    pub message_type: u8,
+    /// None for the common messages, that are part of every protocol
+    pub protocol: Option<Protocol>,
+    pub min_version: u16,
+}
+
+impl MessageTypeEntry {
+    const fn new(message_type: u8, protocol: Option<Protocol>, min_version: u16) -> Self {
+        Self {
+            message_type,
+            protocol,
+            min_version,
+        }
+    }
+}
+
+/// Every message type of the subprotocols. The version of a message added by a later version
+/// of a protocol is checked by [`message_type_entry`], so that a role can refuse it on a
+/// connection that negotiated an older version.
+pub const MESSAGE_TYPES: &[MessageTypeEntry] = &[
+    MessageTypeEntry::new(MESSAGE_TYPE_SETUP_CONNECTION, None, SV2_VERSION),
+    MessageTypeEntry::new(MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS, None, SV2_VERSION),
+    MessageTypeEntry::new(MESSAGE_TYPE_SETUP_CONNECTION_ERROR, None, SV2_VERSION),
+    MessageTypeEntry::new(MESSAGE_TYPE_CHANNEL_ENDPOINT_CHANGED, None, SV2_VERSION),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL_SUCCES,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_OPEN_MINING_CHANNEL_ERROR,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_UPDATE_CHANNEL,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_UPDATE_CHANNEL_ERROR,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_CLOSE_CHANNEL,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_SET_EXTRANONCE_PREFIX,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_SUBMIT_SHARES_STANDARD,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_SUBMIT_SHARES_ERROR,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_NEW_MINING_JOB,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_NEW_EXTENDED_MINING_JOB,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_SET_CUSTOM_MINING_JOB,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_SUCCESS,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_ERROR,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_SET_TARGET,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_RECONNECT,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_SET_GROUP_CHANNEL,
+        Some(Protocol::MiningProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_ALLOCATE_MINING_JOB_TOKEN,
+        Some(Protocol::JobDeclarationProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_ALLOCATE_MINING_JOB_TOKEN_SUCCESS,
+        Some(Protocol::JobDeclarationProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_DECLARE_MINING_JOB,
+        Some(Protocol::JobDeclarationProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_DECLARE_MINING_JOB_SUCCESS,
+        Some(Protocol::JobDeclarationProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_DECLARE_MINING_JOB_ERROR,
+        Some(Protocol::JobDeclarationProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_IDENTIFY_TRANSACTIONS,
+        Some(Protocol::JobDeclarationProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_IDENTIFY_TRANSACTIONS_SUCCESS,
+        Some(Protocol::JobDeclarationProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTIONS,
+        Some(Protocol::JobDeclarationProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTIONS_SUCCESS,
+        Some(Protocol::JobDeclarationProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_SUBMIT_SOLUTION_JD,
+        Some(Protocol::JobDeclarationProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_COINBASE_OUTPUT_DATA_SIZE,
+        Some(Protocol::TemplateDistributionProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_NEW_TEMPLATE,
+        Some(Protocol::TemplateDistributionProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_SET_NEW_PREV_HASH,
+        Some(Protocol::TemplateDistributionProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_REQUEST_TRANSACTION_DATA,
+        Some(Protocol::TemplateDistributionProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_SUCCESS,
+        Some(Protocol::TemplateDistributionProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_ERROR,
+        Some(Protocol::TemplateDistributionProtocol),
+        SV2_VERSION,
+    ),
+    MessageTypeEntry::new(
+        MESSAGE_TYPE_SUBMIT_SOLUTION,
+        Some(Protocol::TemplateDistributionProtocol),
+        SV2_VERSION,
+    ),
+];
+
+/// Entry of `message_type` for a connection that negotiated `negotiated_version` in its
+/// `SetupConnection`
+pub fn message_type_entry(
+    message_type: u8,
+    negotiated_version: u16,
+) -> Result<&'static MessageTypeEntry, Error> {
+    let entry = MESSAGE_TYPES
+        .iter()
+        .find(|e| e.message_type == message_type)
+        .ok_or(Error::UnknownMessageType(message_type))?;
+    if negotiated_version < entry.min_version {
+        return Err(Error::UnsupportedForNegotiatedVersion(
+            message_type,
+            entry.min_version,
+            negotiated_version,
+        ));
+    }
+    Ok(entry)
+}
+
+/// Parses a message received on a connection that negotiated `negotiated_version`, `T` is any
+/// of the message enums of this module
+pub fn parse_for_version<'a, T>(
+    message_type: u8,
+    payload: &'a mut [u8],
+    negotiated_version: u16,
+) -> Result<T, Error>
+where
+    T: TryFrom<(u8, &'a mut [u8]), Error = Error>,
+{
+    message_type_entry(message_type, negotiated_version)?;
+    T::try_from((message_type, payload))
+}
+
+// Error of the conversion of `message_type` to the message types of a subprotocol it is not
+// part of
+fn not_in_protocol(message_type: u8) -> Error {
+    match MESSAGE_TYPES.iter().any(|e| e.message_type == message_type) {
+        true => Error::UnexpectedMessage(message_type),
+        false => Error::UnknownMessageType(message_type),
+    }
+}
+
 impl<'a> IsSv2Message for CommonMessages<'a> {
     fn message_type(&self) -> u8 {
         match self {
@@ -603,7 +1031,7 @@ impl TryFrom<u8> for CommonMessageTypes {
             MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS => Ok(CommonMessageTypes::SetupConnectionSuccess),
             MESSAGE_TYPE_SETUP_CONNECTION_ERROR => Ok(CommonMessageTypes::SetupConnectionError),
             MESSAGE_TYPE_CHANNEL_ENDPOINT_CHANGED => Ok(CommonMessageTypes::ChannelEndpointChanged),
-            _ => Err(Error::UnexpectedMessage(v)),
+            _ => Err(not_in_protocol(v)),
         }
     }
 }
@@ -613,24 +1041,27 @@ impl<'a> TryFrom<(u8, &'a mut [u8])> for CommonMessages<'a> {
 
     fn try_from(v: (u8, &'a mut [u8])) -> Result<Self, Self::Error> {
         let msg_type: CommonMessageTypes = v.0.try_into()?;
-        match msg_type {
+        let message = match msg_type {
             CommonMessageTypes::SetupConnection => {
                 let message: SetupConnection<'a> = from_bytes(v.1)?;
-                Ok(CommonMessages::SetupConnection(message))
+                CommonMessages::SetupConnection(message)
             }
             CommonMessageTypes::SetupConnectionSuccess => {
                 let message: SetupConnectionSuccess = from_bytes(v.1)?;
-                Ok(CommonMessages::SetupConnectionSuccess(message))
+                CommonMessages::SetupConnectionSuccess(message)
             }
             CommonMessageTypes::SetupConnectionError => {
                 let message: SetupConnectionError<'a> = from_bytes(v.1)?;
-                Ok(CommonMessages::SetupConnectionError(message))
+                CommonMessages::SetupConnectionError(message)
             }
             CommonMessageTypes::ChannelEndpointChanged => {
                 let message: ChannelEndpointChanged = from_bytes(v.1)?;
-                Ok(CommonMessages::ChannelEndpointChanged(message))
+                CommonMessages::ChannelEndpointChanged(message)
             }
-        }
+        };
+        #[cfg(feature = "strict_strings")]
+        message.check_strings()?;
+        Ok(message)
     }
 }
 
@@ -667,7 +1098,7 @@ impl TryFrom<u8> for TemplateDistributionTypes {
                 Ok(TemplateDistributionTypes::RequestTransactionDataError)
             }
             MESSAGE_TYPE_SUBMIT_SOLUTION => Ok(TemplateDistributionTypes::SubmitSolution),
-            _ => Err(Error::UnexpectedMessage(v)),
+            _ => Err(not_in_protocol(v)),
         }
     }
 }
@@ -677,36 +1108,39 @@ impl<'a> TryFrom<(u8, &'a mut [u8])> for TemplateDistribution<'a> {
 
     fn try_from(v: (u8, &'a mut [u8])) -> Result<Self, Self::Error> {
         let msg_type: TemplateDistributionTypes = v.0.try_into()?;
-        match msg_type {
+        let message = match msg_type {
             TemplateDistributionTypes::CoinbaseOutputDataSize => {
                 let message: CoinbaseOutputDataSize = from_bytes(v.1)?;
-                Ok(TemplateDistribution::CoinbaseOutputDataSize(message))
+                TemplateDistribution::CoinbaseOutputDataSize(message)
             }
             TemplateDistributionTypes::NewTemplate => {
                 let message: NewTemplate<'a> = from_bytes(v.1)?;
-                Ok(TemplateDistribution::NewTemplate(message))
+                TemplateDistribution::NewTemplate(message)
             }
             TemplateDistributionTypes::SetNewPrevHash => {
                 let message: SetNewPrevHash<'a> = from_bytes(v.1)?;
-                Ok(TemplateDistribution::SetNewPrevHash(message))
+                TemplateDistribution::SetNewPrevHash(message)
             }
             TemplateDistributionTypes::RequestTransactionData => {
                 let message: RequestTransactionData = from_bytes(v.1)?;
-                Ok(TemplateDistribution::RequestTransactionData(message))
+                TemplateDistribution::RequestTransactionData(message)
             }
             TemplateDistributionTypes::RequestTransactionDataSuccess => {
                 let message: RequestTransactionDataSuccess = from_bytes(v.1)?;
-                Ok(TemplateDistribution::RequestTransactionDataSuccess(message))
+                TemplateDistribution::RequestTransactionDataSuccess(message)
             }
             TemplateDistributionTypes::RequestTransactionDataError => {
                 let message: RequestTransactionDataError = from_bytes(v.1)?;
-                Ok(TemplateDistribution::RequestTransactionDataError(message))
+                TemplateDistribution::RequestTransactionDataError(message)
             }
             TemplateDistributionTypes::SubmitSolution => {
                 let message: SubmitSolution = from_bytes(v.1)?;
-                Ok(TemplateDistribution::SubmitSolution(message))
+                TemplateDistribution::SubmitSolution(message)
             }
-        }
+        };
+        #[cfg(feature = "strict_strings")]
+        message.check_strings()?;
+        Ok(message)
     }
 }
 
@@ -753,7 +1187,7 @@ impl TryFrom<u8> for JobDeclarationTypes {
                 Ok(JobDeclarationTypes::ProvideMissingTransactionsSuccess)
             }
             MESSAGE_TYPE_SUBMIT_SOLUTION_JD => Ok(JobDeclarationTypes::SubmitSolution),
-            _ => Err(Error::UnexpectedMessage(v)),
+            _ => Err(not_in_protocol(v)),
         }
     }
 }
@@ -763,48 +1197,51 @@ impl<'a> TryFrom<(u8, &'a mut [u8])> for JobDeclaration<'a> {
 
     fn try_from(v: (u8, &'a mut [u8])) -> Result<Self, Self::Error> {
         let msg_type: JobDeclarationTypes = v.0.try_into()?;
-        match msg_type {
+        let message = match msg_type {
             JobDeclarationTypes::AllocateMiningJobToken => {
                 let message: AllocateMiningJobToken = from_bytes(v.1)?;
-                Ok(JobDeclaration::AllocateMiningJobToken(message))
+                JobDeclaration::AllocateMiningJobToken(message)
             }
             JobDeclarationTypes::AllocateMiningJobTokenSuccess => {
                 let message: AllocateMiningJobTokenSuccess = from_bytes(v.1)?;
-                Ok(JobDeclaration::AllocateMiningJobTokenSuccess(message))
+                JobDeclaration::AllocateMiningJobTokenSuccess(message)
             }
             JobDeclarationTypes::DeclareMiningJob => {
                 let message: DeclareMiningJob = from_bytes(v.1)?;
-                Ok(JobDeclaration::DeclareMiningJob(message))
+                JobDeclaration::DeclareMiningJob(message)
             }
             JobDeclarationTypes::DeclareMiningJobSuccess => {
                 let message: DeclareMiningJobSuccess = from_bytes(v.1)?;
-                Ok(JobDeclaration::DeclareMiningJobSuccess(message))
+                JobDeclaration::DeclareMiningJobSuccess(message)
             }
             JobDeclarationTypes::DeclareMiningJobError => {
                 let message: DeclareMiningJobError = from_bytes(v.1)?;
-                Ok(JobDeclaration::DeclareMiningJobError(message))
+                JobDeclaration::DeclareMiningJobError(message)
             }
             JobDeclarationTypes::IdentifyTransactions => {
                 let message: IdentifyTransactions = from_bytes(v.1)?;
-                Ok(JobDeclaration::IdentifyTransactions(message))
+                JobDeclaration::IdentifyTransactions(message)
             }
             JobDeclarationTypes::IdentifyTransactionsSuccess => {
                 let message: IdentifyTransactionsSuccess = from_bytes(v.1)?;
-                Ok(JobDeclaration::IdentifyTransactionsSuccess(message))
+                JobDeclaration::IdentifyTransactionsSuccess(message)
             }
             JobDeclarationTypes::ProvideMissingTransactions => {
                 let message: ProvideMissingTransactions = from_bytes(v.1)?;
-                Ok(JobDeclaration::ProvideMissingTransactions(message))
+                JobDeclaration::ProvideMissingTransactions(message)
             }
             JobDeclarationTypes::ProvideMissingTransactionsSuccess => {
                 let message: ProvideMissingTransactionsSuccess = from_bytes(v.1)?;
-                Ok(JobDeclaration::ProvideMissingTransactionsSuccess(message))
+                JobDeclaration::ProvideMissingTransactionsSuccess(message)
             }
             JobDeclarationTypes::SubmitSolution => {
                 let message: SubmitSolutionJd = from_bytes(v.1)?;
-                Ok(JobDeclaration::SubmitSolution(message))
+                JobDeclaration::SubmitSolution(message)
             }
-        }
+        };
+        #[cfg(feature = "strict_strings")]
+        message.check_strings()?;
+        Ok(message)
     }
 }
 
@@ -872,7 +1309,7 @@ impl TryFrom<u8> for MiningTypes {
             MESSAGE_TYPE_SETUP_CONNECTION => Err(Error::UnexpectedMessage(v)),
             _ => {
                 error!("Invalid message type: {}", v);
-                Err(Error::UnexpectedMessage(v))
+                Err(not_in_protocol(v))
             }
         }
     }
@@ -883,96 +1320,99 @@ impl<'a> TryFrom<(u8, &'a mut [u8])> for Mining<'a> {
 
     fn try_from(v: (u8, &'a mut [u8])) -> Result<Self, Self::Error> {
         let msg_type: MiningTypes = v.0.try_into()?;
-        match msg_type {
+        let message = match msg_type {
             MiningTypes::CloseChannel => {
                 let message: CloseChannel = from_bytes(v.1)?;
-                Ok(Mining::CloseChannel(message))
+                Mining::CloseChannel(message)
             }
             MiningTypes::NewExtendedMiningJob => {
                 let message: NewExtendedMiningJob = from_bytes(v.1)?;
-                Ok(Mining::NewExtendedMiningJob(message))
+                Mining::NewExtendedMiningJob(message)
             }
             MiningTypes::NewMiningJob => {
                 let message: NewMiningJob = from_bytes(v.1)?;
-                Ok(Mining::NewMiningJob(message))
+                Mining::NewMiningJob(message)
             }
             MiningTypes::OpenExtendedMiningChannel => {
                 let message: OpenExtendedMiningChannel = from_bytes(v.1)?;
-                Ok(Mining::OpenExtendedMiningChannel(message))
+                Mining::OpenExtendedMiningChannel(message)
             }
             MiningTypes::OpenExtendedMiningChannelSuccess => {
                 let message: OpenExtendedMiningChannelSuccess = from_bytes(v.1)?;
-                Ok(Mining::OpenExtendedMiningChannelSuccess(message))
+                Mining::OpenExtendedMiningChannelSuccess(message)
             }
             MiningTypes::OpenMiningChannelError => {
                 let message: OpenMiningChannelError = from_bytes(v.1)?;
-                Ok(Mining::OpenMiningChannelError(message))
+                Mining::OpenMiningChannelError(message)
             }
             MiningTypes::OpenStandardMiningChannel => {
                 let message: OpenStandardMiningChannel = from_bytes(v.1)?;
-                Ok(Mining::OpenStandardMiningChannel(message))
+                Mining::OpenStandardMiningChannel(message)
             }
             MiningTypes::OpenStandardMiningChannelSuccess => {
                 let message: OpenStandardMiningChannelSuccess = from_bytes(v.1)?;
-                Ok(Mining::OpenStandardMiningChannelSuccess(message))
+                Mining::OpenStandardMiningChannelSuccess(message)
             }
             MiningTypes::Reconnect => {
                 let message: Reconnect = from_bytes(v.1)?;
-                Ok(Mining::Reconnect(message))
+                Mining::Reconnect(message)
             }
             MiningTypes::SetCustomMiningJob => {
                 let message: SetCustomMiningJob = from_bytes(v.1)?;
-                Ok(Mining::SetCustomMiningJob(message))
+                Mining::SetCustomMiningJob(message)
             }
             MiningTypes::SetCustomMiningJobError => {
                 let message: SetCustomMiningJobError = from_bytes(v.1)?;
-                Ok(Mining::SetCustomMiningJobError(message))
+                Mining::SetCustomMiningJobError(message)
             }
             MiningTypes::SetCustomMiningJobSuccess => {
                 let message: SetCustomMiningJobSuccess = from_bytes(v.1)?;
-                Ok(Mining::SetCustomMiningJobSuccess(message))
+                Mining::SetCustomMiningJobSuccess(message)
             }
             MiningTypes::SetExtranoncePrefix => {
                 let message: SetExtranoncePrefix = from_bytes(v.1)?;
-                Ok(Mining::SetExtranoncePrefix(message))
+                Mining::SetExtranoncePrefix(message)
             }
             MiningTypes::SetGroupChannel => {
                 let message: SetGroupChannel = from_bytes(v.1)?;
-                Ok(Mining::SetGroupChannel(message))
+                Mining::SetGroupChannel(message)
             }
             MiningTypes::SetNewPrevHash => {
                 let message: MiningSetNewPrevHash = from_bytes(v.1)?;
-                Ok(Mining::SetNewPrevHash(message))
+                Mining::SetNewPrevHash(message)
             }
             MiningTypes::SetTarget => {
                 let message: SetTarget = from_bytes(v.1)?;
-                Ok(Mining::SetTarget(message))
+                Mining::SetTarget(message)
             }
             MiningTypes::SubmitSharesError => {
                 let message: SubmitSharesError = from_bytes(v.1)?;
-                Ok(Mining::SubmitSharesError(message))
+                Mining::SubmitSharesError(message)
             }
             MiningTypes::SubmitSharesExtended => {
                 let message: SubmitSharesExtended = from_bytes(v.1)?;
-                Ok(Mining::SubmitSharesExtended(message))
+                Mining::SubmitSharesExtended(message)
             }
             MiningTypes::SubmitSharesStandard => {
                 let message: SubmitSharesStandard = from_bytes(v.1)?;
-                Ok(Mining::SubmitSharesStandard(message))
+                Mining::SubmitSharesStandard(message)
             }
             MiningTypes::SubmitSharesSuccess => {
                 let message: SubmitSharesSuccess = from_bytes(v.1)?;
-                Ok(Mining::SubmitSharesSuccess(message))
+                Mining::SubmitSharesSuccess(message)
             }
             MiningTypes::UpdateChannel => {
                 let message: UpdateChannel = from_bytes(v.1)?;
-                Ok(Mining::UpdateChannel(message))
+                Mining::UpdateChannel(message)
             }
             MiningTypes::UpdateChannelError => {
                 let message: UpdateChannelError = from_bytes(v.1)?;
-                Ok(Mining::UpdateChannelError(message))
+                Mining::UpdateChannelError(message)
             }
-        }
+        };
+        #[cfg(feature = "strict_strings")]
+        message.check_strings()?;
+        Ok(message)
     }
 }
 
@@ -1001,6 +1441,15 @@ impl GetSize for MiningDeviceMessages<'_> {
         }
     }
 }
+#[cfg(not(feature = "with_serde"))]
+impl<'a> MiningDeviceMessages<'a> {
+    pub fn into_static(self) -> MiningDeviceMessages<'static> {
+        match self {
+            MiningDeviceMessages::Common(m) => MiningDeviceMessages::Common(m.into_static()),
+            MiningDeviceMessages::Mining(m) => MiningDeviceMessages::Mining(m.into_static()),
+        }
+    }
+}
 impl<'a> TryFrom<(u8, &'a mut [u8])> for MiningDeviceMessages<'a> {
     type Error = Error;
 
@@ -1030,6 +1479,21 @@ pub enum PoolMessages<'a> {
     TemplateDistribution(TemplateDistribution<'a>),
 }
 
+#[cfg(not(feature = "with_serde"))]
+impl<'a> PoolMessages<'a> {
+    /// Copies the data borrowed from the decoding buffer, so that the message can outlive it
+    pub fn into_static(self) -> PoolMessages<'static> {
+        match self {
+            PoolMessages::Common(m) => PoolMessages::Common(m.into_static()),
+            PoolMessages::Mining(m) => PoolMessages::Mining(m.into_static()),
+            PoolMessages::JobDeclaration(m) => PoolMessages::JobDeclaration(m.into_static()),
+            PoolMessages::TemplateDistribution(m) => {
+                PoolMessages::TemplateDistribution(m.into_static())
+            }
+        }
+    }
+}
+
 impl<'a> TryFrom<MiningDeviceMessages<'a>> for PoolMessages<'a> {
     type Error = Error;
 
@@ -1224,3 +1688,121 @@ impl<'a> TryFrom<PoolMessages<'a>> for MiningDeviceMessages<'a> {
         }
     }
 }
+
+#[cfg(all(test, not(feature = "with_serde")))]
+mod tests {
+    use super::*;
+    use binary_sv2::{to_bytes, Seq0255, U256};
+
+    #[test]
+    fn test_into_static_outlives_the_buffer() {
+        let merkle_path: Vec<U256> = vec![[1_u8; 32].into(), [2_u8; 32].into()];
+        let template = NewTemplate {
+            template_id: 7,
+            future_template: true,
+            version: 0x20000000,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![3, 4, 5].try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: 625_000_000,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: Seq0255::new(merkle_path).unwrap(),
+        };
+        let mut buffer = to_bytes(template.clone()).unwrap();
+        let message: PoolMessages<'static> = {
+            let decoded: PoolMessages = (MESSAGE_TYPE_NEW_TEMPLATE, buffer.as_mut_slice())
+                .try_into()
+                .unwrap();
+            decoded.into_static()
+        };
+        // the decoded message does not borrow the buffer anymore
+        buffer.iter_mut().for_each(|b| *b = 0);
+        drop(buffer);
+        match message {
+            PoolMessages::TemplateDistribution(TemplateDistribution::NewTemplate(m)) => {
+                assert_eq!(m, template.as_static());
+            }
+            m => panic!("unexpected {:?}", m),
+        }
+    }
+
+    #[test]
+    fn test_check_strings() {
+        let error = |error_code: Vec<u8>| {
+            CommonMessages::SetupConnectionError(SetupConnectionError {
+                flags: 0,
+                error_code: error_code.try_into().unwrap(),
+            })
+        };
+        assert!(error(b"unsupported-protocol".to_vec())
+            .check_strings()
+            .is_ok());
+        assert!(error(vec![0x75, 0xff, 0xfe]).check_strings().is_err());
+        let submit = Mining::SubmitSharesSuccess(SubmitSharesSuccess {
+            channel_id: 0,
+            last_sequence_number: 0,
+            new_submits_accepted_count: 0,
+            new_shares_sum: 0,
+        });
+        assert!(submit.strings().is_empty());
+    }
+
+    #[test]
+    fn test_message_types_registry() {
+        for message_type in 0..=u8::MAX {
+            let protocol = match (
+                CommonMessageTypes::try_from(message_type).is_ok(),
+                MiningTypes::try_from(message_type).is_ok(),
+                JobDeclarationTypes::try_from(message_type).is_ok(),
+                TemplateDistributionTypes::try_from(message_type).is_ok(),
+            ) {
+                (false, false, false, false) => {
+                    assert!(matches!(
+                        message_type_entry(message_type, 2),
+                        Err(Error::UnknownMessageType(t)) if t == message_type
+                    ));
+                    continue;
+                }
+                (true, _, _, _) => None,
+                (_, true, _, _) => Some(Protocol::MiningProtocol),
+                (_, _, true, _) => Some(Protocol::JobDeclarationProtocol),
+                (_, _, _, true) => Some(Protocol::TemplateDistributionProtocol),
+            };
+            let entry = message_type_entry(message_type, 2).unwrap();
+            assert_eq!(entry.protocol, protocol);
+            assert!(matches!(
+                message_type_entry(message_type, 1),
+                Err(Error::UnsupportedForNegotiatedVersion(t, 2, 1)) if t == message_type
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_for_version() {
+        let success = SubmitSharesSuccess {
+            channel_id: 1,
+            last_sequence_number: 2,
+            new_submits_accepted_count: 3,
+            new_shares_sum: 4,
+        };
+        let mut payload = to_bytes(success).unwrap();
+        let message: Mining =
+            parse_for_version(MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS, &mut payload, 2).unwrap();
+        assert!(matches!(
+            message,
+            Mining::SubmitSharesSuccess(m) if m.channel_id == 1 && m.new_shares_sum == 4
+        ));
+
+        let result: Result<Mining, Error> = parse_for_version(0x7f, &mut payload, 2);
+        assert!(matches!(result, Err(Error::UnknownMessageType(0x7f))));
+        // Known type of another subprotocol
+        let result: Result<Mining, Error> =
+            parse_for_version(MESSAGE_TYPE_NEW_TEMPLATE, &mut payload, 2);
+        assert!(matches!(
+            result,
+            Err(Error::UnexpectedMessage(MESSAGE_TYPE_NEW_TEMPLATE))
+        ));
+    }
+}
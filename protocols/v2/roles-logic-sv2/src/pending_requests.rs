@@ -0,0 +1,180 @@
+//! Pairs the requests sent to a remote with their responses by request id.
+//!
+//! `OpenStandardMiningChannel`, `AllocateMiningJobToken`, `DeclareMiningJob`,
+//! `SetCustomMiningJob` and the other requests carry a `request_id` that the remote copies in the
+//! response. [`PendingRequests`] allocates the request ids, keeps the state of each request until
+//! its response is received or it times out, and returns the requests still waiting for a response
+//! when the connection is closed.
+use crate::{utils::Id, Error};
+use nohash_hasher::BuildNoHashHasher;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Time after which a request without a response is considered lost
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+struct Pending<T> {
+    value: T,
+    deadline: Instant,
+}
+
+#[derive(Debug)]
+pub struct PendingRequests<T> {
+    ids: Id,
+    pending: HashMap<u32, Pending<T>, BuildNoHashHasher<u32>>,
+    timeout: Duration,
+}
+
+impl<T> Default for PendingRequests<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_REQUEST_TIMEOUT)
+    }
+}
+
+impl<T> PendingRequests<T> {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            ids: Id::new(),
+            pending: HashMap::with_hasher(BuildNoHashHasher::default()),
+            timeout,
+        }
+    }
+
+    /// Allocates the request id of a new request, `value` is returned when the response arrives
+    pub fn insert(&mut self, value: T) -> u32 {
+        self.insert_at(value, self.timeout, Instant::now())
+    }
+
+    /// Same as [`PendingRequests::insert`] for a request that can wait for its response longer
+    /// (or less) than the default timeout
+    pub fn insert_with_timeout(&mut self, value: T, timeout: Duration) -> u32 {
+        self.insert_at(value, timeout, Instant::now())
+    }
+
+    fn insert_at(&mut self, value: T, timeout: Duration, now: Instant) -> u32 {
+        let request_id = self.ids.next();
+        self.pending.insert(
+            request_id,
+            Pending {
+                value,
+                deadline: now + timeout,
+            },
+        );
+        request_id
+    }
+
+    /// Removes the request answered by a response with `request_id`. Returns
+    /// `Error::UnknownRequestId` if no request is waiting for it and `Error::RequestTimedOut` if
+    /// the response arrived too late.
+    pub fn take(&mut self, request_id: u32) -> Result<T, Error> {
+        self.take_at(request_id, Instant::now())
+    }
+
+    fn take_at(&mut self, request_id: u32, now: Instant) -> Result<T, Error> {
+        match self.pending.remove(&request_id) {
+            Some(pending) if now < pending.deadline => Ok(pending.value),
+            Some(_) => Err(Error::RequestTimedOut(request_id)),
+            None => Err(Error::UnknownRequestId(request_id)),
+        }
+    }
+
+    pub fn get(&self, request_id: u32) -> Option<&T> {
+        self.pending.get(&request_id).map(|pending| &pending.value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Removes the requests that did not receive a response before their timeout, ordered by
+    /// request id
+    pub fn expire(&mut self) -> Vec<(u32, T)> {
+        self.expire_at(Instant::now())
+    }
+
+    fn expire_at(&mut self, now: Instant) -> Vec<(u32, T)> {
+        let mut expired: Vec<u32> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| now >= pending.deadline)
+            .map(|(request_id, _)| *request_id)
+            .collect();
+        expired.sort_unstable();
+        expired
+            .into_iter()
+            .filter_map(|request_id| {
+                self.pending
+                    .remove(&request_id)
+                    .map(|pending| (request_id, pending.value))
+            })
+            .collect()
+    }
+
+    /// Removes all the pending requests, ordered by request id. Used when the connection is
+    /// closed, as no response is going to arrive anymore.
+    pub fn drain(&mut self) -> Vec<(u32, T)> {
+        let mut drained: Vec<(u32, T)> = self
+            .pending
+            .drain()
+            .map(|(request_id, pending)| (request_id, pending.value))
+            .collect();
+        drained.sort_unstable_by_key(|(request_id, _)| *request_id);
+        drained
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_requests() {
+        let mut requests = PendingRequests::new(Duration::from_secs(10));
+        let now = Instant::now();
+        let first = requests.insert_at("first", Duration::from_secs(10), now);
+        let second = requests.insert_at("second", Duration::from_secs(10), now);
+        assert_ne!(first, second);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests.get(second), Some(&"second"));
+
+        assert_eq!(requests.take_at(second, now).unwrap(), "second");
+        // A response is matched only once
+        assert!(matches!(
+            requests.take_at(second, now),
+            Err(Error::UnknownRequestId(_))
+        ));
+
+        let later = now + Duration::from_secs(10);
+        assert!(matches!(
+            requests.take_at(first, later),
+            Err(Error::RequestTimedOut(_))
+        ));
+        assert!(requests.is_empty());
+    }
+
+    #[test]
+    fn test_expire_and_drain() {
+        let mut requests = PendingRequests::new(Duration::from_secs(10));
+        let now = Instant::now();
+        let short = requests.insert_at(1, Duration::from_secs(1), now);
+        let long = requests.insert_at(2, Duration::from_secs(10), now);
+        let other = requests.insert_at(3, Duration::from_secs(10), now);
+
+        assert!(requests.expire_at(now).is_empty());
+        assert_eq!(
+            requests.expire_at(now + Duration::from_secs(1)),
+            vec![(short, 1)]
+        );
+        assert_eq!(requests.drain(), vec![(long, 2), (other, 3)]);
+        assert!(requests.is_empty());
+        // Ids are not reused after a drain
+        assert!(requests.insert(4) > other);
+    }
+}
@@ -0,0 +1,207 @@
+//! Maps the errors of this crate to the error messages sent on the wire.
+//!
+//! The same [`Error`] can be answered with different messages depending on the request that
+//! caused it: [`IntoProtocolError`] has a constructor for each of them so that every role uses the
+//! same error code for the same failure. Errors that have no specific code in a context are sent
+//! with [`INTERNAL_ERROR`].
+use crate::Error;
+use common_messages_sv2::SetupConnectionError;
+use job_declaration_sv2::DeclareMiningJobError;
+use mining_sv2::{OpenMiningChannelError, SubmitSharesError};
+use std::convert::TryInto;
+
+/// Sent when no error code of the protocol describes the failure
+pub const INTERNAL_ERROR: &str = "internal-error";
+pub const MAX_TARGET_OUT_OF_RANGE: &str = "max-target-out-of-range";
+pub const EXTRANONCE_SPACE_EXHAUSTED: &str = "extranonce-space-exhausted";
+pub const INVALID_EXTRANONCE_SIZE: &str = "invalid-extranonce-size";
+pub const UNSUPPORTED_CHANNEL_TYPE: &str = "unsupported-channel-type";
+pub const INVALID_MINING_JOB_TOKEN: &str = "invalid-mining-job-token";
+pub const INVALID_JOB_PARAM_VALUE_VERSION: &str = "invalid-job-param-value-version";
+pub const INVALID_JOB_PARAM_VALUE_COINBASE_PREFIX: &str = "invalid-job-param-value-coinbase_prefix";
+pub const INVALID_JOB_PARAM_VALUE_COINBASE_SUFFIX: &str = "invalid-job-param-value-coinbase_suffix";
+pub const INVALID_JOB_PARAM_VALUE_TX_SHORT_HASH_LIST: &str =
+    "invalid-job-param-value-tx_short_hash_list";
+
+pub trait IntoProtocolError {
+    /// Error code sent in an `OpenMiningChannelError`
+    fn open_mining_channel_error_code(&self) -> &'static str;
+    /// Error code sent in a `SubmitSharesError`
+    fn submit_shares_error_code(&self) -> &'static str;
+    /// Error code sent in a `SetupConnectionError`
+    fn setup_connection_error_code(&self) -> &'static str;
+    /// Error code sent in a `DeclareMiningJobError`
+    fn declare_mining_job_error_code(&self) -> &'static str;
+
+    fn open_mining_channel_error(&self, request_id: u32) -> OpenMiningChannelError<'static> {
+        OpenMiningChannelError {
+            request_id,
+            error_code: str0255(self.open_mining_channel_error_code()),
+        }
+    }
+
+    fn submit_shares_error(
+        &self,
+        channel_id: u32,
+        sequence_number: u32,
+    ) -> SubmitSharesError<'static> {
+        SubmitSharesError {
+            channel_id,
+            sequence_number,
+            error_code: str0255(self.submit_shares_error_code()),
+        }
+    }
+
+    fn setup_connection_error(&self, flags: u32) -> SetupConnectionError<'static> {
+        SetupConnectionError::new(flags, self.setup_connection_error_code())
+    }
+
+    /// The details are left empty, use [`IntoProtocolError::declare_mining_job_error_with_details`]
+    /// to send the description of the error
+    fn declare_mining_job_error(&self, request_id: u32) -> DeclareMiningJobError<'static> {
+        self.declare_mining_job_error_with_details(request_id, Vec::new())
+    }
+
+    /// `details` are truncated to the max length of `error_details`
+    fn declare_mining_job_error_with_details(
+        &self,
+        request_id: u32,
+        mut details: Vec<u8>,
+    ) -> DeclareMiningJobError<'static> {
+        details.truncate(u16::MAX as usize);
+        DeclareMiningJobError {
+            request_id,
+            error_code: str0255(self.declare_mining_job_error_code()),
+            // Infallible unwrap the details are truncated above
+            error_details: details.try_into().unwrap(),
+        }
+    }
+}
+
+impl IntoProtocolError for Error {
+    fn open_mining_channel_error_code(&self) -> &'static str {
+        match self {
+            Error::TargetError(_) | Error::HashrateError(_) | Error::DifficultyError(_) => {
+                MAX_TARGET_OUT_OF_RANGE
+            }
+            Error::NoMoreExtranonces | Error::ExtranonceSpaceEnded => EXTRANONCE_SPACE_EXHAUSTED,
+            Error::InvalidExtranonceSize(_, _) => INVALID_EXTRANONCE_SIZE,
+            Error::MessageNotAllowedByFlags(_) => UNSUPPORTED_CHANNEL_TYPE,
+            _ => INTERNAL_ERROR,
+        }
+    }
+
+    fn submit_shares_error_code(&self) -> &'static str {
+        match self {
+            Error::ShareDoNotMatchAnyChannel
+            | Error::NotFoundChannelId
+            | Error::MessageNotAllowedByFlags(_) => SubmitSharesError::invalid_channel_error_code(),
+            Error::ShareDoNotMatchAnyJob | Error::NoValidJob | Error::JobNotUpdated(_, _) => {
+                SubmitSharesError::invalid_job_id_error_code()
+            }
+            Error::TargetError(_) | Error::DifficultyError(_) => {
+                SubmitSharesError::difficulty_too_low_error_code()
+            }
+            Error::InvalidExtranonceSize(_, _) => INVALID_EXTRANONCE_SIZE,
+            _ => INTERNAL_ERROR,
+        }
+    }
+
+    fn setup_connection_error_code(&self) -> &'static str {
+        match self {
+            Error::NoPairableUpstream(_) => {
+                SetupConnectionError::protocol_version_mismatch_error_code()
+            }
+            Error::NoCompatibleUpstream(_) | Error::MessageNotAllowedByFlags(_) => {
+                SetupConnectionError::unsupported_feature_flags_error_code()
+            }
+            Error::UnimplementedProtocol => SetupConnectionError::unsupported_protocol_error_code(),
+            _ => INTERNAL_ERROR,
+        }
+    }
+
+    fn declare_mining_job_error_code(&self) -> &'static str {
+        match self {
+            Error::InvalidJobToken(_)
+            | Error::ExpiredJobToken(_)
+            | Error::UsedJobToken(_)
+            | Error::InvalidJobTokenLen(_)
+            | Error::JobTokenRateLimited(_) => INVALID_MINING_JOB_TOKEN,
+            Error::VersionTooBig => INVALID_JOB_PARAM_VALUE_VERSION,
+            Error::InvalidBip34Bytes(_) | Error::TxVersionTooBig | Error::TxVersionTooLow => {
+                INVALID_JOB_PARAM_VALUE_COINBASE_PREFIX
+            }
+            Error::InvalidCoinbase
+            | Error::ValueRemainingNotUpdated
+            | Error::UnknownOutputScriptType
+            | Error::InvalidOutputScript
+            | Error::EmptyCoinbaseOutputs
+            | Error::CoinbaseOutputsTooBig(_, _) => INVALID_JOB_PARAM_VALUE_COINBASE_SUFFIX,
+            Error::ShortTxIdCollision(_) => INVALID_JOB_PARAM_VALUE_TX_SHORT_HASH_LIST,
+            _ => INTERNAL_ERROR,
+        }
+    }
+}
+
+// Infallible unwrap all the error codes are shorter than 255 bytes
+fn str0255(error_code: &str) -> binary_sv2::Str0255<'static> {
+    error_code.to_string().into_bytes().try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_by_context() {
+        let error = Error::NotFoundChannelId;
+        let submit_error = error.submit_shares_error(1, 2);
+        assert_eq!(submit_error.channel_id, 1);
+        assert_eq!(submit_error.sequence_number, 2);
+        assert_eq!(
+            submit_error.error_code.as_ref(),
+            SubmitSharesError::invalid_channel_error_code().as_bytes()
+        );
+        // Same error, no specific code when opening a channel
+        assert_eq!(
+            error.open_mining_channel_error(3).error_code.as_ref(),
+            INTERNAL_ERROR.as_bytes()
+        );
+
+        let error = Error::MessageNotAllowedByFlags(0x13);
+        assert_eq!(
+            error.open_mining_channel_error(3).error_code.as_ref(),
+            UNSUPPORTED_CHANNEL_TYPE.as_bytes()
+        );
+
+        let error = Error::InvalidExtranonceSize(8, 4);
+        assert_eq!(
+            error.submit_shares_error(1, 2).error_code.as_ref(),
+            INVALID_EXTRANONCE_SIZE.as_bytes()
+        );
+        assert_eq!(
+            error.open_mining_channel_error(3).error_code.as_ref(),
+            INVALID_EXTRANONCE_SIZE.as_bytes()
+        );
+
+        let error = Error::ExpiredJobToken(7);
+        let declare_error =
+            error.declare_mining_job_error_with_details(4, error.to_string().into_bytes());
+        assert_eq!(declare_error.request_id, 4);
+        assert_eq!(
+            declare_error.error_code.as_ref(),
+            INVALID_MINING_JOB_TOKEN.as_bytes()
+        );
+        assert_eq!(
+            declare_error.error_details.as_ref(),
+            error.to_string().as_bytes()
+        );
+
+        let setup_error = Error::NoPairableUpstream((2, 2, 0)).setup_connection_error(1);
+        assert_eq!(setup_error.flags, 1);
+        assert_eq!(
+            setup_error.error_code.as_ref(),
+            SetupConnectionError::protocol_version_mismatch_error_code().as_bytes()
+        );
+    }
+}
@@ -243,12 +243,14 @@ impl<
             .ok_or(Error::NoCompatibleUpstream(*downstream_mining_data))?;
         // If we are here a list of possible upstreams has been already selected
         // TODO the upstream selection logic should be specified by the caller
-        let upstream =
-            Self::select_upstreams(&mut upstreams.to_vec()).ok_or(Error::NoUpstreamsConnected)?;
+        let upstream = self
+            .select_upstreams(&mut upstreams.to_vec())
+            .ok_or(Error::NoUpstreamsConnected)?;
         let old_id = request.get_request_id_as_u32();
         let new_req_id = upstream
-            .safe_lock(|u| u.get_mapper().unwrap().on_open_channel(old_id))
-            .map_err(|e| Error::PoisonLock(e.to_string()))?;
+            .safe_lock(|u| u.get_mapper().map(|mapper| mapper.on_open_channel(old_id)))
+            .map_err(|e| Error::PoisonLock(e.to_string()))?
+            .ok_or(Error::RequestIdNotMapped(old_id))?;
         request.update_id(new_req_id);
         self.on_open_standard_channel_request_header_only(downstream, request)
     }
@@ -267,29 +269,6 @@ pub struct MiningProxyRoutingLogic<
     //pub upstream_startegy: MiningUpstreamSelectionStrategy<Up,Down,Sel>,
 }
 
-fn minor_total_hr_upstream<Down, Up, Sel>(ups: &mut [Arc<Mutex<Up>>]) -> Arc<Mutex<Up>>
-where
-    Down: IsMiningDownstream + D,
-    Up: IsMiningUpstream<Down, Sel> + D,
-    Sel: DownstreamMiningSelector<Down> + D,
-{
-    ups.iter_mut()
-        .reduce(|acc, item| {
-            // Is fine to unwrap a safe_lock result
-            if acc.safe_lock(|x| x.total_hash_rate()).unwrap()
-                < item.safe_lock(|x| x.total_hash_rate()).unwrap()
-            {
-                acc
-            } else {
-                item
-            }
-        })
-        // Internal private function we only call thi function with non void vectors so is safe to
-        // unwrap here
-        .unwrap()
-        .clone()
-}
-
 fn filter_header_only<Down, Up, Sel>(ups: &mut [Arc<Mutex<Up>>]) -> Vec<Arc<Mutex<Up>>>
 where
     Down: IsMiningDownstream + D,
@@ -309,8 +288,11 @@ where
 
 /// If only one upstream is avaiable return it.
 /// Try to return an upstream that is not header only.
-/// Return the upstream that has less hash rate from downstreams.
-fn select_upstream<Down, Up, Sel>(ups: &mut [Arc<Mutex<Up>>]) -> Option<Arc<Mutex<Up>>>
+/// Return the upstream picked by the strategy of the selector (see [`GeneralMiningSelector`]).
+fn select_upstream<Down, Up, Sel>(
+    ups: &mut [Arc<Mutex<Up>>],
+    selector: &GeneralMiningSelector<Sel, Down, Up>,
+) -> Option<Arc<Mutex<Up>>>
 where
     Down: IsMiningDownstream + D,
     Up: IsMiningUpstream<Down, Sel> + D,
@@ -321,9 +303,9 @@ where
     } else if ups.len() == 1 {
         Some(ups[0].clone())
     } else if !filter_header_only(ups).is_empty() {
-        Some(minor_total_hr_upstream(&mut filter_header_only(ups)))
+        selector.select(&filter_header_only(ups))
     } else {
-        Some(minor_total_hr_upstream(ups))
+        selector.select(ups)
     }
 }
 
@@ -335,16 +317,16 @@ impl<
 {
     /// TODO this should stay in a enum UpstreamSelectionLogic that get passed from the caller to
     /// the several methods
-    fn select_upstreams(ups: &mut [Arc<Mutex<Up>>]) -> Option<Arc<Mutex<Up>>> {
-        select_upstream(ups)
+    fn select_upstreams(&self, ups: &mut [Arc<Mutex<Up>>]) -> Option<Arc<Mutex<Up>>> {
+        select_upstream(ups, &self.upstream_selector)
     }
 
     /// On setup connection the proxy finds all the upstreams that support the downstream
     /// connection, creates a downstream message parser that points to all the possible
     /// upstreams, and then responds with suppported flags.
     ///
-    /// The upstream with min total_hash_rate is selected (TODO a method to let the caller which
-    /// upstream select from the possible ones should be added
+    /// The upstream is picked by the strategy of the upstream selector (TODO a method to let the
+    /// caller which upstream select from the possible ones should be added
     /// on_setup_connection_mining_header_only_2 that return a Vec of possibe upstreams)
     ///
     /// This function returns a downstream id that the new created downstream must return via the
@@ -355,8 +337,9 @@ impl<
     ) -> Result<(CommonDownstreamData, SetupConnectionSuccess), Error> {
         let mut upstreams = self.upstream_selector.on_setup_connection(pair_settings)?;
         // TODO the upstream selection logic should be specified by the caller
-        let upstream =
-            Self::select_upstreams(&mut upstreams.0).ok_or(Error::NoUpstreamsConnected)?;
+        let upstream = self
+            .select_upstreams(&mut upstreams.0)
+            .ok_or(Error::NoUpstreamsConnected)?;
         let downstream_data = CommonDownstreamData {
             header_only: true,
             work_selection: false,
@@ -2,11 +2,12 @@
 //! a message should be ralyied, or to which remote or set of remotes a message should be sent.
 use crate::{
     common_properties::{IsDownstream, IsMiningDownstream, IsMiningUpstream, PairSettings},
+    upstream_selection::{LatencySamples, LeastLoaded, SelectionStrategy, UpstreamCandidate},
     utils::Mutex,
     Error,
 };
 use nohash_hasher::BuildNoHashHasher;
-use std::{collections::HashMap, fmt::Debug as D, sync::Arc};
+use std::{collections::HashMap, fmt::Debug as D, sync::Arc, time::Duration};
 
 /// A DownstreamMiningSelector useful for routing messages in a mining proxy
 #[derive(Debug, Clone, Default)]
@@ -24,6 +25,26 @@ impl<Down: IsDownstream> ProxyDownstreamMiningSelector<Down> {
             channel_id_to_downstream: HashMap::with_hasher(BuildNoHashHasher::default()),
         }
     }
+
+    /// Moves the downstreams of `channel_ids` to the group `group_channel_id`, as requested by a
+    /// `SetGroupChannel`. Channels that are not known are ignored.
+    pub fn set_group_channel(&mut self, group_channel_id: u32, channel_ids: &[u32]) {
+        for channel_id in channel_ids {
+            let d = match self.channel_id_to_downstream.get(channel_id) {
+                Some(d) => d.clone(),
+                None => continue,
+            };
+            for dws in self.channel_id_to_downstreams.values_mut() {
+                dws.retain(|x| !Arc::ptr_eq(x, &d));
+            }
+            self.channel_id_to_downstreams
+                .entry(group_channel_id)
+                .or_default()
+                .push(d);
+        }
+        self.channel_id_to_downstreams
+            .retain(|_, dws| !dws.is_empty());
+    }
     pub fn new_as_mutex() -> Arc<Mutex<Self>>
     where
         Self: Sized,
@@ -86,8 +107,10 @@ impl<Down: IsMiningDownstream> DownstreamMiningSelector<Down>
 
     fn remove_downstream(&mut self, d: &Arc<Mutex<Down>>) {
         for dws in self.channel_id_to_downstreams.values_mut() {
-            dws.retain(|d| !Arc::ptr_eq(d, d));
+            dws.retain(|x| !Arc::ptr_eq(x, d));
         }
+        self.channel_id_to_downstreams
+            .retain(|_, dws| !dws.is_empty());
 
         self._remove_downstream(d);
     }
@@ -96,7 +119,14 @@ impl<Down: IsMiningDownstream> DownstreamMiningSelector<Down>
         self.channel_id_to_downstream.get(&channel_id).cloned()
     }
     fn get_all_downstreams(&self) -> Vec<Arc<Mutex<Down>>> {
-        self.channel_id_to_downstream.values().cloned().collect()
+        // A downstream that opened more than one channel is returned once
+        let mut downstreams: Vec<Arc<Mutex<Down>>> = vec![];
+        for d in self.channel_id_to_downstream.values() {
+            if !downstreams.iter().any(|x| Arc::ptr_eq(x, d)) {
+                downstreams.push(d.clone());
+            }
+        }
+        downstreams
     }
 }
 
@@ -132,6 +162,47 @@ pub trait DownstreamMiningSelector<Downstream: IsMiningDownstream>:
     fn downstream_from_channel_id(&self, channel_id: u32) -> Option<Arc<Mutex<Downstream>>>;
 
     fn get_all_downstreams(&self) -> Vec<Arc<Mutex<Downstream>>>;
+
+    /// Removes the downstream that opened the standard channel `channel_id` and returns it
+    fn remove_downstream_by_channel_id(
+        &mut self,
+        channel_id: u32,
+    ) -> Option<Arc<Mutex<Downstream>>> {
+        let downstream = self.downstream_from_channel_id(channel_id)?;
+        self.remove_downstream(&downstream);
+        Some(downstream)
+    }
+
+    /// Removes every downstream for which `f` returns false
+    fn retain_downstreams<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&Arc<Mutex<Downstream>>) -> bool,
+        Self: Sized,
+    {
+        for d in self.get_all_downstreams() {
+            if !f(&d) {
+                self.remove_downstream(&d);
+            }
+        }
+    }
+
+    /// Number of downstreams
+    fn len(&self) -> usize {
+        self.get_all_downstreams().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calls `f` once for every downstream, e.g. to broadcast a message
+    fn for_each_downstream<F>(&self, f: F)
+    where
+        F: FnMut(&Arc<Mutex<Downstream>>),
+        Self: Sized,
+    {
+        self.get_all_downstreams().iter().for_each(f)
+    }
 }
 
 pub trait DownstreamSelector<D: IsDownstream> {}
@@ -211,6 +282,11 @@ pub trait UpstreamMiningSelctor<
 
 /// Upstream selector is used to chose between a set of known mining upstream nodes which one/ones
 /// can accept messages from a specific mining downstream node
+///
+/// The upstream is picked by a [`SelectionStrategy`], [`LeastLoaded`] if not set. Each upstream
+/// has a weight (1 if not set): with [`LeastLoaded`] the hash rate is split between the upstreams
+/// proportionally to their weights. Upstreams with weight 0 are only used when no other upstream
+/// is available.
 #[derive(Debug)]
 pub struct GeneralMiningSelector<
     Sel: DownstreamMiningSelector<Down>,
@@ -219,6 +295,9 @@ pub struct GeneralMiningSelector<
 > {
     pub upstreams: Vec<Arc<Mutex<Up>>>,
     pub id_to_upstream: HashMap<u32, Arc<Mutex<Up>>, BuildNoHashHasher<u32>>,
+    weights: HashMap<u32, f32, BuildNoHashHasher<u32>>,
+    latencies: HashMap<u32, LatencySamples, BuildNoHashHasher<u32>>,
+    strategy: Box<dyn SelectionStrategy>,
     sel: std::marker::PhantomData<Sel>,
     down: std::marker::PhantomData<Down>,
 }
@@ -232,12 +311,14 @@ impl<
     pub fn new(upstreams: Vec<Arc<Mutex<Up>>>) -> Self {
         let mut id_to_upstream = HashMap::with_hasher(BuildNoHashHasher::default());
         for up in &upstreams {
-            // Is ok to unwrap safe_lock result
-            id_to_upstream.insert(up.safe_lock(|u| u.get_id()).unwrap(), up.clone());
+            id_to_upstream.insert(up.super_safe_lock(|u| u.get_id()), up.clone());
         }
         Self {
             upstreams,
             id_to_upstream,
+            weights: HashMap::with_hasher(BuildNoHashHasher::default()),
+            latencies: HashMap::with_hasher(BuildNoHashHasher::default()),
+            strategy: Box::new(LeastLoaded),
             sel: std::marker::PhantomData,
             down: std::marker::PhantomData,
         }
@@ -245,6 +326,73 @@ impl<
     pub fn update_upstreams(&mut self, upstreams: Vec<Arc<Mutex<Up>>>) {
         self.upstreams = upstreams;
     }
+
+    pub fn set_weight(&mut self, upstream_id: u32, weight: f32) {
+        self.weights.insert(upstream_id, weight);
+    }
+
+    pub fn get_weight(&self, upstream_id: u32) -> f32 {
+        self.weights.get(&upstream_id).copied().unwrap_or(1.0)
+    }
+
+    pub fn set_strategy(&mut self, strategy: Box<dyn SelectionStrategy>) {
+        self.strategy = strategy;
+    }
+
+    /// Records a ping sample of the upstream `upstream_id`, see [`GeneralMiningSelector::latency`]
+    pub fn record_latency(&mut self, upstream_id: u32, sample: Duration) {
+        self.latencies
+            .entry(upstream_id)
+            .or_default()
+            .record(sample);
+    }
+
+    /// Average of the last ping samples of the upstream `upstream_id`
+    pub fn latency(&self, upstream_id: u32) -> Option<Duration> {
+        self.latencies.get(&upstream_id)?.average()
+    }
+
+    /// Stops selecting the upstream `upstream_id` and returns it. The upstream can still be found
+    /// with `get_upstream`, so that it can be added back with `update_upstreams` once available.
+    pub fn remove_upstream(&mut self, upstream_id: u32) -> Option<Arc<Mutex<Up>>> {
+        let index = self
+            .upstreams
+            .iter()
+            .position(|up| up.super_safe_lock(|u| u.get_id()) == upstream_id)?;
+        Some(self.upstreams.remove(index))
+    }
+
+    /// Stops selecting every upstream for which `f` returns false
+    pub fn retain_upstreams<F: FnMut(&Arc<Mutex<Up>>) -> bool>(&mut self, f: F) {
+        self.upstreams.retain(f);
+    }
+
+    /// Number of upstreams that can be selected
+    pub fn len(&self) -> usize {
+        self.upstreams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.upstreams.is_empty()
+    }
+
+    /// Returns the upstream in `ups` picked by the strategy of the selector
+    pub fn select(&self, ups: &[Arc<Mutex<Up>>]) -> Option<Arc<Mutex<Up>>> {
+        let candidates: Vec<UpstreamCandidate> = ups
+            .iter()
+            // Is ok to unwrap safe_lock result
+            .map(|up| up.safe_lock(|u| (u.get_id(), u.total_hash_rate())).unwrap())
+            .map(|(id, total_hash_rate)| UpstreamCandidate {
+                id,
+                weight: self.get_weight(id),
+                total_hash_rate,
+                latency: self.latency(id),
+            })
+            .collect();
+        self.strategy
+            .select(&candidates)
+            .map(|index| ups[index].clone())
+    }
 }
 impl<
         Sel: DownstreamMiningSelector<Down>,
@@ -270,13 +418,9 @@ impl<
         let mut supported_upstreams = vec![];
         let mut supported_flags: u32 = 0;
         for node in &self.upstreams {
-            let is_pairable = node
-                .safe_lock(|node| node.is_pairable(pair_settings))
-                // Is ok to unwrap safe_lock result
-                .unwrap();
+            let is_pairable = node.super_safe_lock(|node| node.is_pairable(pair_settings));
             if is_pairable {
-                // Is ok to unwrap safe_lock result
-                supported_flags |= node.safe_lock(|n| n.get_flags()).unwrap();
+                supported_flags |= node.super_safe_lock(|n| n.get_flags());
                 supported_upstreams.push(node.clone());
             }
         }
@@ -291,3 +435,190 @@ impl<
         self.id_to_upstream.get(&upstream_id).cloned()
     }
 }
+
+/// A selector shared between the tasks of a proxy. Every method locks the selector only for the
+/// time of the call, the downstreams and the upstreams are never locked while the selector is.
+#[derive(Debug)]
+pub struct SharedSelector<S>(Arc<Mutex<S>>);
+
+impl<S> Clone for SharedSelector<S> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<S> SharedSelector<S> {
+    pub fn new(selector: S) -> Self {
+        Self(Arc::new(Mutex::new(selector)))
+    }
+
+    pub fn from_mutex(selector: Arc<Mutex<S>>) -> Self {
+        Self(selector)
+    }
+
+    pub fn as_mutex(&self) -> &Arc<Mutex<S>> {
+        &self.0
+    }
+
+    /// Calls `f` with the selector locked
+    pub fn with<F: FnOnce(&mut S) -> Ret, Ret>(&self, f: F) -> Ret {
+        self.0.super_safe_lock(f)
+    }
+}
+
+impl<Down: IsMiningDownstream> SharedSelector<ProxyDownstreamMiningSelector<Down>> {
+    pub fn remove_downstream(&self, d: &Arc<Mutex<Down>>) {
+        self.with(|s| s.remove_downstream(d))
+    }
+
+    pub fn remove_downstream_by_channel_id(&self, channel_id: u32) -> Option<Arc<Mutex<Down>>> {
+        self.with(|s| s.remove_downstream_by_channel_id(channel_id))
+    }
+
+    /// Removes every downstream for which `f` returns false, `f` is called without the selector
+    /// locked
+    pub fn retain_downstreams<F: FnMut(&Arc<Mutex<Down>>) -> bool>(&self, mut f: F) {
+        let removed: Vec<Arc<Mutex<Down>>> = self
+            .with(|s| s.get_all_downstreams())
+            .into_iter()
+            .filter(|d| !f(d))
+            .collect();
+        self.with(|s| {
+            for d in &removed {
+                s.remove_downstream(d);
+            }
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.with(|s| s.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calls `f` once for every downstream, `f` is called without the selector locked so that it
+    /// can lock the downstream or remove it from the selector
+    pub fn for_each_downstream<F: FnMut(&Arc<Mutex<Down>>)>(&self, f: F) {
+        self.with(|s| s.get_all_downstreams()).iter().for_each(f)
+    }
+}
+
+impl<
+        Sel: DownstreamMiningSelector<Down>,
+        Down: IsMiningDownstream,
+        Up: IsMiningUpstream<Down, Sel>,
+    > SharedSelector<GeneralMiningSelector<Sel, Down, Up>>
+{
+    pub fn remove_upstream(&self, upstream_id: u32) -> Option<Arc<Mutex<Up>>> {
+        self.with(|s| s.remove_upstream(upstream_id))
+    }
+
+    pub fn retain_upstreams<F: FnMut(&Arc<Mutex<Up>>) -> bool>(&self, mut f: F) {
+        let upstreams = self.with(|s| s.upstreams.clone());
+        let removed: Vec<Arc<Mutex<Up>>> = upstreams.into_iter().filter(|u| !f(u)).collect();
+        self.with(|s| {
+            s.retain_upstreams(|u| !removed.iter().any(|r| Arc::ptr_eq(r, u)));
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.with(|s| s.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calls `f` once for every upstream that can be selected, without the selector locked
+    pub fn for_each_upstream<F: FnMut(&Arc<Mutex<Up>>)>(&self, f: F) {
+        self.with(|s| s.upstreams.clone()).iter().for_each(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_channel(
+        selector: &mut ProxyDownstreamMiningSelector<()>,
+        request_id: u32,
+        group_channel_id: u32,
+        channel_id: u32,
+    ) -> Arc<Mutex<()>> {
+        let downstream = Arc::new(Mutex::new(()));
+        selector.on_open_standard_channel_request(request_id, downstream.clone());
+        selector
+            .on_open_standard_channel_success(request_id, group_channel_id, channel_id)
+            .unwrap();
+        downstream
+    }
+
+    #[test]
+    fn test_remove_downstream() {
+        let mut selector = ProxyDownstreamMiningSelector::new();
+        let d1 = open_channel(&mut selector, 1, 10, 1);
+        let d2 = open_channel(&mut selector, 2, 10, 2);
+        let d3 = open_channel(&mut selector, 3, 20, 3);
+        assert_eq!(selector.len(), 3);
+
+        selector.remove_downstream(&d1);
+        let in_group = selector.get_downstreams_in_channel(10).unwrap();
+        assert_eq!(in_group.len(), 1);
+        assert!(Arc::ptr_eq(&in_group[0], &d2));
+
+        let removed = selector.remove_downstream_by_channel_id(3).unwrap();
+        assert!(Arc::ptr_eq(&removed, &d3));
+        assert!(selector.get_downstreams_in_channel(20).is_none());
+        assert!(selector.remove_downstream_by_channel_id(3).is_none());
+        assert_eq!(selector.len(), 1);
+    }
+
+    #[test]
+    fn test_set_group_channel() {
+        let mut selector = ProxyDownstreamMiningSelector::new();
+        let d1 = open_channel(&mut selector, 1, 10, 1);
+        let d2 = open_channel(&mut selector, 2, 10, 2);
+        selector.set_group_channel(20, &[2, 7]);
+        let in_group = selector.get_downstreams_in_channel(10).unwrap();
+        assert_eq!(in_group.len(), 1);
+        assert!(Arc::ptr_eq(&in_group[0], &d1));
+        let in_group = selector.get_downstreams_in_channel(20).unwrap();
+        assert_eq!(in_group.len(), 1);
+        assert!(Arc::ptr_eq(&in_group[0], &d2));
+
+        selector.set_group_channel(20, &[1]);
+        assert!(selector.get_downstreams_in_channel(10).is_none());
+        assert_eq!(selector.get_downstreams_in_channel(20).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_retain_and_broadcast() {
+        let selector = SharedSelector::new(ProxyDownstreamMiningSelector::new());
+        let downstreams: Vec<Arc<Mutex<()>>> = selector.with(|s| {
+            (0..4)
+                .map(|id| open_channel(s, id, 100, id))
+                .collect::<Vec<_>>()
+        });
+        // A downstream with two channels is only visited once
+        selector.with(|s| {
+            s.on_open_standard_channel_request(4, downstreams[0].clone());
+            s.on_open_standard_channel_success(4, 100, 4).unwrap();
+        });
+        assert_eq!(selector.len(), 4);
+
+        let dead = [downstreams[1].clone(), downstreams[3].clone()];
+        selector.retain_downstreams(|d| !dead.iter().any(|x| Arc::ptr_eq(x, d)));
+        assert_eq!(selector.len(), 2);
+
+        let mut visited = 0;
+        selector.for_each_downstream(|d| {
+            // the selector is not locked while broadcasting
+            selector.remove_downstream(d);
+            visited += 1;
+        });
+        assert_eq!(visited, 2);
+        assert!(selector.is_empty());
+    }
+}
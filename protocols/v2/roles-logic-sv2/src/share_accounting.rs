@@ -0,0 +1,325 @@
+//! Bookkeeping of the `SubmitShares.Success` messages on both sides of a channel.
+//!
+//! A `SubmitShares.Success` can acknowledge many submits at once. Upstream, the
+//! [`SharesSuccessBatcher`] accumulates the accepted submits of each channel and emits a single
+//! success when enough of them have been accepted or when the oldest one waited too long.
+//! Downstream, the [`SequenceNumbers`] assigns the sequence numbers of the submits and checks that
+//! the upstream acknowledges them in order and never more than the ones sent.
+use crate::Error;
+use mining_sv2::{SubmitSharesError, SubmitSharesSuccess};
+use nohash_hasher::BuildNoHashHasher;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// Submits acknowledged together by default, 1 is a success for every submit
+pub const DEFAULT_MAX_SUBMITS_PER_SUCCESS: u32 = 1;
+/// Time after which the accepted submits are acknowledged even if the batch is not full
+pub const DEFAULT_MAX_SUCCESS_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+struct PendingSuccess {
+    last_sequence_number: u32,
+    new_submits_accepted_count: u32,
+    new_shares_sum: u64,
+    first_accepted_at: Instant,
+}
+
+impl PendingSuccess {
+    fn into_message(self, channel_id: u32) -> SubmitSharesSuccess {
+        SubmitSharesSuccess {
+            channel_id,
+            last_sequence_number: self.last_sequence_number,
+            new_submits_accepted_count: self.new_submits_accepted_count,
+            new_shares_sum: self.new_shares_sum,
+        }
+    }
+}
+
+/// Upstream side: accumulates the accepted submits of each channel in batched successes
+#[derive(Debug)]
+pub struct SharesSuccessBatcher {
+    pending: HashMap<u32, PendingSuccess, BuildNoHashHasher<u32>>,
+    max_submits: u32,
+    max_delay: Duration,
+}
+
+impl Default for SharesSuccessBatcher {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_SUBMITS_PER_SUCCESS, DEFAULT_MAX_SUCCESS_DELAY)
+    }
+}
+
+impl SharesSuccessBatcher {
+    /// A success is emitted every `max_submits` accepted submits of a channel, or by
+    /// [`SharesSuccessBatcher::flush_expired`] when the first one has been accepted more than
+    /// `max_delay` ago
+    pub fn new(max_submits: u32, max_delay: Duration) -> Self {
+        Self {
+            pending: HashMap::with_hasher(BuildNoHashHasher::default()),
+            max_submits: max_submits.max(1),
+            max_delay,
+        }
+    }
+
+    /// Records an accepted submit worth `shares` shares. Returns the success to send if the batch
+    /// of the channel is full.
+    pub fn on_share_accepted(
+        &mut self,
+        channel_id: u32,
+        sequence_number: u32,
+        shares: u64,
+    ) -> Option<SubmitSharesSuccess> {
+        self.on_share_accepted_at(channel_id, sequence_number, shares, Instant::now())
+    }
+
+    fn on_share_accepted_at(
+        &mut self,
+        channel_id: u32,
+        sequence_number: u32,
+        shares: u64,
+        now: Instant,
+    ) -> Option<SubmitSharesSuccess> {
+        let pending = self
+            .pending
+            .entry(channel_id)
+            .or_insert_with(|| PendingSuccess {
+                last_sequence_number: sequence_number,
+                new_submits_accepted_count: 0,
+                new_shares_sum: 0,
+                first_accepted_at: now,
+            });
+        pending.last_sequence_number = sequence_number;
+        pending.new_submits_accepted_count += 1;
+        pending.new_shares_sum = pending.new_shares_sum.saturating_add(shares);
+        if pending.new_submits_accepted_count >= self.max_submits {
+            self.flush_channel(channel_id)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the success of the submits of the channel not yet acknowledged, if any. To be
+    /// called before sending a `SubmitShares.Error`, so that the successes of the previous submits
+    /// are not sent after it.
+    pub fn flush_channel(&mut self, channel_id: u32) -> Option<SubmitSharesSuccess> {
+        self.pending
+            .remove(&channel_id)
+            .map(|pending| pending.into_message(channel_id))
+    }
+
+    /// Returns the successes of the channels whose first pending submit has been accepted more
+    /// than `max_delay` ago, to be called periodically
+    pub fn flush_expired(&mut self) -> Vec<SubmitSharesSuccess> {
+        self.flush_expired_at(Instant::now())
+    }
+
+    fn flush_expired_at(&mut self, now: Instant) -> Vec<SubmitSharesSuccess> {
+        let max_delay = self.max_delay;
+        let mut expired: Vec<u32> = self
+            .pending
+            .iter()
+            .filter(|(_, p)| now.saturating_duration_since(p.first_accepted_at) >= max_delay)
+            .map(|(channel_id, _)| *channel_id)
+            .collect();
+        expired.sort_unstable();
+        expired
+            .into_iter()
+            .filter_map(|channel_id| self.flush_channel(channel_id))
+            .collect()
+    }
+
+    /// Returns the successes of every channel, e.g. when the connection is closing
+    pub fn flush_all(&mut self) -> Vec<SubmitSharesSuccess> {
+        let mut channels: Vec<u32> = self.pending.keys().copied().collect();
+        channels.sort_unstable();
+        channels
+            .into_iter()
+            .filter_map(|channel_id| self.flush_channel(channel_id))
+            .collect()
+    }
+
+    /// Forgets the pending submits of a closed channel
+    pub fn remove_channel(&mut self, channel_id: u32) {
+        self.pending.remove(&channel_id);
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct ChannelSequence {
+    next: u32,
+    // sent and neither acknowledged nor refused, in the order they were sent
+    in_flight: VecDeque<u32>,
+    last_acknowledged: Option<u32>,
+}
+
+/// Downstream side: the sequence numbers of the submits sent on each channel and the ones still
+/// waiting for a response
+#[derive(Debug, Default)]
+pub struct SequenceNumbers {
+    channels: HashMap<u32, ChannelSequence, BuildNoHashHasher<u32>>,
+}
+
+impl SequenceNumbers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the sequence number of the next submit of the channel, that is then waiting for a
+    /// response
+    pub fn next_sequence_number(&mut self, channel_id: u32) -> u32 {
+        let channel = self.channels.entry(channel_id).or_default();
+        let sequence_number = channel.next;
+        channel.next = channel.next.wrapping_add(1);
+        channel.in_flight.push_back(sequence_number);
+        sequence_number
+    }
+
+    /// Checks a received success: `last_sequence_number` must be a submit waiting for a response,
+    /// sent after the one acknowledged by the previous success, and the success can not
+    /// acknowledge more submits than the ones sent up to it. Returns how many submits are no more
+    /// waiting for a response.
+    pub fn on_success(&mut self, m: &SubmitSharesSuccess) -> Result<u32, Error> {
+        let channel = self
+            .channels
+            .get_mut(&m.channel_id)
+            .ok_or(Error::NotFoundChannelId)?;
+        let last = m.last_sequence_number;
+        let index = match channel.in_flight.iter().position(|s| *s == last) {
+            Some(index) => index,
+            None => {
+                return match channel.last_acknowledged {
+                    // `last` is not after the last acknowledged submit, the wrapping distance is
+                    // used as the sequence numbers can overflow
+                    Some(acknowledged) if acknowledged.wrapping_sub(last) < u32::MAX / 2 => Err(
+                        Error::SequenceNumberNotMonotonic(m.channel_id, acknowledged, last),
+                    ),
+                    _ => Err(Error::UnknownSequenceNumber(m.channel_id, last)),
+                };
+            }
+        };
+        let acknowledged = index as u32 + 1;
+        if m.new_submits_accepted_count > acknowledged {
+            return Err(Error::TooManySubmitsAcknowledged(
+                m.channel_id,
+                m.new_submits_accepted_count,
+                acknowledged,
+            ));
+        }
+        channel.in_flight.drain(..=index);
+        channel.last_acknowledged = Some(last);
+        Ok(acknowledged)
+    }
+
+    /// A refused submit is no more waiting for a response
+    pub fn on_error(&mut self, m: &SubmitSharesError) -> Result<(), Error> {
+        let channel = self
+            .channels
+            .get_mut(&m.channel_id)
+            .ok_or(Error::NotFoundChannelId)?;
+        match channel
+            .in_flight
+            .iter()
+            .position(|s| *s == m.sequence_number)
+        {
+            Some(index) => {
+                channel.in_flight.remove(index);
+                Ok(())
+            }
+            None => Err(Error::UnknownSequenceNumber(
+                m.channel_id,
+                m.sequence_number,
+            )),
+        }
+    }
+
+    /// Submits of the channel waiting for a response
+    pub fn in_flight(&self, channel_id: u32) -> usize {
+        self.channels
+            .get(&channel_id)
+            .map(|c| c.in_flight.len())
+            .unwrap_or(0)
+    }
+
+    pub fn last_acknowledged(&self, channel_id: u32) -> Option<u32> {
+        self.channels
+            .get(&channel_id)
+            .and_then(|c| c.last_acknowledged)
+    }
+
+    pub fn remove_channel(&mut self, channel_id: u32) {
+        self.channels.remove(&channel_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binary_sv2::Str0255;
+    use std::convert::TryInto;
+
+    #[test]
+    fn test_batched_successes() {
+        let now = Instant::now();
+        let mut batcher = SharesSuccessBatcher::new(3, Duration::from_secs(1));
+        assert!(batcher.on_share_accepted_at(1, 0, 10, now).is_none());
+        assert!(batcher.on_share_accepted_at(2, 0, 5, now).is_none());
+        assert!(batcher.on_share_accepted_at(1, 1, 10, now).is_none());
+        let success = batcher.on_share_accepted_at(1, 3, 10, now).unwrap();
+        assert_eq!(success.channel_id, 1);
+        assert_eq!(success.last_sequence_number, 3);
+        assert_eq!(success.new_submits_accepted_count, 3);
+        assert_eq!(success.new_shares_sum, 30);
+
+        assert!(batcher.flush_expired_at(now).is_empty());
+        let expired = batcher.flush_expired_at(now + Duration::from_secs(1));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].channel_id, 2);
+        assert_eq!(expired[0].new_submits_accepted_count, 1);
+        assert!(batcher.flush_all().is_empty());
+
+        let mut batcher = SharesSuccessBatcher::default();
+        assert!(batcher.on_share_accepted(1, 0, 1).is_some());
+    }
+
+    #[test]
+    fn test_sequence_numbers() {
+        let mut sequence = SequenceNumbers::new();
+        for expected in 0..5 {
+            assert_eq!(sequence.next_sequence_number(1), expected);
+        }
+        let success = |last_sequence_number, new_submits_accepted_count| SubmitSharesSuccess {
+            channel_id: 1,
+            last_sequence_number,
+            new_submits_accepted_count,
+            new_shares_sum: 0,
+        };
+        let error_code: Str0255 = "stale-share".to_string().try_into().unwrap();
+        sequence
+            .on_error(&SubmitSharesError {
+                channel_id: 1,
+                sequence_number: 1,
+                error_code,
+            })
+            .unwrap();
+        // 0 and 2 are acknowledged, 1 has been refused
+        assert_eq!(sequence.on_success(&success(2, 2)).unwrap(), 2);
+        assert_eq!(sequence.in_flight(1), 2);
+        assert!(matches!(
+            sequence.on_success(&success(1, 1)),
+            Err(Error::SequenceNumberNotMonotonic(1, 2, 1))
+        ));
+        assert!(matches!(
+            sequence.on_success(&success(4, 3)),
+            Err(Error::TooManySubmitsAcknowledged(1, 3, 2))
+        ));
+        assert!(matches!(
+            sequence.on_success(&success(7, 1)),
+            Err(Error::UnknownSequenceNumber(1, 7))
+        ));
+        assert_eq!(sequence.on_success(&success(4, 2)).unwrap(), 2);
+        assert_eq!(sequence.last_acknowledged(1), Some(4));
+        assert_eq!(sequence.in_flight(1), 0);
+    }
+}
@@ -0,0 +1,177 @@
+//! Checks of the fields of a share that do not depend on its hash.
+//!
+//! A miner can roll the `ntime` of a job while it mines it. [`NtimeLimits`] accepts an `ntime`
+//! that is not lower than the `min_ntime` of the job, not higher than `min_ntime` plus the time
+//! elapsed since the job has been received plus `max_ntime_drift`, and not further than
+//! `future_tolerance` ahead of the local clock.
+use std::{
+    fmt::{self, Display, Formatter},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Error code of the `SubmitShares.Error` sent for a share with an `ntime` out of limits
+pub const INVALID_NTIME: &str = "invalid-ntime";
+/// Error code of the `SubmitShares.Error` sent for an extended share whose extranonce is not as
+/// long as the `extranonce_size` of its channel
+pub const INVALID_EXTRANONCE_SIZE: &str = "invalid-extranonce-size";
+/// Error code of the `SubmitShares.Error` sent for a share that rolled version bits that its job
+/// does not allow to roll, see [`mining_sv2::version_rolling`]
+pub const INVALID_VERSION: &str = "invalid-version";
+/// Seconds the `ntime` of a share can be ahead of the time elapsed since its job was received
+pub const DEFAULT_MAX_NTIME_DRIFT: u32 = 60;
+/// Seconds the `ntime` of a share can be ahead of the local clock, bitcoin nodes do not accept
+/// blocks more than 2 hours in the future
+pub const DEFAULT_NTIME_FUTURE_TOLERANCE: u32 = 7200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NtimeError {
+    /// `ntime` is lower than the `min_ntime` of the job
+    BelowMinNtime { ntime: u32, min_ntime: u32 },
+    /// `ntime` is rolled further than the time elapsed since the job was received allows
+    TooFarAhead { ntime: u32, max_ntime: u32 },
+    /// `ntime` is too far ahead of the local clock
+    InTheFuture { ntime: u32, now: u32 },
+}
+
+impl Display for NtimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            NtimeError::BelowMinNtime { ntime, min_ntime } => {
+                write!(f, "ntime {} is lower than min_ntime {}", ntime, min_ntime)
+            }
+            NtimeError::TooFarAhead { ntime, max_ntime } => {
+                write!(
+                    f,
+                    "ntime {} is higher than the max ntime {}",
+                    ntime, max_ntime
+                )
+            }
+            NtimeError::InTheFuture { ntime, now } => write!(
+                f,
+                "ntime {} is too far ahead of the local time {}",
+                ntime, now
+            ),
+        }
+    }
+}
+
+/// Limits of the `ntime` of the shares, in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NtimeLimits {
+    pub max_ntime_drift: u32,
+    pub future_tolerance: u32,
+}
+
+impl Default for NtimeLimits {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_NTIME_DRIFT, DEFAULT_NTIME_FUTURE_TOLERANCE)
+    }
+}
+
+impl NtimeLimits {
+    pub fn new(max_ntime_drift: u32, future_tolerance: u32) -> Self {
+        Self {
+            max_ntime_drift,
+            future_tolerance,
+        }
+    }
+
+    /// Checks the `ntime` of a share against the `min_ntime` of its job, received `elapsed` ago
+    pub fn check(&self, ntime: u32, min_ntime: u32, elapsed: Duration) -> Result<(), NtimeError> {
+        self.check_at(ntime, min_ntime, elapsed, unix_time())
+    }
+
+    /// Same as [`NtimeLimits::check`] with `now` as local time
+    pub fn check_at(
+        &self,
+        ntime: u32,
+        min_ntime: u32,
+        elapsed: Duration,
+        now: u32,
+    ) -> Result<(), NtimeError> {
+        if ntime < min_ntime {
+            return Err(NtimeError::BelowMinNtime { ntime, min_ntime });
+        }
+        let elapsed = elapsed.as_secs().min(u32::MAX as u64) as u32;
+        let max_ntime = min_ntime
+            .saturating_add(elapsed)
+            .saturating_add(self.max_ntime_drift);
+        if ntime > max_ntime {
+            return Err(NtimeError::TooFarAhead { ntime, max_ntime });
+        }
+        if ntime > now.saturating_add(self.future_tolerance) {
+            return Err(NtimeError::InTheFuture { ntime, now });
+        }
+        Ok(())
+    }
+}
+
+/// Seconds since the unix epoch, 0 if the clock is before it
+fn unix_time() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN_NTIME: u32 = 1_700_000_000;
+
+    #[test]
+    fn test_ntime_lower_bound() {
+        let limits = NtimeLimits::new(0, 0);
+        let now = MIN_NTIME + 1000;
+        assert!(limits
+            .check_at(MIN_NTIME, MIN_NTIME, Duration::ZERO, now)
+            .is_ok());
+        assert_eq!(
+            limits.check_at(MIN_NTIME - 1, MIN_NTIME, Duration::ZERO, now),
+            Err(NtimeError::BelowMinNtime {
+                ntime: MIN_NTIME - 1,
+                min_ntime: MIN_NTIME
+            })
+        );
+    }
+
+    #[test]
+    fn test_ntime_drift() {
+        let limits = NtimeLimits::new(60, 7200);
+        let now = MIN_NTIME;
+        // 30 seconds since the job was received, ntime can be rolled up to 30 + 60 seconds
+        let elapsed = Duration::from_millis(30_900);
+        assert!(limits
+            .check_at(MIN_NTIME + 90, MIN_NTIME, elapsed, now)
+            .is_ok());
+        assert_eq!(
+            limits.check_at(MIN_NTIME + 91, MIN_NTIME, elapsed, now),
+            Err(NtimeError::TooFarAhead {
+                ntime: MIN_NTIME + 91,
+                max_ntime: MIN_NTIME + 90
+            })
+        );
+        // Does not overflow
+        let limits = NtimeLimits::new(u32::MAX, u32::MAX);
+        assert!(limits
+            .check_at(u32::MAX, MIN_NTIME, Duration::MAX, now)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_ntime_future_tolerance() {
+        let limits = NtimeLimits::new(u32::MAX, 10);
+        let now = MIN_NTIME + 100;
+        assert!(limits
+            .check_at(now + 10, MIN_NTIME, Duration::ZERO, now)
+            .is_ok());
+        assert_eq!(
+            limits.check_at(now + 11, MIN_NTIME, Duration::ZERO, now),
+            Err(NtimeError::InTheFuture {
+                ntime: now + 11,
+                now
+            })
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Cache for the templates received from a Template Provider and for their transaction data.
+//!
+//! The store pairs every `RequestTransactionData` with the `RequestTransactionData.Success` or
+//! `RequestTransactionData.Error` that answers it and evicts the templates that are not valid
+//! anymore when a `SetNewPrevHash` is received.
+use crate::Error;
+use nohash_hasher::BuildNoHashHasher;
+use std::collections::{HashMap, HashSet};
+use template_distribution_sv2::{
+    NewTemplate, RequestTransactionData, RequestTransactionDataError,
+    RequestTransactionDataSuccess, SetNewPrevHash,
+};
+use tracing::{debug, warn};
+
+/// Used by roles that receive templates (pool, jd-client) to keep the templates and their
+/// transaction data around until they are not valid anymore.
+#[derive(Debug, Default)]
+pub struct TemplateStore {
+    templates: HashMap<u64, NewTemplate<'static>, BuildNoHashHasher<u64>>,
+    transaction_data: HashMap<u64, RequestTransactionDataSuccess<'static>, BuildNoHashHasher<u64>>,
+    pending_requests: HashSet<u64, BuildNoHashHasher<u64>>,
+    // template id activated by the last received SetNewPrevHash
+    active_template: Option<u64>,
+}
+
+impl TemplateStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called when a `NewTemplate` is received
+    pub fn on_new_template(&mut self, m: NewTemplate<'static>) {
+        debug!("Caching template {}", m.template_id);
+        if !m.future_template {
+            self.active_template = Some(m.template_id);
+        }
+        self.templates.insert(m.template_id, m);
+    }
+
+    /// Called when a `SetNewPrevHash` is received. Every template but the one activated by the
+    /// message has been built on top of an old tip so it is evicted together with its transaction
+    /// data and with the pending requests for it.
+    pub fn on_set_new_prev_hash(&mut self, m: &SetNewPrevHash) {
+        let template_id = m.template_id;
+        self.templates.retain(|id, _| *id == template_id);
+        self.transaction_data.retain(|id, _| *id == template_id);
+        self.pending_requests.retain(|id| *id == template_id);
+        if let Some(template) = self.templates.get_mut(&template_id) {
+            template.future_template = false;
+        } else {
+            warn!(
+                "SetNewPrevHash received for template {} that is not in the store",
+                template_id
+            );
+        }
+        self.active_template = Some(template_id);
+    }
+
+    /// Returns the `RequestTransactionData` to be sent to the Template Provider or `None` if the
+    /// data is already cached or a request is already pending.
+    pub fn request_transaction_data(
+        &mut self,
+        template_id: u64,
+    ) -> Result<Option<RequestTransactionData>, Error> {
+        if !self.templates.contains_key(&template_id) {
+            return Err(Error::NoValidTemplate(template_id.to_string()));
+        }
+        if self.transaction_data.contains_key(&template_id)
+            || !self.pending_requests.insert(template_id)
+        {
+            return Ok(None);
+        }
+        Ok(Some(RequestTransactionData { template_id }))
+    }
+
+    /// Called when a `RequestTransactionData.Success` is received
+    pub fn on_request_transaction_data_success(
+        &mut self,
+        m: RequestTransactionDataSuccess<'static>,
+    ) -> Result<(), Error> {
+        if !self.pending_requests.remove(&m.template_id) {
+            return Err(Error::TransactionDataNotRequested(m.template_id));
+        }
+        self.transaction_data.insert(m.template_id, m);
+        Ok(())
+    }
+
+    /// Called when a `RequestTransactionData.Error` is received. The template is not valid
+    /// anymore for the Template Provider so it is evicted.
+    pub fn on_request_transaction_data_error(
+        &mut self,
+        m: &RequestTransactionDataError,
+    ) -> Result<(), Error> {
+        if !self.pending_requests.remove(&m.template_id) {
+            return Err(Error::TransactionDataNotRequested(m.template_id));
+        }
+        self.templates.remove(&m.template_id);
+        Ok(())
+    }
+
+    pub fn get_template(&self, template_id: u64) -> Option<&NewTemplate<'static>> {
+        self.templates.get(&template_id)
+    }
+
+    /// Returns the template activated by the last `SetNewPrevHash` (or the last non future
+    /// template received)
+    pub fn active_template(&self) -> Option<&NewTemplate<'static>> {
+        self.templates.get(&self.active_template?)
+    }
+
+    pub fn get_transaction_data(
+        &self,
+        template_id: u64,
+    ) -> Option<&RequestTransactionDataSuccess<'static>> {
+        self.transaction_data.get(&template_id)
+    }
+
+    pub fn is_request_pending(&self, template_id: u64) -> bool {
+        self.pending_requests.contains(&template_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binary_sv2::{Seq0255, Seq064K};
+    use std::convert::TryInto;
+
+    fn template(template_id: u64, future_template: bool) -> NewTemplate<'static> {
+        NewTemplate {
+            template_id,
+            future_template,
+            version: 0x2000_0000,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![3, 1, 0, 0].try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: 5_000_000_000,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+        }
+    }
+
+    fn transaction_data(template_id: u64) -> RequestTransactionDataSuccess<'static> {
+        RequestTransactionDataSuccess {
+            template_id,
+            excess_data: vec![].try_into().unwrap(),
+            transaction_list: Seq064K::new(vec![vec![1, 2, 3].try_into().unwrap()]).unwrap(),
+        }
+    }
+
+    fn prev_hash(template_id: u64) -> SetNewPrevHash<'static> {
+        SetNewPrevHash {
+            template_id,
+            prev_hash: [0; 32].into(),
+            header_timestamp: 0,
+            n_bits: 0,
+            target: [0; 32].into(),
+        }
+    }
+
+    #[test]
+    fn test_request_transaction_data_pairing() {
+        let mut store = TemplateStore::new();
+        assert!(store.request_transaction_data(1).is_err());
+
+        store.on_new_template(template(1, false));
+        assert_eq!(
+            store.request_transaction_data(1).unwrap(),
+            Some(RequestTransactionData { template_id: 1 })
+        );
+        assert!(store.is_request_pending(1));
+        // Do not request twice
+        assert_eq!(store.request_transaction_data(1).unwrap(), None);
+
+        assert!(store
+            .on_request_transaction_data_success(transaction_data(2))
+            .is_err());
+        store
+            .on_request_transaction_data_success(transaction_data(1))
+            .unwrap();
+        assert!(!store.is_request_pending(1));
+        assert!(store.get_transaction_data(1).is_some());
+        // Already cached
+        assert_eq!(store.request_transaction_data(1).unwrap(), None);
+    }
+
+    #[test]
+    fn test_request_transaction_data_error_evicts_template() {
+        let mut store = TemplateStore::new();
+        store.on_new_template(template(1, false));
+        store.request_transaction_data(1).unwrap();
+        let error = RequestTransactionDataError {
+            template_id: 1,
+            error_code: "stale-template-id".to_string().try_into().unwrap(),
+        };
+        store.on_request_transaction_data_error(&error).unwrap();
+        assert!(store.get_template(1).is_none());
+        assert!(store.on_request_transaction_data_error(&error).is_err());
+    }
+
+    #[test]
+    fn test_set_new_prev_hash_evicts_stale_templates() {
+        let mut store = TemplateStore::new();
+        store.on_new_template(template(1, false));
+        store.request_transaction_data(1).unwrap();
+        store
+            .on_request_transaction_data_success(transaction_data(1))
+            .unwrap();
+        store.on_new_template(template(2, true));
+        store.on_new_template(template(3, true));
+        store.request_transaction_data(3).unwrap();
+        assert_eq!(store.active_template().unwrap().template_id, 1);
+
+        store.on_set_new_prev_hash(&prev_hash(2));
+        assert_eq!(store.len(), 1);
+        assert!(store.get_template(1).is_none());
+        assert!(store.get_transaction_data(1).is_none());
+        assert!(!store.is_request_pending(3));
+        let active = store.active_template().unwrap();
+        assert_eq!(active.template_id, 2);
+        assert!(!active.future_template);
+    }
+}
@@ -0,0 +1,397 @@
+//! Lifecycle of the mining job tokens allocated with `AllocateMiningJobToken`.
+//!
+//! `AllocateMiningJobToken` is rate limited: the [`TokenManager`] issues tokens, refuses to issue
+//! more than `max_tokens_per_window` tokens per user identifier in `rate_limit_window`, validates
+//! the tokens received in `DeclareMiningJob` and expires them after `token_lifetime`.
+//!
+//! The token of a job accepted by the JDS, sent in `DeclareMiningJobSuccess.new_mining_job_token`
+//! and then in `SetCustomMiningJob.token`, is a [`DeclaredJobToken`] signed with the authority key
+//! of the JDS: the pool does not share any state with the JDS, it checks the signature with the
+//! public key of the JDS and that the token has not been used already.
+use crate::{utils::Id, Error};
+use binary_sv2::B0255;
+use nohash_hasher::BuildNoHashHasher;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryInto,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use stratum_common::{
+    bitcoin::hashes::{sha256, Hash, HashEngine},
+    secp256k1::{schnorr::Signature, Keypair, Message, Secp256k1, SecretKey, XOnlyPublicKey},
+};
+
+/// Time after which an allocated token can not be used anymore
+pub const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(600);
+/// Tokens that can be allocated by a user identifier in `DEFAULT_RATE_LIMIT_WINDOW`
+pub const DEFAULT_MAX_TOKENS_PER_WINDOW: usize = 60;
+pub const DEFAULT_RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+/// Time after which the token of a declared job is refused by the pool. The job of a future
+/// template is sent to the pool only when the template is activated, that can be hours later.
+pub const DECLARED_JOB_TOKEN_LIFETIME: Duration = Duration::from_secs(24 * 3600);
+
+const DECLARED_JOB_TOKEN_TAG: &[u8] = b"sv2-declared-job-token";
+// token + issued_at + schnorr signature
+const DECLARED_JOB_TOKEN_SIZE: usize = 4 + 8 + 64;
+
+#[derive(Debug, Clone)]
+struct IssuedToken {
+    user_identifier: String,
+    issued_at: Instant,
+}
+
+#[derive(Debug)]
+pub struct TokenManager {
+    ids: Id,
+    tokens: HashMap<u32, IssuedToken, BuildNoHashHasher<u32>>,
+    // user identifier -> time at which the tokens in the current window have been issued
+    allocations: HashMap<String, Vec<Instant>>,
+    token_lifetime: Duration,
+    max_tokens_per_window: usize,
+    rate_limit_window: Duration,
+}
+
+impl Default for TokenManager {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_TOKEN_LIFETIME,
+            DEFAULT_MAX_TOKENS_PER_WINDOW,
+            DEFAULT_RATE_LIMIT_WINDOW,
+        )
+    }
+}
+
+impl TokenManager {
+    pub fn new(
+        token_lifetime: Duration,
+        max_tokens_per_window: usize,
+        rate_limit_window: Duration,
+    ) -> Self {
+        Self {
+            ids: Id::new(),
+            tokens: HashMap::with_hasher(BuildNoHashHasher::default()),
+            allocations: HashMap::new(),
+            token_lifetime,
+            max_tokens_per_window,
+            rate_limit_window,
+        }
+    }
+
+    /// Issues a new token for `user_identifier`, returns `Error::JobTokenRateLimited` if the user
+    /// already allocated too many tokens in the current window.
+    pub fn allocate(&mut self, user_identifier: &str) -> Result<u32, Error> {
+        self.allocate_at(user_identifier, Instant::now())
+    }
+
+    fn allocate_at(&mut self, user_identifier: &str, now: Instant) -> Result<u32, Error> {
+        let window = self.rate_limit_window;
+        let allocations = self
+            .allocations
+            .entry(user_identifier.to_string())
+            .or_default();
+        allocations.retain(|t| now.saturating_duration_since(*t) < window);
+        if allocations.len() >= self.max_tokens_per_window {
+            return Err(Error::JobTokenRateLimited(user_identifier.to_string()));
+        }
+        allocations.push(now);
+        let token = self.ids.next();
+        self.tokens.insert(
+            token,
+            IssuedToken {
+                user_identifier: user_identifier.to_string(),
+                issued_at: now,
+            },
+        );
+        Ok(token)
+    }
+
+    /// Checks that `token` has been issued by this manager and is not expired
+    pub fn validate(&self, token: u32) -> Result<(), Error> {
+        self.validate_at(token, Instant::now())
+    }
+
+    fn validate_at(&self, token: u32, now: Instant) -> Result<(), Error> {
+        match self.tokens.get(&token) {
+            Some(issued)
+                if now.saturating_duration_since(issued.issued_at) < self.token_lifetime =>
+            {
+                Ok(())
+            }
+            Some(_) => Err(Error::ExpiredJobToken(token)),
+            None => Err(Error::InvalidJobToken(token)),
+        }
+    }
+
+    /// Same as [`TokenManager::validate`] for the serialized token received in
+    /// `DeclareMiningJob.mining_job_token`
+    pub fn validate_bytes(&self, token: &[u8]) -> Result<u32, Error> {
+        let token = Self::token_from_bytes(token)?;
+        self.validate(token)?;
+        Ok(token)
+    }
+
+    /// Returns the user identifier that allocated `token`
+    pub fn user_identifier(&self, token: u32) -> Option<&str> {
+        self.tokens
+            .get(&token)
+            .map(|issued| issued.user_identifier.as_str())
+    }
+
+    /// Removes the expired tokens and the allocations out of the rate limit window. Returns the
+    /// removed tokens.
+    pub fn expire(&mut self) -> Vec<u32> {
+        self.expire_at(Instant::now())
+    }
+
+    fn expire_at(&mut self, now: Instant) -> Vec<u32> {
+        let lifetime = self.token_lifetime;
+        let window = self.rate_limit_window;
+        let mut expired: Vec<u32> = self
+            .tokens
+            .iter()
+            .filter(|(_, issued)| now.saturating_duration_since(issued.issued_at) >= lifetime)
+            .map(|(token, _)| *token)
+            .collect();
+        expired.sort_unstable();
+        for token in &expired {
+            self.tokens.remove(token);
+        }
+        self.allocations.retain(|_, allocations| {
+            allocations.retain(|t| now.saturating_duration_since(*t) < window);
+            !allocations.is_empty()
+        });
+        expired
+    }
+
+    /// Serialized token as sent in `AllocateMiningJobToken.Success.mining_job_token`
+    pub fn token_to_bytes(token: u32) -> B0255<'static> {
+        // Infallible unwrap 4 bytes fit in a B0255
+        token.to_le_bytes().to_vec().try_into().unwrap()
+    }
+
+    pub fn token_from_bytes(token: &[u8]) -> Result<u32, Error> {
+        let token: [u8; 4] = token
+            .try_into()
+            .map_err(|_| Error::InvalidJobTokenLen(token.len()))?;
+        Ok(u32::from_le_bytes(token))
+    }
+}
+
+/// Token of a job accepted by the JDS: the token allocated for the job and the time at which the
+/// job has been accepted, signed by the JDS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeclaredJobToken {
+    pub token: u32,
+    /// Seconds since the unix epoch, the pool runs on another host so an `Instant` can not be
+    /// used
+    pub issued_at: u64,
+}
+
+impl DeclaredJobToken {
+    pub fn new(token: u32) -> Self {
+        Self {
+            token,
+            issued_at: unix_time(),
+        }
+    }
+
+    /// Serialized token as sent in `DeclareMiningJobSuccess.new_mining_job_token`
+    pub fn sign(&self, secret_key: &SecretKey) -> B0255<'static> {
+        let secp = Secp256k1::signing_only();
+        let keypair = Keypair::from_secret_key(&secp, secret_key);
+        let signature = secp.sign_schnorr(&self.message(), &keypair);
+        let mut bytes = self.payload().to_vec();
+        bytes.extend_from_slice(signature.as_ref());
+        // Infallible unwrap DECLARED_JOB_TOKEN_SIZE bytes fit in a B0255
+        bytes.try_into().unwrap()
+    }
+
+    /// Parses the token received in `SetCustomMiningJob.token` and checks that it has been signed
+    /// by `public_key`
+    pub fn verify(bytes: &[u8], public_key: &XOnlyPublicKey) -> Result<Self, Error> {
+        if bytes.len() != DECLARED_JOB_TOKEN_SIZE {
+            return Err(Error::InvalidJobTokenLen(bytes.len()));
+        }
+        // Infallible unwraps the len is checked above
+        let token = Self {
+            token: u32::from_le_bytes(bytes[..4].try_into().unwrap()),
+            issued_at: u64::from_le_bytes(bytes[4..12].try_into().unwrap()),
+        };
+        let signature =
+            Signature::from_slice(&bytes[12..]).map_err(|_| Error::InvalidJobToken(token.token))?;
+        Secp256k1::verification_only()
+            .verify_schnorr(&signature, &token.message(), public_key)
+            .map_err(|_| Error::InvalidJobToken(token.token))?;
+        Ok(token)
+    }
+
+    fn payload(&self) -> [u8; 12] {
+        let mut payload = [0; 12];
+        payload[..4].copy_from_slice(&self.token.to_le_bytes());
+        payload[4..].copy_from_slice(&self.issued_at.to_le_bytes());
+        payload
+    }
+
+    fn message(&self) -> Message {
+        let mut engine = sha256::Hash::engine();
+        engine.input(DECLARED_JOB_TOKEN_TAG);
+        engine.input(&self.payload());
+        Message::from_digest(sha256::Hash::from_engine(engine).into_inner())
+    }
+}
+
+/// Tokens of the declared jobs already used by the pool, a token can be used for a single
+/// `SetCustomMiningJob`
+#[derive(Debug)]
+pub struct DeclaredJobTokens {
+    public_key: XOnlyPublicKey,
+    used: HashSet<DeclaredJobToken>,
+    lifetime: Duration,
+}
+
+impl DeclaredJobTokens {
+    /// `public_key` is the authority public key of the JDS
+    pub fn new(public_key: XOnlyPublicKey) -> Self {
+        Self {
+            public_key,
+            used: HashSet::new(),
+            lifetime: DECLARED_JOB_TOKEN_LIFETIME,
+        }
+    }
+
+    /// Checks that `token` has been signed by the JDS, is not expired and has not been used
+    /// already
+    pub fn check(&self, token: &[u8]) -> Result<DeclaredJobToken, Error> {
+        self.check_at(token, unix_time())
+    }
+
+    fn check_at(&self, token: &[u8], now: u64) -> Result<DeclaredJobToken, Error> {
+        let token = DeclaredJobToken::verify(token, &self.public_key)?;
+        if now.saturating_sub(token.issued_at) >= self.lifetime.as_secs() {
+            return Err(Error::ExpiredJobToken(token.token));
+        }
+        if self.used.contains(&token) {
+            return Err(Error::UsedJobToken(token.token));
+        }
+        Ok(token)
+    }
+
+    /// Marks `token` as used, the expired tokens are forgotten as they are refused anyway
+    pub fn use_token(&mut self, token: DeclaredJobToken) {
+        let now = unix_time();
+        let lifetime = self.lifetime.as_secs();
+        self.used
+            .retain(|used| now.saturating_sub(used.issued_at) < lifetime);
+        self.used.insert(token);
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_lifecycle() {
+        let mut manager = TokenManager::new(Duration::from_secs(10), 2, Duration::from_secs(1));
+        let now = Instant::now();
+        let token = manager.allocate_at("user", now).unwrap();
+        assert!(manager.validate_at(token, now).is_ok());
+        assert_eq!(manager.user_identifier(token), Some("user"));
+        assert!(matches!(
+            manager.validate_at(token + 1, now),
+            Err(Error::InvalidJobToken(_))
+        ));
+
+        let bytes = TokenManager::token_to_bytes(token);
+        assert_eq!(
+            TokenManager::token_from_bytes(bytes.inner_as_ref()).unwrap(),
+            token
+        );
+        assert!(matches!(
+            TokenManager::token_from_bytes(&[0; 3]),
+            Err(Error::InvalidJobTokenLen(3))
+        ));
+
+        let later = now + Duration::from_secs(10);
+        assert!(matches!(
+            manager.validate_at(token, later),
+            Err(Error::ExpiredJobToken(_))
+        ));
+        assert_eq!(manager.expire_at(later), vec![token]);
+        assert!(matches!(
+            manager.validate_at(token, later),
+            Err(Error::InvalidJobToken(_))
+        ));
+    }
+
+    #[test]
+    fn test_token_rate_limit() {
+        let mut manager = TokenManager::new(Duration::from_secs(10), 2, Duration::from_secs(1));
+        let now = Instant::now();
+        manager.allocate_at("user", now).unwrap();
+        manager.allocate_at("user", now).unwrap();
+        assert!(matches!(
+            manager.allocate_at("user", now),
+            Err(Error::JobTokenRateLimited(_))
+        ));
+        // Limits are per user identifier
+        assert!(manager.allocate_at("other", now).is_ok());
+        // Window is over
+        assert!(manager
+            .allocate_at("user", now + Duration::from_secs(1))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_declared_job_token() {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let public_key = secret_key.x_only_public_key(&secp).0;
+        let other_key = SecretKey::from_slice(&[8; 32]).unwrap();
+
+        let token = DeclaredJobToken::new(3);
+        let bytes = token.sign(&secret_key);
+        let mut tokens = DeclaredJobTokens::new(public_key);
+        assert_eq!(tokens.check(bytes.inner_as_ref()).unwrap(), token);
+
+        // Not signed by the JDS
+        let forged = token.sign(&other_key);
+        assert!(matches!(
+            tokens.check(forged.inner_as_ref()),
+            Err(Error::InvalidJobToken(3))
+        ));
+        let mut tampered = bytes.to_vec();
+        tampered[0] = 4;
+        assert!(matches!(
+            tokens.check(&tampered),
+            Err(Error::InvalidJobToken(4))
+        ));
+        assert!(matches!(
+            tokens.check(&[1, 2, 3]),
+            Err(Error::InvalidJobTokenLen(3))
+        ));
+        assert!(matches!(
+            tokens.check_at(bytes.inner_as_ref(), token.issued_at + 24 * 3600),
+            Err(Error::ExpiredJobToken(3))
+        ));
+
+        tokens.use_token(token);
+        assert!(matches!(
+            tokens.check(bytes.inner_as_ref()),
+            Err(Error::UsedJobToken(3))
+        ));
+        // Same token accepted again by the JDS
+        let token = DeclaredJobToken {
+            token: 3,
+            issued_at: token.issued_at + 1,
+        };
+        assert!(tokens.check(token.sign(&secret_key).inner_as_ref()).is_ok());
+    }
+}
@@ -0,0 +1,265 @@
+//! Strategies that pick the upstream a new downstream is sent to.
+//!
+//! A [`SelectionStrategy`] only sees an [`UpstreamCandidate`] for each upstream that can accept
+//! the downstream, so the same strategies are used by the [`crate::selectors::GeneralMiningSelector`]
+//! of a mining proxy and by a proxy that connects to a single upstream at a time and picks it
+//! from a list, e.g. when it reconnects.
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+/// Number of ping samples averaged by [`LatencySamples`]
+pub const LATENCY_SAMPLES: usize = 8;
+
+/// What a [`SelectionStrategy`] knows of an upstream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UpstreamCandidate {
+    pub id: u32,
+    /// Share of the hash rate the upstream should get relative to the others, 0 for a backup
+    pub weight: f32,
+    pub total_hash_rate: u64,
+    /// Average of the last ping samples, `None` if the upstream was never pinged
+    pub latency: Option<Duration>,
+}
+
+/// Picks the upstream that gets the next downstream
+pub trait SelectionStrategy: Debug + Send + Sync {
+    /// Index in `candidates` of the selected upstream, `None` only if `candidates` is empty
+    fn select(&self, candidates: &[UpstreamCandidate]) -> Option<usize>;
+}
+
+/// Selects the upstream that is the most below its share of the hash rate, that is the one with
+/// the smallest total hash rate / weight. Upstreams with weight 0 are only selected when no other
+/// upstream is available.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LeastLoaded;
+
+impl SelectionStrategy for LeastLoaded {
+    fn select(&self, candidates: &[UpstreamCandidate]) -> Option<usize> {
+        let loads: Vec<(f32, u64)> = candidates
+            .iter()
+            .map(|c| (c.weight, c.total_hash_rate))
+            .collect();
+        least_loaded_index(&loads)
+    }
+}
+
+/// `loads` are the (weight, total hash rate) of the upstreams. Ties go to the biggest weight.
+fn least_loaded_index(loads: &[(f32, u64)]) -> Option<usize> {
+    let weighted = loads.iter().any(|(weight, _)| *weight > 0.0);
+    loads
+        .iter()
+        .enumerate()
+        .filter(|(_, (weight, _))| !weighted || *weight > 0.0)
+        .map(|(index, (weight, hash_rate))| {
+            let load = match *weight > 0.0 {
+                true => *hash_rate as f64 / *weight as f64,
+                false => *hash_rate as f64,
+            };
+            (index, load, *weight)
+        })
+        .min_by(|a, b| {
+            a.1.partial_cmp(&b.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal))
+        })
+        .map(|(index, _, _)| index)
+}
+
+/// Selects the available upstream that comes first in a fixed order, the other upstreams are only
+/// used when it is down
+#[derive(Debug, Clone, Default)]
+pub struct StaticPriority {
+    order: Vec<u32>,
+}
+
+impl StaticPriority {
+    /// `order` are the ids of the upstreams from the preferred one. The upstreams that are not in
+    /// `order` come after, by id.
+    pub fn new(order: Vec<u32>) -> Self {
+        Self { order }
+    }
+
+    fn rank(&self, id: u32) -> (usize, u32) {
+        let position = self.order.iter().position(|i| *i == id);
+        (position.unwrap_or(self.order.len()), id)
+    }
+}
+
+impl SelectionStrategy for StaticPriority {
+    fn select(&self, candidates: &[UpstreamCandidate]) -> Option<usize> {
+        candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, c)| self.rank(c.id))
+            .map(|(index, _)| index)
+    }
+}
+
+/// Selects the upstreams in turn
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl RoundRobin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SelectionStrategy for RoundRobin {
+    fn select(&self, candidates: &[UpstreamCandidate]) -> Option<usize> {
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(self.next.fetch_add(1, Ordering::Relaxed) % candidates.len())
+    }
+}
+
+/// Selects the upstream with the lowest average ping. The upstreams that were never pinged are
+/// only selected when no other upstream is, ties go to the least loaded upstream (see
+/// [`LeastLoaded`]).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LowestLatency;
+
+impl SelectionStrategy for LowestLatency {
+    fn select(&self, candidates: &[UpstreamCandidate]) -> Option<usize> {
+        let lowest = candidates
+            .iter()
+            .map(|c| c.latency.unwrap_or(Duration::MAX))
+            .min()?;
+        let fastest: Vec<usize> = (0..candidates.len())
+            .filter(|i| candidates[*i].latency.unwrap_or(Duration::MAX) == lowest)
+            .collect();
+        let loads: Vec<(f32, u64)> = fastest
+            .iter()
+            .map(|i| (candidates[*i].weight, candidates[*i].total_hash_rate))
+            .collect();
+        least_loaded_index(&loads).map(|index| fastest[index])
+    }
+}
+
+/// Last [`LATENCY_SAMPLES`] ping samples of an upstream
+#[derive(Debug, Clone, Default)]
+pub struct LatencySamples {
+    samples: VecDeque<Duration>,
+}
+
+impl LatencySamples {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, sample: Duration) {
+        if self.samples.len() == LATENCY_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Average of the samples, `None` if there are none
+    pub fn average(&self) -> Option<Duration> {
+        let total: Duration = self.samples.iter().sum();
+        (!self.samples.is_empty()).then(|| total / self.samples.len() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(id: u32, total_hash_rate: u64, latency_ms: Option<u64>) -> UpstreamCandidate {
+        UpstreamCandidate {
+            id,
+            weight: 1.0,
+            total_hash_rate,
+            latency: latency_ms.map(Duration::from_millis),
+        }
+    }
+
+    #[test]
+    fn test_least_loaded_index() {
+        assert_eq!(least_loaded_index(&[]), None);
+        // The first downstream goes to the upstream with the biggest weight
+        assert_eq!(least_loaded_index(&[(30.0, 0), (70.0, 0)]), Some(1));
+        assert_eq!(least_loaded_index(&[(30.0, 0), (70.0, 100)]), Some(0));
+        assert_eq!(least_loaded_index(&[(30.0, 100), (70.0, 100)]), Some(1));
+        // Upstreams with weight 0 are only used as backup
+        assert_eq!(least_loaded_index(&[(0.0, 0), (1.0, 100)]), Some(1));
+        assert_eq!(least_loaded_index(&[(0.0, 100), (0.0, 0)]), Some(1));
+    }
+
+    #[test]
+    fn test_hash_rate_is_split_by_weight() {
+        let mut loads = vec![(70.0, 0), (30.0, 0)];
+        for _ in 0..100 {
+            let index = least_loaded_index(&loads).unwrap();
+            loads[index].1 += 10;
+        }
+        assert_eq!(loads[0].1, 700);
+        assert_eq!(loads[1].1, 300);
+    }
+
+    #[test]
+    fn test_static_priority() {
+        let strategy = StaticPriority::new(vec![2, 0]);
+        let candidates = [candidate(0, 0, None), candidate(1, 0, None)];
+        assert_eq!(strategy.select(&candidates), Some(0));
+        let candidates = [candidate(1, 0, None), candidate(2, 100, None)];
+        assert_eq!(strategy.select(&candidates), Some(1));
+        // Not in the order, by id
+        let candidates = [candidate(4, 0, None), candidate(3, 0, None)];
+        assert_eq!(strategy.select(&candidates), Some(1));
+        assert_eq!(strategy.select(&[]), None);
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let strategy = RoundRobin::new();
+        let candidates = [candidate(0, 0, None), candidate(1, 0, None)];
+        let selected: Vec<_> = (0..4)
+            .map(|_| strategy.select(&candidates).unwrap())
+            .collect();
+        assert_eq!(selected, vec![0, 1, 0, 1]);
+        assert_eq!(strategy.select(&[]), None);
+    }
+
+    #[test]
+    fn test_lowest_latency() {
+        let strategy = LowestLatency;
+        let candidates = [
+            candidate(0, 0, Some(40)),
+            candidate(1, 100, Some(10)),
+            candidate(2, 0, None),
+        ];
+        assert_eq!(strategy.select(&candidates), Some(1));
+        // Same latency, the least loaded
+        let candidates = [
+            candidate(0, 100, Some(10)),
+            candidate(1, 0, Some(10)),
+            candidate(2, 0, Some(40)),
+        ];
+        assert_eq!(strategy.select(&candidates), Some(1));
+        // Never pinged, the least loaded
+        let candidates = [candidate(0, 100, None), candidate(1, 0, None)];
+        assert_eq!(strategy.select(&candidates), Some(1));
+        assert_eq!(strategy.select(&[]), None);
+    }
+
+    #[test]
+    fn test_latency_samples() {
+        let mut samples = LatencySamples::new();
+        assert_eq!(samples.average(), None);
+        samples.record(Duration::from_millis(10));
+        samples.record(Duration::from_millis(30));
+        assert_eq!(samples.average(), Some(Duration::from_millis(20)));
+        for _ in 0..LATENCY_SAMPLES {
+            samples.record(Duration::from_millis(5));
+        }
+        assert_eq!(samples.average(), Some(Duration::from_millis(5)));
+    }
+}
@@ -1,13 +1,16 @@
 use std::{
+    collections::HashMap,
     convert::{TryFrom, TryInto},
     ops::{Div, Mul},
     str::FromStr,
     sync::{Mutex as Mutex_, MutexGuard, PoisonError},
 };
 
-use binary_sv2::{Seq064K, ShortTxId, U256};
+use binary_sv2::{Seq064K, ShortTxId, B016M, U256};
 use job_declaration_sv2::{DeclareMiningJob, SubmitSolutionJd};
+use mining_sv2::NewExtendedMiningJob;
 use siphasher::sip::SipHasher24;
+use template_distribution_sv2::{CoinbaseOutputDataSize, NewTemplate, SubmitSolution};
 //compact_target_from_u256
 use bitcoin::Block;
 use stratum_common::{
@@ -22,12 +25,12 @@ use stratum_common::{
             uint::{Uint128, Uint256},
             BitArray,
         },
-        PublicKey, Script, Transaction, XOnlyPublicKey,
+        PublicKey, Script, Transaction, TxOut, XOnlyPublicKey,
     },
 };
 use tracing::error;
 
-use crate::errors::Error;
+use crate::{channel_logic::channel_factory::Share, errors::Error};
 
 /// Generator of unique ids
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -54,61 +57,48 @@ impl Default for Id {
 }
 
 /// Safer Mutex wrapper
+///
+/// The lock is never poisoned: if a thread panics while holding it, the next `safe_lock` gets the
+/// value as the panicking thread left it. With the `lock_diagnostics` feature the locks are
+/// checked for deadlocks, see the `lock_diagnostics` module.
 #[derive(Debug)]
 pub struct Mutex<T: ?Sized>(Mutex_<T>);
 
 impl<T> Mutex<T> {
-    /// `safe_lock` takes a closure that takes a mutable reference to the inner value, and returns a
-    /// result that either contains the return value of the closure, or a `PoisonError` that
-    /// contains a `MutexGuard` to the inner value. This is used to ensure no async executions
-    /// while locked. To prevent `PoisonLock` errors, unwraps should never be used within the
-    /// closure. Always return the result and handle outside of the safe lock.
+    /// `safe_lock` takes a closure that takes a mutable reference to the inner value, and returns
+    /// the return value of the closure. This is used to ensure no async executions while locked.
+    /// The lock is not poisoned by a panic in the closure, so the result is always `Ok`; it is
+    /// kept for the callers that already handle it.
     ///
     /// Arguments:
     ///
     /// * `thunk`: A closure that takes a mutable reference to the value inside the Mutex and
     ///   returns a
     /// value of type Ret.
+    #[cfg_attr(feature = "lock_diagnostics", track_caller)]
     pub fn safe_lock<F, Ret>(&self, thunk: F) -> Result<Ret, PoisonError<MutexGuard<'_, T>>>
     where
         F: FnOnce(&mut T) -> Ret,
     {
-        let mut lock = self.0.lock()?;
-        let return_value = thunk(&mut *lock);
-        drop(lock);
-        Ok(return_value)
+        Ok(self.super_safe_lock(thunk))
     }
 
+    /// Like `safe_lock` but returns the return value of the closure directly
+    #[cfg_attr(feature = "lock_diagnostics", track_caller)]
     pub fn super_safe_lock<F, Ret>(&self, thunk: F) -> Ret
     where
         F: FnOnce(&mut T) -> Ret,
     {
-        //#[cfg(feature = "disable_nopanic")]
-        {
-            self.safe_lock(thunk).unwrap()
-        }
-        //#[cfg(not(feature = "disable_nopanic"))]
-        //{
-        //    // based on https://github.com/dtolnay/no-panic
-        //    struct __NoPanic;
-        //    extern "C" {
-        //        #[link_name = "super_safe_lock called on a function that may panic"]
-        //        fn trigger() -> !;
-        //    }
-        //    impl core::ops::Drop for __NoPanic {
-        //        fn drop(&mut self) {
-        //            unsafe {
-        //                trigger();
-        //            }
-        //        }
-        //    }
-        //    let mut lock = self.0.lock().expect("threads to never panic");
-        //    let __guard = __NoPanic;
-        //    let return_value = thunk(&mut *lock);
-        //    core::mem::forget(__guard);
-        //    drop(lock);
-        //    return_value
-        //}
+        #[cfg(feature = "lock_diagnostics")]
+        let (mut lock, held) =
+            crate::lock_diagnostics::lock(&self.0, std::panic::Location::caller());
+        #[cfg(not(feature = "lock_diagnostics"))]
+        let mut lock = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let return_value = thunk(&mut *lock);
+        drop(lock);
+        #[cfg(feature = "lock_diagnostics")]
+        drop(held);
+        return_value
     }
 
     pub fn new(v: T) -> Self {
@@ -116,7 +106,14 @@ impl<T> Mutex<T> {
     }
 
     pub fn to_remove(&self) -> Result<MutexGuard<'_, T>, PoisonError<MutexGuard<'_, T>>> {
-        self.0.lock()
+        Ok(self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+#[cfg(feature = "lock_diagnostics")]
+impl<T: ?Sized> Drop for Mutex<T> {
+    fn drop(&mut self) {
+        crate::lock_diagnostics::forget(&self.0);
     }
 }
 
@@ -256,6 +253,7 @@ impl TryFrom<CoinbaseOutput> for Script {
 pub enum InputError {
     NegativeInput,
     DivisionByZero,
+    NonFiniteInput,
 }
 
 /// The pool set a target for each miner. Each target is calibrated on the hashrate of the miner.
@@ -698,6 +696,72 @@ pub fn get_target(
     hash.reverse();
     hash
 }
+/// Max size of the scriptSig of a coinbase input
+const MAX_COINBASE_SCRIPT_SIG_SIZE: usize = 100;
+
+/// Serialized size of the parts of a coinbase transaction that do not depend on the outputs,
+/// assuming a maximally-sized (100 bytes) scriptSig and a segwit commitment witness: version (4),
+/// marker and flag (2), input count (1), outpoint (36), scriptSig len (1), scriptSig (100),
+/// sequence (4), witness (1 + 1 + 32) and locktime (4).
+const COINBASE_FIXED_MAX_SIZE: usize =
+    4 + 2 + 1 + 36 + 1 + MAX_COINBASE_SCRIPT_SIG_SIZE + 4 + 34 + 4;
+
+/// Max number of bytes of the output count variable-length integer, coinbase transactions are at
+/// most 64KB so 3 bytes are always enough.
+const MAX_OUTPUT_COUNT_VARINT_SIZE: usize = 3;
+
+/// Returns the serialized size of the outputs that a role will add to the coinbase
+pub fn coinbase_outputs_serialized_size(outputs: &[TxOut]) -> usize {
+    outputs
+        .iter()
+        .map(|o| bitcoin::consensus::serialize(o).len())
+        .sum()
+}
+
+/// Returns the `CoinbaseOutputDataSize` that must be sent to the Template Provider by a role that
+/// will add `outputs` to the coinbase of every template.
+pub fn coinbase_output_data_size(outputs: &[TxOut]) -> Result<CoinbaseOutputDataSize, Error> {
+    let size = coinbase_outputs_serialized_size(outputs);
+    let coinbase_output_max_additional_size =
+        u32::try_from(size).map_err(|_| Error::CoinbaseOutputsTooBig(size, u32::MAX as usize))?;
+    Ok(CoinbaseOutputDataSize {
+        coinbase_output_max_additional_size,
+    })
+}
+
+/// Checks that `outputs` fit in the space that has been reserved with `CoinbaseOutputDataSize`
+pub fn check_coinbase_outputs_size(
+    outputs: &[TxOut],
+    coinbase_output_max_additional_size: u32,
+) -> Result<(), Error> {
+    let size = coinbase_outputs_serialized_size(outputs);
+    if size > coinbase_output_max_additional_size as usize {
+        return Err(Error::CoinbaseOutputsTooBig(
+            size,
+            coinbase_output_max_additional_size as usize,
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that a template leaves enough space for the `coinbase_output_max_additional_size` bytes
+/// requested with `CoinbaseOutputDataSize`. The coinbase built on top of the template, with a
+/// maximally-sized scriptSig and all the additional outputs, must still fit in the 64KB fields
+/// used to send it (e.g. `SubmitSolution.coinbase_tx`).
+pub fn check_template_coinbase_space(
+    template: &NewTemplate,
+    coinbase_output_max_additional_size: u32,
+) -> Result<(), Error> {
+    let size = COINBASE_FIXED_MAX_SIZE
+        + MAX_OUTPUT_COUNT_VARINT_SIZE
+        + template.coinbase_tx_outputs.as_ref().len()
+        + coinbase_output_max_additional_size as usize;
+    if size > u16::MAX as usize {
+        return Err(Error::TemplateCoinbaseTooBig(template.template_id, size));
+    }
+    Ok(())
+}
+
 pub fn hash_lists_tuple(
     tx_data: Vec<Transaction>,
     tx_short_hash_nonce: u64,
@@ -706,27 +770,67 @@ pub fn hash_lists_tuple(
     for tx in tx_data {
         txid_list.push(tx.txid());
     }
-    let mut tx_short_hash_list_: Vec<ShortTxId> = Vec::new();
-    for txid in txid_list.clone() {
-        tx_short_hash_list_.push(get_short_hash(txid, tx_short_hash_nonce));
-    }
-    let tx_short_hash_list: Seq064K<'static, ShortTxId> = Seq064K::from(tx_short_hash_list_);
+    let tx_short_hash_list = short_txids(&txid_list, tx_short_hash_nonce);
     let tx_hash_list_hash = tx_hash_list_hash_builder(txid_list);
     (tx_short_hash_list, tx_hash_list_hash)
 }
 
-pub fn get_short_hash(txid: bitcoin::Txid, tx_short_hash_nonce: u64) -> ShortTxId<'static> {
-    // hash the short hash nonce
+/// Returns the SipHash-2-4 keys used to compute the short ids of a job declared with
+/// `tx_short_hash_nonce`: the first two little-endian u64 of SHA256(`tx_short_hash_nonce`).
+pub fn short_txid_keys(tx_short_hash_nonce: u64) -> (u64, u64) {
     let nonce_hash = sha256::Hash::hash(&tx_short_hash_nonce.to_le_bytes());
-    // take first two integers from the hash
+    // Infallible unwraps a sha256 is 32 bytes long
     let k0 = u64::from_le_bytes(nonce_hash[0..8].try_into().unwrap());
     let k1 = u64::from_le_bytes(nonce_hash[8..16].try_into().unwrap());
-    // get every transaction, hash it, remove first two bytes and push the ShortTxId in a vector
+    (k0, k1)
+}
+
+/// Computes the short id of a transaction as required by the Job Declaration protocol:
+/// SipHash-2-4(`txid`, `k0`, `k1`) with the two most significant bytes dropped, that is the 6
+/// first bytes of its little-endian encoding as in BIP152.
+pub fn short_txid(txid: &bitcoin::Txid, k0: u64, k1: u64) -> ShortTxId<'static> {
     let hasher = SipHasher24::new_with_keys(k0, k1);
-    let tx_hashed = hasher.hash(&txid);
-    let tx_hashed_bytes: Vec<u8> = tx_hashed.to_le_bytes()[2..].to_vec();
-    let short_tx_id: ShortTxId = tx_hashed_bytes.try_into().unwrap();
-    short_tx_id
+    let tx_hashed = hasher.hash(&txid[..]);
+    // Infallible unwrap 6 bytes is a valid ShortTxId
+    tx_hashed.to_le_bytes()[..6].to_vec().try_into().unwrap()
+}
+
+/// Computes the short ids of `txids` for a job declared with `tx_short_hash_nonce`
+pub fn short_txids(
+    txids: &[bitcoin::Txid],
+    tx_short_hash_nonce: u64,
+) -> Seq064K<'static, ShortTxId<'static>> {
+    let (k0, k1) = short_txid_keys(tx_short_hash_nonce);
+    let short_ids: Vec<ShortTxId<'static>> =
+        txids.iter().map(|txid| short_txid(txid, k0, k1)).collect();
+    Seq064K::from(short_ids)
+}
+
+/// Builds a map short id -> txid used to resolve the short ids of a declared job. Returns
+/// `Error::ShortTxIdCollision` if two transactions have the same short id, in that case the
+/// short ids can not be used to identify the transactions.
+pub fn short_txid_map<'a, I>(
+    txids: I,
+    tx_short_hash_nonce: u64,
+) -> Result<HashMap<[u8; 6], bitcoin::Txid>, Error>
+where
+    I: IntoIterator<Item = &'a bitcoin::Txid>,
+{
+    let (k0, k1) = short_txid_keys(tx_short_hash_nonce);
+    let mut map = HashMap::new();
+    for txid in txids {
+        // Infallible unwrap ShortTxId are always 6 bytes long
+        let short_id: [u8; 6] = short_txid(txid, k0, k1).to_vec().try_into().unwrap();
+        if map.insert(short_id, *txid).is_some() {
+            return Err(Error::ShortTxIdCollision(short_id));
+        }
+    }
+    Ok(map)
+}
+
+pub fn get_short_hash(txid: bitcoin::Txid, tx_short_hash_nonce: u64) -> ShortTxId<'static> {
+    let (k0, k1) = short_txid_keys(tx_short_hash_nonce);
+    short_txid(&txid, k0, k1)
 }
 
 fn tx_hash_list_hash_builder(txid_list: Vec<bitcoin::Txid>) -> U256<'static> {
@@ -807,6 +911,89 @@ impl<'a> From<BlockCreator<'a>> for bitcoin::Block {
     }
 }
 
+/// Builds the block of a solution found on a template of the Template Distribution protocol, e.g.
+/// to submit it directly to a node when the Template Provider can not be reached. `prev_hash` and
+/// `nbits` come from the `SetNewPrevHash` of the template and `transactions` from its
+/// `RequestTransactionData.Success`.
+pub fn block_from_template_solution(
+    solution: &SubmitSolution,
+    prev_hash: U256,
+    nbits: u32,
+    transactions: &[B016M],
+) -> Result<Block, Error> {
+    let coinbase = Transaction::deserialize(solution.coinbase_tx.inner_as_ref())
+        .map_err(|_| Error::InvalidCoinbase)?;
+    let mut txdata = vec![coinbase];
+    for tx in transactions {
+        let tx = Transaction::deserialize(tx.inner_as_ref())
+            .map_err(|e| Error::TxDecodingError(e.to_string()))?;
+        txdata.push(tx);
+    }
+    let mut block = Block {
+        header: BlockHeader {
+            version: solution.version as i32,
+            prev_blockhash: u256_to_block_hash(prev_hash.into_static()),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: solution.header_timestamp,
+            bits: nbits,
+            nonce: solution.header_nonce,
+        },
+        txdata,
+    };
+    // Never None, there is always the coinbase
+    block.header.merkle_root = block
+        .compute_merkle_root()
+        .ok_or(Error::ImpossibleToCalculateMerkleRoot)?;
+    Ok(block)
+}
+
+/// Builds the `SubmitSolution` of a share that meets the bitcoin target, to be sent to the
+/// Template Provider. `job` is the job the share has been mined on and `extranonce` the full
+/// extranonce of the share: the prefix of the channel followed by the part rolled by the miner.
+/// `prev_hash` and `nbits` come from the `SetNewPrevHash` of the job.
+///
+/// The header is rebuilt the way the node will do it from the coinbase and the merkle path of the
+/// job, if its hash does not meet `nbits` [`Error::InvalidSolution`] is returned instead of a
+/// solution that the node would reject.
+pub fn submit_solution_from_share(
+    template_id: u64,
+    job: &NewExtendedMiningJob,
+    share: &Share,
+    extranonce: &[u8],
+    prev_hash: BlockHash,
+    nbits: u32,
+) -> Result<SubmitSolution<'static>, Error> {
+    let coinbase = [
+        job.coinbase_tx_prefix.inner_as_ref(),
+        extranonce,
+        job.coinbase_tx_suffix.inner_as_ref(),
+    ]
+    .concat();
+    let coinbase_id = Transaction::deserialize(&coinbase)
+        .map_err(|_| Error::InvalidCoinbase)?
+        .txid()
+        .into_inner();
+    let merkle_root = merkle_root_from_path_(coinbase_id, &job.merkle_path.to_vec());
+    let header = BlockHeader {
+        version: share.get_version() as i32,
+        prev_blockhash: prev_hash,
+        merkle_root: TxMerkleNode::from_inner(merkle_root),
+        time: share.get_n_time(),
+        bits: nbits,
+        nonce: share.get_nonce(),
+    };
+    header
+        .validate_pow(&header.target())
+        .map_err(|_| Error::InvalidSolution(template_id))?;
+    Ok(SubmitSolution {
+        template_id,
+        version: share.get_version(),
+        header_timestamp: share.get_n_time(),
+        header_nonce: share.get_nonce(),
+        coinbase_tx: coinbase.try_into()?,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     #[cfg(feature = "serde")]
@@ -1075,4 +1262,243 @@ mod tests {
         // m.super_safe_lock(|i| *i = (*i).checked_add(1).unwrap()); // will not compile
         m.super_safe_lock(|i| *i = (*i).checked_add(1).unwrap_or_default()); // compiles
     }
+
+    #[test]
+    fn test_safe_lock_is_not_poisoned() {
+        use std::sync::Arc;
+
+        let m = Arc::new(super::Mutex::new(1u32));
+        let m_ = m.clone();
+        let panicked = std::thread::spawn(move || {
+            m_.super_safe_lock(|i| {
+                *i += 1;
+                panic!("panic while holding the lock");
+            })
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert_eq!(m.safe_lock(|i| *i).unwrap(), 2);
+        assert_eq!(m.super_safe_lock(|i| *i), 2);
+    }
+
+    #[test]
+    fn test_coinbase_output_data_size() {
+        use super::*;
+        use binary_sv2::Seq0255;
+        use std::convert::TryInto;
+
+        // p2wpkh output: value (8) + script len (1) + script (22)
+        let output = TxOut {
+            value: 0,
+            script_pubkey: Script::from(vec![0; 22]),
+        };
+        let outputs = vec![output.clone(), output];
+        let size = coinbase_output_data_size(&outputs).unwrap();
+        assert_eq!(size.coinbase_output_max_additional_size, 62);
+        assert!(check_coinbase_outputs_size(&outputs, 62).is_ok());
+        assert!(check_coinbase_outputs_size(&outputs, 61).is_err());
+
+        let mut template = NewTemplate {
+            template_id: 1,
+            future_template: false,
+            version: 0x2000_0000,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![3, 1, 0, 0].try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: 5_000_000_000,
+            coinbase_tx_outputs_count: 1,
+            coinbase_tx_outputs: vec![0; 43].try_into().unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+        };
+        assert!(check_template_coinbase_space(&template, 62).is_ok());
+        template.coinbase_tx_outputs = vec![0; 65_400].try_into().unwrap();
+        assert!(matches!(
+            check_template_coinbase_space(&template, 62),
+            Err(Error::TemplateCoinbaseTooBig(1, _))
+        ));
+    }
+
+    #[test]
+    fn test_short_txid_vectors() {
+        use super::*;
+        use bitcoin::hashes::Hash;
+        use core::hash::Hasher;
+
+        // Reference vector of the SipHash paper (appendix A): key 00..0f, message 00..0e
+        let mut hasher = SipHasher24::new_with_keys(0x0706050403020100, 0x0f0e0d0c0b0a0908);
+        hasher.write(&(0..15).collect::<Vec<u8>>());
+        assert_eq!(hasher.finish(), 0xa129ca6149be45e5);
+
+        // Computed following BIP152 with a standalone SipHash-2-4 checked against the reference
+        // vector above: k0 and k1 are the first two little-endian u64 of
+        // SHA256(tx_short_hash_nonce as u64 LE), the short id is the 6 least significant bytes
+        // of SipHash-2-4(k0, k1, txid) in little-endian order.
+        // (tx_short_hash_nonce, txid, k0, k1, short id)
+        type Vector = (u64, [u8; 32], u64, u64, [u8; 6]);
+        let vectors: [Vector; 4] = [
+            (
+                0,
+                [0; 32],
+                0x7a0b81a1f57055af,
+                0x0f660ac74baf8cf7,
+                [0x3e, 0x9b, 0x3a, 0x50, 0x05, 0x1f],
+            ),
+            (
+                42,
+                [
+                    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21,
+                    22, 23, 24, 25, 26, 27, 28, 29, 30, 31,
+                ],
+                0xc6f218bc089104ed,
+                0x0b8542ead0e86943,
+                [0xc8, 0xb8, 0xb0, 0x0e, 0xcb, 0x91],
+            ),
+            (
+                0xdeadbeef,
+                [0xff; 32],
+                0x6e6c936178c3d4ee,
+                0x481b5c199dab91bb,
+                [0x15, 0xba, 0x05, 0xca, 0x92, 0xb9],
+            ),
+            (
+                // txid of the genesis coinbase in internal byte order
+                1,
+                [
+                    0x3b, 0xa3, 0xed, 0xfd, 0x7a, 0x7b, 0x12, 0xb2, 0x7a, 0xc7, 0x2c, 0x3e, 0x67,
+                    0x76, 0x8f, 0x61, 0x7f, 0xc8, 0x1b, 0xc3, 0x88, 0x8a, 0x51, 0x32, 0x3a, 0x9f,
+                    0xb8, 0xaa, 0x4b, 0x1e, 0x5e, 0x4a,
+                ],
+                0xa63f41d436a19f7c,
+                0x8d99b683e8373617,
+                [0x62, 0xad, 0x52, 0x2c, 0x44, 0xe9],
+            ),
+        ];
+        for (nonce, txid, k0, k1, short_id) in vectors {
+            let txid = bitcoin::Txid::from_inner(txid);
+            assert_eq!(short_txid_keys(nonce), (k0, k1));
+            assert_eq!(short_txid(&txid, k0, k1).to_vec(), short_id.to_vec());
+            assert_eq!(get_short_hash(txid, nonce).to_vec(), short_id.to_vec());
+            let map = short_txid_map([txid].iter(), nonce).unwrap();
+            assert_eq!(map.get(&short_id), Some(&txid));
+        }
+
+        let txid = bitcoin::Txid::from_inner([1; 32]);
+        assert!(matches!(
+            short_txid_map([txid, txid].iter(), 0),
+            Err(Error::ShortTxIdCollision(_))
+        ));
+    }
+
+    #[test]
+    fn test_block_from_template_solution() {
+        use super::*;
+        use bitcoin::{blockdata::constants::genesis_block, consensus::encode::serialize, Network};
+        use std::convert::TryInto;
+
+        let genesis = genesis_block(Network::Bitcoin);
+        let coinbase = serialize(&genesis.txdata[0]);
+        let mut solution = SubmitSolution {
+            template_id: 1,
+            version: genesis.header.version as u32,
+            header_timestamp: genesis.header.time,
+            header_nonce: genesis.header.nonce,
+            coinbase_tx: coinbase.clone().try_into().unwrap(),
+        };
+        let prev_hash: U256 = [0; 32].into();
+        let block =
+            block_from_template_solution(&solution, prev_hash.clone(), genesis.header.bits, &[])
+                .unwrap();
+        assert_eq!(block, genesis);
+
+        let transactions: Vec<B016M> = vec![coinbase.try_into().unwrap()];
+        let block = block_from_template_solution(
+            &solution,
+            prev_hash.clone(),
+            genesis.header.bits,
+            &transactions,
+        )
+        .unwrap();
+        assert_eq!(block.txdata.len(), 2);
+        assert!(block.check_merkle_root());
+
+        solution.coinbase_tx = vec![0; 10].try_into().unwrap();
+        assert!(matches!(
+            block_from_template_solution(&solution, prev_hash, genesis.header.bits, &[]),
+            Err(Error::InvalidCoinbase)
+        ));
+    }
+
+    #[test]
+    fn test_submit_solution_from_share() {
+        use super::*;
+        use binary_sv2::Sv2Option;
+        use bitcoin::{blockdata::constants::genesis_block, consensus::encode::serialize, Network};
+        use mining_sv2::SubmitSharesExtended;
+        use std::convert::TryInto;
+
+        // The genesis block mined as a share with an extranonce in its coinbase script
+        let genesis = genesis_block(Network::Bitcoin);
+        let coinbase = serialize(&genesis.txdata[0]);
+        let job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 1,
+            min_ntime: Sv2Option::new(None),
+            version: genesis.header.version as u32,
+            version_rolling_allowed: false,
+            merkle_path: vec![].try_into().unwrap(),
+            coinbase_tx_prefix: coinbase[..50].to_vec().try_into().unwrap(),
+            coinbase_tx_suffix: coinbase[58..].to_vec().try_into().unwrap(),
+        };
+        let mut share = SubmitSharesExtended {
+            channel_id: 1,
+            sequence_number: 0,
+            job_id: 1,
+            nonce: genesis.header.nonce,
+            ntime: genesis.header.time,
+            version: genesis.header.version as u32,
+            extranonce: coinbase[54..58].to_vec().try_into().unwrap(),
+        };
+        let prev_hash = genesis.header.prev_blockhash;
+        let bits = genesis.header.bits;
+
+        let solution = submit_solution_from_share(
+            7,
+            &job,
+            &Share::Extended(share.clone()),
+            &coinbase[50..58],
+            prev_hash,
+            bits,
+        )
+        .unwrap();
+        assert_eq!(solution.template_id, 7);
+        assert_eq!(solution.coinbase_tx.to_vec(), coinbase);
+        let block = block_from_template_solution(&solution, [0; 32].into(), bits, &[]).unwrap();
+        assert_eq!(block, genesis);
+
+        // A wrong extranonce changes the merkle root, the header does not meet the target anymore
+        assert!(matches!(
+            submit_solution_from_share(
+                7,
+                &job,
+                &Share::Extended(share.clone()),
+                &[0; 8],
+                prev_hash,
+                bits
+            ),
+            Err(Error::InvalidSolution(7))
+        ));
+        share.nonce += 1;
+        assert!(matches!(
+            submit_solution_from_share(
+                7,
+                &job,
+                &Share::Extended(share),
+                &coinbase[50..58],
+                prev_hash,
+                bits
+            ),
+            Err(Error::InvalidSolution(7))
+        ));
+    }
 }
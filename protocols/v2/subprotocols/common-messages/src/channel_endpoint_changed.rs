@@ -6,6 +6,9 @@ use binary_sv2::{Deserialize, Serialize};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{CHANNEL_BIT_CHANNEL_ENDPOINT_CHANGED, MESSAGE_TYPE_CHANNEL_ENDPOINT_CHANGED};
+
 /// ## ChannelEndpointChanged (Server -> Client)
 /// When a channel’s upstream or downstream endpoint changes and that channel had previously
 /// sent messages with [channel_msg] bitset of unknown extension_type, the intermediate proxy
@@ -18,6 +21,11 @@ pub struct ChannelEndpointChanged {
     /// The channel which has changed endpoint.
     pub channel_id: u32,
 }
+
+impl MessageType for ChannelEndpointChanged {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_CHANNEL_ENDPOINT_CHANGED;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_CHANNEL_ENDPOINT_CHANGED;
+}
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
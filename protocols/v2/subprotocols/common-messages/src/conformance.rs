@@ -0,0 +1,55 @@
+//! Encodings of the common messages that the serde and the no-serde backends have to agree on, the
+//! bytes are in `test-vectors/common-messages.json`. Run the tests with and without `with_serde`.
+use super::*;
+use binary_sv2::{assert_test_vector, conformance::bytes};
+
+const VECTORS: &str = include_str!("../../test-vectors/common-messages.json");
+
+#[test]
+fn channel_endpoint_changed() {
+    assert_test_vector!(
+        VECTORS,
+        ChannelEndpointChanged = ChannelEndpointChanged { channel_id: 1 }
+    );
+}
+
+#[test]
+fn setup_connection() {
+    assert_test_vector!(
+        VECTORS,
+        SetupConnection = SetupConnection {
+            protocol: Protocol::JobDeclarationProtocol,
+            min_version: 2,
+            max_version: 2,
+            flags: 3,
+            endpoint_host: bytes(4, b'h'),
+            endpoint_port: 3333,
+            vendor: bytes(1, b'v'),
+            hardware_version: bytes(1, b'w'),
+            firmware: bytes(1, b'f'),
+            device_id: bytes(1, b'd'),
+        }
+    );
+}
+
+#[test]
+fn setup_connection_success() {
+    assert_test_vector!(
+        VECTORS,
+        SetupConnectionSuccess = SetupConnectionSuccess {
+            used_version: 2,
+            flags: 3,
+        }
+    );
+}
+
+#[test]
+fn setup_connection_error() {
+    assert_test_vector!(
+        VECTORS,
+        SetupConnectionError = SetupConnectionError {
+            flags: 3,
+            error_code: bytes(3, b'e'),
+        }
+    );
+}
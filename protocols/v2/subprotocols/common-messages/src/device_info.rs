@@ -0,0 +1,189 @@
+use alloc::string::{String, ToString};
+use binary_sv2::Str0255;
+use core::{
+    convert::TryInto,
+    fmt::{self, Display, Formatter},
+};
+
+/// Errors returned by [`DeviceInfoBuilder::build`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceInfoError {
+    /// A field is longer than 255 bytes (name of the field, len of the field)
+    FieldTooLong(&'static str, usize),
+}
+
+impl Display for DeviceInfoError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            DeviceInfoError::FieldTooLong(field, l) => {
+                write!(
+                    f,
+                    "{} must be at most 255 bytes, received {} bytes",
+                    field, l
+                )
+            }
+        }
+    }
+}
+
+/// The device information fields of a `SetupConnection`. The default value has all the fields
+/// empty, what a role that is not a mining device sends.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceInfo {
+    vendor: String,
+    hardware_version: String,
+    firmware: String,
+    device_id: String,
+}
+
+impl DeviceInfo {
+    pub fn vendor(&self) -> &str {
+        &self.vendor
+    }
+
+    pub fn hardware_version(&self) -> &str {
+        &self.hardware_version
+    }
+
+    pub fn firmware(&self) -> &str {
+        &self.firmware
+    }
+
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    /// Returns the `vendor`, `hardware_version`, `firmware` and `device_id` fields of a
+    /// `SetupConnection`
+    pub fn into_setup_connection_fields(
+        self,
+    ) -> (
+        Str0255<'static>,
+        Str0255<'static>,
+        Str0255<'static>,
+        Str0255<'static>,
+    ) {
+        // Infallible unwraps the len of the fields has been checked by DeviceInfoBuilder::build
+        (
+            self.vendor.into_bytes().try_into().unwrap(),
+            self.hardware_version.into_bytes().try_into().unwrap(),
+            self.firmware.into_bytes().try_into().unwrap(),
+            self.device_id.into_bytes().try_into().unwrap(),
+        )
+    }
+}
+
+/// Builder for [`DeviceInfo`] that checks that every field fits in a `STR0_255`. The spec asks
+/// mining devices to always set the vendor, and to set the device id only when they are
+/// configured to provide telemetry data.
+///
+/// # Examples
+///
+/// ```
+/// use common_messages_sv2::*;
+/// let device_info = DeviceInfoBuilder::new("Bitmain")
+///     .hardware_version("S19")
+///     .firmware("1.0.0")
+///     .build()
+///     .unwrap();
+/// assert_eq!(device_info.device_id(), "");
+/// ```
+#[derive(Debug, Clone)]
+pub struct DeviceInfoBuilder<'a> {
+    vendor: &'a str,
+    hardware_version: &'a str,
+    firmware: &'a str,
+    device_id: &'a str,
+}
+
+impl<'a> DeviceInfoBuilder<'a> {
+    pub fn new(vendor: &'a str) -> Self {
+        Self {
+            vendor,
+            hardware_version: "",
+            firmware: "",
+            device_id: "",
+        }
+    }
+
+    pub fn hardware_version(mut self, hardware_version: &'a str) -> Self {
+        self.hardware_version = hardware_version;
+        self
+    }
+
+    pub fn firmware(mut self, firmware: &'a str) -> Self {
+        self.firmware = firmware;
+        self
+    }
+
+    pub fn device_id(mut self, device_id: &'a str) -> Self {
+        self.device_id = device_id;
+        self
+    }
+
+    pub fn build(self) -> Result<DeviceInfo, DeviceInfoError> {
+        for (field, value) in [
+            ("vendor", self.vendor),
+            ("hardware_version", self.hardware_version),
+            ("firmware", self.firmware),
+            ("device_id", self.device_id),
+        ] {
+            if value.len() > 255 {
+                return Err(DeviceInfoError::FieldTooLong(field, value.len()));
+            }
+        }
+        Ok(DeviceInfo {
+            vendor: self.vendor.to_string(),
+            hardware_version: self.hardware_version.to_string(),
+            firmware: self.firmware.to_string(),
+            device_id: self.device_id.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Endpoint, Protocol, SetupConnection};
+
+    fn str0255(s: &str) -> Str0255<'static> {
+        s.to_string().into_bytes().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_device_info_builder() {
+        let device_id = "d".repeat(256);
+        assert_eq!(
+            DeviceInfoBuilder::new("vendor")
+                .device_id(&device_id)
+                .build(),
+            Err(DeviceInfoError::FieldTooLong("device_id", 256))
+        );
+
+        let device_info = DeviceInfoBuilder::new("vendor")
+            .hardware_version("hw")
+            .firmware("fw")
+            .device_id(&device_id[..255])
+            .build()
+            .unwrap();
+        assert_eq!(
+            SetupConnection::for_protocol(
+                Protocol::MiningProtocol,
+                device_info,
+                Endpoint::new("127.0.0.1", 3333).unwrap(),
+            ),
+            SetupConnection {
+                protocol: Protocol::MiningProtocol,
+                min_version: 2,
+                max_version: 2,
+                flags: 0,
+                endpoint_host: str0255("127.0.0.1"),
+                endpoint_port: 3333,
+                vendor: str0255("vendor"),
+                hardware_version: str0255("hw"),
+                firmware: str0255("fw"),
+                device_id: str0255(&device_id[..255]),
+            }
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use crate::ChannelEndpointChanged;
+use alloc::{string::String, vec::Vec};
+use binary_sv2::Str0255;
+use core::{
+    convert::TryInto,
+    fmt::{self, Display, Formatter},
+};
+
+/// Errors returned when an endpoint is not valid for a `Reconnect`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndpointError {
+    /// The host is longer than 255 bytes (len of the host)
+    HostTooLong(usize),
+    /// The host contains something that is not a printable ASCII char
+    InvalidHost,
+}
+
+impl Display for EndpointError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            EndpointError::HostTooLong(l) => {
+                write!(f, "Host must be at most 255 bytes, received {} bytes", l)
+            }
+            EndpointError::InvalidHost => write!(f, "Host must be printable ASCII"),
+        }
+    }
+}
+
+/// A validated (host, port) couple where clients are asked to reconnect. As in `Reconnect`, an
+/// empty host means the present host and a 0 port means the present port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    host: String,
+    port: u16,
+}
+
+impl Endpoint {
+    pub fn new(host: &str, port: u16) -> Result<Self, EndpointError> {
+        if host.len() > 255 {
+            return Err(EndpointError::HostTooLong(host.len()));
+        }
+        if !host.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(EndpointError::InvalidHost);
+        }
+        Ok(Self {
+            host: host.into(),
+            port,
+        })
+    }
+
+    /// Build an endpoint from the raw `new_host` and `new_port` fields of a `Reconnect`
+    pub fn from_raw(host: &[u8], port: u16) -> Result<Self, EndpointError> {
+        let host = core::str::from_utf8(host).map_err(|_| EndpointError::InvalidHost)?;
+        Self::new(host, port)
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// True when the client is asked to reconnect to its present host
+    pub fn is_same_host(&self) -> bool {
+        self.host.is_empty()
+    }
+
+    /// True when the client is asked to reconnect to its present port
+    pub fn is_same_port(&self) -> bool {
+        self.port == 0
+    }
+
+    /// Returns the `new_host` and `new_port` fields of a `Reconnect` message
+    pub fn into_reconnect_fields(self) -> (Str0255<'static>, u16) {
+        // Infallible unwrap host len has been checked in Endpoint::new
+        let host: Str0255<'static> = self.host.into_bytes().try_into().unwrap();
+        (host, self.port)
+    }
+}
+
+impl ChannelEndpointChanged {
+    pub fn new(channel_id: u32) -> Self {
+        Self { channel_id }
+    }
+}
+
+/// Used by servers that want to move their clients to another endpoint. For each open channel a
+/// `ChannelEndpointChanged` must be sent, then the connection is redirected with a `Reconnect`
+/// built from [`Endpoint::into_reconnect_fields`].
+#[derive(Debug, Clone)]
+pub struct EndpointMigration {
+    endpoint: Endpoint,
+    channels: Vec<u32>,
+}
+
+impl EndpointMigration {
+    pub fn new(endpoint: Endpoint, channels: Vec<u32>) -> Self {
+        Self { endpoint, channels }
+    }
+
+    /// Messages to be sent downstream before the `Reconnect`
+    pub fn channel_endpoint_changed_messages(&self) -> Vec<ChannelEndpointChanged> {
+        self.channels
+            .iter()
+            .map(|id| ChannelEndpointChanged::new(*id))
+            .collect()
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+}
+
+/// Who opened a channel tracked by [`MigrationTracker`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelOwner {
+    /// Channel opened by this role
+    Local,
+    /// Channel opened by a downstream and relayed upstream by this role (proxy)
+    Downstream,
+}
+
+/// What a role must do after a `ChannelEndpointChanged` or `Reconnect` is received
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationAction {
+    /// The channel belongs to a downstream, relay the message as it is
+    Relay(ChannelEndpointChanged),
+    /// Share submission for the channel is paused until the role reconnects
+    PauseChannel(u32),
+    /// Connect to the endpoint, redo the handshake and reopen the paused channels
+    Reconnect(Endpoint),
+    /// The message is for a channel that we do not know
+    Ignore,
+}
+
+/// Client side of an endpoint migration. Keeps track of the open channels, of the ones for which
+/// share submission is paused and tells the role what to do when upstream migrates it.
+#[derive(Debug, Clone, Default)]
+pub struct MigrationTracker {
+    channels: Vec<(u32, ChannelOwner)>,
+    paused: Vec<u32>,
+}
+
+impl MigrationTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_channel(&mut self, channel_id: u32, owner: ChannelOwner) {
+        self.remove_channel(channel_id);
+        self.channels.push((channel_id, owner));
+    }
+
+    pub fn remove_channel(&mut self, channel_id: u32) {
+        self.channels.retain(|(id, _)| *id != channel_id);
+        self.paused.retain(|id| *id != channel_id);
+    }
+
+    pub fn on_channel_endpoint_changed(&mut self, m: ChannelEndpointChanged) -> MigrationAction {
+        match self.channels.iter().find(|(id, _)| *id == m.channel_id) {
+            Some((_, ChannelOwner::Downstream)) => MigrationAction::Relay(m),
+            Some((id, ChannelOwner::Local)) => {
+                let id = *id;
+                if !self.paused.contains(&id) {
+                    self.paused.push(id);
+                }
+                MigrationAction::PauseChannel(id)
+            }
+            None => MigrationAction::Ignore,
+        }
+    }
+
+    /// Called with the fields of a received `Reconnect`. Every local channel is paused until
+    /// [`MigrationTracker::on_reconnected`] is called.
+    pub fn on_reconnect(
+        &mut self,
+        new_host: &[u8],
+        new_port: u16,
+    ) -> Result<MigrationAction, EndpointError> {
+        let endpoint = Endpoint::from_raw(new_host, new_port)?;
+        for (id, owner) in &self.channels {
+            if *owner == ChannelOwner::Local && !self.paused.contains(id) {
+                self.paused.push(*id);
+            }
+        }
+        Ok(MigrationAction::Reconnect(endpoint))
+    }
+
+    /// Called when the role is connected to the new endpoint. Channels are not valid anymore on the
+    /// new connection so all of them are dropped and the ids that need to be reopened are
+    /// returned.
+    pub fn on_reconnected(&mut self) -> Vec<u32> {
+        let to_reopen = self
+            .channels
+            .iter()
+            .filter(|(_, owner)| *owner == ChannelOwner::Local)
+            .map(|(id, _)| *id)
+            .collect();
+        self.channels.clear();
+        self.paused.clear();
+        to_reopen
+    }
+
+    /// Returns false if shares for the channel must not be sent upstream
+    pub fn can_submit_shares(&self, channel_id: u32) -> bool {
+        !self.paused.contains(&channel_id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_endpoint_validation() {
+        assert!(Endpoint::new("pool.example.com", 34254).is_ok());
+        assert!(Endpoint::new("", 0).unwrap().is_same_host());
+        assert_eq!(
+            Endpoint::new("pool example", 1),
+            Err(EndpointError::InvalidHost)
+        );
+        let long = "a".repeat(256);
+        assert_eq!(
+            Endpoint::new(&long, 1),
+            Err(EndpointError::HostTooLong(256))
+        );
+        let (host, port) = Endpoint::new("127.0.0.1", 3333)
+            .unwrap()
+            .into_reconnect_fields();
+        assert_eq!(host.to_vec(), b"127.0.0.1".to_vec());
+        assert_eq!(port, 3333);
+    }
+
+    #[test]
+    fn test_server_migration_messages() {
+        let endpoint = Endpoint::new("127.0.0.1", 3333).unwrap();
+        let migration = EndpointMigration::new(endpoint, vec![1, 2]);
+        assert_eq!(
+            migration.channel_endpoint_changed_messages(),
+            vec![
+                ChannelEndpointChanged::new(1),
+                ChannelEndpointChanged::new(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_proxy_pass_through() {
+        let mut tracker = MigrationTracker::new();
+        tracker.add_channel(1, ChannelOwner::Local);
+        tracker.add_channel(2, ChannelOwner::Downstream);
+
+        assert_eq!(
+            tracker.on_channel_endpoint_changed(ChannelEndpointChanged::new(2)),
+            MigrationAction::Relay(ChannelEndpointChanged::new(2))
+        );
+        assert!(tracker.can_submit_shares(2));
+
+        assert_eq!(
+            tracker.on_channel_endpoint_changed(ChannelEndpointChanged::new(1)),
+            MigrationAction::PauseChannel(1)
+        );
+        assert!(!tracker.can_submit_shares(1));
+
+        assert_eq!(
+            tracker.on_channel_endpoint_changed(ChannelEndpointChanged::new(3)),
+            MigrationAction::Ignore
+        );
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let mut tracker = MigrationTracker::new();
+        tracker.add_channel(1, ChannelOwner::Local);
+        tracker.add_channel(2, ChannelOwner::Downstream);
+
+        assert!(tracker.on_reconnect(b"bad host", 0).is_err());
+        assert!(tracker.can_submit_shares(1));
+
+        let action = tracker.on_reconnect(b"", 4444).unwrap();
+        assert_eq!(
+            action,
+            MigrationAction::Reconnect(Endpoint::new("", 4444).unwrap())
+        );
+        assert!(!tracker.can_submit_shares(1));
+        assert!(tracker.can_submit_shares(2));
+
+        assert_eq!(tracker.on_reconnected(), vec![1]);
+        assert!(tracker.can_submit_shares(1));
+    }
+}
@@ -4,6 +4,10 @@
 //! The following protocol messages are common across all of the sv2 (sub)protocols.
 extern crate alloc;
 mod channel_endpoint_changed;
+#[cfg(test)]
+mod conformance;
+mod device_info;
+mod endpoint_migration;
 mod setup_connection;
 
 #[cfg(feature = "prop_test")]
@@ -13,14 +17,37 @@ use core::convert::TryInto;
 #[cfg(feature = "prop_test")]
 use quickcheck::{Arbitrary, Gen};
 
-pub use channel_endpoint_changed::ChannelEndpointChanged;
+pub use channel_endpoint_changed::{
+    ChannelEndpointChanged, CHANNEL_BIT_CHANNEL_ENDPOINT_CHANGED,
+    MESSAGE_TYPE_CHANNEL_ENDPOINT_CHANGED,
+};
+pub use device_info::{DeviceInfo, DeviceInfoBuilder, DeviceInfoError};
+pub use endpoint_migration::{
+    ChannelOwner, Endpoint, EndpointError, EndpointMigration, MigrationAction, MigrationTracker,
+};
 pub use setup_connection::{
-    has_requires_std_job, has_version_rolling, has_work_selection, Protocol, SetupConnection,
-    SetupConnectionError, SetupConnectionSuccess,
+    has_requires_std_job, has_version_rolling, has_work_selection, negotiate, Negotiated, Protocol,
+    SetupConnection, SetupConnectionError, SetupConnectionSuccess, CHANNEL_BIT_SETUP_CONNECTION,
+    CHANNEL_BIT_SETUP_CONNECTION_ERROR, CHANNEL_BIT_SETUP_CONNECTION_SUCCESS,
+    MESSAGE_TYPE_SETUP_CONNECTION, MESSAGE_TYPE_SETUP_CONNECTION_ERROR,
+    MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS,
 };
 #[cfg(not(feature = "with_serde"))]
 pub use setup_connection::{CSetupConnection, CSetupConnectionError};
 
+pub use const_sv2::{message_type_of, MessageType, EXTENSION_TYPE_NO_EXTENSION};
+
+/// Name of the common message with type `message_type`, for logging
+pub fn message_type_name(message_type: u8) -> Option<&'static str> {
+    match message_type {
+        MESSAGE_TYPE_CHANNEL_ENDPOINT_CHANGED => Some("ChannelEndpointChanged"),
+        MESSAGE_TYPE_SETUP_CONNECTION => Some("SetupConnection"),
+        MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS => Some("SetupConnectionSuccess"),
+        MESSAGE_TYPE_SETUP_CONNECTION_ERROR => Some("SetupConnectionError"),
+        _ => None,
+    }
+}
+
 #[cfg(not(feature = "with_serde"))]
 #[no_mangle]
 pub extern "C" fn _c_export_channel_endpoint_changed(_a: ChannelEndpointChanged) {}
@@ -107,3 +134,43 @@ impl SetupConnectionSuccess {
         }
     }
 }
+
+#[cfg(feature = "prop_test")]
+macro_rules! impl_arbitrary_from_gen {
+    ($($message:ty),*) => {$(
+        impl Arbitrary for $message {
+            fn arbitrary(g: &mut Gen) -> Self {
+                Self::from_gen(g)
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "prop_test")]
+impl_arbitrary_from_gen!(
+    ChannelEndpointChanged,
+    SetupConnection<'static>,
+    SetupConnectionSuccess,
+    SetupConnectionError<'static>
+);
+
+#[cfg(all(test, feature = "prop_test", not(feature = "with_serde")))]
+mod round_trip {
+    use super::*;
+
+    macro_rules! round_trip_tests {
+        ($($name:ident: $message:ty,)*) => {$(
+            #[test]
+            fn $name() {
+                quickcheck::quickcheck(binary_sv2::round_trip::<$message> as fn($message) -> bool);
+            }
+        )*};
+    }
+
+    round_trip_tests! {
+        channel_endpoint_changed: ChannelEndpointChanged,
+        setup_connection: SetupConnection<'static>,
+        setup_connection_success: SetupConnectionSuccess,
+        setup_connection_error: SetupConnectionError<'static>,
+    }
+}
@@ -1,3 +1,5 @@
+use crate::{DeviceInfo, Endpoint};
+use alloc::string::ToString;
 #[cfg(not(feature = "with_serde"))]
 use alloc::vec::Vec;
 #[cfg(not(feature = "with_serde"))]
@@ -10,12 +12,17 @@ use const_sv2::{
     SV2_JOB_DECLARATION_PROTOCOL_DISCRIMINANT, SV2_MINING_PROTOCOL_DISCRIMINANT,
     SV2_TEMPLATE_DISTR_PROTOCOL_DISCRIMINANT,
 };
-use core::convert::TryFrom;
-#[cfg(not(feature = "with_serde"))]
-use core::convert::TryInto;
+use core::convert::{TryFrom, TryInto};
 #[cfg(feature = "with_serde")]
 use serde_repr::*;
 
+use const_sv2::MessageType;
+pub use const_sv2::{
+    CHANNEL_BIT_SETUP_CONNECTION, CHANNEL_BIT_SETUP_CONNECTION_ERROR,
+    CHANNEL_BIT_SETUP_CONNECTION_SUCCESS, MESSAGE_TYPE_SETUP_CONNECTION,
+    MESSAGE_TYPE_SETUP_CONNECTION_ERROR, MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS,
+};
+
 /// ## SetupConnection (Client -> Server)
 /// Initiates the connection. This MUST be the first message sent by the client on the newly
 /// opened connection. Server MUST respond with either a [`SetupConnectionSuccess`] or
@@ -51,6 +58,11 @@ pub struct SetupConnection<'decoder> {
     pub device_id: Str0255<'decoder>,
 }
 
+impl<'decoder> MessageType for SetupConnection<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SETUP_CONNECTION;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SETUP_CONNECTION;
+}
+
 impl<'decoder> SetupConnection<'decoder> {
     pub fn set_requires_standard_job(&mut self) {
         self.flags |= 0b_0000_0000_0000_0000_0000_0000_0000_0001;
@@ -142,6 +154,99 @@ impl<'decoder> SetupConnection<'decoder> {
     }
 }
 
+impl SetupConnection<'static> {
+    /// A `SetupConnection` for `protocol` with version 2 and no flags set, the flags are set by
+    /// the caller.
+    pub fn for_protocol(protocol: Protocol, device_info: DeviceInfo, endpoint: Endpoint) -> Self {
+        let (endpoint_host, endpoint_port) = endpoint.into_reconnect_fields();
+        let (vendor, hardware_version, firmware, device_id) =
+            device_info.into_setup_connection_fields();
+        Self {
+            protocol,
+            min_version: 2,
+            max_version: 2,
+            flags: 0,
+            endpoint_host,
+            endpoint_port,
+            vendor,
+            hardware_version,
+            firmware,
+            device_id,
+        }
+    }
+}
+
+/// Result of a successful [`negotiate`]: what the server is going to use for the rest of the
+/// connection life.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Negotiated {
+    pub protocol: Protocol,
+    /// Biggest version supported by both sides.
+    pub used_version: u16,
+    /// Flags set by the client in `SetupConnection`. They are all supported by the server, as
+    /// [`negotiate`] rejects the connection otherwise.
+    pub flags: u32,
+}
+
+impl Negotiated {
+    /// Only meaningful for [`Protocol::MiningProtocol`], where bit 0 is REQUIRES_STANDARD_JOBS.
+    pub fn requires_standard_jobs(&self) -> bool {
+        self.protocol == Protocol::MiningProtocol && has_requires_std_job(self.flags)
+    }
+
+    /// `server_flags` are the features of the server, their bits do not have the same meaning as
+    /// the client flags (e.g. REQUIRES_FIXED_VERSION and REQUIRES_EXTENDED_CHANNELS for the
+    /// Mining Protocol).
+    pub fn success(&self, server_flags: u32) -> SetupConnectionSuccess {
+        SetupConnectionSuccess {
+            used_version: self.used_version,
+            flags: server_flags,
+        }
+    }
+}
+
+/// Encodes the rules that a server MUST follow when a [`SetupConnection`] is received.
+///
+/// The version used is the biggest one in both the client and the server ranges, if the ranges do
+/// not overlap a `protocol-version-mismatch` error is returned. Every flag that the client sets
+/// and that is not in `our_flags` is unsupported: the server MUST reply with an
+/// `unsupported-feature-flags` error listing all of them. The only exception is the Template
+/// Distribution Protocol that do not define any flag, so client flags are ignored.
+pub fn negotiate(
+    setup: &SetupConnection,
+    our_min: u16,
+    our_max: u16,
+    our_flags: u32,
+) -> Result<Negotiated, SetupConnectionError<'static>> {
+    let used_version = match setup.get_version(our_min, our_max) {
+        Some(version) if our_min <= our_max => version,
+        _ => {
+            return Err(SetupConnectionError::new(
+                0,
+                SetupConnectionError::protocol_version_mismatch_error_code(),
+            ))
+        }
+    };
+    let flags = match setup.protocol {
+        Protocol::TemplateDistributionProtocol => 0,
+        Protocol::MiningProtocol | Protocol::JobDeclarationProtocol => {
+            let unsupported = setup.flags & !our_flags;
+            if unsupported != 0 {
+                return Err(SetupConnectionError::new(
+                    unsupported,
+                    SetupConnectionError::unsupported_feature_flags_error_code(),
+                ));
+            }
+            setup.flags
+        }
+    };
+    Ok(Negotiated {
+        protocol: setup.protocol,
+        used_version,
+        flags,
+    })
+}
+
 pub fn has_requires_std_job(flags: u32) -> bool {
     let flags = flags.reverse_bits();
     let flag = flags >> 31;
@@ -252,6 +357,11 @@ pub struct SetupConnectionSuccess {
     pub flags: u32,
 }
 
+impl MessageType for SetupConnectionSuccess {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SETUP_CONNECTION_SUCCESS;
+}
+
 /// ## SetupConnection.Error (Server -> Client)
 /// When protocol version negotiation fails (or there is another reason why the upstream node
 /// cannot setup the connection) the server sends this message with a particular error code prior
@@ -277,6 +387,30 @@ pub struct SetupConnectionError<'decoder> {
     pub error_code: Str0255<'decoder>,
 }
 
+impl<'decoder> MessageType for SetupConnectionError<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SETUP_CONNECTION_ERROR;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SETUP_CONNECTION_ERROR;
+}
+
+impl SetupConnectionError<'static> {
+    pub fn new(flags: u32, error_code: &str) -> Self {
+        Self {
+            flags,
+            // Infallible unwrap error codes are always shorter than 255 bytes
+            error_code: error_code.to_string().into_bytes().try_into().unwrap(),
+        }
+    }
+    pub fn unsupported_feature_flags_error_code() -> &'static str {
+        "unsupported-feature-flags"
+    }
+    pub fn unsupported_protocol_error_code() -> &'static str {
+        "unsupported-protocol"
+    }
+    pub fn protocol_version_mismatch_error_code() -> &'static str {
+        "protocol-version-mismatch"
+    }
+}
+
 #[repr(C)]
 #[cfg(not(feature = "with_serde"))]
 #[derive(Debug, Clone)]
@@ -492,6 +626,43 @@ mod test {
         assert_eq!(setup_conn.get_version(6, 6), None);
     }
 
+    #[test]
+    fn test_negotiate() {
+        let mut setup_conn = create_setup_connection();
+        let negotiated = negotiate(&setup_conn, 2, 2, 0).unwrap();
+        assert_eq!(negotiated.used_version, 2);
+        assert_eq!(negotiated.flags, 0);
+        assert!(!negotiated.requires_standard_jobs());
+
+        let err = negotiate(&setup_conn, 5, 6, 0).unwrap_err();
+        assert_eq!(err.flags, 0);
+        assert_eq!(
+            err.error_code.to_vec(),
+            b"protocol-version-mismatch".to_vec()
+        );
+
+        setup_conn.set_requires_standard_job();
+        setup_conn.flags |= 0b_0100;
+        let err = negotiate(&setup_conn, 2, 2, 0b_0001).unwrap_err();
+        assert_eq!(err.flags, 0b_0100);
+        assert_eq!(
+            err.error_code.to_vec(),
+            b"unsupported-feature-flags".to_vec()
+        );
+
+        let negotiated = negotiate(&setup_conn, 2, 2, 0b_0111).unwrap();
+        assert_eq!(negotiated.flags, 0b_0101);
+        assert!(negotiated.requires_standard_jobs());
+        // REQUIRES_EXTENDED_CHANNELS
+        let success = negotiated.success(0b_0010);
+        assert_eq!(success.used_version, 2);
+        assert_eq!(success.flags, 0b_0010);
+
+        setup_conn.protocol = Protocol::TemplateDistributionProtocol;
+        let negotiated = negotiate(&setup_conn, 2, 2, 0).unwrap();
+        assert_eq!(negotiated.flags, 0);
+    }
+
     // Test SetupConnection::set_requires_std_job
     #[test]
     fn test_set_requires_std_job() {
@@ -6,6 +6,12 @@ use binary_sv2::{Deserialize, Serialize, Str0255, B0255, B064K};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{
+    CHANNEL_BIT_ALLOCATE_MINING_JOB_TOKEN, CHANNEL_BIT_ALLOCATE_MINING_JOB_TOKEN_SUCCESS,
+    MESSAGE_TYPE_ALLOCATE_MINING_JOB_TOKEN, MESSAGE_TYPE_ALLOCATE_MINING_JOB_TOKEN_SUCCESS,
+};
+
 /// ## AllocateMiningJobToken (Client -> Server)
 /// A request to get an identifier for a future-submitted mining job.
 /// Rate limited to a rather slow rate and only available on connections where this has been
@@ -18,6 +24,11 @@ pub struct AllocateMiningJobToken<'decoder> {
     pub request_id: u32,
 }
 
+impl<'decoder> MessageType for AllocateMiningJobToken<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_ALLOCATE_MINING_JOB_TOKEN;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_ALLOCATE_MINING_JOB_TOKEN;
+}
+
 /// ## AllocateMiningJobTokenSuccess (Server -> Clien)
 /// The Server MUST NOT change the value of `coinbase_output_max_additional_size` in
 /// `AllocateMiningJobToken.Success` messages unless required for changes to the pool’
@@ -37,6 +48,11 @@ pub struct AllocateMiningJobTokenSuccess<'decoder> {
     pub async_mining_allowed: bool,
 }
 
+impl<'decoder> MessageType for AllocateMiningJobTokenSuccess<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_ALLOCATE_MINING_JOB_TOKEN_SUCCESS;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_ALLOCATE_MINING_JOB_TOKEN_SUCCESS;
+}
+
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
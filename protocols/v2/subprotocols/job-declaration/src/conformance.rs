@@ -0,0 +1,129 @@
+//! Encodings of the job declaration messages that the serde and the no-serde backends have to
+//! agree on, the bytes are in `test-vectors/job-declaration.json`. Run the tests with and without
+//! `with_serde`.
+use super::*;
+use binary_sv2::{assert_test_vector, conformance::bytes, Seq064K};
+
+const VECTORS: &str = include_str!("../../test-vectors/job-declaration.json");
+
+#[test]
+fn allocate_mining_job_token() {
+    assert_test_vector!(
+        VECTORS,
+        AllocateMiningJobToken = AllocateMiningJobToken {
+            user_identifier: bytes(4, b'u'),
+            request_id: 1,
+        }
+    );
+}
+
+#[test]
+fn allocate_mining_job_token_success() {
+    assert_test_vector!(
+        VECTORS,
+        AllocateMiningJobTokenSuccess = AllocateMiningJobTokenSuccess {
+            request_id: 1,
+            mining_job_token: bytes(2, 0x02),
+            coinbase_output_max_additional_size: 3,
+            coinbase_output: bytes(3, 0x04),
+            async_mining_allowed: true,
+        }
+    );
+}
+
+#[test]
+fn declare_mining_job() {
+    assert_test_vector!(
+        VECTORS,
+        DeclareMiningJob = DeclareMiningJob {
+            request_id: 1,
+            mining_job_token: bytes(2, 0x02),
+            version: 3,
+            coinbase_prefix: bytes(2, 0x04),
+            coinbase_suffix: bytes(2, 0x05),
+            tx_short_hash_nonce: 6,
+            tx_short_hash_list: Seq064K::new(vec![bytes(6, 0x07), bytes(6, 0x08)]).unwrap(),
+            tx_hash_list_hash: [0x09; 32].into(),
+            excess_data: bytes(1, 0x0a),
+        }
+    );
+}
+
+#[test]
+fn declare_mining_job_success() {
+    assert_test_vector!(
+        VECTORS,
+        DeclareMiningJobSuccess = DeclareMiningJobSuccess {
+            request_id: 1,
+            new_mining_job_token: bytes(2, 0x02),
+        }
+    );
+}
+
+#[test]
+fn declare_mining_job_error() {
+    assert_test_vector!(
+        VECTORS,
+        DeclareMiningJobError = DeclareMiningJobError {
+            request_id: 1,
+            error_code: bytes(3, b'e'),
+            error_details: bytes(2, 0x02),
+        }
+    );
+}
+
+#[test]
+fn identify_transactions() {
+    assert_test_vector!(
+        VECTORS,
+        IdentifyTransactions = IdentifyTransactions { request_id: 1 }
+    );
+}
+
+#[test]
+fn identify_transactions_success() {
+    assert_test_vector!(
+        VECTORS,
+        IdentifyTransactionsSuccess = IdentifyTransactionsSuccess {
+            request_id: 1,
+            tx_data_hashes: Seq064K::new(vec![[0x02; 32].into()]).unwrap(),
+        }
+    );
+}
+
+#[test]
+fn provide_missing_transactions() {
+    assert_test_vector!(
+        VECTORS,
+        ProvideMissingTransactions = ProvideMissingTransactions {
+            request_id: 1,
+            unknown_tx_position_list: Seq064K::new(vec![2, 3]).unwrap(),
+        }
+    );
+}
+
+#[test]
+fn provide_missing_transactions_success() {
+    assert_test_vector!(
+        VECTORS,
+        ProvideMissingTransactionsSuccess = ProvideMissingTransactionsSuccess {
+            request_id: 1,
+            transaction_list: Seq064K::new(vec![bytes(2, 0x02), bytes(3, 0x03)]).unwrap(),
+        }
+    );
+}
+
+#[test]
+fn submit_solution_jd() {
+    assert_test_vector!(
+        VECTORS,
+        SubmitSolutionJd = SubmitSolutionJd {
+            extranonce: bytes(4, 0x01),
+            prev_hash: [0x02; 32].into(),
+            ntime: 3,
+            nonce: 4,
+            nbits: 5,
+            version: 6,
+        }
+    );
+}
@@ -6,6 +6,13 @@ use binary_sv2::{Deserialize, Seq064K, Serialize, ShortTxId, Str0255, B0255, B06
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{
+    CHANNEL_BIT_DECLARE_MINING_JOB, CHANNEL_BIT_DECLARE_MINING_JOB_ERROR,
+    CHANNEL_BIT_DECLARE_MINING_JOB_SUCCESS, MESSAGE_TYPE_DECLARE_MINING_JOB,
+    MESSAGE_TYPE_DECLARE_MINING_JOB_ERROR, MESSAGE_TYPE_DECLARE_MINING_JOB_SUCCESS,
+};
+
 /// ## DeclareMiningJob (Client -> Server)
 /// A request sent by the Job Declarator that proposes a selected set of transactions to the
 /// upstream (pool) node.
@@ -29,6 +36,11 @@ pub struct DeclareMiningJob<'decoder> {
     pub excess_data: B064K<'decoder>,
 }
 
+impl<'decoder> MessageType for DeclareMiningJob<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_DECLARE_MINING_JOB;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_DECLARE_MINING_JOB;
+}
+
 /// ## DeclareMiningJobSuccess (Server -> Client)
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 #[repr(C)]
@@ -38,6 +50,11 @@ pub struct DeclareMiningJobSuccess<'decoder> {
     pub new_mining_job_token: B0255<'decoder>,
 }
 
+impl<'decoder> MessageType for DeclareMiningJobSuccess<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_DECLARE_MINING_JOB_SUCCESS;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_DECLARE_MINING_JOB_SUCCESS;
+}
+
 /// ## DeclareMiningJobError (Server -> Client)
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 #[repr(C)]
@@ -49,6 +66,11 @@ pub struct DeclareMiningJobError<'decoder> {
     pub error_details: B064K<'decoder>,
 }
 
+impl<'decoder> MessageType for DeclareMiningJobError<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_DECLARE_MINING_JOB_ERROR;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_DECLARE_MINING_JOB_ERROR;
+}
+
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
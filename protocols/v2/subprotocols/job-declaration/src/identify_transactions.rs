@@ -6,6 +6,12 @@ use binary_sv2::{Deserialize, Seq064K, Serialize, U256};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{
+    CHANNEL_BIT_IDENTIFY_TRANSACTIONS, CHANNEL_BIT_IDENTIFY_TRANSACTIONS_SUCCESS,
+    MESSAGE_TYPE_IDENTIFY_TRANSACTIONS, MESSAGE_TYPE_IDENTIFY_TRANSACTIONS_SUCCESS,
+};
+
 /// TODO: comment
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 #[repr(C)]
@@ -13,6 +19,11 @@ pub struct IdentifyTransactions {
     pub request_id: u32,
 }
 
+impl MessageType for IdentifyTransactions {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_IDENTIFY_TRANSACTIONS;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_IDENTIFY_TRANSACTIONS;
+}
+
 /// TODO: comment
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 #[repr(C)]
@@ -22,6 +33,11 @@ pub struct IdentifyTransactionsSuccess<'decoder> {
     pub tx_data_hashes: Seq064K<'decoder, U256<'decoder>>,
 }
 
+impl<'decoder> MessageType for IdentifyTransactionsSuccess<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_IDENTIFY_TRANSACTIONS_SUCCESS;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_IDENTIFY_TRANSACTIONS_SUCCESS;
+}
+
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
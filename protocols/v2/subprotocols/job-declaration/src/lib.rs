@@ -9,15 +9,126 @@
 
 extern crate alloc;
 mod allocate_mining_job_token;
+#[cfg(test)]
+mod conformance;
 mod declare_mining_job;
 mod identify_transactions;
 mod provide_missing_transactions;
 mod submit_solution;
 
-pub use allocate_mining_job_token::{AllocateMiningJobToken, AllocateMiningJobTokenSuccess};
-pub use declare_mining_job::{DeclareMiningJob, DeclareMiningJobError, DeclareMiningJobSuccess};
-pub use identify_transactions::{IdentifyTransactions, IdentifyTransactionsSuccess};
+pub use allocate_mining_job_token::{
+    AllocateMiningJobToken, AllocateMiningJobTokenSuccess, CHANNEL_BIT_ALLOCATE_MINING_JOB_TOKEN,
+    CHANNEL_BIT_ALLOCATE_MINING_JOB_TOKEN_SUCCESS, MESSAGE_TYPE_ALLOCATE_MINING_JOB_TOKEN,
+    MESSAGE_TYPE_ALLOCATE_MINING_JOB_TOKEN_SUCCESS,
+};
+pub use declare_mining_job::{
+    DeclareMiningJob, DeclareMiningJobError, DeclareMiningJobSuccess,
+    CHANNEL_BIT_DECLARE_MINING_JOB, CHANNEL_BIT_DECLARE_MINING_JOB_ERROR,
+    CHANNEL_BIT_DECLARE_MINING_JOB_SUCCESS, MESSAGE_TYPE_DECLARE_MINING_JOB,
+    MESSAGE_TYPE_DECLARE_MINING_JOB_ERROR, MESSAGE_TYPE_DECLARE_MINING_JOB_SUCCESS,
+};
+pub use identify_transactions::{
+    IdentifyTransactions, IdentifyTransactionsSuccess, CHANNEL_BIT_IDENTIFY_TRANSACTIONS,
+    CHANNEL_BIT_IDENTIFY_TRANSACTIONS_SUCCESS, MESSAGE_TYPE_IDENTIFY_TRANSACTIONS,
+    MESSAGE_TYPE_IDENTIFY_TRANSACTIONS_SUCCESS,
+};
 pub use provide_missing_transactions::{
     ProvideMissingTransactions, ProvideMissingTransactionsSuccess,
+    CHANNEL_BIT_PROVIDE_MISSING_TRANSACTIONS, CHANNEL_BIT_PROVIDE_MISSING_TRANSACTIONS_SUCCESS,
+    MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTIONS, MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTIONS_SUCCESS,
 };
-pub use submit_solution::SubmitSolutionJd;
+pub use submit_solution::{
+    SubmitSolutionJd, CHANNEL_BIT_SUBMIT_SOLUTION_JD, MESSAGE_TYPE_SUBMIT_SOLUTION_JD,
+};
+
+pub use const_sv2::{message_type_of, MessageType, EXTENSION_TYPE_NO_EXTENSION};
+
+/// Name of the job declaration message with type `message_type`, for logging
+pub fn message_type_name(message_type: u8) -> Option<&'static str> {
+    match message_type {
+        MESSAGE_TYPE_ALLOCATE_MINING_JOB_TOKEN => Some("AllocateMiningJobToken"),
+        MESSAGE_TYPE_ALLOCATE_MINING_JOB_TOKEN_SUCCESS => Some("AllocateMiningJobTokenSuccess"),
+        MESSAGE_TYPE_DECLARE_MINING_JOB => Some("DeclareMiningJob"),
+        MESSAGE_TYPE_DECLARE_MINING_JOB_SUCCESS => Some("DeclareMiningJobSuccess"),
+        MESSAGE_TYPE_DECLARE_MINING_JOB_ERROR => Some("DeclareMiningJobError"),
+        MESSAGE_TYPE_IDENTIFY_TRANSACTIONS => Some("IdentifyTransactions"),
+        MESSAGE_TYPE_IDENTIFY_TRANSACTIONS_SUCCESS => Some("IdentifyTransactionsSuccess"),
+        MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTIONS => Some("ProvideMissingTransactions"),
+        MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTIONS_SUCCESS => {
+            Some("ProvideMissingTransactionsSuccess")
+        }
+        MESSAGE_TYPE_SUBMIT_SOLUTION_JD => Some("SubmitSolutionJd"),
+        _ => None,
+    }
+}
+
+/// Implements [`quickcheck::Arbitrary`] for messages whose fields all implement it
+#[cfg(all(feature = "prop_test", not(feature = "with_serde")))]
+macro_rules! impl_arbitrary {
+    ($($message:ident$(<$lt:lifetime>)? { $($field:ident),* $(,)? })*) => {$(
+        impl quickcheck::Arbitrary for $message$(<$lt>)? {
+            fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+                $message {
+                    $($field: quickcheck::Arbitrary::arbitrary(g),)*
+                }
+            }
+        }
+    )*};
+}
+
+#[cfg(all(feature = "prop_test", not(feature = "with_serde")))]
+impl_arbitrary! {
+    AllocateMiningJobToken<'static> { user_identifier, request_id }
+    AllocateMiningJobTokenSuccess<'static> {
+        request_id,
+        mining_job_token,
+        coinbase_output_max_additional_size,
+        coinbase_output,
+        async_mining_allowed,
+    }
+    DeclareMiningJob<'static> {
+        request_id,
+        mining_job_token,
+        version,
+        coinbase_prefix,
+        coinbase_suffix,
+        tx_short_hash_nonce,
+        tx_short_hash_list,
+        tx_hash_list_hash,
+        excess_data,
+    }
+    DeclareMiningJobSuccess<'static> { request_id, new_mining_job_token }
+    DeclareMiningJobError<'static> { request_id, error_code, error_details }
+    IdentifyTransactions { request_id }
+    IdentifyTransactionsSuccess<'static> { request_id, tx_data_hashes }
+    ProvideMissingTransactions<'static> { request_id, unknown_tx_position_list }
+    ProvideMissingTransactionsSuccess<'static> { request_id, transaction_list }
+    SubmitSolutionJd<'static> { extranonce, prev_hash, ntime, nonce, nbits, version }
+}
+
+#[cfg(all(test, feature = "prop_test", not(feature = "with_serde")))]
+mod round_trip {
+    use super::*;
+
+    macro_rules! round_trip_tests {
+        ($($name:ident: $message:ty,)*) => {$(
+            #[test]
+            fn $name() {
+                quickcheck::quickcheck(binary_sv2::round_trip::<$message> as fn($message) -> bool);
+            }
+        )*};
+    }
+
+    round_trip_tests! {
+        allocate_mining_job_token: AllocateMiningJobToken<'static>,
+        allocate_mining_job_token_success: AllocateMiningJobTokenSuccess<'static>,
+        declare_mining_job: DeclareMiningJob<'static>,
+        declare_mining_job_success: DeclareMiningJobSuccess<'static>,
+        declare_mining_job_error: DeclareMiningJobError<'static>,
+        identify_transactions: IdentifyTransactions,
+        identify_transactions_success: IdentifyTransactionsSuccess<'static>,
+        provide_missing_transactions: ProvideMissingTransactions<'static>,
+        provide_missing_transactions_success: ProvideMissingTransactionsSuccess<'static>,
+        submit_solution_jd: SubmitSolutionJd<'static>,
+    }
+}
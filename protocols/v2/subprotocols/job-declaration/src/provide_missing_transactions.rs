@@ -14,6 +14,12 @@ use core::convert::TryInto;
 // position in the original DeclareMiningJob message, 0-indexed not including the coinbase
 // transaction transaction.
 
+use const_sv2::MessageType;
+pub use const_sv2::{
+    CHANNEL_BIT_PROVIDE_MISSING_TRANSACTIONS, CHANNEL_BIT_PROVIDE_MISSING_TRANSACTIONS_SUCCESS,
+    MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTIONS, MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTIONS_SUCCESS,
+};
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 #[repr(C)]
 pub struct ProvideMissingTransactions<'decoder> {
@@ -22,6 +28,11 @@ pub struct ProvideMissingTransactions<'decoder> {
     pub unknown_tx_position_list: Seq064K<'decoder, u16>,
 }
 
+impl<'decoder> MessageType for ProvideMissingTransactions<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTIONS;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_PROVIDE_MISSING_TRANSACTIONS;
+}
+
 // List of full transactions as requested by ProvideMissingTransactions, in the order they were
 // requested in ProvideMissingTransactions
 
@@ -33,6 +44,11 @@ pub struct ProvideMissingTransactionsSuccess<'decoder> {
     pub transaction_list: Seq064K<'decoder, B016M<'decoder>>,
 }
 
+impl<'decoder> MessageType for ProvideMissingTransactionsSuccess<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTIONS_SUCCESS;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_PROVIDE_MISSING_TRANSACTIONS_SUCCESS;
+}
+
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
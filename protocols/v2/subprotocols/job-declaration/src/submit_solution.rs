@@ -6,6 +6,9 @@ use binary_sv2::{Deserialize, Serialize, B032, U256};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{CHANNEL_BIT_SUBMIT_SOLUTION_JD, MESSAGE_TYPE_SUBMIT_SOLUTION_JD};
+
 /// TODO: comment
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 #[repr(C)]
@@ -20,6 +23,11 @@ pub struct SubmitSolutionJd<'decoder> {
     pub version: u32,
 }
 
+impl<'decoder> MessageType for SubmitSolutionJd<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SUBMIT_SOLUTION_JD;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SUBMIT_SOLUTION_JD;
+}
+
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
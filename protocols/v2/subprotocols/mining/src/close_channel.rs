@@ -6,6 +6,9 @@ use binary_sv2::{Deserialize, Serialize, Str0255};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{CHANNEL_BIT_CLOSE_CHANNEL, MESSAGE_TYPE_CLOSE_CHANNEL};
+
 /// # CloseChannel (Client -> Server, Server -> Client)
 ///
 /// Client sends this message when it ends its operation. The server MUST stop sending messages
@@ -26,6 +29,11 @@ pub struct CloseChannel<'decoder> {
     pub reason_code: Str0255<'decoder>,
 }
 
+impl<'decoder> MessageType for CloseChannel<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_CLOSE_CHANNEL;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_CLOSE_CHANNEL;
+}
+
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
@@ -38,9 +46,12 @@ impl<'d> GetSize for CloseChannel<'d> {
 #[cfg(feature = "with_serde")]
 impl<'a> CloseChannel<'a> {
     pub fn into_static(self) -> CloseChannel<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        CloseChannel {
+            channel_id: self.channel_id,
+            reason_code: self.reason_code.into_static(),
+        }
     }
     pub fn as_static(&self) -> CloseChannel<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
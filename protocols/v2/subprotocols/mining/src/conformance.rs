@@ -0,0 +1,299 @@
+//! Encodings of the mining messages that the serde and the no-serde backends have to agree on, the
+//! bytes are in `test-vectors/mining.json`. Run the tests with and without `with_serde`.
+use super::*;
+use binary_sv2::{assert_test_vector, conformance::bytes, Seq0255, Seq064K, Sv2Option};
+
+const VECTORS: &str = include_str!("../../test-vectors/mining.json");
+
+#[test]
+fn close_channel() {
+    assert_test_vector!(
+        VECTORS,
+        CloseChannel = CloseChannel {
+            channel_id: 1,
+            reason_code: bytes(3, b'a'),
+        }
+    );
+}
+
+#[test]
+fn new_mining_job() {
+    assert_test_vector!(
+        VECTORS,
+        NewMiningJob = NewMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            min_ntime: Sv2Option::new(Some(3)),
+            version: 4,
+            merkle_root: bytes(32, 0x05),
+        }
+    );
+}
+
+#[test]
+fn new_extended_mining_job() {
+    assert_test_vector!(
+        VECTORS,
+        NewExtendedMiningJob = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            min_ntime: Sv2Option::new(None),
+            version: 4,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![[0x06; 32].into(), [0x07; 32].into()]).unwrap(),
+            coinbase_tx_prefix: bytes(2, 0x08),
+            coinbase_tx_suffix: bytes(3, 0x09),
+        }
+    );
+}
+
+#[test]
+fn open_standard_mining_channel() {
+    assert_test_vector!(
+        VECTORS,
+        OpenStandardMiningChannel = OpenStandardMiningChannel {
+            request_id: 1_u32.into(),
+            user_identity: bytes(4, b'u'),
+            nominal_hash_rate: 1.5,
+            max_target: [0xff; 32].into(),
+        }
+    );
+}
+
+#[test]
+fn open_standard_mining_channel_success() {
+    assert_test_vector!(
+        VECTORS,
+        OpenStandardMiningChannelSuccess = OpenStandardMiningChannelSuccess {
+            request_id: 1_u32.into(),
+            channel_id: 2,
+            target: [0x03; 32].into(),
+            extranonce_prefix: bytes(4, 0x04),
+            group_channel_id: 5,
+        }
+    );
+}
+
+#[test]
+fn open_extended_mining_channel() {
+    assert_test_vector!(
+        VECTORS,
+        OpenExtendedMiningChannel = OpenExtendedMiningChannel {
+            request_id: 1,
+            user_identity: bytes(4, b'u'),
+            nominal_hash_rate: 1.5,
+            max_target: [0xff; 32].into(),
+            min_extranonce_size: 8,
+        }
+    );
+}
+
+#[test]
+fn open_extended_mining_channel_success() {
+    assert_test_vector!(
+        VECTORS,
+        OpenExtendedMiningChannelSuccess = OpenExtendedMiningChannelSuccess {
+            request_id: 1,
+            channel_id: 2,
+            target: [0x03; 32].into(),
+            extranonce_size: 8,
+            extranonce_prefix: bytes(4, 0x04),
+        }
+    );
+}
+
+#[test]
+fn open_mining_channel_error() {
+    assert_test_vector!(
+        VECTORS,
+        OpenMiningChannelError = OpenMiningChannelError {
+            request_id: 1,
+            error_code: bytes(3, b'e'),
+        }
+    );
+}
+
+#[test]
+fn reconnect() {
+    assert_test_vector!(
+        VECTORS,
+        Reconnect = Reconnect {
+            new_host: bytes(4, b'h'),
+            new_port: 3333,
+        }
+    );
+}
+
+#[test]
+fn set_custom_mining_job() {
+    assert_test_vector!(
+        VECTORS,
+        SetCustomMiningJob = SetCustomMiningJob {
+            channel_id: 1,
+            request_id: 2,
+            token: bytes(2, 0x03),
+            version: 4,
+            prev_hash: [0x05; 32].into(),
+            min_ntime: 6,
+            nbits: 7,
+            coinbase_tx_version: 2,
+            coinbase_prefix: bytes(2, 0x08),
+            coinbase_tx_input_n_sequence: 9,
+            coinbase_tx_value_remaining: 10,
+            coinbase_tx_outputs: bytes(3, 0x0b),
+            coinbase_tx_locktime: 12,
+            merkle_path: Seq0255::new(vec![[0x0d; 32].into()]).unwrap(),
+            extranonce_size: 14,
+        }
+    );
+}
+
+#[test]
+fn set_custom_mining_job_success() {
+    assert_test_vector!(
+        VECTORS,
+        SetCustomMiningJobSuccess = SetCustomMiningJobSuccess {
+            channel_id: 1,
+            request_id: 2,
+            job_id: 3,
+        }
+    );
+}
+
+#[test]
+fn set_custom_mining_job_error() {
+    assert_test_vector!(
+        VECTORS,
+        SetCustomMiningJobError = SetCustomMiningJobError {
+            channel_id: 1,
+            request_id: 2,
+            error_code: bytes(3, b'e'),
+        }
+    );
+}
+
+#[test]
+fn set_extranonce_prefix() {
+    assert_test_vector!(
+        VECTORS,
+        SetExtranoncePrefix = SetExtranoncePrefix {
+            channel_id: 1,
+            extranonce_prefix: bytes(4, 0x02),
+        }
+    );
+}
+
+#[test]
+fn set_group_channel() {
+    assert_test_vector!(
+        VECTORS,
+        SetGroupChannel = SetGroupChannel {
+            group_channel_id: 1,
+            channel_ids: Seq064K::new(vec![2, 3]).unwrap(),
+        }
+    );
+}
+
+#[test]
+fn set_new_prev_hash() {
+    assert_test_vector!(
+        VECTORS,
+        SetNewPrevHash = SetNewPrevHash {
+            channel_id: 1,
+            job_id: 2,
+            prev_hash: [0x03; 32].into(),
+            min_ntime: 4,
+            nbits: 5,
+        }
+    );
+}
+
+#[test]
+fn set_target() {
+    assert_test_vector!(
+        VECTORS,
+        SetTarget = SetTarget {
+            channel_id: 1,
+            maximum_target: [0x02; 32].into(),
+        }
+    );
+}
+
+#[test]
+fn submit_shares_standard() {
+    assert_test_vector!(
+        VECTORS,
+        SubmitSharesStandard = SubmitSharesStandard {
+            channel_id: 1,
+            sequence_number: 2,
+            job_id: 3,
+            nonce: 4,
+            ntime: 5,
+            version: 6,
+        }
+    );
+}
+
+#[test]
+fn submit_shares_extended() {
+    assert_test_vector!(
+        VECTORS,
+        SubmitSharesExtended = SubmitSharesExtended {
+            channel_id: 1,
+            sequence_number: 2,
+            job_id: 3,
+            nonce: 4,
+            ntime: 5,
+            version: 6,
+            extranonce: bytes(4, 0x07),
+        }
+    );
+}
+
+#[test]
+fn submit_shares_success() {
+    assert_test_vector!(
+        VECTORS,
+        SubmitSharesSuccess = SubmitSharesSuccess {
+            channel_id: 1,
+            last_sequence_number: 2,
+            new_submits_accepted_count: 3,
+            new_shares_sum: 4,
+        }
+    );
+}
+
+#[test]
+fn submit_shares_error() {
+    assert_test_vector!(
+        VECTORS,
+        SubmitSharesError = SubmitSharesError {
+            channel_id: 1,
+            sequence_number: 2,
+            error_code: bytes(3, b'e'),
+        }
+    );
+}
+
+#[test]
+fn update_channel() {
+    assert_test_vector!(
+        VECTORS,
+        UpdateChannel = UpdateChannel {
+            channel_id: 1,
+            nominal_hash_rate: 1.5,
+            maximum_target: [0x02; 32].into(),
+        }
+    );
+}
+
+#[test]
+fn update_channel_error() {
+    assert_test_vector!(
+        VECTORS,
+        UpdateChannelError = UpdateChannelError {
+            channel_id: 1,
+            error_code: bytes(3, b'e'),
+        }
+    );
+}
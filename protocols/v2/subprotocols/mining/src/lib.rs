@@ -106,7 +106,7 @@
 //!
 //! This protocol explicitly expects that upstream server software is able to manage the size of
 //! the hashing space correctly for its clients and can provide new jobs quickly enough.
-use binary_sv2::{B032, U256};
+use binary_sv2::{U256Ext, B032, U256};
 use core::{
     cmp::{Ord, PartialOrd},
     convert::TryInto,
@@ -116,6 +116,8 @@ use core::{
 extern crate alloc;
 
 mod close_channel;
+#[cfg(test)]
+mod conformance;
 mod new_mining_job;
 mod open_channel;
 mod reconnect;
@@ -126,26 +128,89 @@ mod set_new_prev_hash;
 mod set_target;
 mod submit_shares;
 mod update_channel;
+pub mod version_rolling;
 
-pub use close_channel::CloseChannel;
+pub use close_channel::{CloseChannel, CHANNEL_BIT_CLOSE_CHANNEL, MESSAGE_TYPE_CLOSE_CHANNEL};
 use core::ops::Range;
-pub use new_mining_job::{NewExtendedMiningJob, NewMiningJob};
+pub use new_mining_job::{
+    NewExtendedMiningJob, NewMiningJob, CHANNEL_BIT_NEW_EXTENDED_MINING_JOB,
+    CHANNEL_BIT_NEW_MINING_JOB, MESSAGE_TYPE_NEW_EXTENDED_MINING_JOB, MESSAGE_TYPE_NEW_MINING_JOB,
+};
 pub use open_channel::{
-    OpenExtendedMiningChannel, OpenExtendedMiningChannelSuccess, OpenMiningChannelError,
-    OpenStandardMiningChannel, OpenStandardMiningChannelSuccess,
+    OpenChannelBuilderError, OpenExtendedMiningChannel, OpenExtendedMiningChannelSuccess,
+    OpenMiningChannelBuilder, OpenMiningChannelError, OpenStandardMiningChannel,
+    OpenStandardMiningChannelSuccess, CHANNEL_BIT_OPEN_EXTENDED_MINING_CHANNEL,
+    CHANNEL_BIT_OPEN_EXTENDED_MINING_CHANNEL_SUCCES, CHANNEL_BIT_OPEN_MINING_CHANNEL_ERROR,
+    CHANNEL_BIT_OPEN_STANDARD_MINING_CHANNEL, CHANNEL_BIT_OPEN_STANDARD_MINING_CHANNEL_SUCCESS,
+    MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL, MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL_SUCCES,
+    MESSAGE_TYPE_OPEN_MINING_CHANNEL_ERROR, MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL,
+    MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS,
 };
-pub use reconnect::Reconnect;
+pub use reconnect::{Reconnect, CHANNEL_BIT_RECONNECT, MESSAGE_TYPE_RECONNECT};
 pub use set_custom_mining_job::{
     SetCustomMiningJob, SetCustomMiningJobError, SetCustomMiningJobSuccess,
+    CHANNEL_BIT_SET_CUSTOM_MINING_JOB, CHANNEL_BIT_SET_CUSTOM_MINING_JOB_ERROR,
+    CHANNEL_BIT_SET_CUSTOM_MINING_JOB_SUCCESS, MESSAGE_TYPE_SET_CUSTOM_MINING_JOB,
+    MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_ERROR, MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_SUCCESS,
+};
+pub use set_extranonce_prefix::{
+    SetExtranoncePrefix, CHANNEL_BIT_SET_EXTRANONCE_PREFIX, MESSAGE_TYPE_SET_EXTRANONCE_PREFIX,
+};
+pub use set_group_channel::{
+    SetGroupChannel, CHANNEL_BIT_SET_GROUP_CHANNEL, MESSAGE_TYPE_SET_GROUP_CHANNEL,
 };
-pub use set_extranonce_prefix::SetExtranoncePrefix;
-pub use set_group_channel::SetGroupChannel;
-pub use set_new_prev_hash::SetNewPrevHash;
-pub use set_target::SetTarget;
+pub use set_new_prev_hash::{
+    SetNewPrevHash, CHANNEL_BIT_MINING_SET_NEW_PREV_HASH, MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH,
+};
+pub use set_target::{SetTarget, CHANNEL_BIT_SET_TARGET, MESSAGE_TYPE_SET_TARGET};
 pub use submit_shares::{
     SubmitSharesError, SubmitSharesExtended, SubmitSharesStandard, SubmitSharesSuccess,
+    CHANNEL_BIT_SUBMIT_SHARES_ERROR, CHANNEL_BIT_SUBMIT_SHARES_EXTENDED,
+    CHANNEL_BIT_SUBMIT_SHARES_STANDARD, CHANNEL_BIT_SUBMIT_SHARES_SUCCESS,
+    MESSAGE_TYPE_SUBMIT_SHARES_ERROR, MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED,
+    MESSAGE_TYPE_SUBMIT_SHARES_STANDARD, MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS,
+};
+pub use update_channel::{
+    UpdateChannel, UpdateChannelError, CHANNEL_BIT_UPDATE_CHANNEL,
+    CHANNEL_BIT_UPDATE_CHANNEL_ERROR, MESSAGE_TYPE_UPDATE_CHANNEL,
+    MESSAGE_TYPE_UPDATE_CHANNEL_ERROR,
 };
-pub use update_channel::{UpdateChannel, UpdateChannelError};
+pub use version_rolling::{VersionRollingError, BIP320_VERSION_ROLLING_MASK};
+
+pub use const_sv2::{message_type_of, MessageType, EXTENSION_TYPE_NO_EXTENSION};
+
+/// Name of the mining message with type `message_type`, for logging
+pub fn message_type_name(message_type: u8) -> Option<&'static str> {
+    match message_type {
+        MESSAGE_TYPE_CLOSE_CHANNEL => Some("CloseChannel"),
+        MESSAGE_TYPE_NEW_MINING_JOB => Some("NewMiningJob"),
+        MESSAGE_TYPE_NEW_EXTENDED_MINING_JOB => Some("NewExtendedMiningJob"),
+        MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL => Some("OpenStandardMiningChannel"),
+        MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS => {
+            Some("OpenStandardMiningChannelSuccess")
+        }
+        MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL => Some("OpenExtendedMiningChannel"),
+        MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL_SUCCES => {
+            Some("OpenExtendedMiningChannelSuccess")
+        }
+        MESSAGE_TYPE_OPEN_MINING_CHANNEL_ERROR => Some("OpenMiningChannelError"),
+        MESSAGE_TYPE_RECONNECT => Some("Reconnect"),
+        MESSAGE_TYPE_SET_CUSTOM_MINING_JOB => Some("SetCustomMiningJob"),
+        MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_SUCCESS => Some("SetCustomMiningJobSuccess"),
+        MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_ERROR => Some("SetCustomMiningJobError"),
+        MESSAGE_TYPE_SET_EXTRANONCE_PREFIX => Some("SetExtranoncePrefix"),
+        MESSAGE_TYPE_SET_GROUP_CHANNEL => Some("SetGroupChannel"),
+        MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH => Some("SetNewPrevHash"),
+        MESSAGE_TYPE_SET_TARGET => Some("SetTarget"),
+        MESSAGE_TYPE_SUBMIT_SHARES_STANDARD => Some("SubmitSharesStandard"),
+        MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED => Some("SubmitSharesExtended"),
+        MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS => Some("SubmitSharesSuccess"),
+        MESSAGE_TYPE_SUBMIT_SHARES_ERROR => Some("SubmitSharesError"),
+        MESSAGE_TYPE_UPDATE_CHANNEL => Some("UpdateChannel"),
+        MESSAGE_TYPE_UPDATE_CHANNEL_ERROR => Some("UpdateChannelError"),
+        _ => None,
+    }
+}
 const MAX_EXTRANONCE_LEN: usize = 32;
 
 /// Target is a 256-bit unsigned integer in little-endian
@@ -156,9 +221,58 @@ pub struct Target {
 }
 
 impl Target {
+    /// Target of difficulty 1, `0x00000000ffff0000000000000000000000000000000000000000000000000000`
+    pub const DIFFICULTY_1: Target = Target {
+        head: 0,
+        tail: 0xffff << 80,
+    };
+
+    /// Biggest possible target, every hash meets it
+    pub const MAX: Target = Target {
+        head: u128::MAX,
+        tail: u128::MAX,
+    };
+
     pub fn new(head: u128, tail: u128) -> Self {
         Self { head, tail }
     }
+
+    /// Target from its little endian bytes, as it is encoded in the messages
+    pub fn from_le_bytes(bytes: [u8; 32]) -> Self {
+        bytes.into()
+    }
+
+    /// Target from its big endian bytes, as it is usually displayed
+    pub fn from_be_bytes(mut bytes: [u8; 32]) -> Self {
+        bytes.reverse();
+        bytes.into()
+    }
+
+    pub fn to_le_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        bytes[..16].copy_from_slice(&self.head.to_le_bytes());
+        bytes[16..].copy_from_slice(&self.tail.to_le_bytes());
+        bytes
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = self.to_le_bytes();
+        bytes.reverse();
+        bytes
+    }
+
+    /// Target of `difficulty`, [`Target::DIFFICULTY_1`] divided by it, see
+    /// [`U256Ext::from_difficulty`]. `None` if `difficulty` is not a finite positive number.
+    pub fn from_difficulty(difficulty: f64) -> Option<Self> {
+        U256::from_difficulty(difficulty).ok().map(Into::into)
+    }
+
+    /// Ratio of [`Target::DIFFICULTY_1`] to this target, infinite for a zero target
+    pub fn difficulty(&self) -> f64 {
+        U256::from(self.clone())
+            .difficulty()
+            .unwrap_or(f64::INFINITY)
+    }
 }
 
 impl From<[u8; 32]> for Target {
@@ -656,12 +770,121 @@ fn increment_bytes_be(bs: &mut [u8]) -> Result<(), ()> {
     Err(())
 }
 
+/// Implements [`quickcheck::Arbitrary`] for messages whose fields all implement it
+#[cfg(all(feature = "prop_test", not(feature = "with_serde")))]
+macro_rules! impl_arbitrary {
+    ($($message:ident$(<$lt:lifetime>)? { $($field:ident),* $(,)? })*) => {$(
+        impl quickcheck::Arbitrary for $message$(<$lt>)? {
+            fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+                $message {
+                    $($field: quickcheck::Arbitrary::arbitrary(g),)*
+                }
+            }
+        }
+    )*};
+}
+
+#[cfg(all(feature = "prop_test", not(feature = "with_serde")))]
+impl_arbitrary! {
+    CloseChannel<'static> { channel_id, reason_code }
+    NewMiningJob<'static> { channel_id, job_id, min_ntime, version, merkle_root }
+    NewExtendedMiningJob<'static> {
+        channel_id,
+        job_id,
+        min_ntime,
+        version,
+        version_rolling_allowed,
+        merkle_path,
+        coinbase_tx_prefix,
+        coinbase_tx_suffix,
+    }
+    OpenStandardMiningChannel<'static> { request_id, user_identity, nominal_hash_rate, max_target }
+    OpenStandardMiningChannelSuccess<'static> {
+        request_id,
+        channel_id,
+        target,
+        extranonce_prefix,
+        group_channel_id,
+    }
+    OpenExtendedMiningChannel<'static> {
+        request_id,
+        user_identity,
+        nominal_hash_rate,
+        max_target,
+        min_extranonce_size,
+    }
+    OpenExtendedMiningChannelSuccess<'static> {
+        request_id,
+        channel_id,
+        target,
+        extranonce_size,
+        extranonce_prefix,
+    }
+    OpenMiningChannelError<'static> { request_id, error_code }
+    Reconnect<'static> { new_host, new_port }
+    SetCustomMiningJob<'static> {
+        channel_id,
+        request_id,
+        token,
+        version,
+        prev_hash,
+        min_ntime,
+        nbits,
+        coinbase_tx_version,
+        coinbase_prefix,
+        coinbase_tx_input_n_sequence,
+        coinbase_tx_value_remaining,
+        coinbase_tx_outputs,
+        coinbase_tx_locktime,
+        merkle_path,
+        extranonce_size,
+    }
+    SetCustomMiningJobSuccess { channel_id, request_id, job_id }
+    SetCustomMiningJobError<'static> { channel_id, request_id, error_code }
+    SetExtranoncePrefix<'static> { channel_id, extranonce_prefix }
+    SetGroupChannel<'static> { group_channel_id, channel_ids }
+    SetNewPrevHash<'static> { channel_id, job_id, prev_hash, min_ntime, nbits }
+    SetTarget<'static> { channel_id, maximum_target }
+    SubmitSharesStandard { channel_id, sequence_number, job_id, nonce, ntime, version }
+    SubmitSharesExtended<'static> {
+        channel_id,
+        sequence_number,
+        job_id,
+        nonce,
+        ntime,
+        version,
+        extranonce,
+    }
+    SubmitSharesSuccess {
+        channel_id,
+        last_sequence_number,
+        new_submits_accepted_count,
+        new_shares_sum,
+    }
+    SubmitSharesError<'static> { channel_id, sequence_number, error_code }
+    UpdateChannel<'static> { channel_id, nominal_hash_rate, maximum_target }
+    UpdateChannelError<'static> { channel_id, error_code }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
     use alloc::vec::Vec;
     use quickcheck_macros;
 
+    #[test]
+    fn test_message_types() {
+        assert_eq!(message_type_of::<SetTarget>(), MESSAGE_TYPE_SET_TARGET);
+        assert_eq!(message_type_of::<SetNewPrevHash>(), 0x20);
+        assert_eq!(
+            SubmitSharesStandard::CHANNEL_BIT,
+            CHANNEL_BIT_SUBMIT_SHARES_STANDARD
+        );
+        assert_eq!(SetTarget::EXTENSION_TYPE, EXTENSION_TYPE_NO_EXTENSION);
+        assert_eq!(message_type_name(0x20), Some("SetNewPrevHash"));
+        assert_eq!(message_type_name(0x72), None);
+    }
+
     #[test]
     fn test_extranonce_errors() {
         let extranonce = Extranonce::try_from(vec![0; MAX_EXTRANONCE_LEN + 1]);
@@ -1075,6 +1298,53 @@ pub mod tests {
         target_start == target_final
     }
 
+    #[test]
+    fn test_target_endianness() {
+        let mut be = [0_u8; 32];
+        be[31] = 1;
+        let target = Target::from_be_bytes(be);
+        assert_eq!(target, Target::new(1, 0));
+        assert_eq!(target.to_be_bytes(), be);
+        assert_eq!(Target::from_le_bytes(target.to_le_bytes()), target);
+        assert_eq!(U256::from(target.clone()), U256::from(target.to_le_bytes()));
+        assert!(Target::from_be_bytes([0xff; 32]) == Target::MAX);
+    }
+
+    #[test]
+    fn test_target_difficulty() {
+        assert_eq!(Target::from_difficulty(1.0), Some(Target::DIFFICULTY_1));
+        assert_eq!(Target::DIFFICULTY_1.difficulty(), 1.0);
+        let target = Target::from_difficulty(1024.0).unwrap();
+        assert_eq!(target, Target::new(0, 0xffff << 70));
+        assert_eq!(target.difficulty(), 1024.0);
+        // lower difficulty, bigger target
+        assert!(Target::from_difficulty(0.5).unwrap() > Target::DIFFICULTY_1);
+        // below the fixed point precision
+        assert_eq!(
+            Target::from_difficulty(1e-80),
+            Target::from_difficulty(2_f64.powi(-32))
+        );
+        assert_eq!(Target::from_difficulty(1e80), Some(Target::new(0, 0)));
+        // exact for large difficulties
+        let target = Target::from_difficulty(2_f64.powi(100)).unwrap();
+        assert_eq!(target, Target::new(0xffff << 108, 0));
+        assert_eq!(target.difficulty(), 2_f64.powi(100));
+        assert_eq!(Target::from_difficulty(0.0), None);
+        assert_eq!(Target::from_difficulty(f64::NAN), None);
+        assert!(Target::new(0, 0).difficulty().is_infinite());
+    }
+
+    #[test]
+    fn test_set_target_new() {
+        let set_target = SetTarget::new(7, Target::DIFFICULTY_1);
+        assert_eq!(set_target.channel_id, 7);
+        assert_eq!(
+            set_target.maximum_target.inner_as_ref(),
+            &Target::DIFFICULTY_1.to_le_bytes()[..]
+        );
+        assert_eq!(set_target.target(), Target::DIFFICULTY_1);
+    }
+
     #[quickcheck_macros::quickcheck]
     fn test_vec_from_extranonce(input: Vec<u8>) -> bool {
         let input_start = from_arbitrary_vec_to_array(input).to_vec();
@@ -1125,3 +1395,42 @@ pub mod tests {
         assert!(prefix_len == 4);
     }
 }
+
+#[cfg(all(test, feature = "prop_test", not(feature = "with_serde")))]
+mod round_trip {
+    use super::*;
+
+    macro_rules! round_trip_tests {
+        ($($name:ident: $message:ty,)*) => {$(
+            #[test]
+            fn $name() {
+                quickcheck::quickcheck(binary_sv2::round_trip::<$message> as fn($message) -> bool);
+            }
+        )*};
+    }
+
+    round_trip_tests! {
+        close_channel: CloseChannel<'static>,
+        new_mining_job: NewMiningJob<'static>,
+        new_extended_mining_job: NewExtendedMiningJob<'static>,
+        open_standard_mining_channel: OpenStandardMiningChannel<'static>,
+        open_standard_mining_channel_success: OpenStandardMiningChannelSuccess<'static>,
+        open_extended_mining_channel: OpenExtendedMiningChannel<'static>,
+        open_extended_mining_channel_success: OpenExtendedMiningChannelSuccess<'static>,
+        open_mining_channel_error: OpenMiningChannelError<'static>,
+        reconnect: Reconnect<'static>,
+        set_custom_mining_job: SetCustomMiningJob<'static>,
+        set_custom_mining_job_success: SetCustomMiningJobSuccess,
+        set_custom_mining_job_error: SetCustomMiningJobError<'static>,
+        set_extranonce_prefix: SetExtranoncePrefix<'static>,
+        set_group_channel: SetGroupChannel<'static>,
+        set_new_prev_hash: SetNewPrevHash<'static>,
+        set_target: SetTarget<'static>,
+        submit_shares_standard: SubmitSharesStandard,
+        submit_shares_extended: SubmitSharesExtended<'static>,
+        submit_shares_success: SubmitSharesSuccess,
+        submit_shares_error: SubmitSharesError<'static>,
+        update_channel: UpdateChannel<'static>,
+        update_channel_error: UpdateChannelError<'static>,
+    }
+}
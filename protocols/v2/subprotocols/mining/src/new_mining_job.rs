@@ -6,12 +6,18 @@ use binary_sv2::{Deserialize, Seq0255, Serialize, Sv2Option, B032, B064K, U256};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
 /// # NewMiningJob (Server -> Client)
 ///
-/// The server provides an updated mining job to the client through a standard channel. This MUST be
-/// the first message after the channel has been successfully opened. This first message will have
-/// min_ntime unset (future job). If the `min_ntime` field is set, the client MUST start to mine on
-/// the new job immediately after receiving this message, and use the value for the initial nTime.
+/// The server provides an updated mining job to the client through a standard channel. This
+/// MUST be the first message after the channel has been successfully opened. This first
+/// message will have min_ntime unset (future job). If the `min_ntime` field is set, the client
+/// MUST start to mine on the new job immediately after receiving this message, and use the
+/// value for the initial nTime.
+pub use const_sv2::{
+    CHANNEL_BIT_NEW_EXTENDED_MINING_JOB, CHANNEL_BIT_NEW_MINING_JOB,
+    MESSAGE_TYPE_NEW_EXTENDED_MINING_JOB, MESSAGE_TYPE_NEW_MINING_JOB,
+};
 
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -39,6 +45,11 @@ pub struct NewMiningJob<'decoder> {
     pub merkle_root: B032<'decoder>,
 }
 
+impl<'decoder> MessageType for NewMiningJob<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_NEW_MINING_JOB;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_NEW_MINING_JOB;
+}
+
 impl<'d> NewMiningJob<'d> {
     pub fn is_future(&self) -> bool {
         self.min_ntime.clone().into_inner().is_none()
@@ -107,6 +118,11 @@ pub struct NewExtendedMiningJob<'decoder> {
     pub coinbase_tx_suffix: B064K<'decoder>,
 }
 
+impl<'decoder> MessageType for NewExtendedMiningJob<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_NEW_EXTENDED_MINING_JOB;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_NEW_EXTENDED_MINING_JOB;
+}
+
 impl<'d> NewExtendedMiningJob<'d> {
     pub fn is_future(&self) -> bool {
         self.min_ntime.clone().into_inner().is_none()
@@ -234,18 +250,33 @@ mod tests {
 #[cfg(feature = "with_serde")]
 impl<'a> NewExtendedMiningJob<'a> {
     pub fn into_static(self) -> NewExtendedMiningJob<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        NewExtendedMiningJob {
+            channel_id: self.channel_id,
+            job_id: self.job_id,
+            min_ntime: self.min_ntime.into_static(),
+            version: self.version,
+            version_rolling_allowed: self.version_rolling_allowed,
+            merkle_path: self.merkle_path.into_static(),
+            coinbase_tx_prefix: self.coinbase_tx_prefix.into_static(),
+            coinbase_tx_suffix: self.coinbase_tx_suffix.into_static(),
+        }
     }
     pub fn as_static(&self) -> NewExtendedMiningJob<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
 #[cfg(feature = "with_serde")]
 impl<'a> NewMiningJob<'a> {
     pub fn into_static(self) -> NewMiningJob<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        NewMiningJob {
+            channel_id: self.channel_id,
+            job_id: self.job_id,
+            min_ntime: self.min_ntime.into_static(),
+            version: self.version,
+            merkle_root: self.merkle_root.into_static(),
+        }
     }
     pub fn as_static(&self) -> NewMiningJob<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
@@ -9,6 +9,15 @@ use core::convert::TryInto;
 #[cfg(feature = "with_serde")]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{
+    CHANNEL_BIT_OPEN_EXTENDED_MINING_CHANNEL, CHANNEL_BIT_OPEN_EXTENDED_MINING_CHANNEL_SUCCES,
+    CHANNEL_BIT_OPEN_MINING_CHANNEL_ERROR, CHANNEL_BIT_OPEN_STANDARD_MINING_CHANNEL,
+    CHANNEL_BIT_OPEN_STANDARD_MINING_CHANNEL_SUCCESS, MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL,
+    MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL_SUCCES, MESSAGE_TYPE_OPEN_MINING_CHANNEL_ERROR,
+    MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL, MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS,
+};
+
 /// # OpenStandardMiningChannel (Client -> Server)
 /// This message requests to open a standard channel to the upstream node.
 /// After receiving a SetupConnection.Success message, the client SHOULD respond by opening
@@ -48,6 +57,11 @@ pub struct OpenStandardMiningChannel<'decoder> {
     pub max_target: U256<'decoder>,
 }
 
+impl<'decoder> MessageType for OpenStandardMiningChannel<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_OPEN_STANDARD_MINING_CHANNEL;
+}
+
 impl<'decoder> OpenStandardMiningChannel<'decoder> {
     #[cfg(not(feature = "with_serde"))]
     pub fn get_request_id_as_u32(&self) -> u32 {
@@ -105,6 +119,11 @@ pub struct OpenStandardMiningChannelSuccess<'decoder> {
     pub group_channel_id: u32,
 }
 
+impl<'decoder> MessageType for OpenStandardMiningChannelSuccess<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_OPEN_STANDARD_MINING_CHANNEL_SUCCESS;
+}
+
 impl<'decoder> OpenStandardMiningChannelSuccess<'decoder> {
     #[cfg(not(feature = "with_serde"))]
     pub fn get_request_id_as_u32(&self) -> u32 {
@@ -163,6 +182,11 @@ pub struct OpenExtendedMiningChannel<'decoder> {
     /// Minimum size of extranonce needed by the device/node.
     pub min_extranonce_size: u16,
 }
+
+impl<'decoder> MessageType for OpenExtendedMiningChannel<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_OPEN_EXTENDED_MINING_CHANNEL;
+}
 impl<'decoder> OpenExtendedMiningChannel<'decoder> {
     pub fn get_request_id_as_u32(&self) -> u32 {
         self.request_id
@@ -190,6 +214,11 @@ pub struct OpenExtendedMiningChannelSuccess<'decoder> {
     pub extranonce_prefix: B032<'decoder>,
 }
 
+impl<'decoder> MessageType for OpenExtendedMiningChannelSuccess<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL_SUCCES;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_OPEN_EXTENDED_MINING_CHANNEL_SUCCES;
+}
+
 /// # OpenMiningChannel.Error (Server -> Client)
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OpenMiningChannelError<'decoder> {
@@ -203,6 +232,11 @@ pub struct OpenMiningChannelError<'decoder> {
     pub error_code: Str0255<'decoder>,
 }
 
+impl<'decoder> MessageType for OpenMiningChannelError<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_OPEN_MINING_CHANNEL_ERROR;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_OPEN_MINING_CHANNEL_ERROR;
+}
+
 impl<'a> OpenMiningChannelError<'a> {
     pub fn new_max_target_out_of_range(request_id: u32) -> Self {
         Self {
@@ -227,6 +261,117 @@ impl<'a> OpenMiningChannelError<'a> {
     }
 }
 
+/// Errors returned by the open channel builders
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpenChannelBuilderError {
+    /// User identity longer than 255 bytes (len of the identity)
+    UserIdentityTooLong(usize),
+    /// Nominal hash rate must be a finite non negative number
+    InvalidNominalHashRate(f32),
+    /// Min extranonce size bigger than 32 bytes
+    InvalidMinExtranonceSize(u16),
+}
+
+/// Builder for [`OpenStandardMiningChannel`] and [`OpenExtendedMiningChannel`] that validates
+/// the fields before constructing the message. By default the max target is the biggest possible
+/// target, the nominal hash rate is 0.0 (what a proxy MUST send when no device is connected) and
+/// the min extranonce size is 0.
+///
+/// # Examples
+///
+/// ```
+/// use mining_sv2::*;
+/// let open_channel = OpenMiningChannelBuilder::new(1, "user.worker1")
+///     .nominal_hash_rate(10_000_000_000.0)
+///     .min_extranonce_size(8)
+///     .build_extended()
+///     .unwrap();
+/// assert_eq!(open_channel.min_extranonce_size, 8);
+/// ```
+#[derive(Debug, Clone)]
+pub struct OpenMiningChannelBuilder<'a> {
+    request_id: u32,
+    user_identity: &'a str,
+    nominal_hash_rate: f32,
+    max_target: crate::Target,
+    min_extranonce_size: u16,
+}
+
+impl<'a> OpenMiningChannelBuilder<'a> {
+    pub fn new(request_id: u32, user_identity: &'a str) -> Self {
+        Self {
+            request_id,
+            user_identity,
+            nominal_hash_rate: 0.0,
+            max_target: crate::Target::new(u128::MAX, u128::MAX),
+            min_extranonce_size: 0,
+        }
+    }
+
+    /// Expected hash rate of the device in h/s
+    pub fn nominal_hash_rate(mut self, nominal_hash_rate: f32) -> Self {
+        self.nominal_hash_rate = nominal_hash_rate;
+        self
+    }
+
+    pub fn max_target(mut self, max_target: impl Into<crate::Target>) -> Self {
+        self.max_target = max_target.into();
+        self
+    }
+
+    /// Only used by [`OpenMiningChannelBuilder::build_extended`]
+    pub fn min_extranonce_size(mut self, min_extranonce_size: u16) -> Self {
+        self.min_extranonce_size = min_extranonce_size;
+        self
+    }
+
+    fn validate(&self) -> Result<(Str0255<'static>, U256<'static>), OpenChannelBuilderError> {
+        if self.user_identity.len() > 255 {
+            return Err(OpenChannelBuilderError::UserIdentityTooLong(
+                self.user_identity.len(),
+            ));
+        }
+        if !self.nominal_hash_rate.is_finite() || self.nominal_hash_rate < 0.0 {
+            return Err(OpenChannelBuilderError::InvalidNominalHashRate(
+                self.nominal_hash_rate,
+            ));
+        }
+        // Infallible unwrap len has been checked above
+        let user_identity: Str0255<'static> = self.user_identity.to_string().try_into().unwrap();
+        Ok((user_identity, self.max_target.clone().into()))
+    }
+
+    pub fn build_standard(
+        self,
+    ) -> Result<OpenStandardMiningChannel<'static>, OpenChannelBuilderError> {
+        let (user_identity, max_target) = self.validate()?;
+        Ok(OpenStandardMiningChannel {
+            request_id: self.request_id.into(),
+            user_identity,
+            nominal_hash_rate: self.nominal_hash_rate,
+            max_target,
+        })
+    }
+
+    pub fn build_extended(
+        self,
+    ) -> Result<OpenExtendedMiningChannel<'static>, OpenChannelBuilderError> {
+        let (user_identity, max_target) = self.validate()?;
+        if self.min_extranonce_size as usize > crate::MAX_EXTRANONCE_LEN {
+            return Err(OpenChannelBuilderError::InvalidMinExtranonceSize(
+                self.min_extranonce_size,
+            ));
+        }
+        Ok(OpenExtendedMiningChannel {
+            request_id: self.request_id,
+            user_identity,
+            nominal_hash_rate: self.nominal_hash_rate,
+            max_target,
+            min_extranonce_size: self.min_extranonce_size,
+        })
+    }
+}
+
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
@@ -272,7 +417,7 @@ impl<'d> GetSize for OpenExtendedMiningChannelSuccess<'d> {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(feature = "with_serde")))]
 mod tests {
 
     use super::*;
@@ -353,6 +498,50 @@ mod tests {
         request_id == test_request_id_1
     }
 
+    // *** OPEN MINING CHANNEL BUILDER ***
+    #[test]
+    fn test_open_mining_channel_builder() {
+        let standard = OpenMiningChannelBuilder::new(1, "user")
+            .nominal_hash_rate(100.0)
+            .max_target([1; 32])
+            .build_standard()
+            .unwrap();
+        assert_eq!(standard.get_request_id_as_u32(), 1);
+        assert_eq!(standard.user_identity.to_vec(), b"user".to_vec());
+        assert_eq!(standard.max_target, U256::from([1; 32]));
+
+        let extended = OpenMiningChannelBuilder::new(2, "user")
+            .min_extranonce_size(32)
+            .build_extended()
+            .unwrap();
+        assert_eq!(extended.max_target, U256::from([255; 32]));
+        assert_eq!(extended.nominal_hash_rate, 0.0);
+
+        assert_eq!(
+            OpenMiningChannelBuilder::new(2, "user")
+                .min_extranonce_size(33)
+                .build_extended(),
+            Err(OpenChannelBuilderError::InvalidMinExtranonceSize(33))
+        );
+        assert_eq!(
+            OpenMiningChannelBuilder::new(2, "user")
+                .nominal_hash_rate(-1.0)
+                .build_extended(),
+            Err(OpenChannelBuilderError::InvalidNominalHashRate(-1.0))
+        );
+        assert!(OpenMiningChannelBuilder::new(2, "user")
+            .nominal_hash_rate(f32::NAN)
+            .build_standard()
+            .is_err());
+        let long_identity = "a".repeat(256);
+        assert_eq!(
+            OpenMiningChannelBuilder::new(2, &long_identity)
+                .build_extended()
+                .map(|_| ()),
+            Err(OpenChannelBuilderError::UserIdentityTooLong(256))
+        );
+    }
+
     // *** HELPERS ***
     mod helpers {
         use super::*;
@@ -375,45 +564,71 @@ mod tests {
 #[cfg(feature = "with_serde")]
 impl<'a> OpenExtendedMiningChannel<'a> {
     pub fn into_static(self) -> OpenExtendedMiningChannel<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        OpenExtendedMiningChannel {
+            request_id: self.request_id,
+            user_identity: self.user_identity.into_static(),
+            nominal_hash_rate: self.nominal_hash_rate,
+            max_target: self.max_target.into_static(),
+            min_extranonce_size: self.min_extranonce_size,
+        }
     }
     pub fn as_static(&self) -> OpenExtendedMiningChannel<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
 #[cfg(feature = "with_serde")]
 impl<'a> OpenExtendedMiningChannelSuccess<'a> {
     pub fn into_static(self) -> OpenExtendedMiningChannelSuccess<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        OpenExtendedMiningChannelSuccess {
+            request_id: self.request_id,
+            channel_id: self.channel_id,
+            target: self.target.into_static(),
+            extranonce_size: self.extranonce_size,
+            extranonce_prefix: self.extranonce_prefix.into_static(),
+        }
     }
     pub fn as_static(&self) -> OpenExtendedMiningChannelSuccess<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
 #[cfg(feature = "with_serde")]
 impl<'a> OpenMiningChannelError<'a> {
     pub fn into_static(self) -> OpenMiningChannelError<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        OpenMiningChannelError {
+            request_id: self.request_id,
+            error_code: self.error_code.into_static(),
+        }
     }
     pub fn as_static(&self) -> OpenMiningChannelError<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
 #[cfg(feature = "with_serde")]
 impl<'a> OpenStandardMiningChannel<'a> {
     pub fn into_static(self) -> OpenStandardMiningChannel<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        OpenStandardMiningChannel {
+            request_id: self.request_id,
+            user_identity: self.user_identity.into_static(),
+            nominal_hash_rate: self.nominal_hash_rate,
+            max_target: self.max_target.into_static(),
+        }
     }
     pub fn as_static(&self) -> OpenStandardMiningChannel<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
 #[cfg(feature = "with_serde")]
 impl<'a> OpenStandardMiningChannelSuccess<'a> {
     pub fn into_static(self) -> OpenStandardMiningChannelSuccess<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        OpenStandardMiningChannelSuccess {
+            request_id: self.request_id,
+            channel_id: self.channel_id,
+            target: self.target.into_static(),
+            extranonce_prefix: self.extranonce_prefix.into_static(),
+            group_channel_id: self.group_channel_id,
+        }
     }
     pub fn as_static(&self) -> OpenStandardMiningChannelSuccess<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
@@ -6,6 +6,9 @@ use binary_sv2::{Deserialize, Serialize, Str0255};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{CHANNEL_BIT_RECONNECT, MESSAGE_TYPE_RECONNECT};
+
 /// # Reconnect (Server -> Client)
 ///
 /// This message allows clients to be redirected to a new upstream node.
@@ -29,6 +32,11 @@ pub struct Reconnect<'decoder> {
     /// When 0, downstream node attempts to reconnect to its present port.
     pub new_port: u16,
 }
+
+impl<'decoder> MessageType for Reconnect<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_RECONNECT;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_RECONNECT;
+}
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
@@ -40,9 +48,12 @@ impl<'d> GetSize for Reconnect<'d> {
 #[cfg(feature = "with_serde")]
 impl<'a> Reconnect<'a> {
     pub fn into_static(self) -> Reconnect<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        Reconnect {
+            new_host: self.new_host.into_static(),
+            new_port: self.new_port,
+        }
     }
     pub fn as_static(&self) -> Reconnect<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
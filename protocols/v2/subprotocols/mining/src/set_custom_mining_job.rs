@@ -6,6 +6,13 @@ use binary_sv2::{Deserialize, Seq0255, Serialize, Str0255, B0255, B064K, U256};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{
+    CHANNEL_BIT_SET_CUSTOM_MINING_JOB, CHANNEL_BIT_SET_CUSTOM_MINING_JOB_ERROR,
+    CHANNEL_BIT_SET_CUSTOM_MINING_JOB_SUCCESS, MESSAGE_TYPE_SET_CUSTOM_MINING_JOB,
+    MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_ERROR, MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_SUCCESS,
+};
+
 /// # SetCustomMiningJob (Client -> Server)
 ///
 /// Can be sent only on extended channel. SetupConnection.flags MUST contain
@@ -57,6 +64,11 @@ pub struct SetCustomMiningJob<'decoder> {
     pub extranonce_size: u16,
 }
 
+impl<'decoder> MessageType for SetCustomMiningJob<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SET_CUSTOM_MINING_JOB;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SET_CUSTOM_MINING_JOB;
+}
+
 /// # SetCustomMiningJob.Success (Server -> Client)
 ///
 /// Response from the server when it accepts the custom mining job. Client can start to mine on
@@ -72,6 +84,11 @@ pub struct SetCustomMiningJobSuccess {
     pub job_id: u32,
 }
 
+impl MessageType for SetCustomMiningJobSuccess {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_SUCCESS;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SET_CUSTOM_MINING_JOB_SUCCESS;
+}
+
 /// # SetCustomMiningJob.Error (Server -> Client)
 ///
 /// Possible errors:
@@ -90,6 +107,11 @@ pub struct SetCustomMiningJobError<'decoder> {
     #[cfg_attr(feature = "with_serde", serde(borrow))]
     pub error_code: Str0255<'decoder>,
 }
+
+impl<'decoder> MessageType for SetCustomMiningJobError<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_ERROR;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SET_CUSTOM_MINING_JOB_ERROR;
+}
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
@@ -127,27 +149,47 @@ impl<'d> GetSize for SetCustomMiningJobError<'d> {
 #[cfg(feature = "with_serde")]
 impl<'a> SetCustomMiningJob<'a> {
     pub fn into_static(self) -> SetCustomMiningJob<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        SetCustomMiningJob {
+            channel_id: self.channel_id,
+            request_id: self.request_id,
+            token: self.token.into_static(),
+            version: self.version,
+            prev_hash: self.prev_hash.into_static(),
+            min_ntime: self.min_ntime,
+            nbits: self.nbits,
+            coinbase_tx_version: self.coinbase_tx_version,
+            coinbase_prefix: self.coinbase_prefix.into_static(),
+            coinbase_tx_input_n_sequence: self.coinbase_tx_input_n_sequence,
+            coinbase_tx_value_remaining: self.coinbase_tx_value_remaining,
+            coinbase_tx_outputs: self.coinbase_tx_outputs.into_static(),
+            coinbase_tx_locktime: self.coinbase_tx_locktime,
+            merkle_path: self.merkle_path.into_static(),
+            extranonce_size: self.extranonce_size,
+        }
     }
     pub fn as_static(&self) -> SetCustomMiningJob<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
 #[cfg(feature = "with_serde")]
 impl<'a> SetCustomMiningJobError<'a> {
     pub fn into_static(self) -> SetCustomMiningJobError<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        SetCustomMiningJobError {
+            channel_id: self.channel_id,
+            request_id: self.request_id,
+            error_code: self.error_code.into_static(),
+        }
     }
     pub fn as_static(&self) -> SetCustomMiningJobError<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
 #[cfg(feature = "with_serde")]
 impl SetCustomMiningJobSuccess {
     pub fn into_static(self) -> SetCustomMiningJobSuccess {
-        panic!("This function shouldn't be called by the Message Generator");
+        self
     }
     pub fn as_static(&self) -> SetCustomMiningJobSuccess {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone()
     }
 }
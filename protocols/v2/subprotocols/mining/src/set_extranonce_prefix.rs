@@ -6,6 +6,9 @@ use binary_sv2::{Deserialize, Serialize, B032};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{CHANNEL_BIT_SET_EXTRANONCE_PREFIX, MESSAGE_TYPE_SET_EXTRANONCE_PREFIX};
+
 /// # SetExtranoncePrefix (Server -> Client)
 ///
 /// Changes downstream node’s extranonce prefix. It is applicable for all jobs sent after this
@@ -20,6 +23,11 @@ pub struct SetExtranoncePrefix<'decoder> {
     #[cfg_attr(feature = "with_serde", serde(borrow))]
     pub extranonce_prefix: B032<'decoder>,
 }
+
+impl<'decoder> MessageType for SetExtranoncePrefix<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SET_EXTRANONCE_PREFIX;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SET_EXTRANONCE_PREFIX;
+}
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
@@ -31,9 +39,12 @@ impl<'d> GetSize for SetExtranoncePrefix<'d> {
 #[cfg(feature = "with_serde")]
 impl<'a> SetExtranoncePrefix<'a> {
     pub fn into_static(self) -> SetExtranoncePrefix<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        SetExtranoncePrefix {
+            channel_id: self.channel_id,
+            extranonce_prefix: self.extranonce_prefix.into_static(),
+        }
     }
     pub fn as_static(&self) -> SetExtranoncePrefix<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
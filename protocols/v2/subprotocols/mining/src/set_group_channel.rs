@@ -6,6 +6,9 @@ use binary_sv2::{Deserialize, Seq064K, Serialize};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{CHANNEL_BIT_SET_GROUP_CHANNEL, MESSAGE_TYPE_SET_GROUP_CHANNEL};
+
 /// # SetGroupChannel (Server -> Client)
 ///
 /// Every standard channel is a member of a group of standard channels, addressed by the
@@ -30,6 +33,11 @@ pub struct SetGroupChannel<'decoder> {
     #[cfg_attr(feature = "with_serde", serde(borrow))]
     pub channel_ids: Seq064K<'decoder, u32>,
 }
+
+impl<'decoder> MessageType for SetGroupChannel<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SET_GROUP_CHANNEL;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SET_GROUP_CHANNEL;
+}
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
@@ -41,9 +49,12 @@ impl<'d> GetSize for SetGroupChannel<'d> {
 #[cfg(feature = "with_serde")]
 impl<'a> SetGroupChannel<'a> {
     pub fn into_static(self) -> SetGroupChannel<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        SetGroupChannel {
+            group_channel_id: self.group_channel_id,
+            channel_ids: self.channel_ids.into_static(),
+        }
     }
     pub fn as_static(&self) -> SetGroupChannel<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
@@ -6,6 +6,9 @@ use binary_sv2::{Deserialize, Serialize, U256};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{CHANNEL_BIT_MINING_SET_NEW_PREV_HASH, MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH};
+
 /// # SetNewPrevHash (Server -> Client, broadcast)
 ///
 /// Prevhash is distributed whenever a new block is detected in the network by an upstream node.
@@ -32,6 +35,11 @@ pub struct SetNewPrevHash<'decoder> {
     pub nbits: u32,
 }
 
+impl<'decoder> MessageType for SetNewPrevHash<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_MINING_SET_NEW_PREV_HASH;
+}
+
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
@@ -47,9 +55,15 @@ impl<'d> GetSize for SetNewPrevHash<'d> {
 #[cfg(feature = "with_serde")]
 impl<'a> SetNewPrevHash<'a> {
     pub fn into_static(self) -> SetNewPrevHash<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        SetNewPrevHash {
+            channel_id: self.channel_id,
+            job_id: self.job_id,
+            prev_hash: self.prev_hash.into_static(),
+            min_ntime: self.min_ntime,
+            nbits: self.nbits,
+        }
     }
     pub fn as_static(&self) -> SetNewPrevHash<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
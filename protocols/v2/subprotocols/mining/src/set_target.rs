@@ -6,6 +6,9 @@ use binary_sv2::{Deserialize, Serialize, U256};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{CHANNEL_BIT_SET_TARGET, MESSAGE_TYPE_SET_TARGET};
+
 /// # SetTarget (Server -> Client)
 ///
 /// The server controls the submission rate by adjusting the difficulty target on a specified
@@ -26,6 +29,26 @@ pub struct SetTarget<'decoder> {
     #[cfg_attr(feature = "with_serde", serde(borrow))]
     pub maximum_target: U256<'decoder>,
 }
+
+impl SetTarget<'static> {
+    pub fn new(channel_id: u32, maximum_target: impl Into<crate::Target>) -> Self {
+        Self {
+            channel_id,
+            maximum_target: maximum_target.into().into(),
+        }
+    }
+}
+
+impl<'decoder> SetTarget<'decoder> {
+    pub fn target(&self) -> crate::Target {
+        self.maximum_target.clone().into()
+    }
+}
+
+impl<'decoder> MessageType for SetTarget<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SET_TARGET;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SET_TARGET;
+}
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
@@ -37,9 +60,12 @@ impl<'d> GetSize for SetTarget<'d> {
 #[cfg(feature = "with_serde")]
 impl<'a> SetTarget<'a> {
     pub fn into_static(self) -> SetTarget<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        SetTarget {
+            channel_id: self.channel_id,
+            maximum_target: self.maximum_target.into_static(),
+        }
     }
     pub fn as_static(&self) -> SetTarget<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
@@ -6,6 +6,14 @@ use binary_sv2::{Deserialize, Serialize, Str0255, B032};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{
+    CHANNEL_BIT_SUBMIT_SHARES_ERROR, CHANNEL_BIT_SUBMIT_SHARES_EXTENDED,
+    CHANNEL_BIT_SUBMIT_SHARES_STANDARD, CHANNEL_BIT_SUBMIT_SHARES_SUCCESS,
+    MESSAGE_TYPE_SUBMIT_SHARES_ERROR, MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED,
+    MESSAGE_TYPE_SUBMIT_SHARES_STANDARD, MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS,
+};
+
 /// # SubmitSharesStandard (Client -> Server)
 ///
 /// Client sends result of its hashing work to the server.
@@ -28,6 +36,11 @@ pub struct SubmitSharesStandard {
     /// Full nVersion field.
     pub version: u32,
 }
+
+impl MessageType for SubmitSharesStandard {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SUBMIT_SHARES_STANDARD;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SUBMIT_SHARES_STANDARD;
+}
 /// # SubmitSharesExtended (Client -> Server)
 /// Only relevant for extended channels. The message is the same as SubmitShares, with the
 /// following additional field:
@@ -59,6 +72,11 @@ pub struct SubmitSharesExtended<'decoder> {
     pub extranonce: B032<'decoder>,
 }
 
+impl<'decoder> MessageType for SubmitSharesExtended<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SUBMIT_SHARES_EXTENDED;
+}
+
 /// # SubmitShares.Success (Server -> Client)
 ///
 /// Response to SubmitShares or SubmitSharesExtended, accepting results from the miner.
@@ -80,6 +98,11 @@ pub struct SubmitSharesSuccess {
     pub new_shares_sum: u64,
 }
 
+impl MessageType for SubmitSharesSuccess {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SUBMIT_SHARES_SUCCESS;
+}
+
 /// # SubmitShares.Error (Server -> Client)
 ///
 /// An error is immediately submitted for every incorrect submit attempt. In case the server is not
@@ -100,6 +123,11 @@ pub struct SubmitSharesError<'decoder> {
     pub error_code: Str0255<'decoder>,
 }
 
+impl<'decoder> MessageType for SubmitSharesError<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SUBMIT_SHARES_ERROR;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SUBMIT_SHARES_ERROR;
+}
+
 impl<'a> SubmitSharesError<'a> {
     pub fn invalid_channel_error_code() -> &'static str {
         "invalid-channel-id"
@@ -157,18 +185,30 @@ impl<'d> GetSize for SubmitSharesError<'d> {
 #[cfg(feature = "with_serde")]
 impl<'a> SubmitSharesError<'a> {
     pub fn into_static(self) -> SubmitSharesError<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        SubmitSharesError {
+            channel_id: self.channel_id,
+            sequence_number: self.sequence_number,
+            error_code: self.error_code.into_static(),
+        }
     }
     pub fn as_static(&self) -> SubmitSharesError<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
 #[cfg(feature = "with_serde")]
 impl<'a> SubmitSharesExtended<'a> {
     pub fn into_static(self) -> SubmitSharesExtended<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        SubmitSharesExtended {
+            channel_id: self.channel_id,
+            sequence_number: self.sequence_number,
+            job_id: self.job_id,
+            nonce: self.nonce,
+            ntime: self.ntime,
+            version: self.version,
+            extranonce: self.extranonce.into_static(),
+        }
     }
     pub fn as_static(&self) -> SubmitSharesExtended<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
@@ -6,6 +6,12 @@ use binary_sv2::{Deserialize, Serialize, Str0255, U256};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{
+    CHANNEL_BIT_UPDATE_CHANNEL, CHANNEL_BIT_UPDATE_CHANNEL_ERROR, MESSAGE_TYPE_UPDATE_CHANNEL,
+    MESSAGE_TYPE_UPDATE_CHANNEL_ERROR,
+};
+
 /// # UpdateChannel (Client -> Server)
 ///
 /// Client notifies the server about changes on the specified channel. If a client performs
@@ -32,6 +38,11 @@ pub struct UpdateChannel<'decoder> {
     pub maximum_target: U256<'decoder>,
 }
 
+impl<'decoder> MessageType for UpdateChannel<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_UPDATE_CHANNEL;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_UPDATE_CHANNEL;
+}
+
 /// # Update.Error (Server -> Client)
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct UpdateChannelError<'decoder> {
@@ -44,6 +55,11 @@ pub struct UpdateChannelError<'decoder> {
     #[cfg_attr(feature = "with_serde", serde(borrow))]
     pub error_code: Str0255<'decoder>,
 }
+
+impl<'decoder> MessageType for UpdateChannelError<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_UPDATE_CHANNEL_ERROR;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_UPDATE_CHANNEL_ERROR;
+}
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
@@ -61,18 +77,25 @@ impl<'d> GetSize for UpdateChannelError<'d> {
 #[cfg(feature = "with_serde")]
 impl<'a> UpdateChannel<'a> {
     pub fn into_static(self) -> UpdateChannel<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        UpdateChannel {
+            channel_id: self.channel_id,
+            nominal_hash_rate: self.nominal_hash_rate,
+            maximum_target: self.maximum_target.into_static(),
+        }
     }
     pub fn as_static(&self) -> UpdateChannel<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
 #[cfg(feature = "with_serde")]
 impl<'a> UpdateChannelError<'a> {
     pub fn into_static(self) -> UpdateChannelError<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        UpdateChannelError {
+            channel_id: self.channel_id,
+            error_code: self.error_code.into_static(),
+        }
     }
     pub fn as_static(&self) -> UpdateChannelError<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
@@ -0,0 +1,141 @@
+//! # Version rolling (BIP320)
+//!
+//! BIP320 reserves 16 bits of the block header version field (bits 13 to 28) as general purpose
+//! bits that miners can roll to extend the search space. When a job is sent with
+//! `version_rolling_allowed` set to false the downstream MUST NOT change the version, otherwise
+//! it can only change the general purpose bits.
+use crate::{SubmitSharesExtended, SubmitSharesStandard};
+
+/// Bits of the version field that BIP320 allows to roll
+pub const BIP320_VERSION_ROLLING_MASK: u32 = 0x1fff_e000;
+
+/// Errors returned when a share version do not respect the job version rolling rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionRollingError {
+    /// Bits rolled by downstream that are not allowed by the mask
+    ForbiddenBits(u32),
+}
+
+/// Returns the mask that downstream can roll for a job with the given `version_rolling_allowed`
+pub fn allowed_version_mask(version_rolling_allowed: bool) -> u32 {
+    if version_rolling_allowed {
+        BIP320_VERSION_ROLLING_MASK
+    } else {
+        0
+    }
+}
+
+/// Restricts a mask requested by downstream (e.g. in a SV1 `mining.configure`) to the bits that
+/// BIP320 allows to roll
+pub fn negotiate_version_mask(requested_mask: u32) -> u32 {
+    requested_mask & BIP320_VERSION_ROLLING_MASK
+}
+
+/// Returns the bits that have been rolled in `share_version` relative to `job_version`
+pub fn rolled_bits(job_version: u32, share_version: u32) -> u32 {
+    job_version ^ share_version
+}
+
+/// Replaces the bits of `job_version` in `mask` with the ones in `rolled_version`. Used when a
+/// version rolled by downstream must be applied to a job (e.g. a SV1 share with version bits).
+pub fn apply_rolled_bits(job_version: u32, rolled_version: u32, mask: u32) -> u32 {
+    (job_version & !mask) | (rolled_version & mask)
+}
+
+/// Checks that downstream only rolled bits in `mask`
+pub fn check_version_rolling(
+    job_version: u32,
+    share_version: u32,
+    mask: u32,
+) -> Result<(), VersionRollingError> {
+    let forbidden = rolled_bits(job_version, share_version) & !mask;
+    if forbidden == 0 {
+        Ok(())
+    } else {
+        Err(VersionRollingError::ForbiddenBits(forbidden))
+    }
+}
+
+impl SubmitSharesStandard {
+    /// Checks that the share version respects the rules of the job it refers to
+    pub fn check_version_rolling(
+        &self,
+        job_version: u32,
+        version_rolling_allowed: bool,
+    ) -> Result<(), VersionRollingError> {
+        check_version_rolling(
+            job_version,
+            self.version,
+            allowed_version_mask(version_rolling_allowed),
+        )
+    }
+}
+
+impl<'a> SubmitSharesExtended<'a> {
+    /// Checks that the share version respects the rules of the job it refers to
+    pub fn check_version_rolling(
+        &self,
+        job_version: u32,
+        version_rolling_allowed: bool,
+    ) -> Result<(), VersionRollingError> {
+        check_version_rolling(
+            job_version,
+            self.version,
+            allowed_version_mask(version_rolling_allowed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JOB_VERSION: u32 = 0x2000_0000;
+
+    #[test]
+    fn test_negotiate_version_mask() {
+        assert_eq!(
+            negotiate_version_mask(0xffff_ffff),
+            BIP320_VERSION_ROLLING_MASK
+        );
+        assert_eq!(negotiate_version_mask(0x0000_6000), 0x0000_6000);
+        assert_eq!(negotiate_version_mask(0x0000_1fff), 0);
+    }
+
+    #[test]
+    fn test_apply_rolled_bits() {
+        let rolled = apply_rolled_bits(JOB_VERSION, 0xffff_ffff, BIP320_VERSION_ROLLING_MASK);
+        assert_eq!(rolled, 0x3fff_e000);
+        assert_eq!(
+            rolled_bits(JOB_VERSION, rolled),
+            BIP320_VERSION_ROLLING_MASK
+        );
+    }
+
+    #[test]
+    fn test_check_version_rolling() {
+        let mut share = SubmitSharesStandard {
+            channel_id: 1,
+            sequence_number: 0,
+            job_id: 1,
+            nonce: 0,
+            ntime: 0,
+            version: JOB_VERSION,
+        };
+        assert!(share.check_version_rolling(JOB_VERSION, false).is_ok());
+        assert!(share.check_version_rolling(JOB_VERSION, true).is_ok());
+
+        share.version = JOB_VERSION | 0x0000_e000;
+        assert_eq!(
+            share.check_version_rolling(JOB_VERSION, false),
+            Err(VersionRollingError::ForbiddenBits(0x0000_e000))
+        );
+        assert!(share.check_version_rolling(JOB_VERSION, true).is_ok());
+
+        share.version = JOB_VERSION | 0x0000_0001;
+        assert_eq!(
+            share.check_version_rolling(JOB_VERSION, true),
+            Err(VersionRollingError::ForbiddenBits(0x0000_0001))
+        );
+    }
+}
@@ -6,6 +6,11 @@ use binary_sv2::{Deserialize, Serialize};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{
+    CHANNEL_BIT_COINBASE_OUTPUT_DATA_SIZE, MESSAGE_TYPE_COINBASE_OUTPUT_DATA_SIZE,
+};
+
 /// ## CoinbaseOutputDataSize (Client -> Server)
 /// Ultimately, the pool is responsible for adding coinbase transaction outputs for payouts and
 /// other uses, and thus the Template Provider will need to consider this additional block size
@@ -28,6 +33,11 @@ pub struct CoinbaseOutputDataSize {
     pub coinbase_output_max_additional_size: u32,
 }
 
+impl MessageType for CoinbaseOutputDataSize {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_COINBASE_OUTPUT_DATA_SIZE;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_COINBASE_OUTPUT_DATA_SIZE;
+}
+
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
 #[cfg(feature = "with_serde")]
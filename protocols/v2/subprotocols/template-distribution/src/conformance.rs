@@ -0,0 +1,96 @@
+//! Encodings of the template distribution messages that the serde and the no-serde backends have to
+//! agree on, the bytes are in `test-vectors/template-distribution.json`. Run the tests with and
+//! without `with_serde`.
+use super::*;
+use binary_sv2::{assert_test_vector, conformance::bytes, Seq0255, Seq064K};
+
+const VECTORS: &str = include_str!("../../test-vectors/template-distribution.json");
+
+#[test]
+fn coinbase_output_data_size() {
+    assert_test_vector!(
+        VECTORS,
+        CoinbaseOutputDataSize = CoinbaseOutputDataSize {
+            coinbase_output_max_additional_size: 1,
+        }
+    );
+}
+
+#[test]
+fn new_template() {
+    assert_test_vector!(
+        VECTORS,
+        NewTemplate = NewTemplate {
+            template_id: 1,
+            future_template: true,
+            version: 2,
+            coinbase_tx_version: 2,
+            coinbase_prefix: bytes(2, 0x03),
+            coinbase_tx_input_sequence: 4,
+            coinbase_tx_value_remaining: 5,
+            coinbase_tx_outputs_count: 1,
+            coinbase_tx_outputs: bytes(3, 0x06),
+            coinbase_tx_locktime: 7,
+            merkle_path: Seq0255::new(vec![[0x08; 32].into()]).unwrap(),
+        }
+    );
+}
+
+#[test]
+fn request_transaction_data() {
+    assert_test_vector!(
+        VECTORS,
+        RequestTransactionData = RequestTransactionData { template_id: 1 }
+    );
+}
+
+#[test]
+fn request_transaction_data_success() {
+    assert_test_vector!(
+        VECTORS,
+        RequestTransactionDataSuccess = RequestTransactionDataSuccess {
+            template_id: 1,
+            excess_data: bytes(1, 0x02),
+            transaction_list: Seq064K::new(vec![bytes(2, 0x03), bytes(3, 0x04)]).unwrap(),
+        }
+    );
+}
+
+#[test]
+fn request_transaction_data_error() {
+    assert_test_vector!(
+        VECTORS,
+        RequestTransactionDataError = RequestTransactionDataError {
+            template_id: 1,
+            error_code: bytes(3, b'e'),
+        }
+    );
+}
+
+#[test]
+fn set_new_prev_hash() {
+    assert_test_vector!(
+        VECTORS,
+        SetNewPrevHash = SetNewPrevHash {
+            template_id: 1,
+            prev_hash: [0x02; 32].into(),
+            header_timestamp: 3,
+            n_bits: 4,
+            target: [0x05; 32].into(),
+        }
+    );
+}
+
+#[test]
+fn submit_solution() {
+    assert_test_vector!(
+        VECTORS,
+        SubmitSolution = SubmitSolution {
+            template_id: 1,
+            version: 2,
+            header_timestamp: 3,
+            header_nonce: 4,
+            coinbase_tx: bytes(2, 0x05),
+        }
+    );
+}
@@ -28,26 +28,54 @@ use core::convert::TryInto;
 use quickcheck::{Arbitrary, Gen};
 
 mod coinbase_output_data_size;
+#[cfg(test)]
+mod conformance;
 mod new_template;
 mod request_transaction_data;
 mod set_new_prev_hash;
 mod submit_solution;
 //
-pub use coinbase_output_data_size::CoinbaseOutputDataSize;
+pub use coinbase_output_data_size::{
+    CoinbaseOutputDataSize, CHANNEL_BIT_COINBASE_OUTPUT_DATA_SIZE,
+    MESSAGE_TYPE_COINBASE_OUTPUT_DATA_SIZE,
+};
 #[cfg(not(feature = "with_serde"))]
 pub use new_template::CNewTemplate;
-pub use new_template::NewTemplate;
+pub use new_template::{NewTemplate, CHANNEL_BIT_NEW_TEMPLATE, MESSAGE_TYPE_NEW_TEMPLATE};
 #[cfg(not(feature = "with_serde"))]
 pub use request_transaction_data::{CRequestTransactionDataError, CRequestTransactionDataSuccess};
 pub use request_transaction_data::{
     RequestTransactionData, RequestTransactionDataError, RequestTransactionDataSuccess,
+    CHANNEL_BIT_REQUEST_TRANSACTION_DATA, CHANNEL_BIT_REQUEST_TRANSACTION_DATA_ERROR,
+    CHANNEL_BIT_REQUEST_TRANSACTION_DATA_SUCCESS, MESSAGE_TYPE_REQUEST_TRANSACTION_DATA,
+    MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_ERROR, MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_SUCCESS,
 };
 #[cfg(not(feature = "with_serde"))]
 pub use set_new_prev_hash::CSetNewPrevHash;
-pub use set_new_prev_hash::SetNewPrevHash;
+pub use set_new_prev_hash::{
+    SetNewPrevHash, CHANNEL_BIT_SET_NEW_PREV_HASH, MESSAGE_TYPE_SET_NEW_PREV_HASH,
+};
 #[cfg(not(feature = "with_serde"))]
 pub use submit_solution::CSubmitSolution;
-pub use submit_solution::SubmitSolution;
+pub use submit_solution::{
+    SubmitSolution, CHANNEL_BIT_SUBMIT_SOLUTION, MESSAGE_TYPE_SUBMIT_SOLUTION,
+};
+
+pub use const_sv2::{message_type_of, MessageType, EXTENSION_TYPE_NO_EXTENSION};
+
+/// Name of the template distribution message with type `message_type`, for logging
+pub fn message_type_name(message_type: u8) -> Option<&'static str> {
+    match message_type {
+        MESSAGE_TYPE_COINBASE_OUTPUT_DATA_SIZE => Some("CoinbaseOutputDataSize"),
+        MESSAGE_TYPE_NEW_TEMPLATE => Some("NewTemplate"),
+        MESSAGE_TYPE_REQUEST_TRANSACTION_DATA => Some("RequestTransactionData"),
+        MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_SUCCESS => Some("RequestTransactionDataSuccess"),
+        MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_ERROR => Some("RequestTransactionDataError"),
+        MESSAGE_TYPE_SET_NEW_PREV_HASH => Some("SetNewPrevHash"),
+        MESSAGE_TYPE_SUBMIT_SOLUTION => Some("SubmitSolution"),
+        _ => None,
+    }
+}
 
 #[no_mangle]
 pub extern "C" fn _c_export_coinbase_out(_a: CoinbaseOutputDataSize) {}
@@ -169,3 +197,51 @@ impl SubmitSolution<'static> {
         }
     }
 }
+
+#[cfg(feature = "prop_test")]
+macro_rules! impl_arbitrary_from_gen {
+    ($($message:ty),*) => {$(
+        impl Arbitrary for $message {
+            fn arbitrary(g: &mut Gen) -> Self {
+                Self::from_gen(g)
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "prop_test")]
+impl_arbitrary_from_gen!(
+    CoinbaseOutputDataSize,
+    RequestTransactionData,
+    RequestTransactionDataError<'static>,
+    SubmitSolution<'static>
+);
+#[cfg(all(feature = "prop_test", not(feature = "with_serde")))]
+impl_arbitrary_from_gen!(
+    RequestTransactionDataSuccess<'static>,
+    SetNewPrevHash<'static>
+);
+
+#[cfg(all(test, feature = "prop_test", not(feature = "with_serde")))]
+mod round_trip {
+    use super::*;
+
+    macro_rules! round_trip_tests {
+        ($($name:ident: $message:ty,)*) => {$(
+            #[test]
+            fn $name() {
+                quickcheck::quickcheck(binary_sv2::round_trip::<$message> as fn($message) -> bool);
+            }
+        )*};
+    }
+
+    round_trip_tests! {
+        coinbase_output_data_size: CoinbaseOutputDataSize,
+        new_template: NewTemplate<'static>,
+        request_transaction_data: RequestTransactionData,
+        request_transaction_data_success: RequestTransactionDataSuccess<'static>,
+        request_transaction_data_error: RequestTransactionDataError<'static>,
+        set_new_prev_hash: SetNewPrevHash<'static>,
+        submit_solution: SubmitSolution<'static>,
+    }
+}
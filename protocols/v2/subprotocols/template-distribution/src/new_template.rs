@@ -10,6 +10,9 @@ use core::convert::TryInto;
 #[cfg(all(feature = "with_serde", not(feature = "no_std")))]
 use std::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{CHANNEL_BIT_NEW_TEMPLATE, MESSAGE_TYPE_NEW_TEMPLATE};
+
 /// ## NewTemplate (Server -> Client)
 /// The primary template-providing function. Note that the coinbase_tx_outputs bytes will appear
 /// as is at the end of the coinbase transaction.
@@ -55,6 +58,11 @@ pub struct NewTemplate<'decoder> {
     pub merkle_path: Seq0255<'decoder, U256<'decoder>>,
 }
 
+impl<'decoder> MessageType for NewTemplate<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_NEW_TEMPLATE;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_NEW_TEMPLATE;
+}
+
 #[repr(C)]
 #[cfg(not(feature = "with_serde"))]
 pub struct CNewTemplate {
@@ -158,10 +166,22 @@ impl<'d> GetSize for NewTemplate<'d> {
 #[cfg(feature = "with_serde")]
 impl<'a> NewTemplate<'a> {
     pub fn into_static(self) -> NewTemplate<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        NewTemplate {
+            template_id: self.template_id,
+            future_template: self.future_template,
+            version: self.version,
+            coinbase_tx_version: self.coinbase_tx_version,
+            coinbase_prefix: self.coinbase_prefix.into_static(),
+            coinbase_tx_input_sequence: self.coinbase_tx_input_sequence,
+            coinbase_tx_value_remaining: self.coinbase_tx_value_remaining,
+            coinbase_tx_outputs_count: self.coinbase_tx_outputs_count,
+            coinbase_tx_outputs: self.coinbase_tx_outputs.into_static(),
+            coinbase_tx_locktime: self.coinbase_tx_locktime,
+            merkle_path: self.merkle_path.into_static(),
+        }
     }
     pub fn as_static(&self) -> NewTemplate<'static> {
-        panic!("This function shouldn't be called by the Message Generator");
+        self.clone().into_static()
     }
 }
 
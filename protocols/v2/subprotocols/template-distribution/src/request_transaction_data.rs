@@ -8,6 +8,13 @@ use binary_sv2::{Deserialize, Seq064K, Serialize, Str0255, B016M, B064K};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{
+    CHANNEL_BIT_REQUEST_TRANSACTION_DATA, CHANNEL_BIT_REQUEST_TRANSACTION_DATA_ERROR,
+    CHANNEL_BIT_REQUEST_TRANSACTION_DATA_SUCCESS, MESSAGE_TYPE_REQUEST_TRANSACTION_DATA,
+    MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_ERROR, MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_SUCCESS,
+};
+
 /// ## RequestTransactionData (Client -> Server)
 /// A request sent by the Job Declarator to the Template Provider which requests the set of
 /// transaction data for all transactions (excluding the coinbase transaction) included in a block,
@@ -19,6 +26,11 @@ pub struct RequestTransactionData {
     pub template_id: u64,
 }
 
+impl MessageType for RequestTransactionData {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_REQUEST_TRANSACTION_DATA;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_REQUEST_TRANSACTION_DATA;
+}
+
 /// ## RequestTransactionData.Success (Server->Client)
 /// A response to [`RequestTransactionData`] which contains the set of full transaction data and
 /// excess data required for validation. For practical purposes, the excess data is usually the
@@ -58,6 +70,11 @@ pub struct RequestTransactionDataSuccess<'decoder> {
     pub transaction_list: Seq064K<'decoder, B016M<'decoder>>,
 }
 
+impl<'decoder> MessageType for RequestTransactionDataSuccess<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_SUCCESS;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_REQUEST_TRANSACTION_DATA_SUCCESS;
+}
+
 #[repr(C)]
 #[cfg(not(feature = "with_serde"))]
 pub struct CRequestTransactionDataSuccess {
@@ -122,6 +139,11 @@ pub struct RequestTransactionDataError<'decoder> {
     pub error_code: Str0255<'decoder>,
 }
 
+impl<'decoder> MessageType for RequestTransactionDataError<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_ERROR;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_REQUEST_TRANSACTION_DATA_ERROR;
+}
+
 #[repr(C)]
 #[cfg(not(feature = "with_serde"))]
 pub struct CRequestTransactionDataError {
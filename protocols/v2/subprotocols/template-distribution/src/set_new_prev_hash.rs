@@ -8,6 +8,9 @@ use binary_sv2::{Deserialize, Serialize, U256};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{CHANNEL_BIT_SET_NEW_PREV_HASH, MESSAGE_TYPE_SET_NEW_PREV_HASH};
+
 /// ## SetNewPrevHash (Server -> Client)
 /// Upon successful validation of a new best block, the server MUST immediately provide a
 /// SetNewPrevHash message. If a [NewWork] message has previously been sent with the
@@ -35,6 +38,11 @@ pub struct SetNewPrevHash<'decoder> {
     pub target: U256<'decoder>,
 }
 
+impl<'decoder> MessageType for SetNewPrevHash<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SET_NEW_PREV_HASH;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SET_NEW_PREV_HASH;
+}
+
 #[cfg(not(feature = "with_serde"))]
 #[repr(C)]
 pub struct CSetNewPrevHash {
@@ -8,6 +8,9 @@ use binary_sv2::{Deserialize, Serialize, B064K};
 #[cfg(not(feature = "with_serde"))]
 use core::convert::TryInto;
 
+use const_sv2::MessageType;
+pub use const_sv2::{CHANNEL_BIT_SUBMIT_SOLUTION, MESSAGE_TYPE_SUBMIT_SOLUTION};
+
 /// ## SubmitSolution (Client -> Server)
 /// Upon finding a coinbase transaction/nonce pair which double-SHA256 hashes at or below
 /// [`crate::SetNewPrevHash.target`], the client MUST immediately send this message, and the server
@@ -34,6 +37,11 @@ pub struct SubmitSolution<'decoder> {
     pub coinbase_tx: B064K<'decoder>,
 }
 
+impl<'decoder> MessageType for SubmitSolution<'decoder> {
+    const MESSAGE_TYPE: u8 = MESSAGE_TYPE_SUBMIT_SOLUTION;
+    const CHANNEL_BIT: bool = CHANNEL_BIT_SUBMIT_SOLUTION;
+}
+
 #[cfg(not(feature = "with_serde"))]
 #[repr(C)]
 pub struct CSubmitSolution {
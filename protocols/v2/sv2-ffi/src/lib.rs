@@ -38,6 +38,9 @@ use const_sv2::{
 };
 use core::convert::{TryFrom, TryInto};
 
+// The noise handshake and transport encryption functions are part of the library
+pub use noise_sv2::ffi as noise;
+
 #[derive(Clone, Debug)]
 pub enum Sv2Message<'a> {
     CoinbaseOutputDataSize(CoinbaseOutputDataSize),
@@ -461,6 +461,10 @@ struct CError {
     FramingError,
     /// Framing Sv2 error.
     FramingSv2Error,
+    /// The frame, of the given size, is bigger than the configured maximum slice size.
+    FrameTooLarge,
+    /// The Noise handshake did not complete before its deadline.
+    HandshakeTimeout,
     /// Invalid step for initiator in the Noise protocol.
     InvalidStepForInitiator,
     /// Invalid step for responder in the Noise protocol.
@@ -471,16 +475,23 @@ struct CError {
     NoiseSv2Error,
     /// Noise protocol is not in the expected handshake state.
     NotInHandShakeState,
+    /// The buffer pool is full and its exhaustion policy does not allow to allocate.
+    PoolExhausted,
     /// Unexpected state in the Noise protocol.
     UnexpectedNoiseState,
   };
 
+  struct FrameTooLarge_Body {
+    uintptr_t _0;
+  };
+
   struct MissingBytes_Body {
     uintptr_t _0;
   };
 
   Tag tag;
   union {
+    FrameTooLarge_Body frame_too_large;
     MissingBytes_Body missing_bytes;
   };
 };
@@ -501,6 +512,150 @@ CError export_cerror();
 #include <ostream>
 #include <new>
 
+/// Outcome of the C API functions.
+enum class NoiseResult {
+  Ok,
+  /// A pointer argument is null.
+  NullPointer,
+  /// The handshake message is not valid or the responder certificate can not be verified.
+  InvalidHandshakeMessage,
+  /// The buffer is too small to hold the encrypted message.
+  BufferTooSmall,
+  /// Encryption or decryption failed, e.g. the message has been tampered with.
+  AeadError,
+};
+
+/// Manages the initiator's role in the Noise NX handshake, handling key exchange, encryption, and
+/// handshake state. It securely generates and manages cryptographic keys, performs Diffie-Hellman
+/// exchanges, and maintains the handshake hash, chaining key, and nonce for message encryption.
+/// After the handshake, it facilitates secure communication using either [`ChaCha20Poly1305`] or
+/// `AES-GCM` ciphers. Sensitive data is securely erased when no longer needed.
+struct Initiator;
+
+/// A codec for managing encrypted communication in the Noise protocol.
+///
+/// Manages the encryption and decryption of messages between two parties, the [`Initiator`] and
+/// [`Responder`], using the Noise protocol. A symmetric cipher is used for both encrypting
+/// outgoing messages and decrypting incoming messages.
+///
+/// The session keys are wiped from memory when the codec is dropped.
+struct NoiseCodec;
+
+/// Represents the state and operations of the responder in the Noise NX protocol handshake.
+/// It handles cryptographic key exchanges, manages handshake state, and securely establishes
+/// a connection with the initiator. The responder manages key generation, Diffie-Hellman exchanges,
+/// message decryption, and state transitions, ensuring secure communication. Sensitive
+/// cryptographic material is securely erased when no longer needed.
+struct Responder;
+
+/// Buffer owned by the C side, laid out like `CVec`: the first `len` bytes of `data` are used, out
+/// of `capacity`.
+///
+/// Encryption grows the content by the size of the MAC (`AEAD_MAC_LEN`), so `capacity` must be at
+/// least `len + AEAD_MAC_LEN` to encrypt a buffer.
+struct NoiseBuffer {
+  uint8_t *data;
+  uintptr_t len;
+  uintptr_t capacity;
+};
+
+extern "C" {
+
+/// Creates an initiator, `authority_public_key` is the 32 bytes x-only public key of the
+/// responder authority, or null to not verify the responder certificate.
+///
+/// Returns null if the key is not valid.
+///
+/// # Safety
+///
+/// `authority_public_key` must be null or point to 32 readable bytes.
+Initiator *new_noise_initiator(const uint8_t *authority_public_key);
+
+/// Creates a responder from its authority key pair, the certificates it sends are valid for
+/// `cert_validity_sec` seconds.
+///
+/// Returns null if the key pair is not valid.
+///
+/// # Safety
+///
+/// `public_key` and `private_key` must point to 32 readable bytes.
+Responder *new_noise_responder(const uint8_t *public_key,
+                               const uint8_t *private_key,
+                               uint32_t cert_validity_sec);
+
+/// Writes the first handshake message, to be sent to the responder, to `message_out`.
+///
+/// # Safety
+///
+/// `initiator` must come from [`new_noise_initiator`] and `message_out` must point to
+/// `ELLSWIFT_ENCODING_SIZE` writable bytes.
+NoiseResult noise_initiator_step_0(Initiator *initiator, uint8_t *message_out);
+
+/// Reads the first handshake message of the initiator from `message_in`, writes the answer to
+/// `message_out` and the codec for the rest of the connection to `codec_out`.
+///
+/// # Safety
+///
+/// `responder` must come from [`new_noise_responder`], `message_in` must point to
+/// `ELLSWIFT_ENCODING_SIZE` readable bytes, `message_out` to
+/// `INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE` writable bytes and `codec_out` to a writable
+/// pointer.
+NoiseResult noise_responder_step_1(Responder *responder,
+                                   const uint8_t *message_in,
+                                   uint8_t *message_out,
+                                   NoiseCodec **codec_out);
+
+/// Reads the answer of the responder from `message_in` and writes the codec for the rest of the
+/// connection to `codec_out`.
+///
+/// # Safety
+///
+/// `initiator` must come from [`new_noise_initiator`], `message_in` must point to
+/// `INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE` readable bytes and `codec_out` to a writable
+/// pointer.
+NoiseResult noise_initiator_step_2(Initiator *initiator,
+                                   const uint8_t *message_in,
+                                   NoiseCodec **codec_out);
+
+/// Encrypts the content of `buffer` in place, `buffer.len` grows by `AEAD_MAC_LEN`.
+///
+/// # Safety
+///
+/// `codec` must come from a handshake step and `buffer.data` must point to `buffer.capacity`
+/// writable bytes.
+NoiseResult noise_encrypt(NoiseCodec *codec, NoiseBuffer *buffer);
+
+/// Decrypts the content of `buffer` in place, `buffer.len` shrinks by `AEAD_MAC_LEN`.
+///
+/// # Safety
+///
+/// `codec` must come from a handshake step and `buffer.data` must point to `buffer.capacity`
+/// writable bytes.
+NoiseResult noise_decrypt(NoiseCodec *codec, NoiseBuffer *buffer);
+
+/// # Safety
+///
+/// `initiator` must be null or come from [`new_noise_initiator`] and not be used afterwards.
+void free_noise_initiator(Initiator *initiator);
+
+/// # Safety
+///
+/// `responder` must be null or come from [`new_noise_responder`] and not be used afterwards.
+void free_noise_responder(Responder *responder);
+
+/// # Safety
+///
+/// `codec` must be null or come from a handshake step and not be used afterwards.
+void free_noise_codec(NoiseCodec *codec);
+
+} // extern "C"
+
+#include <cstdarg>
+#include <cstdint>
+#include <cstdlib>
+#include <ostream>
+#include <new>
+
 struct DecoderWrapper;
 
 struct EncoderWrapper;
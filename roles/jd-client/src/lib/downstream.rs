@@ -19,7 +19,7 @@ use roles_logic_sv2::{
     template_distribution_sv2::{NewTemplate, SubmitSolution},
     utils::Mutex,
 };
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
 use codec_sv2::{HandshakeRole, Responder, StandardEitherFrame, StandardSv2Frame};
 use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
@@ -356,6 +356,7 @@ impl DownstreamMiningNode {
         self_mutex: &Arc<Mutex<Self>>,
         mut new_template: NewTemplate<'static>,
         pool_output: &[u8],
+        additional_outputs: &[TxOut],
     ) -> Result<(), Error> {
         if !self_mutex.safe_lock(|s| s.status.have_channel()).unwrap() {
             super::IS_NEW_TEMPLATE_HANDLED.store(true, std::sync::atomic::Ordering::Release);
@@ -367,7 +368,9 @@ impl DownstreamMiningNode {
         let to_send = self_mutex
             .safe_lock(|s| {
                 let channel = s.status.get_channel();
-                channel.update_pool_outputs(vec![pool_output]);
+                let mut outputs = vec![pool_output];
+                outputs.extend_from_slice(additional_outputs);
+                channel.update_pool_outputs(outputs);
                 channel.on_new_template(&mut new_template)
             })
             .unwrap()?;
@@ -378,11 +381,11 @@ impl DownstreamMiningNode {
         for message in to_send {
             let message = if let Mining::NewExtendedMiningJob(job) = message {
                 if let Some(jd) = self_mutex.safe_lock(|s| s.jd.clone()).unwrap() {
-                    jd.safe_lock(|jd| {
-                        jd.coinbase_tx_prefix = job.coinbase_tx_prefix.clone();
-                        jd.coinbase_tx_suffix = job.coinbase_tx_suffix.clone();
-                    })
-                    .unwrap();
+                    JobDeclarator::set_coinbase(
+                        &jd,
+                        job.coinbase_tx_prefix.clone(),
+                        job.coinbase_tx_suffix.clone(),
+                    );
                 }
                 Mining::NewExtendedMiningJob(job)
             } else {
@@ -529,10 +532,7 @@ impl
             self.status
                 .get_channel()
                 .update_target_for_channel(m.channel_id, maximum_target.clone().into());
-            let set_target = SetTarget {
-                channel_id: m.channel_id,
-                maximum_target,
-            };
+            let set_target = SetTarget::new(m.channel_id, maximum_target);
             Ok(SendTo::Respond(Mining::SetTarget(set_target)))
         }
     }
@@ -549,6 +549,13 @@ impl
         &mut self,
         m: SubmitSharesExtended,
     ) -> Result<SendTo<UpstreamMiningNode>, Error> {
+        let _span = info_span!(
+            "share",
+            channel_id = m.channel_id,
+            sequence_number = m.sequence_number,
+            job_id = m.job_id
+        )
+        .entered();
         match self
             .status
             .get_channel()
@@ -684,7 +691,8 @@ pub async fn listen_for_downstream_mining(
     info!("Listening for downstream mining connections on {}", address);
     let listner = TcpListener::bind(address).await.unwrap();
 
-    if let Ok((stream, _)) = listner.accept().await {
+    if let Ok((stream, addr)) = listner.accept().await {
+        let span = info_span!("downstream", addr = %addr);
         let responder = Responder::from_authority_kp(
             &authority_public_key.into_bytes(),
             &authority_secret_key.into_bytes(),
@@ -693,6 +701,7 @@ pub async fn listen_for_downstream_mining(
         .unwrap();
         let (receiver, sender, recv_task_abort_handler, send_task_abort_handler) =
             Connection::new(stream, HandshakeRole::Responder(responder))
+                .instrument(span.clone())
                 .await
                 .expect("impossible to connect");
         let node = DownstreamMiningNode::new(
@@ -730,11 +739,15 @@ pub async fn listen_for_downstream_mining(
                     roles_logic_sv2::parsers::CommonMessages::SetupConnectionSuccess(m) => m,
                     _ => panic!(),
                 };
+                let diagnostics = admin_sv2::diagnostics().open_connection(addr, "sv2 downstream");
                 let main_task = tokio::task::spawn({
                     let node = node.clone();
                     async move {
+                        // unregistered from the admin interface when the connection is closed
+                        let _diagnostics = diagnostics;
                         DownstreamMiningNode::start(&node, message).await;
                     }
+                    .instrument(span)
                 });
                 node.safe_lock(|n| {
                     n.task_collector
@@ -35,6 +35,8 @@ pub enum Error<'a> {
     BadCliArgs,
     /// Errors on bad `config` TOML deserialize.
     BadConfigDeserialize(ConfigError),
+    /// Errors on a config that can not be loaded or has invalid values.
+    InvalidConfig(config_helpers_sv2::ConfigError),
     /// Errors from `binary_sv2` crate.
     BinarySv2(binary_sv2::Error),
     /// Errors on bad noise handshake.
@@ -58,6 +60,8 @@ pub enum Error<'a> {
     // Channel Sender Errors
     ChannelErrorSender(ChannelSendError<'a>),
     Uint256Conversion(ParseLengthError),
+    /// The pool output and the additional coinbase outputs (size, max size allowed by the pool)
+    CoinbaseOutputsTooBig(usize, u32),
     Infallible(std::convert::Infallible),
 }
 
@@ -67,6 +71,7 @@ impl<'a> fmt::Display for Error<'a> {
         match self {
             BadCliArgs => write!(f, "Bad CLI arg input"),
             BadConfigDeserialize(ref e) => write!(f, "Bad `config` TOML deserialize: `{:?}`", e),
+            InvalidConfig(ref e) => write!(f, "{}", e),
             BinarySv2(ref e) => write!(f, "Binary SV2 error: `{:?}`", e),
             CodecNoise(ref e) => write!(f, "Noise error: `{:?}", e),
             FramingSv2(ref e) => write!(f, "Framing SV2 error: `{:?}`", e),
@@ -80,6 +85,11 @@ impl<'a> fmt::Display for Error<'a> {
             TokioChannelErrorRecv(ref e) => write!(f, "Channel receive error: `{:?}`", e),
             ChannelErrorSender(ref e) => write!(f, "Channel send error: `{:?}`", e),
             Uint256Conversion(ref e) => write!(f, "U256 Conversion Error: `{:?}`", e),
+            CoinbaseOutputsTooBig(size, max) => write!(
+                f,
+                "Coinbase outputs of {} bytes, the pool allows at most {} bytes",
+                size, max
+            ),
             VecToSlice32(ref e) => write!(f, "Standard Error: `{:?}`", e),
             Infallible(ref e) => write!(f, "Infallible Error:`{:?}`", e),
         }
@@ -128,6 +138,12 @@ impl<'a> From<ConfigError> for Error<'a> {
     }
 }
 
+impl<'a> From<config_helpers_sv2::ConfigError> for Error<'a> {
+    fn from(e: config_helpers_sv2::ConfigError) -> Self {
+        Error::InvalidConfig(e)
+    }
+}
+
 impl<'a> From<async_channel::RecvError> for Error<'a> {
     fn from(e: async_channel::RecvError) -> Self {
         Error::ChannelErrorReceiver(e)
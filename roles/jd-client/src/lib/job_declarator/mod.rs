@@ -1,78 +1,66 @@
-pub mod message_handler;
-use async_channel::{Receiver, Sender};
-use binary_sv2::{Seq0255, Seq064K, B016M, B064K, U256};
+use async_channel::{unbounded, Receiver, Sender};
+use binary_sv2::{Seq064K, B016M, B064K};
 use codec_sv2::{HandshakeRole, Initiator, StandardEitherFrame, StandardSv2Frame};
 use network_helpers_sv2::noise_connection_tokio::Connection;
 use roles_logic_sv2::{
-    handlers::SendTo_,
-    job_declaration_sv2::{AllocateMiningJobTokenSuccess, SubmitSolutionJd},
-    mining_sv2::SubmitSharesExtended,
+    errors::Error as RolesLogicError,
+    handlers::job_declaration::ParseServerJobDeclarationMessages,
+    job_declaration_sv2::AllocateMiningJobTokenSuccess,
+    job_declarator_client::{JobDeclaratorClient, JobDeclaratorSender, DEFAULT_MIN_TOKENS},
+    mining_sv2::{SetCustomMiningJob, SubmitSharesExtended},
     parsers::{JobDeclaration, PoolMessages},
-    template_distribution_sv2::SetNewPrevHash,
-    utils::{hash_lists_tuple, Mutex},
+    template_distribution_sv2::{NewTemplate, SetNewPrevHash},
+    utils::Mutex,
 };
-use std::{collections::HashMap, convert::TryInto, str::FromStr};
-use stratum_common::bitcoin::{util::psbt::serialize::Deserialize, Transaction};
+use std::{convert::TryInto, str::FromStr};
 use tokio::task::AbortHandle;
 use tracing::{error, info};
 
-use async_recursion::async_recursion;
-use nohash_hasher::BuildNoHashHasher;
-use roles_logic_sv2::{
-    handlers::job_declaration::ParseServerJobDeclarationMessages,
-    job_declaration_sv2::{AllocateMiningJobToken, DeclareMiningJob},
-    template_distribution_sv2::NewTemplate,
-    utils::Id,
-};
 use std::{
     net::{IpAddr, SocketAddr},
     sync::Arc,
 };
 
 pub type Message = PoolMessages<'static>;
-pub type SendTo = SendTo_<JobDeclaration<'static>, ()>;
 pub type StdFrame = StandardSv2Frame<Message>;
 
 mod setup_connection;
 use setup_connection::SetupConnectionHandler;
 
-use super::{error::Error, proxy_config::ProxyConfig, upstream_sv2::Upstream};
+use super::{error::Error, proxy_config::ProxyConfig, status, upstream_sv2::Upstream};
+
+/// Sends the messages of the [`JobDeclaratorClient`] to the tasks that forward them to the JDS
+/// and to the pool
+#[derive(Debug)]
+pub struct JdSender {
+    jds: Sender<JobDeclaration<'static>>,
+    pool: Sender<(SetCustomMiningJob<'static>, u64)>,
+}
+
+impl JobDeclaratorSender for JdSender {
+    fn send_to_jds(&mut self, message: JobDeclaration<'static>) -> Result<(), RolesLogicError> {
+        self.jds
+            .try_send(message)
+            .map_err(|_| RolesLogicError::ConnectionClosed("JDS".to_string()))
+    }
 
-#[derive(Debug, Clone)]
-pub struct LastDeclareJob {
-    declare_job: DeclareMiningJob<'static>,
-    template: NewTemplate<'static>,
-    coinbase_pool_output: Vec<u8>,
-    tx_list: Seq064K<'static, B016M<'static>>,
+    fn send_to_pool(
+        &mut self,
+        job: SetCustomMiningJob<'static>,
+        template_id: u64,
+    ) -> Result<(), RolesLogicError> {
+        self.pool
+            .try_send((job, template_id))
+            .map_err(|_| RolesLogicError::ConnectionClosed("pool".to_string()))
+    }
 }
 
 #[derive(Debug)]
 pub struct JobDeclarator {
     receiver: Receiver<StandardEitherFrame<PoolMessages<'static>>>,
-    sender: Sender<StandardEitherFrame<PoolMessages<'static>>>,
-    allocated_tokens: Vec<AllocateMiningJobTokenSuccess<'static>>,
-    req_ids: Id,
-    min_extranonce_size: u16,
-    // (Sent DeclareMiningJob, is future, template id, merkle path)
-    last_declare_mining_jobs_sent: [Option<(u32, LastDeclareJob)>; 2],
-    last_set_new_prev_hash: Option<SetNewPrevHash<'static>>,
-    set_new_prev_hash_counter: u8,
-    #[allow(clippy::type_complexity)]
-    future_jobs: HashMap<
-        u64,
-        (
-            DeclareMiningJob<'static>,
-            Seq0255<'static, U256<'static>>,
-            NewTemplate<'static>,
-            // pool's outputs
-            Vec<u8>,
-        ),
-        BuildNoHashHasher<u64>,
-    >,
+    client: Arc<Mutex<JobDeclaratorClient<JdSender>>>,
     up: Arc<Mutex<Upstream>>,
     task_collector: Arc<Mutex<Vec<AbortHandle>>>,
-    pub coinbase_tx_prefix: B064K<'static>,
-    pub coinbase_tx_suffix: B064K<'static>,
 }
 
 impl JobDeclarator {
@@ -106,133 +94,84 @@ impl JobDeclarator {
 
         info!("JD CONNECTED");
 
-        let min_extranonce_size = config.min_extranonce2_size;
-
+        let (jds_sender, jds_receiver) = unbounded();
+        let (pool_sender, pool_receiver) = unbounded();
+        let client = JobDeclaratorClient::new(
+            JdSender {
+                jds: jds_sender,
+                pool: pool_sender,
+            },
+            "todo".to_string(),
+            DEFAULT_MIN_TOKENS,
+        );
         let self_ = Arc::new(Mutex::new(JobDeclarator {
             receiver,
-            sender,
-            allocated_tokens: vec![],
-            req_ids: Id::new(),
-            min_extranonce_size,
-            last_declare_mining_jobs_sent: [None, None],
-            last_set_new_prev_hash: None,
-            future_jobs: HashMap::with_hasher(BuildNoHashHasher::default()),
-            up,
-            task_collector,
-            coinbase_tx_prefix: vec![].try_into().unwrap(),
-            coinbase_tx_suffix: vec![].try_into().unwrap(),
-            set_new_prev_hash_counter: 0,
+            client: Arc::new(Mutex::new(client)),
+            up: up.clone(),
+            task_collector: task_collector.clone(),
         }));
 
-        Self::allocate_tokens(&self_, 2).await;
+        let to_jds = tokio::task::spawn(async move {
+            while let Ok(message) = jds_receiver.recv().await {
+                // Safe unwrap message is build by the client and is valid
+                let frame: StdFrame = PoolMessages::JobDeclaration(message).try_into().unwrap();
+                if sender.send(frame.into()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let to_pool = tokio::task::spawn(async move {
+            while let Ok((job, template_id)) = pool_receiver.recv().await {
+                if let Err(e) = Upstream::send_custom_job(&up, job, template_id).await {
+                    error!("Failed to send the custom job to the pool: {:?}", e);
+                }
+            }
+        });
+        task_collector
+            .safe_lock(|c| {
+                c.push(to_jds.abort_handle());
+                c.push(to_pool.abort_handle());
+            })
+            .unwrap();
+
+        Self::client(&self_)
+            .safe_lock(|c| c.allocate_tokens())
+            .unwrap()?;
         Self::on_upstream_message(self_.clone());
         Ok(self_)
     }
 
-    fn get_last_declare_job_sent(
-        self_mutex: &Arc<Mutex<Self>>,
-        request_id: u32,
-    ) -> Option<LastDeclareJob> {
-        self_mutex
-            .safe_lock(|s| {
-                for (id, job) in s.last_declare_mining_jobs_sent.iter().flatten() {
-                    if *id == request_id {
-                        return Some(job.to_owned());
-                    }
-                }
-                None
-            })
-            .unwrap()
+    fn client(self_mutex: &Arc<Mutex<Self>>) -> Arc<Mutex<JobDeclaratorClient<JdSender>>> {
+        self_mutex.safe_lock(|s| s.client.clone()).unwrap()
     }
 
-    /// We maintain a window of 2 jobs. If more than 2 blocks are found,
-    /// the ordering will depend on the request ID. Only the 2 most recent request
-    /// IDs will be kept in memory, while the rest will be discarded.
-    /// More information can be found here: https://github.com/stratum-mining/stratum/pull/904#discussion_r1609469048
-    fn update_last_declare_job_sent(
+    /// Sets the coinbase prefix and suffix of the jobs declared from now on
+    pub fn set_coinbase(
         self_mutex: &Arc<Mutex<Self>>,
-        request_id: u32,
-        j: LastDeclareJob,
+        prefix: B064K<'static>,
+        suffix: B064K<'static>,
     ) {
-        self_mutex
-            .safe_lock(|s| {
-                if let Some(empty_index) = s
-                    .last_declare_mining_jobs_sent
-                    .iter()
-                    .position(|entry| entry.is_none())
-                {
-                    s.last_declare_mining_jobs_sent[empty_index] = Some((request_id, j));
-                } else if let Some((min_index, _)) = s
-                    .last_declare_mining_jobs_sent
-                    .iter()
-                    .enumerate()
-                    .filter_map(|(i, entry)| entry.as_ref().map(|(id, _)| (i, id)))
-                    .min_by_key(|&(_, id)| id)
-                {
-                    s.last_declare_mining_jobs_sent[min_index] = Some((request_id, j));
-                }
-            })
+        Self::client(self_mutex)
+            .safe_lock(|c| c.set_coinbase(prefix, suffix))
             .unwrap();
     }
 
-    #[async_recursion]
+    /// Waits until a token is allocated by the JDS
     pub async fn get_last_token(
         self_mutex: &Arc<Mutex<Self>>,
     ) -> AllocateMiningJobTokenSuccess<'static> {
-        let mut token_len = self_mutex.safe_lock(|s| s.allocated_tokens.len()).unwrap();
-        match token_len {
-            0 => {
-                {
-                    let task = {
-                        let self_mutex = self_mutex.clone();
-                        tokio::task::spawn(async move {
-                            Self::allocate_tokens(&self_mutex, 2).await;
-                        })
-                    };
-                    self_mutex
-                        .safe_lock(|s| {
-                            s.task_collector
-                                .safe_lock(|c| c.push(task.abort_handle()))
-                                .unwrap()
-                        })
-                        .unwrap();
-                }
-
-                // we wait for token allocation to avoid infinite recursion
-                while token_len == 0 {
-                    tokio::task::yield_now().await;
-                    token_len = self_mutex.safe_lock(|s| s.allocated_tokens.len()).unwrap();
-                }
-
-                Self::get_last_token(self_mutex).await
-            }
-            1 => {
-                {
-                    let task = {
-                        let self_mutex = self_mutex.clone();
-                        tokio::task::spawn(async move {
-                            Self::allocate_tokens(&self_mutex, 1).await;
-                        })
-                    };
-                    self_mutex
-                        .safe_lock(|s| {
-                            s.task_collector
-                                .safe_lock(|c| c.push(task.abort_handle()))
-                                .unwrap()
-                        })
-                        .unwrap();
+        let client = Self::client(self_mutex);
+        loop {
+            match client.safe_lock(|c| c.take_token()).unwrap() {
+                Ok(Some(token)) => break token,
+                Ok(None) => tokio::task::yield_now().await,
+                Err(e) => {
+                    // The connection with the JDS is closed, the shutdown of the upstream aborts
+                    // the task waiting here
+                    error!("Failed to allocate a token: {}", e);
+                    std::future::pending::<()>().await
                 }
-                // There is a token, unwrap is safe
-                self_mutex
-                    .safe_lock(|s| s.allocated_tokens.pop())
-                    .unwrap()
-                    .unwrap()
             }
-            // There are tokens, unwrap is safe
-            _ => self_mutex
-                .safe_lock(|s| s.allocated_tokens.pop())
-                .unwrap()
-                .unwrap(),
         }
     }
 
@@ -240,241 +179,93 @@ impl JobDeclarator {
         self_mutex: &Arc<Mutex<Self>>,
         template: NewTemplate<'static>,
         token: Vec<u8>,
-        tx_list_: Seq064K<'static, B016M<'static>>,
+        tx_list: Seq064K<'static, B016M<'static>>,
         excess_data: B064K<'static>,
         coinbase_pool_output: Vec<u8>,
     ) {
-        let (id, _, sender) = self_mutex
-            .safe_lock(|s| (s.req_ids.next(), s.min_extranonce_size, s.sender.clone()))
+        let declared = Self::client(self_mutex)
+            .safe_lock(|c| {
+                c.declare_job(
+                    template,
+                    token.try_into()?,
+                    tx_list,
+                    excess_data,
+                    coinbase_pool_output,
+                )
+            })
             .unwrap();
-        // TODO: create right nonce
-        let tx_short_hash_nonce = 0;
-        let mut tx_list: Vec<Transaction> = Vec::new();
-        for tx in tx_list_.to_vec() {
-            //TODO remove unwrap
-            let tx = Transaction::deserialize(&tx).unwrap();
-            tx_list.push(tx);
+        if let Err(e) = declared {
+            error!("Failed to declare the job: {}", e);
         }
-        let declare_job = DeclareMiningJob {
-            request_id: id,
-            mining_job_token: token.try_into().unwrap(),
-            version: template.version,
-            coinbase_prefix: self_mutex
-                .safe_lock(|s| s.coinbase_tx_prefix.clone())
-                .unwrap(),
-            coinbase_suffix: self_mutex
-                .safe_lock(|s| s.coinbase_tx_suffix.clone())
-                .unwrap(),
-            tx_short_hash_nonce,
-            tx_short_hash_list: hash_lists_tuple(tx_list.clone(), tx_short_hash_nonce).0,
-            tx_hash_list_hash: hash_lists_tuple(tx_list.clone(), tx_short_hash_nonce).1,
-            excess_data, // request transaction data
-        };
-        let last_declare = LastDeclareJob {
-            declare_job: declare_job.clone(),
-            template,
-            coinbase_pool_output,
-            tx_list: tx_list_.clone(),
-        };
-        Self::update_last_declare_job_sent(self_mutex, id, last_declare);
-        let frame: StdFrame =
-            PoolMessages::JobDeclaration(JobDeclaration::DeclareMiningJob(declare_job))
-                .try_into()
-                .unwrap();
-        sender.send(frame.into()).await.unwrap();
     }
 
     pub fn on_upstream_message(self_mutex: Arc<Mutex<Self>>) {
-        let up = self_mutex.safe_lock(|s| s.up.clone()).unwrap();
+        let (up, client, task_collector) = self_mutex
+            .safe_lock(|s| (s.up.clone(), s.client.clone(), s.task_collector.clone()))
+            .unwrap();
         let main_task = {
             let self_mutex = self_mutex.clone();
             tokio::task::spawn(async move {
                 let receiver = self_mutex.safe_lock(|d| d.receiver.clone()).unwrap();
                 loop {
-                    let mut incoming: StdFrame = receiver.recv().await.unwrap().try_into().unwrap();
+                    let incoming = match receiver.recv().await {
+                        Ok(incoming) => incoming,
+                        Err(e) => {
+                            error!("Connection with the JDS lost: {}", e);
+                            let tx_status = up.safe_lock(|u| u.tx_status()).unwrap();
+                            let _ = tx_status
+                                .send(status::Status {
+                                    state: status::State::UpstreamShutdown(
+                                        Error::ChannelErrorReceiver(e),
+                                    ),
+                                })
+                                .await;
+                            break;
+                        }
+                    };
+                    let mut incoming: StdFrame = incoming.try_into().unwrap();
                     let message_type = incoming.get_header().unwrap().msg_type();
                     let payload = incoming.payload();
-                    let next_message_to_send =
+                    if let Err(e) =
                         ParseServerJobDeclarationMessages::handle_message_job_declaration(
-                            self_mutex.clone(),
+                            client.clone(),
                             message_type,
                             payload,
-                        );
-                    match next_message_to_send {
-                        Ok(SendTo::None(Some(JobDeclaration::DeclareMiningJobSuccess(m)))) => {
-                            let new_token = m.new_mining_job_token;
-                            let last_declare = Self::get_last_declare_job_sent(&self_mutex, m.request_id).unwrap_or_else(|| panic!("Failed to get last declare job: job not found, Request Id: {:?}.", m.request_id));
-                            let mut last_declare_mining_job_sent = last_declare.declare_job;
-                            let is_future = last_declare.template.future_template;
-                            let id = last_declare.template.template_id;
-                            let merkle_path = last_declare.template.merkle_path.clone();
-                            let template = last_declare.template;
-
-                            // TODO where we should have a sort of signaling that is green after
-                            // that the token has been updated so that on_set_new_prev_hash know it
-                            // and can decide if send the set_custom_job or not
-                            if is_future {
-                                last_declare_mining_job_sent.mining_job_token = new_token;
-                                self_mutex
-                                    .safe_lock(|s| {
-                                        s.future_jobs.insert(
-                                            id,
-                                            (
-                                                last_declare_mining_job_sent,
-                                                merkle_path,
-                                                template,
-                                                last_declare.coinbase_pool_output,
-                                            ),
-                                        );
-                                    })
-                                    .unwrap();
-                            } else {
-                                let set_new_prev_hash = self_mutex
-                                    .safe_lock(|s| s.last_set_new_prev_hash.clone())
-                                    .unwrap();
-                                let mut template_outs = template.coinbase_tx_outputs.to_vec();
-                                let mut pool_outs = last_declare.coinbase_pool_output;
-                                pool_outs.append(&mut template_outs);
-                                match set_new_prev_hash {
-                                    Some(p) => Upstream::set_custom_jobs(
-                                        &up,
-                                        last_declare_mining_job_sent,
-                                        p,
-                                        merkle_path,
-                                        new_token,
-                                        template.coinbase_tx_version,
-                                        template.coinbase_prefix,
-                                        template.coinbase_tx_input_sequence,
-                                        template.coinbase_tx_value_remaining,
-                                        pool_outs,
-                                        template.coinbase_tx_locktime,
-                                        template.template_id
-                                        ).await.unwrap(),
-                                    None => panic!("Invalid state we received a NewTemplate not future, without having received a set new prev hash")
-                                }
-                            }
-                        }
-                        Ok(SendTo::None(Some(JobDeclaration::DeclareMiningJobError(m)))) => {
-                            error!("Job is not verified: {:?}", m);
-                        }
-                        Ok(SendTo::None(None)) => (),
-                        Ok(SendTo::Respond(m)) => {
-                            let sv2_frame: StdFrame =
-                                PoolMessages::JobDeclaration(m).try_into().unwrap();
-                            let sender =
-                                self_mutex.safe_lock(|self_| self_.sender.clone()).unwrap();
-                            sender.send(sv2_frame.into()).await.unwrap();
-                        }
-                        Ok(_) => unreachable!(),
-                        Err(_) => todo!(),
+                        )
+                    {
+                        error!("Failed to handle the message of the JDS: {}", e);
                     }
                 }
             })
         };
-        self_mutex
-            .safe_lock(|s| {
-                s.task_collector
-                    .safe_lock(|c| c.push(main_task.abort_handle()))
-                    .unwrap()
-            })
+        task_collector
+            .safe_lock(|c| c.push(main_task.abort_handle()))
             .unwrap();
     }
 
+    /// The job of the future template activated by `set_new_prev_hash` is sent to the pool as soon
+    /// as the JDS accepted it
     pub fn on_set_new_prev_hash(
         self_mutex: Arc<Mutex<Self>>,
         set_new_prev_hash: SetNewPrevHash<'static>,
     ) {
-        tokio::task::spawn(async move {
-            let id = set_new_prev_hash.template_id;
-            let _ = self_mutex.safe_lock(|s| {
-                s.last_set_new_prev_hash = Some(set_new_prev_hash.clone());
-                s.set_new_prev_hash_counter += 1;
-            });
-            let (job, up, merkle_path, template, mut pool_outs) = loop {
-                match self_mutex
-                    .safe_lock(|s| {
-                        if s.set_new_prev_hash_counter > 1
-                            && s.last_set_new_prev_hash != Some(set_new_prev_hash.clone())
-                        //it means that a new prev_hash is arrived while the previous hasn't exited
-                        // the loop yet
-                        {
-                            s.set_new_prev_hash_counter -= 1;
-                            Some(None)
-                        } else {
-                            s.future_jobs.remove(&id).map(
-                                |(job, merkle_path, template, pool_outs)| {
-                                    s.future_jobs =
-                                        HashMap::with_hasher(BuildNoHashHasher::default());
-                                    s.set_new_prev_hash_counter -= 1;
-                                    Some((job, s.up.clone(), merkle_path, template, pool_outs))
-                                },
-                            )
-                        }
-                    })
-                    .unwrap()
-                {
-                    Some(Some(future_job_tuple)) => break future_job_tuple,
-                    Some(None) => return,
-                    None => {}
-                };
-                tokio::task::yield_now().await;
-            };
-            let signed_token = job.mining_job_token.clone();
-            let mut template_outs = template.coinbase_tx_outputs.to_vec();
-            pool_outs.append(&mut template_outs);
-            Upstream::set_custom_jobs(
-                &up,
-                job,
-                set_new_prev_hash,
-                merkle_path,
-                signed_token,
-                template.coinbase_tx_version,
-                template.coinbase_prefix,
-                template.coinbase_tx_input_sequence,
-                template.coinbase_tx_value_remaining,
-                pool_outs,
-                template.coinbase_tx_locktime,
-                template.template_id,
-            )
-            .await
+        let sent = Self::client(&self_mutex)
+            .safe_lock(|c| c.on_set_new_prev_hash(set_new_prev_hash))
             .unwrap();
-        });
-    }
-
-    async fn allocate_tokens(self_mutex: &Arc<Mutex<Self>>, token_to_allocate: u32) {
-        for i in 0..token_to_allocate {
-            let message = JobDeclaration::AllocateMiningJobToken(AllocateMiningJobToken {
-                user_identifier: "todo".to_string().try_into().unwrap(),
-                request_id: i,
-            });
-            let sender = self_mutex.safe_lock(|s| s.sender.clone()).unwrap();
-            // Safe unwrap message is build above and is valid, below can never panic
-            let frame: StdFrame = PoolMessages::JobDeclaration(message).try_into().unwrap();
-            // TODO join re
-            sender.send(frame.into()).await.unwrap();
+        if let Err(e) = sent {
+            error!("Failed to activate the future job: {}", e);
         }
     }
+
     pub async fn on_solution(
         self_mutex: &Arc<Mutex<Self>>,
         solution: SubmitSharesExtended<'static>,
     ) {
-        let prev_hash = self_mutex
-            .safe_lock(|s| s.last_set_new_prev_hash.clone())
-            .unwrap()
-            .expect("");
-        let solution = SubmitSolutionJd {
-            extranonce: solution.extranonce,
-            prev_hash: prev_hash.prev_hash,
-            ntime: solution.ntime,
-            nonce: solution.nonce,
-            nbits: prev_hash.n_bits,
-            version: solution.version,
-        };
-        let frame: StdFrame =
-            PoolMessages::JobDeclaration(JobDeclaration::SubmitSolution(solution))
-                .try_into()
-                .unwrap();
-        let sender = self_mutex.safe_lock(|s| s.sender.clone()).unwrap();
-        sender.send(frame.into()).await.unwrap();
+        let sent = Self::client(self_mutex)
+            .safe_lock(|c| c.on_solution(solution))
+            .unwrap();
+        if let Err(e) = sent {
+            error!("Failed to send the solution to the JDS: {}", e);
+        }
     }
 }
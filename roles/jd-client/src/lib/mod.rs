@@ -6,23 +6,27 @@ pub mod status;
 pub mod template_receiver;
 pub mod upstream_sv2;
 
-use std::{sync::atomic::AtomicBool, time::Duration};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
 use job_declarator::JobDeclarator;
 use proxy_config::ProxyConfig;
-use template_receiver::TemplateRx;
+use template_receiver::{selector, TemplateRx};
 
-use async_channel::{bounded, unbounded};
+use async_channel::{bounded, unbounded, Receiver};
 use futures::{select, FutureExt};
-use roles_logic_sv2::utils::Mutex;
+use roles_logic_sv2::{template_distribution_sv2::SubmitSolution, utils::Mutex};
 use std::{
     net::{IpAddr, SocketAddr},
     str::FromStr,
     sync::Arc,
 };
-use tokio::task::AbortHandle;
+use stratum_common::bitcoin::TxOut;
+use tokio::{net::TcpStream, task::AbortHandle};
 
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 /// Is used by the template receiver and the downstream. When a NewTemplate is received the context
 /// that is running the template receiver set this value to false and then the message is sent to
@@ -47,6 +51,13 @@ use tracing::{error, info};
 ///    between all the contexts is not necessary.
 pub static IS_NEW_TEMPLATE_HANDLED: AtomicBool = AtomicBool::new(true);
 
+/// True while the JDC is solo mining because the pool or the JDS can not be reached (see
+/// `solo_mining_fallback` in the config). It can be read by anything that reports the JDC state.
+pub static IS_SOLO_MINING_FALLBACK: AtomicBool = AtomicBool::new(false);
+
+/// Time given to the pool and the JDS to accept a connection when checking if they are reachable
+const UPSTREAM_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Job Declarator Client (or JDC) is the role which is Miner-side, in charge of creating new
 /// mining jobs from the templates received by the Template Provider to which it is connected. It
 /// declares custom jobs to the JDS, in order to start working on them.
@@ -65,6 +76,19 @@ impl JobDeclaratorClient {
     }
 
     pub async fn start(self) {
+        if let Some(metrics_address) = &self.config.metrics_address {
+            if let Err(e) = monitoring_sv2::serve(metrics_address, "jd-client") {
+                error!("Failed to serve the metrics on {}: {}", metrics_address, e);
+            }
+        }
+        if let Some(admin_address) = &self.config.admin_address {
+            if let Err(e) = admin_sv2::serve(admin_address) {
+                error!(
+                    "Failed to serve the admin interface on {}: {}",
+                    admin_address, e
+                );
+            }
+        }
         let mut upstream_index = 0;
         let mut interrupt_signal_future = Box::pin(tokio::signal::ctrl_c().fuse());
 
@@ -74,16 +98,48 @@ impl JobDeclaratorClient {
         let task_collector = Arc::new(Mutex::new(vec![]));
 
         let proxy_config = &self.config;
+        // True while solo mining because the current upstream is not reachable
+        let mut solo_fallback = false;
 
         loop {
             let task_collector = task_collector.clone();
             let tx_status = tx_status.clone();
-            if let Some(upstream) = proxy_config.upstreams.get(upstream_index) {
-                self.initialize_jd(tx_status.clone(), task_collector.clone(), upstream.clone())
-                    .await;
-            } else {
-                self.initialize_jd_as_solo_miner(tx_status.clone(), task_collector.clone())
-                    .await;
+            let upstream = proxy_config.upstreams.get(upstream_index);
+            if let Some(upstream) = upstream {
+                if proxy_config.solo_mining_fallback
+                    && !solo_fallback
+                    && !is_upstream_reachable(upstream).await
+                {
+                    warn!(
+                        "Pool {} or JDS {} not reachable, falling back to solo mining",
+                        upstream.pool_address, upstream.jd_address
+                    );
+                    solo_fallback = true;
+                }
+            }
+            IS_SOLO_MINING_FALLBACK.store(solo_fallback, Ordering::Relaxed);
+            match upstream {
+                Some(upstream) if !solo_fallback => {
+                    self.initialize_jd(tx_status.clone(), task_collector.clone(), upstream.clone())
+                        .await;
+                }
+                Some(upstream) => {
+                    info!(
+                        "Solo mining until pool {} is reachable again",
+                        upstream.pool_address
+                    );
+                    self.watch_upstream(
+                        tx_status.clone(),
+                        task_collector.clone(),
+                        upstream.clone(),
+                    );
+                    self.initialize_jd_as_solo_miner(tx_status.clone(), task_collector.clone())
+                        .await;
+                }
+                None => {
+                    self.initialize_jd_as_solo_miner(tx_status.clone(), task_collector.clone())
+                        .await;
+                }
             }
             // Check all tasks if is_finished() is true, if so exit
             loop {
@@ -120,6 +176,23 @@ impl JobDeclaratorClient {
                         break;
                     }
                     status::State::UpstreamShutdown(err) => {
+                        error!("SHUTDOWN from: {}", err);
+                        if proxy_config.solo_mining_fallback && !solo_fallback {
+                            warn!("Connection with the pool lost, falling back to solo mining");
+                            solo_fallback = true;
+                        }
+                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        task_collector
+                            .safe_lock(|s| {
+                                for handle in s {
+                                    handle.abort();
+                                }
+                            })
+                            .unwrap();
+                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        break;
+                    }
+                    status::State::TemplateReceiverShutdown(err) => {
                         error!("SHUTDOWN from: {}", err);
                         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
                         task_collector
@@ -132,6 +205,19 @@ impl JobDeclaratorClient {
                         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
                         break;
                     }
+                    status::State::UpstreamReachable => {
+                        info!("Pool reachable again, leaving solo mining");
+                        solo_fallback = false;
+                        task_collector
+                            .safe_lock(|s| {
+                                for handle in s {
+                                    handle.abort();
+                                }
+                            })
+                            .unwrap();
+                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        break;
+                    }
                     status::State::UpstreamRogue => {
                         error!("Changin Pool");
                         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
@@ -154,13 +240,37 @@ impl JobDeclaratorClient {
         }
     }
 
+    /// While solo mining as fallback, checks every `upstream_check_interval` if `upstream` is
+    /// reachable again
+    fn watch_upstream(
+        &self,
+        tx_status: async_channel::Sender<status::Status<'static>>,
+        task_collector: Arc<Mutex<Vec<AbortHandle>>>,
+        upstream: proxy_config::Upstream,
+    ) {
+        let interval = self.config.upstream_check_interval;
+        let task = tokio::task::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if is_upstream_reachable(&upstream).await {
+                    let _ = tx_status
+                        .send(status::Status {
+                            state: status::State::UpstreamReachable,
+                        })
+                        .await;
+                    break;
+                }
+            }
+        });
+        let _ = task_collector.safe_lock(|c| c.push(task.abort_handle()));
+    }
+
     async fn initialize_jd_as_solo_miner(
         &self,
         tx_status: async_channel::Sender<status::Status<'static>>,
         task_collector: Arc<Mutex<Vec<AbortHandle>>>,
     ) {
         let proxy_config = &self.config;
-        let timeout = proxy_config.timeout;
         let miner_tx_out = proxy_config::get_coinbase_output(proxy_config).unwrap();
 
         // When Downstream receive a share that meets bitcoin target it transformit in a
@@ -190,21 +300,13 @@ impl JobDeclaratorClient {
         .await
         .unwrap();
 
-        // Initialize JD part
-        let mut parts = proxy_config.tp_address.split(':');
-        let ip_tp = parts.next().unwrap().to_string();
-        let port_tp = parts.next().unwrap().parse::<u16>().unwrap();
-
-        TemplateRx::connect(
-            SocketAddr::new(IpAddr::from_str(ip_tp.as_str()).unwrap(), port_tp),
+        self.connect_template_providers(
             recv_solution,
-            status::Sender::TemplateReceiver(tx_status.clone()),
+            tx_status,
             None,
             downstream,
             task_collector,
-            Arc::new(Mutex::new(PoolChangerTrigger::new(timeout))),
-            miner_tx_out.clone(),
-            proxy_config.tp_authority_public_key,
+            miner_tx_out,
             false,
         )
         .await;
@@ -288,10 +390,6 @@ impl JobDeclaratorClient {
         );
 
         // Initialize JD part
-        let mut parts = proxy_config.tp_address.split(':');
-        let ip_tp = parts.next().unwrap().to_string();
-        let port_tp = parts.next().unwrap().parse::<u16>().unwrap();
-
         let mut parts = upstream_config.jd_address.split(':');
         let ip_jd = parts.next().unwrap().to_string();
         let port_jd = parts.next().unwrap().parse::<u16>().unwrap();
@@ -332,20 +430,112 @@ impl JobDeclaratorClient {
         .await
         .unwrap();
 
-        TemplateRx::connect(
-            SocketAddr::new(IpAddr::from_str(ip_tp.as_str()).unwrap(), port_tp),
+        self.connect_template_providers(
             recv_solution,
-            status::Sender::TemplateReceiver(tx_status.clone()),
-            Some(jd.clone()),
+            tx_status,
+            Some(jd),
             downstream,
             task_collector,
-            Arc::new(Mutex::new(PoolChangerTrigger::new(timeout))),
             vec![],
-            proxy_config.tp_authority_public_key,
             test_only_do_not_send_solution_to_tp,
         )
         .await;
     }
+
+    /// Connects to every Template Provider in the config and sends each solution found by the
+    /// downstream to the Template Provider that built its template
+    #[allow(clippy::too_many_arguments)]
+    async fn connect_template_providers(
+        &self,
+        recv_solution: Receiver<SubmitSolution<'static>>,
+        tx_status: async_channel::Sender<status::Status<'static>>,
+        jd: Option<Arc<Mutex<JobDeclarator>>>,
+        downstream: Arc<Mutex<downstream::DownstreamMiningNode>>,
+        task_collector: Arc<Mutex<Vec<AbortHandle>>>,
+        miner_tx_out: Vec<TxOut>,
+        test_only_do_not_send_solution_to_tp: bool,
+    ) {
+        let timeout = self.config.timeout;
+        let selector = Arc::new(Mutex::new(selector::TemplateSelector::new()));
+        let additional_outputs =
+            proxy_config::get_additional_coinbase_outputs(&self.config).unwrap();
+        let submit_block_rpc = self
+            .config
+            .submit_block_rpc
+            .as_ref()
+            .map(|rpc| rpc.client());
+        let mut solution_senders = vec![];
+        let mut last_error = None;
+        for (source, tp) in self.config.template_providers().into_iter().enumerate() {
+            let address = SocketAddr::from_str(&tp.address)
+                .unwrap_or_else(|_| panic!("Invalid template provider address {}", tp.address));
+            let (send_solution, recv_tp_solution) = bounded(10);
+            match TemplateRx::connect(
+                address,
+                recv_tp_solution,
+                status::Sender::TemplateReceiver(tx_status.clone()),
+                jd.clone(),
+                downstream.clone(),
+                task_collector.clone(),
+                Arc::new(Mutex::new(PoolChangerTrigger::new(timeout))),
+                miner_tx_out.clone(),
+                additional_outputs.clone(),
+                tp.authority_public_key,
+                test_only_do_not_send_solution_to_tp,
+                source,
+                selector.clone(),
+                submit_block_rpc.clone(),
+            )
+            .await
+            {
+                Ok(()) => solution_senders.push(Some(send_solution)),
+                Err(e) => {
+                    error!(
+                        "Failed to connect to Template Provider {}: {}",
+                        tp.address, e
+                    );
+                    solution_senders.push(None);
+                    last_error = Some(e);
+                }
+            }
+        }
+        if solution_senders.iter().all(Option::is_none) {
+            if let Some(e) = last_error {
+                let _ = tx_status
+                    .send(status::Status {
+                        state: status::State::TemplateReceiverShutdown(e),
+                    })
+                    .await;
+            }
+            return;
+        }
+
+        let task = tokio::task::spawn(async move {
+            while let Ok(solution) = recv_solution.recv().await {
+                let (source, _) = selector::from_global_id(solution.template_id);
+                match solution_senders.get(source).and_then(Option::as_ref) {
+                    Some(sender) => {
+                        if sender.send(solution).await.is_err() {
+                            error!("Template Provider {} can not receive solutions", source);
+                        }
+                    }
+                    None => error!("Solution for unknown Template Provider {}", source),
+                }
+            }
+        });
+        let _ = task_collector.safe_lock(|c| c.push(task.abort_handle()));
+    }
+}
+
+/// Returns true if both the pool and the JDS of `upstream` accept connections
+async fn is_upstream_reachable(upstream: &proxy_config::Upstream) -> bool {
+    for address in [&upstream.pool_address, &upstream.jd_address] {
+        match tokio::time::timeout(UPSTREAM_CONNECT_TIMEOUT, TcpStream::connect(address)).await {
+            Ok(Ok(_)) => (),
+            _ => return false,
+        }
+    }
+    true
 }
 
 #[derive(Debug)]
@@ -1,9 +1,14 @@
 #![allow(dead_code)]
+use config_helpers_sv2::{Validate, Validator};
 use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
 use roles_logic_sv2::{errors::Error, utils::CoinbaseOutput as CoinbaseOutput_};
+use rpc_sv2::mini_rpc_client::{Auth, MiniRpcClient};
 use serde::Deserialize;
 use std::time::Duration;
-use stratum_common::bitcoin::TxOut;
+use stratum_common::bitcoin::{consensus::encode::serialize, hashes::hex::FromHex, Script, TxOut};
+
+/// Bytes of data allowed in a standard OP_RETURN output
+const MAX_OP_RETURN_DATA: usize = 80;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct CoinbaseOutput {
@@ -18,6 +23,11 @@ impl CoinbaseOutput {
             output_script_value,
         }
     }
+
+    fn to_script(&self) -> Result<Script, Error> {
+        let coinbase_output: CoinbaseOutput_ = self.try_into()?;
+        coinbase_output.try_into()
+    }
 }
 
 impl TryFrom<&CoinbaseOutput> for CoinbaseOutput_ {
@@ -34,6 +44,61 @@ impl TryFrom<&CoinbaseOutput> for CoinbaseOutput_ {
     }
 }
 
+/// An output added by the JDC to the coinbase of the declared jobs, right after the pool output
+#[derive(Debug, Deserialize, Clone)]
+pub struct AdditionalCoinbaseOutput {
+    /// Hex encoded data of a value 0 OP_RETURN output, e.g. a tag or a merge mining commitment
+    pub op_return: Option<String>,
+    /// Or a script as in `coinbase_outputs`, e.g. for a donation
+    pub output_script_type: Option<String>,
+    pub output_script_value: Option<String>,
+    /// Satoshis paid to the output, taken from the value of the pool output
+    #[serde(default)]
+    pub value: u64,
+}
+
+impl TryFrom<&AdditionalCoinbaseOutput> for TxOut {
+    type Error = String;
+
+    fn try_from(output: &AdditionalCoinbaseOutput) -> Result<Self, Self::Error> {
+        let script_pubkey =
+            match (
+                &output.op_return,
+                &output.output_script_type,
+                &output.output_script_value,
+            ) {
+                (Some(data), None, None) => {
+                    let data = Vec::<u8>::from_hex(data)
+                        .map_err(|_| format!("op_return `{}` is not valid hex", data))?;
+                    if data.len() > MAX_OP_RETURN_DATA {
+                        return Err(format!(
+                            "op_return has {} bytes, at most {} are standard",
+                            data.len(),
+                            MAX_OP_RETURN_DATA
+                        ));
+                    }
+                    if output.value != 0 {
+                        return Err("an op_return output must have value 0".to_string());
+                    }
+                    Script::new_op_return(&data)
+                }
+                (None, Some(script_type), Some(script_value)) => {
+                    CoinbaseOutput::new(script_type.clone(), script_value.clone())
+                        .to_script()
+                        .map_err(|e| format!("{:?}", e))?
+                }
+                _ => return Err(
+                    "either op_return or output_script_type and output_script_value must be set"
+                        .to_string(),
+                ),
+            };
+        Ok(TxOut {
+            value: output.value,
+            script_pubkey,
+        })
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct ProxyConfig {
     pub downstream_address: String,
@@ -47,13 +112,39 @@ pub struct ProxyConfig {
     pub cert_validity_sec: u64,
     pub tp_address: String,
     pub tp_authority_public_key: Option<Secp256k1PublicKey>,
+    /// Template Providers used along with `tp_address`, the JDC mines on the best template
+    /// received from all of them
+    #[serde(default)]
+    pub additional_template_providers: Vec<TemplateProvider>,
     #[allow(dead_code)]
     pub retry: u32,
     pub upstreams: Vec<Upstream>,
     #[serde(deserialize_with = "duration_from_toml")]
     pub timeout: Duration,
     pub coinbase_outputs: Vec<CoinbaseOutput>,
+    /// Outputs added to the coinbase of every declared job, they must fit along with the pool
+    /// output in the `coinbase_output_max_additional_size` of the pool
+    #[serde(default)]
+    pub additional_coinbase_outputs: Vec<AdditionalCoinbaseOutput>,
+    /// Node where a block is submitted with `submitblock` when the Template Provider that built
+    /// its template can not be reached
+    pub submit_block_rpc: Option<SubmitBlockRpc>,
     pub test_only_do_not_send_solution_to_tp: Option<bool>,
+    /// Mine solo paying to `coinbase_outputs` while the pool or the JDS can not be reached, and
+    /// go back to the pool as soon as it is reachable again
+    #[serde(default)]
+    pub solo_mining_fallback: bool,
+    /// How often the pool is checked while solo mining as fallback
+    #[serde(
+        default = "default_upstream_check_interval",
+        deserialize_with = "duration_from_toml"
+    )]
+    pub upstream_check_interval: Duration,
+    /// Address where the metrics are served in the OpenMetrics text format, not served if not set
+    pub metrics_address: Option<String>,
+    /// Localhost address of the admin interface (log level, connections, channels and state
+    /// snapshot), not served if not set
+    pub admin_address: Option<String>,
 }
 
 pub struct PoolConfig {
@@ -138,13 +229,111 @@ impl ProxyConfig {
             cert_validity_sec: tp_config.cert_validity_sec,
             tp_address: tp_config.tp_address,
             tp_authority_public_key: tp_config.tp_authority_public_key,
+            additional_template_providers: vec![],
             retry: 0,
             upstreams,
             timeout,
             coinbase_outputs: protocol_config.coinbase_outputs,
+            additional_coinbase_outputs: vec![],
+            submit_block_rpc: None,
             test_only_do_not_send_solution_to_tp: None,
+            solo_mining_fallback: false,
+            upstream_check_interval: default_upstream_check_interval(),
+            metrics_address: None,
+            admin_address: None,
         }
     }
+
+    /// The Template Provider at `tp_address` followed by the additional ones
+    pub fn template_providers(&self) -> Vec<TemplateProvider> {
+        let mut template_providers = vec![TemplateProvider {
+            address: self.tp_address.clone(),
+            authority_public_key: self.tp_authority_public_key,
+        }];
+        template_providers.extend(self.additional_template_providers.iter().cloned());
+        template_providers
+    }
+}
+
+impl Validate for ProxyConfig {
+    fn validate(&self, v: &mut Validator) {
+        v.ip_address("downstream_address", &self.downstream_address);
+        v.ordered(
+            ("min_supported_version", self.min_supported_version),
+            ("max_supported_version", self.max_supported_version),
+        );
+        v.range("min_extranonce2_size", self.min_extranonce2_size, 1, 32);
+        v.keypair(
+            ("authority_public_key", &self.authority_public_key),
+            ("authority_secret_key", &self.authority_secret_key),
+        );
+        v.check(
+            self.cert_validity_sec > 0,
+            "cert_validity_sec must be greater than 0",
+        );
+        v.socket_address("tp_address", &self.tp_address);
+        for (i, tp) in self.additional_template_providers.iter().enumerate() {
+            v.socket_address(
+                &format!("additional_template_providers[{}].address", i),
+                &tp.address,
+            );
+        }
+        if let Some(rpc) = &self.submit_block_rpc {
+            v.check(
+                rpc.url.starts_with("http://") || rpc.url.starts_with("https://"),
+                format!(
+                    "submit_block_rpc.url: `{}` must start with http:// or https://",
+                    rpc.url
+                ),
+            );
+        }
+        if let Some(metrics_address) = &self.metrics_address {
+            v.socket_address("metrics_address", metrics_address);
+        }
+        if let Some(admin_address) = &self.admin_address {
+            v.socket_address("admin_address", admin_address);
+        }
+        v.not_empty("upstreams", &self.upstreams);
+        for (i, upstream) in self.upstreams.iter().enumerate() {
+            v.socket_address(
+                &format!("upstreams[{}].pool_address", i),
+                &upstream.pool_address,
+            );
+            v.socket_address(
+                &format!("upstreams[{}].jd_address", i),
+                &upstream.jd_address,
+            );
+        }
+        if let Err(e) = get_coinbase_output(self) {
+            v.check(false, format!("coinbase_outputs: {}", e));
+        }
+        for (i, output) in self.additional_coinbase_outputs.iter().enumerate() {
+            if let Err(e) = TxOut::try_from(output) {
+                v.check(false, format!("additional_coinbase_outputs[{}]: {}", i, e));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SubmitBlockRpc {
+    pub url: String,
+    pub port: u16,
+    pub user: String,
+    pub pass: String,
+}
+
+impl SubmitBlockRpc {
+    pub fn client(&self) -> MiniRpcClient {
+        let url = format!("{}:{}", self.url, self.port);
+        MiniRpcClient::new(url, Auth::new(self.user.clone(), self.pass.clone()))
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TemplateProvider {
+    pub address: String,
+    pub authority_public_key: Option<Secp256k1PublicKey>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -171,6 +360,10 @@ impl Upstream {
     }
 }
 
+fn default_upstream_check_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
 fn duration_from_toml<'de, D>(deserializer: D) -> Result<Duration, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -203,11 +396,9 @@ where
 pub fn get_coinbase_output(config: &ProxyConfig) -> Result<Vec<TxOut>, Error> {
     let mut result = Vec::new();
     for coinbase_output_pool in &config.coinbase_outputs {
-        let coinbase_output: CoinbaseOutput_ = coinbase_output_pool.try_into()?;
-        let output_script = coinbase_output.try_into()?;
         result.push(TxOut {
             value: 0,
-            script_pubkey: output_script,
+            script_pubkey: coinbase_output_pool.to_script()?,
         });
     }
     match result.is_empty() {
@@ -215,3 +406,17 @@ pub fn get_coinbase_output(config: &ProxyConfig) -> Result<Vec<TxOut>, Error> {
         _ => Ok(result),
     }
 }
+
+/// The additional outputs of the config, already checked by the validation of the config
+pub fn get_additional_coinbase_outputs(config: &ProxyConfig) -> Result<Vec<TxOut>, String> {
+    config
+        .additional_coinbase_outputs
+        .iter()
+        .map(TxOut::try_from)
+        .collect()
+}
+
+/// Serialized size of `outputs` in the coinbase
+pub fn outputs_size(outputs: &[TxOut]) -> usize {
+    outputs.iter().map(|o| serialize(o).len()).sum()
+}
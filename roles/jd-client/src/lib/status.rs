@@ -36,8 +36,12 @@ impl Clone for Sender {
 #[derive(Debug)]
 pub enum State<'a> {
     DownstreamShutdown(Error<'a>),
+    /// The connection with the pool or with the JDS dropped
     UpstreamShutdown(Error<'a>),
+    TemplateReceiverShutdown(Error<'a>),
     UpstreamRogue,
+    /// The pool is reachable again while solo mining as fallback
+    UpstreamReachable,
     Healthy(String),
 }
 
@@ -75,7 +79,7 @@ async fn send_status(
         }
         Sender::TemplateReceiver(tx) => {
             tx.send(Status {
-                state: State::UpstreamShutdown(e),
+                state: State::TemplateReceiverShutdown(e),
             })
             .await
             .unwrap_or(());
@@ -98,6 +102,8 @@ pub async fn handle_error(
         Error::BadConfigDeserialize(_) => {
             send_status(sender, e, error_handling::ErrorBranch::Break).await
         }
+        // Errors on a config with invalid values.
+        Error::InvalidConfig(_) => send_status(sender, e, error_handling::ErrorBranch::Break).await,
         // Errors from `binary_sv2` crate.
         Error::BinarySv2(_) => send_status(sender, e, error_handling::ErrorBranch::Break).await,
         // Errors on bad noise handshake.
@@ -132,6 +138,9 @@ pub async fn handle_error(
         Error::Uint256Conversion(_) => {
             send_status(sender, e, error_handling::ErrorBranch::Break).await
         }
+        Error::CoinbaseOutputsTooBig(..) => {
+            send_status(sender, e, error_handling::ErrorBranch::Break).await
+        }
         Error::Infallible(_) => send_status(sender, e, error_handling::ErrorBranch::Break).await,
     }
 }
@@ -1,4 +1,4 @@
-use super::TemplateRx;
+use super::{selector, TemplateRx};
 use roles_logic_sv2::{
     errors::Error,
     handlers::template_distribution::{ParseServerTemplateDistributionMessages, SendTo},
@@ -8,19 +8,24 @@ use roles_logic_sv2::{
 
 impl ParseServerTemplateDistributionMessages for TemplateRx {
     fn handle_new_template(&mut self, m: NewTemplate) -> Result<SendTo, Error> {
-        let new_template = m.into_static();
+        let mut new_template = m.into_static();
+        new_template.template_id = selector::to_global_id(self.source, new_template.template_id);
         let new_template = TemplateDistribution::NewTemplate(new_template);
         Ok(SendTo::None(Some(new_template)))
     }
 
     fn handle_set_new_prev_hash(&mut self, m: SetNewPrevHash) -> Result<SendTo, Error> {
         let new_prev_hash = SetNewPrevHash {
-            template_id: m.template_id,
+            template_id: selector::to_global_id(self.source, m.template_id),
             prev_hash: m.prev_hash.into_static(),
             header_timestamp: m.header_timestamp,
             n_bits: m.n_bits,
             target: m.target.into_static(),
         };
+        // Only the templates built on the new prev hash can become blocks
+        self.transactions
+            .retain(|id, _| *id >= new_prev_hash.template_id);
+        self.last_prev_hash = Some(new_prev_hash.clone());
         let new_prev_hash = TemplateDistribution::SetNewPrevHash(new_prev_hash);
         self.pool_chaneger_trigger.safe_lock(|t| t.stop()).unwrap();
         Ok(SendTo::None(Some(new_prev_hash)))
@@ -35,6 +40,10 @@ impl ParseServerTemplateDistributionMessages for TemplateRx {
             excess_data: m.excess_data.into_static(),
             template_id: m.template_id,
         };
+        self.transactions.insert(
+            selector::to_global_id(self.source, m.template_id),
+            m.transaction_list.clone(),
+        );
         let tx_received = TemplateDistribution::RequestTransactionDataSuccess(m);
         Ok(SendTo::None(Some(tx_received)))
     }
@@ -1,5 +1,9 @@
-use super::{job_declarator::JobDeclarator, status, PoolChangerTrigger};
+use super::{
+    error::Error, job_declarator::JobDeclarator, proxy_config::outputs_size, status,
+    PoolChangerTrigger,
+};
 use async_channel::{Receiver, Sender};
+use binary_sv2::{Seq064K, B016M};
 use codec_sv2::{HandshakeRole, Initiator, StandardEitherFrame, StandardSv2Frame};
 use error_handling::handle_result;
 use key_utils::Secp256k1PublicKey;
@@ -9,17 +13,23 @@ use roles_logic_sv2::{
     job_declaration_sv2::AllocateMiningJobTokenSuccess,
     parsers::{PoolMessages, TemplateDistribution},
     template_distribution_sv2::{
-        CoinbaseOutputDataSize, NewTemplate, RequestTransactionData, SubmitSolution,
+        CoinbaseOutputDataSize, NewTemplate, RequestTransactionData, SetNewPrevHash, SubmitSolution,
     },
-    utils::Mutex,
+    utils::{block_from_template_solution, Mutex},
 };
+use rpc_sv2::mini_rpc_client::MiniRpcClient;
+use selector::TemplateSelector;
 use setup_connection::SetupConnectionHandler;
-use std::{convert::TryInto, net::SocketAddr, sync::Arc};
-use stratum_common::bitcoin::{consensus::Encodable, TxOut};
+use std::{collections::HashMap, convert::TryInto, net::SocketAddr, sync::Arc};
+use stratum_common::bitcoin::{
+    consensus::{encode::serialize_hex, Encodable},
+    TxOut,
+};
 use tokio::task::AbortHandle;
 use tracing::{error, info, warn};
 
 mod message_handler;
+pub mod selector;
 mod setup_connection;
 
 pub type SendTo = SendTo_<roles_logic_sv2::parsers::TemplateDistribution<'static>, ()>;
@@ -39,7 +49,18 @@ pub struct TemplateRx {
     new_template_message: Option<NewTemplate<'static>>,
     pool_chaneger_trigger: Arc<Mutex<PoolChangerTrigger>>,
     miner_coinbase_output: Vec<u8>,
+    /// Outputs added after the pool output in the coinbase of every job
+    additional_coinbase_outputs: Vec<TxOut>,
     test_only_do_not_send_solution_to_tp: bool,
+    /// Index of this Template Provider in the ones the JDC is connected to
+    source: usize,
+    selector: Arc<Mutex<TemplateSelector>>,
+    /// Last SetNewPrevHash of this Template Provider
+    last_prev_hash: Option<SetNewPrevHash<'static>>,
+    /// Transactions of the templates built on `last_prev_hash`, by global template id
+    transactions: HashMap<u64, Seq064K<'static, B016M<'static>>>,
+    /// Node where the blocks are submitted when this Template Provider can not be reached
+    submit_block_rpc: Option<MiniRpcClient>,
 }
 
 impl TemplateRx {
@@ -53,9 +74,13 @@ impl TemplateRx {
         task_collector: Arc<Mutex<Vec<AbortHandle>>>,
         pool_chaneger_trigger: Arc<Mutex<PoolChangerTrigger>>,
         miner_coinbase_outputs: Vec<TxOut>,
+        additional_coinbase_outputs: Vec<TxOut>,
         authority_public_key: Option<Secp256k1PublicKey>,
         test_only_do_not_send_solution_to_tp: bool,
-    ) {
+        source: usize,
+        selector: Arc<Mutex<TemplateSelector>>,
+        submit_block_rpc: Option<MiniRpcClient>,
+    ) -> Result<(), Error<'static>> {
         let mut encoded_outputs = vec![];
         // jd is set to None in initialize_jd_as_solo_miner (in this case we need to take the first
         // output as done by JDS)
@@ -68,7 +93,7 @@ impl TemplateRx {
                 .consensus_encode(&mut encoded_outputs)
                 .expect("Invalid coinbase output in config");
         }
-        let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let stream = tokio::net::TcpStream::connect(address).await?;
 
         let initiator = match authority_public_key {
             Some(pub_key) => Initiator::from_raw_k(pub_key.into_bytes()),
@@ -96,14 +121,22 @@ impl TemplateRx {
             new_template_message: None,
             pool_chaneger_trigger,
             miner_coinbase_output: encoded_outputs,
+            additional_coinbase_outputs,
             test_only_do_not_send_solution_to_tp,
+            source,
+            selector: selector.clone(),
+            last_prev_hash: None,
+            transactions: HashMap::new(),
+            submit_block_rpc,
         }));
+        selector.safe_lock(|s| s.add_source(source)).unwrap();
 
         let task = tokio::task::spawn(Self::on_new_solution(self_mutex.clone(), solution_receiver));
         task_collector
             .safe_lock(|c| c.push(task.abort_handle()))
             .unwrap();
-        Self::start_templates(self_mutex);
+        Self::start_templates(self_mutex, address);
+        Ok(())
     }
 
     pub async fn send(self_: &Arc<Mutex<Self>>, sv2_frame: StdFrame) {
@@ -129,10 +162,9 @@ impl TemplateRx {
         self_mutex: &Arc<Mutex<Self>>,
         new_template: NewTemplate<'static>,
     ) {
+        let (_, template_id) = selector::from_global_id(new_template.template_id);
         let tx_data_request = PoolMessages::TemplateDistribution(
-            TemplateDistribution::RequestTransactionData(RequestTransactionData {
-                template_id: new_template.template_id,
-            }),
+            TemplateDistribution::RequestTransactionData(RequestTransactionData { template_id }),
         );
         let frame: StdFrame = tx_data_request.try_into().unwrap();
         Self::send(self_mutex, frame).await;
@@ -141,6 +173,7 @@ impl TemplateRx {
     async fn get_last_token(
         jd: Option<Arc<Mutex<JobDeclarator>>>,
         miner_coinbase_output: &[u8],
+        additional_size: usize,
     ) -> AllocateMiningJobTokenSuccess<'static> {
         if let Some(jd) = jd {
             super::job_declarator::JobDeclarator::get_last_token(&jd).await
@@ -148,22 +181,38 @@ impl TemplateRx {
             AllocateMiningJobTokenSuccess {
                 request_id: 0,
                 mining_job_token: vec![0; 32].try_into().unwrap(),
-                coinbase_output_max_additional_size: 100,
+                coinbase_output_max_additional_size: 100 + additional_size as u32,
                 coinbase_output: miner_coinbase_output.to_vec().try_into().unwrap(),
                 async_mining_allowed: true,
             }
         }
     }
 
-    pub fn start_templates(self_mutex: Arc<Mutex<Self>>) {
+    pub fn start_templates(self_mutex: Arc<Mutex<Self>>, address: SocketAddr) {
         let jd = self_mutex.safe_lock(|s| s.jd.clone()).unwrap();
+        let (source, selector) = self_mutex
+            .safe_lock(|s| (s.source, s.selector.clone()))
+            .unwrap();
         let down = self_mutex.safe_lock(|s| s.down.clone()).unwrap();
         let tx_status = self_mutex.safe_lock(|s| s.tx_status.clone()).unwrap();
         let mut coinbase_output_max_additional_size_sent = false;
         let mut last_token = None;
-        let miner_coinbase_output = self_mutex
-            .safe_lock(|s| s.miner_coinbase_output.clone())
+        let (miner_coinbase_output, additional_outputs) = self_mutex
+            .safe_lock(|s| {
+                (
+                    s.miner_coinbase_output.clone(),
+                    s.additional_coinbase_outputs.clone(),
+                )
+            })
             .unwrap();
+        let additional_size = outputs_size(&additional_outputs);
+        let additional_value: u64 = additional_outputs.iter().map(|o| o.value).sum();
+        let mut encoded_additional_outputs = vec![];
+        for output in &additional_outputs {
+            output
+                .consensus_encode(&mut encoded_additional_outputs)
+                .expect("Invalid additional coinbase output in config");
+        }
         let main_task = {
             let self_mutex = self_mutex.clone();
             tokio::task::spawn(async move {
@@ -171,8 +220,20 @@ impl TemplateRx {
                 loop {
                     if last_token.is_none() {
                         let jd = self_mutex.safe_lock(|s| s.jd.clone()).unwrap();
-                        last_token =
-                            Some(Self::get_last_token(jd, &miner_coinbase_output[..]).await);
+                        let token =
+                            Self::get_last_token(jd, &miner_coinbase_output[..], additional_size)
+                                .await;
+                        // The pool output and the additional ones are all the outputs added by
+                        // the JDC, they must fit in the space reserved by the TP
+                        let size = token.coinbase_output.inner_as_ref().len() + additional_size;
+                        let max_size = token.coinbase_output_max_additional_size;
+                        if size > max_size as usize {
+                            handle_result!(
+                                tx_status.clone(),
+                                Err(Error::CoinbaseOutputsTooBig(size, max_size))
+                            );
+                        }
+                        last_token = Some(token);
                     }
                     if !coinbase_output_max_additional_size_sent {
                         coinbase_output_max_additional_size_sent = true;
@@ -191,7 +252,22 @@ impl TemplateRx {
                         .clone()
                         .safe_lock(|s| s.receiver.clone())
                         .unwrap();
-                    let received = handle_result!(tx_status.clone(), receiver.recv().await);
+                    let received = match receiver.recv().await {
+                        Ok(received) => received,
+                        Err(e) => {
+                            // Keep mining on the templates of the other Template Providers if any
+                            let connected =
+                                selector.safe_lock(|s| s.remove_source(source)).unwrap();
+                            if connected > 0 {
+                                warn!(
+                                    "Template Provider {} disconnected, {} left",
+                                    address, connected
+                                );
+                                break;
+                            }
+                            handle_result!(tx_status.clone(), Err(e))
+                        }
+                    };
                     let mut frame: StdFrame =
                         handle_result!(tx_status.clone(), received.try_into());
                     let message_type = frame.get_header().unwrap().msg_type();
@@ -208,7 +284,30 @@ impl TemplateRx {
                             match m {
                                 // Send the new template along with the token to the JD so that JD
                                 // can declare the mining job
-                                Some(TemplateDistribution::NewTemplate(m)) => {
+                                Some(TemplateDistribution::NewTemplate(mut m)) => {
+                                    if !selector
+                                        .safe_lock(|s| s.on_new_template(source, &m))
+                                        .unwrap()
+                                    {
+                                        continue;
+                                    }
+                                    // The additional outputs are paid with the value of the pool
+                                    // output
+                                    m.coinbase_tx_value_remaining = match m
+                                        .coinbase_tx_value_remaining
+                                        .checked_sub(additional_value)
+                                    {
+                                        Some(value) => value,
+                                        None => {
+                                            error!(
+                                                "Additional coinbase outputs of {} sats, template {} has only {} sats, ignoring it",
+                                                additional_value,
+                                                m.template_id,
+                                                m.coinbase_tx_value_remaining
+                                            );
+                                            continue;
+                                        }
+                                    };
                                     // See coment on the definition of the global for memory
                                     // ordering
                                     super::IS_NEW_TEMPLATE_HANDLED
@@ -223,11 +322,24 @@ impl TemplateRx {
                                         &down,
                                         m.clone(),
                                         &pool_output[..],
+                                        &additional_outputs,
                                     )
                                     .await
                                     .unwrap();
                                 }
                                 Some(TemplateDistribution::SetNewPrevHash(m)) => {
+                                    if !selector.safe_lock(|s| s.is_active(source)).unwrap() {
+                                        continue;
+                                    }
+                                    // The job of the future template has been declared
+                                    // beforehand, so it is activated upstream right away without
+                                    // waiting for the downstream
+                                    if let Some(jd) = jd.as_ref() {
+                                        super::job_declarator::JobDeclarator::on_set_new_prev_hash(
+                                            jd.clone(),
+                                            m.clone(),
+                                        );
+                                    }
                                     info!("Received SetNewPrevHash, waiting for IS_NEW_TEMPLATE_HANDLED");
                                     // See coment on the definition of the global for memory
                                     // ordering
@@ -237,12 +349,6 @@ impl TemplateRx {
                                         tokio::task::yield_now().await;
                                     }
                                     info!("IS_NEW_TEMPLATE_HANDLED ok");
-                                    if let Some(jd) = jd.as_ref() {
-                                        super::job_declarator::JobDeclarator::on_set_new_prev_hash(
-                                            jd.clone(),
-                                            m.clone(),
-                                        );
-                                    }
                                     super::downstream::DownstreamMiningNode::on_set_new_prev_hash(
                                         &down, m,
                                     )
@@ -251,6 +357,11 @@ impl TemplateRx {
                                 }
 
                                 Some(TemplateDistribution::RequestTransactionDataSuccess(m)) => {
+                                    // A better template has been received from another Template
+                                    // Provider in the meantime
+                                    if !selector.safe_lock(|s| s.is_active(source)).unwrap() {
+                                        continue;
+                                    }
                                     // safe to unwrap because this message is received after the new
                                     // template message
                                     let transactions_data = m.transaction_list;
@@ -262,7 +373,9 @@ impl TemplateRx {
                                     let token = last_token.unwrap();
                                     last_token = None;
                                     let mining_token = token.mining_job_token.to_vec();
-                                    let pool_coinbase_out = token.coinbase_output.to_vec();
+                                    let mut pool_coinbase_out = token.coinbase_output.to_vec();
+                                    pool_coinbase_out
+                                        .extend_from_slice(&encoded_additional_outputs);
                                     if let Some(jd) = jd.as_ref() {
                                         super::job_declarator::JobDeclarator::on_new_template(
                                             jd,
@@ -315,17 +428,69 @@ impl TemplateRx {
 
     async fn on_new_solution(self_: Arc<Mutex<Self>>, rx: Receiver<SubmitSolution<'static>>) {
         while let Ok(solution) = rx.recv().await {
-            if !self_
+            if self_
                 .safe_lock(|s| s.test_only_do_not_send_solution_to_tp)
                 .unwrap()
             {
-                let sv2_frame: StdFrame = PoolMessages::TemplateDistribution(
-                    TemplateDistribution::SubmitSolution(solution),
+                continue;
+            }
+            let mut for_tp = solution.clone();
+            for_tp.template_id = selector::from_global_id(solution.template_id).1;
+            let sv2_frame: StdFrame =
+                PoolMessages::TemplateDistribution(TemplateDistribution::SubmitSolution(for_tp))
+                    .try_into()
+                    .expect("Failed to convert solution to sv2 frame!");
+            let sender = self_.safe_lock(|s| s.sender.clone()).unwrap();
+            if sender.send(sv2_frame.into()).await.is_err() {
+                error!("Template Provider down, submitting the block to the node");
+                Self::submit_block(&self_, &solution).await;
+            }
+        }
+    }
+
+    /// Rebuilds the block of `solution` and submits it to `submit_block_rpc`
+    async fn submit_block(self_: &Arc<Mutex<Self>>, solution: &SubmitSolution<'static>) {
+        let (client, prev_hash, transactions) = self_
+            .safe_lock(|s| {
+                (
+                    s.submit_block_rpc.clone(),
+                    s.last_prev_hash.clone(),
+                    s.transactions.get(&solution.template_id).cloned(),
                 )
-                .try_into()
-                .expect("Failed to convert solution to sv2 frame!");
-                Self::send(&self_, sv2_frame).await
+            })
+            .unwrap();
+        let client = match client {
+            Some(client) => client,
+            None => {
+                error!("Block lost: submit_block_rpc is not configured");
+                return;
             }
+        };
+        let (prev_hash, transactions) = match (prev_hash, transactions) {
+            (Some(prev_hash), Some(transactions)) => (prev_hash, transactions),
+            _ => {
+                error!(
+                    "Block lost: prev hash or transactions of template {} not received",
+                    solution.template_id
+                );
+                return;
+            }
+        };
+        let block = match block_from_template_solution(
+            solution,
+            prev_hash.prev_hash,
+            prev_hash.n_bits,
+            &transactions.into_inner(),
+        ) {
+            Ok(block) => block,
+            Err(e) => {
+                error!("Block lost: impossible to reconstruct it: {:?}", e);
+                return;
+            }
+        };
+        match client.submit_block(serialize_hex(&block)).await {
+            Ok(()) => info!("Block {} submitted to the node", block.block_hash()),
+            Err(e) => error!("Failed to submit block {}: {:?}", block.block_hash(), e),
         }
     }
 }
@@ -0,0 +1,121 @@
+//! Selection of the best template when the JDC is connected to more than one Template Provider.
+//!
+//! Every Template Provider is a source, templates are compared by the height they are built on
+//! (taken from the BIP34 height in the coinbase prefix) and then by coinbase value. Only the
+//! messages of the source that sent the best template (the active source) are forwarded to the
+//! downstream and to the JDS. The active source changes as soon as another source sends a better
+//! template, or when the active source disconnects.
+//!
+//! Template ids are only unique per Template Provider, so the id of the source is put in the most
+//! significant byte of the template ids forwarded to the rest of the JDC.
+use roles_logic_sv2::template_distribution_sv2::NewTemplate;
+use tracing::info;
+
+const SOURCE_SHIFT: u32 = 56;
+const LOCAL_ID_MASK: u64 = (1 << SOURCE_SHIFT) - 1;
+
+/// Templates are ordered first by height and then by coinbase value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TemplateKey {
+    pub height: u64,
+    pub coinbase_value: u64,
+}
+
+impl TemplateKey {
+    /// `None` when the coinbase prefix does not start with a BIP34 height
+    pub fn new(template: &NewTemplate) -> Option<Self> {
+        Some(Self {
+            height: bip34_height(template.coinbase_prefix.as_ref())?,
+            coinbase_value: template.coinbase_tx_value_remaining,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TemplateSelector {
+    sources: Vec<usize>,
+    active: Option<usize>,
+    // `None` also when the last template of the active source has no valid height
+    active_key: Option<TemplateKey>,
+}
+
+impl TemplateSelector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_source(&mut self, source: usize) {
+        if !self.sources.contains(&source) {
+            self.sources.push(source);
+        }
+    }
+
+    /// Returns the number of sources still connected
+    pub fn remove_source(&mut self, source: usize) -> usize {
+        self.sources.retain(|s| *s != source);
+        if self.active == Some(source) {
+            self.active = None;
+            self.active_key = None;
+        }
+        self.sources.len()
+    }
+
+    pub fn is_active(&self, source: usize) -> bool {
+        self.active == Some(source)
+    }
+
+    /// Returns true if the template must be forwarded, that is if `source` is the active source
+    /// or if the template is better than the last one of the active source (in that case
+    /// `source` becomes the active source).
+    pub fn on_new_template(&mut self, source: usize, template: &NewTemplate) -> bool {
+        let key = TemplateKey::new(template);
+        match self.active {
+            Some(active) if active == source => {
+                self.active_key = key;
+                true
+            }
+            Some(_) if key <= self.active_key => false,
+            _ => {
+                info!(
+                    "Switching to the templates of Template Provider {}: {:?}",
+                    source, key
+                );
+                self.active = Some(source);
+                self.active_key = key;
+                true
+            }
+        }
+    }
+}
+
+/// Template id used in the JDC for the template `template_id` of `source`
+pub fn to_global_id(source: usize, template_id: u64) -> u64 {
+    ((source as u64) << SOURCE_SHIFT) | (template_id & LOCAL_ID_MASK)
+}
+
+/// Returns the source and the template id used by the Template Provider
+pub fn from_global_id(template_id: u64) -> (usize, u64) {
+    (
+        (template_id >> SOURCE_SHIFT) as usize,
+        template_id & LOCAL_ID_MASK,
+    )
+}
+
+fn bip34_height(coinbase_prefix: &[u8]) -> Option<u64> {
+    match *coinbase_prefix.first()? {
+        // OP_0
+        0x00 => Some(0),
+        // OP_1 to OP_16
+        op @ 0x51..=0x60 => Some((op - 0x50) as u64),
+        len @ 1..=8 => {
+            let bytes = coinbase_prefix.get(1..1 + len as usize)?;
+            Some(
+                bytes
+                    .iter()
+                    .rev()
+                    .fold(0, |height, byte| (height << 8) | *byte as u64),
+            )
+        }
+        _ => None,
+    }
+}
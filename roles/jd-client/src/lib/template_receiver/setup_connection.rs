@@ -1,7 +1,7 @@
 use async_channel::{Receiver, Sender};
 use codec_sv2::{StandardEitherFrame, StandardSv2Frame};
 use roles_logic_sv2::{
-    common_messages_sv2::{Protocol, SetupConnection},
+    common_messages_sv2::{DeviceInfo, Endpoint, Protocol, SetupConnection},
     handlers::common::{ParseUpstreamCommonMessages, SendTo},
     parsers::PoolMessages,
     routing_logic::{CommonRoutingLogic, NoRouting},
@@ -15,23 +15,12 @@ pub struct SetupConnectionHandler {}
 
 impl SetupConnectionHandler {
     fn get_setup_connection_message(address: SocketAddr) -> SetupConnection<'static> {
-        let endpoint_host = address.ip().to_string().into_bytes().try_into().unwrap();
-        let vendor = String::new().try_into().unwrap();
-        let hardware_version = String::new().try_into().unwrap();
-        let firmware = String::new().try_into().unwrap();
-        let device_id = String::new().try_into().unwrap();
-        SetupConnection {
-            protocol: Protocol::TemplateDistributionProtocol,
-            min_version: 2,
-            max_version: 2,
-            flags: 0b0000_0000_0000_0000_0000_0000_0000_0000,
-            endpoint_host,
-            endpoint_port: address.port(),
-            vendor,
-            hardware_version,
-            firmware,
-            device_id,
-        }
+        let endpoint = Endpoint::new(&address.ip().to_string(), address.port()).unwrap();
+        SetupConnection::for_protocol(
+            Protocol::TemplateDistributionProtocol,
+            DeviceInfo::default(),
+            endpoint,
+        )
     }
 
     pub async fn setup(
@@ -10,28 +10,27 @@ use super::super::{
     PoolChangerTrigger,
 };
 use async_channel::{Receiver, Sender};
-use binary_sv2::{Seq0255, U256};
 use codec_sv2::{HandshakeRole, Initiator};
 use error_handling::handle_result;
 use key_utils::Secp256k1PublicKey;
 use network_helpers_sv2::noise_connection_tokio::Connection;
 use roles_logic_sv2::{
     channel_logic::channel_factory::PoolChannelFactory,
-    common_messages_sv2::{Protocol, SetupConnection},
+    common_messages_sv2::{DeviceInfo, Endpoint, Protocol, SetupConnection},
     common_properties::{IsMiningUpstream, IsUpstream},
     handlers::{
         common::{ParseUpstreamCommonMessages, SendTo as SendToCommon},
         mining::{ParseUpstreamMiningMessages, SendTo},
     },
-    job_declaration_sv2::DeclareMiningJob,
     mining_sv2::{ExtendedExtranonce, Extranonce, SetCustomMiningJob},
     parsers::{Mining, MiningDeviceMessages, PoolMessages},
+    pending_requests::PendingRequests,
     routing_logic::{CommonRoutingLogic, MiningRoutingLogic, NoRouting},
     selectors::NullDownstreamMiningSelector,
-    utils::{Id, Mutex},
+    utils::Mutex,
     Error as RolesLogicError,
 };
-use std::{collections::HashMap, net::SocketAddr, sync::Arc, thread::sleep, time::Duration};
+use std::{net::SocketAddr, sync::Arc, thread::sleep, time::Duration};
 use tokio::{net::TcpStream, task, task::AbortHandle};
 use tracing::{error, info, warn};
 
@@ -74,13 +73,13 @@ impl std::default::Default for CircularBuffer {
 #[derive(Debug, Default)]
 struct TemplateToJobId {
     template_id_to_job_id: CircularBuffer,
-    request_id_to_template_id: HashMap<u32, u64>,
+    request_id_to_template_id: PendingRequests<u64>,
 }
 
 impl TemplateToJobId {
-    fn register_template_id(&mut self, template_id: u64, request_id: u32) {
-        self.request_id_to_template_id
-            .insert(request_id, template_id);
+    /// Returns the request id of the `SetCustomMiningJob` for `template_id`
+    fn register_template_id(&mut self, template_id: u64) -> u32 {
+        self.request_id_to_template_id.insert(template_id)
     }
 
     fn register_job_id(&mut self, template_id: u64, job_id: u32) {
@@ -91,8 +90,8 @@ impl TemplateToJobId {
         self.template_id_to_job_id.get(template_id)
     }
 
-    fn take_template_id(&mut self, request_id: u32) -> Option<u64> {
-        self.request_id_to_template_id.remove(&request_id)
+    fn take_template_id(&mut self, request_id: u32) -> Result<u64, RolesLogicError> {
+        self.request_id_to_template_id.take(request_id)
     }
 
     fn new() -> Self {
@@ -125,10 +124,15 @@ pub struct Upstream {
     pool_chaneger_trigger: Arc<Mutex<PoolChangerTrigger>>,
     channel_factory: Option<PoolChannelFactory>,
     template_to_job_id: TemplateToJobId,
-    req_ids: Id,
 }
 
 impl Upstream {
+    /// Used by the tasks bound to the pool connection (e.g. the JDS connection) to report that the
+    /// pool is not reachable anymore
+    pub fn tx_status(&self) -> status::Sender {
+        self.tx_status.clone()
+    }
+
     pub async fn send(self_: &Arc<Mutex<Self>>, sv2_frame: StdFrame) -> ProxyResult<'static, ()> {
         let sender = self_
             .safe_lock(|s| s.sender.clone())
@@ -198,7 +202,6 @@ impl Upstream {
             pool_chaneger_trigger,
             channel_factory: None,
             template_to_job_id: TemplateToJobId::new(),
-            req_ids: Id::new(),
         })))
     }
 
@@ -209,7 +212,7 @@ impl Upstream {
         max_version: u16,
     ) -> ProxyResult<'static, ()> {
         // Get the `SetupConnection` message with Mining Device information (currently hard coded)
-        let setup_connection = Self::get_setup_connection_message(min_version, max_version, true)?;
+        let setup_connection = Self::get_setup_connection_message(min_version, max_version, true);
 
         // Put the `SetupConnection` message in a `StdFrame` to be sent over the wire
         let sv2_frame: StdFrame = Message::Common(setup_connection.into()).try_into()?;
@@ -253,23 +256,17 @@ impl Upstream {
         Ok(())
     }
 
-    #[allow(clippy::too_many_arguments)]
-    pub async fn set_custom_jobs(
+    /// Sends a job accepted by the JDS for `template_id`, its prev hash is already set by the
+    /// [`roles_logic_sv2::job_declarator_client::JobDeclaratorClient`]
+    pub async fn send_custom_job(
         self_: &Arc<Mutex<Self>>,
-        declare_mining_job: DeclareMiningJob<'static>,
-        set_new_prev_hash: roles_logic_sv2::template_distribution_sv2::SetNewPrevHash<'static>,
-        merkle_path: Seq0255<'static, U256<'static>>,
-        signed_token: binary_sv2::B0255<'static>,
-        coinbase_tx_version: u32,
-        coinbase_prefix: binary_sv2::B0255<'static>,
-        coinbase_tx_input_n_sequence: u32,
-        coinbase_tx_value_remaining: u64,
-        coinbase_tx_outs: Vec<u8>,
-        coinbase_tx_locktime: u32,
+        mut custom_job: SetCustomMiningJob<'static>,
         template_id: u64,
     ) -> ProxyResult<'static, ()> {
         info!("Sending set custom mining job");
-        let request_id = self_.safe_lock(|s| s.req_ids.next()).unwrap();
+        let request_id = self_
+            .safe_lock(|s| s.template_to_job_id.register_template_id(template_id))
+            .unwrap();
         let channel_id = loop {
             if let Some(id) = self_.safe_lock(|s| s.channel_id).unwrap() {
                 break id;
@@ -277,36 +274,10 @@ impl Upstream {
             tokio::task::yield_now().await;
         };
 
-        let updated_timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as u32;
-
-        let to_send = SetCustomMiningJob {
-            channel_id,
-            request_id,
-            token: signed_token,
-            version: declare_mining_job.version,
-            prev_hash: set_new_prev_hash.prev_hash,
-            min_ntime: updated_timestamp,
-            nbits: set_new_prev_hash.n_bits,
-            coinbase_tx_version,
-            coinbase_prefix,
-            coinbase_tx_input_n_sequence,
-            coinbase_tx_value_remaining,
-            coinbase_tx_outputs: coinbase_tx_outs.try_into().unwrap(),
-            coinbase_tx_locktime,
-            merkle_path,
-            extranonce_size: 0,
-        };
-        let message = PoolMessages::Mining(Mining::SetCustomMiningJob(to_send));
+        custom_job.channel_id = channel_id;
+        custom_job.request_id = request_id;
+        let message = PoolMessages::Mining(Mining::SetCustomMiningJob(custom_job));
         let frame: StdFrame = message.try_into().unwrap();
-        self_
-            .safe_lock(|s| {
-                s.template_to_job_id
-                    .register_template_id(template_id, request_id)
-            })
-            .unwrap();
         Self::send(self_, frame).await
     }
 
@@ -403,33 +374,25 @@ impl Upstream {
     /// Creates the `SetupConnection` message to setup the connection with the SV2 Upstream role.
     /// TODO: The Mining Device information is hard coded here, need to receive from Downstream
     /// instead.
-    #[allow(clippy::result_large_err)]
     fn get_setup_connection_message(
         min_version: u16,
         max_version: u16,
         is_work_selection_enabled: bool,
-    ) -> ProxyResult<'static, SetupConnection<'static>> {
-        let endpoint_host = "0.0.0.0".to_string().into_bytes().try_into()?;
-        let vendor = String::new().try_into()?;
-        let hardware_version = String::new().try_into()?;
-        let firmware = String::new().try_into()?;
-        let device_id = String::new().try_into()?;
-        let flags = match is_work_selection_enabled {
+    ) -> SetupConnection<'static> {
+        // Infallible unwrap the host is valid
+        let endpoint = Endpoint::new("0.0.0.0", 50).unwrap();
+        let mut setup_connection = SetupConnection::for_protocol(
+            Protocol::MiningProtocol,
+            DeviceInfo::default(),
+            endpoint,
+        );
+        setup_connection.min_version = min_version;
+        setup_connection.max_version = max_version;
+        setup_connection.flags = match is_work_selection_enabled {
             false => 0b0000_0000_0000_0000_0000_0000_0000_0100,
             true => 0b0000_0000_0000_0000_0000_0000_0000_0110,
         };
-        Ok(SetupConnection {
-            protocol: Protocol::MiningProtocol,
-            min_version,
-            max_version,
-            flags,
-            endpoint_host,
-            endpoint_port: 50,
-            vendor,
-            hardware_version,
-            firmware,
-            device_id,
-        })
+        setup_connection
     }
 
     pub async fn take_channel_factory(self_: Arc<Mutex<Self>>) -> PoolChannelFactory {
@@ -595,6 +558,8 @@ impl ParseUpstreamMiningMessages<Downstream, NullDownstreamMiningSelector, NoRou
             .try_into()
             .unwrap();
         self.channel_id = Some(m.channel_id);
+        // The JDC has a single channel, the one of a previous upstream is gone
+        monitoring_sv2::metrics().channels_open.set(1);
         channel_factory
             .replicate_upstream_extended_channel_only_jd(
                 m.target.into_static(),
@@ -635,6 +600,7 @@ impl ParseUpstreamMiningMessages<Downstream, NullDownstreamMiningSelector, NoRou
         &mut self,
         _m: roles_logic_sv2::mining_sv2::CloseChannel,
     ) -> Result<roles_logic_sv2::handlers::mining::SendTo<Downstream>, RolesLogicError> {
+        monitoring_sv2::metrics().channels_open.set(0);
         Ok(SendTo::RelaySameMessageToRemote(
             self.downstream.as_ref().unwrap().clone(),
         ))
@@ -653,8 +619,11 @@ impl ParseUpstreamMiningMessages<Downstream, NullDownstreamMiningSelector, NoRou
     /// Handles the SV2 `SubmitSharesSuccess` message.
     fn handle_submit_shares_success(
         &mut self,
-        _m: roles_logic_sv2::mining_sv2::SubmitSharesSuccess,
+        m: roles_logic_sv2::mining_sv2::SubmitSharesSuccess,
     ) -> Result<roles_logic_sv2::handlers::mining::SendTo<Downstream>, RolesLogicError> {
+        monitoring_sv2::metrics()
+            .shares_accepted
+            .inc_by(m.new_submits_accepted_count.into());
         Ok(SendTo::RelaySameMessageToRemote(
             self.downstream.as_ref().unwrap().clone(),
         ))
@@ -663,8 +632,9 @@ impl ParseUpstreamMiningMessages<Downstream, NullDownstreamMiningSelector, NoRou
     /// Handles the SV2 `SubmitSharesError` message.
     fn handle_submit_shares_error(
         &mut self,
-        _m: roles_logic_sv2::mining_sv2::SubmitSharesError,
+        m: roles_logic_sv2::mining_sv2::SubmitSharesError,
     ) -> Result<roles_logic_sv2::handlers::mining::SendTo<Downstream>, RolesLogicError> {
+        monitoring_sv2::metrics().on_share_error(m.error_code.as_ref());
         self.pool_chaneger_trigger
             .safe_lock(|t| t.start(self.tx_status.clone()))
             .unwrap();
@@ -710,14 +680,14 @@ impl ParseUpstreamMiningMessages<Downstream, NullDownstreamMiningSelector, NoRou
     ) -> Result<roles_logic_sv2::handlers::mining::SendTo<Downstream>, RolesLogicError> {
         // TODO
         info!("Set custom mining job success {}", m.job_id);
-        if let Some(template_id) = self.template_to_job_id.take_template_id(m.request_id) {
-            self.template_to_job_id
-                .register_job_id(template_id, m.job_id);
-            Ok(SendTo::None(None))
-        } else {
-            error!("Attention received a SetupConnectionSuccess with unknown request_id");
-            Ok(SendTo::None(None))
+        match self.template_to_job_id.take_template_id(m.request_id) {
+            Ok(template_id) => {
+                self.template_to_job_id
+                    .register_job_id(template_id, m.job_id);
+            }
+            Err(e) => error!("SetCustomMiningJobSuccess not paired with a request: {}", e),
         }
+        Ok(SendTo::None(None))
     }
 
     /// Handles the SV2 `SetCustomMiningJobError` message (TODO).
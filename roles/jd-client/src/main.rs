@@ -9,7 +9,6 @@ use lib::{
 };
 
 use args::Args;
-use ext_config::{Config, File, FileFormat};
 use tracing::error;
 
 /// Process CLI args and load configuration.
@@ -21,18 +20,8 @@ fn process_cli_args<'a>() -> ProxyResult<'a, ProxyConfig> {
         Error::BadCliArgs
     })?;
 
-    // Build configuration from the provided file path
-    let config_path = args.config_path.to_str().ok_or_else(|| {
-        error!("Invalid configuration path.");
-        Error::BadCliArgs
-    })?;
-
-    let settings = Config::builder()
-        .add_source(File::new(config_path, FileFormat::Toml))
-        .build()?;
-
-    // Deserialize settings into ProxyConfig
-    let config = settings.try_deserialize::<ProxyConfig>()?;
+    // Load the configuration from the provided file path
+    let config = config_helpers_sv2::load::<ProxyConfig>(&args.config_path, "JDC")?;
     Ok(config)
 }
 
@@ -84,13 +73,17 @@ fn process_cli_args<'a>() -> ProxyResult<'a, ProxyConfig> {
 /// the actual NewTemplate so that we do not send a lot of useless future Job to the pool. That
 /// means that SetCustomMiningJob is sent only when a NewTemplate become "active"
 ///
+/// Future NewTemplates are declared to the JDS as soon as they are received and the
+/// SetCustomMiningJob is built when the JDS accepts them, so that when the SetNewPrevHash arrives
+/// only the prev hash has to be filled in before sending the job to the pool.
+///
 /// The JobDeclarator always have 2 avaiable token, that means that whenever a token is used to
 /// commit a job with upstream we require a new one. Having always a token when needed means that
 /// whenever we want to commit a mining job we can do that without waiting for upstream to provide
 /// a new token.
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt::init();
+    logging_sv2::init();
     let proxy_config = match process_cli_args() {
         Ok(p) => p,
         Err(e) => {
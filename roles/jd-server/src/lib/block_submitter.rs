@@ -0,0 +1,356 @@
+//! Propagation of the blocks found with declared jobs.
+//!
+//! A found block is submitted in parallel to every configured endpoint: the node used for the
+//! mempool, any additional node via `submitblock` and any peer via a raw P2P `block` message. The
+//! submission fails only if no endpoint accepted the block.
+use super::error::{JdsError, SubmitBlockFailure};
+use rpc_sv2::mini_rpc_client::{Auth, MiniRpcClient};
+use serde::Deserialize;
+use std::{
+    net::SocketAddr,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use stratum_common::bitcoin::{
+    consensus::encode::{deserialize, serialize},
+    network::{
+        constants::ServiceFlags,
+        message::{NetworkMessage, RawNetworkMessage},
+        message_network::VersionMessage,
+        Address,
+    },
+    Block, Network,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+use tracing::{info, warn};
+
+/// Time given to each endpoint to accept the block
+const SUBMIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Biggest P2P payload accepted from a peer, the `MAX_SIZE` of Bitcoin Core
+const MAX_SIZE: usize = 0x0200_0000;
+
+/// Additional node where found blocks are submitted
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SubmitBlockNode {
+    /// Submit with the `submitblock` RPC
+    Rpc {
+        url: String,
+        port: u16,
+        user: String,
+        pass: String,
+    },
+    /// Send the block to a peer with a P2P `block` message. `network` is one of "bitcoin",
+    /// "testnet", "signet", "regtest".
+    P2p { address: String, network: String },
+}
+
+#[derive(Debug, Clone)]
+enum Endpoint {
+    Rpc(String, MiniRpcClient),
+    P2p(SocketAddr, Network),
+}
+
+impl Endpoint {
+    fn name(&self) -> String {
+        match self {
+            Endpoint::Rpc(url, _) => format!("rpc {}", url),
+            Endpoint::P2p(address, _) => format!("p2p {}", address),
+        }
+    }
+
+    async fn submit(&self, block_hex: &str, block: &Block) -> Result<(), String> {
+        match self {
+            Endpoint::Rpc(_, client) => client
+                .submit_block(block_hex.to_string())
+                .await
+                .map_err(|e| format!("{:?}", e)),
+            Endpoint::P2p(address, network) => submit_p2p(*address, *network, block).await,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BlockSubmitter {
+    endpoints: Vec<Endpoint>,
+}
+
+impl BlockSubmitter {
+    /// `primary` is the node used to keep the mempool in sync
+    pub fn new(
+        primary: Option<MiniRpcClient>,
+        primary_url: &str,
+        nodes: &[SubmitBlockNode],
+    ) -> Result<Self, JdsError> {
+        let mut endpoints = Vec::new();
+        if let Some(client) = primary {
+            endpoints.push(Endpoint::Rpc(primary_url.to_string(), client));
+        }
+        for node in nodes {
+            match node {
+                SubmitBlockNode::Rpc {
+                    url,
+                    port,
+                    user,
+                    pass,
+                } => {
+                    let url = format!("{}:{}", url, port);
+                    let auth = Auth::new(user.clone(), pass.clone());
+                    endpoints.push(Endpoint::Rpc(url.clone(), MiniRpcClient::new(url, auth)));
+                }
+                SubmitBlockNode::P2p { address, network } => {
+                    let address = SocketAddr::from_str(address)
+                        .map_err(|e| JdsError::Custom(format!("{}: {}", address, e)))?;
+                    let network = Network::from_str(network)
+                        .map_err(|e| JdsError::Custom(format!("{}: {}", network, e)))?;
+                    endpoints.push(Endpoint::P2p(address, network));
+                }
+            }
+        }
+        Ok(Self { endpoints })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    /// Submits the block to every endpoint in parallel. Returns the endpoints that accepted the
+    /// block or `JdsError::BlockSubmission` with the failure of each endpoint if none did.
+    pub async fn submit(&self, block_hex: String) -> Result<Vec<String>, JdsError> {
+        let block: Block = hex::decode(&block_hex)
+            .ok()
+            .and_then(|bytes| deserialize(&bytes).ok())
+            .ok_or_else(|| JdsError::ImpossibleToReconstructBlock(block_hex.clone()))?;
+        let submissions = self.endpoints.iter().map(|endpoint| {
+            let endpoint = endpoint.clone();
+            let block_hex = block_hex.clone();
+            let block = block.clone();
+            tokio::spawn(async move {
+                let result =
+                    match timeout(SUBMIT_TIMEOUT, endpoint.submit(&block_hex, &block)).await {
+                        Ok(result) => result,
+                        Err(_) => Err("timeout".to_string()),
+                    };
+                (endpoint.name(), result)
+            })
+        });
+        let mut accepted = Vec::new();
+        let mut failures = Vec::new();
+        for submission in submissions.collect::<Vec<_>>() {
+            match submission.await {
+                Ok((endpoint, Ok(()))) => {
+                    info!("Block {} submitted to {}", block.block_hash(), endpoint);
+                    accepted.push(endpoint);
+                }
+                Ok((endpoint, Err(error))) => {
+                    warn!(
+                        "Failed to submit block {} to {}: {}",
+                        block.block_hash(),
+                        endpoint,
+                        error
+                    );
+                    failures.push(SubmitBlockFailure { endpoint, error });
+                }
+                Err(e) => failures.push(SubmitBlockFailure {
+                    endpoint: "unknown".to_string(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+        if accepted.is_empty() {
+            Err(JdsError::BlockSubmission(failures))
+        } else {
+            Ok(accepted)
+        }
+    }
+}
+
+/// Connects to `address`, does the version handshake and sends the block. The block is only
+/// reported as submitted once the peer answered the `ping` sent after it, as a peer processes the
+/// messages of a connection in order.
+async fn submit_p2p(address: SocketAddr, network: Network, block: &Block) -> Result<(), String> {
+    let mut stream = TcpStream::connect(address)
+        .await
+        .map_err(|e| e.to_string())?;
+    let magic = network.magic();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let version = VersionMessage::new(
+        ServiceFlags::NONE,
+        timestamp,
+        Address::new(&address, ServiceFlags::NONE),
+        Address::new(&address, ServiceFlags::NONE),
+        rand::random(),
+        "/jd-server/".to_string(),
+        0,
+    );
+    send_p2p(&mut stream, magic, NetworkMessage::Version(version)).await?;
+    let mut verack_received = false;
+    while !verack_received {
+        match recv_p2p(&mut stream).await? {
+            NetworkMessage::Version(_) => {
+                send_p2p(&mut stream, magic, NetworkMessage::Verack).await?;
+            }
+            NetworkMessage::Verack => verack_received = true,
+            NetworkMessage::Ping(nonce) => {
+                send_p2p(&mut stream, magic, NetworkMessage::Pong(nonce)).await?;
+            }
+            _ => (),
+        }
+    }
+    send_p2p(&mut stream, magic, NetworkMessage::Block(block.clone())).await?;
+    let nonce = rand::random();
+    send_p2p(&mut stream, magic, NetworkMessage::Ping(nonce)).await?;
+    stream.flush().await.map_err(|e| e.to_string())?;
+    loop {
+        match recv_p2p(&mut stream).await? {
+            NetworkMessage::Pong(n) if n == nonce => return Ok(()),
+            NetworkMessage::Ping(n) => {
+                send_p2p(&mut stream, magic, NetworkMessage::Pong(n)).await?;
+            }
+            _ => (),
+        }
+    }
+}
+
+async fn send_p2p(
+    stream: &mut TcpStream,
+    magic: u32,
+    payload: NetworkMessage,
+) -> Result<(), String> {
+    let message = RawNetworkMessage { magic, payload };
+    stream
+        .write_all(&serialize(&message))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn recv_p2p(stream: &mut TcpStream) -> Result<NetworkMessage, String> {
+    // magic (4) + command (12) + payload len (4) + checksum (4)
+    let mut message = vec![0; 24];
+    stream
+        .read_exact(&mut message)
+        .await
+        .map_err(|e| e.to_string())?;
+    let len = u32::from_le_bytes([message[16], message[17], message[18], message[19]]) as usize;
+    if len > MAX_SIZE {
+        return Err(format!(
+            "Message of {} bytes is bigger than the max of {} bytes",
+            len, MAX_SIZE
+        ));
+    }
+    message.resize(24 + len, 0);
+    stream
+        .read_exact(&mut message[24..])
+        .await
+        .map_err(|e| e.to_string())?;
+    let message: RawNetworkMessage = deserialize(&message).map_err(|e| e.to_string())?;
+    Ok(message.payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stratum_common::bitcoin::blockdata::constants::genesis_block;
+    use tokio::net::TcpListener;
+
+    // Plays the peer side of the P2P connection and returns the received block
+    async fn peer(listener: TcpListener, network: Network) -> Block {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let magic = network.magic();
+        assert!(matches!(
+            recv_p2p(&mut stream).await.unwrap(),
+            NetworkMessage::Version(_)
+        ));
+        let version = VersionMessage::new(
+            ServiceFlags::NETWORK,
+            0,
+            Address::new(&listener.local_addr().unwrap(), ServiceFlags::NONE),
+            Address::new(&listener.local_addr().unwrap(), ServiceFlags::NONE),
+            0,
+            "/peer/".to_string(),
+            0,
+        );
+        send_p2p(&mut stream, magic, NetworkMessage::Version(version))
+            .await
+            .unwrap();
+        assert_eq!(recv_p2p(&mut stream).await.unwrap(), NetworkMessage::Verack);
+        send_p2p(&mut stream, magic, NetworkMessage::Verack)
+            .await
+            .unwrap();
+        let block = match recv_p2p(&mut stream).await.unwrap() {
+            NetworkMessage::Block(block) => block,
+            m => panic!("Unexpected message {:?}", m),
+        };
+        match recv_p2p(&mut stream).await.unwrap() {
+            NetworkMessage::Ping(nonce) => {
+                send_p2p(&mut stream, magic, NetworkMessage::Pong(nonce))
+                    .await
+                    .unwrap()
+            }
+            m => panic!("Unexpected message {:?}", m),
+        }
+        block
+    }
+
+    #[tokio::test]
+    async fn test_submit_block() {
+        let block = genesis_block(Network::Regtest);
+        let block_hex = hex::encode(serialize(&block));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let peer = tokio::spawn(peer(listener, Network::Regtest));
+        // Nothing is listening on the second endpoint
+        let unreachable = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let unreachable_address = unreachable.local_addr().unwrap();
+        drop(unreachable);
+        let nodes = vec![
+            SubmitBlockNode::P2p {
+                address: address.to_string(),
+                network: "regtest".to_string(),
+            },
+            SubmitBlockNode::P2p {
+                address: unreachable_address.to_string(),
+                network: "regtest".to_string(),
+            },
+        ];
+        let submitter = BlockSubmitter::new(None, "", &nodes).unwrap();
+        assert_eq!(
+            submitter.submit(block_hex.clone()).await.unwrap(),
+            vec![format!("p2p {}", address)]
+        );
+        assert_eq!(peer.await.unwrap(), block);
+
+        let submitter = BlockSubmitter::new(None, "", &nodes[1..]).unwrap();
+        match submitter.submit(block_hex).await {
+            Err(JdsError::BlockSubmission(failures)) => {
+                assert_eq!(failures.len(), 1);
+                assert_eq!(failures[0].endpoint, format!("p2p {}", unreachable_address));
+            }
+            r => panic!("Unexpected result {:?}", r),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oversized_message_is_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let peer = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut header = vec![0; 24];
+            header[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+            stream.write_all(&header).await.unwrap();
+            stream
+        });
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let _peer_stream = peer.await.unwrap();
+        assert!(recv_p2p(&mut stream).await.is_err());
+    }
+}
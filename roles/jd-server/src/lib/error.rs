@@ -24,6 +24,15 @@ pub enum JdsError {
     MempoolError(JdsMempoolError),
     ImpossibleToReconstructBlock(String),
     NoLastDeclaredJob,
+    /// No endpoint accepted the found block
+    BlockSubmission(Vec<SubmitBlockFailure>),
+}
+
+/// Failure of an endpoint to accept a found block
+#[derive(Debug)]
+pub struct SubmitBlockFailure {
+    pub endpoint: String,
+    pub error: String,
 }
 
 impl std::fmt::Display for JdsError {
@@ -48,6 +57,13 @@ impl std::fmt::Display for JdsError {
                 write!(f, "Error in reconstructing the block: {:?}", e)
             }
             NoLastDeclaredJob => write!(f, "Last declared job not found"),
+            BlockSubmission(failures) => {
+                write!(f, "Block submission failed:")?;
+                for failure in failures {
+                    write!(f, " {}: `{}`;", failure.endpoint, failure.error)?;
+                }
+                Ok(())
+            }
         }
     }
 }
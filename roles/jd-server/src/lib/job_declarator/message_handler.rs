@@ -7,6 +7,8 @@ use roles_logic_sv2::{
         ProvideMissingTransactions, ProvideMissingTransactionsSuccess, SubmitSolutionJd,
     },
     parsers::JobDeclaration,
+    protocol_errors::IntoProtocolError,
+    token_manager::TokenManager,
     utils::Mutex,
 };
 use std::{convert::TryInto, io::Cursor, sync::Arc};
@@ -14,7 +16,11 @@ use stratum_common::bitcoin::{Transaction, Txid};
 pub type SendTo = SendTo_<JobDeclaration<'static>, ()>;
 use crate::mempool::JDsMempool;
 
-use super::{signed_token, TransactionState};
+use super::{
+    super::job_store::{JobRecord, JobVerdict},
+    policy::{self, PolicyViolation, POLICY_VIOLATION_ERROR_CODE},
+    signed_token, TransactionState,
+};
 use roles_logic_sv2::{errors::Error, parsers::PoolMessages as AllMessages};
 use stratum_common::bitcoin::consensus::Decodable;
 use tracing::{debug, info};
@@ -22,35 +28,74 @@ use tracing::{debug, info};
 use super::JobDeclaratorDownstream;
 
 impl JobDeclaratorDownstream {
-    fn verify_job(&mut self, message: &DeclareMiningJob) -> bool {
-        // Convert token from B0255 to u32
-        let four_byte_array: [u8; 4] = message
-            .mining_job_token
-            .clone()
-            .to_vec()
-            .as_slice()
-            .try_into()
-            .unwrap();
-        let token_u32 = u32::from_le_bytes(four_byte_array);
+    fn verify_job(&mut self, message: &DeclareMiningJob) -> Result<(), Error> {
         // TODO Function to implement, it must be checked if the requested job has:
         // 1. right coinbase
         // 2. right version field
         // 3. right prev-hash
         // 4. right nbits
-        self.token_to_job_map.contains_key(&(token_u32))
+        match self
+            .token_manager
+            .safe_lock(|manager| manager.validate_bytes(message.mining_job_token.inner_as_ref()))
+            .map_err(|e| Error::PoisonLock(e.to_string()))?
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                info!("Invalid DeclareMiningJob: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Checks the declared transactions whose data is known against the policy of the JDS
+    fn check_policy(
+        &self,
+        transactions: &[Transaction],
+    ) -> Result<Result<(), PolicyViolation>, Error> {
+        self.mempool
+            .safe_lock(|mempool| {
+                policy::check_transactions(self.job_policy.as_ref(), transactions, mempool)
+            })
+            .map_err(|e| Error::PoisonLock(e.to_string()))
     }
 }
 
+fn policy_violation_error(request_id: u32, violation: PolicyViolation) -> SendTo {
+    info!(
+        "Declared job {} violates the policy: {}",
+        request_id, violation
+    );
+    let mut error_details = violation.to_string().into_bytes();
+    error_details.truncate(u16::MAX as usize);
+    let message_error = DeclareMiningJobError {
+        request_id,
+        // Infallible unwraps the code is shorter than 255 bytes and the details are truncated
+        error_code: POLICY_VIOLATION_ERROR_CODE
+            .to_string()
+            .into_bytes()
+            .try_into()
+            .unwrap(),
+        error_details: error_details.try_into().unwrap(),
+    };
+    SendTo::Respond(JobDeclaration::DeclareMiningJobError(message_error))
+}
+
 impl ParseClientJobDeclarationMessages for JobDeclaratorDownstream {
     fn handle_allocate_mining_job_token(
         &mut self,
         message: AllocateMiningJobToken,
     ) -> Result<SendTo, Error> {
-        let token = self.tokens.next();
-        self.token_to_job_map.insert(token, None);
+        let user_identifier = String::from_utf8_lossy(message.user_identifier.inner_as_ref());
+        let token = self
+            .token_manager
+            .safe_lock(|manager| {
+                manager.expire();
+                manager.allocate(&user_identifier)
+            })
+            .map_err(|e| Error::PoisonLock(e.to_string()))??;
         let message_success = AllocateMiningJobTokenSuccess {
             request_id: message.request_id,
-            mining_job_token: token.to_le_bytes().to_vec().try_into().unwrap(),
+            mining_job_token: TokenManager::token_to_bytes(token),
             coinbase_output_max_additional_size: 100,
             async_mining_allowed: self.async_mining_allowed,
             coinbase_output: self.coinbase_output.clone().try_into().unwrap(),
@@ -73,80 +118,101 @@ impl ParseClientJobDeclarationMessages for JobDeclaratorDownstream {
         // The unknown transactions is a vector that contains the transactions that are not in the
         // jds mempool, and will be non-empty in the ProvideMissingTransactionsSuccess message
         let mut known_transactions: Vec<Txid> = vec![];
-        self.tx_hash_list_hash = Some(message.tx_hash_list_hash.clone().into_static());
-        if self.verify_job(&message) {
-            let short_hash_list: Vec<ShortTxId> = message
-                .tx_short_hash_list
-                .inner_as_ref()
-                .iter()
-                .map(|x| x.to_vec().try_into().unwrap())
-                .collect();
-            let nonce = message.tx_short_hash_nonce;
-            // TODO return None when we have a collision handle that case as weel
-            let short_id_mempool = self
-                .mempool
-                .safe_lock(|x| x.to_short_ids(nonce))
-                .unwrap()
-                .unwrap();
-            let mut transactions_with_state =
-                vec![TransactionState::Missing; short_hash_list.len()];
-            let mut missing_txs: Vec<u16> = Vec::new();
+        if let Err(e) = self.verify_job(&message) {
+            self.record(JobRecord::declared(
+                self.user_identifier(&message),
+                &message,
+                JobVerdict::Rejected(e.declare_mining_job_error_code().to_string()),
+            ));
+            let message_error = e.declare_mining_job_error_with_details(
+                message.request_id,
+                e.to_string().into_bytes(),
+            );
+            let message_enum_error = JobDeclaration::DeclareMiningJobError(message_error);
+            return Ok(SendTo::Respond(message_enum_error));
+        }
+        let short_hash_list: Vec<ShortTxId> = message
+            .tx_short_hash_list
+            .inner_as_ref()
+            .iter()
+            .map(|x| x.to_vec().try_into().unwrap())
+            .collect();
+        let nonce = message.tx_short_hash_nonce;
+        // TODO return None when we have a collision handle that case as weel
+        let short_id_mempool = self
+            .mempool
+            .safe_lock(|x| x.to_short_ids(nonce))
+            .unwrap()
+            .unwrap();
+        let mut transactions_with_state = vec![TransactionState::Missing; short_hash_list.len()];
+        let mut missing_txs: Vec<u16> = Vec::new();
+        // transactions in the mempool with their data, they are checked against the policy
+        let mut known_transactions_data: Vec<Transaction> = Vec::new();
 
-            for (i, sid) in short_hash_list.iter().enumerate() {
-                let sid_: [u8; 6] = sid.to_vec().try_into().unwrap();
-                match short_id_mempool.get(&sid_) {
-                    Some(tx_data) => {
-                        transactions_with_state[i] = TransactionState::PresentInMempool(tx_data.id);
-                        known_transactions.push(tx_data.id);
-                    }
-                    None => {
-                        transactions_with_state[i] = TransactionState::Missing;
-                        missing_txs.push(i as u16);
+        for (i, sid) in short_hash_list.iter().enumerate() {
+            let sid_: [u8; 6] = sid.to_vec().try_into().unwrap();
+            match short_id_mempool.get(&sid_) {
+                Some(tx_data) => {
+                    transactions_with_state[i] = TransactionState::PresentInMempool(tx_data.id);
+                    known_transactions.push(tx_data.id);
+                    if let Some((tx, _)) = &tx_data.tx {
+                        known_transactions_data.push(tx.clone());
                     }
                 }
+                None => {
+                    transactions_with_state[i] = TransactionState::Missing;
+                    missing_txs.push(i as u16);
+                }
             }
-            self.declared_mining_job = (
-                Some(message.clone().into_static()),
-                transactions_with_state,
-                missing_txs.clone(),
-            );
-            // here we send the transactions that we want to be stored in jds mempool with full data
+        }
+        if let Err(violation) = self.check_policy(&known_transactions_data)? {
+            self.record(JobRecord::declared(
+                self.user_identifier(&message),
+                &message,
+                JobVerdict::Rejected(violation.to_string()),
+            ));
+            return Ok(policy_violation_error(message.request_id, violation));
+        }
+        self.declared_mining_job = (
+            Some(message.clone().into_static()),
+            transactions_with_state,
+            missing_txs.clone(),
+        );
+        // here we send the transactions that we want to be stored in jds mempool with full data
 
-            self.add_txs_to_mempool
-                .add_txs_to_mempool_inner
-                .known_transactions
-                .append(&mut known_transactions);
+        self.add_txs_to_mempool
+            .add_txs_to_mempool_inner
+            .known_transactions
+            .append(&mut known_transactions);
 
-            if missing_txs.is_empty() {
-                let message_success = DeclareMiningJobSuccess {
-                    request_id: message.request_id,
-                    new_mining_job_token: signed_token(
-                        message.tx_hash_list_hash.clone(),
-                        &self.public_key.clone(),
-                        &self.private_key.clone(),
-                    ),
-                };
-                let message_enum_success = JobDeclaration::DeclareMiningJobSuccess(message_success);
-                Ok(SendTo::Respond(message_enum_success))
-            } else {
-                let message_provide_missing_transactions = ProvideMissingTransactions {
-                    request_id: message.request_id,
-                    unknown_tx_position_list: missing_txs.into(),
-                };
-                let message_enum_provide_missing_transactions =
-                    JobDeclaration::ProvideMissingTransactions(
-                        message_provide_missing_transactions,
-                    );
-                Ok(SendTo::Respond(message_enum_provide_missing_transactions))
-            }
+        if missing_txs.is_empty() {
+            self.record(JobRecord::declared(
+                self.user_identifier(&message),
+                &message,
+                JobVerdict::Accepted,
+            ));
+            let message_success = DeclareMiningJobSuccess {
+                request_id: message.request_id,
+                new_mining_job_token: signed_token(
+                    message.mining_job_token.inner_as_ref(),
+                    &self.private_key,
+                )?,
+            };
+            let message_enum_success = JobDeclaration::DeclareMiningJobSuccess(message_success);
+            Ok(SendTo::Respond(message_enum_success))
         } else {
-            let message_error = DeclareMiningJobError {
+            self.record(JobRecord::declared(
+                self.user_identifier(&message),
+                &message,
+                JobVerdict::MissingTransactions,
+            ));
+            let message_provide_missing_transactions = ProvideMissingTransactions {
                 request_id: message.request_id,
-                error_code: Vec::new().try_into().unwrap(),
-                error_details: Vec::new().try_into().unwrap(),
+                unknown_tx_position_list: missing_txs.into(),
             };
-            let message_enum_error = JobDeclaration::DeclareMiningJobError(message_error);
-            Ok(SendTo::Respond(message_enum_error))
+            let message_enum_provide_missing_transactions =
+                JobDeclaration::ProvideMissingTransactions(message_provide_missing_transactions);
+            Ok(SendTo::Respond(message_enum_provide_missing_transactions))
         }
     }
 
@@ -161,6 +227,14 @@ impl ParseClientJobDeclarationMessages for JobDeclaratorDownstream {
         &mut self,
         message: ProvideMissingTransactionsSuccess,
     ) -> Result<SendTo, Error> {
+        let (user_identifier, request_id, mining_job_token) = match &self.declared_mining_job.0 {
+            Some(declared_job) => (
+                self.user_identifier(declared_job),
+                declared_job.request_id,
+                declared_job.mining_job_token.to_vec(),
+            ),
+            None => (None, 0, Vec::new()),
+        };
         let (declared_mining_job, ref mut transactions_with_state, missing_indexes) =
             &mut self.declared_mining_job;
         let mut unknown_transactions: Vec<Transaction> = vec![];
@@ -190,26 +264,40 @@ impl ParseClientJobDeclarationMessages for JobDeclaratorDownstream {
                         transactions_with_state[index] =
                             TransactionState::PresentInMempool(transaction.txid());
                     }
+                    if let Err(violation) = self.check_policy(&unknown_transactions)? {
+                        self.record(JobRecord::verdict(
+                            user_identifier,
+                            request_id,
+                            JobVerdict::Rejected(violation.to_string()),
+                        ));
+                        return Ok(policy_violation_error(message.request_id, violation));
+                    }
                     self.add_txs_to_mempool
                         .add_txs_to_mempool_inner
                         .unknown_transactions
                         .append(&mut unknown_transactions);
                     // if there still a missing transaction return an error
-                    for tx_with_state in transactions_with_state {
-                        match tx_with_state {
-                            TransactionState::PresentInMempool(_) => continue,
-                            TransactionState::Missing => return Err(Error::JDSMissingTransactions),
-                        }
+                    let still_missing = self
+                        .declared_mining_job
+                        .1
+                        .iter()
+                        .any(|tx| matches!(tx, TransactionState::Missing));
+                    if still_missing {
+                        self.record(JobRecord::verdict(
+                            user_identifier,
+                            request_id,
+                            JobVerdict::Rejected("missing-transactions".to_string()),
+                        ));
+                        return Err(Error::JDSMissingTransactions);
                     }
-                    // TODO check it
-                    let tx_hash_list_hash = self.tx_hash_list_hash.clone().unwrap().into_static();
+                    self.record(JobRecord::verdict(
+                        user_identifier,
+                        request_id,
+                        JobVerdict::Accepted,
+                    ));
                     let message_success = DeclareMiningJobSuccess {
                         request_id: message.request_id,
-                        new_mining_job_token: signed_token(
-                            tx_hash_list_hash,
-                            &self.public_key.clone(),
-                            &self.private_key.clone(),
-                        ),
+                        new_mining_job_token: signed_token(&mining_job_token, &self.private_key)?,
                     };
                     let message_enum_success =
                         JobDeclaration::DeclareMiningJobSuccess(message_success);
@@ -257,19 +345,13 @@ fn clear_declared_mining_job(
                     let txid = transaction_with_hash.id;
                     match mempool_.mempool.get_mut(&txid) {
                         Some(Some((_transaction, counter))) => {
-                            if *counter > 1 {
-                                *counter -= 1;
-                                debug!(
-                                    "Fat transaction {:?} counter decremented; job id {:?} dropped",
-                                    txid, old_mining_job.request_id
-                                );
-                            } else {
-                                mempool_.mempool.remove(&txid);
-                                debug!(
-                                    "Fat transaction {:?} with job id {:?} removed from mempool",
-                                    txid, old_mining_job.request_id
-                                );
-                            }
+                            // the data is kept while the transaction is in the node mempool, see
+                            // `JDsMempool::on_new_block`
+                            *counter = counter.saturating_sub(1);
+                            debug!(
+                                "Fat transaction {:?} counter decremented; job id {:?} dropped",
+                                txid, old_mining_job.request_id
+                            );
                         }
                         Some(None) => debug!(
                             "Thin transaction {:?} with job id {:?} removed from mempool",
@@ -1,28 +1,33 @@
 pub mod message_handler;
-use super::{error::JdsError, mempool::JDsMempool, status, Configuration, EitherFrame, StdFrame};
+pub mod policy;
+use super::{
+    error::JdsError, job_store::JobRecord, mempool::JDsMempool, status, Configuration, EitherFrame,
+    StdFrame,
+};
 use async_channel::{Receiver, Sender};
-use binary_sv2::{B0255, U256};
+use binary_sv2::B0255;
 use codec_sv2::{HandshakeRole, Responder};
 use core::panic;
 use error_handling::handle_result;
-use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey, SignatureService};
+use key_utils::Secp256k1SecretKey;
 use network_helpers_sv2::noise_connection_tokio::Connection;
-use nohash_hasher::BuildNoHashHasher;
+use policy::JobPolicy;
 use roles_logic_sv2::{
     common_messages_sv2::{
         Protocol, SetupConnection, SetupConnectionError, SetupConnectionSuccess,
     },
     handlers::job_declaration::{ParseClientJobDeclarationMessages, SendTo},
-    job_declaration_sv2::{DeclareMiningJob, SubmitSolutionJd},
+    job_declaration_sv2::{DeclareMiningJob, ProvideMissingTransactionsSuccess, SubmitSolutionJd},
     parsers::{JobDeclaration, PoolMessages as JdsMessages},
-    utils::{Id, Mutex},
+    token_manager::{DeclaredJobToken, TokenManager},
+    utils::Mutex,
 };
-use std::{collections::HashMap, convert::TryInto, sync::Arc};
+use std::{convert::TryInto, io::Cursor, sync::Arc};
 use tokio::{net::TcpListener, time::Duration};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, info_span, Instrument};
 
 use stratum_common::bitcoin::{
-    consensus::{encode::serialize, Encodable},
+    consensus::{encode::serialize, Decodable, Encodable},
     Block, Transaction, Txid,
 };
 
@@ -54,9 +59,9 @@ pub struct JobDeclaratorDownstream {
     #[allow(dead_code)]
     // TODO: use coinbase output
     coinbase_output: Vec<u8>,
-    token_to_job_map: HashMap<u32, Option<u8>, BuildNoHashHasher<u32>>,
-    tokens: Id,
-    public_key: Secp256k1PublicKey,
+    // shared by all the connections, so that the rate limit of a user identifier can not be
+    // bypassed by reconnecting
+    token_manager: Arc<Mutex<TokenManager>>,
     private_key: Secp256k1SecretKey,
     mempool: Arc<Mutex<JDsMempool>>,
     // Vec<u16> is the vector of missing transactions
@@ -65,11 +70,13 @@ pub struct JobDeclaratorDownstream {
         Vec<TransactionState>,
         Vec<u16>,
     ),
-    tx_hash_list_hash: Option<U256<'static>>,
     add_txs_to_mempool: AddTrasactionsToMempool,
+    job_store: Option<Sender<JobRecord>>,
+    job_policy: Arc<dyn JobPolicy>,
 }
 
 impl JobDeclaratorDownstream {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         async_mining_allowed: bool,
         receiver: Receiver<EitherFrame>,
@@ -77,11 +84,11 @@ impl JobDeclaratorDownstream {
         config: &Configuration,
         mempool: Arc<Mutex<JDsMempool>>,
         sender_add_txs_to_mempool: Sender<AddTrasactionsToMempoolInner>,
+        job_store: Option<Sender<JobRecord>>,
+        job_policy: Arc<dyn JobPolicy>,
+        token_manager: Arc<Mutex<TokenManager>>,
     ) -> Self {
         let mut coinbase_output = vec![];
-        // TODO: use next variables
-        let token_to_job_map = HashMap::with_hasher(BuildNoHashHasher::default());
-        let tokens = Id::new();
         let add_txs_to_mempool_inner = AddTrasactionsToMempoolInner {
             known_transactions: vec![],
             unknown_transactions: vec![],
@@ -95,20 +102,41 @@ impl JobDeclaratorDownstream {
             receiver,
             sender,
             coinbase_output,
-            token_to_job_map,
-            tokens,
-            public_key: config.authority_public_key,
+            token_manager,
             private_key: config.authority_secret_key,
             mempool,
             declared_mining_job: (None, Vec::new(), Vec::new()),
-            tx_hash_list_hash: None,
             add_txs_to_mempool: AddTrasactionsToMempool {
                 add_txs_to_mempool_inner,
                 sender_add_txs_to_mempool,
             },
+            job_store,
+            job_policy,
+        }
+    }
+
+    /// Sends `record` to the declared jobs store, if any. Failures are only logged so that
+    /// auditing never interferes with the job declaration.
+    fn record(&self, record: JobRecord) {
+        if let Some(job_store) = &self.job_store {
+            if let Err(e) = job_store.try_send(record) {
+                error!("Failed to record declared job: {}", e);
+            }
         }
     }
 
+    /// User identifier that allocated the token of `job`
+    fn user_identifier(&self, job: &DeclareMiningJob) -> Option<String> {
+        TokenManager::token_from_bytes(job.mining_job_token.inner_as_ref())
+            .ok()
+            .and_then(|token| {
+                self.token_manager
+                    .safe_lock(|manager| manager.user_identifier(token).map(ToString::to_string))
+                    .ok()
+                    .flatten()
+            })
+    }
+
     fn get_block_hex(
         self_mutex: Arc<Mutex<Self>>,
         message: SubmitSolutionJd,
@@ -118,10 +146,17 @@ impl JobDeclaratorDownstream {
             .safe_lock(|x| x.declared_mining_job.clone())
             .map_err(|e| Box::new(JdsError::PoisonLock(e.to_string())))?;
         let last_declare = last_declare_.ok_or(Box::new(JdsError::NoLastDeclaredJob))?;
-        let transactions_list = Self::collect_txs_in_job(self_mutex)?;
+        let request_id = last_declare.request_id;
+        let user_identifier = self_mutex
+            .safe_lock(|x| x.user_identifier(&last_declare))
+            .map_err(|e| Box::new(JdsError::PoisonLock(e.to_string())))?;
+        let transactions_list = Self::collect_txs_in_job(self_mutex.clone())?;
         let block: Block =
             roles_logic_sv2::utils::BlockCreator::new(last_declare, transactions_list, message)
                 .into();
+        self_mutex
+            .safe_lock(|x| x.record(JobRecord::block_found(user_identifier, request_id, &block)))
+            .map_err(|e| Box::new(JdsError::PoisonLock(e.to_string())))?;
         Ok(hex::encode(serialize(&block)))
     }
 
@@ -189,6 +224,31 @@ impl JobDeclaratorDownstream {
         known_transactions
     }
 
+    /// Retrieves from the node the fees of the transactions provided with
+    /// `ProvideMissingTransactionsSuccess`, so that the handler can check them against the policy
+    async fn fetch_provided_fees(self_mutex: Arc<Mutex<Self>>, payload: &mut [u8]) {
+        let transactions: Vec<Transaction> =
+            match binary_sv2::from_bytes::<ProvideMissingTransactionsSuccess>(payload) {
+                Ok(message) => message
+                    .transaction_list
+                    .inner_as_ref()
+                    .iter()
+                    .filter_map(|tx| {
+                        Transaction::consensus_decode_from_finite_reader(&mut Cursor::new(tx)).ok()
+                    })
+                    .collect(),
+                // the error is returned by the handler
+                Err(_) => return,
+            };
+        let mempool = self_mutex.safe_lock(|a| a.mempool.clone()).unwrap();
+        if let Err(e) = JDsMempool::fetch_fees(mempool, &transactions).await {
+            debug!(
+                "Failed to retrieve the fees of the provided transactions: {:?}",
+                e
+            );
+        }
+    }
+
     pub async fn send(
         self_mutex: Arc<Mutex<Self>>,
         message: roles_logic_sv2::parsers::JobDeclaration<'static>,
@@ -202,9 +262,12 @@ impl JobDeclaratorDownstream {
         self_mutex: Arc<Mutex<Self>>,
         tx_status: status::Sender,
         new_block_sender: Sender<String>,
+        diagnostics: admin_sv2::Connection,
     ) {
         let recv = self_mutex.safe_lock(|s| s.receiver.clone()).unwrap();
-        tokio::spawn(async move {
+        let receive = async move {
+            // unregistered from the admin interface when the connection is closed
+            let _diagnostics = diagnostics;
             loop {
                 match recv.recv().await {
                     Ok(message) => {
@@ -215,6 +278,11 @@ impl JobDeclaratorDownstream {
                         let header = handle_result!(tx_status, header);
                         let message_type = header.msg_type();
                         let payload = frame.payload();
+                        if message_type
+                            == const_sv2::MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTIONS_SUCCESS
+                        {
+                            Self::fetch_provided_fees(self_mutex.clone(), payload).await;
+                        }
                         let next_message_to_send =
                             ParseClientJobDeclarationMessages::handle_message_job_declaration(
                                 self_mutex.clone(),
@@ -399,21 +467,21 @@ impl JobDeclaratorDownstream {
                     }
                 }
             }
-        });
+        };
+        // in the span of the connection, see `accept_incoming_connection`
+        tokio::spawn(receive.in_current_span());
     }
 }
 
+/// Token sent in `DeclareMiningJobSuccess.new_mining_job_token` for the job declared with
+/// `mining_job_token`. It is signed with the authority key of the JDS, the pool checks it before
+/// accepting the `SetCustomMiningJob` of the job, see [`DeclaredJobToken`].
 pub fn signed_token(
-    tx_hash_list_hash: U256,
-    _pub_key: &Secp256k1PublicKey,
+    mining_job_token: &[u8],
     prv_key: &Secp256k1SecretKey,
-) -> B0255<'static> {
-    let secp = SignatureService::default();
-
-    let signature = secp.sign(tx_hash_list_hash.to_vec(), prv_key.0);
-
-    // Sign message
-    signature.as_ref().to_vec().try_into().unwrap()
+) -> Result<B0255<'static>, roles_logic_sv2::Error> {
+    let token = TokenManager::token_from_bytes(mining_job_token)?;
+    Ok(DeclaredJobToken::new(token).sign(&prv_key.0))
 }
 
 fn _get_random_token() -> B0255<'static> {
@@ -430,8 +498,11 @@ impl JobDeclarator {
         mempool: Arc<Mutex<JDsMempool>>,
         new_block_sender: Sender<String>,
         sender_add_txs_to_mempool: Sender<AddTrasactionsToMempoolInner>,
+        job_store: Option<Sender<JobRecord>>,
+        job_policy: Arc<dyn JobPolicy>,
     ) {
         let self_ = Arc::new(Mutex::new(Self {}));
+        let token_manager = Arc::new(Mutex::new(TokenManager::default()));
         info!("JD INITIALIZED");
         Self::accept_incoming_connection(
             self_,
@@ -440,9 +511,13 @@ impl JobDeclarator {
             mempool,
             new_block_sender,
             sender_add_txs_to_mempool,
+            job_store,
+            job_policy,
+            token_manager,
         )
         .await;
     }
+    #[allow(clippy::too_many_arguments)]
     async fn accept_incoming_connection(
         _self_: Arc<Mutex<JobDeclarator>>,
         config: Configuration,
@@ -450,6 +525,9 @@ impl JobDeclarator {
         mempool: Arc<Mutex<JDsMempool>>,
         new_block_sender: Sender<String>,
         sender_add_txs_to_mempool: Sender<AddTrasactionsToMempoolInner>,
+        job_store: Option<Sender<JobRecord>>,
+        job_policy: Arc<dyn JobPolicy>,
+        token_manager: Arc<Mutex<TokenManager>>,
     ) {
         let listener = TcpListener::bind(&config.listen_jd_address).await.unwrap();
 
@@ -462,9 +540,15 @@ impl JobDeclarator {
             .unwrap();
 
             let addr = stream.peer_addr();
+            let span = match &addr {
+                Ok(addr) => info_span!("jd_downstream", addr = %addr),
+                Err(_) => info_span!("jd_downstream"),
+            };
 
             if let Ok((receiver, sender, _, _)) =
-                Connection::new(stream, HandshakeRole::Responder(responder)).await
+                Connection::new(stream, HandshakeRole::Responder(responder))
+                    .instrument(span.clone())
+                    .await
             {
                 match receiver.recv().await {
                     Ok(EitherFrame::Sv2(mut sv2_message)) => {
@@ -503,14 +587,24 @@ impl JobDeclarator {
                                         &config,
                                         mempool.clone(),
                                         sender_add_txs_to_mempool.clone(), /* each downstream has its own sender (multi producer single consumer) */
+                                        job_store.clone(),
+                                        job_policy.clone(),
+                                        token_manager.clone(),
                                     ),
                                 ));
 
-                                JobDeclaratorDownstream::start(
-                                    jddownstream,
-                                    status_tx.clone(),
-                                    new_block_sender.clone(),
+                                let diagnostics = admin_sv2::diagnostics().open_connection(
+                                    addr.as_ref().map(ToString::to_string).unwrap_or_default(),
+                                    "jd downstream",
                                 );
+                                span.in_scope(|| {
+                                    JobDeclaratorDownstream::start(
+                                        jddownstream,
+                                        status_tx.clone(),
+                                        new_block_sender.clone(),
+                                        diagnostics,
+                                    )
+                                });
                             } else {
                                 let error_message = SetupConnectionError {
                                     flags: flag,
@@ -547,3 +641,91 @@ impl JobDeclarator {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ext_config::{Config, File, FileFormat};
+    use policy::DefaultJobPolicy;
+    use roles_logic_sv2::{
+        job_declaration_sv2::AllocateMiningJobToken, token_manager::DEFAULT_MAX_TOKENS_PER_WINDOW,
+        Error,
+    };
+
+    fn downstream(token_manager: Arc<Mutex<TokenManager>>) -> JobDeclaratorDownstream {
+        let config: Configuration = Config::builder()
+            .add_source(File::new(
+                "config-examples/jds-config-local-example.toml",
+                FileFormat::Toml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        let (sender, receiver) = async_channel::unbounded();
+        let (_, new_block_receiver) = async_channel::unbounded();
+        let mempool = Arc::new(Mutex::new(JDsMempool::new(
+            config.core_rpc_url.clone(),
+            config.core_rpc_user.clone(),
+            config.core_rpc_pass.clone(),
+            new_block_receiver,
+        )));
+        let (sender_add_txs_to_mempool, _) = async_channel::unbounded();
+        JobDeclaratorDownstream::new(
+            false,
+            receiver,
+            sender,
+            &config,
+            mempool,
+            sender_add_txs_to_mempool,
+            None,
+            Arc::new(DefaultJobPolicy::default()),
+            token_manager,
+        )
+    }
+
+    fn allocate(downstream: &mut JobDeclaratorDownstream, request_id: u32) -> Result<u32, Error> {
+        let message = AllocateMiningJobToken {
+            user_identifier: "miner".to_string().try_into().unwrap(),
+            request_id,
+        };
+        match downstream.handle_allocate_mining_job_token(message)? {
+            SendTo::Respond(JobDeclaration::AllocateMiningJobTokenSuccess(success)) => {
+                TokenManager::token_from_bytes(success.mining_job_token.inner_as_ref())
+            }
+            _ => panic!("unexpected response"),
+        }
+    }
+
+    #[test]
+    fn test_token_manager_shared_between_connections() {
+        let token_manager = Arc::new(Mutex::new(TokenManager::default()));
+        let mut first = downstream(token_manager.clone());
+        let mut second = downstream(token_manager);
+
+        let token = allocate(&mut first, 0).unwrap();
+        // a token allocated on a connection is known to the others
+        assert_eq!(
+            second.user_identifier(&DeclareMiningJob {
+                request_id: 0,
+                mining_job_token: TokenManager::token_to_bytes(token),
+                version: 0,
+                coinbase_prefix: vec![].try_into().unwrap(),
+                coinbase_suffix: vec![].try_into().unwrap(),
+                tx_short_hash_nonce: 0,
+                tx_short_hash_list: vec![].into(),
+                tx_hash_list_hash: vec![0; 32].try_into().unwrap(),
+                excess_data: vec![].try_into().unwrap(),
+            }),
+            Some("miner".to_string())
+        );
+        for request_id in 1..DEFAULT_MAX_TOKENS_PER_WINDOW as u32 {
+            allocate(&mut first, request_id).unwrap();
+        }
+        // reconnecting does not reset the rate limit of the user identifier
+        assert!(matches!(
+            allocate(&mut second, 0),
+            Err(Error::JobTokenRateLimited(user_identifier)) if user_identifier == "miner"
+        ));
+    }
+}
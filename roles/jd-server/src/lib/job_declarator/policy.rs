@@ -0,0 +1,369 @@
+//! Policy applied to the transactions of the declared jobs.
+//!
+//! A [`JobPolicy`] is evaluated on every transaction of a `DeclareMiningJob` whose data is known
+//! to the JDS, that is the transactions that are in the JDS mempool with their data and the ones
+//! provided with `ProvideMissingTransactionsSuccess`. The job is rejected with a
+//! `DeclareMiningJobError` as soon as a transaction violates the policy.
+//!
+//! The fees of the transactions are retrieved from the node, see [`JDsMempool::fetch_fees`].
+use crate::mempool::JDsMempool;
+use serde::Deserialize;
+use std::fmt::{self, Debug, Display};
+use stratum_common::bitcoin::{
+    blockdata::{
+        opcodes::all::{OP_CHECKMULTISIG, OP_CHECKMULTISIGVERIFY, OP_CHECKSIG, OP_CHECKSIGVERIFY},
+        script::Instruction,
+    },
+    Script, Transaction, Txid,
+};
+
+/// Error code sent in `DeclareMiningJobError` when a job violates the policy
+pub const POLICY_VIOLATION_ERROR_CODE: &str = "policy-violation";
+
+/// Max weight of a standard transaction
+pub const DEFAULT_MAX_TX_WEIGHT: usize = 400_000;
+/// Max legacy sigops of a standard transaction (`MAX_STANDARD_TX_SIGOPS_COST` / 4)
+pub const DEFAULT_MAX_TX_SIGOPS: usize = 4_000;
+/// Min feerate in sat/vB
+pub const DEFAULT_MIN_FEERATE: f64 = 1.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyViolation {
+    FeerateTooLow { txid: Txid, feerate: f64 },
+    TooLarge { txid: Txid, weight: usize },
+    TooManySigops { txid: Txid, sigops: usize },
+    BlockedOutput { txid: Txid, script: Script },
+    Custom { txid: Txid, reason: String },
+}
+
+impl Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use PolicyViolation::*;
+        match self {
+            FeerateTooLow { txid, feerate } => {
+                write!(
+                    f,
+                    "Transaction {} feerate too low: {} sat/vB",
+                    txid, feerate
+                )
+            }
+            TooLarge { txid, weight } => {
+                write!(f, "Transaction {} too large: {} WU", txid, weight)
+            }
+            TooManySigops { txid, sigops } => {
+                write!(f, "Transaction {} has too many sigops: {}", txid, sigops)
+            }
+            BlockedOutput { txid, script } => {
+                write!(
+                    f,
+                    "Transaction {} pays to a blocked script: {}",
+                    txid, script
+                )
+            }
+            Custom { txid, reason } => write!(f, "Transaction {}: {}", txid, reason),
+        }
+    }
+}
+
+/// Policy applied to the transactions of the declared jobs
+pub trait JobPolicy: Send + Sync + Debug {
+    /// `fee` is `None` when it could not be retrieved from the node, e.g. because an output spent
+    /// by `tx` is unknown or the node is not reachable
+    fn check_transaction(&self, tx: &Transaction, fee: Option<u64>) -> Result<(), PolicyViolation>;
+}
+
+/// Filters of the default policy as set in the config file
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct JobPolicyConfig {
+    /// Min feerate in sat/vB, the fee is retrieved from the node (`getmempoolentry`, or
+    /// `gettxout` for the spent outputs of the transactions that are not in the node mempool)
+    pub min_feerate: Option<f64>,
+    pub max_tx_weight: Option<usize>,
+    pub max_tx_sigops: Option<usize>,
+    /// Hex of the output scripts that can not be paid by the declared transactions
+    #[serde(default)]
+    pub blocked_output_scripts: Vec<String>,
+}
+
+/// Policy used when no other policy is given to the JDS: rejects transactions with a low feerate,
+/// non standard size or sigops, or paying to blocked scripts
+#[derive(Debug, Clone)]
+pub struct DefaultJobPolicy {
+    min_feerate: f64,
+    max_tx_weight: usize,
+    max_tx_sigops: usize,
+    blocked_output_scripts: Vec<Script>,
+}
+
+impl Default for DefaultJobPolicy {
+    fn default() -> Self {
+        Self {
+            min_feerate: DEFAULT_MIN_FEERATE,
+            max_tx_weight: DEFAULT_MAX_TX_WEIGHT,
+            max_tx_sigops: DEFAULT_MAX_TX_SIGOPS,
+            blocked_output_scripts: Vec::new(),
+        }
+    }
+}
+
+impl DefaultJobPolicy {
+    pub fn from_config(config: &JobPolicyConfig) -> Result<Self, String> {
+        let blocked_output_scripts = config
+            .blocked_output_scripts
+            .iter()
+            .map(|script| {
+                hex::decode(script)
+                    .map(Script::from)
+                    .map_err(|e| format!("Invalid blocked output script {}: {}", script, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            min_feerate: config.min_feerate.unwrap_or(DEFAULT_MIN_FEERATE),
+            max_tx_weight: config.max_tx_weight.unwrap_or(DEFAULT_MAX_TX_WEIGHT),
+            max_tx_sigops: config.max_tx_sigops.unwrap_or(DEFAULT_MAX_TX_SIGOPS),
+            blocked_output_scripts,
+        })
+    }
+}
+
+impl JobPolicy for DefaultJobPolicy {
+    fn check_transaction(&self, tx: &Transaction, fee: Option<u64>) -> Result<(), PolicyViolation> {
+        let txid = tx.txid();
+        let weight = tx.weight();
+        if weight > self.max_tx_weight {
+            return Err(PolicyViolation::TooLarge { txid, weight });
+        }
+        let sigops = legacy_sigops(tx);
+        if sigops > self.max_tx_sigops {
+            return Err(PolicyViolation::TooManySigops { txid, sigops });
+        }
+        if let Some(output) = tx
+            .output
+            .iter()
+            .find(|output| self.blocked_output_scripts.contains(&output.script_pubkey))
+        {
+            return Err(PolicyViolation::BlockedOutput {
+                txid,
+                script: output.script_pubkey.clone(),
+            });
+        }
+        if let Some(fee) = fee {
+            let feerate = fee as f64 / tx.vsize() as f64;
+            if feerate < self.min_feerate {
+                return Err(PolicyViolation::FeerateTooLow { txid, feerate });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks `transactions` against `policy`, the fee of each transaction is taken from `mempool`
+pub fn check_transactions<'a, I>(
+    policy: &dyn JobPolicy,
+    transactions: I,
+    mempool: &JDsMempool,
+) -> Result<(), PolicyViolation>
+where
+    I: IntoIterator<Item = &'a Transaction>,
+{
+    for tx in transactions {
+        policy.check_transaction(tx, mempool.fee(tx))?;
+    }
+    Ok(())
+}
+
+// Sigops in the scriptSigs and scriptPubKeys, multisigs count as 20
+fn legacy_sigops(tx: &Transaction) -> usize {
+    tx.input
+        .iter()
+        .map(|input| script_sigops(&input.script_sig))
+        .chain(
+            tx.output
+                .iter()
+                .map(|output| script_sigops(&output.script_pubkey)),
+        )
+        .sum()
+}
+
+fn script_sigops(script: &Script) -> usize {
+    script
+        .instructions()
+        .map(|instruction| match instruction {
+            Ok(Instruction::Op(op)) if op == OP_CHECKSIG || op == OP_CHECKSIGVERIFY => 1,
+            Ok(Instruction::Op(op)) if op == OP_CHECKMULTISIG || op == OP_CHECKMULTISIGVERIFY => 20,
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use roles_logic_sv2::utils::Mutex;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use stratum_common::bitcoin::{
+        blockdata::script::Builder, OutPoint, PackedLockTime, Sequence, TxIn, TxOut, Witness,
+    };
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    fn transaction(script_pubkey: Script, value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: PackedLockTime(0),
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Script::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_default_job_policy() {
+        let blocked = Builder::new().push_opcode(OP_CHECKSIG).into_script();
+        let config = JobPolicyConfig {
+            min_feerate: Some(2.0),
+            max_tx_weight: None,
+            max_tx_sigops: Some(20),
+            blocked_output_scripts: vec![hex::encode(blocked.as_bytes())],
+        };
+        let policy = DefaultJobPolicy::from_config(&config).unwrap();
+
+        let tx = transaction(Script::new(), 1000);
+        assert!(policy.check_transaction(&tx, None).is_ok());
+        let vsize = tx.vsize() as u64;
+        assert!(policy.check_transaction(&tx, Some(2 * vsize)).is_ok());
+        assert!(matches!(
+            policy.check_transaction(&tx, Some(vsize)),
+            Err(PolicyViolation::FeerateTooLow { .. })
+        ));
+
+        let tx = transaction(blocked.clone(), 1000);
+        assert_eq!(
+            policy.check_transaction(&tx, None),
+            Err(PolicyViolation::BlockedOutput {
+                txid: tx.txid(),
+                script: blocked
+            })
+        );
+
+        let multisig = Builder::new()
+            .push_opcode(OP_CHECKMULTISIG)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let tx = transaction(multisig, 1000);
+        assert_eq!(
+            policy.check_transaction(&tx, None),
+            Err(PolicyViolation::TooManySigops {
+                txid: tx.txid(),
+                sigops: 21
+            })
+        );
+
+        assert!(DefaultJobPolicy::from_config(&JobPolicyConfig {
+            blocked_output_scripts: vec!["zz".to_string()],
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    // Plays the Bitcoin Core side of the RPC connections, `respond` returns the HTTP status and
+    // the response to the JSON-RPC method of each request
+    async fn node(listener: TcpListener, respond: fn(&str) -> (u16, Value)) {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                loop {
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                        let line = line.trim_end();
+                        if line.is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':') {
+                            if name.eq_ignore_ascii_case("content-length") {
+                                content_length = value.trim().parse().unwrap();
+                            }
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    stream.read_exact(&mut body).await.unwrap();
+                    let request: Value = serde_json::from_slice(&body).unwrap();
+                    let (status, response) = respond(request["method"].as_str().unwrap());
+                    let response = response.to_string();
+                    let head = format!(
+                        "HTTP/1.1 {} OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+                        status,
+                        response.len()
+                    );
+                    let stream = stream.get_mut();
+                    stream.write_all(head.as_bytes()).await.unwrap();
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                }
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_min_feerate_with_confirmed_parent() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(node(listener, |method| match method {
+            // the parent is confirmed, the spent output is worth 10_000 sat
+            "gettxout" => (
+                200,
+                json!({"result": {"value": 0.0001}, "error": null, "id": 1}),
+            ),
+            _ => (
+                500,
+                json!({
+                    "result": null,
+                    "error": {"code": -5, "message": "Transaction not in mempool"},
+                    "id": 1
+                }),
+            ),
+        }));
+        let (_, new_block_receiver) = async_channel::unbounded();
+        let mempool = Arc::new(Mutex::new(JDsMempool::new(
+            url,
+            "user".to_string(),
+            "pass".to_string(),
+            new_block_receiver,
+        )));
+
+        let low_fee = transaction(Script::new(), 9_950);
+        let high_fee = transaction(Script::new(), 5_000);
+        // unknown until retrieved from the node
+        assert_eq!(mempool.safe_lock(|m| m.fee(&low_fee)).unwrap(), None);
+        JDsMempool::fetch_fees(mempool.clone(), &[low_fee.clone(), high_fee.clone()])
+            .await
+            .unwrap();
+
+        let policy = DefaultJobPolicy::default();
+        mempool
+            .safe_lock(|mempool| {
+                assert_eq!(mempool.fee(&low_fee), Some(50));
+                assert_eq!(mempool.fee(&high_fee), Some(5_000));
+                assert!(matches!(
+                    check_transactions(&policy, [&low_fee], mempool),
+                    Err(PolicyViolation::FeerateTooLow { .. })
+                ));
+                assert!(check_transactions(&policy, [&high_fee], mempool).is_ok());
+            })
+            .unwrap();
+    }
+}
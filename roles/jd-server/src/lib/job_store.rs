@@ -0,0 +1,321 @@
+//! Append-only storage of the declared jobs, used by pools to audit which templates miners
+//! actually declared.
+//!
+//! Every `DeclareMiningJob` is recorded with its transaction short ids and the verdict of the JDS,
+//! followed by the found blocks. Records are stored one per line as JSON in a new file every
+//! `ROTATION_INTERVAL`, the files whose records are all older than the configured retention are
+//! removed. The records are written by a dedicated thread, see [`DeclaredJobStore::spawn`].
+use async_channel::Sender;
+use roles_logic_sv2::job_declaration_sv2::DeclareMiningJob;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use stratum_common::bitcoin::Block;
+use tracing::error;
+
+/// Time covered by each file of the store, the expired files are removed when a new one is
+/// created
+const ROTATION_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DeclaredJobsStoreConfig {
+    /// The records are stored in the files `<path>.<unix time of the first record>`
+    pub path: String,
+    /// Records older than this are removed
+    #[serde(deserialize_with = "super::duration_from_toml")]
+    pub retention: Duration,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "verdict", content = "reason", rename_all = "snake_case")]
+pub enum JobVerdict {
+    /// `DeclareMiningJobSuccess` has been sent
+    Accepted,
+    /// Some transactions are unknown, `ProvideMissingTransactions` has been sent
+    MissingTransactions,
+    /// `DeclareMiningJobError` has been sent
+    Rejected(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JobRecord {
+    Declared {
+        timestamp: u64,
+        user_identifier: Option<String>,
+        request_id: u32,
+        mining_job_token: String,
+        version: u32,
+        coinbase_prefix: String,
+        coinbase_suffix: String,
+        tx_short_hash_nonce: u64,
+        tx_short_hash_list: Vec<String>,
+        #[serde(flatten)]
+        verdict: JobVerdict,
+    },
+    /// Verdict given after the missing transactions have been provided
+    Verdict {
+        timestamp: u64,
+        user_identifier: Option<String>,
+        request_id: u32,
+        #[serde(flatten)]
+        verdict: JobVerdict,
+    },
+    BlockFound {
+        timestamp: u64,
+        user_identifier: Option<String>,
+        request_id: u32,
+        block_hash: String,
+    },
+}
+
+impl JobRecord {
+    pub fn declared(
+        user_identifier: Option<String>,
+        job: &DeclareMiningJob,
+        verdict: JobVerdict,
+    ) -> Self {
+        JobRecord::Declared {
+            timestamp: now(),
+            user_identifier,
+            request_id: job.request_id,
+            mining_job_token: hex::encode(job.mining_job_token.inner_as_ref()),
+            version: job.version,
+            coinbase_prefix: hex::encode(job.coinbase_prefix.inner_as_ref()),
+            coinbase_suffix: hex::encode(job.coinbase_suffix.inner_as_ref()),
+            tx_short_hash_nonce: job.tx_short_hash_nonce,
+            tx_short_hash_list: job
+                .tx_short_hash_list
+                .inner_as_ref()
+                .iter()
+                .map(hex::encode)
+                .collect(),
+            verdict,
+        }
+    }
+
+    pub fn verdict(user_identifier: Option<String>, request_id: u32, verdict: JobVerdict) -> Self {
+        JobRecord::Verdict {
+            timestamp: now(),
+            user_identifier,
+            request_id,
+            verdict,
+        }
+    }
+
+    pub fn block_found(user_identifier: Option<String>, request_id: u32, block: &Block) -> Self {
+        JobRecord::BlockFound {
+            timestamp: now(),
+            user_identifier,
+            request_id,
+            block_hash: block.block_hash().to_string(),
+        }
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            JobRecord::Declared { timestamp, .. }
+            | JobRecord::Verdict { timestamp, .. }
+            | JobRecord::BlockFound { timestamp, .. } => *timestamp,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DeclaredJobStore {
+    path: PathBuf,
+    file: File,
+    // start of the time covered by `file`
+    segment: u64,
+    retention: Duration,
+}
+
+impl DeclaredJobStore {
+    /// Opens (or creates) the store at `config.path` and removes the expired files
+    pub fn open(config: &DeclaredJobsStoreConfig) -> io::Result<Self> {
+        let path = PathBuf::from(&config.path);
+        let segment = segment_start(now());
+        let file = open_segment(&path, segment)?;
+        let self_ = Self {
+            path,
+            file,
+            segment,
+            retention: config.retention,
+        };
+        self_.prune()?;
+        Ok(self_)
+    }
+
+    /// Writes the records sent on the returned channel in a dedicated thread, so that recording
+    /// a job never blocks the async runtime
+    pub fn spawn(mut self) -> Sender<JobRecord> {
+        let (sender, receiver) = async_channel::unbounded();
+        std::thread::spawn(move || {
+            while let Ok(record) = receiver.recv_blocking() {
+                if let Err(e) = self.record(&record) {
+                    error!("Failed to record declared job: {}", e);
+                }
+            }
+        });
+        sender
+    }
+
+    pub fn record(&mut self, record: &JobRecord) -> io::Result<()> {
+        let segment = segment_start(record.timestamp());
+        if segment > self.segment {
+            self.file = open_segment(&self.path, segment)?;
+            self.segment = segment;
+            self.prune()?;
+        }
+        let mut line = serde_json::to_string(record)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.flush()
+    }
+
+    /// Reads every record in the store
+    pub fn records(&self) -> io::Result<Vec<JobRecord>> {
+        let mut records = Vec::new();
+        for (_, path) in self.segments()? {
+            let reader = BufReader::new(File::open(path)?);
+            for line in reader.lines() {
+                let line = line?;
+                if line.is_empty() {
+                    continue;
+                }
+                let record = serde_json::from_str(&line)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    /// Removes the files whose records are all older than the retention
+    pub fn prune(&self) -> io::Result<()> {
+        let oldest = now().saturating_sub(self.retention.as_secs());
+        for (segment, path) in self.segments()? {
+            if segment + ROTATION_INTERVAL.as_secs() <= oldest {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Files of the store sorted by the start of the time they cover
+    fn segments(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let prefix = match self.path.file_name() {
+            Some(name) => format!("{}.", name.to_string_lossy()),
+            None => return Ok(Vec::new()),
+        };
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let segment = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(&prefix))
+                .and_then(|start| start.parse::<u64>().ok());
+            if let Some(segment) = segment {
+                segments.push((segment, entry.path()));
+            }
+        }
+        segments.sort();
+        Ok(segments)
+    }
+}
+
+fn segment_start(timestamp: u64) -> u64 {
+    timestamp - timestamp % ROTATION_INTERVAL.as_secs()
+}
+
+fn open_segment(path: &Path, segment: u64) -> io::Result<File> {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", segment));
+    OpenOptions::new().create(true).append(true).open(name)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn declare_mining_job() -> DeclareMiningJob<'static> {
+        DeclareMiningJob {
+            request_id: 3,
+            mining_job_token: vec![1, 0, 0, 0].try_into().unwrap(),
+            version: 0x2000_0000,
+            coinbase_prefix: vec![0xab].try_into().unwrap(),
+            coinbase_suffix: vec![0xcd].try_into().unwrap(),
+            tx_short_hash_nonce: 42,
+            tx_short_hash_list: vec![[1_u8; 6].to_vec().try_into().unwrap()].into(),
+            tx_hash_list_hash: [0; 32].into(),
+            excess_data: vec![].try_into().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_declared_job_store() {
+        let path = std::env::temp_dir().join(format!("jds-store-{}.jsonl", rand::random::<u64>()));
+        let config = DeclaredJobsStoreConfig {
+            path: path.to_str().unwrap().to_string(),
+            retention: Duration::from_secs(3600),
+        };
+        let mut store = DeclaredJobStore::open(&config).unwrap();
+        let declared = JobRecord::declared(
+            Some("user".to_string()),
+            &declare_mining_job(),
+            JobVerdict::MissingTransactions,
+        );
+        let verdict = JobRecord::verdict(
+            Some("user".to_string()),
+            3,
+            JobVerdict::Rejected("invalid-mining-job-token".to_string()),
+        );
+        let old = JobRecord::Verdict {
+            timestamp: 0,
+            user_identifier: None,
+            request_id: 1,
+            verdict: JobVerdict::Accepted,
+        };
+        // written by a previous run of the JDS
+        let mut old_file = open_segment(&path, 0).unwrap();
+        writeln!(old_file, "{}", serde_json::to_string(&old).unwrap()).unwrap();
+        store.record(&declared).unwrap();
+        store.record(&verdict).unwrap();
+        assert_eq!(
+            store.records().unwrap(),
+            vec![old, declared.clone(), verdict.clone()]
+        );
+        match &declared {
+            JobRecord::Declared {
+                tx_short_hash_list, ..
+            } => assert_eq!(tx_short_hash_list, &vec!["010101010101".to_string()]),
+            _ => unreachable!(),
+        }
+
+        // Reopening the store removes the expired files
+        drop(store);
+        let store = DeclaredJobStore::open(&config).unwrap();
+        assert_eq!(store.records().unwrap(), vec![declared, verdict]);
+        for (_, path) in store.segments().unwrap() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -8,6 +8,7 @@ pub enum JdsMempoolError {
     NoClient,
     Rpc(RpcError),
     PoisonLock(String),
+    Zmq(String),
 }
 
 impl From<RpcError> for JdsMempoolError {
@@ -40,5 +41,9 @@ pub fn handle_error(err: &JdsMempoolError) {
             error!("{:?}", err);
             error!("Poison lock error)");
         }
+        JdsMempoolError::Zmq(_) => {
+            error!("{:?}", err);
+            error!("Unable to receive notifications from the node ZMQ endpoint (possible reasons: node started without -zmqpubrawtx/-zmqpubhashblock, down)");
+        }
     }
 }
@@ -1,13 +1,19 @@
 pub mod error;
+pub mod zmq;
 use super::job_declarator::AddTrasactionsToMempoolInner;
 use crate::mempool::error::JdsMempoolError;
 use async_channel::Receiver;
 use bitcoin::blockdata::transaction::Transaction;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use roles_logic_sv2::utils::Mutex;
 use rpc_sv2::{mini_rpc_client, mini_rpc_client::RpcError};
 use std::{convert::TryInto, str::FromStr, sync::Arc};
-use stratum_common::{bitcoin, bitcoin::hash_types::Txid};
+use stratum_common::{
+    bitcoin,
+    bitcoin::{hash_types::Txid, OutPoint},
+};
+use tracing::{debug, info};
+use zmq::{ZmqNotification, ZmqSubscriber};
 
 #[derive(Clone, Debug)]
 pub struct TransactionWithHash {
@@ -18,6 +24,8 @@ pub struct TransactionWithHash {
 #[derive(Clone, Debug)]
 pub struct JDsMempool {
     pub mempool: HashMap<Txid, Option<(Transaction, u32)>>,
+    // fees retrieved from the node with `fetch_fees`
+    fees: HashMap<Txid, u64>,
     auth: mini_rpc_client::Auth,
     url: String,
     new_block_receiver: Receiver<String>,
@@ -53,6 +61,7 @@ impl JDsMempool {
         let empty_mempool: HashMap<Txid, Option<(Transaction, u32)>> = HashMap::new();
         JDsMempool {
             mempool: empty_mempool,
+            fees: HashMap::new(),
             auth,
             url,
             new_block_receiver,
@@ -73,15 +82,24 @@ impl JDsMempool {
             .ok_or(JdsMempoolError::NoClient)?;
         // fill in the mempool the transactions id in the mempool with the full transactions
         // retrieved from the jd client
+        let mut retrieved = Vec::new();
         for txid in txids {
-            if let Some(None) = self_
+            let entry = self_
                 .safe_lock(|a| a.mempool.get(&txid).cloned())
-                .map_err(|e| JdsMempoolError::PoisonLock(e.to_string()))?
-            {
+                .map_err(|e| JdsMempoolError::PoisonLock(e.to_string()))?;
+            if let Some(Some(_)) = entry {
+                // already received with its data, e.g. with the zmq notifications
+                self_.safe_lock(|a| {
+                    if let Some(Some((_, count))) = a.mempool.get_mut(&txid) {
+                        *count += 1;
+                    }
+                })?;
+            } else if let Some(None) = entry {
                 let transaction = client
                     .get_raw_transaction(&txid.to_string(), None)
                     .await
                     .map_err(JdsMempoolError::Rpc)?;
+                retrieved.push(transaction.clone());
                 let _ = self_.safe_lock(|a| {
                     a.mempool
                         .entry(transaction.txid())
@@ -96,6 +114,10 @@ impl JDsMempool {
                 });
             }
         }
+        // the transactions with data are checked against the policy in the next declared jobs
+        if let Err(e) = Self::fetch_fees(self_.clone(), &retrieved).await {
+            debug!("Failed to retrieve the fees of the transactions: {:?}", e);
+        }
 
         // fill in the mempool the transactions given in input
         for transaction in transactions {
@@ -147,26 +169,151 @@ impl JDsMempool {
         }
     }
 
-    pub async fn on_submit(self_: Arc<Mutex<Self>>) -> Result<(), JdsMempoolError> {
-        let new_block_receiver: Receiver<String> =
-            self_.safe_lock(|x| x.new_block_receiver.clone())?;
+    /// Called when a new block is connected to the node tip. Transactions that are not in the
+    /// node mempool anymore have been included in the block (or evicted) so they are removed,
+    /// unless they are still used by declared jobs.
+    pub async fn on_new_block(self_: Arc<Mutex<Self>>) -> Result<(), JdsMempoolError> {
         let client = self_
             .safe_lock(|x| x.get_client())?
             .ok_or(JdsMempoolError::NoClient)?;
 
-        while let Ok(block_hex) = new_block_receiver.recv().await {
-            match mini_rpc_client::MiniRpcClient::submit_block(&client, block_hex).await {
-                Ok(_) => return Ok(()),
-                Err(e) => JdsMempoolError::Rpc(e),
+        let mempool = client.get_raw_mempool().await?;
+        let raw_mempool_txids: HashSet<Txid> = mempool
+            .into_iter()
+            .map(|id| {
+                Txid::from_str(&id)
+                    .map_err(|err| JdsMempoolError::Rpc(RpcError::Deserialization(err.to_string())))
+            })
+            .collect::<Result<_, _>>()?;
+
+        self_.safe_lock(|x| {
+            x.mempool.retain(|txid, tx| match tx {
+                Some((_, count)) if *count > 0 => true,
+                _ => raw_mempool_txids.contains(txid),
+            });
+            for txid in raw_mempool_txids {
+                x.mempool.entry(txid).or_insert(None);
+            }
+            let mempool = &x.mempool;
+            x.fees.retain(|txid, _| mempool.contains_key(txid));
+        })?;
+        Ok(())
+    }
+
+    /// Keeps the mempool in sync with the notifications published by the node on `zmq_address`.
+    /// Returns when the connection with the node is lost.
+    pub async fn listen_zmq(
+        self_: Arc<Mutex<Self>>,
+        zmq_address: &str,
+    ) -> Result<(), JdsMempoolError> {
+        let mut subscriber = ZmqSubscriber::connect(zmq_address).await?;
+        info!("Listening for mempool updates on {}", zmq_address);
+        loop {
+            match subscriber.recv().await? {
+                ZmqNotification::RawTx(transaction) => {
+                    self_.safe_lock(|x| x.on_new_transaction(&transaction))?;
+                    // the fee is retrieved in the background not to delay the notifications
+                    let mempool = self_.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = Self::fetch_fees(mempool, &[transaction]).await {
+                            debug!("Failed to retrieve the fee of the transaction: {:?}", e);
+                        }
+                    });
+                }
+                ZmqNotification::HashBlock(hash) => {
+                    debug!("New block {}, updating the mempool", hash);
+                    Self::on_new_block(self_.clone()).await?;
+                }
+            }
+        }
+    }
+
+    /// Adds a transaction received via ZMQ (`rawtx`) to the mempool with its data, so that it does
+    /// not have to be retrieved again when a job declares it. It is not used by any declared job
+    /// yet.
+    pub fn on_new_transaction(&mut self, transaction: &Transaction) {
+        let entry = self.mempool.entry(transaction.txid()).or_insert(None);
+        if entry.is_none() {
+            *entry = Some((transaction.clone(), 0));
+        }
+    }
+
+    /// Receives the hex of the blocks found with declared jobs, they are submitted by the
+    /// [`crate::block_submitter::BlockSubmitter`]
+    pub fn new_block_receiver(&self) -> Receiver<String> {
+        self.new_block_receiver.clone()
+    }
+
+    /// Retrieves from the node the fees of the `transactions` that are not known yet. The fee of a
+    /// transaction in the node mempool is returned by `getmempoolentry`, otherwise it is computed
+    /// with the values of the outputs it spends, taken from the JDS mempool or from `gettxout`
+    /// when the parent transaction is confirmed.
+    pub async fn fetch_fees(
+        self_: Arc<Mutex<Self>>,
+        transactions: &[Transaction],
+    ) -> Result<(), JdsMempoolError> {
+        let client = self_
+            .safe_lock(|x| x.get_client())?
+            .ok_or(JdsMempoolError::NoClient)?;
+        for tx in transactions {
+            let txid = tx.txid();
+            if self_.safe_lock(|x| x.fees.contains_key(&txid))? {
+                continue;
+            }
+            let fee = match client.get_mempool_entry_fee(&txid.to_string()).await {
+                Ok(fee) => Some(fee),
+                // not in the node mempool, e.g. provided by the downstream
+                Err(RpcError::JsonRpc(_)) => {
+                    let mut spent_values = HashMap::new();
+                    for input in &tx.input {
+                        let outpoint = input.previous_output;
+                        let value = match self_.safe_lock(|x| x.spent_output_value(&outpoint))? {
+                            Some(value) => Some(value),
+                            None => {
+                                client
+                                    .get_tx_out_value(&outpoint.txid.to_string(), outpoint.vout)
+                                    .await?
+                            }
+                        };
+                        match value {
+                            Some(value) => spent_values.insert(outpoint, value),
+                            // spent or unknown output
+                            None => break,
+                        };
+                    }
+                    fee_with_spent_outputs(tx, |outpoint| spent_values.get(outpoint).copied())
+                }
+                Err(e) => return Err(JdsMempoolError::Rpc(e)),
             };
+            if let Some(fee) = fee {
+                self_.safe_lock(|x| x.fees.insert(txid, fee))?;
+            }
         }
         Ok(())
     }
 
+    /// Fee of `tx`, `None` if it has not been retrieved with [`Self::fetch_fees`] and some of the
+    /// spent outputs are not in the mempool with their data
+    pub fn fee(&self, tx: &Transaction) -> Option<u64> {
+        self.fees
+            .get(&tx.txid())
+            .copied()
+            .or_else(|| fee_with_spent_outputs(tx, |outpoint| self.spent_output_value(outpoint)))
+    }
+
+    fn spent_output_value(&self, outpoint: &OutPoint) -> Option<u64> {
+        let (prev_tx, _) = self.mempool.get(&outpoint.txid)?.as_ref()?;
+        prev_tx
+            .output
+            .get(outpoint.vout as usize)
+            .map(|output| output.value)
+    }
+
     pub fn to_short_ids(&self, nonce: u64) -> Option<HashMap<[u8; 6], TransactionWithHash>> {
         let mut ret = HashMap::new();
+        let (k0, k1) = roles_logic_sv2::utils::short_txid_keys(nonce);
         for tx in &self.mempool {
-            let s_id = roles_logic_sv2::utils::get_short_hash(*tx.0, nonce)
+            let s_id = roles_logic_sv2::utils::short_txid(tx.0, k0, k1)
                 .to_vec()
                 .try_into()
                 .unwrap();
@@ -183,3 +330,51 @@ impl JDsMempool {
         Some(ret)
     }
 }
+
+/// Fee of `tx`, `spent_value` returns the value of the outputs spent by `tx`
+fn fee_with_spent_outputs<F>(tx: &Transaction, spent_value: F) -> Option<u64>
+where
+    F: Fn(&OutPoint) -> Option<u64>,
+{
+    let mut input_value = 0_u64;
+    for input in &tx.input {
+        input_value = input_value.checked_add(spent_value(&input.previous_output)?)?;
+    }
+    let output_value = tx.output.iter().map(|output| output.value).sum();
+    input_value.checked_sub(output_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stratum_common::bitcoin::{PackedLockTime, TxOut};
+
+    #[test]
+    fn test_zmq_transactions_are_stored_with_their_data() {
+        let (_, new_block_receiver) = async_channel::unbounded();
+        let mut mempool = JDsMempool::new(
+            "http://127.0.0.1:18332".to_string(),
+            "user".to_string(),
+            "pass".to_string(),
+            new_block_receiver,
+        );
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime(0),
+            input: vec![],
+            output: vec![TxOut {
+                value: 1,
+                script_pubkey: vec![0; 30].into(),
+            }],
+        };
+        // known from getrawmempool without its data
+        mempool.mempool.insert(tx.txid(), None);
+        mempool.on_new_transaction(&tx);
+        assert_eq!(mempool.mempool[&tx.txid()], Some((tx.clone(), 0)));
+
+        // already used by a declared job
+        mempool.mempool.insert(tx.txid(), Some((tx.clone(), 2)));
+        mempool.on_new_transaction(&tx);
+        assert_eq!(mempool.mempool[&tx.txid()], Some((tx.clone(), 2)));
+    }
+}
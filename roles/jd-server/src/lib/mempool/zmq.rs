@@ -0,0 +1,289 @@
+//! Minimal ZMTP 3.0 SUB client used to receive the `rawtx` and `hashblock` notifications that
+//! Bitcoin Core publishes when started with `-zmqpubrawtx` and `-zmqpubhashblock`.
+//!
+//! Only what is needed to talk with Bitcoin Core is implemented: NULL security mechanism, a single
+//! SUB socket and multipart messages.
+use super::error::JdsMempoolError;
+use stratum_common::bitcoin::{
+    consensus::encode::deserialize, hash_types::BlockHash, hashes::Hash, Transaction,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+pub const RAWTX_TOPIC: &[u8] = b"rawtx";
+pub const HASHBLOCK_TOPIC: &[u8] = b"hashblock";
+
+const FLAG_MORE: u8 = 0x01;
+const FLAG_LONG: u8 = 0x02;
+const FLAG_COMMAND: u8 = 0x04;
+
+/// Biggest frame body accepted from the peer. A `rawtx` notification can not be bigger than a
+/// block (4M weight units, so at most 4MB), plus some room for the commands.
+const MAX_BODY_SIZE: usize = 4_000_000 + 1024;
+
+/// Notification received from the node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZmqNotification {
+    /// A transaction entered the node mempool
+    RawTx(Transaction),
+    /// A new block has been connected to the node tip
+    HashBlock(BlockHash),
+}
+
+pub struct ZmqSubscriber<S> {
+    stream: S,
+}
+
+impl ZmqSubscriber<TcpStream> {
+    /// Connects to a Bitcoin Core ZMQ endpoint (e.g. `tcp://127.0.0.1:28332`) and subscribes to
+    /// `rawtx` and `hashblock`
+    pub async fn connect(address: &str) -> Result<Self, JdsMempoolError> {
+        let address = address.strip_prefix("tcp://").unwrap_or(address);
+        let stream = TcpStream::connect(address)
+            .await
+            .map_err(|e| JdsMempoolError::Zmq(e.to_string()))?;
+        Self::new(stream, &[RAWTX_TOPIC, HASHBLOCK_TOPIC]).await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> ZmqSubscriber<S> {
+    /// Does the ZMTP handshake on `stream` and subscribes to `topics`
+    pub async fn new(mut stream: S, topics: &[&[u8]]) -> Result<Self, JdsMempoolError> {
+        stream.write_all(&greeting()).await.map_err(io_error)?;
+        let mut peer_greeting = [0_u8; 64];
+        stream
+            .read_exact(&mut peer_greeting)
+            .await
+            .map_err(io_error)?;
+        if peer_greeting[0] != 0xff || peer_greeting[9] != 0x7f || peer_greeting[10] < 3 {
+            return Err(JdsMempoolError::Zmq("Invalid ZMTP greeting".to_string()));
+        }
+        if peer_greeting[12..32] != greeting()[12..32] {
+            return Err(JdsMempoolError::Zmq(
+                "Only the NULL security mechanism is supported".to_string(),
+            ));
+        }
+        stream.write_all(&ready_command()).await.map_err(io_error)?;
+        let mut self_ = Self { stream };
+        let (is_command, body) = self_.read_frame().await?;
+        if !is_command || !body.starts_with(b"\x05READY") {
+            return Err(JdsMempoolError::Zmq("Expected READY command".to_string()));
+        }
+        for topic in topics {
+            let mut subscription = vec![0x01];
+            subscription.extend_from_slice(topic);
+            self_.write_frame(&subscription).await?;
+        }
+        Ok(self_)
+    }
+
+    /// Waits for the next `rawtx` or `hashblock` notification, other topics are skipped
+    pub async fn recv(&mut self) -> Result<ZmqNotification, JdsMempoolError> {
+        loop {
+            let message = self.read_message().await?;
+            if message.len() < 2 {
+                continue;
+            }
+            match &message[0][..] {
+                RAWTX_TOPIC => {
+                    let tx: Transaction = deserialize(&message[1])
+                        .map_err(|e| JdsMempoolError::Zmq(e.to_string()))?;
+                    return Ok(ZmqNotification::RawTx(tx));
+                }
+                HASHBLOCK_TOPIC => {
+                    // Bitcoin Core publishes the hash in RPC (reversed) byte order
+                    let mut hash = message[1].clone();
+                    hash.reverse();
+                    let hash = BlockHash::from_slice(&hash)
+                        .map_err(|e| JdsMempoolError::Zmq(e.to_string()))?;
+                    return Ok(ZmqNotification::HashBlock(hash));
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    async fn read_message(&mut self) -> Result<Vec<Vec<u8>>, JdsMempoolError> {
+        let mut parts = Vec::new();
+        loop {
+            let flags = self.stream.read_u8().await.map_err(io_error)?;
+            let body = self.read_body(flags).await?;
+            if flags & FLAG_COMMAND != 0 {
+                // Commands (e.g. PING) are not part of messages
+                continue;
+            }
+            parts.push(body);
+            if flags & FLAG_MORE == 0 {
+                return Ok(parts);
+            }
+        }
+    }
+
+    async fn read_frame(&mut self) -> Result<(bool, Vec<u8>), JdsMempoolError> {
+        let flags = self.stream.read_u8().await.map_err(io_error)?;
+        let body = self.read_body(flags).await?;
+        Ok((flags & FLAG_COMMAND != 0, body))
+    }
+
+    async fn read_body(&mut self, flags: u8) -> Result<Vec<u8>, JdsMempoolError> {
+        let len = if flags & FLAG_LONG != 0 {
+            self.stream.read_u64().await.map_err(io_error)? as usize
+        } else {
+            self.stream.read_u8().await.map_err(io_error)? as usize
+        };
+        if len > MAX_BODY_SIZE {
+            return Err(JdsMempoolError::Zmq(format!(
+                "Frame of {} bytes is bigger than the max of {} bytes",
+                len, MAX_BODY_SIZE
+            )));
+        }
+        let mut body = vec![0; len];
+        self.stream.read_exact(&mut body).await.map_err(io_error)?;
+        Ok(body)
+    }
+
+    async fn write_frame(&mut self, body: &[u8]) -> Result<(), JdsMempoolError> {
+        self.stream
+            .write_all(&encode_frame(0, body))
+            .await
+            .map_err(io_error)
+    }
+}
+
+fn io_error(e: std::io::Error) -> JdsMempoolError {
+    JdsMempoolError::Zmq(e.to_string())
+}
+
+fn encode_frame(flags: u8, body: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(body.len() + 9);
+    if body.len() > u8::MAX as usize {
+        frame.push(flags | FLAG_LONG);
+        frame.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    } else {
+        frame.push(flags);
+        frame.push(body.len() as u8);
+    }
+    frame.extend_from_slice(body);
+    frame
+}
+
+// signature (10) + version (2) + mechanism (20) + as-server (1) + filler (31)
+fn greeting() -> [u8; 64] {
+    let mut greeting = [0_u8; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    greeting[10] = 3;
+    greeting[11] = 0;
+    greeting[12..16].copy_from_slice(b"NULL");
+    greeting
+}
+
+fn ready_command() -> Vec<u8> {
+    let mut body = vec![5];
+    body.extend_from_slice(b"READY");
+    body.push(11);
+    body.extend_from_slice(b"Socket-Type");
+    body.extend_from_slice(&3_u32.to_be_bytes());
+    body.extend_from_slice(b"SUB");
+    encode_frame(FLAG_COMMAND, &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stratum_common::bitcoin::{consensus::encode::serialize, PackedLockTime, TxOut};
+
+    // Plays the Bitcoin Core (PUB) side of the connection
+    async fn publisher(mut stream: tokio::io::DuplexStream, messages: Vec<Vec<Vec<u8>>>) {
+        let mut peer_greeting = [0_u8; 64];
+        stream.read_exact(&mut peer_greeting).await.unwrap();
+        assert_eq!(&peer_greeting[12..16], b"NULL");
+        stream.write_all(&greeting()).await.unwrap();
+        let mut ready = vec![0; ready_command().len()];
+        stream.read_exact(&mut ready).await.unwrap();
+        assert_eq!(ready, ready_command());
+        let mut body = vec![5];
+        body.extend_from_slice(b"READY");
+        body.push(11);
+        body.extend_from_slice(b"Socket-Type");
+        body.extend_from_slice(&3_u32.to_be_bytes());
+        body.extend_from_slice(b"PUB");
+        stream
+            .write_all(&encode_frame(FLAG_COMMAND, &body))
+            .await
+            .unwrap();
+        for topic in [RAWTX_TOPIC, HASHBLOCK_TOPIC] {
+            let mut subscription = vec![0; topic.len() + 3];
+            stream.read_exact(&mut subscription).await.unwrap();
+            assert_eq!(subscription[2], 0x01);
+            assert_eq!(&subscription[3..], topic);
+        }
+        for message in messages {
+            let n_parts = message.len();
+            for (i, part) in message.into_iter().enumerate() {
+                let flags = if i + 1 < n_parts { FLAG_MORE } else { 0 };
+                stream.write_all(&encode_frame(flags, &part)).await.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_and_other_mechanism_are_rejected() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            let mut peer_greeting = [0_u8; 64];
+            server.read_exact(&mut peer_greeting).await.unwrap();
+            server.write_all(&greeting()).await.unwrap();
+            let mut frame = vec![FLAG_COMMAND | FLAG_LONG];
+            frame.extend_from_slice(&u64::MAX.to_be_bytes());
+            server.write_all(&frame).await.unwrap();
+            // Keeps the stream open
+            let _ = server.read_u8().await;
+        });
+        assert!(ZmqSubscriber::new(client, &[RAWTX_TOPIC]).await.is_err());
+
+        let (client, mut server) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            let mut plain = greeting();
+            plain[12..17].copy_from_slice(b"PLAIN");
+            server.write_all(&plain).await.unwrap();
+            let _ = server.read_u8().await;
+        });
+        assert!(ZmqSubscriber::new(client, &[RAWTX_TOPIC]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_zmq_notifications() {
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime(0),
+            input: vec![],
+            output: vec![TxOut {
+                value: 1,
+                script_pubkey: vec![0; 300].into(),
+            }],
+        };
+        let mut hash = [0_u8; 32];
+        hash[31] = 1;
+        let seq = 0_u32.to_le_bytes().to_vec();
+        let messages = vec![
+            vec![b"sequence".to_vec(), vec![0; 33], seq.clone()],
+            vec![RAWTX_TOPIC.to_vec(), serialize(&tx), seq.clone()],
+            vec![HASHBLOCK_TOPIC.to_vec(), hash.to_vec(), seq],
+        ];
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(publisher(server, messages));
+
+        let mut subscriber = ZmqSubscriber::new(client, &[RAWTX_TOPIC, HASHBLOCK_TOPIC])
+            .await
+            .unwrap();
+        assert_eq!(subscriber.recv().await.unwrap(), ZmqNotification::RawTx(tx));
+        hash.reverse();
+        assert_eq!(
+            subscriber.recv().await.unwrap(),
+            ZmqNotification::HashBlock(BlockHash::from_slice(&hash).unwrap())
+        );
+    }
+}
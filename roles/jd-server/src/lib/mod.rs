@@ -1,11 +1,19 @@
+pub mod block_submitter;
 pub mod error;
 pub mod job_declarator;
+pub mod job_store;
 pub mod mempool;
 pub mod status;
 
 use async_channel::{bounded, unbounded, Receiver, Sender};
+use block_submitter::{BlockSubmitter, SubmitBlockNode};
+use config_helpers_sv2::{Validate, Validator};
 use error_handling::handle_result;
-use job_declarator::JobDeclarator;
+use job_declarator::{
+    policy::{DefaultJobPolicy, JobPolicy, JobPolicyConfig},
+    JobDeclarator,
+};
+use job_store::{DeclaredJobStore, DeclaredJobsStoreConfig};
 use mempool::error::JdsMempoolError;
 use roles_logic_sv2::utils::Mutex;
 use std::{ops::Sub, sync::Arc};
@@ -30,14 +38,48 @@ pub type EitherFrame = StandardEitherFrame<Message>;
 
 pub struct JobDeclaratorServer {
     config: Configuration,
+    job_policy: Option<Arc<dyn JobPolicy>>,
 }
 
 impl JobDeclaratorServer {
     pub fn new(config: Configuration) -> Self {
-        Self { config }
+        Self {
+            config,
+            job_policy: None,
+        }
+    }
+
+    /// Uses `job_policy` instead of the [`DefaultJobPolicy`] built from the config
+    pub fn with_job_policy(mut self, job_policy: Arc<dyn JobPolicy>) -> Self {
+        self.job_policy = Some(job_policy);
+        self
     }
+
     pub async fn start(&self) {
         let config = self.config.clone();
+        let job_policy: Arc<dyn JobPolicy> = match &self.job_policy {
+            Some(job_policy) => job_policy.clone(),
+            None => match DefaultJobPolicy::from_config(&config.job_policy) {
+                Ok(job_policy) => Arc::new(job_policy),
+                Err(e) => {
+                    error!("Invalid job_policy: {}", e);
+                    return;
+                }
+            },
+        };
+        if let Some(metrics_address) = &config.metrics_address {
+            if let Err(e) = monitoring_sv2::serve(metrics_address, "jd-server") {
+                error!("Failed to serve the metrics on {}: {}", metrics_address, e);
+            }
+        }
+        if let Some(admin_address) = &config.admin_address {
+            if let Err(e) = admin_sv2::serve(admin_address) {
+                error!(
+                    "Failed to serve the admin interface on {}: {}",
+                    admin_address, e
+                );
+            }
+        }
         let url = config.core_rpc_url.clone() + ":" + &config.core_rpc_port.clone().to_string();
         let username = config.core_rpc_user.clone();
         let password = config.core_rpc_pass.clone();
@@ -57,10 +99,35 @@ impl JobDeclaratorServer {
         let mut last_empty_mempool_warning =
             std::time::Instant::now().sub(std::time::Duration::from_secs(60));
 
-        // TODO if the jd-server is launched with core_rpc_url empty, the following flow is never
-        // taken. Consequentally new_block_receiver in JDsMempool::on_submit is never read, possibly
-        // reaching the channel bound. The new_block_sender is given as input to
-        // JobDeclarator::start()
+        // Found blocks are submitted to the mempool node (when core_rpc_url is set) and to every
+        // node in submit_block_nodes
+        let primary_client = mempool
+            .safe_lock(|x| x.get_client())
+            .expect("Mempool mutex is poisoned");
+        let block_submitter =
+            match BlockSubmitter::new(primary_client, &url, &config.submit_block_nodes) {
+                Ok(block_submitter) => block_submitter,
+                Err(e) => {
+                    error!("Invalid submit_block_nodes: {}", e);
+                    return;
+                }
+            };
+        if block_submitter.is_empty() {
+            warn!("No node configured to submit the blocks found with declared jobs");
+        }
+        let new_block_receiver = mempool
+            .safe_lock(|x| x.new_block_receiver())
+            .expect("Mempool mutex is poisoned");
+        let sender_submit_solution = sender.clone();
+        task::spawn(async move {
+            while let Ok(block_hex) = new_block_receiver.recv().await {
+                handle_result!(
+                    sender_submit_solution,
+                    block_submitter.submit(block_hex).await
+                );
+            }
+        });
+
         if url.contains("http") {
             let sender_update_mempool = sender.clone();
             task::spawn(async move {
@@ -88,6 +155,9 @@ impl JobDeclaratorServer {
                                 mempool::error::handle_error(&err);
                                 handle_result!(sender_update_mempool, Err(err));
                             }
+                            JdsMempoolError::Zmq(_) => {
+                                mempool::error::handle_error(&err);
+                            }
                         }
                     }
                     tokio::time::sleep(mempool_update_interval).await;
@@ -97,29 +167,37 @@ impl JobDeclaratorServer {
                 }
             });
 
-            let mempool_cloned = mempool.clone();
-            let sender_submit_solution = sender.clone();
-            task::spawn(async move {
-                loop {
-                    let result = mempool::JDsMempool::on_submit(mempool_cloned.clone()).await;
-                    if let Err(err) = result {
-                        match err {
-                            JdsMempoolError::EmptyMempool => {
-                                if last_empty_mempool_warning.elapsed().as_secs() >= 60 {
-                                    warn!("{:?}", err);
-                                    warn!("Template Provider is running, but its mempool is empty (possible reasons: you're testing in testnet, signet, or regtest)");
-                                    last_empty_mempool_warning = std::time::Instant::now();
-                                }
-                            }
-                            _ => {
-                                // TODO here there should be a better error managmenet
-                                mempool::error::handle_error(&err);
-                                handle_result!(sender_submit_solution, Err(err));
-                            }
+            // When the node publishes its mempool changes over ZMQ the JDS mempool is updated as
+            // soon as a transaction or a block is received, the polling above is still used to
+            // recover from missed notifications so it can run with a much longer interval.
+            if let Some(zmq_address) = config.core_zmq_address.clone() {
+                let mempool_cloned = mempool.clone();
+                task::spawn(async move {
+                    loop {
+                        let result =
+                            mempool::JDsMempool::listen_zmq(mempool_cloned.clone(), &zmq_address)
+                                .await;
+                        if let Err(err) = result {
+                            mempool::error::handle_error(&err);
                         }
+                        tokio::time::sleep(Duration::from_secs(5)).await;
                     }
+                });
+            }
+        };
+
+        let job_store = match &config.declared_jobs_store {
+            Some(store_config) => match DeclaredJobStore::open(store_config) {
+                Ok(job_store) => Some(job_store.spawn()),
+                Err(e) => {
+                    error!(
+                        "Unable to open declared jobs store {}: {}",
+                        store_config.path, e
+                    );
+                    return;
                 }
-            });
+            },
+            None => None,
         };
 
         let cloned = config.clone();
@@ -132,6 +210,8 @@ impl JobDeclaratorServer {
                 mempool_cloned,
                 new_block_sender,
                 sender_add_txs_to_mempool,
+                job_store,
+                job_policy,
             )
             .await
         });
@@ -261,6 +341,24 @@ pub struct Configuration {
     pub core_rpc_pass: String,
     #[serde(deserialize_with = "duration_from_toml")]
     pub mempool_update_interval: Duration,
+    /// Bitcoin Core ZMQ endpoint publishing `rawtx` and `hashblock` (e.g. "tcp://127.0.0.1:28332")
+    #[serde(default)]
+    pub core_zmq_address: Option<String>,
+    /// Nodes where the blocks found with declared jobs are submitted in addition to the mempool
+    /// node
+    #[serde(default)]
+    pub submit_block_nodes: Vec<SubmitBlockNode>,
+    /// Where the declared jobs are recorded for auditing, nothing is recorded if not set
+    #[serde(default)]
+    pub declared_jobs_store: Option<DeclaredJobsStoreConfig>,
+    /// Filters of the policy applied to the declared transactions
+    #[serde(default)]
+    pub job_policy: JobPolicyConfig,
+    /// Address where the metrics are served in the OpenMetrics text format, not served if not set
+    pub metrics_address: Option<String>,
+    /// Localhost address of the admin interface (log level, connections, channels and state
+    /// snapshot), not served if not set
+    pub admin_address: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -304,6 +402,56 @@ impl Configuration {
             core_rpc_user: core_rpc.user,
             core_rpc_pass: core_rpc.pass,
             mempool_update_interval,
+            core_zmq_address: None,
+            submit_block_nodes: Vec::new(),
+            declared_jobs_store: None,
+            job_policy: JobPolicyConfig::default(),
+            metrics_address: None,
+            admin_address: None,
+        }
+    }
+}
+
+impl Validate for Configuration {
+    fn validate(&self, v: &mut Validator) {
+        v.socket_address("listen_jd_address", &self.listen_jd_address);
+        v.keypair(
+            ("authority_public_key", &self.authority_public_key),
+            ("authority_secret_key", &self.authority_secret_key),
+        );
+        v.check(
+            self.cert_validity_sec > 0,
+            "cert_validity_sec must be greater than 0",
+        );
+        if let Err(e) = get_coinbase_output(self) {
+            v.check(false, format!("coinbase_outputs: {}", e));
+        }
+        v.check(
+            self.core_rpc_url.is_empty()
+                || self.core_rpc_url.starts_with("http://")
+                || self.core_rpc_url.starts_with("https://"),
+            format!(
+                "core_rpc_url: `{}` must start with http:// or https://",
+                self.core_rpc_url
+            ),
+        );
+        if let Some(zmq_address) = &self.core_zmq_address {
+            v.check(
+                zmq_address.starts_with("tcp://") || zmq_address.starts_with("ipc://"),
+                format!(
+                    "core_zmq_address: `{}` must start with tcp:// or ipc://",
+                    zmq_address
+                ),
+            );
+        }
+        if let Err(e) = DefaultJobPolicy::from_config(&self.job_policy) {
+            v.check(false, format!("job_policy: {}", e));
+        }
+        if let Some(metrics_address) = &self.metrics_address {
+            v.socket_address("metrics_address", metrics_address);
+        }
+        if let Some(admin_address) = &self.admin_address {
+            v.socket_address("admin_address", admin_address);
         }
     }
 }
@@ -396,6 +544,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_config_validation() {
+        for path in [
+            "config-examples/jds-config-hosted-example.toml",
+            "config-examples/jds-config-local-example.toml",
+        ] {
+            config_helpers_sv2::load::<Configuration>(std::path::Path::new(path), "JDS").unwrap();
+        }
+
+        let mut config = load_config("config-examples/jds-config-local-example.toml");
+        config.core_rpc_url = "127.0.0.1".to_string();
+        config.core_zmq_address = Some("127.0.0.1:28332".to_string());
+        config.coinbase_outputs.clear();
+        let mut v = Validator::default();
+        config.validate(&mut v);
+        match v.finish() {
+            Err(config_helpers_sv2::ConfigError::Invalid(errors)) => {
+                assert_eq!(errors.len(), 3, "{:?}", errors)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
     #[test]
     fn test_try_from_valid_input() {
         let input = CoinbaseOutput {
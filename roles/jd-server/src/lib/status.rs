@@ -128,6 +128,9 @@ pub async fn handle_error(sender: &Sender, e: JdsError) -> error_handling::Error
         JdsError::NoLastDeclaredJob => {
             send_status(sender, e, error_handling::ErrorBranch::Continue).await
         }
+        JdsError::BlockSubmission(_) => {
+            send_status(sender, e, error_handling::ErrorBranch::Continue).await
+        }
     }
 }
 
@@ -135,7 +138,7 @@ pub async fn handle_error(sender: &Sender, e: JdsError) -> error_handling::Error
 mod tests {
     use std::{convert::TryInto, io::Error};
 
-    use super::*;
+    use super::{super::error::SubmitBlockFailure, *};
     use async_channel::{bounded, RecvError};
     use roles_logic_sv2::mining_sv2::OpenMiningChannelError;
 
@@ -353,6 +356,25 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_handle_error_block_submission_error() {
+        let (tx, rx) = bounded(1);
+        let sender = Sender::Downstream(tx);
+        let error = JdsError::BlockSubmission(vec![SubmitBlockFailure {
+            endpoint: "rpc http://127.0.0.1:8332".to_string(),
+            error: "timeout".to_string(),
+        }]);
+        let error_string = error.to_string();
+        handle_error(&sender, error).await;
+        match rx.recv().await {
+            Ok(status) => match status.state {
+                State::Healthy(e) => assert_eq!(e, error_string),
+                _ => panic!("Unexpected state received"),
+            },
+            Err(_) => panic!("Failed to receive status"),
+        }
+    }
+
     #[tokio::test]
     async fn test_handle_error_no_last_declared_job_error() {
         let (tx, rx) = bounded(1);
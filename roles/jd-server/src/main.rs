@@ -1,13 +1,12 @@
 #![allow(special_module_name)]
 pub use crate::lib::{
+    job_declarator::policy,
     mempool::{self},
-    status, Configuration,
+    status, Configuration, JobDeclaratorServer,
 };
 use tracing::error;
 mod lib;
 
-use ext_config::{Config, File, FileFormat};
-
 mod args {
     use std::path::PathBuf;
 
@@ -37,8 +36,8 @@ mod args {
             let cli_args = std::env::args();
 
             if cli_args.len() == 1 {
-                println!("Using default config path: {}", Self::DEFAULT_CONFIG_PATH);
-                println!("{}\n", Self::HELP_MSG);
+                tracing::info!("Using default config path: {}", Self::DEFAULT_CONFIG_PATH);
+                tracing::info!("{}", Self::HELP_MSG);
             }
 
             let config_path = cli_args
@@ -73,7 +72,7 @@ mod args {
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt::init();
+    logging_sv2::init();
     let args = match args::Args::from_args() {
         Ok(cfg) => cfg,
         Err(help) => {
@@ -82,25 +81,13 @@ async fn main() {
         }
     };
 
-    let config_path = args.config_path.to_str().expect("Invalid config path");
-
-    // Load config
-    let config: Configuration = match Config::builder()
-        .add_source(File::new(config_path, FileFormat::Toml))
-        .build()
-    {
-        Ok(settings) => match settings.try_deserialize::<Configuration>() {
-            Ok(c) => c,
-            Err(e) => {
-                error!("Failed to deserialize config: {}", e);
-                return;
-            }
-        },
+    let config: Configuration = match config_helpers_sv2::load(&args.config_path, "JDS") {
+        Ok(c) => c,
         Err(e) => {
-            error!("Failed to build config: {}", e);
+            error!("{}", e);
             return;
         }
     };
 
-    lib::JobDeclaratorServer::new(config).start().await;
+    JobDeclaratorServer::new(config).start().await;
 }
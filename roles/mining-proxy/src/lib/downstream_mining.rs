@@ -12,6 +12,7 @@ use roles_logic_sv2::{
     errors::Error,
     handlers::{
         common::{ParseDownstreamCommonMessages, SendTo as SendToCommon},
+        interceptor::Interceptor,
         mining::{ParseDownstreamMiningMessages, SendTo, SupportedChannelTypes},
     },
     mining_sv2::*,
@@ -37,6 +38,7 @@ pub struct DownstreamMiningNode {
     sender: Sender<EitherFrame>,
     pub status: DownstreamMiningNodeStatus,
     upstream: Option<Arc<Mutex<UpstreamMiningNode>>>,
+    pub nominal_hash_rate: f32,
 }
 
 #[derive(Debug)]
@@ -91,6 +93,32 @@ impl DownstreamMiningNodeStatus {
         }
     }
 
+    /// Id of the opened channel, `None` if no channel is opened yet
+    pub fn channel_id(&self) -> Option<u32> {
+        match self {
+            DownstreamMiningNodeStatus::ChannelOpened(Channel::DownstreamHomUpstreamGroup {
+                channel_id,
+                ..
+            })
+            | DownstreamMiningNodeStatus::ChannelOpened(Channel::DownstreamHomUpstreamExtended {
+                channel_id,
+                ..
+            }) => Some(*channel_id),
+            _ => None,
+        }
+    }
+
+    /// Moves the channel to another group of the upstream, after a `SetGroupChannel`
+    fn set_group_id(&mut self, new_group_id: u32) {
+        if let DownstreamMiningNodeStatus::ChannelOpened(Channel::DownstreamHomUpstreamGroup {
+            group_id,
+            ..
+        }) = self
+        {
+            *group_id = new_group_id;
+        }
+    }
+
     fn open_channel_for_down_hom_up_group(&mut self, channel_id: u32, group_id: u32) {
         match self {
             DownstreamMiningNodeStatus::Initializing => panic!(),
@@ -143,6 +171,9 @@ impl DownstreamMiningNode {
         self.status
             .open_channel_for_down_hom_up_extended(channel_id, group_id);
     }
+    pub fn set_group_id(&mut self, group_id: u32) {
+        self.status.set_group_id(group_id);
+    }
 
     pub fn new(receiver: Receiver<EitherFrame>, sender: Sender<EitherFrame>, id: u32) -> Self {
         Self {
@@ -151,6 +182,7 @@ impl DownstreamMiningNode {
             status: DownstreamMiningNodeStatus::Initializing,
             upstream: None,
             id,
+            nominal_hash_rate: 0.0,
         }
     }
 
@@ -300,6 +332,10 @@ impl
         SupportedChannelTypes::Group
     }
 
+    fn get_interceptor(&self) -> Option<Arc<dyn Interceptor>> {
+        super::INTERCEPTOR.get().cloned()
+    }
+
     fn is_work_selection_enabled(&self) -> bool {
         false
     }
@@ -322,8 +358,10 @@ impl
             .safe_lock(|s| s.channel_ids.safe_lock(|r| r.next()).unwrap())
             .unwrap();
         info!(channel_id);
+        self.nominal_hash_rate = req.nominal_hash_rate;
         let cloned = up.as_ref().expect("No upstream initialized").clone();
-        up.as_ref()
+        let response = up
+            .as_ref()
             .expect("No upstream initialized")
             .safe_lock(|up| {
                 if up.channel_kind.is_extended() {
@@ -342,12 +380,17 @@ impl
                         }
                     }
                     let messages = messages.into_iter().map(SendTo::Respond).collect();
-                    Ok(SendTo::Multiple(messages))
+                    SendTo::Multiple(messages)
                 } else {
-                    Ok(SendTo::RelaySameMessageToRemote(cloned))
+                    SendTo::RelaySameMessageToRemote(cloned.clone())
                 }
             })
-            .unwrap()
+            .unwrap();
+        // The upstream extended channel must be updated with the hash rate of the new channel
+        if let SendTo::Multiple(_) = response {
+            UpstreamMiningNode::send_update_channel(cloned);
+        }
+        Ok(response)
     }
 
     fn handle_open_extended_mining_channel(
@@ -359,9 +402,34 @@ impl
 
     fn handle_update_channel(
         &mut self,
-        _: UpdateChannel,
+        m: UpdateChannel,
     ) -> Result<SendTo<UpstreamMiningNode>, Error> {
-        todo!()
+        match &self.status {
+            DownstreamMiningNodeStatus::ChannelOpened(Channel::DownstreamHomUpstreamGroup {
+                ..
+            }) => {
+                self.nominal_hash_rate = m.nominal_hash_rate;
+                let remote = self.upstream.as_ref().unwrap();
+                remote
+                    .safe_lock(|r| r.set_downstream_hash_rate(m.channel_id, m.nominal_hash_rate))
+                    .unwrap();
+                Ok(SendTo::RelaySameMessageToRemote(remote.clone()))
+            }
+            DownstreamMiningNodeStatus::ChannelOpened(Channel::DownstreamHomUpstreamExtended {
+                ..
+            }) => {
+                self.nominal_hash_rate = m.nominal_hash_rate;
+                let remote = self.upstream.as_ref().unwrap();
+                let res = UpstreamMiningNode::update_standard_channel_down(remote.clone(), m)?;
+                Ok(SendTo::Respond(res))
+            }
+            _ => Ok(SendTo::Respond(Mining::UpdateChannelError(
+                UpdateChannelError {
+                    channel_id: m.channel_id,
+                    error_code: "invalid-channel-id".to_string().try_into().unwrap(),
+                },
+            ))),
+        }
     }
 
     fn handle_submit_shares_standard(
@@ -2,15 +2,21 @@ pub mod downstream_mining;
 pub mod error;
 pub mod upstream_mining;
 
+use config_helpers_sv2::{Validate, Validator};
 use once_cell::sync::OnceCell;
 use roles_logic_sv2::{
+    handlers::interceptor::Interceptor,
     routing_logic::{CommonRoutingLogic, MiningProxyRoutingLogic, MiningRoutingLogic},
     selectors::GeneralMiningSelector,
+    upstream_selection::{
+        LeastLoaded, LowestLatency, RoundRobin, SelectionStrategy, StaticPriority,
+    },
     utils::{GroupId, Id, Mutex},
 };
 use serde::Deserialize;
-use std::{net::SocketAddr, sync::Arc};
-use upstream_mining::UpstreamMiningNode;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+use tracing::info;
+use upstream_mining::{UpstreamMiningNode, UpstreamStats};
 
 type RLogic = MiningProxyRoutingLogic<
     downstream_mining::DownstreamMiningNode,
@@ -26,6 +32,9 @@ type RLogic = MiningProxyRoutingLogic<
 /// So it make sense to use shared mutable memory to lower the complexity of the codebase and to
 /// have some performance gain.
 pub static ROUTING_LOGIC: OnceCell<Mutex<RLogic>> = OnceCell::new();
+/// Called with the mining messages received from the upstreams and the downstreams, it must be
+/// set before the proxy is started
+pub static INTERCEPTOR: OnceCell<Arc<dyn Interceptor>> = OnceCell::new();
 static MIN_EXTRANONCE_SIZE: u16 = 6;
 static EXTRANONCE_RANGE_1_LENGTH: usize = 4;
 
@@ -44,24 +53,88 @@ pub async fn initialize_upstreams(min_version: u16, max_version: u16) {
 }
 
 fn remove_upstream(id: u32) {
-    let upstreams = ROUTING_LOGIC
+    ROUTING_LOGIC
         .get()
         .expect("BUG: ROUTING_LOGIC has not been set yet")
-        .safe_lock(|r_logic| r_logic.upstream_selector.upstreams.clone())
+        .safe_lock(|rl| rl.upstream_selector.remove_upstream(id))
         .unwrap();
-    let mut updated_upstreams = vec![];
-    for upstream in upstreams {
-        if upstream.safe_lock(|s| s.get_id()).unwrap() != id {
-            updated_upstreams.push(upstream)
-        }
-    }
+}
+
+/// Records the round trip of a `SetupConnection` with the upstream `id`, used by
+/// [`UpstreamSelection::LowestLatency`]
+fn record_latency(id: u32, sample: Duration) {
     ROUTING_LOGIC
         .get()
-        .unwrap()
-        .safe_lock(|rl| rl.upstream_selector.update_upstreams(updated_upstreams))
+        .expect("BUG: ROUTING_LOGIC has not been set yet")
+        .safe_lock(|rl| rl.upstream_selector.record_latency(id, sample))
+        .unwrap();
+}
+
+/// Called when an upstream that went down is connected again
+fn add_upstream(upstream: Arc<Mutex<UpstreamMiningNode>>) {
+    let id = upstream.safe_lock(|s| s.get_id()).unwrap();
+    ROUTING_LOGIC
+        .get()
+        .expect("BUG: ROUTING_LOGIC has not been set yet")
+        .safe_lock(|rl| {
+            let mut upstreams = rl.upstream_selector.upstreams.clone();
+            if !upstreams
+                .iter()
+                .any(|u| u.safe_lock(|s| s.get_id()).unwrap() == id)
+            {
+                upstreams.push(upstream);
+                rl.upstream_selector.update_upstreams(upstreams);
+            }
+        })
         .unwrap();
 }
 
+/// Statistics of every configured upstream, also the ones that are down
+pub fn upstream_stats() -> Vec<UpstreamStats> {
+    let mut stats: Vec<UpstreamStats> = ROUTING_LOGIC
+        .get()
+        .expect("BUG: ROUTING_LOGIC has not been set yet")
+        .safe_lock(|rl| {
+            let selector = &rl.upstream_selector;
+            selector
+                .id_to_upstream
+                .iter()
+                .map(|(id, up)| up.safe_lock(|u| u.stats(selector.get_weight(*id))).unwrap())
+                .collect()
+        })
+        .unwrap();
+    stats.sort_by_key(|s| s.id);
+    let total_hash_rate: u64 = stats.iter().map(|s| s.hash_rate).sum();
+    if total_hash_rate > 0 {
+        for s in &mut stats {
+            s.hash_rate_share = s.hash_rate as f32 / total_hash_rate as f32;
+        }
+    }
+    stats
+}
+
+/// Logs [`upstream_stats`] every `interval`
+pub async fn log_upstream_stats(interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        for s in upstream_stats() {
+            info!(
+                "Upstream {} {}: connected {}, weight {}, {} channels, hash rate {} ({:.1}%), {} \
+                 shares accepted, {} rejected",
+                s.id,
+                s.address,
+                s.connected,
+                s.weight,
+                s.channels,
+                s.hash_rate,
+                s.hash_rate_share * 100.0,
+                s.accepted_shares,
+                s.rejected_shares
+            );
+        }
+    }
+}
+
 pub fn get_routing_logic() -> MiningRoutingLogic<
     downstream_mining::DownstreamMiningNode,
     upstream_mining::UpstreamMiningNode,
@@ -88,6 +161,27 @@ pub struct UpstreamMiningValues {
     port: u16,
     pub_key: key_utils::Secp256k1PublicKey,
     channel_kind: ChannelKind,
+    /// Share of the downstream hash rate sent to this upstream relative to the other upstreams,
+    /// an upstream with weight 0 is only used when the others are down
+    #[serde(default = "default_weight")]
+    weight: f32,
+    /// Connect over TLS instead of noise, needs the `tls` feature
+    #[serde(default)]
+    tls: Option<UpstreamTls>,
+}
+
+/// TLS connection to an upstream, for when the encryption is terminated by a TLS proxy
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(not(feature = "tls"), allow(dead_code))]
+pub struct UpstreamTls {
+    /// Name checked against the certificate of the upstream
+    pub server_name: String,
+    /// PEM file with the trusted certificate authorities, the webpki roots are trusted if not set
+    pub ca_file: Option<PathBuf>,
+}
+
+fn default_weight() -> f32 {
+    1.0
 }
 
 #[derive(Debug, Deserialize, Clone, Copy)]
@@ -96,6 +190,32 @@ pub enum ChannelKind {
     Extended,
 }
 
+/// How the upstream of a new downstream is picked among the available ones
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UpstreamSelection {
+    /// The hash rate is split between the upstreams proportionally to their weights
+    #[default]
+    LeastLoaded,
+    /// The first upstream in `upstreams`, the next ones are only used when it is down
+    Priority,
+    /// The upstreams in turn
+    RoundRobin,
+    /// The upstream with the lowest `SetupConnection` round trip, measured at every connection
+    LowestLatency,
+}
+
+impl UpstreamSelection {
+    fn strategy(self) -> Box<dyn SelectionStrategy> {
+        match self {
+            Self::LeastLoaded => Box::new(LeastLoaded),
+            // The ids of the upstreams are their positions in the config
+            Self::Priority => Box::new(StaticPriority::default()),
+            Self::RoundRobin => Box::new(RoundRobin::new()),
+            Self::LowestLatency => Box::new(LowestLatency),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Configuration {
     pub upstreams: Vec<UpstreamMiningValues>,
@@ -106,6 +226,41 @@ pub struct Configuration {
     downstream_share_per_minute: f32,
     expected_total_downstream_hr: f32,
     reconnect: bool,
+    /// How often the statistics of the upstreams are logged, not logged if not set
+    pub upstreams_stats_interval_sec: Option<u64>,
+    /// How the upstream of a new downstream is picked, `LeastLoaded` if not set
+    #[serde(default)]
+    pub upstream_selection: UpstreamSelection,
+}
+
+impl Validate for Configuration {
+    fn validate(&self, v: &mut Validator) {
+        v.ip_address("listen_address", &self.listen_address);
+        v.ordered(
+            ("min_supported_version", self.min_supported_version),
+            ("max_supported_version", self.max_supported_version),
+        );
+        v.check(
+            self.downstream_share_per_minute > 0.0,
+            "downstream_share_per_minute must be greater than 0",
+        );
+        v.not_empty("upstreams", &self.upstreams);
+        for (i, upstream) in self.upstreams.iter().enumerate() {
+            v.ip_address(&format!("upstreams[{}].address", i), &upstream.address);
+            v.check(
+                upstream.weight >= 0.0,
+                format!("upstreams[{}].weight must not be negative", i),
+            );
+            #[cfg(not(feature = "tls"))]
+            v.check(
+                upstream.tls.is_none(),
+                format!(
+                    "upstreams[{}].tls needs the proxy to be built with the tls feature",
+                    i
+                ),
+            );
+        }
+    }
 }
 pub async fn initialize_r_logic(
     upstreams: &[UpstreamMiningValues],
@@ -116,6 +271,13 @@ pub async fn initialize_r_logic(
     let mut upstream_mining_nodes = Vec::with_capacity(upstreams.len());
     for (index, upstream_) in upstreams.iter().enumerate() {
         let socket = SocketAddr::new(upstream_.address.parse().unwrap(), upstream_.port);
+        #[cfg(not(feature = "tls"))]
+        if upstream_.tls.is_some() {
+            panic!(
+                "Upstream {} uses TLS but the proxy was built without the tls feature",
+                socket
+            );
+        }
 
         let upstream = Arc::new(Mutex::new(UpstreamMiningNode::new(
             index as u32,
@@ -129,6 +291,7 @@ pub async fn initialize_r_logic(
             None,
             config.expected_total_downstream_hr,
             config.reconnect,
+            upstream_.tls.clone(),
         )));
 
         match upstream_.channel_kind {
@@ -138,7 +301,11 @@ pub async fn initialize_r_logic(
 
         upstream_mining_nodes.push(upstream);
     }
-    let upstream_selector = GeneralMiningSelector::new(upstream_mining_nodes);
+    let mut upstream_selector = GeneralMiningSelector::new(upstream_mining_nodes);
+    for (index, upstream_) in upstreams.iter().enumerate() {
+        upstream_selector.set_weight(index as u32, upstream_.weight);
+    }
+    upstream_selector.set_strategy(config.upstream_selection.strategy());
     MiningProxyRoutingLogic {
         upstream_selector,
         downstream_id_generator: Id::new(),
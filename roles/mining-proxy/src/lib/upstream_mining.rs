@@ -1,7 +1,12 @@
 #![allow(dead_code)]
 
 use core::convert::TryInto;
-use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use async_channel::{Receiver, SendError, Sender};
 use async_recursion::async_recursion;
@@ -16,12 +21,15 @@ use roles_logic_sv2::{
         channel_factory::{ExtendedChannelKind, OnNewShare, ProxyExtendedChannelFactory, Share},
         proxy_group_channel::GroupChannels,
     },
-    common_messages_sv2::{Protocol, SetupConnection},
+    common_messages_sv2::{DeviceInfo, Endpoint, Protocol, SetupConnection},
     common_properties::{
         IsMiningDownstream, IsMiningUpstream, IsUpstream, RequestIdMapper, UpstreamChannel,
     },
     errors::Error,
-    handlers::mining::{ParseUpstreamMiningMessages, SendTo, SupportedChannelTypes},
+    handlers::{
+        interceptor::Interceptor,
+        mining::{ParseUpstreamMiningMessages, SendTo, SupportedChannelTypes},
+    },
     job_dispatcher::GroupChannelJobDispatcher,
     mining_sv2::*,
     parsers::{CommonMessages, Mining, MiningDeviceMessages, PoolMessages},
@@ -164,6 +172,8 @@ pub struct UpstreamMiningNode {
     connection: Option<UpstreamMiningConnection>,
     sv2_connection: Option<Sv2MiningConnection>,
     authority_public_key: [u8; 32],
+    /// Frames are sent over TLS instead of noise if set
+    tls: Option<super::UpstreamTls>,
     /// group_channel id/channel_id -> dispatcher
     pub channel_id_to_job_dispatcher: HashMap<u32, JobDispatcher, BuildNoHashHasher<u32>>,
     /// Each relayed message that has a `request_id` field must have a unique `request_id` number,
@@ -188,7 +198,27 @@ pub struct UpstreamMiningNode {
     job_up_to_down_ids:
         HashMap<u32, Vec<(Arc<Mutex<DownstreamMiningNode>>, u32)>, BuildNoHashHasher<u32>>,
     downstream_hash_rate: f32,
+    // Nominal hash rate of each downstream channel, `total_hash_rate` is their sum. When the
+    // channels are opened on an upstream extended channel, the upstream channel is updated with it
+    downstream_hash_rates: HashMap<u32, f32, BuildNoHashHasher<u32>>,
     reconnect: bool,
+    accepted_shares: u64,
+    rejected_shares: u64,
+}
+
+/// Statistics of an upstream, see [`super::upstream_stats`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpstreamStats {
+    pub id: u32,
+    pub address: SocketAddr,
+    pub connected: bool,
+    pub weight: f32,
+    pub channels: usize,
+    pub hash_rate: u64,
+    /// Fraction of the hash rate of the proxy sent to this upstream
+    pub hash_rate_share: f32,
+    pub accepted_shares: u64,
+    pub rejected_shares: u64,
 }
 
 /// It assume that endpoint NEVER change flags and version!
@@ -207,6 +237,7 @@ impl UpstreamMiningNode {
         recv_coinbase_out: Option<Receiver<(Vec<TxOut>, Vec<u8>)>>,
         downstream_hash_rate: f32,
         reconnect: bool,
+        tls: Option<super::UpstreamTls>,
     ) -> Self {
         let request_id_mapper = RequestIdMapper::new();
         let downstream_selector = ProxyRemoteSelector::new();
@@ -217,6 +248,7 @@ impl UpstreamMiningNode {
             connection: None,
             sv2_connection: None,
             authority_public_key,
+            tls,
             channel_id_to_job_dispatcher: HashMap::with_hasher(BuildNoHashHasher::default()),
             request_id_mapper,
             downstream_selector,
@@ -229,7 +261,10 @@ impl UpstreamMiningNode {
             tx_outs: HashMap::new(),
             job_up_to_down_ids: HashMap::with_hasher(BuildNoHashHasher::default()),
             downstream_hash_rate,
+            downstream_hash_rates: HashMap::with_hasher(BuildNoHashHasher::default()),
             reconnect,
+            accepted_shares: 0,
+            rejected_shares: 0,
         }
     }
     fn on_p_hash(
@@ -359,23 +394,52 @@ impl UpstreamMiningNode {
         match has_connection {
             true => Ok(()),
             false => {
-                let (address, authority_public_key) = self_mutex
-                    .safe_lock(|self_| (self_.address, self_.authority_public_key))
+                let (address, authority_public_key, tls) = self_mutex
+                    .safe_lock(|self_| {
+                        (self_.address, self_.authority_public_key, self_.tls.clone())
+                    })
                     .unwrap();
                 let socket = TcpStream::connect(address).await.map_err(|_| {
                     error!("Upstream node {} is not available", address);
                     super::error::Error::UpstreamNotAvailabe(address)
                 })?;
-                info!(
-                    "Connected to upstream node {}: now handling noise handshake",
-                    address
-                );
 
-                let initiator = Initiator::from_raw_k(authority_public_key).unwrap();
-                let (receiver, sender, _, _) =
-                    Connection::new(socket, HandshakeRole::Initiator(initiator))
+                let (receiver, sender) = match tls {
+                    #[cfg(feature = "tls")]
+                    Some(tls) => {
+                        info!(
+                            "Connected to upstream node {}: now handling TLS handshake",
+                            address
+                        );
+                        let config = network_helpers_sv2::tls_connection_tokio::TlsClientConfig {
+                            server_name: tls.server_name,
+                            ca_file: tls.ca_file,
+                        };
+                        network_helpers_sv2::tls_connection_tokio::TlsConnection::connect(
+                            socket,
+                            &config,
+                            Default::default(),
+                        )
                         .await
-                        .expect("impossible to conenct");
+                        .map_err(|e| {
+                            error!("TLS handshake with {} failed: {:?}", address, e);
+                            super::error::Error::UpstreamNotAvailabe(address)
+                        })?
+                    }
+                    // Refused when the config is loaded if the tls feature is not enabled
+                    _ => {
+                        info!(
+                            "Connected to upstream node {}: now handling noise handshake",
+                            address
+                        );
+                        let initiator = Initiator::from_raw_k(authority_public_key).unwrap();
+                        let (receiver, sender, _, _) =
+                            Connection::new(socket, HandshakeRole::Initiator(initiator))
+                                .await
+                                .expect("impossible to conenct");
+                        (receiver, sender)
+                    }
+                };
                 let connection = UpstreamMiningConnection { receiver, sender };
                 self_mutex
                     .safe_lock(|self_| {
@@ -448,36 +512,101 @@ impl UpstreamMiningNode {
         self.id
     }
 
+    /// `hash_rate_share` is left to 0 as it depends on the other upstreams
+    pub fn stats(&self, weight: f32) -> UpstreamStats {
+        UpstreamStats {
+            id: self.id,
+            address: self.address,
+            connected: self.sv2_connection.is_some(),
+            weight,
+            channels: self.downstream_hash_rates.len(),
+            hash_rate: self.total_hash_rate,
+            hash_rate_share: 0.0,
+            accepted_shares: self.accepted_shares,
+            rejected_shares: self.rejected_shares,
+        }
+    }
+
+    pub fn set_downstream_hash_rate(&mut self, channel_id: u32, hash_rate: f32) {
+        self.downstream_hash_rates.insert(channel_id, hash_rate);
+        self.update_total_hash_rate();
+    }
+
+    fn remove_downstream_hash_rate(&mut self, channel_id: u32) -> bool {
+        let removed = self.downstream_hash_rates.remove(&channel_id).is_some();
+        self.update_total_hash_rate();
+        removed
+    }
+
+    fn update_total_hash_rate(&mut self) {
+        self.total_hash_rate = self.downstream_hash_rates.values().sum::<f32>() as u64;
+    }
+
     pub fn remove_dowstream(self_: Arc<Mutex<Self>>, down: &Arc<Mutex<DownstreamMiningNode>>) {
-        self_
-            .safe_lock(|s| s.downstream_selector.remove_downstream(down))
+        let channel_id = down.safe_lock(|d| d.status.channel_id()).unwrap();
+        let hash_rate_changed = self_
+            .safe_lock(|s| {
+                s.downstream_selector.remove_downstream(down);
+                let channel_id = match channel_id {
+                    Some(channel_id) => channel_id,
+                    None => return false,
+                };
+                let removed = s.remove_downstream_hash_rate(channel_id);
+                match &mut s.channel_kind {
+                    ChannelKind::Extended(Some(factory)) => {
+                        factory.remove_channel(channel_id);
+                        removed
+                    }
+                    _ => false,
+                }
+            })
             .unwrap();
+        if hash_rate_changed {
+            Self::send_update_channel(self_);
+        }
     }
 
-    fn exit(self_: Arc<Mutex<Self>>) {
-        if !self_.safe_lock(|s| s.reconnect).unwrap() {
-            super::remove_upstream(self_.safe_lock(|s| s.id).unwrap());
+    /// Sends upstream an `UpdateChannel` for the extended channel with the sum of the nominal hash
+    /// rates of the downstream standard channels. Nothing is sent when there are no downstreams.
+    pub fn send_update_channel(self_: Arc<Mutex<Self>>) {
+        let message = self_
+            .safe_lock(|s| match &s.channel_kind {
+                ChannelKind::Extended(Some(factory)) if !s.downstream_hash_rates.is_empty() => {
+                    Some(Mining::UpdateChannel(UpdateChannel {
+                        channel_id: factory.get_this_channel_id(),
+                        nominal_hash_rate: s.downstream_hash_rates.values().sum(),
+                        maximum_target: [255; 32].into(),
+                    }))
+                }
+                _ => None,
+            })
+            .unwrap();
+        if let Some(message) = message {
+            let frame: StdFrame = PoolMessages::Mining(message).try_into().unwrap();
+            tokio::task::spawn(async move {
+                if let Err(e) = UpstreamMiningNode::send(self_, frame).await {
+                    error!("Failed to send UpdateChannel upstream: {:?}", e);
+                }
+            });
         }
+    }
+
+    fn exit(self_: Arc<Mutex<Self>>) {
+        // New downstreams go to the other upstreams until this one is back
+        super::remove_upstream(self_.safe_lock(|s| s.id).unwrap());
         let downstreams = self_
             .safe_lock(|s| s.downstream_selector.get_all_downstreams())
             .unwrap();
+        // The upstream channel is gone, there is nothing to update
+        self_
+            .safe_lock(|s| {
+                s.downstream_hash_rates.clear();
+                s.update_total_hash_rate();
+            })
+            .unwrap();
         let mut dowstreams_: Vec<Arc<Mutex<DownstreamMiningNode>>> = vec![];
         for d in downstreams {
-            if let Some(id) = d
-                .safe_lock(|d| match &d.status {
-                    super::downstream_mining::DownstreamMiningNodeStatus::Initializing => None,
-                    super::downstream_mining::DownstreamMiningNodeStatus::Paired(_) => None,
-                    super::downstream_mining::DownstreamMiningNodeStatus::ChannelOpened(
-                        channel,
-                    ) => match channel {
-                        Channel::DownstreamHomUpstreamGroup { channel_id, .. } => Some(*channel_id),
-                        Channel::DownstreamHomUpstreamExtended { channel_id, .. } => {
-                            Some(*channel_id)
-                        }
-                    },
-                })
-                .unwrap()
-            {
+            if let Some(id) = d.safe_lock(|d| d.status.channel_id()).unwrap() {
                 self_
                     .safe_lock(|s| s.downstream_selector.remove_downstreams_in_channel(id))
                     .unwrap();
@@ -502,9 +631,10 @@ impl UpstreamMiningNode {
             self_.safe_lock(|s| s.channel_kind.reset()).unwrap();
             tokio::task::spawn(async move {
                 tokio::time::sleep(std::time::Duration::from_secs(10)).await;
-                Self::setup_flag_and_version(self_, Some(flags), 2, 2)
-                    .await
-                    .unwrap();
+                match Self::setup_flag_and_version(self_.clone(), Some(flags), 2, 2).await {
+                    Ok(()) => super::add_upstream(self_),
+                    Err(e) => error!("Failed to reconnect upstream: {:?}", e),
+                }
             });
         }
     }
@@ -605,6 +735,7 @@ impl UpstreamMiningNode {
                 )
             })
             .unwrap();
+        let sent_at = Instant::now();
         Self::send(self_mutex.clone(), frame).await?;
 
         let cloned = self_mutex.clone();
@@ -612,11 +743,14 @@ impl UpstreamMiningNode {
             .await
             .unwrap()
             .unwrap();
+        let round_trip = sent_at.elapsed();
 
         let message_type = response.get_header().unwrap().msg_type();
         let payload = response.payload();
         match (message_type, payload).try_into() {
             Ok(CommonMessages::SetupConnectionSuccess(m)) => {
+                let id = self_mutex.safe_lock(|self_| self_.id).unwrap();
+                super::record_latency(id, round_trip);
                 let receiver = self_mutex
                     .safe_lock(|self_| {
                         self_.sv2_connection = Some(Sv2MiningConnection {
@@ -692,30 +826,16 @@ impl UpstreamMiningNode {
         min_version: u16,
         max_version: u16,
     ) -> StdFrame {
-        let endpoint_host = self
-            .address
-            .ip()
-            .to_string()
-            .into_bytes()
-            .try_into()
-            .unwrap();
-        let vendor = String::new().try_into().unwrap();
-        let hardware_version = String::new().try_into().unwrap();
-        let firmware = String::new().try_into().unwrap();
-        let device_id = String::new().try_into().unwrap();
-        let setup_connection: PoolMessages = SetupConnection {
-            protocol: Protocol::MiningProtocol,
-            min_version,
-            max_version,
-            flags,
-            endpoint_host,
-            endpoint_port: self.address.port(),
-            vendor,
-            hardware_version,
-            firmware,
-            device_id,
-        }
-        .into();
+        let endpoint = Endpoint::new(&self.address.ip().to_string(), self.address.port()).unwrap();
+        let mut setup_connection = SetupConnection::for_protocol(
+            Protocol::MiningProtocol,
+            DeviceInfo::default(),
+            endpoint,
+        );
+        setup_connection.min_version = min_version;
+        setup_connection.max_version = max_version;
+        setup_connection.flags = flags;
+        let setup_connection: PoolMessages = setup_connection.into();
         setup_connection.try_into().unwrap()
     }
 
@@ -745,12 +865,62 @@ impl UpstreamMiningNode {
                         channel_id,
                     )
                     .unwrap();
-                messages.into_iter().map(|x| x.into_static()).collect()
+                let messages = messages.into_iter().map(|x| x.into_static()).collect();
+                self.set_downstream_hash_rate(channel_id, downstream_hash_rate);
+                messages
             }
             _ => panic!("Channel factory not initialized"),
         }
     }
 
+    /// Called when a downstream standard channel opened on the upstream extended channel sends an
+    /// `UpdateChannel`: the channel gets the target of its new nominal hash rate, and the upstream
+    /// channel is updated with the new total hash rate.
+    pub fn update_standard_channel_down(
+        self_: Arc<Mutex<Self>>,
+        m: UpdateChannel,
+    ) -> Result<Mining<'static>, Error> {
+        let response = self_.safe_lock(|s| s.update_standard_channel(m)).unwrap();
+        if let Mining::SetTarget(_) = response {
+            Self::send_update_channel(self_);
+        }
+        Ok(response)
+    }
+
+    fn update_standard_channel(&mut self, m: UpdateChannel) -> Mining<'static> {
+        let share_per_minute = self.downstream_share_per_minute;
+        let factory = match &mut self.channel_kind {
+            ChannelKind::Extended(Some(factory)) => factory,
+            _ => return update_channel_error(m.channel_id, "invalid-channel-id"),
+        };
+        let target = roles_logic_sv2::utils::hash_rate_to_target(
+            m.nominal_hash_rate.into(),
+            share_per_minute.into(),
+        );
+        let mut target: Target = match target {
+            Ok(target) => target.into(),
+            Err(e) => {
+                error!("Invalid nominal hash rate {}: {:?}", m.nominal_hash_rate, e);
+                return update_channel_error(m.channel_id, "max-target-out-of-range");
+            }
+        };
+        let maximum_target: Target = m.maximum_target.into();
+        if target > maximum_target {
+            target = maximum_target;
+        }
+        if factory
+            .update_target_for_channel(m.channel_id, target.clone())
+            .is_none()
+        {
+            return update_channel_error(m.channel_id, "invalid-channel-id");
+        }
+        self.set_downstream_hash_rate(m.channel_id, m.nominal_hash_rate);
+        Mining::SetTarget(SetTarget {
+            channel_id: m.channel_id,
+            maximum_target: target.into(),
+        })
+    }
+
     pub fn handle_std_shr(
         self_: Arc<Mutex<Self>>,
         share_: SubmitSharesStandard,
@@ -888,6 +1058,10 @@ impl
         SupportedChannelTypes::GroupAndExtended
     }
 
+    fn get_interceptor(&self) -> Option<Arc<dyn Interceptor>> {
+        super::INTERCEPTOR.get().cloned()
+    }
+
     fn is_work_selection_enabled(&self) -> bool {
         true
     }
@@ -897,6 +1071,10 @@ impl
         m: OpenStandardMiningChannelSuccess,
         remote: Option<Arc<Mutex<DownstreamMiningNode>>>,
     ) -> Result<SendTo<DownstreamMiningNode>, Error> {
+        if let (ChannelKind::Group(_), Some(remote)) = (&self.channel_kind, &remote) {
+            let hash_rate = remote.safe_lock(|r| r.nominal_hash_rate).unwrap();
+            self.set_downstream_hash_rate(m.channel_id, hash_rate);
+        }
         match &mut self.channel_kind {
             ChannelKind::Group(group) => {
                 let down_is_header_only = remote
@@ -964,9 +1142,26 @@ impl
 
     fn handle_update_channel_error(
         &mut self,
-        _m: UpdateChannelError,
+        m: UpdateChannelError,
     ) -> Result<SendTo<DownstreamMiningNode>, Error> {
-        todo!("470")
+        match &self.channel_kind {
+            // The error is for the UpdateChannel sent by the proxy for the aggregated hash rate
+            ChannelKind::Extended(_) => {
+                error!(
+                    "Upstream refused to update channel {}: {}",
+                    m.channel_id,
+                    String::from_utf8_lossy(m.error_code.inner_as_ref())
+                );
+                Ok(SendTo::None(None))
+            }
+            ChannelKind::Group(_) => match self
+                .downstream_selector
+                .downstream_from_channel_id(m.channel_id)
+            {
+                Some(d) => Ok(SendTo::RelaySameMessageToRemote(d)),
+                None => Ok(SendTo::None(None)),
+            },
+        }
     }
 
     fn handle_close_channel(
@@ -987,6 +1182,7 @@ impl
         &mut self,
         m: SubmitSharesSuccess,
     ) -> Result<SendTo<DownstreamMiningNode>, Error> {
+        self.accepted_shares += m.new_submits_accepted_count as u64;
         match &self
             .downstream_selector
             .downstream_from_channel_id(m.channel_id)
@@ -1003,6 +1199,7 @@ impl
         &mut self,
         _m: SubmitSharesError,
     ) -> Result<SendTo<DownstreamMiningNode>, Error> {
+        self.rejected_shares += 1;
         Ok(SendTo::None(None))
     }
 
@@ -1154,6 +1351,36 @@ impl
         }
     }
 
+    fn handle_set_group_channel(
+        &mut self,
+        m: SetGroupChannel,
+    ) -> Result<SendTo<DownstreamMiningNode>, Error> {
+        match &mut self.channel_kind {
+            ChannelKind::Group(group) => {
+                group.on_set_group_channel(&m);
+                let group_channel_id = m.group_channel_id;
+                let channel_ids = m.channel_ids.into_inner();
+                self.downstream_selector
+                    .set_group_channel(group_channel_id, &channel_ids);
+                for channel_id in channel_ids {
+                    if let Some(downstream) = self
+                        .downstream_selector
+                        .downstream_from_channel_id(channel_id)
+                    {
+                        downstream
+                            .safe_lock(|d| d.set_group_id(group_channel_id))
+                            .map_err(|e| Error::PoisonLock(e.to_string()))?;
+                    }
+                }
+                // Downstreams are HOM, they do not know about groups
+                Ok(SendTo::None(None))
+            }
+            ChannelKind::Extended(_) => {
+                Err(Error::UnexpectedMessage(MESSAGE_TYPE_SET_GROUP_CHANNEL))
+            }
+        }
+    }
+
     fn handle_set_custom_mining_job_success(
         &mut self,
         _m: SetCustomMiningJobSuccess,
@@ -1169,8 +1396,22 @@ impl
         todo!("560")
     }
 
-    fn handle_set_target(&mut self, _m: SetTarget) -> Result<SendTo<DownstreamMiningNode>, Error> {
-        todo!("570")
+    fn handle_set_target(&mut self, m: SetTarget) -> Result<SendTo<DownstreamMiningNode>, Error> {
+        match &mut self.channel_kind {
+            // Shares of the downstreams are sent upstream only if they meet the new target
+            ChannelKind::Extended(Some(factory)) => {
+                factory.set_target(&mut m.maximum_target.into());
+                Ok(SendTo::None(None))
+            }
+            ChannelKind::Extended(None) => Ok(SendTo::None(None)),
+            ChannelKind::Group(_) => match self
+                .downstream_selector
+                .downstream_from_channel_id(m.channel_id)
+            {
+                Some(d) => Ok(SendTo::RelaySameMessageToRemote(d)),
+                None => Ok(SendTo::None(None)),
+            },
+        }
     }
 
     fn handle_reconnect(&mut self, _m: Reconnect) -> Result<SendTo<DownstreamMiningNode>, Error> {
@@ -1182,6 +1423,13 @@ impl
     }
 }
 
+fn update_channel_error(channel_id: u32, error_code: &str) -> Mining<'static> {
+    Mining::UpdateChannelError(UpdateChannelError {
+        channel_id,
+        error_code: error_code.to_string().try_into().unwrap(),
+    })
+}
+
 pub async fn scan(
     nodes: Vec<Arc<Mutex<UpstreamMiningNode>>>,
     min_version: u16,
@@ -1283,6 +1531,7 @@ mod tests {
             None,
             100_000.0,
             false,
+            None,
         );
 
         assert_eq!(actual.id, id);
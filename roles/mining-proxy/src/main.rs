@@ -17,12 +17,11 @@
 //! A Downstream that signal the capacity to handle group channels can open more than one channel.
 //! A Downstream that signal the incapacity to handle group channels can open only one channel.
 #![allow(special_module_name)]
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use tokio::{net::TcpListener, sync::oneshot};
 use tracing::{error, info};
 
-use ext_config::{Config, File, FileFormat};
 use lib::Configuration;
 use roles_logic_sv2::utils::{GroupId, Mutex};
 
@@ -112,21 +111,10 @@ async fn main() {
         }
     };
 
-    let config_path = args.config_path.to_str().expect("Invalid config path");
-
-    let config: Configuration = match Config::builder()
-        .add_source(File::new(config_path, FileFormat::Toml))
-        .build()
-    {
-        Ok(settings) => match settings.try_deserialize::<Configuration>() {
-            Ok(c) => c,
-            Err(e) => {
-                error!("Failed to deserialize config: {}", e);
-                return;
-            }
-        },
+    let config: Configuration = match config_helpers_sv2::load(&args.config_path, "MINING_PROXY") {
+        Ok(c) => c,
         Err(e) => {
-            error!("Failed to build config: {}", e);
+            error!("{}", e);
             return;
         }
     };
@@ -140,6 +128,9 @@ async fn main() {
 
     info!("Initializing upstream scanner");
     lib::initialize_upstreams(config.min_supported_version, config.max_supported_version).await;
+    if let Some(interval) = config.upstreams_stats_interval_sec {
+        tokio::spawn(lib::log_upstream_stats(Duration::from_secs(interval)));
+    }
     info!("Initializing downstream listener");
 
     let socket = SocketAddr::new(
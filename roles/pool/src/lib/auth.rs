@@ -0,0 +1,288 @@
+//! Authentication of the miners.
+//!
+//! When an [`AuthProvider`] is set, the `user_identity` of every `OpenStandardMiningChannel` and
+//! `OpenExtendedMiningChannel` is checked before the channel is opened. Unknown users get an
+//! `OpenMiningChannel.Error` with the `unknown-user` error code and are disconnected.
+//!
+//! The provider is either configured in the `[auth]` section of the config or set with
+//! [`crate::PoolSv2::with_auth_provider`]. Three providers are available:
+//! * [`StaticAuth`]: a list of accounts
+//! * [`WebhookAuth`]: an HTTP endpoint that answers 200 to known users
+//! * [`SignatureAuth`]: the user identity carries a signature of the account by the pool
+//!
+//! A `.worker` suffix is allowed after the account, e.g. `alice.rig1` is accepted when `alice` is.
+use key_utils::{Secp256k1PublicKey, SignatureService};
+use serde::Deserialize;
+use std::{collections::HashSet, fmt::Debug, future::Future, pin::Pin, time::Duration};
+use stratum_common::{
+    bitcoin::hashes::{sha256, Hash},
+    secp256k1::schnorr::Signature,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tracing::warn;
+
+pub type AuthFuture<'a> = Pin<Box<dyn Future<Output = bool> + Send + 'a>>;
+
+pub trait AuthProvider: Send + Sync + Debug {
+    /// Returns true if `user_identity` can open channels
+    fn authorize<'a>(&'a self, user_identity: &'a str) -> AuthFuture<'a>;
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AuthConfig {
+    Static {
+        users: Vec<String>,
+    },
+    Webhook {
+        url: String,
+        #[serde(default = "default_webhook_timeout_sec")]
+        timeout_sec: u64,
+    },
+    Signature {
+        public_key: Secp256k1PublicKey,
+    },
+}
+
+fn default_webhook_timeout_sec() -> u64 {
+    5
+}
+
+impl AuthConfig {
+    pub fn into_provider(self) -> Result<Box<dyn AuthProvider>, String> {
+        Ok(match self {
+            AuthConfig::Static { users } => Box::new(StaticAuth::new(users)),
+            AuthConfig::Webhook { url, timeout_sec } => {
+                Box::new(WebhookAuth::new(&url, Duration::from_secs(timeout_sec))?)
+            }
+            AuthConfig::Signature { public_key } => Box::new(SignatureAuth::new(public_key)),
+        })
+    }
+}
+
+// The account is what comes before the first `.`
+fn account(user_identity: &str) -> &str {
+    user_identity.split('.').next().unwrap_or_default()
+}
+
+#[derive(Debug, Clone)]
+pub struct StaticAuth {
+    users: HashSet<String>,
+}
+
+impl StaticAuth {
+    pub fn new(users: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            users: users.into_iter().collect(),
+        }
+    }
+}
+
+impl AuthProvider for StaticAuth {
+    fn authorize<'a>(&'a self, user_identity: &'a str) -> AuthFuture<'a> {
+        let authorized =
+            self.users.contains(user_identity) || self.users.contains(account(user_identity));
+        Box::pin(async move { authorized })
+    }
+}
+
+/// Sends `GET <url>?user_identity=<user identity>` and accepts the user if the answer is 200.
+/// Only plain `http://` urls are supported, the user is refused if the endpoint is unreachable.
+#[derive(Debug, Clone)]
+pub struct WebhookAuth {
+    host: String,
+    path: String,
+    timeout: Duration,
+}
+
+impl WebhookAuth {
+    pub fn new(url: &str, timeout: Duration) -> Result<Self, String> {
+        let url = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("Only http:// urls are supported: {}", url))?;
+        let (host, path) = match url.find('/') {
+            Some(index) => (&url[..index], &url[index..]),
+            None => (url, "/"),
+        };
+        let host = match host.contains(':') {
+            true => host.to_string(),
+            false => format!("{}:80", host),
+        };
+        Ok(Self {
+            host,
+            path: path.to_string(),
+            timeout,
+        })
+    }
+
+    async fn request(&self, user_identity: &str) -> std::io::Result<bool> {
+        let separator = if self.path.contains('?') { '&' } else { '?' };
+        let request = format!(
+            "GET {}{}user_identity={} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            separator,
+            percent_encode(user_identity),
+            self.host
+        );
+        let mut stream = TcpStream::connect(&self.host).await?;
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        let status_line = response.split(|b| *b == b'\n').next().unwrap_or_default();
+        let status = std::str::from_utf8(status_line)
+            .unwrap_or_default()
+            .split_whitespace()
+            .nth(1);
+        Ok(status == Some("200"))
+    }
+}
+
+impl AuthProvider for WebhookAuth {
+    fn authorize<'a>(&'a self, user_identity: &'a str) -> AuthFuture<'a> {
+        Box::pin(async move {
+            match tokio::time::timeout(self.timeout, self.request(user_identity)).await {
+                Ok(Ok(authorized)) => authorized,
+                Ok(Err(e)) => {
+                    warn!("Auth webhook {} failed: {}", self.host, e);
+                    false
+                }
+                Err(_) => {
+                    warn!("Auth webhook {} timed out", self.host);
+                    false
+                }
+            }
+        })
+    }
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The user identity is `<account>:<signature>`, optionally followed by `.<worker>`, where
+/// signature is the hex encoded schnorr signature of sha256(account) by `public_key`.
+#[derive(Debug, Clone)]
+pub struct SignatureAuth {
+    public_key: Secp256k1PublicKey,
+}
+
+impl SignatureAuth {
+    pub fn new(public_key: Secp256k1PublicKey) -> Self {
+        Self { public_key }
+    }
+
+    fn verify(&self, user_identity: &str) -> bool {
+        let (account, signature) = match account(user_identity).split_once(':') {
+            Some(parts) => parts,
+            None => return false,
+        };
+        let signature = match hex::decode(signature)
+            .ok()
+            .and_then(|s| Signature::from_slice(&s).ok())
+        {
+            Some(signature) => signature,
+            None => return false,
+        };
+        let message = sha256::Hash::hash(account.as_bytes()).to_vec();
+        SignatureService::default()
+            .verify(message, signature, self.public_key.0)
+            .is_ok()
+    }
+}
+
+impl AuthProvider for SignatureAuth {
+    fn authorize<'a>(&'a self, user_identity: &'a str) -> AuthFuture<'a> {
+        let authorized = self.verify(user_identity);
+        Box::pin(async move { authorized })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stratum_common::secp256k1::SecretKey;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_static_auth() {
+        let auth = StaticAuth::new(vec!["alice".to_string()]);
+        assert!(auth.authorize("alice").await);
+        assert!(auth.authorize("alice.rig1").await);
+        assert!(!auth.authorize("bob").await);
+        assert!(!auth.authorize("alicebob").await);
+    }
+
+    #[tokio::test]
+    async fn test_signature_auth() {
+        let secret_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let public_key: Secp256k1PublicKey = key_utils::Secp256k1SecretKey(secret_key).into();
+        let message = sha256::Hash::hash(b"alice").to_vec();
+        let signature = SignatureService::default().sign(message, secret_key);
+        let user_identity = format!("alice:{}", hex::encode(signature.as_ref()));
+
+        let auth = SignatureAuth::new(public_key);
+        assert!(auth.authorize(&user_identity).await);
+        assert!(auth.authorize(&format!("{}.rig1", user_identity)).await);
+        let forged = user_identity.replacen("alice", "bob", 1);
+        assert!(!auth.authorize(&forged).await);
+        assert!(!auth.authorize("alice").await);
+    }
+
+    #[test]
+    fn test_auth_config() {
+        let config = r#"
+            type = "signature"
+            public_key = "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72"
+        "#;
+        let config: AuthConfig = ext_config::Config::builder()
+            .add_source(ext_config::File::from_str(
+                config,
+                ext_config::FileFormat::Toml,
+            ))
+            .build()
+            .and_then(|c| c.try_deserialize())
+            .unwrap();
+        assert!(matches!(config, AuthConfig::Signature { .. }));
+        assert!(AuthConfig::Webhook {
+            url: "ftp://127.0.0.1".to_string(),
+            timeout_sec: 5
+        }
+        .into_provider()
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_auth() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let len = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..len]).to_string();
+                let response = if request.starts_with("GET /auth?user_identity=alice%2Brig ") {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"
+                } else {
+                    "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n"
+                };
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        let auth =
+            WebhookAuth::new(&format!("http://{}/auth", address), Duration::from_secs(5)).unwrap();
+        assert!(auth.authorize("alice+rig").await);
+        assert!(!auth.authorize("bob").await);
+        assert!(WebhookAuth::new("https://example.com", Duration::from_secs(5)).is_err());
+    }
+}
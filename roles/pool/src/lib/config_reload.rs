@@ -0,0 +1,130 @@
+//! Reload of the coinbase outputs without restarting the pool.
+//!
+//! The config file is read again on SIGHUP, and when it changes if `config_watch_interval_sec`
+//! is set. Only `coinbase_outputs` are applied: they are validated, the new coinbase output data
+//! size is sent to the Template Provider and the outputs are used for the jobs of the next
+//! templates. Miners stay connected. If the new outputs are not valid the old ones are kept.
+use super::{
+    error::{PoolError, PoolResult},
+    mining_pool::{get_coinbase_output, Configuration, Pool},
+    template_receiver::TemplateRx,
+};
+use async_channel::{unbounded, Sender};
+use ext_config::{Config, File, FileFormat};
+use roles_logic_sv2::utils::{coinbase_output_data_size, Mutex};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use stratum_common::bitcoin::TxOut;
+use tokio::task;
+use tracing::{error, info};
+
+/// Starts the tasks that reload the coinbase outputs from `path`
+pub fn start(
+    path: PathBuf,
+    watch_interval: Option<Duration>,
+    pool: Arc<Mutex<Pool>>,
+    template_rx: Arc<Mutex<TemplateRx>>,
+) {
+    let (reload_tx, reload_rx) = unbounded();
+    #[cfg(unix)]
+    task::spawn(on_hangup(reload_tx.clone()));
+    if let Some(watch_interval) = watch_interval {
+        task::spawn(watch(path.clone(), watch_interval, reload_tx));
+    }
+    task::spawn(async move {
+        while reload_rx.recv().await.is_ok() {
+            match reload(&path, &pool, &template_rx).await {
+                Ok(outputs) => info!("Reloaded {} coinbase outputs", outputs),
+                Err(e) => error!("Coinbase outputs not reloaded: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn on_hangup(reload_tx: Sender<()>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!("Unable to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        info!("SIGHUP received, reloading the coinbase outputs");
+        if reload_tx.send(()).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn watch(path: PathBuf, watch_interval: Duration, reload_tx: Sender<()>) {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_modified: Option<SystemTime> = modified(&path);
+    loop {
+        tokio::time::sleep(watch_interval).await;
+        let current = modified(&path);
+        if current != last_modified {
+            last_modified = current;
+            info!("{} changed, reloading the coinbase outputs", path.display());
+            if reload_tx.send(()).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Returns the number of coinbase outputs loaded
+async fn reload(
+    path: &Path,
+    pool: &Arc<Mutex<Pool>>,
+    template_rx: &Arc<Mutex<TemplateRx>>,
+) -> PoolResult<usize> {
+    let outputs = load_coinbase_outputs(path)?;
+    let coinbase_output_len =
+        coinbase_output_data_size(&outputs)?.coinbase_output_max_additional_size;
+    // The Template Provider must reserve the space before the outputs are used
+    TemplateRx::update_coinbase_output_size(template_rx.clone(), coinbase_output_len).await?;
+    let len = outputs.len();
+    Pool::update_coinbase_outputs(pool, outputs)?;
+    Ok(len)
+}
+
+#[allow(clippy::result_large_err)]
+fn load_coinbase_outputs(path: &Path) -> PoolResult<Vec<TxOut>> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| PoolError::Custom(format!("Invalid config path: {}", path.display())))?;
+    let config: Configuration = Config::builder()
+        .add_source(File::new(path, FileFormat::Toml))
+        .build()
+        .and_then(|settings| settings.try_deserialize())
+        .map_err(|e| PoolError::Custom(format!("Failed to load config: {}", e)))?;
+    Ok(get_coinbase_output(&config)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_coinbase_outputs() {
+        let outputs = load_coinbase_outputs(Path::new(
+            "./config-examples/pool-config-local-tp-example.toml",
+        ))
+        .unwrap();
+        assert_eq!(outputs.len(), 1);
+
+        let path = std::env::temp_dir().join(format!("{}-pool-config.toml", std::process::id()));
+        let config = std::fs::read_to_string("./config-examples/pool-config-local-tp-example.toml")
+            .unwrap()
+            .replace("\"P2WPKH\"", "\"P2XX\"");
+        std::fs::write(&path, config).unwrap();
+        assert!(load_coinbase_outputs(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
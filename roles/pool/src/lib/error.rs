@@ -20,6 +20,10 @@ pub enum PoolError {
     ComponentShutdown(String),
     Custom(String),
     Sv2ProtocolError((u32, Mining<'static>)),
+    /// The downstream exceeded a rate limit and must be dropped
+    RateLimited(u32),
+    /// The downstream does not read its frames and must be dropped
+    SlowDownstream(u32),
 }
 
 impl std::fmt::Display for PoolError {
@@ -40,6 +44,8 @@ impl std::fmt::Display for PoolError {
             Sv2ProtocolError(ref e) => {
                 write!(f, "Received Sv2 Protocol Error from upstream: `{:?}`", e)
             }
+            RateLimited(ref id) => write!(f, "Downstream {} exceeded the rate limits", id),
+            SlowDownstream(ref id) => write!(f, "Downstream {} is too slow", id),
         }
     }
 }
@@ -0,0 +1,139 @@
+//! Maintenance mode.
+//!
+//! Maintenance is started with SIGUSR1, or by sending `maintenance` on `admin_socket`. The pool
+//! then refuses new connections and new channels, asks every downstream to move to the backup
+//! endpoint with a `ChannelEndpointChanged` for each of its channels followed by a `Reconnect`,
+//! and shuts down once every downstream is gone or after `drain_timeout_sec`.
+use super::{
+    error::{PoolError, PoolResult},
+    mining_pool::Pool,
+};
+use async_channel::{bounded, Sender};
+use roles_logic_sv2::{common_messages_sv2::Endpoint, utils::Mutex};
+use serde::Deserialize;
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::task;
+use tracing::{error, info, warn};
+
+/// Error code of the `OpenMiningChannel.Error` sent during maintenance
+pub const MAINTENANCE: &str = "pool-in-maintenance";
+
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MaintenanceConfig {
+    pub backup_host: String,
+    pub backup_port: u16,
+    #[serde(default = "default_drain_timeout_sec")]
+    pub drain_timeout_sec: u64,
+    /// Unix socket accepting the `maintenance` command
+    pub admin_socket: Option<PathBuf>,
+}
+
+fn default_drain_timeout_sec() -> u64 {
+    300
+}
+
+/// Starts listening for the maintenance command, `shutdown` is notified when the pool is drained
+#[allow(clippy::result_large_err)]
+pub fn start(
+    config: MaintenanceConfig,
+    pool: Arc<Mutex<Pool>>,
+    shutdown: Sender<()>,
+) -> PoolResult<()> {
+    let endpoint = Endpoint::new(&config.backup_host, config.backup_port)
+        .map_err(|e| PoolError::Custom(format!("Invalid backup endpoint: {}", e)))?;
+    let (command_tx, command_rx) = bounded(1);
+    #[cfg(unix)]
+    {
+        task::spawn(on_user_signal(command_tx.clone()));
+        if let Some(path) = config.admin_socket.clone() {
+            // A socket left by a previous run would make the bind fail
+            let _ = std::fs::remove_file(&path);
+            let listener = tokio::net::UnixListener::bind(&path)?;
+            info!("Listening for admin commands on {}", path.display());
+            task::spawn(admin_socket(listener, command_tx));
+        }
+    }
+    #[cfg(not(unix))]
+    drop(command_tx);
+    let drain_timeout = Duration::from_secs(config.drain_timeout_sec);
+    task::spawn(async move {
+        if command_rx.recv().await.is_err() {
+            return;
+        }
+        if let Err(e) = Pool::start_maintenance(&pool, endpoint).await {
+            error!("Failed to migrate the downstreams: {}", e);
+        }
+        drain(&pool, drain_timeout).await;
+        let _ = shutdown.send(()).await;
+    });
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn on_user_signal(command_tx: Sender<()>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut user_signal = match signal(SignalKind::user_defined1()) {
+        Ok(user_signal) => user_signal,
+        Err(e) => {
+            error!("Unable to listen for SIGUSR1: {}", e);
+            return;
+        }
+    };
+    if user_signal.recv().await.is_some() {
+        info!("SIGUSR1 received, starting maintenance");
+        let _ = command_tx.try_send(());
+    }
+}
+
+#[cfg(unix)]
+async fn admin_socket(listener: tokio::net::UnixListener, command_tx: Sender<()>) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    while let Ok((stream, _)) = listener.accept().await {
+        let (reader, mut writer) = stream.into_split();
+        let mut command = String::new();
+        if BufReader::new(reader)
+            .read_line(&mut command)
+            .await
+            .is_err()
+        {
+            continue;
+        }
+        let answer = match command.trim() {
+            "maintenance" => {
+                info!("Maintenance requested on the admin socket");
+                let _ = command_tx.try_send(());
+                "ok\n".to_string()
+            }
+            command => format!("unknown command: {}\n", command),
+        };
+        let _ = writer.write_all(answer.as_bytes()).await;
+    }
+}
+
+async fn drain(pool: &Arc<Mutex<Pool>>, drain_timeout: Duration) {
+    let deadline = Instant::now() + drain_timeout;
+    loop {
+        let count = pool.safe_lock(|p| p.downstream_count()).ok();
+        match count {
+            Some(0) => {
+                info!("Every downstream left, shutting down");
+                return;
+            }
+            Some(count) if Instant::now() >= deadline => {
+                warn!(
+                    "Drain timeout reached with {} downstreams, shutting down",
+                    count
+                );
+                return;
+            }
+            Some(_) => tokio::time::sleep(DRAIN_CHECK_INTERVAL).await,
+            None => return,
+        }
+    }
+}
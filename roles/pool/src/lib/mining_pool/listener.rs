@@ -0,0 +1,267 @@
+//! The endpoints on which the pool accepts downstream connections.
+//!
+//! Besides `listen_address`, the pool can listen on any number of `[[listeners]]`, e.g. one per
+//! public ip when the pool is behind GeoDNS, or a plaintext one on the local network for the
+//! proxies that run next to the pool. Every listener has its own [`ListenerPolicy`], applied to
+//! the downstreams that connect through it.
+use super::{
+    super::{error::PoolError, status},
+    default_noise, Configuration, Pool,
+};
+use config_helpers_sv2::Validator;
+use network_helpers_sv2::transport::{NoiseResponderTransport, PlainTransport};
+use roles_logic_sv2::utils::Mutex;
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::task;
+use tracing::{error, info};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ListenerConfig {
+    pub address: String,
+    /// False to accept connections without noise, only allowed on loopback and private addresses
+    #[serde(default = "default_noise")]
+    pub noise: bool,
+    /// False to let the downstreams of this listener open channels without being accepted by
+    /// `[auth]`, e.g. for the proxies of the pool operator
+    #[serde(default = "default_auth_required")]
+    pub auth_required: bool,
+    /// Channels per connection, overrides `rate_limits.max_channels_per_connection`
+    pub max_channels: Option<u32>,
+}
+
+fn default_auth_required() -> bool {
+    true
+}
+
+/// Policies of the downstreams accepted by a listener
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenerPolicy {
+    pub auth_required: bool,
+    pub max_channels: Option<u32>,
+}
+
+impl Default for ListenerPolicy {
+    fn default() -> Self {
+        Self {
+            auth_required: true,
+            max_channels: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listener {
+    pub address: String,
+    pub noise: bool,
+    pub policy: ListenerPolicy,
+}
+
+impl From<&ListenerConfig> for Listener {
+    fn from(config: &ListenerConfig) -> Self {
+        Self {
+            address: config.address.clone(),
+            noise: config.noise,
+            policy: ListenerPolicy {
+                auth_required: config.auth_required,
+                max_channels: config.max_channels,
+            },
+        }
+    }
+}
+
+/// Starts a task accepting the downstream connections for each listener of the config
+pub struct ListenerManager {
+    listeners: Vec<Listener>,
+}
+
+impl ListenerManager {
+    /// `listen_address` first, then `listeners`
+    pub fn from_config(config: &Configuration) -> Self {
+        let mut listeners = vec![Listener {
+            address: config.listen_address.clone(),
+            noise: true,
+            policy: ListenerPolicy::default(),
+        }];
+        #[cfg(feature = "test_only_allow_unencrypted")]
+        listeners.push(Listener {
+            address: config.test_only_listen_adress_plain.clone(),
+            noise: false,
+            policy: ListenerPolicy::default(),
+        });
+        listeners.extend(config.listeners.iter().map(Listener::from));
+        Self { listeners }
+    }
+
+    /// The pool shuts down if a listener stops accepting connections
+    pub fn start(self, pool: Arc<Mutex<Pool>>, config: &Configuration, status_tx: status::Sender) {
+        for listener in self.listeners {
+            let pool = pool.clone();
+            let status_tx = status_tx.clone();
+            let options = config.connection_options();
+            let noise = NoiseResponderTransport {
+                authority_public_key: config.authority_public_key.into_bytes(),
+                authority_secret_key: config.authority_secret_key.into_bytes(),
+                cert_validity: Duration::from_secs(config.cert_validity_sec),
+                options,
+            };
+            task::spawn(async move {
+                info!(
+                    "Starting up pool listener on {} (noise: {}, {:?})",
+                    listener.address, listener.noise, listener.policy
+                );
+                let address = listener.address.clone();
+                let res = match listener.noise {
+                    true => {
+                        Pool::accept_incoming_connection(pool, address, noise, listener.policy)
+                            .await
+                    }
+                    false => {
+                        let transport = PlainTransport { options };
+                        Pool::accept_incoming_connection(pool, address, transport, listener.policy)
+                            .await
+                    }
+                };
+                if let Err(e) = res {
+                    error!("{}", e);
+                }
+                if status_tx
+                    .send(status::Status {
+                        state: status::State::DownstreamShutdown(PoolError::ComponentShutdown(
+                            format!(
+                                "Downstream no longer accepting incoming connections on {}",
+                                listener.address
+                            ),
+                        )),
+                    })
+                    .await
+                    .is_err()
+                {
+                    error!("Downstream shutdown and Status Channel dropped");
+                }
+            });
+        }
+    }
+}
+
+/// Plaintext listeners must not be reachable from the internet
+fn is_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        // fc00::/7 are the unique local addresses
+        IpAddr::V6(ip) => ip.is_loopback() || (ip.segments()[0] & 0xfe00) == 0xfc00,
+    }
+}
+
+pub fn validate_listeners(v: &mut Validator, listeners: &[ListenerConfig]) {
+    let mut addresses = HashSet::new();
+    for (i, listener) in listeners.iter().enumerate() {
+        let field = format!("listeners[{}].address", i);
+        v.socket_address(&field, &listener.address);
+        v.check(
+            addresses.insert(listener.address.as_str()),
+            format!("{}: `{}` is used twice", field, listener.address),
+        );
+        if !listener.noise {
+            let local = matches!(listener.address.parse::<SocketAddr>(), Ok(a) if is_local(a.ip()));
+            v.check(
+                local,
+                format!(
+                    "{}: noise can only be disabled on loopback and private addresses",
+                    field
+                ),
+            );
+        }
+        if let Some(max_channels) = listener.max_channels {
+            v.check(
+                max_channels > 0,
+                format!("listeners[{}].max_channels must be greater than 0", i),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    fn listener(address: &str, noise: bool, max_channels: Option<u32>) -> ListenerConfig {
+        ListenerConfig {
+            address: address.to_string(),
+            noise,
+            auth_required: true,
+            max_channels,
+        }
+    }
+
+    #[test]
+    fn test_validate_listeners() {
+        let mut v = Validator::default();
+        validate_listeners(
+            &mut v,
+            &[
+                listener("0.0.0.0:34255", true, Some(10)),
+                listener("127.0.0.1:34256", false, None),
+                listener("10.0.0.2:34256", false, None),
+                listener("[fd00::1]:34256", false, None),
+            ],
+        );
+        assert!(v.finish().is_ok());
+
+        let mut v = Validator::default();
+        validate_listeners(
+            &mut v,
+            &[
+                listener("0.0.0.0:34255", false, None),
+                listener("0.0.0.0:34255", true, Some(0)),
+                listener("pool.example.com:34256", false, None),
+            ],
+        );
+        let errors = match v.finish() {
+            Err(config_helpers_sv2::ConfigError::Invalid(errors)) => errors,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(errors.len(), 4, "{:?}", errors);
+        assert!(errors[0].contains("noise"));
+        assert!(errors[1].contains("used twice"));
+        assert!(errors[2].contains("max_channels"));
+        assert!(errors[3].contains("noise"));
+    }
+
+    #[test]
+    fn test_listeners_from_config() {
+        let config: Configuration = config_helpers_sv2::load(
+            Path::new("./config-examples/pool-config-local-tp-example.toml"),
+            "POOL",
+        )
+        .unwrap();
+        let config = Configuration {
+            listeners: vec![ListenerConfig {
+                address: "10.0.0.2:34256".to_string(),
+                noise: false,
+                auth_required: false,
+                max_channels: Some(1000),
+            }],
+            ..config
+        };
+        let listeners = ListenerManager::from_config(&config).listeners;
+        assert_eq!(listeners[0].address, config.listen_address);
+        assert!(listeners[0].noise);
+        assert_eq!(listeners[0].policy, ListenerPolicy::default());
+        let last = listeners.last().unwrap();
+        assert!(!last.noise);
+        assert_eq!(
+            last.policy,
+            ListenerPolicy {
+                auth_required: false,
+                max_channels: Some(1000),
+            }
+        );
+    }
+}
@@ -1,16 +1,18 @@
-use super::super::mining_pool::Downstream;
+use super::super::{mining_pool::Downstream, stats::ShareOutcome};
 use roles_logic_sv2::{
+    channel_logic::channel_factory::{OnNewShare, PoolChannelFactory},
     errors::Error,
     handlers::mining::{ParseDownstreamMiningMessages, SendTo, SupportedChannelTypes},
     mining_sv2::*,
     parsers::Mining,
+    protocol_errors::IntoProtocolError,
     routing_logic::NoRouting,
     selectors::NullDownstreamMiningSelector,
     template_distribution_sv2::SubmitSolution,
     utils::Mutex,
 };
 use std::{convert::TryInto, sync::Arc};
-use tracing::error;
+use tracing::{error, info, info_span, warn};
 
 impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting> for Downstream {
     fn get_channel_type(&self) -> SupportedChannelTypes {
@@ -29,12 +31,29 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
         Ok(false)
     }
 
+    #[cfg(not(feature = "MG_reject_auth"))]
+    fn is_downstream_authorized(
+        self_mutex: Arc<Mutex<Self>>,
+        user_identity: &binary_sv2::Str0255,
+    ) -> Result<bool, Error> {
+        self_mutex
+            .safe_lock(|d| d.is_authorized(user_identity))
+            .map_err(|e| Error::PoisonLock(e.to_string()))?
+    }
+
     fn handle_open_standard_mining_channel(
         &mut self,
         incoming: OpenStandardMiningChannel,
         _m: Option<Arc<Mutex<()>>>,
     ) -> Result<SendTo<()>, Error> {
+        if let Some(error) = self.check_open_channel_request(incoming.request_id.as_u32())? {
+            return Ok(SendTo::Respond(error));
+        }
         let header_only = self.downstream_data.header_only;
+        let user_identity = std::str::from_utf8(incoming.user_identity.as_ref())
+            .unwrap_or_default()
+            .to_string();
+        let _span = info_span!("open_channel", user = %user_identity).entered();
         let reposnses = self
             .channel_factory
             .safe_lock(|factory| {
@@ -54,9 +73,33 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
                     Err(e) => Err(e),
                 }
             })
-            .map_err(|e| roles_logic_sv2::Error::PoisonLock(e.to_string()))??;
+            .map_err(|e| roles_logic_sv2::Error::PoisonLock(e.to_string()))?;
+        let reposnses = match reposnses {
+            Ok(reposnses) => reposnses,
+            Err(e) => {
+                warn!("Failed to open standard channel: {}", e);
+                self.on_failed_channel_request()?;
+                let error = e.open_mining_channel_error(incoming.request_id.as_u32());
+                return Ok(SendTo::Respond(Mining::OpenMiningChannelError(error)));
+            }
+        };
+        self.on_open_channel_responses(&reposnses)?;
         let mut result = vec![];
         for response in reposnses {
+            if let Mining::OpenStandardMiningChannelSuccess(success) = &response {
+                info!(channel_id = success.channel_id, "Standard channel opened");
+                self.diagnostics
+                    .open_channel(success.channel_id, Some(&user_identity));
+                self.stats
+                    .safe_lock(|s| {
+                        s.open_channel(
+                            success.channel_id,
+                            &user_identity,
+                            success.target.inner_as_ref(),
+                        )
+                    })
+                    .map_err(|e| roles_logic_sv2::Error::PoisonLock(e.to_string()))?;
+            }
             result.push(SendTo::Respond(response.into_static()))
         }
         Ok(SendTo::Multiple(result))
@@ -67,18 +110,47 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
         m: OpenExtendedMiningChannel,
     ) -> Result<SendTo<()>, Error> {
         let request_id = m.request_id;
+        if let Some(error) = self.check_open_channel_request(request_id)? {
+            return Ok(SendTo::Respond(error));
+        }
         let hash_rate = m.nominal_hash_rate;
         let min_extranonce_size = m.min_extranonce_size;
+        let user_identity = std::str::from_utf8(m.user_identity.as_ref())
+            .unwrap_or_default()
+            .to_string();
+        let _span = info_span!("open_channel", user = %user_identity).entered();
         let messages_res = self
             .channel_factory
             .safe_lock(|s| s.new_extended_channel(request_id, hash_rate, min_extranonce_size))
             .map_err(|e| roles_logic_sv2::Error::PoisonLock(e.to_string()))?;
         match messages_res {
             Ok(messages) => {
+                self.on_open_channel_responses(&messages)?;
+                for message in &messages {
+                    if let Mining::OpenExtendedMiningChannelSuccess(success) = message {
+                        info!(channel_id = success.channel_id, "Extended channel opened");
+                        self.diagnostics
+                            .open_channel(success.channel_id, Some(&user_identity));
+                        self.stats
+                            .safe_lock(|s| {
+                                s.open_channel(
+                                    success.channel_id,
+                                    &user_identity,
+                                    success.target.inner_as_ref(),
+                                )
+                            })
+                            .map_err(|e| roles_logic_sv2::Error::PoisonLock(e.to_string()))?;
+                    }
+                }
                 let messages = messages.into_iter().map(SendTo::Respond).collect();
                 Ok(SendTo::Multiple(messages))
             }
-            Err(_) => Err(roles_logic_sv2::Error::ChannelIsNeitherExtendedNeitherInAPool),
+            Err(e) => {
+                warn!("Failed to open extended channel: {}", e);
+                self.on_failed_channel_request()?;
+                let error = e.open_mining_channel_error(request_id);
+                Ok(SendTo::Respond(Mining::OpenMiningChannelError(error)))
+            }
         }
     }
 
@@ -90,10 +162,10 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
             .unwrap_or_else(|_| {
                 std::process::exit(1);
             });
-        let set_target = SetTarget {
-            channel_id: m.channel_id,
-            maximum_target,
-        };
+        self.stats
+            .safe_lock(|s| s.set_target(m.channel_id, maximum_target.inner_as_ref()))
+            .map_err(|e| roles_logic_sv2::Error::PoisonLock(e.to_string()))?;
+        let set_target = SetTarget::new(m.channel_id, maximum_target);
         Ok(SendTo::Respond(Mining::SetTarget(set_target)))
     }
 
@@ -101,29 +173,39 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
         &mut self,
         m: SubmitSharesStandard,
     ) -> Result<SendTo<()>, Error> {
+        let _span = share_span(m.channel_id, m.sequence_number, m.job_id).entered();
         let res = self
             .channel_factory
-            .safe_lock(|cf| cf.on_submit_shares_standard(m.clone()))
+            .safe_lock(|cf| {
+                let res = cf.on_submit_shares_standard(m.clone());
+                let solution_error = check_solution(cf, &res);
+                (res, cf.last_share_hash(), solution_error)
+            })
             .map_err(|e| roles_logic_sv2::Error::PoisonLock(e.to_string()))?;
+        let (res, hash, solution_error) = res;
+        let (sequence_number, job_id) = (m.sequence_number, m.job_id);
         match res {
             Ok(res) => match res  {
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::SendErrorDownstream(m) => {
+                    self.record_share(m.channel_id, sequence_number, job_id, ShareOutcome::from_error(&m), None, false);
                     Ok(SendTo::Respond(Mining::SubmitSharesError(m)))
                 }
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::SendSubmitShareUpstream(_) => unreachable!(),
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::RelaySubmitShareUpstream => unreachable!(),
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::ShareMeetBitcoinTarget((share,t_id,coinbase,_)) => {
+                    if let Some(e) = solution_error {
+                        error!("Share meets the bitcoin target but its header can not be rebuilt: {}", e);
+                    }
                     if let Some(template_id) = t_id {
-                        let solution = SubmitSolution {
+                        self.submit_solution(SubmitSolution {
                             template_id,
                             version: share.get_version(),
                             header_timestamp: share.get_n_time(),
                             header_nonce: share.get_nonce(),
                             coinbase_tx: coinbase.try_into()?,
-                        };
-                        // TODO we can block everything with the below (looks like this will infinite loop??)
-                        while self.solution_sender.try_send(solution.clone()).is_err() {};
+                        });
                     }
+                    self.record_share(m.channel_id, sequence_number, job_id, ShareOutcome::Accepted, hash, true);
                     let success = SubmitSharesSuccess {
                         channel_id: m.channel_id,
                         last_sequence_number: m.sequence_number,
@@ -135,6 +217,7 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
 
                 },
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::ShareMeetDownstreamTarget => {
+                    self.record_share(m.channel_id, sequence_number, job_id, ShareOutcome::Accepted, hash, false);
                  let success = SubmitSharesSuccess {
                         channel_id: m.channel_id,
                         last_sequence_number: m.sequence_number,
@@ -144,7 +227,17 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
                     Ok(SendTo::Respond(Mining::SubmitSharesSuccess(success)))
                 },
             },
-            Err(_) => todo!(),
+            Err(Error::ShareDoNotMatchAnyChannel) => {
+                let error = invalid_channel_error(m.channel_id, sequence_number);
+                self.record_share(m.channel_id, sequence_number, job_id, ShareOutcome::from_error(&error), None, false);
+                Ok(SendTo::Respond(Mining::SubmitSharesError(error)))
+            }
+            Err(e) => {
+                warn!("Share rejected: {}", e);
+                let error = e.submit_shares_error(m.channel_id, sequence_number);
+                self.record_share(m.channel_id, sequence_number, job_id, ShareOutcome::from_error(&error), None, false);
+                Ok(SendTo::Respond(Mining::SubmitSharesError(error)))
+            }
         }
     }
 
@@ -152,29 +245,39 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
         &mut self,
         m: SubmitSharesExtended,
     ) -> Result<SendTo<()>, Error> {
+        let _span = share_span(m.channel_id, m.sequence_number, m.job_id).entered();
         let res = self
             .channel_factory
-            .safe_lock(|cf| cf.on_submit_shares_extended(m.clone()))
+            .safe_lock(|cf| {
+                let res = cf.on_submit_shares_extended(m.clone());
+                let solution_error = check_solution(cf, &res);
+                (res, cf.last_share_hash(), solution_error)
+            })
             .map_err(|e| roles_logic_sv2::Error::PoisonLock(e.to_string()))?;
+        let (res, hash, solution_error) = res;
+        let (sequence_number, job_id) = (m.sequence_number, m.job_id);
         match res {
             Ok(res) => match res  {
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::SendErrorDownstream(m) => {
+                    self.record_share(m.channel_id, sequence_number, job_id, ShareOutcome::from_error(&m), None, false);
                     Ok(SendTo::Respond(Mining::SubmitSharesError(m)))
                 }
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::SendSubmitShareUpstream(_) => unreachable!(),
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::RelaySubmitShareUpstream => unreachable!(),
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::ShareMeetBitcoinTarget((share,t_id,coinbase,_)) => {
+                    if let Some(e) = solution_error {
+                        error!("Share meets the bitcoin target but its header can not be rebuilt: {}", e);
+                    }
                     if let Some(template_id) = t_id {
-                        let solution = SubmitSolution {
+                        self.submit_solution(SubmitSolution {
                             template_id,
                             version: share.get_version(),
                             header_timestamp: share.get_n_time(),
                             header_nonce: share.get_nonce(),
                             coinbase_tx: coinbase.try_into()?,
-                        };
-                        // TODO we can block everything with the below (looks like this will infinite loop??)
-                        while self.solution_sender.try_send(solution.clone()).is_err() {};
+                        });
                     }
+                    self.record_share(m.channel_id, sequence_number, job_id, ShareOutcome::Accepted, hash, true);
                     let success = SubmitSharesSuccess {
                         channel_id: m.channel_id,
                         last_sequence_number: m.sequence_number,
@@ -186,6 +289,7 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
 
                 },
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::ShareMeetDownstreamTarget => {
+                    self.record_share(m.channel_id, sequence_number, job_id, ShareOutcome::Accepted, hash, false);
                 let success = SubmitSharesSuccess {
                         channel_id: m.channel_id,
                         last_sequence_number: m.sequence_number,
@@ -195,22 +299,59 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
                     Ok(SendTo::Respond(Mining::SubmitSharesSuccess(success)))
                 },
             },
+            Err(Error::ShareDoNotMatchAnyChannel) => {
+                let error = invalid_channel_error(m.channel_id, sequence_number);
+                self.record_share(m.channel_id, sequence_number, job_id, ShareOutcome::from_error(&error), None, false);
+                Ok(SendTo::Respond(Mining::SubmitSharesError(error)))
+            }
             Err(e) => {
-                error!("{:?}",e);
-                todo!();
+                warn!("Share rejected: {}", e);
+                let error = e.submit_shares_error(m.channel_id, sequence_number);
+                self.record_share(m.channel_id, sequence_number, job_id, ShareOutcome::from_error(&error), None, false);
+                Ok(SendTo::Respond(Mining::SubmitSharesError(error)))
             }
         }
     }
 
     fn handle_set_custom_mining_job(&mut self, m: SetCustomMiningJob) -> Result<SendTo<()>, Error> {
-        let m = SetCustomMiningJobSuccess {
-            channel_id: m.channel_id,
-            request_id: m.request_id,
-            job_id: self
-                .channel_factory
-                .safe_lock(|cf| cf.on_new_set_custom_mining_job(m.into_static()).job_id)
-                .unwrap(),
-        };
-        Ok(SendTo::Respond(Mining::SetCustomMiningJobSuccess(m)))
+        let result = self
+            .channel_factory
+            .safe_lock(|cf| cf.on_new_set_custom_mining_job(m.into_static()))
+            .map_err(|e| roles_logic_sv2::Error::PoisonLock(e.to_string()))?;
+        match result {
+            Ok(success) => Ok(SendTo::Respond(Mining::SetCustomMiningJobSuccess(success))),
+            Err(error) => Ok(SendTo::Respond(Mining::SetCustomMiningJobError(error))),
+        }
+    }
+}
+
+/// Span of the handling of a share, its events are also in the span of the downstream
+fn share_span(channel_id: u32, sequence_number: u32, job_id: u32) -> tracing::Span {
+    info_span!("share", channel_id, sequence_number, job_id)
+}
+
+/// Response to a share sent on a channel that is not open
+fn invalid_channel_error(channel_id: u32, sequence_number: u32) -> SubmitSharesError<'static> {
+    SubmitSharesError {
+        channel_id,
+        sequence_number,
+        // Infallible unwrap we already know the len of the error code (is a static string)
+        error_code: SubmitSharesError::invalid_channel_error_code()
+            .to_string()
+            .try_into()
+            .unwrap(),
+    }
+}
+
+/// Rebuilds the header of a share that met the bitcoin target while the channel factory is still
+/// locked, from the job and the prev hash the share has been checked against. It is only a
+/// diagnostic: the factory already decided that the share is a block, so the solution is submitted
+/// even if the header can not be rebuilt.
+fn check_solution(cf: &PoolChannelFactory, res: &Result<OnNewShare, Error>) -> Option<Error> {
+    match res {
+        Ok(OnNewShare::ShareMeetBitcoinTarget((share, Some(template_id), _, extranonce))) => {
+            cf.submit_solution(share, extranonce, *template_id).err()
+        }
+        _ => None,
     }
 }
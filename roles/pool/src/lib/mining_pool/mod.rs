@@ -1,49 +1,89 @@
 use super::{
+    auth::{AuthConfig, AuthProvider},
     error::{PoolError, PoolResult},
+    maintenance::{self, MaintenanceConfig},
+    rate_limit::{self, MessageRate, RateLimitConfig, RateLimiter},
+    share_log::{ShareLogConfig, ShareLogger, ShareRecord},
+    stats::{
+        target_to_difficulty, PoolStats, ShareOutcome, StatsConfig, StatsSnapshot,
+        HASHRATE_WINDOW_10M,
+    },
     status,
+    sv1_listener::Sv1ListenerConfig,
 };
 use async_channel::{Receiver, Sender};
-use binary_sv2::U256;
-use codec_sv2::{HandshakeRole, Responder, StandardEitherFrame, StandardSv2Frame};
+use binary_sv2::Str0255;
+use codec_sv2::{StandardEitherFrame, StandardSv2Frame};
+use config_helpers_sv2::{Validate, Validator};
+use const_sv2::{
+    MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL, MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL,
+};
 use error_handling::handle_result;
-use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey, SignatureService};
-use network_helpers_sv2::noise_connection_tokio::Connection;
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use network_helpers_sv2::{
+    backpressure::{self, ConnectionOptions, DEFAULT_CHANNEL_CAPACITY},
+    keepalive::KeepaliveConfig,
+    transport::Sv2Transport,
+};
 use nohash_hasher::BuildNoHashHasher;
 use roles_logic_sv2::{
-    channel_logic::channel_factory::PoolChannelFactory,
+    channel_logic::{
+        channel_factory::PoolChannelFactory,
+        template_pipeline::{JobBatch, TemplateToJobPipeline},
+    },
+    common_messages_sv2::{Endpoint, EndpointMigration},
     common_properties::{CommonDownstreamData, IsDownstream, IsMiningDownstream},
     errors::Error,
     handlers::mining::{ParseDownstreamMiningMessages, SendTo},
     job_creator::JobsCreators,
-    mining_sv2::{ExtendedExtranonce, SetNewPrevHash as SetNPH},
-    parsers::{Mining, PoolMessages},
+    mining_sv2::{ExtendedExtranonce, OpenMiningChannelError, Reconnect, SetTarget},
+    parsers::{CommonMessages, Mining, PoolMessages},
     routing_logic::MiningRoutingLogic,
+    share_validation::{NtimeLimits, DEFAULT_NTIME_FUTURE_TOLERANCE},
     template_distribution_sv2::{NewTemplate, SetNewPrevHash, SubmitSolution},
     utils::{CoinbaseOutput as CoinbaseOutput_, Mutex},
 };
 use serde::Deserialize;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     convert::{TryFrom, TryInto},
     net::SocketAddr,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
-use stratum_common::{
-    bitcoin::{Script, TxOut},
-    secp256k1,
+use stratum_common::bitcoin::{Script, TxOut};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    task,
 };
-use tokio::{net::TcpListener, task};
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
 pub mod setup_connection;
 use setup_connection::SetupConnectionHandler;
 
+pub mod listener;
+use listener::{ListenerConfig, ListenerManager, ListenerPolicy};
+
 pub mod message_handler;
 
 pub type Message = PoolMessages<'static>;
 pub type StdFrame = StandardSv2Frame<Message>;
 pub type EitherFrame = StandardEitherFrame<Message>;
 
+/// A channel is retargeted only if the difficulty changes by more than this fraction
+const RETARGET_MIN_CHANGE: f64 = 0.2;
+
+/// Block found by a downstream, with the time the share that solves the block was received
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub message: SubmitSolution<'static>,
+    pub share_received_at: Instant,
+}
+
 pub fn get_coinbase_output(config: &Configuration) -> Result<Vec<TxOut>, Error> {
     let mut result = Vec::new();
     for coinbase_output_pool in &config.coinbase_outputs {
@@ -94,20 +134,85 @@ impl TryFrom<&CoinbaseOutput> for CoinbaseOutput_ {
 #[derive(Debug, Deserialize, Clone)]
 pub struct Configuration {
     pub listen_address: String,
+    /// Endpoints on which the pool also listens, with their own policies
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+    /// `host:port`, or `unix:///path/to/socket` for a Template Provider on the same host
     pub tp_address: String,
     pub tp_authority_public_key: Option<Secp256k1PublicKey>,
+    /// False to connect to the Template Provider without noise, only allowed on Unix sockets
+    #[serde(default = "default_noise")]
+    pub tp_noise: bool,
     pub authority_public_key: Secp256k1PublicKey,
     pub authority_secret_key: Secp256k1SecretKey,
+    /// Authority public key of the JDS that signs the tokens of the custom jobs, the
+    /// `authority_public_key` of the pool if not set
+    pub jds_authority_public_key: Option<Secp256k1PublicKey>,
     pub cert_validity_sec: u64,
     pub coinbase_outputs: Vec<CoinbaseOutput>,
     pub pool_signature: String,
     #[cfg(feature = "test_only_allow_unencrypted")]
     pub test_only_listen_adress_plain: String,
+    /// Template Providers used when `tp_address` is unreachable, in order of priority
+    #[serde(default)]
+    pub additional_template_providers: Vec<TemplateProviderConfig>,
+    /// How often the Template Providers with an higher priority than the connected one are
+    /// checked, and how often the connection is retried when none is reachable
+    #[serde(default = "default_tp_health_check_interval_sec")]
+    pub tp_health_check_interval_sec: u64,
+    /// Fail over when the Template Provider sends nothing for this long
+    pub tp_max_silence_sec: Option<u64>,
+    /// How often the config file is checked for changes, the coinbase outputs are reloaded when
+    /// it changes. They are also reloaded on SIGHUP.
+    pub config_watch_interval_sec: Option<u64>,
+    #[serde(default)]
+    pub rate_limits: RateLimitConfig,
+    #[serde(default)]
+    pub stats: StatsConfig,
+    /// Shares are not logged if not set
+    #[serde(default)]
+    pub share_log: Option<ShareLogConfig>,
+    /// Maintenance mode is not available if not set
+    #[serde(default)]
+    pub maintenance: Option<MaintenanceConfig>,
+    /// Every user can open channels if not set
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    /// SV1 miners are not accepted if not set
+    #[serde(default)]
+    pub sv1_listener: Option<Sv1ListenerConfig>,
+    /// Idle time before TCP keepalive probes are sent to the downstreams, off if not set
+    pub tcp_keepalive_sec: Option<u64>,
+    /// Downstreams that send nothing for this long are disconnected, it must be well above the
+    /// time between two shares of the slowest miner
+    pub liveness_timeout_sec: Option<u64>,
+    /// Frames buffered per downstream connection
+    #[serde(default = "default_channel_capacity")]
+    pub channel_capacity: usize,
+    /// Downstreams that do not read their frames for this long are disconnected, the pool waits
+    /// for them if not set
+    pub send_timeout_ms: Option<u64>,
+    /// Shares whose ntime is rolled more than this many seconds ahead of the min_ntime of the
+    /// last prev hash plus the time since it was received are rejected, the ntime of the shares
+    /// is not checked if not set
+    pub max_ntime_drift_sec: Option<u32>,
+    /// Shares whose ntime is more than this many seconds ahead of the local clock are rejected,
+    /// only used when `max_ntime_drift_sec` is set
+    #[serde(default = "default_ntime_future_tolerance_sec")]
+    pub ntime_future_tolerance_sec: u32,
+    /// Address where the metrics are served in the OpenMetrics text format, not served if not set
+    pub metrics_address: Option<String>,
+    /// Localhost address of the admin interface (log level, connections, channels and state
+    /// snapshot), not served if not set
+    pub admin_address: Option<String>,
 }
 
+#[derive(Debug, Deserialize, Clone)]
 pub struct TemplateProviderConfig {
-    address: String,
-    authority_public_key: Option<Secp256k1PublicKey>,
+    pub address: String,
+    pub authority_public_key: Option<Secp256k1PublicKey>,
+    #[serde(default = "default_noise")]
+    pub noise: bool,
 }
 
 impl TemplateProviderConfig {
@@ -115,6 +220,7 @@ impl TemplateProviderConfig {
         Self {
             address,
             authority_public_key,
+            noise: true,
         }
     }
 }
@@ -149,6 +255,22 @@ impl ConnectionConfig {
     }
 }
 
+fn default_tp_health_check_interval_sec() -> u64 {
+    30
+}
+
+fn default_noise() -> bool {
+    true
+}
+
+fn default_channel_capacity() -> usize {
+    DEFAULT_CHANNEL_CAPACITY
+}
+
+fn default_ntime_future_tolerance_sec() -> u32 {
+    DEFAULT_NTIME_FUTURE_TOLERANCE
+}
+
 impl Configuration {
     pub fn new(
         pool_connection: ConnectionConfig,
@@ -159,8 +281,10 @@ impl Configuration {
     ) -> Self {
         Self {
             listen_address: pool_connection.listen_address,
+            listeners: vec![],
             tp_address: template_provider.address,
             tp_authority_public_key: template_provider.authority_public_key,
+            tp_noise: template_provider.noise,
             authority_public_key: authority_config.public_key,
             authority_secret_key: authority_config.secret_key,
             cert_validity_sec: pool_connection.cert_validity_sec,
@@ -168,10 +292,146 @@ impl Configuration {
             pool_signature: pool_connection.signature,
             #[cfg(feature = "test_only_allow_unencrypted")]
             test_only_listen_adress_plain,
+            additional_template_providers: vec![],
+            tp_health_check_interval_sec: default_tp_health_check_interval_sec(),
+            tp_max_silence_sec: None,
+            config_watch_interval_sec: None,
+            rate_limits: RateLimitConfig::default(),
+            stats: StatsConfig::default(),
+            share_log: None,
+            maintenance: None,
+            auth: None,
+            sv1_listener: None,
+            tcp_keepalive_sec: None,
+            liveness_timeout_sec: None,
+            channel_capacity: default_channel_capacity(),
+            send_timeout_ms: None,
+            max_ntime_drift_sec: None,
+            ntime_future_tolerance_sec: default_ntime_future_tolerance_sec(),
+            metrics_address: None,
+            admin_address: None,
+        }
+    }
+
+    /// Template Providers in order of priority
+    pub fn template_providers(&self) -> Vec<TemplateProviderConfig> {
+        let mut providers = vec![TemplateProviderConfig {
+            address: self.tp_address.clone(),
+            authority_public_key: self.tp_authority_public_key,
+            noise: self.tp_noise,
+        }];
+        providers.extend(self.additional_template_providers.iter().cloned());
+        providers
+    }
+
+    /// Limits of the ntime of the shares, None if it is not checked
+    pub fn ntime_limits(&self) -> Option<NtimeLimits> {
+        self.max_ntime_drift_sec
+            .map(|drift| NtimeLimits::new(drift, self.ntime_future_tolerance_sec))
+    }
+
+    /// Dead peer detection of the downstream connections
+    pub fn keepalive(&self) -> KeepaliveConfig {
+        KeepaliveConfig {
+            tcp_keepalive: self.tcp_keepalive_sec.map(Duration::from_secs),
+            liveness_timeout: self.liveness_timeout_sec.map(Duration::from_secs),
+        }
+    }
+
+    /// Configuration of the downstream connections
+    pub fn connection_options(&self) -> ConnectionOptions {
+        ConnectionOptions {
+            keepalive: self.keepalive(),
+            channel_capacity: self.channel_capacity,
+            ..Default::default()
         }
     }
 }
 
+impl Validate for Configuration {
+    fn validate(&self, v: &mut Validator) {
+        v.socket_address("listen_address", &self.listen_address);
+        listener::validate_listeners(v, &self.listeners);
+        validate_tp_address(v, "tp_address", &self.tp_address, self.tp_noise);
+        v.exclusive(&[
+            ("tp_noise = false", !self.tp_noise),
+            (
+                "tp_authority_public_key",
+                self.tp_authority_public_key.is_some(),
+            ),
+        ]);
+        for (i, tp) in self.additional_template_providers.iter().enumerate() {
+            let field = format!("additional_template_providers[{}].address", i);
+            validate_tp_address(v, &field, &tp.address, tp.noise);
+        }
+        v.keypair(
+            ("authority_public_key", &self.authority_public_key),
+            ("authority_secret_key", &self.authority_secret_key),
+        );
+        v.check(
+            self.cert_validity_sec > 0,
+            "cert_validity_sec must be greater than 0",
+        );
+        if let Err(e) = get_coinbase_output(self) {
+            v.check(false, format!("coinbase_outputs: {}", e));
+        }
+        v.check(
+            self.tp_health_check_interval_sec > 0,
+            "tp_health_check_interval_sec must be greater than 0",
+        );
+        v.check(
+            self.channel_capacity > 0,
+            "channel_capacity must be greater than 0",
+        );
+        if let Some(http_address) = &self.stats.http_address {
+            v.socket_address("stats.http_address", http_address);
+        }
+        if let Some(metrics_address) = &self.metrics_address {
+            v.socket_address("metrics_address", metrics_address);
+        }
+        if let Some(admin_address) = &self.admin_address {
+            v.socket_address("admin_address", admin_address);
+        }
+        if let Some(sv1_listener) = &self.sv1_listener {
+            v.socket_address("sv1_listener.listen_address", &sv1_listener.listen_address);
+            if let Some(pool_address) = &sv1_listener.pool_address {
+                v.socket_address("sv1_listener.pool_address", pool_address);
+            }
+            v.check(
+                sv1_listener.min_individual_miner_hashrate > 0.0,
+                "sv1_listener.min_individual_miner_hashrate must be greater than 0",
+            );
+            v.check(
+                sv1_listener.shares_per_minute > 0.0,
+                "sv1_listener.shares_per_minute must be greater than 0",
+            );
+            v.range(
+                "sv1_listener.min_extranonce2_size",
+                sv1_listener.min_extranonce2_size,
+                1,
+                32,
+            );
+        }
+        #[cfg(feature = "test_only_allow_unencrypted")]
+        v.socket_address(
+            "test_only_listen_adress_plain",
+            &self.test_only_listen_adress_plain,
+        );
+    }
+}
+
+/// Noise can only be disabled on Unix sockets
+fn validate_tp_address(v: &mut Validator, field: &str, address: &str, noise: bool) {
+    if address.starts_with("unix://") {
+        return;
+    }
+    v.socket_address(field, address);
+    v.check(
+        noise,
+        format!("{}: noise can only be disabled on unix:// addresses", field),
+    );
+}
+
 #[derive(Debug)]
 pub struct Downstream {
     // Either group or channel id
@@ -179,18 +439,47 @@ pub struct Downstream {
     receiver: Receiver<EitherFrame>,
     sender: Sender<EitherFrame>,
     downstream_data: CommonDownstreamData,
-    solution_sender: Sender<SubmitSolution<'static>>,
+    solution_sender: Sender<Solution>,
     channel_factory: Arc<Mutex<PoolChannelFactory>>,
+    stats: Arc<Mutex<PoolStats>>,
+    share_logger: Option<ShareLogger>,
+    address: SocketAddr,
+    rate_limiter: Arc<Mutex<RateLimiter>>,
+    message_rate: Option<MessageRate>,
+    // channels opened on this connection
+    channels: Vec<u32>,
+    auth_provider: Option<Arc<dyn AuthProvider>>,
+    // users accepted by `auth_provider`
+    authorized_users: HashSet<String>,
+    // set when the pool is in maintenance, see `maintenance`
+    maintenance: Arc<AtomicBool>,
+    // the downstream is disconnected if a frame can not be sent within this time
+    send_timeout: Option<Duration>,
+    // policies of the listener that accepted the connection
+    policy: ListenerPolicy,
+    // when the message being handled has been received
+    message_received_at: Instant,
+    // set when the downstream went over a channel request limit, it is disconnected once the
+    // `OpenMiningChannel.Error` has been sent
+    rate_limited: bool,
+    // the connection and its channels in the admin interface
+    diagnostics: admin_sv2::Connection,
 }
 
 /// Accept downstream connection
 pub struct Pool {
     downstreams: HashMap<u32, Arc<Mutex<Downstream>>, BuildNoHashHasher<u32>>,
-    solution_sender: Sender<SubmitSolution<'static>>,
+    solution_sender: Sender<Solution>,
     new_template_processed: bool,
     channel_factory: Arc<Mutex<PoolChannelFactory>>,
     last_prev_hash_template_id: u64,
     status_tx: status::Sender,
+    stats: Arc<Mutex<PoolStats>>,
+    share_logger: Option<ShareLogger>,
+    rate_limiter: Arc<Mutex<RateLimiter>>,
+    auth_provider: Option<Arc<dyn AuthProvider>>,
+    maintenance: Arc<AtomicBool>,
+    send_timeout: Option<Duration>,
 }
 
 impl Downstream {
@@ -198,11 +487,18 @@ impl Downstream {
     pub async fn new(
         mut receiver: Receiver<EitherFrame>,
         mut sender: Sender<EitherFrame>,
-        solution_sender: Sender<SubmitSolution<'static>>,
+        solution_sender: Sender<Solution>,
         pool: Arc<Mutex<Pool>>,
         channel_factory: Arc<Mutex<PoolChannelFactory>>,
         status_tx: status::Sender,
         address: SocketAddr,
+        stats: Arc<Mutex<PoolStats>>,
+        share_logger: Option<ShareLogger>,
+        rate_limiter: Arc<Mutex<RateLimiter>>,
+        auth_provider: Option<Arc<dyn AuthProvider>>,
+        maintenance: Arc<AtomicBool>,
+        send_timeout: Option<Duration>,
+        policy: ListenerPolicy,
     ) -> PoolResult<Arc<Mutex<Self>>> {
         let setup_connection = Arc::new(Mutex::new(SetupConnectionHandler::new()));
         let downstream_data =
@@ -214,6 +510,8 @@ impl Downstream {
             true => channel_factory.safe_lock(|c| c.new_standard_id_for_hom())?,
         };
 
+        let message_rate = rate_limiter.safe_lock(|r| r.message_rate())?;
+
         let self_ = Arc::new(Mutex::new(Downstream {
             id,
             receiver,
@@ -221,11 +519,25 @@ impl Downstream {
             downstream_data,
             solution_sender,
             channel_factory,
+            stats,
+            share_logger,
+            address,
+            rate_limiter,
+            message_rate,
+            channels: vec![],
+            auth_provider,
+            authorized_users: HashSet::new(),
+            maintenance,
+            send_timeout,
+            policy,
+            message_received_at: Instant::now(),
+            rate_limited: false,
+            diagnostics: admin_sv2::diagnostics().open_connection(address, "sv2 downstream"),
         }));
 
         let cloned = self_.clone();
 
-        task::spawn(async move {
+        let receiver_task = async move {
             debug!("Starting up downstream receiver");
             let receiver_res = cloned
                 .safe_lock(|d| d.receiver.clone())
@@ -271,11 +583,19 @@ impl Downstream {
                 }
             }
             warn!("Downstream connection dropped");
-        });
+        };
+        task::spawn(receiver_task.instrument(info_span!("downstream", id, addr = %address)));
         Ok(self_)
     }
 
     pub async fn next(self_mutex: Arc<Mutex<Self>>, mut incoming: StdFrame) -> PoolResult<()> {
+        let (id, allowed) = self_mutex.safe_lock(|d| {
+            d.message_received_at = Instant::now();
+            (d.id, d.check_message_rate())
+        })?;
+        if !(allowed?) {
+            return Err(PoolError::RateLimited(id));
+        }
         let message_type = incoming
             .get_header()
             .ok_or_else(|| PoolError::Custom(String::from("No header set")))?
@@ -285,13 +605,218 @@ impl Downstream {
             "Received downstream message type: {:?}, payload: {:?}",
             message_type, payload
         );
+        Self::authorize(&self_mutex, message_type, payload).await?;
         let next_message_to_send = ParseDownstreamMiningMessages::handle_message_mining(
             self_mutex.clone(),
             message_type,
             payload,
             MiningRoutingLogic::None,
         );
-        Self::match_send_to(self_mutex, next_message_to_send).await
+        Self::match_send_to(self_mutex.clone(), next_message_to_send).await?;
+        if self_mutex.safe_lock(|d| d.rate_limited)? {
+            return Err(PoolError::RateLimited(id));
+        }
+        Ok(())
+    }
+
+    /// Asks the auth provider if the user of an open channel request is known. The answer is
+    /// used by `is_downstream_authorized`, that rejects the request if the user is unknown.
+    async fn authorize(
+        self_mutex: &Arc<Mutex<Self>>,
+        message_type: u8,
+        payload: &mut [u8],
+    ) -> PoolResult<()> {
+        if message_type != MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL
+            && message_type != MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL
+        {
+            return Ok(());
+        }
+        let auth_provider =
+            match self_mutex.safe_lock(|d| (d.auth_provider.clone(), d.policy.auth_required))? {
+                (Some(auth_provider), true) => auth_provider,
+                _ => return Ok(()),
+            };
+        let user_identity = match (message_type, payload).try_into() {
+            Ok(Mining::OpenStandardMiningChannel(m)) => user_identity_to_string(&m.user_identity),
+            Ok(Mining::OpenExtendedMiningChannel(m)) => user_identity_to_string(&m.user_identity),
+            _ => return Ok(()),
+        };
+        if self_mutex.safe_lock(|d| d.authorized_users.contains(&user_identity))? {
+            return Ok(());
+        }
+        if auth_provider.authorize(&user_identity).await {
+            self_mutex.safe_lock(|d| d.authorized_users.insert(user_identity))?;
+        } else {
+            info!("Unknown user {}", user_identity);
+        }
+        Ok(())
+    }
+
+    /// Used by the handler of the open channel requests
+    fn is_authorized(&mut self, user_identity: &Str0255) -> Result<bool, Error> {
+        if self.auth_provider.is_none()
+            || !self.policy.auth_required
+            || self
+                .authorized_users
+                .contains(&user_identity_to_string(user_identity))
+        {
+            return Ok(true);
+        }
+        self.on_failed_channel_request()?;
+        Ok(false)
+    }
+
+    /// Returns false, and bans the downstream ip, if the downstream sent too many messages
+    #[allow(clippy::result_large_err)]
+    fn check_message_rate(&mut self) -> PoolResult<bool> {
+        let allowed = self
+            .message_rate
+            .as_mut()
+            .map(|rate| rate.on_message())
+            .unwrap_or(true);
+        if !allowed {
+            let ip = self.address.ip();
+            self.rate_limiter.safe_lock(|r| r.ban(ip))?;
+        }
+        Ok(allowed)
+    }
+
+    /// Returns the `OpenMiningChannel.Error` to send if the downstream can not open a new channel
+    fn check_open_channel_request(
+        &mut self,
+        request_id: u32,
+    ) -> Result<Option<Mining<'static>>, Error> {
+        let ip = self.address.ip();
+        let (allowed, max_channels) = self
+            .rate_limiter
+            .safe_lock(|r| {
+                (
+                    r.on_open_channel_request(ip),
+                    r.max_channels_per_connection(),
+                )
+            })
+            .map_err(|e| Error::PoisonLock(e.to_string()))?;
+        let max_channels = self.policy.max_channels.or(max_channels);
+        let error_code = if self.maintenance.load(Ordering::Relaxed) {
+            maintenance::MAINTENANCE
+        } else if !allowed {
+            self.rate_limited = true;
+            rate_limit::TOO_MANY_CHANNEL_REQUESTS
+        } else if matches!(max_channels, Some(max) if self.channels.len() >= max as usize) {
+            rate_limit::TOO_MANY_CHANNELS
+        } else {
+            return Ok(None);
+        };
+        warn!("Refusing channel request from {}: {}", ip, error_code);
+        Ok(Some(Mining::OpenMiningChannelError(
+            OpenMiningChannelError {
+                request_id,
+                error_code: error_code
+                    .to_string()
+                    .try_into()
+                    .map_err(Error::BinarySv2Error)?,
+            },
+        )))
+    }
+
+    /// Counts the channels opened and the failed channel requests of `responses`
+    fn on_open_channel_responses(&mut self, responses: &[Mining<'static>]) -> Result<(), Error> {
+        for response in responses {
+            match response {
+                Mining::OpenStandardMiningChannelSuccess(m) => {
+                    monitoring_sv2::metrics().channels_open.inc();
+                    self.channels.push(m.channel_id)
+                }
+                Mining::OpenExtendedMiningChannelSuccess(m) => {
+                    monitoring_sv2::metrics().channels_open.inc();
+                    self.channels.push(m.channel_id)
+                }
+                Mining::OpenMiningChannelError(_) => self.on_failed_channel_request()?,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts a failed channel request, the downstream is disconnected once the error is sent if
+    /// it failed too many requests
+    fn on_failed_channel_request(&mut self) -> Result<(), Error> {
+        let ip = self.address.ip();
+        let allowed = self
+            .rate_limiter
+            .safe_lock(|r| r.on_failed_channel_request(ip))
+            .map_err(|e| Error::PoisonLock(e.to_string()))?;
+        if !allowed {
+            self.rate_limited = true;
+        }
+        Ok(())
+    }
+
+    /// Hands the solution to the Template Provider receiver right away, without waiting for the
+    /// share accounting. The solution channel is unbounded so this never blocks the downstream.
+    fn submit_solution(&self, message: SubmitSolution<'static>) {
+        let solution = Solution {
+            message,
+            share_received_at: self.message_received_at,
+        };
+        if let Err(e) = self.solution_sender.try_send(solution) {
+            error!(
+                "Block found but the solution can not be sent to the TP: {}",
+                e
+            );
+        }
+    }
+
+    /// Records a share of `channel_id` in the pool statistics and in the share log
+    fn record_share(
+        &self,
+        channel_id: u32,
+        sequence_number: u32,
+        job_id: u32,
+        outcome: ShareOutcome,
+        hash: Option<[u8; 32]>,
+        block_found: bool,
+    ) {
+        let record = self.stats.safe_lock(|s| {
+            s.on_share(channel_id, outcome, hash);
+            let (user_identity, difficulty) = s.channel(channel_id)?;
+            Some(ShareRecord {
+                timestamp: ShareRecord::now_millis(),
+                channel_id,
+                user_identity: user_identity.to_string(),
+                sequence_number,
+                job_id,
+                difficulty,
+                share_difficulty: hash
+                    .map(|hash| target_to_difficulty(&hash))
+                    .unwrap_or(difficulty),
+                outcome,
+                block_found,
+            })
+        });
+        let metrics = monitoring_sv2::metrics();
+        match outcome {
+            ShareOutcome::Accepted => metrics.shares_accepted.inc(),
+            ShareOutcome::Rejected => metrics.shares_rejected.inc(),
+            ShareOutcome::Stale => metrics.shares_stale.inc(),
+        }
+        if let Ok(Some(record)) = &record {
+            if outcome == ShareOutcome::Accepted {
+                metrics.share_difficulty.observe(record.share_difficulty);
+            }
+            debug!(
+                user = %record.user_identity,
+                share_difficulty = record.share_difficulty,
+                ?outcome,
+                block_found,
+                "Share"
+            );
+        }
+        match (record, &self.share_logger) {
+            (Ok(Some(record)), Some(share_logger)) => share_logger.log(record),
+            (Ok(_), _) => (),
+            (Err(_), _) => error!("Stats mutex poisoned"),
+        }
     }
 
     #[async_recursion::async_recursion]
@@ -338,6 +863,20 @@ impl Downstream {
         Ok(())
     }
 
+    /// Asks the downstream to move to `endpoint`: a `ChannelEndpointChanged` is sent for each of
+    /// its channels, then a `Reconnect`
+    async fn migrate(self_mutex: Arc<Mutex<Self>>, endpoint: Endpoint) -> PoolResult<()> {
+        let channels = self_mutex.safe_lock(|d| d.channels.clone())?;
+        let migration = EndpointMigration::new(endpoint, channels);
+        for message in migration.channel_endpoint_changed_messages() {
+            let message = PoolMessages::Common(CommonMessages::ChannelEndpointChanged(message));
+            Self::send_message(self_mutex.clone(), message).await?;
+        }
+        let (new_host, new_port) = migration.endpoint().clone().into_reconnect_fields();
+        let reconnect = Mining::Reconnect(Reconnect { new_host, new_port });
+        Self::send(self_mutex, reconnect).await
+    }
+
     async fn send(
         self_mutex: Arc<Mutex<Self>>,
         message: roles_logic_sv2::parsers::Mining<'static>,
@@ -347,31 +886,39 @@ impl Downstream {
         //} else {
         //    message
         //};
-        let sv2_frame: StdFrame = PoolMessages::Mining(message).try_into()?;
-        let sender = self_mutex.safe_lock(|self_| self_.sender.clone())?;
-        sender.send(sv2_frame.into()).await?;
-        Ok(())
+        Self::send_message(self_mutex, PoolMessages::Mining(message)).await
     }
-}
-
-// Verifies token for a custom job which is the signed tx_hash_list_hash by Job Declarator Server
-//TODO: implement the use of this fuction in main.rs
-#[allow(dead_code)]
-pub fn verify_token(
-    tx_hash_list_hash: U256,
-    signature: secp256k1::schnorr::Signature,
-    pub_key: key_utils::Secp256k1PublicKey,
-) -> Result<(), secp256k1::Error> {
-    let message: Vec<u8> = tx_hash_list_hash.to_vec();
 
-    let secp = SignatureService::default();
-
-    let is_verified = secp.verify(tx_hash_list_hash.to_vec(), signature, pub_key.0);
+    async fn send_message(self_mutex: Arc<Mutex<Self>>, message: Message) -> PoolResult<()> {
+        let sv2_frame: StdFrame = message.try_into()?;
+        let (id, sender, send_timeout) =
+            self_mutex.safe_lock(|self_| (self_.id, self_.sender.clone(), self_.send_timeout))?;
+        let send_timeout = match send_timeout {
+            Some(send_timeout) => send_timeout,
+            None => {
+                sender.send(sv2_frame.into()).await?;
+                return Ok(());
+            }
+        };
+        match backpressure::send_timeout(&sender, sv2_frame.into(), send_timeout).await {
+            Ok(()) => Ok(()),
+            Err(network_helpers_sv2::Error::SendTimeout) => {
+                warn!("Downstream {} is too slow, disconnecting it", id);
+                // Closing the channels stops the connection and the downstream receiver loop
+                let receiver = self_mutex.safe_lock(|self_| self_.receiver.clone())?;
+                sender.close();
+                receiver.close();
+                Err(PoolError::SlowDownstream(id))
+            }
+            Err(e) => Err(PoolError::Custom(format!("{:?}", e))),
+        }
+    }
+}
 
-    // debug
-    debug!("Message: {}", std::str::from_utf8(&message).unwrap());
-    debug!("Verified signature {:?}", is_verified);
-    is_verified
+fn user_identity_to_string(user_identity: &Str0255) -> String {
+    std::str::from_utf8(user_identity.as_ref())
+        .unwrap_or_default()
+        .to_string()
 }
 
 impl IsDownstream for Downstream {
@@ -383,80 +930,55 @@ impl IsDownstream for Downstream {
 impl IsMiningDownstream for Downstream {}
 
 impl Pool {
-    #[cfg(feature = "test_only_allow_unencrypted")]
-    async fn accept_incoming_plain_connection(
+    async fn accept_incoming_connection<T: Sv2Transport>(
         self_: Arc<Mutex<Pool>>,
-        config: Configuration,
+        listen_address: String,
+        transport: T,
+        policy: ListenerPolicy,
     ) -> PoolResult<()> {
-        let listner = TcpListener::bind(&config.test_only_listen_adress_plain)
-            .await
-            .unwrap();
         let status_tx = self_.safe_lock(|s| s.status_tx.clone())?;
-
-        info!(
-            "Listening for unencrypted connection on: {}",
-            config.test_only_listen_adress_plain
-        );
-        while let Ok((stream, _)) = listner.accept().await {
-            let address = stream.peer_addr().unwrap();
+        let listener = TcpListener::bind(&listen_address).await?;
+        info!("Listening for connections on: {}", listen_address);
+        while let Ok((stream, address)) = listener.accept().await {
             debug!("New connection from {}", address);
-
-            let (receiver, sender): (Receiver<EitherFrame>, Sender<EitherFrame>) =
-                network_helpers_sv2::plain_connection_tokio::PlainConnection::new(stream).await;
-
-            handle_result!(
-                status_tx,
-                Self::accept_incoming_connection_(self_.clone(), receiver, sender, address).await
-            );
+            if handle_result!(status_tx, Self::is_refused(&self_, address)) {
+                continue;
+            }
+            match transport.open(stream).await {
+                Ok(connection) => {
+                    handle_result!(
+                        status_tx,
+                        Self::accept_incoming_connection_(
+                            self_.clone(),
+                            connection.receiver,
+                            connection.sender,
+                            address,
+                            policy
+                        )
+                        .instrument(info_span!("downstream", addr = %address))
+                        .await
+                    );
+                }
+                Err(e) => warn!("Failed to open the connection from {}: {:?}", address, e),
+            }
         }
         Ok(())
     }
 
-    async fn accept_incoming_connection(
-        self_: Arc<Mutex<Pool>>,
-        config: Configuration,
-    ) -> PoolResult<()> {
-        let status_tx = self_.safe_lock(|s| s.status_tx.clone())?;
-        let listener = TcpListener::bind(&config.listen_address).await?;
-        info!(
-            "Listening for encrypted connection on: {}",
-            config.listen_address
-        );
-        while let Ok((stream, _)) = listener.accept().await {
-            let address = stream.peer_addr().unwrap();
-            debug!(
-                "New connection from {:?}",
-                stream.peer_addr().map_err(PoolError::Io)
-            );
-
-            let responder = Responder::from_authority_kp(
-                &config.authority_public_key.into_bytes(),
-                &config.authority_secret_key.into_bytes(),
-                std::time::Duration::from_secs(config.cert_validity_sec),
-            );
-            match responder {
-                Ok(resp) => {
-                    if let Ok((receiver, sender, _, _)) =
-                        Connection::new(stream, HandshakeRole::Responder(resp)).await
-                    {
-                        handle_result!(
-                            status_tx,
-                            Self::accept_incoming_connection_(
-                                self_.clone(),
-                                receiver,
-                                sender,
-                                address
-                            )
-                            .await
-                        );
-                    }
-                }
-                Err(_e) => {
-                    todo!()
-                }
-            }
+    /// New connections from banned ips, or during maintenance, are dropped before the handshake
+    #[allow(clippy::result_large_err)]
+    fn is_refused(self_: &Arc<Mutex<Pool>>, address: SocketAddr) -> PoolResult<bool> {
+        let (rate_limiter, maintenance) =
+            self_.safe_lock(|p| (p.rate_limiter.clone(), p.maintenance.clone()))?;
+        if maintenance.load(Ordering::Relaxed) {
+            debug!("Refusing connection from {} during maintenance", address);
+            return Ok(true);
         }
-        Ok(())
+        let banned = rate_limiter.safe_lock(|r| r.is_banned(address.ip()))?;
+        if banned {
+            debug!("Refusing connection from banned ip {}", address);
+        }
+        Ok(banned)
     }
 
     async fn accept_incoming_connection_(
@@ -464,10 +986,17 @@ impl Pool {
         receiver: Receiver<EitherFrame>,
         sender: Sender<EitherFrame>,
         address: SocketAddr,
+        policy: ListenerPolicy,
     ) -> PoolResult<()> {
         let solution_sender = self_.safe_lock(|p| p.solution_sender.clone())?;
         let status_tx = self_.safe_lock(|s| s.status_tx.clone())?;
         let channel_factory = self_.safe_lock(|s| s.channel_factory.clone())?;
+        let stats = self_.safe_lock(|s| s.stats.clone())?;
+        let share_logger = self_.safe_lock(|s| s.share_logger.clone())?;
+        let rate_limiter = self_.safe_lock(|s| s.rate_limiter.clone())?;
+        let auth_provider = self_.safe_lock(|s| s.auth_provider.clone())?;
+        let maintenance = self_.safe_lock(|s| s.maintenance.clone())?;
+        let send_timeout = self_.safe_lock(|s| s.send_timeout)?;
 
         let downstream = Downstream::new(
             receiver,
@@ -478,6 +1007,13 @@ impl Pool {
             // convert Listener variant to Downstream variant
             status_tx.listener_to_connection(),
             address,
+            stats,
+            share_logger,
+            rate_limiter,
+            auth_provider,
+            maintenance,
+            send_timeout,
+            policy,
         )
         .await?;
 
@@ -494,9 +1030,9 @@ impl Pool {
         rx: Receiver<SetNewPrevHash<'static>>,
         sender_message_received_signal: Sender<()>,
     ) -> PoolResult<()> {
-        let status_tx = self_
-            .safe_lock(|s| s.status_tx.clone())
-            .map_err(|e| PoolError::PoisonLock(e.to_string()))?;
+        let status_tx = self_.safe_lock(|s| s.status_tx.clone())?;
+        let pipeline =
+            self_.safe_lock(|s| TemplateToJobPipeline::new(s.channel_factory.clone()))?;
         while let Ok(new_prev_hash) = rx.recv().await {
             debug!("New prev hash received: {:?}", new_prev_hash);
             let res = self_
@@ -506,40 +1042,18 @@ impl Pool {
                 .map_err(|e| PoolError::PoisonLock(e.to_string()));
             handle_result!(status_tx, res);
 
-            let job_id_res = self_
-                .safe_lock(|s| {
-                    s.channel_factory
-                        .safe_lock(|f| f.on_new_prev_hash_from_tp(&new_prev_hash))
-                        .map_err(|e| PoolError::PoisonLock(e.to_string()))
-                })
-                .map_err(|e| PoolError::PoisonLock(e.to_string()));
-            let job_id = handle_result!(status_tx, handle_result!(status_tx, job_id_res));
-
-            match job_id {
-                Ok(job_id) => {
-                    let downstreams = self_
-                        .safe_lock(|s| s.downstreams.clone())
-                        .map_err(|e| PoolError::PoisonLock(e.to_string()));
-                    let downstreams = handle_result!(status_tx, downstreams);
-
-                    for (channel_id, downtream) in downstreams {
-                        let message = Mining::SetNewPrevHash(SetNPH {
-                            channel_id,
-                            job_id,
-                            prev_hash: new_prev_hash.prev_hash.clone(),
-                            min_ntime: new_prev_hash.header_timestamp,
-                            nbits: new_prev_hash.n_bits,
-                        });
-                        let res = Downstream::match_send_to(
-                            downtream.clone(),
-                            Ok(SendTo::Respond(message)),
-                        )
-                        .await;
-                        handle_result!(status_tx, res);
-                    }
+            match pipeline.on_new_prev_hash(&new_prev_hash) {
+                Ok(messages) => {
+                    handle_result!(status_tx, Self::send_job_batch(&self_, messages).await);
                     handle_result!(status_tx, sender_message_received_signal.send(()).await);
                 }
-                Err(_) => todo!(),
+                Err(Error::PoisonLock(e)) => {
+                    handle_result!(status_tx, Err(PoolError::PoisonLock(e)));
+                }
+                Err(e) => error!(
+                    "Prev hash of template {} not activated: {}",
+                    new_prev_hash.template_id, e
+                ),
             }
         }
         Ok(())
@@ -551,34 +1065,17 @@ impl Pool {
         sender_message_received_signal: Sender<()>,
     ) -> PoolResult<()> {
         let status_tx = self_.safe_lock(|s| s.status_tx.clone())?;
-        let channel_factory = self_.safe_lock(|s| s.channel_factory.clone())?;
+        let pipeline =
+            self_.safe_lock(|s| TemplateToJobPipeline::new(s.channel_factory.clone()))?;
         while let Ok(mut new_template) = rx.recv().await {
             debug!(
                 "New template received, creating a new mining job(s): {:?}",
                 new_template
             );
 
-            let messages = channel_factory
-                .safe_lock(|cf| cf.on_new_template(&mut new_template))
-                .map_err(|e| PoolError::PoisonLock(e.to_string()));
-            let messages = handle_result!(status_tx, messages);
-            let mut messages = handle_result!(status_tx, messages);
-
-            let downstreams = self_
-                .safe_lock(|s| s.downstreams.clone())
-                .map_err(|e| PoolError::PoisonLock(e.to_string()));
-            let downstreams = handle_result!(status_tx, downstreams);
+            let messages = handle_result!(status_tx, pipeline.on_new_template(&mut new_template));
+            handle_result!(status_tx, Self::send_job_batch(&self_, messages).await);
 
-            for (channel_id, downtream) in downstreams {
-                if let Some(to_send) = messages.remove(&channel_id) {
-                    if let Err(e) =
-                        Downstream::match_send_to(downtream.clone(), Ok(SendTo::Respond(to_send)))
-                            .await
-                    {
-                        error!("Unknown template provider message: {:?}", e);
-                    }
-                }
-            }
             let res = self_
                 .safe_lock(|s| s.new_template_processed = true)
                 .map_err(|e| PoolError::PoisonLock(e.to_string()));
@@ -589,13 +1086,51 @@ impl Pool {
         Ok(())
     }
 
+    /// Sends the messages of each channel to the downstream that opened it. The messages of the
+    /// group channels go to the downstream of the group.
+    async fn send_job_batch(self_: &Arc<Mutex<Self>>, mut messages: JobBatch) -> PoolResult<()> {
+        let downstreams = self_.safe_lock(|s| s.downstreams.clone())?;
+        let mut to_send = Vec::with_capacity(downstreams.len());
+        for (id, downstream) in downstreams {
+            let channels = downstream.safe_lock(|d| d.channels.clone())?;
+            let mut batch = vec![];
+            for channel_id in channels {
+                batch.extend(messages.remove(&channel_id).unwrap_or_default());
+            }
+            to_send.push((id, downstream, batch));
+        }
+        // Once the messages of the channels are taken, what is left is keyed by group id
+        let to_send = to_send
+            .into_iter()
+            .map(|(group_id, downstream, mut batch)| {
+                batch.extend(messages.remove(&group_id).unwrap_or_default());
+                (downstream, batch)
+            });
+        for (downstream, batch) in to_send {
+            for message in batch {
+                if let Err(e) =
+                    Downstream::match_send_to(downstream.clone(), Ok(SendTo::Respond(message)))
+                        .await
+                {
+                    error!("Failed to send a job to a downstream: {:?}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn start(
         config: Configuration,
         new_template_rx: Receiver<NewTemplate<'static>>,
         new_prev_hash_rx: Receiver<SetNewPrevHash<'static>>,
-        solution_sender: Sender<SubmitSolution<'static>>,
+        solution_sender: Sender<Solution>,
         sender_message_received_signal: Sender<()>,
         status_tx: status::Sender,
+        stats: Arc<Mutex<PoolStats>>,
+        stats_sender: Option<Sender<StatsSnapshot>>,
+        share_logger: Option<ShareLogger>,
+        auth_provider: Option<Arc<dyn AuthProvider>>,
     ) -> Arc<Mutex<Self>> {
         let extranonce_len = 32;
         let range_0 = std::ops::Range { start: 0, end: 0 };
@@ -611,7 +1146,7 @@ impl Pool {
         let creator = JobsCreators::new(extranonce_len as u8);
         let share_per_min = 1.0;
         let kind = roles_logic_sv2::channel_logic::channel_factory::ExtendedChannelKind::Pool;
-        let channel_factory = Arc::new(Mutex::new(PoolChannelFactory::new(
+        let mut channel_factory = PoolChannelFactory::new(
             ids,
             extranonces,
             creator,
@@ -619,7 +1154,17 @@ impl Pool {
             kind,
             pool_coinbase_outputs.expect("Invalid coinbase output in config"),
             config.pool_signature.clone(),
-        )));
+        );
+        channel_factory.set_ntime_limits(config.ntime_limits());
+        channel_factory.set_jds_public_key(
+            config
+                .jds_authority_public_key
+                .unwrap_or(config.authority_public_key)
+                .0,
+        );
+        let channel_factory = Arc::new(Mutex::new(channel_factory));
+        Self::start_stats(&config.stats, stats.clone(), stats_sender);
+        let retarget_interval = config.stats.retarget_interval_sec.map(Duration::from_secs);
         let pool = Arc::new(Mutex::new(Pool {
             downstreams: HashMap::with_hasher(BuildNoHashHasher::default()),
             solution_sender,
@@ -627,55 +1172,22 @@ impl Pool {
             channel_factory,
             last_prev_hash_template_id: 0,
             status_tx: status_tx.clone(),
+            stats,
+            share_logger,
+            rate_limiter: Arc::new(Mutex::new(RateLimiter::new(config.rate_limits.clone()))),
+            auth_provider,
+            maintenance: Arc::new(AtomicBool::new(false)),
+            send_timeout: config.send_timeout_ms.map(Duration::from_millis),
         }));
 
-        let cloned = pool.clone();
+        if let Some(interval) = retarget_interval {
+            Self::start_retarget(pool.clone(), interval, share_per_min.into());
+        }
+
         let cloned2 = pool.clone();
         let cloned3 = pool.clone();
 
-        #[cfg(feature = "test_only_allow_unencrypted")]
-        {
-            let cloned4 = pool.clone();
-            let status_tx_clone_unenc = status_tx.clone();
-            let config_unenc = config.clone();
-
-            task::spawn(async move {
-                if let Err(e) = Self::accept_incoming_plain_connection(cloned4, config_unenc).await
-                {
-                    error!("{}", e);
-                }
-                if status_tx_clone_unenc
-                    .send(status::Status {
-                        state: status::State::DownstreamShutdown(PoolError::ComponentShutdown(
-                            "Downstream no longer accepting incoming connections".to_string(),
-                        )),
-                    })
-                    .await
-                    .is_err()
-                {
-                    error!("Downstream shutdown and Status Channel dropped");
-                }
-            });
-        }
-
-        info!("Starting up pool listener");
-        let status_tx_clone = status_tx.clone();
-        task::spawn(async move {
-            if let Err(e) = Self::accept_incoming_connection(cloned, config).await {
-                error!("{}", e);
-            }
-            if status_tx_clone
-                .send(status::Status {
-                    state: status::State::DownstreamShutdown(PoolError::ComponentShutdown(
-                        "Downstream no longer accepting incoming connections".to_string(),
-                    )),
-                })
-                .await
-                .is_err()
-            {
-                error!("Downstream shutdown and Status Channel dropped");
-            }
-        });
+        ListenerManager::from_config(&config).start(pool.clone(), &config, status_tx.clone());
 
         let cloned = sender_message_received_signal.clone();
         let status_tx_clone = status_tx.clone();
@@ -720,21 +1232,188 @@ impl Pool {
         cloned3
     }
 
+    /// Sends a snapshot of `stats` to `stats_sender` every `config.snapshot_interval_sec` and
+    /// serves the statistics as JSON at `config.http_address`
+    fn start_stats(
+        config: &StatsConfig,
+        stats: Arc<Mutex<PoolStats>>,
+        stats_sender: Option<Sender<StatsSnapshot>>,
+    ) {
+        if let Some(stats_sender) = stats_sender {
+            let interval = Duration::from_secs(config.snapshot_interval_sec);
+            let stats = stats.clone();
+            task::spawn(async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    let snapshot = match stats.safe_lock(|s| s.snapshot()) {
+                        Ok(snapshot) => snapshot,
+                        Err(e) => {
+                            error!("Stats mutex poisoned: {}", e);
+                            break;
+                        }
+                    };
+                    if stats_sender.send(snapshot).await.is_err() {
+                        warn!("Stats receiver dropped, no more snapshots will be sent");
+                        break;
+                    }
+                }
+            });
+        }
+        if let Some(address) = config.http_address.clone() {
+            task::spawn(async move {
+                if let Err(e) = Self::serve_stats(address, stats).await {
+                    error!("Stats HTTP endpoint stopped: {}", e);
+                }
+            });
+        }
+    }
+
+    async fn serve_stats(address: String, stats: Arc<Mutex<PoolStats>>) -> PoolResult<()> {
+        let listener = TcpListener::bind(&address).await?;
+        info!("Serving stats on: {}", address);
+        loop {
+            let (mut stream, _) = listener.accept().await?;
+            let snapshot = stats.safe_lock(|s| s.snapshot())?;
+            task::spawn(async move {
+                // Any request gets the stats, the request itself is not parsed
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                let body = serde_json::to_string(&snapshot).unwrap_or_default();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                if let Err(e) = stream.write_all(response.as_bytes()).await {
+                    debug!("Failed to send stats: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Recalculates the target of every channel from its 10 minutes hashrate every `interval`
+    fn start_retarget(self_: Arc<Mutex<Self>>, interval: Duration, share_per_min: f64) {
+        task::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if let Err(e) = Self::retarget(&self_, share_per_min).await {
+                    error!("Channel retargeting stopped: {}", e);
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Sends a `SetTarget` for every channel whose estimated hashrate asks for a difficulty that
+    /// differs by more than `RETARGET_MIN_CHANGE` from the current one
+    async fn retarget(self_: &Arc<Mutex<Self>>, share_per_min: f64) -> PoolResult<()> {
+        let (downstreams, channel_factory, stats) = self_.safe_lock(|p| {
+            (
+                p.downstreams.clone(),
+                p.channel_factory.clone(),
+                p.stats.clone(),
+            )
+        })?;
+        for downstream in downstreams.into_values() {
+            let channels = downstream.safe_lock(|d| d.channels.clone())?;
+            for channel_id in channels {
+                let estimate = stats.safe_lock(|s| {
+                    let hashrate = s.channel_hashrate(channel_id, HASHRATE_WINDOW_10M)?;
+                    let (_, difficulty) = s.channel(channel_id)?;
+                    Some((hashrate, difficulty))
+                })?;
+                // Keeps the target of the channels without accepted shares
+                let (hashrate, difficulty) = match estimate {
+                    Some((hashrate, difficulty)) if hashrate > 0.0 => (hashrate, difficulty),
+                    _ => continue,
+                };
+                let maximum_target =
+                    roles_logic_sv2::utils::hash_rate_to_target(hashrate, share_per_min)?;
+                let new_difficulty = target_to_difficulty(maximum_target.inner_as_ref());
+                if (new_difficulty - difficulty).abs() <= difficulty * RETARGET_MIN_CHANGE {
+                    continue;
+                }
+                let updated = channel_factory.safe_lock(|f| {
+                    f.update_target_for_channel(channel_id, maximum_target.clone().into())
+                })?;
+                if updated.is_none() {
+                    continue;
+                }
+                stats.safe_lock(|s| s.set_target(channel_id, maximum_target.inner_as_ref()))?;
+                debug!(
+                    channel_id,
+                    hashrate,
+                    difficulty = new_difficulty,
+                    "Channel retargeted"
+                );
+                let set_target = Mining::SetTarget(SetTarget::new(channel_id, maximum_target));
+                if let Err(e) = Downstream::send(downstream.clone(), set_target).await {
+                    debug!(channel_id, "Failed to send SetTarget: {}", e);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Coinbase outputs of the jobs created from the next templates
+    #[allow(clippy::result_large_err)]
+    pub fn update_coinbase_outputs(
+        self_: &Arc<Mutex<Self>>,
+        outputs: Vec<TxOut>,
+    ) -> PoolResult<()> {
+        let channel_factory = self_.safe_lock(|p| p.channel_factory.clone())?;
+        channel_factory.safe_lock(|cf| cf.update_pool_outputs(outputs))?;
+        Ok(())
+    }
+
     /// This removes the downstream from the list of downstreams
     /// due to a race condition it's possible for downstreams to have been cloned right before
     /// this remove happens which will cause the cloning task to still attempt to communicate with
     /// the downstream. This is going to be rare and will won't cause any issues as the attempt
     /// to communicate will fail but continue with the next downstream.
     pub fn remove_downstream(&mut self, downstream_id: u32) {
-        self.downstreams.remove(&downstream_id);
+        if let Some(downstream) = self.downstreams.remove(&downstream_id) {
+            let channels = downstream.safe_lock(|d| d.channels.len()).unwrap_or(0);
+            monitoring_sv2::metrics()
+                .channels_open
+                .add(-(channels as i64));
+        }
+    }
+
+    pub fn downstream_count(&self) -> usize {
+        self.downstreams.len()
+    }
+
+    /// Stops accepting new connections and channels and moves every downstream to `endpoint`
+    pub async fn start_maintenance(self_: &Arc<Mutex<Self>>, endpoint: Endpoint) -> PoolResult<()> {
+        let downstreams = self_
+            .safe_lock(|p| {
+                p.maintenance.store(true, Ordering::Relaxed);
+                p.downstreams.clone()
+            })
+            .map_err(|e| PoolError::PoisonLock(e.to_string()))?;
+        info!(
+            "Maintenance started, moving {} downstreams to {}:{}",
+            downstreams.len(),
+            endpoint.host(),
+            endpoint.port()
+        );
+        for (id, downstream) in downstreams {
+            if let Err(e) = Downstream::migrate(downstream, endpoint.clone()).await {
+                warn!("Failed to move downstream {}: {}", id, e);
+            }
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
     use binary_sv2::{B0255, B064K};
+    use config_helpers_sv2::{Validate, Validator};
     use ext_config::{Config, File, FileFormat};
-    use std::convert::TryInto;
+    use std::{convert::TryInto, path::Path};
     use tracing::error;
 
     use stratum_common::{
@@ -779,7 +1458,8 @@ mod test {
         let _coinbase_tx_outputs_count = 0;
         let coinbase_tx_locktime = 0;
         let coinbase_tx_outputs: Vec<bitcoin::TxOut> = super::get_coinbase_output(&config).unwrap();
-        // extranonce len set to max_extranonce_size in `ChannelFactory::new_extended_channel()`
+        // extranonce prefix + extranonce of the channels opened by
+        // `ChannelFactory::new_extended_channel()`
         let extranonce_len = 32;
 
         // build coinbase TX from 'job_creator::coinbase()'
@@ -837,6 +1517,42 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_config_validation() {
+        for path in [
+            "./config-examples/pool-config-hosted-tp-example.toml",
+            "./config-examples/pool-config-local-tp-example.toml",
+        ] {
+            config_helpers_sv2::load::<Configuration>(Path::new(path), "POOL").unwrap();
+        }
+
+        let config: Configuration = Config::builder()
+            .add_source(File::new(
+                "./config-examples/pool-config-local-tp-example.toml",
+                FileFormat::Toml,
+            ))
+            .build()
+            .and_then(|settings| settings.try_deserialize())
+            .unwrap();
+        let config = Configuration {
+            listen_address: "0.0.0.0".to_string(),
+            tp_address: "127.0.0.1:8442".to_string(),
+            tp_noise: false,
+            coinbase_outputs: vec![],
+            ..config
+        };
+        let mut v = Validator::default();
+        config.validate(&mut v);
+        let errors = match v.finish() {
+            Err(config_helpers_sv2::ConfigError::Invalid(errors)) => errors,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors[0].starts_with("listen_address"));
+        assert!(errors[1].contains("unix://"));
+        assert!(errors[2].starts_with("coinbase_outputs"));
+    }
+
     // copied from roles-logic-sv2::job_creator
     fn coinbase_tx_prefix(coinbase: &Transaction, script_prefix_len: usize) -> B064K<'static> {
         let encoded = coinbase.serialize();
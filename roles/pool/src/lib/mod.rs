@@ -1,12 +1,25 @@
+pub mod auth;
+pub mod config_reload;
 pub mod error;
+pub mod maintenance;
 pub mod mining_pool;
+pub mod rate_limit;
+pub mod self_test;
+pub mod share_log;
+pub mod stats;
 pub mod status;
+pub mod sv1_listener;
 pub mod template_receiver;
 
-use async_channel::{bounded, unbounded};
+use async_channel::{bounded, unbounded, Sender};
 
+use auth::AuthProvider;
 use error::PoolError;
 use mining_pool::{get_coinbase_output, Configuration, Pool};
+use roles_logic_sv2::utils::{coinbase_output_data_size, Mutex};
+use share_log::ShareLogger;
+use stats::{PoolStats, StatsSnapshot};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 use template_receiver::TemplateRx;
 use tracing::{error, info, warn};
 
@@ -15,11 +28,38 @@ use tokio::select;
 #[derive(Debug, Clone)]
 pub struct PoolSv2 {
     config: Configuration,
+    stats_sender: Option<Sender<StatsSnapshot>>,
+    config_path: Option<PathBuf>,
+    auth_provider: Option<Arc<dyn AuthProvider>>,
 }
 
 impl PoolSv2 {
     pub fn new(config: Configuration) -> PoolSv2 {
-        PoolSv2 { config }
+        PoolSv2 {
+            config,
+            stats_sender: None,
+            config_path: None,
+            auth_provider: None,
+        }
+    }
+
+    /// A snapshot of the share statistics is sent to `stats_sender` every
+    /// `stats.snapshot_interval_sec`
+    pub fn with_stats_sender(mut self, stats_sender: Sender<StatsSnapshot>) -> Self {
+        self.stats_sender = Some(stats_sender);
+        self
+    }
+
+    /// The coinbase outputs are reloaded from `config_path` on SIGHUP, see [`config_reload`]
+    pub fn with_config_path(mut self, config_path: PathBuf) -> Self {
+        self.config_path = Some(config_path);
+        self
+    }
+
+    /// Used to authorize the miners instead of the `[auth]` section of the config, see [`auth`]
+    pub fn with_auth_provider(mut self, auth_provider: Arc<dyn AuthProvider>) -> Self {
+        self.auth_provider = Some(auth_provider);
+        self
     }
 
     pub async fn start(&self) -> Result<(), PoolError> {
@@ -27,20 +67,37 @@ impl PoolSv2 {
         let (status_tx, status_rx) = unbounded();
         let (s_new_t, r_new_t) = bounded(10);
         let (s_prev_hash, r_prev_hash) = bounded(10);
-        let (s_solution, r_solution) = bounded(10);
+        // unbounded so that a block found is never delayed by the solutions before it
+        let (s_solution, r_solution) = unbounded();
         let (s_message_recv_signal, r_message_recv_signal) = bounded(10);
         let coinbase_output_result = get_coinbase_output(&config);
-        let coinbase_output_len = coinbase_output_result?.len() as u32;
-        let tp_authority_public_key = config.tp_authority_public_key;
-        TemplateRx::connect(
-            config.tp_address.parse().unwrap(),
+        let coinbase_output_len = coinbase_output_data_size(&coinbase_output_result?)?
+            .coinbase_output_max_additional_size;
+        let share_logger = match &config.share_log {
+            Some(share_log) => Some(ShareLogger::from_config(share_log)?),
+            None => None,
+        };
+        let auth_provider = match (&self.auth_provider, &config.auth) {
+            (Some(auth_provider), _) => Some(auth_provider.clone()),
+            (None, Some(auth)) => Some(Arc::from(
+                auth.clone().into_provider().map_err(PoolError::Custom)?,
+            )),
+            (None, None) => None,
+        };
+        let stats = Arc::new(Mutex::new(PoolStats::new(Duration::from_secs(
+            config.stats.hashrate_window_sec,
+        ))));
+        let template_rx = TemplateRx::connect(
+            config.template_providers(),
+            Duration::from_secs(config.tp_health_check_interval_sec),
+            config.tp_max_silence_sec.map(Duration::from_secs),
             s_new_t,
             s_prev_hash,
             r_solution,
             r_message_recv_signal,
             status::Sender::Upstream(status_tx.clone()),
             coinbase_output_len,
-            tp_authority_public_key,
+            stats.clone(),
         )
         .await?;
         let pool = Pool::start(
@@ -50,13 +107,51 @@ impl PoolSv2 {
             s_solution,
             s_message_recv_signal,
             status::Sender::DownstreamListener(status_tx),
+            stats.clone(),
+            self.stats_sender.clone(),
+            share_logger,
+            auth_provider,
         );
+        if let Some(config_path) = self.config_path.clone() {
+            config_reload::start(
+                config_path,
+                config.config_watch_interval_sec.map(Duration::from_secs),
+                pool.clone(),
+                template_rx,
+            );
+        }
+
+        if let Some(metrics_address) = &config.metrics_address {
+            monitoring_sv2::serve(metrics_address, "pool")?;
+        }
+
+        if let Some(admin_address) = &config.admin_address {
+            // The state snapshot of the pool is its statistics
+            admin_sv2::diagnostics().set_snapshot(move || {
+                let snapshot = stats.safe_lock(|s| s.snapshot()).ok();
+                serde_json::to_value(snapshot).unwrap_or_default()
+            });
+            admin_sv2::serve(admin_address)?;
+        }
+
+        if let Some(sv1_listener) = &config.sv1_listener {
+            sv1_listener::start(&config, sv1_listener).map_err(PoolError::Custom)?;
+        }
+
+        let (maintenance_tx, maintenance_rx) = bounded(1);
+        if let Some(maintenance) = config.maintenance.clone() {
+            maintenance::start(maintenance, pool.clone(), maintenance_tx)?;
+        }
 
         // Start the error handling loop
         // See `./status.rs` and `utils/error_handling` for information on how this operates
         loop {
             let task_status = select! {
                 task_status = status_rx.recv() => task_status,
+                Ok(()) = maintenance_rx.recv() => {
+                    info!("Maintenance done");
+                    break Ok(());
+                }
                 interrupt_signal = tokio::signal::ctrl_c() => {
                     match interrupt_signal {
                         Ok(()) => {
@@ -0,0 +1,272 @@
+//! Rate limits and ban list of the downstream connections.
+//!
+//! Open channel requests and failed open channel requests are counted per ip, so that a miner
+//! can not get around the limits by reconnecting. Messages are counted per connection. An ip
+//! that exceeds a limit gets an `OpenMiningChannel.Error` when possible, is disconnected and its
+//! new connections are refused for `ban_duration_sec`. Every limit is disabled if not set.
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// Error code of the `OpenMiningChannel.Error` sent when too many channels are requested
+pub const TOO_MANY_CHANNEL_REQUESTS: &str = "too-many-channel-requests";
+/// Error code of the `OpenMiningChannel.Error` sent when a connection has too many channels
+pub const TOO_MANY_CHANNELS: &str = "too-many-channels";
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RateLimitConfig {
+    pub max_open_channel_requests_per_min: Option<u32>,
+    pub max_failed_channel_requests_per_min: Option<u32>,
+    pub max_channels_per_connection: Option<u32>,
+    pub max_messages_per_sec: Option<u32>,
+    #[serde(default = "default_ban_duration_sec")]
+    pub ban_duration_sec: u64,
+    /// Ips that are always refused
+    #[serde(default)]
+    pub banned_ips: Vec<IpAddr>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_open_channel_requests_per_min: None,
+            max_failed_channel_requests_per_min: None,
+            max_channels_per_connection: None,
+            max_messages_per_sec: None,
+            ban_duration_sec: default_ban_duration_sec(),
+            banned_ips: vec![],
+        }
+    }
+}
+
+fn default_ban_duration_sec() -> u64 {
+    600
+}
+
+/// Number of events in the last `window`
+#[derive(Debug)]
+pub struct RateCounter {
+    window: Duration,
+    events: VecDeque<Instant>,
+}
+
+impl RateCounter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Records an event and returns the number of events in the window
+    pub fn record(&mut self, now: Instant) -> usize {
+        self.trim(now);
+        self.events.push_back(now);
+        self.events.len()
+    }
+
+    fn trim(&mut self, now: Instant) {
+        while let Some(time) = self.events.front() {
+            if now.saturating_duration_since(*time) < self.window {
+                break;
+            }
+            self.events.pop_front();
+        }
+    }
+
+    fn is_empty(&mut self, now: Instant) -> bool {
+        self.trim(now);
+        self.events.is_empty()
+    }
+}
+
+/// Message rate of a connection
+#[derive(Debug)]
+pub struct MessageRate {
+    max_per_sec: u32,
+    counter: RateCounter,
+}
+
+impl MessageRate {
+    /// Returns false if the connection sent too many messages
+    pub fn on_message(&mut self) -> bool {
+        self.on_message_at(Instant::now())
+    }
+
+    fn on_message_at(&mut self, now: Instant) -> bool {
+        self.counter.record(now) <= self.max_per_sec as usize
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    static_bans: HashSet<IpAddr>,
+    // ip -> end of the ban
+    bans: HashMap<IpAddr, Instant>,
+    open_requests: HashMap<IpAddr, RateCounter>,
+    failed_requests: HashMap<IpAddr, RateCounter>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            static_bans: config.banned_ips.iter().copied().collect(),
+            config,
+            bans: HashMap::new(),
+            open_requests: HashMap::new(),
+            failed_requests: HashMap::new(),
+        }
+    }
+
+    pub fn max_channels_per_connection(&self) -> Option<u32> {
+        self.config.max_channels_per_connection
+    }
+
+    /// `None` if the message rate is not limited
+    pub fn message_rate(&self) -> Option<MessageRate> {
+        self.config
+            .max_messages_per_sec
+            .map(|max_per_sec| MessageRate {
+                max_per_sec,
+                counter: RateCounter::new(Duration::from_secs(1)),
+            })
+    }
+
+    /// Called for every new connection, it is also when the expired bans are removed
+    pub fn is_banned(&mut self, ip: IpAddr) -> bool {
+        self.is_banned_at(ip, Instant::now())
+    }
+
+    fn is_banned_at(&mut self, ip: IpAddr, now: Instant) -> bool {
+        self.bans.retain(|_, until| *until > now);
+        self.open_requests.retain(|_, c| !c.is_empty(now));
+        self.failed_requests.retain(|_, c| !c.is_empty(now));
+        self.static_bans.contains(&ip) || self.bans.contains_key(&ip)
+    }
+
+    pub fn ban(&mut self, ip: IpAddr) {
+        self.ban_at(ip, Instant::now())
+    }
+
+    fn ban_at(&mut self, ip: IpAddr, now: Instant) {
+        warn!("Banning {} for {}s", ip, self.config.ban_duration_sec);
+        self.bans
+            .insert(ip, now + Duration::from_secs(self.config.ban_duration_sec));
+    }
+
+    /// Returns false, and bans `ip`, if `ip` requested too many channels
+    pub fn on_open_channel_request(&mut self, ip: IpAddr) -> bool {
+        self.on_open_channel_request_at(ip, Instant::now())
+    }
+
+    fn on_open_channel_request_at(&mut self, ip: IpAddr, now: Instant) -> bool {
+        let max = match self.config.max_open_channel_requests_per_min {
+            Some(max) => max,
+            None => return true,
+        };
+        let count = self
+            .open_requests
+            .entry(ip)
+            .or_insert_with(|| RateCounter::new(MINUTE))
+            .record(now);
+        self.check(ip, count, max, now)
+    }
+
+    /// Returns false, and bans `ip`, if too many channel requests of `ip` failed
+    pub fn on_failed_channel_request(&mut self, ip: IpAddr) -> bool {
+        self.on_failed_channel_request_at(ip, Instant::now())
+    }
+
+    fn on_failed_channel_request_at(&mut self, ip: IpAddr, now: Instant) -> bool {
+        let max = match self.config.max_failed_channel_requests_per_min {
+            Some(max) => max,
+            None => return true,
+        };
+        let count = self
+            .failed_requests
+            .entry(ip)
+            .or_insert_with(|| RateCounter::new(MINUTE))
+            .record(now);
+        self.check(ip, count, max, now)
+    }
+
+    fn check(&mut self, ip: IpAddr, count: usize, max: u32, now: Instant) -> bool {
+        if count > max as usize {
+            self.ban_at(ip, now);
+            false
+        } else {
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            max_open_channel_requests_per_min: Some(2),
+            max_failed_channel_requests_per_min: Some(1),
+            banned_ips: vec!["10.0.0.1".parse().unwrap()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_open_channel_requests_are_limited_per_ip() {
+        let mut limiter = RateLimiter::new(config());
+        let ip: IpAddr = "10.0.0.2".parse().unwrap();
+        let other: IpAddr = "10.0.0.3".parse().unwrap();
+        let now = Instant::now();
+        assert!(limiter.on_open_channel_request_at(ip, now));
+        assert!(limiter.on_open_channel_request_at(ip, now));
+        assert!(limiter.on_open_channel_request_at(other, now));
+        assert!(!limiter.is_banned_at(ip, now));
+        assert!(!limiter.on_open_channel_request_at(ip, now));
+        assert!(limiter.is_banned_at(ip, now));
+        assert!(!limiter.is_banned_at(other, now));
+
+        // The ban expires
+        let later = now + Duration::from_secs(600);
+        assert!(!limiter.is_banned_at(ip, later));
+        assert!(limiter.on_open_channel_request_at(ip, later));
+    }
+
+    #[test]
+    fn test_failed_channel_requests_and_static_bans() {
+        let mut limiter = RateLimiter::new(config());
+        let ip: IpAddr = "10.0.0.2".parse().unwrap();
+        let now = Instant::now();
+        assert!(limiter.is_banned_at("10.0.0.1".parse().unwrap(), now));
+        assert!(limiter.on_failed_channel_request_at(ip, now));
+        // Failures out of the window are not counted
+        assert!(limiter.on_failed_channel_request_at(ip, now + MINUTE));
+        assert!(!limiter.on_failed_channel_request_at(ip, now + MINUTE));
+        assert!(limiter.is_banned_at(ip, now + MINUTE));
+    }
+
+    #[test]
+    fn test_message_rate() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            max_messages_per_sec: Some(2),
+            ..Default::default()
+        });
+        let mut rate = limiter.message_rate().unwrap();
+        let now = Instant::now();
+        assert!(rate.on_message_at(now));
+        assert!(rate.on_message_at(now));
+        assert!(!rate.on_message_at(now));
+        assert!(rate.on_message_at(now + Duration::from_secs(1)));
+        assert!(RateLimiter::new(RateLimitConfig::default())
+            .message_rate()
+            .is_none());
+    }
+}
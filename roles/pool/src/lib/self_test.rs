@@ -0,0 +1,628 @@
+//! Conformance self test of the pool, run with `--self-test`.
+//!
+//! The pool is started in process with the operator config, listening on a free local port and
+//! connected to a synthetic Template Provider. A synthetic miner then drives the mining flow
+//! (setup, open channel, jobs, shares, prev hash) and every step is reported as passed or failed,
+//! which makes it a smoke test after a config change. The side effects of the config are
+//! disabled: the share log, the stats endpoint, the config reload, maintenance and auth.
+use super::{
+    mining_pool::{Configuration, EitherFrame, StdFrame},
+    rate_limit::RateLimitConfig,
+    stats::StatsConfig,
+    PoolSv2,
+};
+use async_channel::{Receiver, Sender};
+use binary_sv2::{Seq0255, B064K, U256};
+use codec_sv2::{HandshakeRole, Initiator, Responder};
+use network_helpers_sv2::noise_connection_tokio::Connection;
+use roles_logic_sv2::{
+    common_messages_sv2::{
+        DeviceInfoBuilder, Endpoint, Protocol, SetupConnection, SetupConnectionSuccess,
+    },
+    mining_sv2::{NewMiningJob, OpenStandardMiningChannel, SubmitSharesStandard},
+    parsers::{CommonMessages, Mining, PoolMessages, TemplateDistribution},
+    template_distribution_sv2::{NewTemplate, SetNewPrevHash},
+};
+use std::{
+    convert::TryInto,
+    fmt,
+    future::Future,
+    net::SocketAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use stratum_common::bitcoin::{
+    blockdata::block::BlockHeader,
+    hash_types::{BlockHash, TxMerkleNode},
+    hashes::Hash,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    select,
+    task::{self, JoinHandle},
+};
+
+/// Time given to each step
+const STEP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Regtest difficulty, about one nonce out of two finds a block
+const N_BITS: u32 = 0x207fffff;
+
+const FIRST_PREV_HASH: [u8; 32] = [1; 32];
+const SECOND_PREV_HASH: [u8; 32] = [2; 32];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    TemplateProviderSetup,
+    CoinbaseOutputDataSize,
+    MinerSetup,
+    OpenChannel,
+    NewMiningJob,
+    SetNewPrevHash,
+    SubmitShare,
+    SubmitSolution,
+    PrevHashUpdate,
+}
+
+impl Step {
+    pub const ALL: [Step; 9] = [
+        Step::TemplateProviderSetup,
+        Step::CoinbaseOutputDataSize,
+        Step::MinerSetup,
+        Step::OpenChannel,
+        Step::NewMiningJob,
+        Step::SetNewPrevHash,
+        Step::SubmitShare,
+        Step::SubmitSolution,
+        Step::PrevHashUpdate,
+    ];
+
+    fn description(&self) -> &'static str {
+        match self {
+            Step::TemplateProviderSetup => "Pool connects to the Template Provider",
+            Step::CoinbaseOutputDataSize => "Pool sends CoinbaseOutputDataSize",
+            Step::MinerSetup => "Miner connects to the pool",
+            Step::OpenChannel => "Miner opens a standard channel",
+            Step::NewMiningJob => "Miner receives a job",
+            Step::SetNewPrevHash => "Miner receives the prev hash of the job",
+            Step::SubmitShare => "Pool accepts a share",
+            Step::SubmitSolution => "Pool submits the block found to the Template Provider",
+            Step::PrevHashUpdate => "Miner receives a job on the next prev hash",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    /// Not run because a previous step failed
+    Skipped,
+}
+
+#[derive(Debug, Default)]
+pub struct SelfTestReport {
+    pub steps: Vec<(Step, Outcome)>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|(_, outcome)| *outcome == Outcome::Passed)
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (step, outcome) in &self.steps {
+            match outcome {
+                Outcome::Passed => writeln!(f, "[PASS] {}", step.description())?,
+                Outcome::Failed(e) => writeln!(f, "[FAIL] {}: {}", step.description(), e)?,
+                Outcome::Skipped => writeln!(f, "[SKIP] {}", step.description())?,
+            }
+        }
+        let passed = self
+            .steps
+            .iter()
+            .filter(|(_, outcome)| *outcome == Outcome::Passed)
+            .count();
+        write!(f, "{}/{} steps passed", passed, self.steps.len())
+    }
+}
+
+/// Runs the self test against a pool started with `config`
+pub async fn run(config: Configuration) -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+    let mut pool = None;
+    // `None` as soon as a step fails, the others are skipped
+    let _ = run_steps(config, &mut report, &mut pool).await;
+    if let Some(pool) = pool {
+        pool.abort();
+    }
+    for step in Step::ALL.iter().skip(report.steps.len()) {
+        report.steps.push((*step, Outcome::Skipped));
+    }
+    report
+}
+
+async fn step<T>(
+    report: &mut SelfTestReport,
+    step: Step,
+    fut: impl Future<Output = Result<T, String>>,
+) -> Option<T> {
+    let result = match tokio::time::timeout(STEP_TIMEOUT, fut).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("timed out after {}s", STEP_TIMEOUT.as_secs())),
+    };
+    match result {
+        Ok(value) => {
+            report.steps.push((step, Outcome::Passed));
+            Some(value)
+        }
+        Err(e) => {
+            report.steps.push((step, Outcome::Failed(e)));
+            None
+        }
+    }
+}
+
+async fn run_steps(
+    mut config: Configuration,
+    report: &mut SelfTestReport,
+    pool: &mut Option<JoinHandle<()>>,
+) -> Option<()> {
+    let tp_listener = TcpListener::bind("127.0.0.1:0").await;
+    let pool_address = free_local_address();
+    let (tp_listener, pool_address) = match (tp_listener, pool_address) {
+        (Ok(tp_listener), Ok(pool_address)) => (tp_listener, pool_address),
+        (Err(e), _) | (_, Err(e)) => {
+            let e = format!("impossible to bind a local port: {}", e);
+            report
+                .steps
+                .push((Step::TemplateProviderSetup, Outcome::Failed(e)));
+            return None;
+        }
+    };
+    let tp_address = tp_listener.local_addr().ok()?;
+
+    config.listen_address = pool_address.to_string();
+    config.tp_address = tp_address.to_string();
+    config.tp_authority_public_key = Some(config.authority_public_key);
+    config.tp_noise = true;
+    config.additional_template_providers = vec![];
+    config.tp_max_silence_sec = None;
+    config.config_watch_interval_sec = None;
+    config.rate_limits = RateLimitConfig::default();
+    config.stats = StatsConfig::default();
+    config.share_log = None;
+    config.maintenance = None;
+    config.auth = None;
+    #[cfg(feature = "test_only_allow_unencrypted")]
+    {
+        config.test_only_listen_adress_plain = free_local_address().ok()?.to_string();
+    }
+
+    // the Template Provider uses the pool keys, it does not need keys of its own
+    let tp_responder = Responder::from_authority_kp(
+        &config.authority_public_key.into_bytes(),
+        &config.authority_secret_key.into_bytes(),
+        Duration::from_secs(config.cert_validity_sec),
+    );
+    let miner_initiator = Initiator::from_raw_k(config.authority_public_key.into_bytes());
+
+    let pool_config = config.clone();
+    let pool = pool.insert(task::spawn(async move {
+        if let Err(e) = PoolSv2::new(pool_config).start().await {
+            tracing::error!("Self test pool stopped: {}", e);
+        }
+    }));
+
+    let tp = step(report, Step::TemplateProviderSetup, async {
+        let responder = tp_responder.map_err(|e| format!("invalid authority keys: {:?}", e))?;
+        let stream = select! {
+            accepted = tp_listener.accept() => accepted.map_err(|e| e.to_string())?.0,
+            _ = &mut *pool => return Err("the pool stopped".to_string()),
+        };
+        let tp = Peer::open(stream, HandshakeRole::Responder(responder)).await?;
+        tp.expect(|message| match message {
+            PoolMessages::Common(CommonMessages::SetupConnection(m)) => Some(match m.protocol {
+                Protocol::TemplateDistributionProtocol => Ok(()),
+                protocol => Err(format!("SetupConnection for {:?}", protocol)),
+            }),
+            _ => None,
+        })
+        .await?;
+        tp.send(PoolMessages::Common(
+            CommonMessages::SetupConnectionSuccess(SetupConnectionSuccess {
+                used_version: 2,
+                flags: 0,
+            }),
+        ))
+        .await?;
+        Ok(tp)
+    })
+    .await?;
+
+    step(report, Step::CoinbaseOutputDataSize, async {
+        tp.expect(|message| match message {
+            PoolMessages::TemplateDistribution(TemplateDistribution::CoinbaseOutputDataSize(_)) => {
+                Some(Ok(()))
+            }
+            _ => None,
+        })
+        .await?;
+        tp.send_template(1, FIRST_PREV_HASH).await
+    })
+    .await?;
+
+    let miner = step(report, Step::MinerSetup, async {
+        let initiator =
+            miner_initiator.map_err(|e| format!("invalid authority public key: {:?}", e))?;
+        let stream = connect(pool_address).await?;
+        let miner = Peer::open(stream, HandshakeRole::Initiator(initiator)).await?;
+        miner
+            .send(PoolMessages::Common(CommonMessages::SetupConnection(
+                setup_connection(pool_address)?,
+            )))
+            .await?;
+        miner
+            .expect(|message| match message {
+                PoolMessages::Common(CommonMessages::SetupConnectionSuccess(_)) => Some(Ok(())),
+                PoolMessages::Common(CommonMessages::SetupConnectionError(m)) => {
+                    Some(Err(format!(
+                        "SetupConnection.Error {}",
+                        error_code(m.error_code.as_ref())
+                    )))
+                }
+                _ => None,
+            })
+            .await?;
+        Ok(miner)
+    })
+    .await?;
+
+    let channel_id = step(report, Step::OpenChannel, async {
+        let user_identity = "self-test"
+            .to_string()
+            .try_into()
+            .map_err(|e| format!("{:?}", e))?;
+        miner
+            .send(PoolMessages::Mining(Mining::OpenStandardMiningChannel(
+                OpenStandardMiningChannel {
+                    request_id: 1.into(),
+                    user_identity,
+                    // the pool answers with the highest target, every hash is a share
+                    nominal_hash_rate: 0.0,
+                    max_target: [255; 32].into(),
+                },
+            )))
+            .await?;
+        miner
+            .expect(|message| match message {
+                PoolMessages::Mining(Mining::OpenStandardMiningChannelSuccess(m)) => {
+                    Some(Ok(m.channel_id))
+                }
+                PoolMessages::Mining(Mining::OpenMiningChannelError(m)) => Some(Err(format!(
+                    "OpenMiningChannel.Error {}",
+                    error_code(m.error_code.as_ref())
+                ))),
+                _ => None,
+            })
+            .await
+    })
+    .await?;
+
+    let first_job = step(report, Step::NewMiningJob, miner.next_job(channel_id)).await?;
+
+    let (job, prev_hash) = step(
+        report,
+        Step::SetNewPrevHash,
+        miner.job_on_prev_hash(channel_id, vec![first_job], FIRST_PREV_HASH),
+    )
+    .await?;
+
+    let share = step(report, Step::SubmitShare, async {
+        let share = mine(channel_id, &job, &prev_hash)?;
+        miner
+            .send(PoolMessages::Mining(Mining::SubmitSharesStandard(
+                share.clone(),
+            )))
+            .await?;
+        miner
+            .expect(|message| match message {
+                PoolMessages::Mining(Mining::SubmitSharesSuccess(m))
+                    if m.channel_id == channel_id =>
+                {
+                    Some(Ok(()))
+                }
+                PoolMessages::Mining(Mining::SubmitSharesError(m)) => Some(Err(format!(
+                    "SubmitShares.Error {}",
+                    error_code(m.error_code.as_ref())
+                ))),
+                _ => None,
+            })
+            .await?;
+        Ok(share)
+    })
+    .await?;
+
+    step(report, Step::SubmitSolution, async {
+        tp.expect(|message| match message {
+            PoolMessages::TemplateDistribution(TemplateDistribution::SubmitSolution(m)) => {
+                Some(match (m.template_id, m.header_nonce) {
+                    (1, nonce) if nonce == share.nonce => Ok(()),
+                    (template_id, nonce) => Err(format!(
+                        "solution for template {} with nonce {}, expected template 1 with \
+                         nonce {}",
+                        template_id, nonce, share.nonce
+                    )),
+                })
+            }
+            _ => None,
+        })
+        .await
+    })
+    .await?;
+
+    step(report, Step::PrevHashUpdate, async {
+        tp.send_template(2, SECOND_PREV_HASH).await?;
+        miner
+            .job_on_prev_hash(channel_id, vec![], SECOND_PREV_HASH)
+            .await
+            .map(|_| ())
+    })
+    .await
+}
+
+/// A local address that was free when this was called
+fn free_local_address() -> std::io::Result<SocketAddr> {
+    std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()
+}
+
+/// Connects to the pool, retrying until its listener is up
+async fn connect(address: SocketAddr) -> Result<TcpStream, String> {
+    loop {
+        match TcpStream::connect(address).await {
+            Ok(stream) => return Ok(stream),
+            Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+}
+
+fn setup_connection(address: SocketAddr) -> Result<SetupConnection<'static>, String> {
+    let device_info = DeviceInfoBuilder::new("")
+        .device_id("self-test")
+        .build()
+        .map_err(|e| e.to_string())?;
+    let endpoint =
+        Endpoint::new(&address.ip().to_string(), address.port()).map_err(|e| e.to_string())?;
+    let mut setup_connection =
+        SetupConnection::for_protocol(Protocol::MiningProtocol, device_info, endpoint);
+    // REQUIRES_STANDARD_JOBS, header only mining
+    setup_connection.flags = 0b0000_0000_0000_0000_0000_0000_0000_0001;
+    Ok(setup_connection)
+}
+
+fn error_code(error_code: &[u8]) -> String {
+    String::from_utf8_lossy(error_code).to_string()
+}
+
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs() as u32)
+        .unwrap_or_default()
+}
+
+/// Prev hash sent to the miner, with the fields needed to mine on it
+struct MinerPrevHash {
+    min_ntime: u32,
+    nbits: u32,
+    prev_hash: [u8; 32],
+}
+
+/// Share on `job` that also meets the block target, so that the pool submits it as a solution
+fn mine(
+    channel_id: u32,
+    job: &NewMiningJob<'static>,
+    prev_hash: &MinerPrevHash,
+) -> Result<SubmitSharesStandard, String> {
+    let merkle_root: [u8; 32] = job
+        .merkle_root
+        .inner_as_ref()
+        .try_into()
+        .map_err(|_| "invalid merkle root".to_string())?;
+    let mut header = BlockHeader {
+        version: job.version as i32,
+        prev_blockhash: BlockHash::from_inner(prev_hash.prev_hash),
+        merkle_root: TxMerkleNode::from_inner(merkle_root),
+        time: prev_hash.min_ntime,
+        bits: prev_hash.nbits,
+        nonce: 0,
+    };
+    let target = header.target();
+    while header.validate_pow(&target).is_err() {
+        header.nonce = header
+            .nonce
+            .checked_add(1)
+            .ok_or_else(|| "no nonce meets the block target".to_string())?;
+    }
+    Ok(SubmitSharesStandard {
+        channel_id,
+        sequence_number: 0,
+        job_id: job.job_id,
+        nonce: header.nonce,
+        ntime: header.time,
+        version: job.version,
+    })
+}
+
+/// Noise connection of the synthetic Template Provider or miner
+struct Peer {
+    receiver: Receiver<EitherFrame>,
+    sender: Sender<EitherFrame>,
+}
+
+impl Peer {
+    async fn open(stream: TcpStream, role: HandshakeRole) -> Result<Self, String> {
+        let (receiver, sender, _, _) = Connection::new(stream, role)
+            .await
+            .map_err(|e| format!("noise handshake failed: {:?}", e))?;
+        Ok(Self { receiver, sender })
+    }
+
+    async fn send(&self, message: PoolMessages<'static>) -> Result<(), String> {
+        let frame: StdFrame = message.try_into().map_err(|e| format!("{:?}", e))?;
+        self.sender
+            .send(frame.into())
+            .await
+            .map_err(|_| "connection closed".to_string())
+    }
+
+    /// Waits for the first message for which `select` returns a result, the other messages are
+    /// ignored
+    async fn expect<T>(
+        &self,
+        mut select: impl FnMut(PoolMessages<'_>) -> Option<Result<T, String>>,
+    ) -> Result<T, String> {
+        loop {
+            let frame = self
+                .receiver
+                .recv()
+                .await
+                .map_err(|_| "connection closed".to_string())?;
+            let mut frame: StdFrame = frame.try_into().map_err(|e| format!("{:?}", e))?;
+            let message_type = frame
+                .get_header()
+                .ok_or_else(|| "frame without header".to_string())?
+                .msg_type();
+            let message: PoolMessages = (message_type, frame.payload())
+                .try_into()
+                .map_err(|e| format!("invalid message {}: {:?}", message_type, e))?;
+            if let Some(result) = select(message) {
+                return result;
+            }
+        }
+    }
+
+    /// Sends a future template and the prev hash that activates it
+    async fn send_template(&self, template_id: u64, prev_hash: [u8; 32]) -> Result<(), String> {
+        let coinbase_prefix = vec![3, 76, 163, 38, 0]
+            .try_into()
+            .map_err(|e| format!("{:?}", e))?;
+        let coinbase_tx_outputs: B064K = vec![].try_into().map_err(|e| format!("{:?}", e))?;
+        let merkle_path: Seq0255<U256> = vec![].into();
+        self.send(PoolMessages::TemplateDistribution(
+            TemplateDistribution::NewTemplate(NewTemplate {
+                template_id,
+                future_template: true,
+                version: 0x2000_0000,
+                coinbase_tx_version: 2,
+                coinbase_prefix,
+                coinbase_tx_input_sequence: u32::MAX,
+                coinbase_tx_value_remaining: 625_000_000,
+                coinbase_tx_outputs_count: 0,
+                coinbase_tx_outputs,
+                coinbase_tx_locktime: 0,
+                merkle_path,
+            }),
+        ))
+        .await?;
+        let mut target = [255; 32];
+        target[31] = 0x7f;
+        self.send(PoolMessages::TemplateDistribution(
+            TemplateDistribution::SetNewPrevHash(SetNewPrevHash {
+                template_id,
+                prev_hash: prev_hash.into(),
+                header_timestamp: now(),
+                n_bits: N_BITS,
+                target: target.into(),
+            }),
+        ))
+        .await
+    }
+
+    async fn next_job(&self, channel_id: u32) -> Result<NewMiningJob<'static>, String> {
+        self.expect(|message| match message {
+            PoolMessages::Mining(Mining::NewMiningJob(m)) if m.channel_id == channel_id => {
+                Some(Ok(m.into_static()))
+            }
+            _ => None,
+        })
+        .await
+    }
+
+    /// Waits for a SetNewPrevHash on `expected`, and returns it with the job it activates out
+    /// of `jobs` and the jobs received meanwhile
+    async fn job_on_prev_hash(
+        &self,
+        channel_id: u32,
+        mut jobs: Vec<NewMiningJob<'static>>,
+        expected: [u8; 32],
+    ) -> Result<(NewMiningJob<'static>, MinerPrevHash), String> {
+        let (job_id, prev_hash) = loop {
+            let message = self
+                .expect(|message| match message {
+                    PoolMessages::Mining(Mining::NewMiningJob(m)) if m.channel_id == channel_id => {
+                        Some(Ok(Mining::NewMiningJob(m.into_static())))
+                    }
+                    PoolMessages::Mining(Mining::SetNewPrevHash(m))
+                        if m.channel_id == channel_id =>
+                    {
+                        Some(Ok(Mining::SetNewPrevHash(m.into_static())))
+                    }
+                    _ => None,
+                })
+                .await?;
+            match message {
+                Mining::NewMiningJob(job) => jobs.push(job),
+                Mining::SetNewPrevHash(m) => {
+                    let prev_hash: [u8; 32] = m
+                        .prev_hash
+                        .inner_as_ref()
+                        .try_into()
+                        .map_err(|_| "invalid prev hash".to_string())?;
+                    if prev_hash != expected {
+                        return Err("SetNewPrevHash on an unknown prev hash".to_string());
+                    }
+                    break (
+                        m.job_id,
+                        MinerPrevHash {
+                            min_ntime: m.min_ntime,
+                            nbits: m.nbits,
+                            prev_hash,
+                        },
+                    );
+                }
+                _ => unreachable!(),
+            }
+        };
+        let job = jobs
+            .into_iter()
+            .find(|job| job.job_id == job_id)
+            .ok_or_else(|| format!("SetNewPrevHash for the unknown job {}", job_id))?;
+        Ok((job, prev_hash))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ext_config::{Config, File, FileFormat};
+
+    #[tokio::test]
+    async fn test_self_test_passes() {
+        let config: Configuration = Config::builder()
+            .add_source(File::new(
+                "./config-examples/pool-config-local-tp-example.toml",
+                FileFormat::Toml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        let report = run(config).await;
+        assert!(report.passed(), "{}", report);
+        assert_eq!(report.steps.len(), Step::ALL.len());
+    }
+}
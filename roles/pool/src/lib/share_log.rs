@@ -0,0 +1,419 @@
+//! Durable log of the shares received by the pool.
+//!
+//! Every share that goes through the share validation is turned in a [`ShareRecord`] and sent to
+//! a [`ShareLogger`], that writes the records in batches to a [`ShareSink`]. The log is meant to
+//! be consumed by payout systems (e.g. PPLNS), so a batch that can not be written is kept and
+//! written again at the next flush rather than dropped.
+//!
+//! Two sinks are provided: [`CsvShareSink`], one line per share with an header, and
+//! [`BinaryShareSink`], an append-only file of fixed layout records that can be read back with
+//! [`read_binary_share_log`].
+use super::stats::ShareOutcome;
+use async_channel::{unbounded, Receiver, Sender};
+use serde::Deserialize;
+use std::{
+    convert::TryInto,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::task;
+use tracing::{error, warn};
+
+const CSV_HEADER: &str = "timestamp,channel_id,user_identity,sequence_number,job_id,difficulty,share_difficulty,outcome,block_found";
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ShareLogFormat {
+    #[default]
+    Csv,
+    Binary,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShareLogConfig {
+    /// File the shares are appended to
+    pub path: String,
+    #[serde(default)]
+    pub format: ShareLogFormat,
+    /// Number of shares that triggers a write
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Shares are written at least this often
+    #[serde(default = "default_flush_interval_sec")]
+    pub flush_interval_sec: u64,
+}
+
+fn default_batch_size() -> usize {
+    100
+}
+
+fn default_flush_interval_sec() -> u64 {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShareRecord {
+    /// Unix time in milliseconds
+    pub timestamp: u64,
+    pub channel_id: u32,
+    pub user_identity: String,
+    pub sequence_number: u32,
+    pub job_id: u32,
+    /// Difficulty of the channel target, that is what the share is worth
+    pub difficulty: f64,
+    /// Difficulty of the share hash, equal to `difficulty` when not known
+    pub share_difficulty: f64,
+    pub outcome: ShareOutcome,
+    /// True if the share is a valid block
+    pub block_found: bool,
+}
+
+impl ShareRecord {
+    pub fn now_millis() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    }
+}
+
+/// Destination of the share log
+pub trait ShareSink: Send {
+    fn write(&mut self, shares: &[ShareRecord]) -> io::Result<()>;
+
+    /// Makes sure that what has been written is on disk
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+fn open_append(path: &Path) -> io::Result<(File, bool)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let is_empty = file.metadata()?.len() == 0;
+    Ok((file, is_empty))
+}
+
+fn outcome_to_str(outcome: ShareOutcome) -> &'static str {
+    match outcome {
+        ShareOutcome::Accepted => "accepted",
+        ShareOutcome::Rejected => "rejected",
+        ShareOutcome::Stale => "stale",
+    }
+}
+
+fn outcome_to_u8(outcome: ShareOutcome) -> u8 {
+    match outcome {
+        ShareOutcome::Accepted => 0,
+        ShareOutcome::Rejected => 1,
+        ShareOutcome::Stale => 2,
+    }
+}
+
+fn outcome_from_u8(outcome: u8) -> io::Result<ShareOutcome> {
+    match outcome {
+        0 => Ok(ShareOutcome::Accepted),
+        1 => Ok(ShareOutcome::Rejected),
+        2 => Ok(ShareOutcome::Stale),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid share outcome: {}", outcome),
+        )),
+    }
+}
+
+/// Appends the shares to a CSV file, the header is written only when the file is created
+pub struct CsvShareSink {
+    file: BufWriter<File>,
+}
+
+impl CsvShareSink {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let (file, is_empty) = open_append(path.as_ref())?;
+        let mut file = BufWriter::new(file);
+        if is_empty {
+            writeln!(file, "{}", CSV_HEADER)?;
+            file.flush()?;
+        }
+        Ok(Self { file })
+    }
+}
+
+// user identities are chosen by the miners, so they are always quoted
+fn csv_quote(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+impl ShareSink for CsvShareSink {
+    fn write(&mut self, shares: &[ShareRecord]) -> io::Result<()> {
+        for share in shares {
+            writeln!(
+                self.file,
+                "{},{},{},{},{},{},{},{},{}",
+                share.timestamp,
+                share.channel_id,
+                csv_quote(&share.user_identity),
+                share.sequence_number,
+                share.job_id,
+                share.difficulty,
+                share.share_difficulty,
+                outcome_to_str(share.outcome),
+                share.block_found,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()
+    }
+}
+
+/// Appends the shares to a binary file. Every record is, in little endian: timestamp (u64),
+/// channel id (u32), sequence number (u32), job id (u32), difficulty (f64), share difficulty
+/// (f64), outcome (u8), block found (u8), length of the user identity (u8) and user identity.
+pub struct BinaryShareSink {
+    file: BufWriter<File>,
+}
+
+impl BinaryShareSink {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let (file, _) = open_append(path.as_ref())?;
+        Ok(Self {
+            file: BufWriter::new(file),
+        })
+    }
+}
+
+fn encode_record(share: &ShareRecord, buffer: &mut Vec<u8>) {
+    // user identity is a Str0255 so it always fits, truncate anyway to keep the file readable
+    let user_identity = &share.user_identity.as_bytes()[..share.user_identity.len().min(255)];
+    buffer.extend_from_slice(&share.timestamp.to_le_bytes());
+    buffer.extend_from_slice(&share.channel_id.to_le_bytes());
+    buffer.extend_from_slice(&share.sequence_number.to_le_bytes());
+    buffer.extend_from_slice(&share.job_id.to_le_bytes());
+    buffer.extend_from_slice(&share.difficulty.to_le_bytes());
+    buffer.extend_from_slice(&share.share_difficulty.to_le_bytes());
+    buffer.push(outcome_to_u8(share.outcome));
+    buffer.push(share.block_found as u8);
+    buffer.push(user_identity.len() as u8);
+    buffer.extend_from_slice(user_identity);
+}
+
+impl ShareSink for BinaryShareSink {
+    fn write(&mut self, shares: &[ShareRecord]) -> io::Result<()> {
+        let mut buffer = Vec::new();
+        for share in shares {
+            encode_record(share, &mut buffer);
+        }
+        self.file.write_all(&buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()
+    }
+}
+
+/// Reads a share log written by [`BinaryShareSink`]
+pub fn read_binary_share_log<P: AsRef<Path>>(path: P) -> io::Result<Vec<ShareRecord>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    let mut shares = Vec::new();
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        let (share, len) = decode_record(rest).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated share record")
+        })??;
+        shares.push(share);
+        rest = &rest[len..];
+    }
+    Ok(shares)
+}
+
+// Returns the record and its length, None if `bytes` does not contain a whole record
+fn decode_record(bytes: &[u8]) -> Option<io::Result<(ShareRecord, usize)>> {
+    const FIXED_LEN: usize = 8 + 4 + 4 + 4 + 8 + 8 + 1 + 1 + 1;
+    let fixed = bytes.get(..FIXED_LEN)?;
+    let user_len = fixed[FIXED_LEN - 1] as usize;
+    let user_identity = bytes.get(FIXED_LEN..FIXED_LEN + user_len)?;
+    let outcome = match outcome_from_u8(fixed[36]) {
+        Ok(outcome) => outcome,
+        Err(e) => return Some(Err(e)),
+    };
+    let share = ShareRecord {
+        timestamp: u64::from_le_bytes(fixed[0..8].try_into().ok()?),
+        channel_id: u32::from_le_bytes(fixed[8..12].try_into().ok()?),
+        sequence_number: u32::from_le_bytes(fixed[12..16].try_into().ok()?),
+        job_id: u32::from_le_bytes(fixed[16..20].try_into().ok()?),
+        difficulty: f64::from_le_bytes(fixed[20..28].try_into().ok()?),
+        share_difficulty: f64::from_le_bytes(fixed[28..36].try_into().ok()?),
+        outcome,
+        block_found: fixed[37] != 0,
+        user_identity: String::from_utf8_lossy(user_identity).into_owned(),
+    };
+    Some(Ok((share, FIXED_LEN + user_len)))
+}
+
+/// Opens the sink described by `config`
+pub fn open_sink(config: &ShareLogConfig) -> io::Result<Box<dyn ShareSink>> {
+    Ok(match config.format {
+        ShareLogFormat::Csv => Box::new(CsvShareSink::open(&config.path)?),
+        ShareLogFormat::Binary => Box::new(BinaryShareSink::open(&config.path)?),
+    })
+}
+
+/// Handle used to log the shares, the shares are written by a background task
+#[derive(Debug, Clone)]
+pub struct ShareLogger {
+    sender: Sender<ShareRecord>,
+}
+
+impl ShareLogger {
+    /// Spawns the task that writes the shares to `sink` every `batch_size` shares or every
+    /// `flush_interval`, whichever comes first. Must be called inside a tokio runtime.
+    pub fn start(sink: Box<dyn ShareSink>, batch_size: usize, flush_interval: Duration) -> Self {
+        let (sender, receiver) = unbounded();
+        task::spawn(Self::run(sink, receiver, batch_size.max(1), flush_interval));
+        Self { sender }
+    }
+
+    pub fn from_config(config: &ShareLogConfig) -> io::Result<Self> {
+        Ok(Self::start(
+            open_sink(config)?,
+            config.batch_size,
+            Duration::from_secs(config.flush_interval_sec),
+        ))
+    }
+
+    pub fn log(&self, share: ShareRecord) {
+        if self.sender.try_send(share).is_err() {
+            error!("Share log writer stopped, share not logged");
+        }
+    }
+
+    async fn run(
+        mut sink: Box<dyn ShareSink>,
+        receiver: Receiver<ShareRecord>,
+        batch_size: usize,
+        flush_interval: Duration,
+    ) {
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut closed = false;
+        while !closed {
+            let deadline = tokio::time::Instant::now() + flush_interval;
+            while batch.len() < batch_size {
+                match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                    Ok(Ok(share)) => batch.push(share),
+                    Ok(Err(_)) => {
+                        closed = true;
+                        break;
+                    }
+                    Err(_) => break,
+                }
+            }
+            if batch.is_empty() {
+                continue;
+            }
+            match sink.write(&batch).and_then(|_| sink.flush()) {
+                Ok(()) => batch.clear(),
+                // The batch is kept and written again after the flush interval
+                Err(e) => {
+                    error!("Failed to write {} shares: {}", batch.len(), e);
+                    tokio::time::sleep(flush_interval).await;
+                }
+            }
+        }
+        if !batch.is_empty() {
+            warn!("Share log closed, {} shares not written", batch.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn share(sequence_number: u32, user_identity: &str, outcome: ShareOutcome) -> ShareRecord {
+        ShareRecord {
+            timestamp: 1_700_000_000_000,
+            channel_id: 1,
+            user_identity: user_identity.to_string(),
+            sequence_number,
+            job_id: 7,
+            difficulty: 1.5,
+            share_difficulty: 300.25,
+            outcome,
+            block_found: sequence_number == 2,
+        }
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_binary_share_log_roundtrip() {
+        let path = temp_path("shares.bin");
+        let shares = vec![
+            share(1, "alice", ShareOutcome::Accepted),
+            share(2, "bob.worker1", ShareOutcome::Stale),
+            share(3, "", ShareOutcome::Rejected),
+        ];
+        let mut sink = BinaryShareSink::open(&path).unwrap();
+        sink.write(&shares[..2]).unwrap();
+        sink.flush().unwrap();
+        // Reopening appends to the existing log
+        let mut sink = BinaryShareSink::open(&path).unwrap();
+        sink.write(&shares[2..]).unwrap();
+        sink.flush().unwrap();
+        assert_eq!(read_binary_share_log(&path).unwrap(), shares);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_csv_share_log() {
+        let path = temp_path("shares.csv");
+        let mut sink = CsvShareSink::open(&path).unwrap();
+        sink.write(&[share(1, "a\"b", ShareOutcome::Accepted)])
+            .unwrap();
+        sink.flush().unwrap();
+        let mut sink = CsvShareSink::open(&path).unwrap();
+        sink.write(&[share(2, "c", ShareOutcome::Stale)]).unwrap();
+        sink.flush().unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                CSV_HEADER,
+                "1700000000000,1,\"a\"\"b\",1,7,1.5,300.25,accepted,false",
+                "1700000000000,1,\"c\",2,7,1.5,300.25,stale,true",
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_share_logger_batches() {
+        let path = temp_path("shares-logger.bin");
+        let logger = ShareLogger::start(
+            Box::new(BinaryShareSink::open(&path).unwrap()),
+            2,
+            Duration::from_millis(50),
+        );
+        logger.log(share(1, "alice", ShareOutcome::Accepted));
+        logger.log(share(2, "alice", ShareOutcome::Accepted));
+        logger.log(share(3, "alice", ShareOutcome::Accepted));
+        // The first two shares fill a batch, the third is written on the flush interval
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let shares = read_binary_share_log(&path).unwrap();
+        assert_eq!(
+            shares.iter().map(|s| s.sequence_number).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,487 @@
+//! Share accounting and per-miner statistics.
+//!
+//! [`PoolStats`] counts the accepted, rejected and stale shares, keeps the best share difficulty
+//! and estimates the hashrate of every channel and of every user identity, over the configured
+//! window and over the last 10 minutes and the last hour. Snapshots of the
+//! statistics can be received on a channel (see [`crate::PoolSv2::with_stats_sender`]) or fetched
+//! as JSON over HTTP at `stats.http_address`. They also have the histogram of the time between the
+//! reception of a share that solves a block and the sending of the solution to the Template
+//! Provider.
+use roles_logic_sv2::{difficulty, mining_sv2::SubmitSharesError};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StatsConfig {
+    /// Address where the statistics are served as JSON over HTTP, not served if not set
+    pub http_address: Option<String>,
+    /// How often a snapshot is sent to the stats sender
+    #[serde(default = "default_snapshot_interval_sec")]
+    pub snapshot_interval_sec: u64,
+    /// Time over which the hashrate is estimated
+    #[serde(default = "default_hashrate_window_sec")]
+    pub hashrate_window_sec: u64,
+    /// How often the target of every channel is recalculated from its 10 minutes hashrate, the
+    /// targets only change on `UpdateChannel` if not set
+    pub retarget_interval_sec: Option<u64>,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            http_address: None,
+            snapshot_interval_sec: default_snapshot_interval_sec(),
+            hashrate_window_sec: default_hashrate_window_sec(),
+            retarget_interval_sec: None,
+        }
+    }
+}
+
+fn default_snapshot_interval_sec() -> u64 {
+    60
+}
+
+fn default_hashrate_window_sec() -> u64 {
+    600
+}
+
+pub const HASHRATE_WINDOW_10M: Duration = Duration::from_secs(600);
+pub const HASHRATE_WINDOW_1H: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareOutcome {
+    Accepted,
+    Rejected,
+    /// Share for a job that is not valid anymore
+    Stale,
+}
+
+impl ShareOutcome {
+    /// Outcome of a share refused with `error`, `invalid-job-id` is counted as stale as that is
+    /// what the pool answers to shares for the jobs of a previous prev hash
+    pub fn from_error(error: &SubmitSharesError) -> Self {
+        let error_code = std::str::from_utf8(error.error_code.as_ref()).unwrap_or("");
+        if error_code == SubmitSharesError::stale_share_error_code()
+            || error_code == SubmitSharesError::invalid_job_id_error_code()
+        {
+            ShareOutcome::Stale
+        } else {
+            ShareOutcome::Rejected
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct ShareStats {
+    pub accepted: u64,
+    pub rejected: u64,
+    pub stale: u64,
+    pub best_share_difficulty: f64,
+    /// Estimated hashrate in H/s, over `hashrate_window_sec`
+    pub hashrate: f64,
+    /// Estimated hashrate in H/s, over the last 10 minutes
+    pub hashrate_10m: f64,
+    /// Estimated hashrate in H/s, over the last hour
+    pub hashrate_1h: f64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ChannelStats {
+    pub user_identity: String,
+    #[serde(flatten)]
+    pub shares: ShareStats,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StatsSnapshot {
+    /// Unix time of the snapshot
+    pub timestamp: u64,
+    pub channels: HashMap<u32, ChannelStats>,
+    pub users: HashMap<String, ShareStats>,
+    /// From the reception of the share to the `SubmitSolution` sent to the Template Provider
+    pub solution_latency: LatencyHistogram,
+}
+
+/// Upper bounds of the buckets of [`LatencyHistogram`], in milliseconds
+pub const LATENCY_BUCKETS_MS: [f64; 10] =
+    [1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0];
+
+/// Histogram of latencies, the buckets are cumulative: `buckets[i]` counts the latencies lower or
+/// equal to `LATENCY_BUCKETS_MS[i]`, the latencies above the last bound are only in `count`
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LatencyHistogram {
+    pub bounds_ms: Vec<f64>,
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub sum_ms: f64,
+    pub max_ms: f64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            bounds_ms: LATENCY_BUCKETS_MS.to_vec(),
+            buckets: vec![0; LATENCY_BUCKETS_MS.len()],
+            count: 0,
+            sum_ms: 0.0,
+            max_ms: 0.0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn observe(&mut self, latency: Duration) {
+        let ms = latency.as_secs_f64() * 1000.0;
+        for (bound, bucket) in self.bounds_ms.iter().zip(self.buckets.iter_mut()) {
+            if ms <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    stats: ShareStats,
+    // (time, difficulty) of the accepted shares in the longest hashrate window
+    accepted_shares: VecDeque<(Instant, f64)>,
+}
+
+impl Counters {
+    fn on_share(&mut self, outcome: ShareOutcome, credited: f64, difficulty: f64, now: Instant) {
+        match outcome {
+            ShareOutcome::Accepted => {
+                self.stats.accepted += 1;
+                self.accepted_shares.push_back((now, credited));
+                if difficulty > self.stats.best_share_difficulty {
+                    self.stats.best_share_difficulty = difficulty;
+                }
+            }
+            ShareOutcome::Rejected => self.stats.rejected += 1,
+            ShareOutcome::Stale => self.stats.stale += 1,
+        }
+    }
+
+    /// Work of the accepted shares received in the last `window`
+    fn work(&self, window: Duration, now: Instant) -> f64 {
+        self.accepted_shares
+            .iter()
+            .rev()
+            .take_while(|(time, _)| now.saturating_duration_since(*time) < window)
+            .map(|(_, d)| d)
+            .sum()
+    }
+
+    /// Forgets the shares older than `retention`
+    fn prune(&mut self, retention: Duration, now: Instant) {
+        while let Some((time, _)) = self.accepted_shares.front() {
+            if now.saturating_duration_since(*time) < retention {
+                break;
+            }
+            self.accepted_shares.pop_front();
+        }
+    }
+
+    fn snapshot(&mut self, window: Duration, now: Instant) -> ShareStats {
+        self.prune(window.max(HASHRATE_WINDOW_1H), now);
+        let mut stats = self.stats.clone();
+        stats.hashrate = hashrate(self.work(window, now), window);
+        stats.hashrate_10m = hashrate(self.work(HASHRATE_WINDOW_10M, now), HASHRATE_WINDOW_10M);
+        stats.hashrate_1h = hashrate(self.work(HASHRATE_WINDOW_1H, now), HASHRATE_WINDOW_1H);
+        stats
+    }
+}
+
+#[derive(Debug)]
+struct Channel {
+    user_identity: String,
+    // difficulty of the channel target, credited for every accepted share
+    target_difficulty: f64,
+    opened_at: Instant,
+    counters: Counters,
+}
+
+#[derive(Debug)]
+pub struct PoolStats {
+    hashrate_window: Duration,
+    channels: HashMap<u32, Channel>,
+    users: HashMap<String, Counters>,
+    solution_latency: LatencyHistogram,
+}
+
+impl PoolStats {
+    pub fn new(hashrate_window: Duration) -> Self {
+        Self {
+            hashrate_window,
+            channels: HashMap::new(),
+            users: HashMap::new(),
+            solution_latency: LatencyHistogram::default(),
+        }
+    }
+
+    /// `target` is the little endian target sent to the downstream
+    pub fn open_channel(&mut self, channel_id: u32, user_identity: &str, target: &[u8]) {
+        self.open_channel_at(channel_id, user_identity, target, Instant::now())
+    }
+
+    fn open_channel_at(
+        &mut self,
+        channel_id: u32,
+        user_identity: &str,
+        target: &[u8],
+        now: Instant,
+    ) {
+        self.channels.insert(
+            channel_id,
+            Channel {
+                user_identity: user_identity.to_string(),
+                target_difficulty: target_to_difficulty(target),
+                opened_at: now,
+                counters: Counters::default(),
+            },
+        );
+    }
+
+    pub fn set_target(&mut self, channel_id: u32, target: &[u8]) {
+        if let Some(channel) = self.channels.get_mut(&channel_id) {
+            channel.target_difficulty = target_to_difficulty(target);
+        }
+    }
+
+    /// User identity and target difficulty of `channel_id`
+    pub fn channel(&self, channel_id: u32) -> Option<(&str, f64)> {
+        self.channels
+            .get(&channel_id)
+            .map(|c| (c.user_identity.as_str(), c.target_difficulty))
+    }
+
+    /// Estimated hashrate of `channel_id` over the last `window`, or since the channel has been
+    /// opened if it is younger. `window` can not be longer than one hour.
+    pub fn channel_hashrate(&self, channel_id: u32, window: Duration) -> Option<f64> {
+        self.channel_hashrate_at(channel_id, window, Instant::now())
+    }
+
+    fn channel_hashrate_at(&self, channel_id: u32, window: Duration, now: Instant) -> Option<f64> {
+        let channel = self.channels.get(&channel_id)?;
+        let age = now.saturating_duration_since(channel.opened_at);
+        let work = channel.counters.work(window, now);
+        Some(hashrate(work, window.min(age)))
+    }
+
+    /// `hash` is the little endian hash of the share, if known
+    pub fn on_share(&mut self, channel_id: u32, outcome: ShareOutcome, hash: Option<[u8; 32]>) {
+        self.on_share_at(channel_id, outcome, hash, Instant::now())
+    }
+
+    fn on_share_at(
+        &mut self,
+        channel_id: u32,
+        outcome: ShareOutcome,
+        hash: Option<[u8; 32]>,
+        now: Instant,
+    ) {
+        let channel = match self.channels.get_mut(&channel_id) {
+            Some(channel) => channel,
+            None => return,
+        };
+        let credited = channel.target_difficulty;
+        let difficulty = hash
+            .map(|hash| target_to_difficulty(&hash))
+            .unwrap_or(credited);
+        channel
+            .counters
+            .on_share(outcome, credited, difficulty, now);
+        self.users
+            .entry(channel.user_identity.clone())
+            .or_default()
+            .on_share(outcome, credited, difficulty, now);
+    }
+
+    /// `latency` is the time from the reception of the share to the solution sent to the Template
+    /// Provider
+    pub fn on_solution_sent(&mut self, latency: Duration) {
+        self.solution_latency.observe(latency);
+    }
+
+    pub fn snapshot(&mut self) -> StatsSnapshot {
+        self.snapshot_at(Instant::now())
+    }
+
+    fn snapshot_at(&mut self, now: Instant) -> StatsSnapshot {
+        let window = self.hashrate_window;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        StatsSnapshot {
+            timestamp,
+            channels: self
+                .channels
+                .iter_mut()
+                .map(|(id, channel)| {
+                    (
+                        *id,
+                        ChannelStats {
+                            user_identity: channel.user_identity.clone(),
+                            shares: channel.counters.snapshot(window, now),
+                        },
+                    )
+                })
+                .collect(),
+            users: self
+                .users
+                .iter_mut()
+                .map(|(user, counters)| (user.clone(), counters.snapshot(window, now)))
+                .collect(),
+            solution_latency: self.solution_latency.clone(),
+        }
+    }
+}
+
+/// Hashrate in H/s that produces `work`, the sum of the difficulties of the shares, in `window`
+fn hashrate(work: f64, window: Duration) -> f64 {
+    if window.is_zero() {
+        return 0.0;
+    }
+    work * 2_f64.powi(32) / window.as_secs_f64()
+}
+
+/// Difficulty of a little endian 256 bits target or hash, `f64::MAX` for a zero target
+pub fn target_to_difficulty(target: &[u8]) -> f64 {
+    let mut bytes = [0_u8; 32];
+    let len = target.len().min(32);
+    bytes[..len].copy_from_slice(&target[..len]);
+    difficulty::target_to_difficulty(&bytes.into()).unwrap_or(f64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn difficulty_1_target() -> [u8; 32] {
+        let mut target = [0; 32];
+        target[26] = 0xff;
+        target[27] = 0xff;
+        target
+    }
+
+    #[test]
+    fn test_target_to_difficulty() {
+        assert!((target_to_difficulty(&difficulty_1_target()) - 1.0).abs() < 1e-9);
+        let mut target = [0; 32];
+        target[25] = 0xff;
+        target[26] = 0xff;
+        assert!((target_to_difficulty(&target) - 256.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_share_accounting() {
+        let mut stats = PoolStats::new(Duration::from_secs(60));
+        let now = Instant::now();
+        stats.open_channel(1, "alice", &difficulty_1_target());
+        stats.open_channel(2, "alice", &difficulty_1_target());
+        let mut best = [0; 32];
+        best[25] = 0xff;
+        best[26] = 0xff;
+        stats.on_share_at(1, ShareOutcome::Accepted, Some(best), now);
+        stats.on_share_at(1, ShareOutcome::Stale, None, now);
+        stats.on_share_at(2, ShareOutcome::Accepted, None, now);
+        stats.on_share_at(2, ShareOutcome::Rejected, None, now);
+        // Unknown channel
+        stats.on_share_at(3, ShareOutcome::Accepted, None, now);
+
+        let snapshot = stats.snapshot_at(now);
+        let channel = &snapshot.channels[&1];
+        assert_eq!(channel.user_identity, "alice");
+        assert_eq!(channel.shares.accepted, 1);
+        assert_eq!(channel.shares.stale, 1);
+        assert!((channel.shares.best_share_difficulty - 256.0).abs() < 1e-6);
+        let user = &snapshot.users["alice"];
+        assert_eq!((user.accepted, user.rejected, user.stale), (2, 1, 1));
+        assert!((user.hashrate - 2.0 * 2_f64.powi(32) / 60.0).abs() < 1e-3);
+
+        // Shares out of the window do not count for the hashrate
+        let snapshot = stats.snapshot_at(now + Duration::from_secs(60));
+        assert_eq!(snapshot.users["alice"].hashrate, 0.0);
+        assert_eq!(snapshot.users["alice"].accepted, 2);
+    }
+
+    #[test]
+    fn test_hashrate_windows() {
+        let mut stats = PoolStats::new(Duration::from_secs(60));
+        let start = Instant::now();
+        stats.open_channel_at(1, "alice", &difficulty_1_target(), start);
+        // One share every 5 minutes for an hour
+        for i in 1..=12 {
+            stats.on_share_at(
+                1,
+                ShareOutcome::Accepted,
+                None,
+                start + Duration::from_secs(i * 300),
+            );
+        }
+        let now = start + Duration::from_secs(3600);
+        let snapshot = stats.snapshot_at(now);
+        let shares = &snapshot.channels[&1].shares;
+        assert!((shares.hashrate - 2_f64.powi(32) / 60.0).abs() < 1e-3);
+        // The shares at 55 and 60 minutes
+        assert!((shares.hashrate_10m - 2.0 * 2_f64.powi(32) / 600.0).abs() < 1e-3);
+        assert!((shares.hashrate_1h - 12.0 * 2_f64.powi(32) / 3600.0).abs() < 1e-3);
+
+        let hashrate = stats
+            .channel_hashrate_at(1, HASHRATE_WINDOW_10M, now)
+            .unwrap();
+        assert!((hashrate - shares.hashrate_10m).abs() < 1e-3);
+        // A channel younger than the window is estimated over its age
+        stats.open_channel_at(2, "bob", &difficulty_1_target(), now);
+        stats.on_share_at(2, ShareOutcome::Accepted, None, now);
+        let later = now + Duration::from_secs(100);
+        let hashrate = stats
+            .channel_hashrate_at(2, HASHRATE_WINDOW_10M, later)
+            .unwrap();
+        assert!((hashrate - 2_f64.powi(32) / 100.0).abs() < 1e-3);
+        assert!(stats.channel_hashrate(3, HASHRATE_WINDOW_10M).is_none());
+    }
+
+    #[test]
+    fn test_solution_latency() {
+        let mut stats = PoolStats::new(Duration::from_secs(60));
+        stats.on_solution_sent(Duration::from_micros(800));
+        stats.on_solution_sent(Duration::from_millis(30));
+        stats.on_solution_sent(Duration::from_secs(2));
+        let latency = stats.snapshot().solution_latency;
+        assert_eq!(latency.count, 3);
+        // <= 1ms, 2ms, 5ms, 10ms, 25ms, 50ms ... 1000ms
+        assert_eq!(latency.buckets, vec![1, 1, 1, 1, 1, 2, 2, 2, 2, 2]);
+        assert!((latency.sum_ms - 2030.8).abs() < 1e-6);
+        assert!((latency.max_ms - 2000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_share_outcome_from_error() {
+        let error = |code: &str| SubmitSharesError {
+            channel_id: 1,
+            sequence_number: 0,
+            error_code: code.to_string().try_into().unwrap(),
+        };
+        assert_eq!(
+            ShareOutcome::from_error(&error("stale-share")),
+            ShareOutcome::Stale
+        );
+        assert_eq!(
+            ShareOutcome::from_error(&error("invalid-job-id")),
+            ShareOutcome::Stale
+        );
+        assert_eq!(
+            ShareOutcome::from_error(&error("difficulty-too-low")),
+            ShareOutcome::Rejected
+        );
+    }
+}
@@ -66,7 +66,9 @@ async fn send_status(
 ) -> error_handling::ErrorBranch {
     match sender {
         Sender::Downstream(tx) => match e {
-            PoolError::Sv2ProtocolError((id, Mining::OpenMiningChannelError(_))) => {
+            PoolError::Sv2ProtocolError((id, Mining::OpenMiningChannelError(_)))
+            | PoolError::RateLimited(id)
+            | PoolError::SlowDownstream(id) => {
                 tx.send(Status {
                     state: State::DownstreamInstanceDropped(id),
                 })
@@ -90,6 +92,14 @@ async fn send_status(
                 .await
                 .unwrap_or(());
             }
+            // Sent while broadcasting to the downstreams, only the slow one is dropped
+            PoolError::SlowDownstream(id) => {
+                tx.send(Status {
+                    state: State::DownstreamInstanceDropped(id),
+                })
+                .await
+                .unwrap_or(());
+            }
             _ => {
                 tx.send(Status {
                     state: State::DownstreamShutdown(e),
@@ -146,5 +156,12 @@ pub async fn handle_error(sender: &Sender, e: PoolError) -> error_handling::Erro
         PoolError::Sv2ProtocolError(_) => {
             send_status(sender, e, error_handling::ErrorBranch::Break).await
         }
+        PoolError::RateLimited(_) => {
+            send_status(sender, e, error_handling::ErrorBranch::Break).await
+        }
+        // Continue so that the other downstreams still get the message being broadcast
+        PoolError::SlowDownstream(_) => {
+            send_status(sender, e, error_handling::ErrorBranch::Continue).await
+        }
     }
 }
@@ -0,0 +1,165 @@
+//! SV1 listener embedded in the pool.
+//!
+//! Small deployments can serve SV1 miners without running a separate translator: when
+//! `[sv1_listener]` is set the pool runs the translator in process. Its `Bridge` opens an extended
+//! channel on one of the pool listeners, so the SV1 miners get their jobs from the same templates,
+//! their shares are validated and counted in the statistics as the ones of that channel.
+use super::mining_pool::Configuration;
+use key_utils::Secp256k1PublicKey;
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::task;
+use tracing::info;
+use translator_sv2::{
+    proxy_config::{
+        DownstreamConfig, DownstreamDifficultyConfig, ProxyConfig, UpstreamConfig,
+        UpstreamDifficultyConfig,
+    },
+    TranslatorSv2,
+};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Sv1ListenerConfig {
+    /// Address on which the SV1 miners connect
+    pub listen_address: String,
+    /// Pool listener the bridge connects to, `listen_address` of the pool if not set. It must use
+    /// noise. The channel of the bridge is opened for a single user, when `[auth]` is set use a
+    /// loopback `[[listeners]]` with `auth_required = false`.
+    pub pool_address: Option<String>,
+    /// Hashrate used for the first difficulty of a SV1 miner
+    pub min_individual_miner_hashrate: f32,
+    /// The difficulty of every SV1 miner is updated to get this many shares per minute
+    #[serde(default = "default_shares_per_minute")]
+    pub shares_per_minute: f32,
+    /// How often the hashrate of the channel of the bridge is updated
+    #[serde(default = "default_channel_diff_update_interval")]
+    pub channel_diff_update_interval: u32,
+    #[serde(default = "default_min_extranonce2_size")]
+    pub min_extranonce2_size: u16,
+}
+
+fn default_shares_per_minute() -> f32 {
+    6.0
+}
+
+fn default_channel_diff_update_interval() -> u32 {
+    60
+}
+
+fn default_min_extranonce2_size() -> u16 {
+    8
+}
+
+impl Sv1ListenerConfig {
+    pub fn new(listen_address: String, min_individual_miner_hashrate: f32) -> Self {
+        Self {
+            listen_address,
+            pool_address: None,
+            min_individual_miner_hashrate,
+            shares_per_minute: default_shares_per_minute(),
+            channel_diff_update_interval: default_channel_diff_update_interval(),
+            min_extranonce2_size: default_min_extranonce2_size(),
+        }
+    }
+
+    /// Config of the translator, connected to `pool_address` or to the `listen_address` of the
+    /// pool, on loopback if the pool listens on every interface
+    pub fn proxy_config(
+        &self,
+        pool_listen_address: &str,
+        authority_public_key: Secp256k1PublicKey,
+    ) -> Result<ProxyConfig, String> {
+        let mut upstream: SocketAddr = self
+            .pool_address
+            .as_deref()
+            .unwrap_or(pool_listen_address)
+            .parse()
+            .map_err(|e| format!("Invalid pool address for the SV1 listener: {}", e))?;
+        if upstream.ip().is_unspecified() {
+            upstream.set_ip(match upstream.ip() {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+        let downstream: SocketAddr = self
+            .listen_address
+            .parse()
+            .map_err(|e| format!("Invalid SV1 listen address: {}", e))?;
+        Ok(ProxyConfig::new(
+            UpstreamConfig::new(
+                upstream.ip().to_string(),
+                upstream.port(),
+                authority_public_key,
+                UpstreamDifficultyConfig::new(
+                    self.channel_diff_update_interval,
+                    self.min_individual_miner_hashrate,
+                    0,
+                    false,
+                ),
+            ),
+            DownstreamConfig::new(
+                downstream.ip().to_string(),
+                downstream.port(),
+                DownstreamDifficultyConfig::new(
+                    self.min_individual_miner_hashrate,
+                    self.shares_per_minute,
+                    0,
+                    0,
+                ),
+            ),
+            2,
+            2,
+            self.min_extranonce2_size,
+        ))
+    }
+}
+
+/// Starts the translator, it connects to the pool once its listeners are up and reconnects if the
+/// connection is lost
+pub fn start(config: &Configuration, sv1_listener: &Sv1ListenerConfig) -> Result<(), String> {
+    let proxy_config =
+        sv1_listener.proxy_config(&config.listen_address, config.authority_public_key)?;
+    info!(
+        "Listening for SV1 miners on {}",
+        sv1_listener.listen_address
+    );
+    task::spawn(TranslatorSv2::new(proxy_config).start());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn public_key() -> Secp256k1PublicKey {
+        "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72"
+            .to_string()
+            .try_into()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_proxy_config() {
+        let mut sv1_listener = Sv1ListenerConfig::new("0.0.0.0:34255".to_string(), 1_000_000.0);
+        let proxy_config = sv1_listener
+            .proxy_config("0.0.0.0:34254", public_key())
+            .unwrap();
+        assert_eq!(proxy_config.upstream_address, "127.0.0.1");
+        assert_eq!(proxy_config.upstream_port, 34254);
+        assert_eq!(proxy_config.downstream_address, "0.0.0.0");
+        assert_eq!(proxy_config.downstream_port, 34255);
+        assert_eq!(proxy_config.min_extranonce2_size, 8);
+
+        sv1_listener.pool_address = Some("127.0.0.1:34256".to_string());
+        let proxy_config = sv1_listener
+            .proxy_config("0.0.0.0:34254", public_key())
+            .unwrap();
+        assert_eq!(proxy_config.upstream_port, 34256);
+
+        sv1_listener.listen_address = "34255".to_string();
+        assert!(sv1_listener
+            .proxy_config("0.0.0.0:34254", public_key())
+            .is_err());
+    }
+}
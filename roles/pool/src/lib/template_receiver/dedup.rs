@@ -0,0 +1,334 @@
+//! Deduplication of the messages of the Template Providers.
+//!
+//! The pool can fail over to another Template Provider, or reconnect to the same one after a
+//! restart. Template ids are only unique per connection, so every template gets an id assigned
+//! by the pool and solutions are mapped back to the id of the connected Template Provider.
+//!
+//! After a connection the Template Provider sends a future template and a SetNewPrevHash. If the
+//! prev hash is the one the pool is already mining on, the SetNewPrevHash is dropped and the
+//! template is forwarded as a non future template, so the miners switch to it without being
+//! asked to discard their work. A template identical to the last forwarded one is dropped and
+//! solutions for the last one are sent to the new Template Provider.
+//!
+//! Templates without enough space for the pool outputs are skipped. The SetNewPrevHash of a
+//! skipped future template is held and activated with the next template on that prev hash, so
+//! the pool never forwards a prev hash whose template it does not know.
+use binary_sv2::U256;
+use roles_logic_sv2::template_distribution_sv2::{NewTemplate, SetNewPrevHash};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, warn};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Forward {
+    Template(NewTemplate<'static>),
+    PrevHash(SetNewPrevHash<'static>),
+}
+
+#[derive(Debug)]
+pub struct TemplateDedup {
+    next_id: u64,
+    // id used by the Template Provider -> id used by the pool
+    pool_ids: HashMap<u64, u64>,
+    // id used by the pool -> id used by the Template Provider
+    tp_ids: HashMap<u64, u64>,
+    last_prev_hash: Option<U256<'static>>,
+    // last non future template forwarded on `last_prev_hash`
+    last_template: Option<NewTemplate<'static>>,
+    // future templates received after a connection, held until their prev hash is known
+    held: Vec<NewTemplate<'static>>,
+    reconnected: bool,
+    // ids used by the Template Provider of the skipped templates
+    skipped: HashSet<u64>,
+    // SetNewPrevHash of a skipped future template
+    pending_prev_hash: Option<SetNewPrevHash<'static>>,
+}
+
+impl Default for TemplateDedup {
+    fn default() -> Self {
+        Self {
+            next_id: 1,
+            pool_ids: HashMap::new(),
+            tp_ids: HashMap::new(),
+            last_prev_hash: None,
+            last_template: None,
+            held: Vec::new(),
+            reconnected: false,
+            skipped: HashSet::new(),
+            pending_prev_hash: None,
+        }
+    }
+}
+
+impl TemplateDedup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Must be called every time the pool connects to a Template Provider
+    pub fn on_connection(&mut self) {
+        self.pool_ids.clear();
+        self.tp_ids.clear();
+        self.held.clear();
+        self.skipped.clear();
+        self.pending_prev_hash = None;
+        self.reconnected = self.last_prev_hash.is_some();
+    }
+
+    /// Must be called for the templates that are not passed to [`Self::on_new_template`]
+    pub fn on_skipped_template(&mut self, template: &NewTemplate) {
+        self.skipped.insert(template.template_id);
+    }
+
+    /// Id of the template `pool_id` for the connected Template Provider, `None` if the template
+    /// comes from a previous connection
+    pub fn tp_template_id(&self, pool_id: u64) -> Option<u64> {
+        self.tp_ids.get(&pool_id).copied()
+    }
+
+    pub fn on_new_template(&mut self, mut template: NewTemplate<'static>) -> Vec<Forward> {
+        let tp_id = template.template_id;
+        if !template.future_template {
+            if let Some(mut prev_hash) = self.pending_prev_hash.take() {
+                // The first usable template on the prev hash of a skipped future template
+                debug!("Activating the held prev hash with template {}", tp_id);
+                template.future_template = true;
+                prev_hash.template_id = tp_id;
+                let mut forward = self.on_new_template(template);
+                forward.extend(self.on_set_new_prev_hash(prev_hash));
+                return forward;
+            }
+        }
+        if !template.future_template {
+            if let Some(last) = &self.last_template {
+                if same_template(last, &template) {
+                    debug!("Dropping duplicated template {}", tp_id);
+                    let pool_id = last.template_id;
+                    self.map_id(tp_id, pool_id);
+                    return vec![];
+                }
+            }
+        }
+        let pool_id = self.next_id;
+        self.next_id += 1;
+        self.map_id(tp_id, pool_id);
+        template.template_id = pool_id;
+        if template.future_template {
+            if self.reconnected {
+                self.held.push(template);
+                return vec![];
+            }
+        } else {
+            self.last_template = Some(template.clone());
+        }
+        vec![Forward::Template(template)]
+    }
+
+    pub fn on_set_new_prev_hash(&mut self, mut prev_hash: SetNewPrevHash<'static>) -> Vec<Forward> {
+        if self.skipped.contains(&prev_hash.template_id) {
+            warn!(
+                "Holding SetNewPrevHash of skipped template {} until a usable template is received",
+                prev_hash.template_id
+            );
+            self.pending_prev_hash = Some(prev_hash);
+            return vec![];
+        }
+        let pool_id = match self.pool_ids.get(&prev_hash.template_id) {
+            Some(pool_id) => *pool_id,
+            None => {
+                warn!(
+                    "Dropping SetNewPrevHash for unknown template {}",
+                    prev_hash.template_id
+                );
+                return vec![];
+            }
+        };
+        let tp_id = prev_hash.template_id;
+        prev_hash.template_id = pool_id;
+        self.pending_prev_hash = None;
+        self.skipped.retain(|id| *id > tp_id);
+        let held = std::mem::take(&mut self.held);
+        self.reconnected = false;
+        if self.last_prev_hash.as_ref() == Some(&prev_hash.prev_hash) {
+            // Already mining on this prev hash, only the template changes
+            let template = held.into_iter().find(|t| t.template_id == pool_id);
+            return match template {
+                Some(mut template) => {
+                    template.future_template = false;
+                    match &self.last_template {
+                        Some(last) if same_template(last, &template) => {
+                            let last_id = last.template_id;
+                            let tp_id = self.tp_ids[&pool_id];
+                            self.map_id(tp_id, last_id);
+                            vec![]
+                        }
+                        _ => {
+                            self.last_template = Some(template.clone());
+                            vec![Forward::Template(template)]
+                        }
+                    }
+                }
+                None => {
+                    debug!("Dropping duplicated SetNewPrevHash");
+                    vec![]
+                }
+            };
+        }
+        self.last_prev_hash = Some(prev_hash.prev_hash.clone());
+        self.last_template = None;
+        // Templates older than the activated one can not be mined anymore
+        self.tp_ids.retain(|id, _| *id >= pool_id);
+        self.pool_ids.retain(|_, id| *id >= pool_id);
+        let mut forward: Vec<Forward> = held.into_iter().map(Forward::Template).collect();
+        forward.push(Forward::PrevHash(prev_hash));
+        forward
+    }
+
+    fn map_id(&mut self, tp_id: u64, pool_id: u64) {
+        self.pool_ids.insert(tp_id, pool_id);
+        self.tp_ids.insert(pool_id, tp_id);
+    }
+}
+
+// Templates are the same if only the id differs
+fn same_template(a: &NewTemplate<'static>, b: &NewTemplate<'static>) -> bool {
+    let mut b = b.clone();
+    b.template_id = a.template_id;
+    b.future_template = a.future_template;
+    *a == b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binary_sv2::Seq0255;
+    use std::convert::TryInto;
+
+    fn template(template_id: u64, future_template: bool, value: u64) -> NewTemplate<'static> {
+        NewTemplate {
+            template_id,
+            future_template,
+            version: 0x2000_0000,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![3, 1, 0, 0].try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: value,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+        }
+    }
+
+    fn prev_hash(template_id: u64, hash: u8) -> SetNewPrevHash<'static> {
+        SetNewPrevHash {
+            template_id,
+            prev_hash: [hash; 32].into(),
+            header_timestamp: 0,
+            n_bits: 0,
+            target: [0; 32].into(),
+        }
+    }
+
+    #[test]
+    fn test_ids_are_mapped() {
+        let mut dedup = TemplateDedup::new();
+        dedup.on_connection();
+        assert_eq!(
+            dedup.on_new_template(template(10, true, 1)),
+            vec![Forward::Template(template(1, true, 1))]
+        );
+        assert_eq!(
+            dedup.on_set_new_prev_hash(prev_hash(10, 1)),
+            vec![Forward::PrevHash(prev_hash(1, 1))]
+        );
+        assert_eq!(
+            dedup.on_new_template(template(11, false, 2)),
+            vec![Forward::Template(template(2, false, 2))]
+        );
+        assert_eq!(dedup.tp_template_id(2), Some(11));
+        // Unknown template
+        assert!(dedup.on_set_new_prev_hash(prev_hash(12, 2)).is_empty());
+    }
+
+    #[test]
+    fn test_duplicated_template_is_dropped() {
+        let mut dedup = TemplateDedup::new();
+        dedup.on_new_template(template(10, true, 1));
+        dedup.on_set_new_prev_hash(prev_hash(10, 1));
+        dedup.on_new_template(template(11, false, 2));
+        assert!(dedup.on_new_template(template(12, false, 2)).is_empty());
+        // Solutions for the forwarded template can use the id of any of the two
+        assert_eq!(dedup.tp_template_id(2), Some(12));
+    }
+
+    #[test]
+    fn test_reconnection_on_same_prev_hash() {
+        let mut dedup = TemplateDedup::new();
+        dedup.on_new_template(template(10, true, 1));
+        dedup.on_set_new_prev_hash(prev_hash(10, 1));
+
+        // The new TP sends a better template for the same prev hash
+        dedup.on_connection();
+        assert_eq!(dedup.tp_template_id(1), None);
+        assert!(dedup.on_new_template(template(5, true, 2)).is_empty());
+        assert_eq!(
+            dedup.on_set_new_prev_hash(prev_hash(5, 1)),
+            vec![Forward::Template(template(2, false, 2))]
+        );
+        assert_eq!(dedup.tp_template_id(2), Some(5));
+
+        // The TP restarts and sends the same template again
+        dedup.on_connection();
+        assert!(dedup.on_new_template(template(1, true, 2)).is_empty());
+        assert!(dedup.on_set_new_prev_hash(prev_hash(1, 1)).is_empty());
+        assert_eq!(dedup.tp_template_id(2), Some(1));
+    }
+
+    #[test]
+    fn test_reconnection_on_new_prev_hash() {
+        let mut dedup = TemplateDedup::new();
+        dedup.on_new_template(template(10, true, 1));
+        dedup.on_set_new_prev_hash(prev_hash(10, 1));
+
+        dedup.on_connection();
+        assert!(dedup.on_new_template(template(5, true, 2)).is_empty());
+        assert_eq!(
+            dedup.on_set_new_prev_hash(prev_hash(5, 2)),
+            vec![
+                Forward::Template(template(2, true, 2)),
+                Forward::PrevHash(prev_hash(2, 2))
+            ]
+        );
+        // Future templates are forwarded right away once the prev hash is known
+        assert_eq!(
+            dedup.on_new_template(template(6, true, 3)),
+            vec![Forward::Template(template(3, true, 3))]
+        );
+    }
+
+    #[test]
+    fn test_prev_hash_of_skipped_template_is_held() {
+        let mut dedup = TemplateDedup::new();
+        dedup.on_new_template(template(10, true, 1));
+        dedup.on_set_new_prev_hash(prev_hash(10, 1));
+
+        // The future template has not enough space for the pool outputs
+        dedup.on_skipped_template(&template(11, true, 2));
+        assert!(dedup.on_set_new_prev_hash(prev_hash(11, 2)).is_empty());
+        assert_eq!(dedup.tp_template_id(2), None);
+
+        // The next template on the new prev hash activates it
+        assert_eq!(
+            dedup.on_new_template(template(12, false, 3)),
+            vec![
+                Forward::Template(template(2, true, 3)),
+                Forward::PrevHash(prev_hash(2, 2))
+            ]
+        );
+        assert_eq!(dedup.tp_template_id(2), Some(12));
+        assert_eq!(
+            dedup.on_new_template(template(13, false, 4)),
+            vec![Forward::Template(template(3, false, 4))]
+        );
+    }
+}
@@ -1,29 +1,39 @@
 use super::{
     error::{PoolError, PoolResult},
-    mining_pool::{EitherFrame, StdFrame},
+    mining_pool::{EitherFrame, Solution, StdFrame, TemplateProviderConfig},
+    stats::PoolStats,
     status,
 };
 use async_channel::{Receiver, Sender};
 use codec_sv2::{HandshakeRole, Initiator};
 use error_handling::handle_result;
-use key_utils::Secp256k1PublicKey;
-use network_helpers_sv2::noise_connection_tokio::Connection;
+#[cfg(unix)]
+use network_helpers_sv2::unix_connection_tokio::UnixConnection;
+use network_helpers_sv2::{
+    backpressure::ConnectionOptions, endpoint::Endpoint, noise_connection_tokio::Connection,
+};
 use roles_logic_sv2::{
     handlers::template_distribution::ParseServerTemplateDistributionMessages,
     parsers::{PoolMessages, TemplateDistribution},
-    template_distribution_sv2::{
-        CoinbaseOutputDataSize, NewTemplate, SetNewPrevHash, SubmitSolution,
-    },
-    utils::Mutex,
+    template_distribution_sv2::{CoinbaseOutputDataSize, NewTemplate, SetNewPrevHash},
+    utils::{check_template_coinbase_space, Mutex},
 };
-use std::{convert::TryInto, net::SocketAddr, sync::Arc};
-use tokio::{net::TcpStream, task};
-use tracing::info;
+#[cfg(unix)]
+use std::path::Path;
+use std::{convert::TryInto, net::SocketAddr, sync::Arc, time::Duration};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::{net::TcpStream, select, task};
+use tracing::{error, info, warn};
 
+pub mod dedup;
 mod message_handler;
 mod setup_connection;
+use dedup::{Forward, TemplateDedup};
 use setup_connection::SetupConnectionHandler;
 
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub struct TemplateRx {
     receiver: Receiver<EitherFrame>,
     sender: Sender<EitherFrame>,
@@ -31,35 +41,38 @@ pub struct TemplateRx {
     new_template_sender: Sender<NewTemplate<'static>>,
     new_prev_hash_sender: Sender<SetNewPrevHash<'static>>,
     status_tx: status::Sender,
+    coinbase_output_max_additional_size: u32,
+    // Template Providers in order of priority
+    providers: Vec<TemplateProviderConfig>,
+    // index in `providers` of the connected Template Provider
+    active: usize,
+    dedup: TemplateDedup,
 }
 
 impl TemplateRx {
+    /// Connects to the first reachable Template Provider of `providers`. When the connection is
+    /// lost, or when nothing is received for `max_silence`, the pool fails over to the next
+    /// reachable one. While connected to a backup Template Provider, the ones with an higher
+    /// priority are checked every `health_check_interval` and the pool switches back to them as
+    /// soon as they are reachable.
     #[allow(clippy::too_many_arguments)]
     pub async fn connect(
-        address: SocketAddr,
+        providers: Vec<TemplateProviderConfig>,
+        health_check_interval: Duration,
+        max_silence: Option<Duration>,
         templ_sender: Sender<NewTemplate<'static>>,
         prev_h_sender: Sender<SetNewPrevHash<'static>>,
-        solution_receiver: Receiver<SubmitSolution<'static>>,
+        solution_receiver: Receiver<Solution>,
         message_received_signal: Receiver<()>,
         status_tx: status::Sender,
         coinbase_out_len: u32,
-        expected_tp_authority_public_key: Option<Secp256k1PublicKey>,
-    ) -> PoolResult<()> {
-        let stream = TcpStream::connect(address).await?;
-        info!("Connected to template distribution server at {}", address);
-
-        let initiator = match expected_tp_authority_public_key {
-            Some(expected_tp_authority_public_key) => {
-                Initiator::from_raw_k(expected_tp_authority_public_key.into_bytes())
-            }
-            None => Initiator::without_pk(),
-        }?;
-        let (mut receiver, mut sender, _, _) =
-            Connection::new(stream, HandshakeRole::Initiator(initiator))
-                .await
-                .unwrap();
-
-        SetupConnectionHandler::setup(&mut receiver, &mut sender, address).await?;
+        stats: Arc<Mutex<PoolStats>>,
+    ) -> PoolResult<Arc<Mutex<Self>>> {
+        let (active, receiver, sender) = Self::connect_first(&providers, coinbase_out_len)
+            .await
+            .ok_or_else(|| PoolError::Custom("No Template Provider reachable".to_string()))?;
+        let mut dedup = TemplateDedup::new();
+        dedup.on_connection();
 
         let self_ = Arc::new(Mutex::new(Self {
             receiver,
@@ -68,9 +81,28 @@ impl TemplateRx {
             new_prev_hash_sender: prev_h_sender,
             message_received_signal,
             status_tx,
+            coinbase_output_max_additional_size: coinbase_out_len,
+            providers,
+            active,
+            dedup,
         }));
         let cloned = self_.clone();
 
+        let cloned2 = self_.clone();
+
+        task::spawn(async move { Self::start(cloned, health_check_interval, max_silence).await });
+        task::spawn(async { Self::on_new_solution(cloned2, solution_receiver, stats).await });
+
+        Ok(self_)
+    }
+
+    /// Sends the new coinbase output data size to the connected Template Provider, the size is
+    /// also sent to the Template Providers the pool connects to later
+    pub async fn update_coinbase_output_size(
+        self_: Arc<Mutex<Self>>,
+        coinbase_out_len: u32,
+    ) -> PoolResult<()> {
+        self_.safe_lock(|s| s.coinbase_output_max_additional_size = coinbase_out_len)?;
         let c_additional_size = CoinbaseOutputDataSize {
             coinbase_output_max_additional_size: coinbase_out_len,
         };
@@ -78,30 +110,266 @@ impl TemplateRx {
             TemplateDistribution::CoinbaseOutputDataSize(c_additional_size),
         )
         .try_into()?;
+        Self::send(self_, frame).await
+    }
 
-        Self::send(self_.clone(), frame).await?;
+    /// Connects to a Template Provider and sends it the coinbase output data size
+    async fn open_connection(
+        provider: &TemplateProviderConfig,
+        coinbase_out_len: u32,
+    ) -> PoolResult<(Receiver<EitherFrame>, Sender<EitherFrame>)> {
+        let (mut receiver, mut sender, address) = match Endpoint::parse(&provider.address) {
+            Endpoint::Tcp(address) => Self::open_tcp(&address, provider).await?,
+            #[cfg(unix)]
+            Endpoint::Unix(path) => Self::open_unix(&path, provider).await?,
+            #[cfg(not(unix))]
+            Endpoint::Unix(_) => {
+                return Err(PoolError::Custom(
+                    "Unix sockets are not supported on this platform".to_string(),
+                ))
+            }
+        };
+
+        SetupConnectionHandler::setup(&mut receiver, &mut sender, address).await?;
+
+        let c_additional_size = CoinbaseOutputDataSize {
+            coinbase_output_max_additional_size: coinbase_out_len,
+        };
+        let frame: StdFrame = PoolMessages::TemplateDistribution(
+            TemplateDistribution::CoinbaseOutputDataSize(c_additional_size),
+        )
+        .try_into()?;
+        sender.send(frame.into()).await?;
 
-        task::spawn(async { Self::start(cloned).await });
-        task::spawn(async { Self::on_new_solution(self_, solution_receiver).await });
+        Ok((receiver, sender))
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn initiator(provider: &TemplateProviderConfig) -> PoolResult<Box<Initiator>> {
+        Ok(match provider.authority_public_key {
+            Some(expected_tp_authority_public_key) => {
+                Initiator::from_raw_k(expected_tp_authority_public_key.into_bytes())
+            }
+            None => Initiator::without_pk(),
+        }?)
+    }
+
+    async fn open_tcp(
+        address: &str,
+        provider: &TemplateProviderConfig,
+    ) -> PoolResult<(Receiver<EitherFrame>, Sender<EitherFrame>, SocketAddr)> {
+        if !provider.noise {
+            return Err(PoolError::Custom(format!(
+                "Noise can only be disabled on Unix sockets, not on {}",
+                address
+            )));
+        }
+        let address: SocketAddr = address.parse().map_err(|_| {
+            PoolError::Custom(format!(
+                "Invalid Template Provider address: {}",
+                provider.address
+            ))
+        })?;
+        let stream = tokio::time::timeout(CONNECTION_TIMEOUT, TcpStream::connect(address))
+            .await
+            .map_err(|_| PoolError::Custom(format!("Connection to {} timed out", address)))??;
+        info!("Connected to template distribution server at {}", address);
 
+        let initiator = Self::initiator(provider)?;
+        let (receiver, sender, _, _) = Connection::new(stream, HandshakeRole::Initiator(initiator))
+            .await
+            .map_err(|e| {
+                PoolError::Custom(format!("Noise handshake with {} failed: {:?}", address, e))
+            })?;
+        Ok((receiver, sender, address))
+    }
+
+    #[cfg(unix)]
+    async fn open_unix(
+        path: &Path,
+        provider: &TemplateProviderConfig,
+    ) -> PoolResult<(Receiver<EitherFrame>, Sender<EitherFrame>, SocketAddr)> {
+        let stream = tokio::time::timeout(CONNECTION_TIMEOUT, UnixStream::connect(path))
+            .await
+            .map_err(|_| {
+                PoolError::Custom(format!("Connection to {} timed out", provider.address))
+            })??;
+        info!(
+            "Connected to template distribution server at {}",
+            provider.address
+        );
+
+        let options = ConnectionOptions::default();
+        let (receiver, sender) = if provider.noise {
+            let initiator = Self::initiator(provider)?;
+            let (receiver, sender, _, _) =
+                UnixConnection::noise(stream, HandshakeRole::Initiator(initiator), options)
+                    .await
+                    .map_err(|e| {
+                        PoolError::Custom(format!(
+                            "Noise handshake with {} failed: {:?}",
+                            provider.address, e
+                        ))
+                    })?;
+            (receiver, sender)
+        } else {
+            UnixConnection::plain(stream, options)
+        };
+        // A Unix socket has no host and port to put in the SetupConnection
+        Ok((receiver, sender, SocketAddr::from(([127, 0, 0, 1], 0))))
+    }
+
+    /// Returns the index and the connection of the first reachable Template Provider
+    async fn connect_first(
+        providers: &[TemplateProviderConfig],
+        coinbase_out_len: u32,
+    ) -> Option<(usize, Receiver<EitherFrame>, Sender<EitherFrame>)> {
+        for (index, provider) in providers.iter().enumerate() {
+            match Self::open_connection(provider, coinbase_out_len).await {
+                Ok((receiver, sender)) => return Some((index, receiver, sender)),
+                Err(e) => warn!("Template Provider {} unreachable: {}", provider.address, e),
+            }
+        }
+        None
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn switch_to(
+        self_: &Arc<Mutex<Self>>,
+        active: usize,
+        receiver: Receiver<EitherFrame>,
+        sender: Sender<EitherFrame>,
+    ) -> PoolResult<()> {
+        let address = self_.safe_lock(|s| {
+            s.active = active;
+            s.receiver = receiver;
+            s.sender = sender;
+            s.dedup.on_connection();
+            s.providers[active].address.clone()
+        })?;
+        info!("Getting templates from {}", address);
+        Ok(())
+    }
+
+    /// Connects to the reachable Template Provider with the highest priority, retrying every
+    /// `retry_interval` until one is reachable
+    async fn failover(self_: Arc<Mutex<Self>>, retry_interval: Duration) -> PoolResult<()> {
+        let (providers, coinbase_out_len) =
+            self_.safe_lock(|s| (s.providers.clone(), s.coinbase_output_max_additional_size))?;
+        loop {
+            if let Some((active, receiver, sender)) =
+                Self::connect_first(&providers, coinbase_out_len).await
+            {
+                return Self::switch_to(&self_, active, receiver, sender);
+            }
+            error!(
+                "No Template Provider reachable, retrying in {}s",
+                retry_interval.as_secs()
+            );
+            tokio::time::sleep(retry_interval).await;
+        }
+    }
+
+    /// Switches to a Template Provider with an higher priority than the connected one if any is
+    /// reachable
+    async fn check_preferred_providers(self_: Arc<Mutex<Self>>) -> PoolResult<()> {
+        let (providers, coinbase_out_len) = self_.safe_lock(|s| {
+            (
+                s.providers[..s.active].to_vec(),
+                s.coinbase_output_max_additional_size,
+            )
+        })?;
+        if let Some((active, receiver, sender)) =
+            Self::connect_first(&providers, coinbase_out_len).await
+        {
+            Self::switch_to(&self_, active, receiver, sender)?;
+        }
+        Ok(())
+    }
+
+    async fn forward(
+        forward: Vec<Forward>,
+        new_template_sender: &Sender<NewTemplate<'static>>,
+        new_prev_hash_sender: &Sender<SetNewPrevHash<'static>>,
+        recv_msg_signal: &Receiver<()>,
+    ) -> PoolResult<()> {
+        for message in forward {
+            match message {
+                Forward::Template(m) => new_template_sender.send(m).await?,
+                Forward::PrevHash(m) => new_prev_hash_sender.send(m).await?,
+            }
+            recv_msg_signal.recv().await?;
+        }
         Ok(())
     }
 
-    pub async fn start(self_: Arc<Mutex<Self>>) {
-        let (recv_msg_signal, receiver, new_template_sender, new_prev_hash_sender, status_tx) =
-            self_
-                .safe_lock(|s| {
-                    (
-                        s.message_received_signal.clone(),
-                        s.receiver.clone(),
-                        s.new_template_sender.clone(),
-                        s.new_prev_hash_sender.clone(),
-                        s.status_tx.clone(),
-                    )
-                })
-                .unwrap();
+    pub async fn start(
+        self_: Arc<Mutex<Self>>,
+        health_check_interval: Duration,
+        max_silence: Option<Duration>,
+    ) {
+        let (recv_msg_signal, new_template_sender, new_prev_hash_sender, status_tx) = self_
+            .safe_lock(|s| {
+                (
+                    s.message_received_signal.clone(),
+                    s.new_template_sender.clone(),
+                    s.new_prev_hash_sender.clone(),
+                    s.status_tx.clone(),
+                )
+            })
+            .unwrap();
+        let mut health_check = tokio::time::interval(health_check_interval);
         loop {
-            let message_from_tp = handle_result!(status_tx, receiver.recv().await);
+            // The coinbase output size can change when the coinbase outputs are reloaded
+            let (receiver, active, coinbase_output_max_additional_size) = handle_result!(
+                status_tx,
+                self_
+                    .safe_lock(|s| {
+                        (
+                            s.receiver.clone(),
+                            s.active,
+                            s.coinbase_output_max_additional_size,
+                        )
+                    })
+                    .map_err(|e| PoolError::PoisonLock(e.to_string()))
+            );
+            let next_message = async {
+                match max_silence {
+                    Some(max_silence) => tokio::time::timeout(max_silence, receiver.recv())
+                        .await
+                        .ok(),
+                    None => Some(receiver.recv().await),
+                }
+            };
+            let message_from_tp = select! {
+                message = next_message => message,
+                _ = health_check.tick(), if active > 0 => {
+                    handle_result!(status_tx, Self::check_preferred_providers(self_.clone()).await);
+                    continue;
+                }
+            };
+            let message_from_tp = match message_from_tp {
+                Some(Ok(message)) => message,
+                Some(Err(_)) => {
+                    warn!("Connection with the Template Provider lost");
+                    handle_result!(
+                        status_tx,
+                        Self::failover(self_.clone(), health_check_interval).await
+                    );
+                    continue;
+                }
+                None => {
+                    warn!(
+                        "No message from the Template Provider for {:?}",
+                        max_silence
+                    );
+                    handle_result!(
+                        status_tx,
+                        Self::failover(self_.clone(), health_check_interval).await
+                    );
+                    continue;
+                }
+            };
             let mut message_from_tp: StdFrame = handle_result!(
                 status_tx,
                 message_from_tp
@@ -125,17 +393,51 @@ impl TemplateRx {
                 roles_logic_sv2::handlers::SendTo_::RelayNewMessageToRemote(_, m) => match m {
                     TemplateDistribution::CoinbaseOutputDataSize(_) => todo!(),
                     TemplateDistribution::NewTemplate(m) => {
-                        let res = new_template_sender.send(m).await;
+                        if let Err(e) =
+                            check_template_coinbase_space(&m, coinbase_output_max_additional_size)
+                        {
+                            warn!("Ignoring template: {}", e);
+                            handle_result!(
+                                status_tx,
+                                self_
+                                    .safe_lock(|s| s.dedup.on_skipped_template(&m))
+                                    .map_err(|e| PoolError::PoisonLock(e.to_string()))
+                            );
+                            continue;
+                        }
+                        let forward = handle_result!(
+                            status_tx,
+                            self_
+                                .safe_lock(|s| s.dedup.on_new_template(m))
+                                .map_err(|e| PoolError::PoisonLock(e.to_string()))
+                        );
+                        let res = Self::forward(
+                            forward,
+                            &new_template_sender,
+                            &new_prev_hash_sender,
+                            &recv_msg_signal,
+                        )
+                        .await;
                         handle_result!(status_tx, res);
-                        handle_result!(status_tx, recv_msg_signal.recv().await);
                     }
                     TemplateDistribution::RequestTransactionData(_) => todo!(),
                     TemplateDistribution::RequestTransactionDataError(_) => todo!(),
                     TemplateDistribution::RequestTransactionDataSuccess(_) => todo!(),
                     TemplateDistribution::SetNewPrevHash(m) => {
-                        let res = new_prev_hash_sender.send(m).await;
+                        let forward = handle_result!(
+                            status_tx,
+                            self_
+                                .safe_lock(|s| s.dedup.on_set_new_prev_hash(m))
+                                .map_err(|e| PoolError::PoisonLock(e.to_string()))
+                        );
+                        let res = Self::forward(
+                            forward,
+                            &new_template_sender,
+                            &new_prev_hash_sender,
+                            &recv_msg_signal,
+                        )
+                        .await;
                         handle_result!(status_tx, res);
-                        handle_result!(status_tx, recv_msg_signal.recv().await);
                     }
                     TemplateDistribution::SubmitSolution(_) => todo!(),
                 },
@@ -157,9 +459,32 @@ impl TemplateRx {
         Ok(())
     }
 
-    async fn on_new_solution(self_: Arc<Mutex<Self>>, rx: Receiver<SubmitSolution<'static>>) {
+    /// Sends the solutions to the Template Provider and records how long after the reception of
+    /// the share they are sent
+    async fn on_new_solution(
+        self_: Arc<Mutex<Self>>,
+        rx: Receiver<Solution>,
+        stats: Arc<Mutex<PoolStats>>,
+    ) {
         let status_tx = self_.safe_lock(|s| s.status_tx.clone()).unwrap();
-        while let Ok(solution) = rx.recv().await {
+        while let Ok(Solution {
+            message: mut solution,
+            share_received_at,
+        }) = rx.recv().await
+        {
+            let template_id = self_
+                .safe_lock(|s| s.dedup.tp_template_id(solution.template_id))
+                .map_err(|e| PoolError::PoisonLock(e.to_string()));
+            match handle_result!(status_tx, template_id) {
+                Some(template_id) => solution.template_id = template_id,
+                None => {
+                    warn!(
+                        "Dropping solution for template {} of a disconnected Template Provider",
+                        solution.template_id
+                    );
+                    continue;
+                }
+            }
             info!("Sending Solution to TP: {:?}", &solution);
             let sv2_frame_res: Result<StdFrame, _> =
                 PoolMessages::TemplateDistribution(TemplateDistribution::SubmitSolution(solution))
@@ -167,6 +492,15 @@ impl TemplateRx {
             match sv2_frame_res {
                 Ok(frame) => {
                     handle_result!(status_tx, Self::send(self_.clone(), frame).await);
+                    let latency = share_received_at.elapsed();
+                    info!(
+                        "Solution sent to TP {:?} after the share was received",
+                        latency
+                    );
+                    let res = stats
+                        .safe_lock(|s| s.on_solution_sent(latency))
+                        .map_err(|e| PoolError::PoisonLock(e.to_string()));
+                    handle_result!(status_tx, res);
                 }
                 Err(_e) => {
                     // return submit error
@@ -4,7 +4,7 @@ use super::super::{
 };
 use async_channel::{Receiver, Sender};
 use roles_logic_sv2::{
-    common_messages_sv2::{Protocol, SetupConnection, SetupConnectionError},
+    common_messages_sv2::{DeviceInfo, Endpoint, Protocol, SetupConnection, SetupConnectionError},
     errors::Error,
     handlers::common::{ParseUpstreamCommonMessages, SendTo},
     parsers::{CommonMessages, PoolMessages},
@@ -18,23 +18,13 @@ pub struct SetupConnectionHandler {}
 impl SetupConnectionHandler {
     #[allow(clippy::result_large_err)]
     fn get_setup_connection_message(address: SocketAddr) -> PoolResult<SetupConnection<'static>> {
-        let endpoint_host = address.ip().to_string().into_bytes().try_into()?;
-        let vendor = String::new().try_into()?;
-        let hardware_version = String::new().try_into()?;
-        let firmware = String::new().try_into()?;
-        let device_id = String::new().try_into()?;
-        Ok(SetupConnection {
-            protocol: Protocol::TemplateDistributionProtocol,
-            min_version: 2,
-            max_version: 2,
-            flags: 0b0000_0000_0000_0000_0000_0000_0000_0000,
-            endpoint_host,
-            endpoint_port: address.port(),
-            vendor,
-            hardware_version,
-            firmware,
-            device_id,
-        })
+        let endpoint = Endpoint::new(&address.ip().to_string(), address.port())
+            .map_err(|e| PoolError::Custom(e.to_string()))?;
+        Ok(SetupConnection::for_protocol(
+            Protocol::TemplateDistributionProtocol,
+            DeviceInfo::default(),
+            endpoint,
+        ))
     }
 
     pub async fn setup(
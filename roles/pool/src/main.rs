@@ -1,8 +1,10 @@
 #![allow(special_module_name)]
 
 mod lib;
-use ext_config::{Config, File, FileFormat};
-pub use lib::{mining_pool::Configuration, status, PoolSv2};
+pub use lib::{
+    auth, config_reload, maintenance, mining_pool::Configuration, rate_limit, self_test, share_log,
+    stats, status, sv1_listener, PoolSv2,
+};
 use tracing::error;
 
 mod args {
@@ -11,6 +13,7 @@ mod args {
     #[derive(Debug)]
     pub struct Args {
         pub config_path: PathBuf,
+        pub self_test: bool,
     }
 
     enum ArgsState {
@@ -21,6 +24,7 @@ mod args {
 
     enum ArgsResult {
         Config(PathBuf),
+        SelfTest,
         None,
         Help(String),
     }
@@ -28,17 +32,17 @@ mod args {
     impl Args {
         const DEFAULT_CONFIG_PATH: &'static str = "pool-config.toml";
         const HELP_MSG: &'static str =
-            "Usage: -h/--help, -c/--config <path|default pool-config.toml>";
+            "Usage: -h/--help, -c/--config <path|default pool-config.toml>, --self-test";
 
         pub fn from_args() -> Result<Self, String> {
             let cli_args = std::env::args();
 
             if cli_args.len() == 1 {
-                println!("Using default config path: {}", Self::DEFAULT_CONFIG_PATH);
-                println!("{}\n", Self::HELP_MSG);
+                tracing::info!("Using default config path: {}", Self::DEFAULT_CONFIG_PATH);
+                tracing::info!("{}", Self::HELP_MSG);
             }
 
-            let config_path = cli_args
+            let results: Vec<ArgsResult> = cli_args
                 .scan(ArgsState::Next, |state, item| {
                     match std::mem::replace(state, ArgsState::Done) {
                         ArgsState::Next => match item.as_str() {
@@ -47,6 +51,10 @@ mod args {
                                 Some(ArgsResult::None)
                             }
                             "-h" | "--help" => Some(ArgsResult::Help(Self::HELP_MSG.to_string())),
+                            "--self-test" => {
+                                *state = ArgsState::Next;
+                                Some(ArgsResult::SelfTest)
+                            }
                             _ => {
                                 *state = ArgsState::Next;
 
@@ -57,20 +65,28 @@ mod args {
                         ArgsState::Done => None,
                     }
                 })
-                .last();
-            let config_path = match config_path {
-                Some(ArgsResult::Config(p)) => p,
-                Some(ArgsResult::Help(h)) => return Err(h),
-                _ => PathBuf::from(Self::DEFAULT_CONFIG_PATH),
-            };
-            Ok(Self { config_path })
+                .collect();
+            let mut config_path = PathBuf::from(Self::DEFAULT_CONFIG_PATH);
+            let mut self_test = false;
+            for result in results {
+                match result {
+                    ArgsResult::Config(p) => config_path = p,
+                    ArgsResult::SelfTest => self_test = true,
+                    ArgsResult::Help(h) => return Err(h),
+                    ArgsResult::None => (),
+                }
+            }
+            Ok(Self {
+                config_path,
+                self_test,
+            })
         }
     }
 }
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt::init();
+    logging_sv2::init();
 
     let args = match args::Args::from_args() {
         Ok(cfg) => cfg,
@@ -80,24 +96,24 @@ async fn main() {
         }
     };
 
-    let config_path = args.config_path.to_str().expect("Invalid config path");
-
     // Load config
-    let config: Configuration = match Config::builder()
-        .add_source(File::new(config_path, FileFormat::Toml))
-        .build()
-    {
-        Ok(settings) => match settings.try_deserialize::<Configuration>() {
-            Ok(c) => c,
-            Err(e) => {
-                error!("Failed to deserialize config: {}", e);
-                return;
-            }
-        },
+    let config: Configuration = match config_helpers_sv2::load(&args.config_path, "POOL") {
+        Ok(c) => c,
         Err(e) => {
-            error!("Failed to build config: {}", e);
+            error!("{}", e);
             return;
         }
     };
-    let _ = PoolSv2::new(config).start().await;
+    if args.self_test {
+        let report = self_test::run(config).await;
+        println!("{}", report);
+        if !report.passed() {
+            std::process::exit(1);
+        }
+        return;
+    }
+    let _ = PoolSv2::new(config)
+        .with_config_path(args.config_path.clone())
+        .start()
+        .await;
 }
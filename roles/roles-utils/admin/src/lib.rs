@@ -0,0 +1,239 @@
+//! Admin interface of the roles.
+//!
+//! The roles register the connections they accept, and the channels opened on them, in the
+//! [`Diagnostics`] of the process returned by [`diagnostics`]. [`serve`] answers the commands of
+//! an operator on a localhost address, one JSON object per line (see [`Command`]): the log filter
+//! can be changed and the connections, the channels and a snapshot of the state of the role can be
+//! dumped, without restarting the role.
+mod server;
+
+pub use server::{serve, Command};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Connection registered with [`Diagnostics::open_connection`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub address: String,
+    /// What the peer is to the role, e.g. `sv1 downstream`
+    pub kind: String,
+    /// Unix time in seconds
+    pub opened_at: u64,
+    /// Ids of the channels opened on the connection
+    pub channels: Vec<u32>,
+}
+
+/// Channel registered with [`Connection::open_channel`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChannelInfo {
+    pub connection_id: u64,
+    pub channel_id: u32,
+    pub user: Option<String>,
+    /// Unix time in seconds
+    pub opened_at: u64,
+}
+
+type Snapshot = Arc<dyn Fn() -> Value + Send + Sync>;
+
+#[derive(Default)]
+struct Inner {
+    next_id: u64,
+    connections: BTreeMap<u64, ConnectionInfo>,
+    channels: BTreeMap<(u64, u32), ChannelInfo>,
+    snapshot: Option<Snapshot>,
+}
+
+/// Connections and channels of a role
+#[derive(Clone, Default)]
+pub struct Diagnostics(Arc<Mutex<Inner>>);
+
+impl fmt::Debug for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Diagnostics").finish_non_exhaustive()
+    }
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Registers a connection with the peer at `address`, until the returned [`Connection`] is
+    /// dropped
+    pub fn open_connection(&self, address: impl ToString, kind: &str) -> Connection {
+        let mut inner = self.lock();
+        inner.next_id += 1;
+        let id = inner.next_id;
+        inner.connections.insert(
+            id,
+            ConnectionInfo {
+                id,
+                address: address.to_string(),
+                kind: kind.to_string(),
+                opened_at: now_secs(),
+                channels: vec![],
+            },
+        );
+        Connection {
+            id,
+            diagnostics: self.clone(),
+        }
+    }
+
+    /// Open connections, oldest first
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        self.lock().connections.values().cloned().collect()
+    }
+
+    /// Open channels, by connection
+    pub fn channels(&self) -> Vec<ChannelInfo> {
+        self.lock().channels.values().cloned().collect()
+    }
+
+    /// Sets the state specific to the role added to [`Diagnostics::snapshot`]
+    pub fn set_snapshot(&self, snapshot: impl Fn() -> Value + Send + Sync + 'static) {
+        self.lock().snapshot = Some(Arc::new(snapshot));
+    }
+
+    /// The log filter, the connections, the channels and the state set with
+    /// [`Diagnostics::set_snapshot`]
+    pub fn snapshot(&self) -> Value {
+        let (connections, channels, snapshot) = {
+            let inner = self.lock();
+            (
+                inner.connections.values().cloned().collect::<Vec<_>>(),
+                inner.channels.values().cloned().collect::<Vec<_>>(),
+                inner.snapshot.clone(),
+            )
+        };
+        // Called without the lock, the role may take its own locks
+        let role = snapshot.map(|snapshot| snapshot()).unwrap_or(Value::Null);
+        json!({
+            "log_filter": logging_sv2::filter(),
+            "connections": connections,
+            "channels": channels,
+            "role": role,
+        })
+    }
+}
+
+/// Connection registered in [`Diagnostics`], unregistered with its channels when dropped
+#[derive(Debug)]
+pub struct Connection {
+    id: u64,
+    diagnostics: Diagnostics,
+}
+
+impl Connection {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Registers a channel opened on the connection, the user of a channel already registered is
+    /// updated
+    pub fn open_channel(&self, channel_id: u32, user: Option<&str>) {
+        let mut inner = self.diagnostics.lock();
+        let user = user.map(str::to_string);
+        match inner.channels.get_mut(&(self.id, channel_id)) {
+            Some(channel) => channel.user = user,
+            None => {
+                inner.channels.insert(
+                    (self.id, channel_id),
+                    ChannelInfo {
+                        connection_id: self.id,
+                        channel_id,
+                        user,
+                        opened_at: now_secs(),
+                    },
+                );
+                if let Some(connection) = inner.connections.get_mut(&self.id) {
+                    connection.channels.push(channel_id);
+                }
+            }
+        }
+    }
+
+    pub fn close_channel(&self, channel_id: u32) {
+        let mut inner = self.diagnostics.lock();
+        inner.channels.remove(&(self.id, channel_id));
+        if let Some(connection) = inner.connections.get_mut(&self.id) {
+            connection.channels.retain(|id| *id != channel_id);
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let mut inner = self.diagnostics.lock();
+        inner.connections.remove(&self.id);
+        let id = self.id;
+        inner
+            .channels
+            .retain(|(connection_id, _), _| *connection_id != id);
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Diagnostics of the process
+pub fn diagnostics() -> &'static Diagnostics {
+    static DIAGNOSTICS: OnceLock<Diagnostics> = OnceLock::new();
+    DIAGNOSTICS.get_or_init(Diagnostics::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connections_and_channels() {
+        let diagnostics = Diagnostics::new();
+        let first = diagnostics.open_connection("127.0.0.1:4000", "sv2 downstream");
+        let second = diagnostics.open_connection("127.0.0.1:4001", "sv2 downstream");
+        first.open_channel(1, None);
+        first.open_channel(1, Some("user"));
+        first.open_channel(2, Some("other"));
+        second.open_channel(3, None);
+        first.close_channel(2);
+
+        let connections = diagnostics.connections();
+        assert_eq!(connections.len(), 2);
+        assert_eq!(connections[0].address, "127.0.0.1:4000");
+        assert_eq!(connections[0].channels, vec![1]);
+        let channels = diagnostics.channels();
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].user.as_deref(), Some("user"));
+
+        drop(first);
+        assert_eq!(diagnostics.connections().len(), 1);
+        assert_eq!(diagnostics.channels()[0].channel_id, 3);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let diagnostics = Diagnostics::new();
+        let _connection = diagnostics.open_connection("127.0.0.1:4000", "sv1 downstream");
+        assert_eq!(diagnostics.snapshot()["role"], Value::Null);
+        diagnostics.set_snapshot(|| json!({ "shares": 3 }));
+        let snapshot = diagnostics.snapshot();
+        assert_eq!(snapshot["role"]["shares"], 3);
+        assert_eq!(snapshot["connections"][0]["kind"], "sv1 downstream");
+    }
+}
@@ -0,0 +1,164 @@
+//! Localhost endpoint of the admin interface.
+//!
+//! Every line received is a [`Command`] in JSON, answered by a line with
+//! `{"ok":true,"result":...}` or `{"ok":false,"error":"..."}`. It runs on its own threads with
+//! blocking IO, so that the roles can use it whatever their async runtime, e.g.
+//! `echo '{"command":"set_log_level","filter":"info,pool_sv2=debug"}' | nc 127.0.0.1 9100`.
+use crate::{diagnostics, Diagnostics};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use tracing::{debug, info};
+
+// An operator session left open is closed after this time without commands
+const IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Command of the admin interface
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    /// Current log filter
+    LogLevel,
+    /// Replaces the log filter, `filter` is in the `RUST_LOG` syntax
+    SetLogLevel { filter: String },
+    /// Open connections
+    Connections,
+    /// Open channels
+    Channels,
+    /// Snapshot of the state of the role, also written to the logs
+    Snapshot,
+}
+
+/// Serves the admin interface of [`diagnostics`] on `address`, that must be a loopback address:
+/// the commands are not authenticated
+pub fn serve(address: &str) -> io::Result<JoinHandle<()>> {
+    let address: SocketAddr = address
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if !address.ip().is_loopback() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a loopback address", address),
+        ));
+    }
+    let listener = TcpListener::bind(address)?;
+    info!("Serving the admin interface on: {}", address);
+    serve_on(listener, diagnostics().clone())
+}
+
+fn serve_on(listener: TcpListener, diagnostics: Diagnostics) -> io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name("admin".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let diagnostics = diagnostics.clone();
+                // A session does not hold the others
+                let session = stream.and_then(|stream| {
+                    thread::Builder::new()
+                        .name("admin-session".to_string())
+                        .spawn(move || {
+                            if let Err(e) = session(stream, &diagnostics) {
+                                debug!("Admin session closed: {}", e);
+                            }
+                        })
+                });
+                if let Err(e) = session {
+                    debug!("Failed to open an admin session: {}", e);
+                }
+            }
+        })
+}
+
+fn session(stream: TcpStream, diagnostics: &Diagnostics) -> io::Result<()> {
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(writer, "{}", respond(&line, diagnostics))?;
+    }
+    Ok(())
+}
+
+fn respond(line: &str, diagnostics: &Diagnostics) -> Value {
+    let result = serde_json::from_str(line)
+        .map_err(|e| format!("invalid command: {}", e))
+        .and_then(|command| execute(command, diagnostics));
+    match result {
+        Ok(result) => json!({ "ok": true, "result": result }),
+        Err(e) => json!({ "ok": false, "error": e }),
+    }
+}
+
+fn execute(command: Command, diagnostics: &Diagnostics) -> Result<Value, String> {
+    match command {
+        Command::LogLevel => Ok(json!(logging_sv2::filter())),
+        Command::SetLogLevel { filter } => {
+            logging_sv2::set_filter(&filter)?;
+            info!("Log filter set to: {}", filter);
+            Ok(Value::Null)
+        }
+        Command::Connections => Ok(json!(diagnostics.connections())),
+        Command::Channels => Ok(json!(diagnostics.channels())),
+        Command::Snapshot => {
+            let snapshot = diagnostics.snapshot();
+            info!(%snapshot, "State snapshot");
+            Ok(snapshot)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_serve() {
+        let diagnostics = Diagnostics::new();
+        let _connection = diagnostics.open_connection("127.0.0.1:4000", "sv2 downstream");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        serve_on(listener, diagnostics).unwrap();
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(b"{\"command\":\"connections\"}\n\n{\"command\":\"restart\"}\n")
+            .unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let responses: Vec<Value> = response
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["ok"], true);
+        assert_eq!(responses[0]["result"][0]["address"], "127.0.0.1:4000");
+        assert_eq!(responses[1]["ok"], false);
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let command: Command =
+            serde_json::from_str("{\"command\":\"set_log_level\",\"filter\":\"debug\"}").unwrap();
+        assert_eq!(
+            command,
+            Command::SetLogLevel {
+                filter: "debug".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_serve_on_remote_address() {
+        assert!(serve("0.0.0.0:0").is_err());
+    }
+}
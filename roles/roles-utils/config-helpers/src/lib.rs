@@ -0,0 +1,309 @@
+//! Configuration loading and validation shared by the roles.
+//!
+//! [`load`] reads the TOML config file of a role, applies the overrides from the environment and
+//! checks the result with [`Validate`]. Every problem found is reported at once, with the name of
+//! the field, so that a config with typos fails with an error that says what to fix instead of a
+//! panic.
+//!
+//! A field is overridden by the environment variable made of the prefix of the role and the name
+//! of the field in upper case, e.g. `POOL_LISTEN_ADDRESS` for `listen_address`. Fields of nested
+//! tables are separated by `__`, e.g. `POOL_RATE_LIMITS__MAX_MESSAGES_PER_SEC`.
+use ext_config::{Config, Environment, File, FileFormat, Map};
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use serde::de::DeserializeOwned;
+use std::{
+    fmt::{self, Display},
+    net::{IpAddr, SocketAddr},
+    path::Path,
+};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file can not be read or parsed, or a field is missing or has the wrong type
+    Load(String),
+    /// The fields that have an invalid value
+    Invalid(Vec<String>),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Load(e) => write!(f, "{}", e),
+            ConfigError::Invalid(errors) => {
+                write!(f, "invalid configuration:")?;
+                for error in errors {
+                    write!(f, "\n  - {}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub trait Validate {
+    /// Records in `v` every invalid field
+    fn validate(&self, v: &mut Validator);
+}
+
+/// Loads the config in `path`, overridden by the environment variables starting with
+/// `env_prefix`, and validates it
+pub fn load<T: DeserializeOwned + Validate>(
+    path: &Path,
+    env_prefix: &str,
+) -> Result<T, ConfigError> {
+    load_with_env(path, env_prefix, None)
+}
+
+fn load_with_env<T: DeserializeOwned + Validate>(
+    path: &Path,
+    env_prefix: &str,
+    env: Option<Map<String, String>>,
+) -> Result<T, ConfigError> {
+    let path_str = path.to_string_lossy();
+    if !path.exists() {
+        return Err(ConfigError::Load(format!(
+            "config file {} not found, pass its path with -c",
+            path_str
+        )));
+    }
+    let config: T = Config::builder()
+        .add_source(File::new(&path_str, FileFormat::Toml))
+        .add_source(
+            Environment::with_prefix(env_prefix)
+                .prefix_separator("_")
+                .separator("__")
+                .try_parsing(true)
+                .source(env),
+        )
+        .build()
+        .and_then(|config| config.try_deserialize())
+        .map_err(|e| ConfigError::Load(format!("config file {}: {}", path_str, e)))?;
+    let mut validator = Validator::default();
+    config.validate(&mut validator);
+    validator.finish()?;
+    Ok(config)
+}
+
+/// Collects the errors found while validating a config
+#[derive(Debug, Default)]
+pub struct Validator {
+    errors: Vec<String>,
+}
+
+impl Validator {
+    /// Records `message` if `condition` is false
+    pub fn check(&mut self, condition: bool, message: impl Into<String>) {
+        if !condition {
+            self.errors.push(message.into());
+        }
+    }
+
+    /// `value` must be a `host:port` address
+    pub fn socket_address(&mut self, field: &str, value: &str) {
+        // host names are not resolved, the host may not be reachable yet
+        let well_formed = value.parse::<SocketAddr>().is_ok()
+            || value
+                .rsplit_once(':')
+                .map(|(host, port)| {
+                    !host.is_empty() && !host.contains(':') && port.parse::<u16>().is_ok()
+                })
+                .unwrap_or(false);
+        if !well_formed {
+            self.errors.push(format!(
+                "{}: `{}` is not a valid address, expected host:port (e.g. 127.0.0.1:34254)",
+                field, value
+            ));
+        }
+    }
+
+    /// `value` must be an ip address
+    pub fn ip_address(&mut self, field: &str, value: &str) {
+        if value.parse::<IpAddr>().is_err() {
+            self.errors.push(format!(
+                "{}: `{}` is not a valid ip address (e.g. 0.0.0.0)",
+                field, value
+            ));
+        }
+    }
+
+    /// `value` must be between `min` and `max` included
+    pub fn range<T: PartialOrd + Display>(&mut self, field: &str, value: T, min: T, max: T) {
+        if value < min || value > max {
+            self.errors.push(format!(
+                "{}: {} is out of range, expected a value between {} and {}",
+                field, value, min, max
+            ));
+        }
+    }
+
+    /// `value` must not be empty
+    pub fn not_empty<T>(&mut self, field: &str, value: &[T]) {
+        if value.is_empty() {
+            self.errors.push(format!("{}: must not be empty", field));
+        }
+    }
+
+    /// `min` must not be greater than `max`
+    pub fn ordered<T: PartialOrd + Display>(
+        &mut self,
+        (min_field, min): (&str, T),
+        (max_field, max): (&str, T),
+    ) {
+        if min > max {
+            self.errors.push(format!(
+                "{} ({}) must not be greater than {} ({})",
+                min_field, min, max_field, max
+            ));
+        }
+    }
+
+    /// The public key must be the one of the secret key
+    pub fn keypair(
+        &mut self,
+        (public_field, public_key): (&str, &Secp256k1PublicKey),
+        (secret_field, secret_key): (&str, &Secp256k1SecretKey),
+    ) {
+        let derived: Secp256k1PublicKey = (*secret_key).into();
+        if derived.into_bytes() != public_key.into_bytes() {
+            self.errors.push(format!(
+                "{} does not match {}, its public key is {}",
+                public_field, secret_field, derived
+            ));
+        }
+    }
+
+    /// At most one of the options can be set
+    pub fn exclusive(&mut self, options: &[(&str, bool)]) {
+        let set: Vec<&str> = options
+            .iter()
+            .filter(|(_, set)| *set)
+            .map(|(name, _)| *name)
+            .collect();
+        if set.len() > 1 {
+            self.errors
+                .push(format!("{} can not be set together", set.join(" and ")));
+        }
+    }
+
+    /// Fails with every error recorded
+    pub fn finish(self) -> Result<(), ConfigError> {
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError::Invalid(self.errors)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::io::Write;
+
+    #[derive(Debug, Deserialize)]
+    struct TestConfig {
+        listen_address: String,
+        min_version: u16,
+        max_version: u16,
+        #[serde(default)]
+        nested: Nested,
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    struct Nested {
+        #[serde(default)]
+        interval: u64,
+    }
+
+    impl Validate for TestConfig {
+        fn validate(&self, v: &mut Validator) {
+            v.socket_address("listen_address", &self.listen_address);
+            v.ordered(
+                ("min_version", self.min_version),
+                ("max_version", self.max_version),
+            );
+            v.range("nested.interval", self.nested.interval, 0, 60);
+        }
+    }
+
+    fn write_config(name: &str, content: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "config_helpers_{}_{}.toml",
+            name,
+            std::process::id()
+        ));
+        std::fs::File::create(&path)
+            .unwrap()
+            .write_all(content.as_bytes())
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load_with_env_overrides() {
+        let path = write_config(
+            "overrides",
+            "listen_address = \"0.0.0.0:34254\"\nmin_version = 2\nmax_version = 2\n",
+        );
+        let env = [
+            ("TEST_LISTEN_ADDRESS", "127.0.0.1:3333"),
+            ("TEST_NESTED__INTERVAL", "30"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let config: TestConfig = load_with_env(&path, "TEST", Some(env)).unwrap();
+        assert_eq!(config.listen_address, "127.0.0.1:3333");
+        assert_eq!(config.nested.interval, 30);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_every_error_is_reported() {
+        let path = write_config(
+            "invalid",
+            "listen_address = \"0.0.0.0\"\nmin_version = 3\nmax_version = 2\n\n[nested]\ninterval = 61\n",
+        );
+        let errors = match load_with_env::<TestConfig>(&path, "TEST", Some(Map::new())) {
+            Err(ConfigError::Invalid(errors)) => errors,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].starts_with("listen_address"));
+
+        let path_missing = write_config("missing", "listen_address = \"0.0.0.0:1\"\n");
+        let error = load_with_env::<TestConfig>(&path_missing, "TEST", Some(Map::new()))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("min_version"), "{}", error);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(path_missing).unwrap();
+    }
+
+    #[test]
+    fn test_keypair_and_exclusive_options() {
+        let public_key: Secp256k1PublicKey = "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72"
+            .parse()
+            .unwrap();
+        let secret_key: Secp256k1SecretKey = "mkDLTBBRxdBv998612qipDYoTK3YUrqLe8uWw7gu3iXbSrn2n"
+            .parse()
+            .unwrap();
+        let other: Secp256k1PublicKey = "9azQdassggC7L3YMVcZyRJmK7qrFDj5MZNHb4LkaUrJRUhct92W"
+            .parse()
+            .unwrap();
+        let mut v = Validator::default();
+        v.keypair(("public", &public_key), ("secret", &secret_key));
+        v.exclusive(&[("a", true), ("b", false)]);
+        assert!(v.finish().is_ok());
+
+        let mut v = Validator::default();
+        v.keypair(("public", &other), ("secret", &secret_key));
+        v.exclusive(&[("a", true), ("b", true)]);
+        match v.finish() {
+            Err(ConfigError::Invalid(errors)) => assert_eq!(errors.len(), 2),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Logging setup shared by the roles.
+//!
+//! The logs are filtered with `RUST_LOG`, in the `tracing_subscriber::EnvFilter` syntax (e.g.
+//! `RUST_LOG=info,pool_sv2=debug`), and default to `info`. With `SV2_LOG_FORMAT=json` every event
+//! is printed as a JSON object together with the fields of the spans it is in: the roles open a
+//! span per connection (remote address) and per channel (channel id, user), so that the events of
+//! a share can be correlated. The filter can be changed while the role runs with [`set_filter`].
+use std::{env, str::FromStr, sync::OnceLock};
+use tracing_subscriber::{fmt, prelude::*, reload, EnvFilter, Registry};
+
+/// Environment variable that selects the [`LogFormat`]
+pub const LOG_FORMAT_ENV: &str = "SV2_LOG_FORMAT";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {}, expected text or json", s)),
+        }
+    }
+}
+
+/// Installs the global subscriber, in the format set in [`LOG_FORMAT_ENV`]
+pub fn init() {
+    let format = match env::var(LOG_FORMAT_ENV) {
+        Ok(format) => format.parse().unwrap_or_else(|e| {
+            eprintln!("{}: {}, falling back to text", LOG_FORMAT_ENV, e);
+            LogFormat::Text
+        }),
+        Err(_) => LogFormat::Text,
+    };
+    init_with(format)
+}
+
+// Set when the global subscriber is installed by `init_with`
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Installs the global subscriber, does nothing if one is already installed
+pub fn init_with(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let (filter, handle) = reload::Layer::new(filter);
+    let subscriber = tracing_subscriber::registry().with(filter);
+    let installed = match format {
+        LogFormat::Text => subscriber.with(fmt::layer()).try_init(),
+        LogFormat::Json => subscriber
+            .with(
+                fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(true),
+            )
+            .try_init(),
+    };
+    if installed.is_ok() {
+        let _ = FILTER.set(handle);
+    }
+}
+
+/// Replaces the filter of the logs, `filter` is in the `RUST_LOG` syntax
+pub fn set_filter(filter: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(filter).map_err(|e| e.to_string())?;
+    let handle = FILTER
+        .get()
+        .ok_or_else(|| "the logs are not set up by logging_sv2".to_string())?;
+    handle.reload(filter).map_err(|e| e.to_string())
+}
+
+/// Current filter of the logs, `None` if they are not set up by [`init`] or [`init_with`]
+pub fn filter() -> Option<String> {
+    FILTER.get()?.with_current(|f| f.to_string()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_format() {
+        assert_eq!("json".parse(), Ok(LogFormat::Json));
+        assert_eq!("Text".parse(), Ok(LogFormat::Text));
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn test_set_filter() {
+        assert!(set_filter("pool_sv2=verbose").is_err());
+        init_with(LogFormat::Text);
+        set_filter("warn,pool_sv2=debug").unwrap();
+        assert_eq!(filter().as_deref(), Some("pool_sv2=debug,warn"));
+    }
+}
@@ -0,0 +1,75 @@
+//! HTTP endpoint of the metrics.
+//!
+//! It runs on its own thread with blocking IO, so that the roles can use it whatever their async
+//! runtime. Any request gets the metrics, the request itself is not parsed.
+use crate::{registry, Registry};
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use tracing::{debug, info};
+
+/// Content type of the OpenMetrics text format
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+// A scraper that stops in the middle of a request does not hold the exporter longer than this
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the metrics of [`registry`] on `address`, with the `role` label
+pub fn serve(address: &str, role: &str) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(address)?;
+    info!("Serving metrics on: {}", address);
+    serve_on(listener, registry().clone(), role.to_string())
+}
+
+fn serve_on(listener: TcpListener, registry: Registry, role: String) -> io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name("metrics-exporter".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let sent = stream.and_then(|stream| respond(stream, &registry, &role));
+                if let Err(e) = sent {
+                    debug!("Failed to send metrics: {}", e);
+                }
+            }
+        })
+}
+
+fn respond(mut stream: TcpStream, registry: &Registry, role: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut request = [0; 1024];
+    let _ = stream.read(&mut request);
+    let body = registry.encode(&[("role", role)]);
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        CONTENT_TYPE,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serve() {
+        let registry = Registry::new();
+        registry.gauge("sv2_test", "Test", &[]).set(7);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        serve_on(listener, registry, "translator".to_string()).unwrap();
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.ends_with("sv2_test{role=\"translator\"} 7\n# EOF\n"));
+    }
+}
@@ -0,0 +1,421 @@
+//! Metrics shared by the roles.
+//!
+//! The roles count the same things under the same names: the shares, the open channels, the
+//! frames and the failed noise handshakes, see [`RoleMetrics`]. They are registered in the
+//! [`Registry`] of the process returned by [`registry`], [`serve`] exposes it over HTTP in the
+//! OpenMetrics text format with a `role` label, so that the roles can be scraped together.
+mod exporter;
+
+pub use exporter::{serve, CONTENT_TYPE};
+
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+/// Shares, labelled with their `outcome`: `accepted`, `rejected` or `stale`
+pub const SHARES: &str = "sv2_shares";
+/// Difficulty of the accepted shares
+pub const SHARE_DIFFICULTY: &str = "sv2_share_difficulty";
+/// Channels currently open
+pub const CHANNELS_OPEN: &str = "sv2_channels_open";
+/// Frames, labelled with their `direction`: `received` or `sent`
+pub const FRAMES: &str = "sv2_frames";
+/// Noise handshakes that failed
+pub const HANDSHAKE_FAILURES: &str = "sv2_handshake_failures";
+
+/// Upper bounds of the buckets of [`SHARE_DIFFICULTY`]
+pub const DIFFICULTY_BUCKETS: [f64; 13] = [
+    1.0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12,
+];
+
+type Labels = Vec<(String, String)>;
+
+/// Value that only goes up
+#[derive(Debug, Clone, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.inc_by(1)
+    }
+
+    pub fn inc_by(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Value that goes up and down
+#[derive(Debug, Clone, Default)]
+pub struct Gauge(Arc<AtomicI64>);
+
+impl Gauge {
+    pub fn inc(&self) {
+        self.add(1)
+    }
+
+    pub fn dec(&self) {
+        self.add(-1)
+    }
+
+    pub fn add(&self, n: i64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+struct HistogramInner {
+    bounds: Vec<f64>,
+    // Cumulative, the bucket of a bound counts all the values lower or equal to it
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    // Bits of the f64 sum of the values
+    sum: AtomicU64,
+}
+
+/// Distribution of values in buckets
+#[derive(Debug, Clone)]
+pub struct Histogram(Arc<HistogramInner>);
+
+impl Histogram {
+    fn new(bounds: &[f64]) -> Self {
+        Self(Arc::new(HistogramInner {
+            bounds: bounds.to_vec(),
+            buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0.0_f64.to_bits()),
+        }))
+    }
+
+    pub fn observe(&self, value: f64) {
+        for (bound, bucket) in self.0.bounds.iter().zip(&self.0.buckets) {
+            if value <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.0.count.fetch_add(1, Ordering::Relaxed);
+        // Infallible the closure always returns Some
+        let _ = self
+            .0
+            .sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+                Some((f64::from_bits(sum) + value).to_bits())
+            });
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum(&self) -> f64 {
+        f64::from_bits(self.0.sum.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Metric {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+impl Metric {
+    fn kind(&self) -> &'static str {
+        match self {
+            Metric::Counter(_) => "counter",
+            Metric::Gauge(_) => "gauge",
+            Metric::Histogram(_) => "histogram",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Family {
+    name: String,
+    help: String,
+    series: Vec<(Labels, Metric)>,
+}
+
+/// Set of metrics, a metric is a family of series told apart by their labels
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    families: Arc<Mutex<Vec<Family>>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Series of the counter `name` with `labels`, registered on the first call.
+    ///
+    /// Panics if `name` is already registered as another type of metric.
+    pub fn counter(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Counter {
+        match self.register(name, help, labels, || Metric::Counter(Counter::default())) {
+            Metric::Counter(counter) => counter,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Like [`Registry::counter`] for a gauge
+    pub fn gauge(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Gauge {
+        match self.register(name, help, labels, || Metric::Gauge(Gauge::default())) {
+            Metric::Gauge(gauge) => gauge,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Like [`Registry::counter`] for an histogram with the buckets `bounds`, in increasing
+    /// order. The bounds of the series registered first are kept.
+    pub fn histogram(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        bounds: &[f64],
+    ) -> Histogram {
+        match self.register(name, help, labels, || {
+            Metric::Histogram(Histogram::new(bounds))
+        }) {
+            Metric::Histogram(histogram) => histogram,
+            _ => unreachable!(),
+        }
+    }
+
+    fn register(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        new: impl FnOnce() -> Metric,
+    ) -> Metric {
+        let labels: Labels = labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let mut families = self.families.lock().unwrap_or_else(|e| e.into_inner());
+        let i = match families.iter().position(|f| f.name == name) {
+            Some(i) => i,
+            None => {
+                families.push(Family {
+                    name: name.to_string(),
+                    help: help.to_string(),
+                    series: vec![],
+                });
+                families.len() - 1
+            }
+        };
+        let family = &mut families[i];
+        let metric = new();
+        if let Some((_, registered)) = family.series.first() {
+            assert_eq!(
+                registered.kind(),
+                metric.kind(),
+                "metric {} already registered with another type",
+                name
+            );
+        }
+        if let Some((_, registered)) = family.series.iter().find(|(l, _)| *l == labels) {
+            return registered.clone();
+        }
+        family.series.push((labels, metric.clone()));
+        metric
+    }
+
+    /// The metrics in the OpenMetrics text format, `const_labels` are added to every sample
+    pub fn encode(&self, const_labels: &[(&str, &str)]) -> String {
+        let families = self.families.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+        for family in families.iter() {
+            let kind = match family.series.first() {
+                Some((_, metric)) => metric.kind(),
+                None => continue,
+            };
+            let name = &family.name;
+            // Infallible writing to a String never fails
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "# HELP {} {}", name, escape(&family.help, false));
+            for (labels, metric) in &family.series {
+                let labels: Vec<(&str, &str)> = const_labels
+                    .iter()
+                    .copied()
+                    .chain(labels.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+                    .collect();
+                let series = format_labels(&labels);
+                match metric {
+                    Metric::Counter(counter) => {
+                        let _ = writeln!(out, "{}_total{} {}", name, series, counter.get());
+                    }
+                    Metric::Gauge(gauge) => {
+                        let _ = writeln!(out, "{}{} {}", name, series, gauge.get());
+                    }
+                    Metric::Histogram(histogram) => {
+                        let bucket_labels = |le: &str| {
+                            let mut labels = labels.clone();
+                            labels.push(("le", le));
+                            format_labels(&labels)
+                        };
+                        let count = histogram.count();
+                        for (bound, bucket) in histogram.0.bounds.iter().zip(&histogram.0.buckets) {
+                            let _ = writeln!(
+                                out,
+                                "{}_bucket{} {}",
+                                name,
+                                bucket_labels(&format!("{:?}", bound)),
+                                bucket.load(Ordering::Relaxed)
+                            );
+                        }
+                        let _ = writeln!(out, "{}_bucket{} {}", name, bucket_labels("+Inf"), count);
+                        let _ = writeln!(out, "{}_count{} {}", name, series, count);
+                        let _ = writeln!(out, "{}_sum{} {:?}", name, series, histogram.sum());
+                    }
+                }
+            }
+        }
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v, true)))
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+fn escape(s: &str, quote: bool) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if quote => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Metrics counted by every role, under the same names whatever the role
+#[derive(Debug, Clone)]
+pub struct RoleMetrics {
+    pub shares_accepted: Counter,
+    pub shares_rejected: Counter,
+    pub shares_stale: Counter,
+    pub share_difficulty: Histogram,
+    pub channels_open: Gauge,
+    pub frames_received: Counter,
+    pub frames_sent: Counter,
+    pub handshake_failures: Counter,
+}
+
+impl RoleMetrics {
+    /// Registers the metrics in `registry`
+    pub fn new(registry: &Registry) -> Self {
+        let shares = |outcome| registry.counter(SHARES, "Shares", &[("outcome", outcome)]);
+        let frames = |direction| registry.counter(FRAMES, "Frames", &[("direction", direction)]);
+        Self {
+            shares_accepted: shares("accepted"),
+            shares_rejected: shares("rejected"),
+            shares_stale: shares("stale"),
+            share_difficulty: registry.histogram(
+                SHARE_DIFFICULTY,
+                "Difficulty of the accepted shares",
+                &[],
+                &DIFFICULTY_BUCKETS,
+            ),
+            channels_open: registry.gauge(CHANNELS_OPEN, "Channels currently open", &[]),
+            frames_received: frames("received"),
+            frames_sent: frames("sent"),
+            handshake_failures: registry.counter(
+                HANDSHAKE_FAILURES,
+                "Noise handshakes that failed",
+                &[],
+            ),
+        }
+    }
+}
+
+impl RoleMetrics {
+    /// Counts a share refused upstream with `error_code`: the shares of a job that is not valid
+    /// anymore are stale, the others are rejected
+    pub fn on_share_error(&self, error_code: &[u8]) {
+        match error_code {
+            b"stale-share" | b"invalid-job-id" => self.shares_stale.inc(),
+            _ => self.shares_rejected.inc(),
+        }
+    }
+}
+
+/// Registry of the process
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::new)
+}
+
+/// Metrics of the role, registered in [`registry`] on the first call
+pub fn metrics() -> &'static RoleMetrics {
+    static METRICS: OnceLock<RoleMetrics> = OnceLock::new();
+    METRICS.get_or_init(|| RoleMetrics::new(registry()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let registry = Registry::new();
+        let metrics = RoleMetrics::new(&registry);
+        metrics.shares_accepted.inc_by(2);
+        metrics.on_share_error(b"difficulty-too-low");
+        metrics.on_share_error(b"stale-share");
+        metrics.channels_open.inc();
+        metrics.share_difficulty.observe(5.0);
+        metrics.share_difficulty.observe(5000.0);
+        // Registering again returns the same series
+        registry
+            .counter(SHARES, "Shares", &[("outcome", "accepted")])
+            .inc();
+        let encoded = registry.encode(&[("role", "pool")]);
+        assert!(encoded.starts_with("# TYPE sv2_shares counter\n# HELP sv2_shares Shares\n"));
+        assert!(encoded.contains("sv2_shares_total{role=\"pool\",outcome=\"accepted\"} 3\n"));
+        assert!(encoded.contains("sv2_shares_total{role=\"pool\",outcome=\"rejected\"} 1\n"));
+        assert!(encoded.contains("sv2_shares_total{role=\"pool\",outcome=\"stale\"} 1\n"));
+        assert!(encoded.contains("sv2_channels_open{role=\"pool\"} 1\n"));
+        assert!(encoded.contains("sv2_share_difficulty_bucket{role=\"pool\",le=\"1.0\"} 0\n"));
+        assert!(encoded.contains("sv2_share_difficulty_bucket{role=\"pool\",le=\"10.0\"} 1\n"));
+        assert!(encoded.contains("sv2_share_difficulty_bucket{role=\"pool\",le=\"+Inf\"} 2\n"));
+        assert!(encoded.contains("sv2_share_difficulty_count{role=\"pool\"} 2\n"));
+        assert!(encoded.contains("sv2_share_difficulty_sum{role=\"pool\"} 5005.0\n"));
+        assert!(encoded.ends_with("sv2_handshake_failures_total{role=\"pool\"} 0\n# EOF\n"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_register_with_another_type() {
+        let registry = Registry::new();
+        registry.gauge(CHANNELS_OPEN, "", &[]);
+        registry.counter(CHANNELS_OPEN, "", &[]);
+    }
+}
@@ -0,0 +1,52 @@
+//! Backpressure on the channels of the connections.
+//!
+//! The channels returned by the connections are bounded, when the peer reads slower than the
+//! role sends the outgoing channel fills up and `send` waits. A role that must not be slowed down
+//! by a single peer sends with [`send_timeout`] or [`try_send`] and drops the peer on error.
+use crate::{keepalive::KeepaliveConfig, Error, DEFAULT_HANDSHAKE_TIMEOUT};
+use async_channel::{Sender, TrySendError};
+use std::time::Duration;
+
+/// Capacity of the channels when not configured
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionOptions {
+    pub keepalive: KeepaliveConfig,
+    /// Capacity of the incoming and of the outgoing channel
+    pub channel_capacity: usize,
+    /// Time allowed to the peer to complete the noise handshake, never expires if `None`
+    pub handshake_timeout: Option<Duration>,
+    /// Plaintext connections only: end the sent frames with a CRC and reject the received frames
+    /// without one. Both ends must enable it.
+    pub frame_crc: bool,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            keepalive: KeepaliveConfig::default(),
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+            handshake_timeout: Some(DEFAULT_HANDSHAKE_TIMEOUT),
+            frame_crc: false,
+        }
+    }
+}
+
+/// Waits up to `timeout` for room in the channel, fails with [`Error::SendTimeout`] if the
+/// channel stays full
+pub async fn send_timeout<T>(sender: &Sender<T>, item: T, timeout: Duration) -> Result<(), Error> {
+    match tokio::time::timeout(timeout, sender.send(item)).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(Error::SendTimeout),
+    }
+}
+
+/// Sends without waiting, fails with [`Error::ChannelFull`] if the channel is full
+pub fn try_send<T>(sender: &Sender<T>, item: T) -> Result<(), Error> {
+    match sender.try_send(item) {
+        Ok(()) => Ok(()),
+        Err(TrySendError::Full(_)) => Err(Error::ChannelFull),
+        Err(TrySendError::Closed(_)) => Err(Error::SendError),
+    }
+}
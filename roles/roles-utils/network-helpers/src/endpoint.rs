@@ -0,0 +1,34 @@
+//! Addresses of the peers as written in the role configs.
+//!
+//! `unix:///var/run/sv2/tp.sock` is a Unix domain socket, `tcp://127.0.0.1:8442` and
+//! `127.0.0.1:8442` are TCP addresses.
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    pub fn parse(address: &str) -> Self {
+        match address.strip_prefix("unix://") {
+            Some(path) => Endpoint::Unix(PathBuf::from(path)),
+            None => Endpoint::Tcp(
+                address
+                    .strip_prefix("tcp://")
+                    .unwrap_or(address)
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Endpoint::Tcp(address) => write!(f, "{}", address),
+            Endpoint::Unix(path) => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
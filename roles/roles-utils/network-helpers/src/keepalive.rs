@@ -0,0 +1,45 @@
+//! Detection of dead peers.
+//!
+//! TCP keepalive lets the OS detect a peer that is gone without closing the connection. The
+//! liveness timeout detects a peer that is connected but hung: if nothing is received for
+//! `liveness_timeout` the connection is closed, the receiver returned by the connection is closed
+//! as when the peer disconnects.
+use socket2::{SockRef, TcpKeepalive};
+use std::{future::Future, io, time::Duration};
+use tokio::net::TcpStream;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeepaliveConfig {
+    /// Idle time before the TCP keepalive probes are sent, TCP keepalive is off if `None`
+    pub tcp_keepalive: Option<Duration>,
+    /// Time without receiving anything after which the connection is closed, never if `None`
+    pub liveness_timeout: Option<Duration>,
+}
+
+impl KeepaliveConfig {
+    /// Enables TCP keepalive on `stream` if configured
+    pub fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        if let Some(time) = self.tcp_keepalive {
+            let keepalive = TcpKeepalive::new().with_time(time).with_interval(time);
+            SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `read`, it fails with [`io::ErrorKind::TimedOut`] if it does not complete within
+/// `liveness_timeout`
+pub(crate) async fn read_with_timeout<F: Future<Output = io::Result<usize>>>(
+    read: F,
+    liveness_timeout: Option<Duration>,
+) -> io::Result<usize> {
+    match liveness_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, read).await.map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("nothing received for {:?}", timeout),
+            )
+        })?,
+        None => read.await,
+    }
+}
@@ -8,13 +8,28 @@ pub use noise_connection_async_std::{connect, listen, Connection};
 #[cfg(feature = "async_std")]
 pub use plain_connection_async_std::{plain_connect, plain_listen, PlainConnection};
 
+#[cfg(feature = "tokio")]
+pub mod backpressure;
+pub mod endpoint;
+#[cfg(feature = "tokio")]
+pub mod keepalive;
 #[cfg(feature = "tokio")]
 pub mod noise_connection_tokio;
 #[cfg(feature = "tokio")]
 pub mod plain_connection_tokio;
+#[cfg(feature = "tokio")]
+pub mod reconnecting_connection_tokio;
+#[cfg(feature = "tls")]
+pub mod tls_connection_tokio;
+#[cfg(feature = "tokio")]
+pub mod transport;
+#[cfg(all(feature = "tokio", unix))]
+pub mod unix_connection_tokio;
 
 use async_channel::{Receiver, RecvError, SendError, Sender};
-use codec_sv2::{Error as CodecError, HandShakeFrame, HandshakeRole, StandardEitherFrame};
+use codec_sv2::{
+    Error as CodecError, HandShakeFrame, HandshakeDriver, HandshakeRole, StandardEitherFrame,
+};
 use const_sv2::{
     INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE, RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
 };
@@ -22,8 +37,12 @@ use futures::lock::Mutex;
 use std::{
     convert::TryInto,
     sync::{atomic::AtomicBool, Arc},
+    time::Duration,
 };
 
+/// Time allowed to the peer to complete the noise handshake when not configured
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Debug)]
 pub enum Error {
     HandshakeRemoteInvalidMessage,
@@ -33,6 +52,12 @@ pub enum Error {
     // This means that a socket that was supposed to be opened have been closed, likley by the
     // peer
     SocketClosed,
+    // The channel stayed full for the whole send timeout, the peer is too slow
+    SendTimeout,
+    // The channel is full and the caller did not want to wait
+    ChannelFull,
+    // The TLS configuration is invalid or the TLS handshake failed
+    Tls(String),
 }
 
 impl From<CodecError> for Error {
@@ -55,6 +80,23 @@ trait SetState {
     async fn set_state(self_: Arc<Mutex<Self>>, state: codec_sv2::State);
 }
 
+/// Receives the next handshake frame, fails with [`CodecError::HandshakeTimeout`] if the handshake
+/// expires first
+async fn recv_handshake_frame<Message>(
+    receiver: &Receiver<StandardEitherFrame<Message>>,
+    driver: &HandshakeDriver,
+) -> Result<StandardEitherFrame<Message>, Error> {
+    #[cfg(feature = "tokio")]
+    if let Some(remaining) = driver.remaining() {
+        return match tokio::time::timeout(remaining, receiver.recv()).await {
+            Ok(frame) => Ok(frame?),
+            Err(_) => Err(CodecError::HandshakeTimeout.into()),
+        };
+    }
+    driver.check_deadline()?;
+    Ok(receiver.recv().await?)
+}
+
 async fn initialize_as_downstream<
     'a,
     Message: Serialize + Deserialize<'a> + GetSize,
@@ -64,15 +106,16 @@ async fn initialize_as_downstream<
     role: HandshakeRole,
     sender_outgoing: Sender<StandardEitherFrame<Message>>,
     receiver_incoming: Receiver<StandardEitherFrame<Message>>,
+    handshake_timeout: Option<Duration>,
 ) -> Result<(), Error> {
-    let mut state = codec_sv2::State::initialized(role);
+    let mut state = HandshakeDriver::new(role, handshake_timeout);
 
     // Create and send first handshake message
     let first_message = state.step_0()?;
     sender_outgoing.send(first_message.into()).await?;
 
     // Receive and deserialize second handshake message
-    let second_message = receiver_incoming.recv().await?;
+    let second_message = recv_handshake_frame(&receiver_incoming, &state).await?;
     let second_message: HandShakeFrame = second_message
         .try_into()
         .map_err(|_| Error::HandshakeRemoteInvalidMessage)?;
@@ -96,12 +139,12 @@ async fn initialize_as_upstream<'a, Message: Serialize + Deserialize<'a> + GetSi
     role: HandshakeRole,
     sender_outgoing: Sender<StandardEitherFrame<Message>>,
     receiver_incoming: Receiver<StandardEitherFrame<Message>>,
+    handshake_timeout: Option<Duration>,
 ) -> Result<(), Error> {
-    let mut state = codec_sv2::State::initialized(role);
+    let mut state = HandshakeDriver::new(role, handshake_timeout);
 
     // Receive and deserialize first handshake message
-    let first_message: HandShakeFrame = receiver_incoming
-        .recv()
+    let first_message: HandShakeFrame = recv_handshake_frame(&receiver_incoming, &state)
         .await?
         .try_into()
         .map_err(|_| Error::HandshakeRemoteInvalidMessage)?;
@@ -79,6 +79,7 @@ impl Connection {
                         drop(connection);
                         match decoded {
                             Ok(x) => {
+                                monitoring_sv2::metrics().frames_received.inc();
                                 if sender_incoming.send(x).await.is_err() {
                                     error!("Shutting down noise stream reader!");
                                     task::yield_now().await;
@@ -129,7 +130,7 @@ impl Connection {
                         let b = b.as_ref();
 
                         match (&writer).write_all(b).await {
-                            Ok(_) => (),
+                            Ok(_) => monitoring_sv2::metrics().frames_sent.inc(),
                             Err(_e) => {
                                 let _ = writer.shutdown(async_std::net::Shutdown::Both);
                             }
@@ -145,7 +146,7 @@ impl Connection {
         });
 
         // DO THE NOISE HANDSHAKE
-        match role {
+        let handshake = match role {
             HandshakeRole::Initiator(_) => {
                 debug!("Initializing as downstream for - {}", &address);
                 crate::initialize_as_downstream(
@@ -153,8 +154,9 @@ impl Connection {
                     role,
                     sender_outgoing.clone(),
                     receiver_incoming.clone(),
+                    None,
                 )
-                .await?
+                .await
             }
             HandshakeRole::Responder(_) => {
                 debug!("Initializing as upstream for - {}", &address);
@@ -163,10 +165,15 @@ impl Connection {
                     role,
                     sender_outgoing.clone(),
                     receiver_incoming.clone(),
+                    None,
                 )
-                .await?
+                .await
             }
         };
+        if let Err(e) = handshake {
+            monitoring_sv2::metrics().handshake_failures.inc();
+            return Err(e);
+        }
         debug!("Noise handshake complete - {}", &address);
 
         Ok((receiver_incoming, sender_outgoing))
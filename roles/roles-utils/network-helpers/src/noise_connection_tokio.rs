@@ -1,18 +1,21 @@
-use crate::Error;
+use crate::{backpressure::ConnectionOptions, keepalive::KeepaliveConfig, Error};
 use async_channel::{bounded, Receiver, Sender};
 use binary_sv2::{Deserialize, Serialize};
 use futures::lock::Mutex;
 use std::{sync::Arc, time::Duration};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::{TcpListener, TcpStream},
     task::{self, AbortHandle},
 };
 
 use binary_sv2::GetSize;
-use codec_sv2::{HandshakeRole, Initiator, Responder, StandardEitherFrame, StandardNoiseDecoder};
+use codec_sv2::{
+    noise_sv2::HandshakeRateLimiter, HandshakeRole, Initiator, Responder, StandardEitherFrame,
+    StandardNoiseDecoder,
+};
 
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
 #[derive(Debug)]
 pub struct Connection {
@@ -47,19 +50,92 @@ impl Connection {
             AbortHandle,
         ),
         Error,
+    > {
+        Self::new_with_keepalive(stream, role, KeepaliveConfig::default()).await
+    }
+
+    /// Like [`Connection::new`] but the peer is considered dead as configured in `keepalive`
+    pub async fn new_with_keepalive<
+        'a,
+        Message: Serialize + Deserialize<'a> + GetSize + Send + 'static,
+    >(
+        stream: TcpStream,
+        role: HandshakeRole,
+        keepalive: KeepaliveConfig,
+    ) -> Result<
+        (
+            Receiver<StandardEitherFrame<Message>>,
+            Sender<StandardEitherFrame<Message>>,
+            AbortHandle,
+            AbortHandle,
+        ),
+        Error,
+    > {
+        let options = ConnectionOptions {
+            keepalive,
+            ..Default::default()
+        };
+        Self::new_with_options(stream, role, options).await
+    }
+
+    /// Like [`Connection::new`] with the channel capacity and the dead peer detection of `options`
+    pub async fn new_with_options<
+        'a,
+        Message: Serialize + Deserialize<'a> + GetSize + Send + 'static,
+    >(
+        stream: TcpStream,
+        role: HandshakeRole,
+        options: ConnectionOptions,
+    ) -> Result<
+        (
+            Receiver<StandardEitherFrame<Message>>,
+            Sender<StandardEitherFrame<Message>>,
+            AbortHandle,
+            AbortHandle,
+        ),
+        Error,
     > {
         let address = stream.peer_addr().map_err(|_| Error::SocketClosed)?;
+        if let Err(e) = options.keepalive.apply(&stream) {
+            warn!("Failed to enable TCP keepalive for {}: {}", address, e);
+        }
+        let (reader, writer) = stream.into_split();
+        Self::from_split(reader, writer, role, options, address.to_string()).await
+    }
 
-        let (mut reader, mut writer) = stream.into_split();
+    /// Does the noise handshake over the given halves of a stream, for the transports other than
+    /// TCP. `address` is only used in the logs.
+    #[allow(clippy::type_complexity)]
+    pub async fn from_split<
+        'a,
+        Message: Serialize + Deserialize<'a> + GetSize + Send + 'static,
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    >(
+        mut reader: R,
+        mut writer: W,
+        role: HandshakeRole,
+        options: ConnectionOptions,
+        address: String,
+    ) -> Result<
+        (
+            Receiver<StandardEitherFrame<Message>>,
+            Sender<StandardEitherFrame<Message>>,
+            AbortHandle,
+            AbortHandle,
+        ),
+        Error,
+    > {
+        let keepalive = options.keepalive;
 
         let (sender_incoming, receiver_incoming): (
             Sender<StandardEitherFrame<Message>>,
             Receiver<StandardEitherFrame<Message>>,
-        ) = bounded(10); // TODO caller should provide this param
+        ) = bounded(options.channel_capacity);
         let (sender_outgoing, receiver_outgoing): (
             Sender<StandardEitherFrame<Message>>,
             Receiver<StandardEitherFrame<Message>>,
-        ) = bounded(10); // TODO caller should provide this param
+        ) = bounded(options.channel_capacity);
 
         let state = codec_sv2::State::not_initialized(&role);
 
@@ -67,14 +143,18 @@ impl Connection {
 
         let cloned1 = connection.clone();
         let cloned2 = connection.clone();
+        let receiver_outgoing_on_timeout = receiver_outgoing.clone();
 
         // RECEIVE AND PARSE INCOMING MESSAGES FROM TCP STREAM
+        let recv_address = address.clone();
         let recv_task = task::spawn(async move {
+            let address = recv_address;
             let mut decoder = StandardNoiseDecoder::<Message>::new();
 
             loop {
                 let writable = decoder.writable();
-                match reader.read_exact(writable).await {
+                let read = reader.read_exact(writable);
+                match crate::keepalive::read_with_timeout(read, keepalive.liveness_timeout).await {
                     Ok(_) => {
                         let mut connection = cloned1.lock().await;
                         let decoded = decoder.next_frame(&mut connection.state);
@@ -82,6 +162,7 @@ impl Connection {
 
                         match decoded {
                             Ok(x) => {
+                                monitoring_sv2::metrics().frames_received.inc();
                                 if sender_incoming.send(x).await.is_err() {
                                     error!("Shutting down noise stream reader!");
                                     task::yield_now().await;
@@ -108,6 +189,10 @@ impl Connection {
                         //kill thread without a panic - don't need to panic everytime a client
                         // disconnects
                         sender_incoming.close();
+                        // A hung peer is still connected, stop the writer to close the socket
+                        if e.kind() == std::io::ErrorKind::TimedOut {
+                            receiver_outgoing_on_timeout.close();
+                        }
                         task::yield_now().await;
                         break;
                     }
@@ -118,7 +203,9 @@ impl Connection {
         let receiver_outgoing_cloned = receiver_outgoing.clone();
 
         // ENCODE AND SEND INCOMING MESSAGES TO TCP STREAM
+        let send_address = address.clone();
         let send_task = task::spawn(async move {
+            let address = send_address;
             let mut encoder = codec_sv2::NoiseEncoder::<Message>::new();
 
             loop {
@@ -135,7 +222,7 @@ impl Connection {
                         let b = b.as_ref();
 
                         match (writer).write_all(b).await {
-                            Ok(_) => (),
+                            Ok(_) => monitoring_sv2::metrics().frames_sent.inc(),
                             Err(e) => {
                                 let _ = writer.shutdown().await;
                                 // Just fail and force to reinitialize everything
@@ -164,7 +251,7 @@ impl Connection {
         });
 
         // DO THE NOISE HANDSHAKE
-        match role {
+        let handshake = match role {
             HandshakeRole::Initiator(_) => {
                 debug!("Initializing as downstream for - {}", &address);
                 crate::initialize_as_downstream(
@@ -172,8 +259,9 @@ impl Connection {
                     role,
                     sender_outgoing.clone(),
                     receiver_incoming.clone(),
+                    options.handshake_timeout,
                 )
-                .await?
+                .await
             }
             HandshakeRole::Responder(_) => {
                 debug!("Initializing as upstream for - {}", &address);
@@ -182,10 +270,19 @@ impl Connection {
                     role,
                     sender_outgoing.clone(),
                     receiver_incoming.clone(),
+                    options.handshake_timeout,
                 )
-                .await?
+                .await
             }
         };
+        if let Err(e) = handshake {
+            // Closes the stream, a stalled peer does not hold it
+            error!("Noise handshake failed: {:?} - {}", e, &address);
+            monitoring_sv2::metrics().handshake_failures.inc();
+            recv_task.abort();
+            send_task.abort();
+            return Err(e);
+        }
         debug!("Noise handshake complete - {}", &address);
         Ok((
             receiver_incoming,
@@ -218,6 +315,35 @@ pub async fn listen(
     }
 }
 
+/// Like [`listen`] but the connections of the peers starting handshakes faster than `limiter`
+/// allows are closed before the responder generates its keys
+pub async fn listen_with_rate_limit(
+    address: &str,
+    authority_public_key: [u8; 32],
+    authority_private_key: [u8; 32],
+    cert_validity: Duration,
+    sender: Sender<(TcpStream, HandshakeRole)>,
+    mut limiter: HandshakeRateLimiter,
+) {
+    let listner = TcpListener::bind(address).await.unwrap();
+    loop {
+        if let Ok((stream, peer)) = listner.accept().await {
+            if !limiter.allow(peer.ip()) {
+                warn!("Too many handshakes, connection refused - {}", peer);
+                continue;
+            }
+            let responder = Responder::from_authority_kp(
+                &authority_public_key,
+                &authority_private_key,
+                cert_validity,
+            )
+            .unwrap();
+            let role = HandshakeRole::Responder(responder);
+            let _ = sender.send((stream, role)).await;
+        }
+    }
+}
+
 pub async fn connect(
     address: &str,
     authority_public_key: [u8; 32],
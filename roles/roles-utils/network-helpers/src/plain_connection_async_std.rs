@@ -9,7 +9,7 @@ use core::convert::TryInto;
 use tracing::error;
 
 use binary_sv2::GetSize;
-use codec_sv2::{StandardDecoder, StandardEitherFrame};
+use codec_sv2::{read_frame, write_frame, FrameIoError, StandardDecoder, StandardEitherFrame};
 
 #[derive(Debug)]
 pub struct PlainConnection {}
@@ -23,7 +23,7 @@ impl PlainConnection {
         Receiver<StandardEitherFrame<Message>>,
         Sender<StandardEitherFrame<Message>>,
     ) {
-        let (mut reader, writer) = (stream.clone(), stream);
+        let (mut reader, mut writer) = (stream.clone(), stream);
 
         let (sender_incoming, receiver_incoming): (
             Sender<StandardEitherFrame<Message>>,
@@ -39,26 +39,20 @@ impl PlainConnection {
             let mut decoder = StandardDecoder::<Message>::new();
 
             loop {
-                let writable = decoder.writable();
-                match reader.read_exact(writable).await {
-                    Ok(_) => match decoder.next_frame() {
-                        Ok(x) => {
-                            if sender_incoming.send(x.into()).await.is_err() {
-                                error!("Shutting down stream reader!");
-                                task::yield_now().await;
-                                break;
-                            }
+                match read_frame(&mut reader, &mut decoder).await {
+                    Ok(x) => {
+                        if sender_incoming.send(x.into()).await.is_err() {
+                            error!("Shutting down stream reader!");
+                            task::yield_now().await;
+                            break;
                         }
-                        Err(e) => {
-                            if let codec_sv2::Error::MissingBytes(_) = e {
-                            } else {
-                                error!("Shutting down stream reader! {:#?}", e);
-                                let _ = reader.shutdown(async_std::net::Shutdown::Both);
-                                break;
-                            }
-                        }
-                    },
-                    Err(_) => {
+                    }
+                    Err(FrameIoError::Codec(e)) => {
+                        error!("Shutting down stream reader! {:#?}", e);
+                        let _ = reader.shutdown(async_std::net::Shutdown::Both);
+                        break;
+                    }
+                    Err(FrameIoError::Io(_)) => {
                         let _ = reader.shutdown(async_std::net::Shutdown::Both);
                         break;
                     }
@@ -74,11 +68,15 @@ impl PlainConnection {
                 let received = receiver_outgoing.recv().await;
                 match received {
                     Ok(frame) => {
-                        let b = encoder.encode(frame.try_into().unwrap()).unwrap();
-
-                        match (&writer).write_all(b).await {
+                        let frame = frame.try_into().unwrap();
+                        match write_frame(&mut writer, &mut encoder, frame).await {
                             Ok(_) => (),
-                            Err(_) => {
+                            Err(FrameIoError::Codec(e)) => {
+                                error!("Failed to encode frame: {:#?}", e);
+                                let _ = writer.shutdown(async_std::net::Shutdown::Both);
+                                break;
+                            }
+                            Err(FrameIoError::Io(_)) => {
                                 let _ = writer.shutdown(async_std::net::Shutdown::Both);
                             }
                         }
@@ -2,14 +2,16 @@ use async_channel::{bounded, Receiver, Sender};
 use binary_sv2::{Deserialize, Serialize};
 use core::convert::TryInto;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::{TcpListener, TcpStream},
     task,
 };
 
 use binary_sv2::GetSize;
 use codec_sv2::{Error::MissingBytes, StandardDecoder, StandardEitherFrame};
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
+
+use crate::{backpressure::ConnectionOptions, keepalive::KeepaliveConfig};
 
 #[derive(Debug)]
 pub struct PlainConnection {}
@@ -28,26 +30,86 @@ impl PlainConnection {
         Receiver<StandardEitherFrame<Message>>,
         Sender<StandardEitherFrame<Message>>,
     ) {
-        const NOISE_HANDSHAKE_SIZE_HINT: usize = 3363412;
+        Self::new_with_keepalive(stream, KeepaliveConfig::default()).await
+    }
+
+    /// Like [`PlainConnection::new`] but the peer is considered dead as configured in `keepalive`
+    pub async fn new_with_keepalive<
+        'a,
+        Message: Serialize + Deserialize<'a> + GetSize + Send + 'static,
+    >(
+        stream: TcpStream,
+        keepalive: KeepaliveConfig,
+    ) -> (
+        Receiver<StandardEitherFrame<Message>>,
+        Sender<StandardEitherFrame<Message>>,
+    ) {
+        let options = ConnectionOptions {
+            keepalive,
+            ..Default::default()
+        };
+        Self::new_with_options(stream, options).await
+    }
+
+    /// Like [`PlainConnection::new`] with the channel capacity and the dead peer detection of
+    /// `options`
+    pub async fn new_with_options<
+        'a,
+        Message: Serialize + Deserialize<'a> + GetSize + Send + 'static,
+    >(
+        stream: TcpStream,
+        options: ConnectionOptions,
+    ) -> (
+        Receiver<StandardEitherFrame<Message>>,
+        Sender<StandardEitherFrame<Message>>,
+    ) {
+        if let Err(e) = options.keepalive.apply(&stream) {
+            warn!("Failed to enable TCP keepalive: {}", e);
+        }
+        let (reader, writer) = stream.into_split();
+        Self::from_split(reader, writer, options)
+    }
 
-        let (mut reader, mut writer) = stream.into_split();
+    /// Carries the frames over the given halves of a stream, for the transports other than TCP.
+    /// TCP keepalive is not applied, it has to be done on the underlying socket.
+    pub fn from_split<
+        'a,
+        Message: Serialize + Deserialize<'a> + GetSize + Send + 'static,
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    >(
+        mut reader: R,
+        mut writer: W,
+        options: ConnectionOptions,
+    ) -> (
+        Receiver<StandardEitherFrame<Message>>,
+        Sender<StandardEitherFrame<Message>>,
+    ) {
+        let keepalive = options.keepalive;
+        let frame_crc = options.frame_crc;
+        const NOISE_HANDSHAKE_SIZE_HINT: usize = 3363412;
 
         let (sender_incoming, receiver_incoming): (
             Sender<StandardEitherFrame<Message>>,
             Receiver<StandardEitherFrame<Message>>,
-        ) = bounded(10); // TODO caller should provide this param
+        ) = bounded(options.channel_capacity);
         let (sender_outgoing, receiver_outgoing): (
             Sender<StandardEitherFrame<Message>>,
             Receiver<StandardEitherFrame<Message>>,
-        ) = bounded(10); // TODO caller should provide this param
+        ) = bounded(options.channel_capacity);
+        let receiver_outgoing_on_timeout = receiver_outgoing.clone();
 
         // RECEIVE AND PARSE INCOMING MESSAGES FROM TCP STREAM
         task::spawn(async move {
-            let mut decoder = StandardDecoder::<Message>::new();
+            let mut decoder = match frame_crc {
+                true => StandardDecoder::<Message>::with_crc(),
+                false => StandardDecoder::<Message>::new(),
+            };
 
             loop {
                 let writable = decoder.writable();
-                match reader.read_exact(writable).await {
+                let read = reader.read_exact(writable);
+                match crate::keepalive::read_with_timeout(read, keepalive.liveness_timeout).await {
                     Ok(_) => {
                         match decoder.next_frame() {
                             Ok(frame) => {
@@ -80,6 +142,10 @@ impl PlainConnection {
                         // Just fail and force to reinitialize everything
                         error!("Failed to read from stream: {}", e);
                         sender_incoming.close();
+                        // A hung peer is still connected, stop the writer to close the socket
+                        if e.kind() == std::io::ErrorKind::TimedOut {
+                            receiver_outgoing_on_timeout.close();
+                        }
                         task::yield_now().await;
                         break;
                     }
@@ -89,7 +155,10 @@ impl PlainConnection {
 
         // ENCODE AND SEND INCOMING MESSAGES TO TCP STREAM
         task::spawn(async move {
-            let mut encoder = codec_sv2::Encoder::<Message>::new();
+            let mut encoder = match frame_crc {
+                true => codec_sv2::Encoder::<Message>::with_crc(),
+                false => codec_sv2::Encoder::<Message>::new(),
+            };
 
             loop {
                 let received = receiver_outgoing.recv().await;
@@ -0,0 +1,206 @@
+//! A connection to an upstream that survives disconnections.
+//!
+//! [`ReconnectingConnection::new`] returns a receiver and a sender like the other connections,
+//! but they stay valid when the TCP connection is lost: the connection is
+//! established again, with the noise handshake if needed, waiting between the attempts with an
+//! exponential backoff. The `SetupConnection` frame, if given, is sent again after every
+//! reconnection and its answer is received as any other message. Changes of the connection are
+//! sent on the status channel.
+//!
+//! Frames sent while the connection is down are sent once it is back, the frame that was being
+//! sent when the connection was lost is lost.
+use crate::{
+    backpressure::ConnectionOptions,
+    transport::{NoiseInitiatorTransport, PlainTransport, Sv2Connection, Sv2Transport},
+    Error,
+};
+use async_channel::{bounded, unbounded, Receiver, Sender};
+use binary_sv2::{Deserialize, GetSize, Serialize};
+use codec_sv2::StandardEitherFrame;
+use std::time::Duration;
+use tokio::task;
+use tracing::{error, info, warn};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Connected,
+    /// The connection is lost or could not be established
+    Disconnected,
+    /// Waiting `delay` before the attempt number `attempt`
+    Reconnecting {
+        attempt: u32,
+        delay: Duration,
+    },
+    /// Reconnection stopped, either because `max_attempts` was reached or because the receiver or
+    /// the sender of the connection has been dropped
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Transport {
+    Plain,
+    Noise { authority_public_key: [u8; 32] },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectConfig {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Consecutive failed attempts after which the connection is closed, never closed if `None`
+    pub max_attempts: Option<u32>,
+    /// Channel capacity and dead peer detection of every connection, a dead connection is
+    /// reconnected
+    pub connection: ConnectionOptions,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            max_attempts: None,
+            connection: ConnectionOptions::default(),
+        }
+    }
+}
+
+impl ReconnectConfig {
+    /// Delay after `failures` consecutive failed attempts
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(failures.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Debug)]
+pub struct ReconnectingConnection {}
+
+impl ReconnectingConnection {
+    /// Returns the receiver and the sender of the connection and the status channel. The first
+    /// connection is made in the background, the status channel says when it is established.
+    #[allow(clippy::new_ret_no_self, clippy::type_complexity)]
+    pub fn new<Message: Serialize + Deserialize<'static> + GetSize + Send + 'static>(
+        address: String,
+        transport: Transport,
+        config: ReconnectConfig,
+        setup_connection: Option<Box<dyn Fn() -> StandardEitherFrame<Message> + Send + Sync>>,
+    ) -> (
+        Receiver<StandardEitherFrame<Message>>,
+        Sender<StandardEitherFrame<Message>>,
+        Receiver<ConnectionStatus>,
+    ) {
+        let (sender_incoming, receiver_incoming) = bounded(config.connection.channel_capacity);
+        let (sender_outgoing, receiver_outgoing) = bounded(config.connection.channel_capacity);
+        let (sender_status, receiver_status) = unbounded();
+        task::spawn(async move {
+            // A lost connection is tried again right away, then with the backoff
+            let mut failures = 0;
+            loop {
+                if failures > 0 {
+                    if matches!(config.max_attempts, Some(max) if failures >= max) {
+                        error!(
+                            "Giving up connecting to {} after {} attempts",
+                            address, failures
+                        );
+                        break;
+                    }
+                    let delay = config.backoff(failures);
+                    let _ = sender_status.try_send(ConnectionStatus::Reconnecting {
+                        attempt: failures + 1,
+                        delay,
+                    });
+                    tokio::time::sleep(delay).await;
+                }
+                let connection = match open::<Message>(&address, transport, config.connection).await
+                {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        warn!("Failed to connect to {}: {:?}", address, e);
+                        failures += 1;
+                        let _ = sender_status.try_send(ConnectionStatus::Disconnected);
+                        continue;
+                    }
+                };
+                if let Some(setup_connection) = &setup_connection {
+                    if connection.sender.send(setup_connection()).await.is_err() {
+                        failures += 1;
+                        let _ = sender_status.try_send(ConnectionStatus::Disconnected);
+                        continue;
+                    }
+                }
+                info!("Connected to {}", address);
+                failures = 0;
+                let _ = sender_status.try_send(ConnectionStatus::Connected);
+                let closed = relay(
+                    &connection.receiver,
+                    &connection.sender,
+                    &sender_incoming,
+                    &receiver_outgoing,
+                )
+                .await;
+                connection.abort();
+                if closed {
+                    break;
+                }
+                warn!("Disconnected from {}", address);
+                let _ = sender_status.try_send(ConnectionStatus::Disconnected);
+            }
+            sender_incoming.close();
+            receiver_outgoing.close();
+            let _ = sender_status.try_send(ConnectionStatus::Closed);
+        });
+        (receiver_incoming, sender_outgoing, receiver_status)
+    }
+}
+
+async fn open<Message: Serialize + Deserialize<'static> + GetSize + Send + 'static>(
+    address: &str,
+    transport: Transport,
+    options: ConnectionOptions,
+) -> Result<Sv2Connection<Message>, Error> {
+    match transport {
+        Transport::Plain => PlainTransport { options }.connect(address).await,
+        Transport::Noise {
+            authority_public_key,
+        } => {
+            NoiseInitiatorTransport {
+                authority_public_key: Some(authority_public_key),
+                options,
+            }
+            .connect(address)
+            .await
+        }
+    }
+}
+
+/// Relays frames until the connection is lost. Returns true if the caller dropped its side of
+/// the connection.
+async fn relay<Message>(
+    inner_receiver: &Receiver<StandardEitherFrame<Message>>,
+    inner_sender: &Sender<StandardEitherFrame<Message>>,
+    sender_incoming: &Sender<StandardEitherFrame<Message>>,
+    receiver_outgoing: &Receiver<StandardEitherFrame<Message>>,
+) -> bool {
+    loop {
+        tokio::select! {
+            incoming = inner_receiver.recv() => match incoming {
+                Ok(frame) => {
+                    if sender_incoming.send(frame).await.is_err() {
+                        return true;
+                    }
+                }
+                Err(_) => return false,
+            },
+            outgoing = receiver_outgoing.recv() => match outgoing {
+                Ok(frame) => {
+                    if inner_sender.send(frame).await.is_err() {
+                        return false;
+                    }
+                }
+                Err(_) => return true,
+            },
+        }
+    }
+}
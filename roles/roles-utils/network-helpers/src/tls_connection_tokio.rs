@@ -0,0 +1,88 @@
+//! Plain SV2 frames over TLS.
+//!
+//! For deployments where the encryption is terminated by a TLS proxy, e.g. a corporate proxy in
+//! front of the pool. There is no noise handshake, the upstream is authenticated by its
+//! certificate. The receiver and the sender are the same as the ones of [`PlainConnection`].
+use crate::{backpressure::ConnectionOptions, plain_connection_tokio::PlainConnection, Error};
+use async_channel::{Receiver, Sender};
+use binary_sv2::{Deserialize, GetSize, Serialize};
+use codec_sv2::StandardEitherFrame;
+use std::{convert::TryFrom, fs::File, io::BufReader, path::PathBuf, sync::Arc};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    rustls::{Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName},
+    TlsConnector,
+};
+use tracing::warn;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsClientConfig {
+    /// Name checked against the certificate of the server
+    pub server_name: String,
+    /// PEM file with the certificates of the trusted authorities, the webpki roots are trusted
+    /// if `None`
+    pub ca_file: Option<PathBuf>,
+}
+
+impl TlsClientConfig {
+    fn root_certificates(&self) -> Result<RootCertStore, Error> {
+        let mut roots = RootCertStore::empty();
+        match &self.ca_file {
+            Some(path) => {
+                let file = File::open(path)
+                    .map_err(|e| Error::Tls(format!("{}: {}", path.display(), e)))?;
+                let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+                    .map_err(|e| Error::Tls(format!("{}: {}", path.display(), e)))?;
+                for cert in certs {
+                    roots
+                        .add(&Certificate(cert))
+                        .map_err(|e| Error::Tls(e.to_string()))?;
+                }
+            }
+            None => roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    ta.subject,
+                    ta.spki,
+                    ta.name_constraints,
+                )
+            })),
+        }
+        Ok(roots)
+    }
+}
+
+#[derive(Debug)]
+pub struct TlsConnection {}
+
+impl TlsConnection {
+    /// Does the TLS handshake on `stream` and returns the receiver and the sender of the
+    /// connection
+    #[allow(clippy::type_complexity)]
+    pub async fn connect<'a, Message: Serialize + Deserialize<'a> + GetSize + Send + 'static>(
+        stream: TcpStream,
+        config: &TlsClientConfig,
+        options: ConnectionOptions,
+    ) -> Result<
+        (
+            Receiver<StandardEitherFrame<Message>>,
+            Sender<StandardEitherFrame<Message>>,
+        ),
+        Error,
+    > {
+        if let Err(e) = options.keepalive.apply(&stream) {
+            warn!("Failed to enable TCP keepalive: {}", e);
+        }
+        let client_config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(config.root_certificates()?)
+            .with_no_client_auth();
+        let server_name = ServerName::try_from(config.server_name.as_str())
+            .map_err(|e| Error::Tls(format!("{}: {}", config.server_name, e)))?;
+        let stream = TlsConnector::from(Arc::new(client_config))
+            .connect(server_name, stream)
+            .await
+            .map_err(|e| Error::Tls(e.to_string()))?;
+        let (reader, writer) = tokio::io::split(stream);
+        Ok(PlainConnection::from_split(reader, writer, options))
+    }
+}
@@ -0,0 +1,210 @@
+//! Transports over which the SV2 frames are carried.
+//!
+//! A [`Sv2Transport`] turns a TCP stream, connected or accepted, into the receiver and the sender
+//! of the frames, so that the roles handle every transport with the same code. New transports
+//! only have to implement [`Sv2Transport::open`].
+use crate::{
+    backpressure::ConnectionOptions, noise_connection_tokio::Connection,
+    plain_connection_tokio::PlainConnection, Error,
+};
+use async_channel::{Receiver, Sender};
+use binary_sv2::{Deserialize, GetSize, Serialize};
+use codec_sv2::{Error as CodecError, HandshakeRole, Initiator, Responder, StandardEitherFrame};
+use std::{future::Future, net::SocketAddr, pin::Pin, time::Duration};
+use tokio::{net::TcpStream, task::AbortHandle};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerInfo {
+    pub address: SocketAddr,
+    /// False if the frames are sent in clear
+    pub encrypted: bool,
+}
+
+#[derive(Debug)]
+pub struct Sv2Connection<Message> {
+    pub receiver: Receiver<StandardEitherFrame<Message>>,
+    pub sender: Sender<StandardEitherFrame<Message>>,
+    pub peer: PeerInfo,
+    // tasks that read and write the stream, if the transport exposes them
+    tasks: Vec<AbortHandle>,
+}
+
+impl<Message> Sv2Connection<Message> {
+    /// Stops reading and writing the stream, this closes the connection
+    pub fn abort(&self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+pub type TransportFuture<'a, Message> =
+    Pin<Box<dyn Future<Output = Result<Sv2Connection<Message>, Error>> + Send + 'a>>;
+
+pub trait Sv2Transport: Send + Sync {
+    /// Opens a connection on a stream, either connected to or accepted from the peer
+    fn open<Message: Serialize + Deserialize<'static> + GetSize + Send + 'static>(
+        &self,
+        stream: TcpStream,
+    ) -> TransportFuture<'_, Message>;
+
+    /// Connects to `address` and opens a connection on it
+    fn connect<Message: Serialize + Deserialize<'static> + GetSize + Send + 'static>(
+        &self,
+        address: &str,
+    ) -> TransportFuture<'_, Message> {
+        let address = address.to_string();
+        Box::pin(async move {
+            let stream = TcpStream::connect(address)
+                .await
+                .map_err(|_| Error::SocketClosed)?;
+            self.open(stream).await
+        })
+    }
+}
+
+fn peer_address(stream: &TcpStream) -> Result<SocketAddr, Error> {
+    stream.peer_addr().map_err(|_| Error::SocketClosed)
+}
+
+/// Frames sent in clear
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlainTransport {
+    pub options: ConnectionOptions,
+}
+
+impl Sv2Transport for PlainTransport {
+    fn open<Message: Serialize + Deserialize<'static> + GetSize + Send + 'static>(
+        &self,
+        stream: TcpStream,
+    ) -> TransportFuture<'_, Message> {
+        let options = self.options;
+        Box::pin(async move {
+            let address = peer_address(&stream)?;
+            let (receiver, sender) = PlainConnection::new_with_options(stream, options).await;
+            Ok(Sv2Connection {
+                receiver,
+                sender,
+                peer: PeerInfo {
+                    address,
+                    encrypted: false,
+                },
+                tasks: vec![],
+            })
+        })
+    }
+}
+
+/// Noise, connecting side. The certificate of the peer is checked against
+/// `authority_public_key` if set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoiseInitiatorTransport {
+    pub authority_public_key: Option<[u8; 32]>,
+    pub options: ConnectionOptions,
+}
+
+impl Sv2Transport for NoiseInitiatorTransport {
+    fn open<Message: Serialize + Deserialize<'static> + GetSize + Send + 'static>(
+        &self,
+        stream: TcpStream,
+    ) -> TransportFuture<'_, Message> {
+        let (authority_public_key, options) = (self.authority_public_key, self.options);
+        Box::pin(async move {
+            let address = peer_address(&stream)?;
+            let initiator = match authority_public_key {
+                Some(key) => Initiator::from_raw_k(key),
+                None => Initiator::without_pk(),
+            }
+            .map_err(CodecError::from)?;
+            let role = HandshakeRole::Initiator(initiator);
+            let (receiver, sender, recv_task, send_task) =
+                Connection::new_with_options(stream, role, options).await?;
+            Ok(Sv2Connection {
+                receiver,
+                sender,
+                peer: PeerInfo {
+                    address,
+                    encrypted: true,
+                },
+                tasks: vec![recv_task, send_task],
+            })
+        })
+    }
+}
+
+/// Noise, accepting side. A certificate valid for `cert_validity` is signed with the authority
+/// keys for every connection.
+#[derive(Debug, Clone, Copy)]
+pub struct NoiseResponderTransport {
+    pub authority_public_key: [u8; 32],
+    pub authority_secret_key: [u8; 32],
+    pub cert_validity: Duration,
+    pub options: ConnectionOptions,
+}
+
+impl Sv2Transport for NoiseResponderTransport {
+    fn open<Message: Serialize + Deserialize<'static> + GetSize + Send + 'static>(
+        &self,
+        stream: TcpStream,
+    ) -> TransportFuture<'_, Message> {
+        let transport = *self;
+        Box::pin(async move {
+            let address = peer_address(&stream)?;
+            let responder = Responder::from_authority_kp(
+                &transport.authority_public_key,
+                &transport.authority_secret_key,
+                transport.cert_validity,
+            )
+            .map_err(CodecError::from)?;
+            let role = HandshakeRole::Responder(responder);
+            let (receiver, sender, recv_task, send_task) =
+                Connection::new_with_options(stream, role, transport.options).await?;
+            Ok(Sv2Connection {
+                receiver,
+                sender,
+                peer: PeerInfo {
+                    address,
+                    encrypted: true,
+                },
+                tasks: vec![recv_task, send_task],
+            })
+        })
+    }
+}
+
+#[cfg(feature = "tls")]
+mod tls {
+    use super::*;
+    use crate::tls_connection_tokio::{TlsClientConfig, TlsConnection};
+
+    /// TLS, connecting side
+    #[derive(Debug, Clone)]
+    pub struct TlsTransport {
+        pub config: TlsClientConfig,
+        pub options: ConnectionOptions,
+    }
+
+    impl Sv2Transport for TlsTransport {
+        fn open<Message: Serialize + Deserialize<'static> + GetSize + Send + 'static>(
+            &self,
+            stream: TcpStream,
+        ) -> TransportFuture<'_, Message> {
+            let (config, options) = (self.config.clone(), self.options);
+            Box::pin(async move {
+                let address = peer_address(&stream)?;
+                let (receiver, sender) = TlsConnection::connect(stream, &config, options).await?;
+                Ok(Sv2Connection {
+                    receiver,
+                    sender,
+                    peer: PeerInfo {
+                        address,
+                        encrypted: true,
+                    },
+                    tasks: vec![],
+                })
+            })
+        }
+    }
+}
+#[cfg(feature = "tls")]
+pub use tls::TlsTransport;
@@ -0,0 +1,76 @@
+//! Connections over Unix domain sockets, for roles running on the same host.
+//!
+//! The frames are the same as over TCP. Since the traffic does not leave the host the noise
+//! handshake can be skipped with [`UnixConnection::plain`]. TCP keepalive does not apply, the
+//! liveness timeout does.
+use crate::{
+    backpressure::ConnectionOptions, noise_connection_tokio::Connection,
+    plain_connection_tokio::PlainConnection, Error,
+};
+use async_channel::{Receiver, Sender};
+use binary_sv2::{Deserialize, GetSize, Serialize};
+use codec_sv2::{HandshakeRole, StandardEitherFrame};
+use std::path::Path;
+use tokio::{
+    net::{UnixListener, UnixStream},
+    task::AbortHandle,
+};
+
+#[derive(Debug)]
+pub struct UnixConnection {}
+
+impl UnixConnection {
+    /// Frames sent in clear
+    pub fn plain<'a, Message: Serialize + Deserialize<'a> + GetSize + Send + 'static>(
+        stream: UnixStream,
+        options: ConnectionOptions,
+    ) -> (
+        Receiver<StandardEitherFrame<Message>>,
+        Sender<StandardEitherFrame<Message>>,
+    ) {
+        let (reader, writer) = stream.into_split();
+        PlainConnection::from_split(reader, writer, options)
+    }
+
+    /// Frames encrypted with noise, like [`Connection::new`]
+    #[allow(clippy::type_complexity)]
+    pub async fn noise<'a, Message: Serialize + Deserialize<'a> + GetSize + Send + 'static>(
+        stream: UnixStream,
+        role: HandshakeRole,
+        options: ConnectionOptions,
+    ) -> Result<
+        (
+            Receiver<StandardEitherFrame<Message>>,
+            Sender<StandardEitherFrame<Message>>,
+            AbortHandle,
+            AbortHandle,
+        ),
+        Error,
+    > {
+        let address = match stream
+            .peer_addr()
+            .ok()
+            .and_then(|a| a.as_pathname().map(Path::to_path_buf))
+        {
+            Some(path) => format!("unix://{}", path.display()),
+            None => "unix socket".to_string(),
+        };
+        let (reader, writer) = stream.into_split();
+        Connection::from_split(reader, writer, role, options, address).await
+    }
+}
+
+/// Accepts the connections on `path`, a socket left by a previous run is removed
+pub async fn unix_listen(path: &Path, sender: Sender<UnixStream>) {
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path).unwrap();
+    loop {
+        if let Ok((stream, _)) = listener.accept().await {
+            let _ = sender.send(stream).await;
+        }
+    }
+}
+
+pub async fn unix_connect(path: &Path) -> Result<UnixStream, ()> {
+    UnixStream::connect(path).await.map_err(|_| ())
+}
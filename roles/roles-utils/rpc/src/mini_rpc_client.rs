@@ -14,7 +14,9 @@ use hyper_util::{
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use stratum_common::bitcoin::{consensus::encode::deserialize as consensus_decode, Transaction};
+use stratum_common::bitcoin::{
+    consensus::encode::deserialize as consensus_decode, Amount, Transaction,
+};
 
 use super::BlockHash;
 
@@ -76,6 +78,37 @@ impl MiniRpcClient {
         }
     }
 
+    /// Fee in satoshis of the transaction `txid` in the mempool of the node (`getmempoolentry`)
+    pub async fn get_mempool_entry_fee(&self, txid: &str) -> Result<u64, RpcError> {
+        let response = self
+            .send_json_rpc_request("getmempoolentry", json!([txid]))
+            .await?;
+        let result_deserialized: JsonRpcResult<MempoolEntry> = serde_json::from_str(&response)
+            .map_err(|e| {
+                RpcError::Deserialization(e.to_string()) // TODO manage message ids
+            })?;
+        let entry = result_deserialized
+            .result
+            .ok_or_else(|| RpcError::Other("Result not found".to_string()))?;
+        btc_to_sat(entry.fees.base)
+    }
+
+    /// Value in satoshis of the output `vout` of `txid` (`gettxout`), `None` if the output is
+    /// spent or unknown to the node
+    pub async fn get_tx_out_value(&self, txid: &str, vout: u32) -> Result<Option<u64>, RpcError> {
+        let response = self
+            .send_json_rpc_request("gettxout", json!([txid, vout, true]))
+            .await?;
+        let result_deserialized: JsonRpcResult<TxOut> =
+            serde_json::from_str(&response).map_err(|e| {
+                RpcError::Deserialization(e.to_string()) // TODO manage message ids
+            })?;
+        result_deserialized
+            .result
+            .map(|tx_out| btc_to_sat(tx_out.value))
+            .transpose()
+    }
+
     pub async fn submit_block(&self, block_hex: String) -> Result<(), RpcError> {
         let response = self
             .send_json_rpc_request("submitblock", json!([block_hex]))
@@ -172,6 +205,28 @@ struct JsonRpcRequest {
     id: u64,
 }
 
+#[derive(Debug, Deserialize)]
+struct MempoolEntry {
+    fees: MempoolEntryFees,
+}
+
+#[derive(Debug, Deserialize)]
+struct MempoolEntryFees {
+    base: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TxOut {
+    value: f64,
+}
+
+// The node returns the amounts in BTC
+fn btc_to_sat(btc: f64) -> Result<u64, RpcError> {
+    Amount::from_btc(btc)
+        .map(|amount| amount.to_sat())
+        .map_err(|e| RpcError::Deserialization(e.to_string()))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct JsonRpcResult<T> {
     result: Option<T>,
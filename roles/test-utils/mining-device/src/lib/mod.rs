@@ -14,18 +14,22 @@ use std::{
 use tokio::net::TcpStream;
 
 use async_channel::{Receiver, Sender};
-use binary_sv2::u256_from_int;
 use codec_sv2::{Initiator, StandardEitherFrame, StandardSv2Frame};
 use rand::{thread_rng, Rng};
 use roles_logic_sv2::{
-    common_messages_sv2::{Protocol, SetupConnection, SetupConnectionSuccess},
+    common_messages_sv2::{
+        DeviceInfoBuilder, Endpoint, Protocol, SetupConnection, SetupConnectionSuccess,
+    },
     common_properties::{IsMiningUpstream, IsUpstream},
     errors::Error,
     handlers::{
         common::ParseUpstreamCommonMessages,
         mining::{ParseUpstreamMiningMessages, SendTo, SupportedChannelTypes},
     },
-    mining_sv2::*,
+    mining_sv2::{
+        version_rolling::{allowed_version_mask, apply_rolled_bits},
+        *,
+    },
     parsers::{Mining, MiningDeviceMessages},
     routing_logic::{CommonRoutingLogic, MiningRoutingLogic, NoRouting},
     selectors::NullDownstreamMiningSelector,
@@ -35,7 +39,10 @@ use std::time::Instant;
 use stratum_common::bitcoin::{
     blockdata::block::BlockHeader, hash_types::BlockHash, hashes::Hash, util::uint::Uint256,
 };
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+/// Flag of `SetupConnectionSuccess` set by an upstream that does not accept rolled versions
+const REQUIRES_FIXED_VERSION: u32 = 0b0001;
 
 pub async fn connect(
     address: String,
@@ -44,6 +51,7 @@ pub async fn connect(
     user_id: Option<String>,
     handicap: u32,
     nominal_hashrate_multiplier: Option<f32>,
+    shares: Option<u32>,
 ) {
     let address = address
         .clone()
@@ -87,6 +95,7 @@ pub async fn connect(
         user_id,
         handicap,
         nominal_hashrate_multiplier,
+        shares,
     )
     .await
 }
@@ -95,46 +104,42 @@ pub type Message = MiningDeviceMessages<'static>;
 pub type StdFrame = StandardSv2Frame<Message>;
 pub type EitherFrame = StandardEitherFrame<Message>;
 
-struct SetupConnectionHandler {}
+struct SetupConnectionHandler {
+    flags: u32,
+}
 use std::convert::TryInto;
 
 impl SetupConnectionHandler {
     pub fn new() -> Self {
-        SetupConnectionHandler {}
+        SetupConnectionHandler { flags: 0 }
     }
     fn get_setup_connection_message(
         address: SocketAddr,
         device_id: Option<String>,
     ) -> SetupConnection<'static> {
-        let endpoint_host = address.ip().to_string().into_bytes().try_into().unwrap();
-        let vendor = String::new().try_into().unwrap();
-        let hardware_version = String::new().try_into().unwrap();
-        let firmware = String::new().try_into().unwrap();
+        let endpoint = Endpoint::new(&address.ip().to_string(), address.port()).unwrap();
         let device_id = device_id.unwrap_or_default();
         info!(
             "Creating SetupConnection message with device id: {:?}",
             device_id
         );
-        SetupConnection {
-            protocol: Protocol::MiningProtocol,
-            min_version: 2,
-            max_version: 2,
-            flags: 0b0000_0000_0000_0000_0000_0000_0000_0001,
-            endpoint_host,
-            endpoint_port: address.port(),
-            vendor,
-            hardware_version,
-            firmware,
-            device_id: device_id.try_into().unwrap(),
-        }
+        let device_info = DeviceInfoBuilder::new("")
+            .device_id(&device_id)
+            .build()
+            .unwrap();
+        let mut setup_connection =
+            SetupConnection::for_protocol(Protocol::MiningProtocol, device_info, endpoint);
+        setup_connection.flags = 0b0000_0000_0000_0000_0000_0000_0000_0001;
+        setup_connection
     }
+    /// Returns the flags of the `SetupConnectionSuccess`
     pub async fn setup(
         self_: Arc<Mutex<Self>>,
         receiver: &mut Receiver<EitherFrame>,
         sender: &mut Sender<EitherFrame>,
         device_id: Option<String>,
         address: SocketAddr,
-    ) {
+    ) -> u32 {
         let setup_connection = Self::get_setup_connection_message(address, device_id);
 
         let sv2_frame: StdFrame = MiningDeviceMessages::Common(setup_connection.into())
@@ -148,31 +153,36 @@ impl SetupConnectionHandler {
         let message_type = incoming.get_header().unwrap().msg_type();
         let payload = incoming.payload();
         ParseUpstreamCommonMessages::handle_message_common(
-            self_,
+            self_.clone(),
             message_type,
             payload,
             CommonRoutingLogic::None,
         )
         .unwrap();
+        self_.safe_lock(|s| s.flags).unwrap()
     }
 }
 
 impl ParseUpstreamCommonMessages<NoRouting> for SetupConnectionHandler {
     fn handle_setup_connection_success(
         &mut self,
-        _: SetupConnectionSuccess,
+        m: SetupConnectionSuccess,
     ) -> Result<roles_logic_sv2::handlers::common::SendTo, roles_logic_sv2::errors::Error> {
         use roles_logic_sv2::handlers::common::SendTo;
         info!("Setup connection success");
+        self.flags = m.flags;
         Ok(SendTo::None(None))
     }
 
     fn handle_setup_connection_error(
         &mut self,
-        _: roles_logic_sv2::common_messages_sv2::SetupConnectionError,
+        m: roles_logic_sv2::common_messages_sv2::SetupConnectionError,
     ) -> Result<roles_logic_sv2::handlers::common::SendTo, roles_logic_sv2::errors::Error> {
-        error!("Setup connection error");
-        todo!()
+        error!(
+            "Setup connection error: {}",
+            String::from_utf8_lossy(m.error_code.inner_as_ref())
+        );
+        std::process::exit(1);
     }
 
     fn handle_channel_endpoint_changed(
@@ -202,6 +212,10 @@ pub struct Device {
     prev_hash: Option<SetNewPrevHash<'static>>,
     sequence_numbers: Id,
     notify_changes_to_mining_thread: NewWorkNotifier,
+    shares_accepted: u32,
+    shares_rejected: u32,
+    /// The device stops once this many shares are accepted
+    shares_to_submit: Option<u32>,
 }
 
 fn open_channel(
@@ -224,7 +238,8 @@ fn open_channel(
         request_id: id.into(),
         user_identity,
         nominal_hash_rate,
-        max_target: u256_from_int(567_u64),
+        // any target set by the upstream is accepted
+        max_target: [255_u8; 32].into(),
     }
 }
 
@@ -237,9 +252,10 @@ impl Device {
         user_id: Option<String>,
         handicap: u32,
         nominal_hashrate_multiplier: Option<f32>,
+        shares_to_submit: Option<u32>,
     ) {
         let setup_connection_handler = Arc::new(Mutex::new(SetupConnectionHandler::new()));
-        SetupConnectionHandler::setup(
+        let flags = SetupConnectionHandler::setup(
             setup_connection_handler,
             &mut receiver,
             &mut sender,
@@ -248,7 +264,14 @@ impl Device {
         )
         .await;
         info!("Pool sv2 connection established at {}", addr);
-        let miner = Arc::new(Mutex::new(Miner::new(handicap)));
+        // with standard jobs the BIP320 bits can be rolled unless the upstream requires a fixed
+        // version
+        let version_rolling_allowed = flags & REQUIRES_FIXED_VERSION == 0;
+        info!("Version rolling allowed: {}", version_rolling_allowed);
+        let miner = Arc::new(Mutex::new(Miner::new(
+            handicap,
+            allowed_version_mask(version_rolling_allowed),
+        )));
         let (notify_changes_to_mining_thread, update_miners) = async_channel::unbounded();
         let self_ = Self {
             channel_opened: false,
@@ -263,6 +286,9 @@ impl Device {
                 should_send: true,
                 sender: notify_changes_to_mining_thread,
             },
+            shares_accepted: 0,
+            shares_rejected: 0,
+            shares_to_submit,
         };
         let open_channel = MiningDeviceMessages::Mining(Mining::OpenStandardMiningChannel(
             open_channel(user_id, nominal_hashrate_multiplier, handicap),
@@ -276,9 +302,7 @@ impl Device {
 
         start_mining_threads(update_miners, miner, share_send);
         tokio::task::spawn(async move {
-            let recv = share_recv.clone();
-            loop {
-                let (nonce, job_id, version, ntime) = recv.recv().await.unwrap();
+            while let Ok((nonce, job_id, version, ntime)) = share_recv.recv().await {
                 Self::send_share(cloned.clone(), nonce, job_id, version, ntime).await;
             }
         });
@@ -298,9 +322,9 @@ impl Device {
                 .safe_lock(|s| s.notify_changes_to_mining_thread.clone())
                 .unwrap();
             if notify_changes_to_mining_thread.should_send
-                && (message_type == const_sv2::MESSAGE_TYPE_NEW_MINING_JOB
-                    || message_type == const_sv2::MESSAGE_TYPE_SET_NEW_PREV_HASH
-                    || message_type == const_sv2::MESSAGE_TYPE_SET_TARGET)
+                && (message_type == message_type_of::<NewMiningJob>()
+                    || message_type == message_type_of::<SetNewPrevHash>()
+                    || message_type == message_type_of::<SetTarget>())
             {
                 notify_changes_to_mining_thread
                     .sender
@@ -318,9 +342,24 @@ impl Device {
                 SendTo::None(_) => (),
                 _ => panic!(),
             }
+            if self_mutex.safe_lock(|s| s.is_done()).unwrap() {
+                info!(
+                    "MINING DEVICE: {} shares accepted, stopping",
+                    shares_to_submit.unwrap_or(0)
+                );
+                // the mining threads stop when the channel is closed
+                notify_changes_to_mining_thread.sender.close();
+                return;
+            }
         }
     }
 
+    fn is_done(&self) -> bool {
+        self.shares_to_submit
+            .map(|shares| self.shares_accepted >= shares)
+            .unwrap_or(false)
+    }
+
     async fn send_share(
         self_mutex: Arc<Mutex<Self>>,
         nonce: u32,
@@ -425,9 +464,13 @@ impl ParseUpstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting> fo
 
     fn handle_open_mining_channel_error(
         &mut self,
-        _: OpenMiningChannelError,
+        m: OpenMiningChannelError,
     ) -> Result<SendTo<()>, Error> {
-        todo!()
+        error!(
+            "MINING DEVICE: open channel error: {}",
+            String::from_utf8_lossy(m.error_code.inner_as_ref())
+        );
+        std::process::exit(1);
     }
 
     fn handle_update_channel_error(&mut self, _: UpdateChannelError) -> Result<SendTo<()>, Error> {
@@ -449,12 +492,21 @@ impl ParseUpstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting> fo
         &mut self,
         m: SubmitSharesSuccess,
     ) -> Result<SendTo<()>, Error> {
-        info!("SUCCESS {:?}", m);
+        self.shares_accepted += m.new_submits_accepted_count;
+        info!(
+            "MINING DEVICE: {} new shares accepted, {} accepted and {} rejected in total",
+            m.new_submits_accepted_count, self.shares_accepted, self.shares_rejected
+        );
         Ok(SendTo::None(None))
     }
 
-    fn handle_submit_shares_error(&mut self, _: SubmitSharesError) -> Result<SendTo<()>, Error> {
-        info!("Submit shares error");
+    fn handle_submit_shares_error(&mut self, m: SubmitSharesError) -> Result<SendTo<()>, Error> {
+        self.shares_rejected += 1;
+        warn!(
+            "MINING DEVICE: share {} rejected: {}",
+            m.sequence_number,
+            String::from_utf8_lossy(m.error_code.inner_as_ref())
+        );
         Ok(SendTo::None(None))
     }
 
@@ -537,18 +589,19 @@ struct Miner {
     header: Option<BlockHeader>,
     target: Option<Uint256>,
     job_id: Option<u32>,
-    version: Option<u32>,
     handicap: u32,
+    /// Bits of the version that can be rolled, 0 if the version is fixed
+    version_mask: u32,
 }
 
 impl Miner {
-    fn new(handicap: u32) -> Self {
+    fn new(handicap: u32, version_mask: u32) -> Self {
         Self {
             target: None,
             header: None,
             job_id: None,
-            version: None,
             handicap,
+            version_mask,
         }
     }
 
@@ -564,7 +617,6 @@ impl Miner {
 
     fn new_header(&mut self, set_new_prev_hash: &SetNewPrevHash, new_job: &NewMiningJob) {
         self.job_id = Some(new_job.job_id);
-        self.version = Some(new_job.version);
         let prev_hash: [u8; 32] = set_new_prev_hash.prev_hash.to_vec().try_into().unwrap();
         let prev_hash = Hash::from_inner(prev_hash);
         let merkle_root: [u8; 32] = new_job.merkle_root.to_vec().try_into().unwrap();
@@ -575,12 +627,12 @@ impl Miner {
             version: new_job.version as i32,
             prev_blockhash: BlockHash::from_hash(prev_hash),
             merkle_root,
-            time: std::time::SystemTime::now()
-                .duration_since(
-                    std::time::SystemTime::UNIX_EPOCH - std::time::Duration::from_secs(60),
-                )
-                .unwrap()
-                .as_secs() as u32,
+            time: set_new_prev_hash.min_ntime.max(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as u32,
+            ),
             bits: set_new_prev_hash.nbits,
             nonce: 0,
         };
@@ -605,6 +657,29 @@ impl Miner {
             NextShareOutcome::InvalidShare
         }
     }
+
+    /// Moves to the next nonce in `first..=last`, once they are all tried the version is rolled
+    /// if allowed, the time otherwise
+    fn next_nonce(&mut self, (first, last): (u32, u32)) {
+        let version_mask = self.version_mask;
+        if let Some(header) = self.header.as_mut() {
+            if header.nonce < last {
+                header.nonce += 1;
+                return;
+            }
+            header.nonce = first;
+            if version_mask != 0 {
+                let version = header.version as u32;
+                // adds 1 to the bits in the mask, the carry skips the bits out of the mask
+                let rolled = ((version | !version_mask)
+                    .wrapping_add(version_mask & version_mask.wrapping_neg()))
+                    & version_mask;
+                header.version = apply_rolled_bits(version, rolled, version_mask) as i32;
+            } else {
+                header.time += 1;
+            }
+        }
+    }
 }
 
 enum NextShareOutcome {
@@ -641,7 +716,7 @@ fn measure_hashrate(duration_secs: u64, handicap: u32) -> f64 {
     let start_time = Instant::now();
     let mut hashes: u64 = 0;
     let duration = Duration::from_secs(duration_secs);
-    let mut miner = Miner::new(handicap);
+    let mut miner = Miner::new(handicap, 0);
     // We put the target to 0 we are only interested in how many hashes per unit of time we can do
     // and do not want to be botherd by messages about valid shares found.
     miner.new_target(vec![0_u8; 32]);
@@ -674,62 +749,57 @@ fn start_mining_threads(
 ) {
     tokio::task::spawn(async move {
         let mut killers: Vec<Arc<AtomicBool>> = vec![];
-        loop {
-            let p = available_parallelism().unwrap().get() as u32;
-            let unit = u32::MAX / p;
-            while have_new_job.recv().await.is_ok() {
-                while let Some(killer) = killers.pop() {
-                    killer.store(true, Ordering::Relaxed);
-                }
-                let miner = miner.safe_lock(|m| m.clone()).unwrap();
-                for i in 0..p {
-                    let mut miner = miner.clone();
-                    let share_send = share_send.clone();
-                    let killer = Arc::new(AtomicBool::new(false));
-                    miner.header.as_mut().map(|h| h.nonce = i * unit);
-                    killers.push(killer.clone());
-                    std::thread::spawn(move || {
-                        mine(miner, share_send, killer);
-                    });
-                }
+        let p = available_parallelism().unwrap().get() as u32;
+        let unit = u32::MAX / p;
+        while have_new_job.recv().await.is_ok() {
+            while let Some(killer) = killers.pop() {
+                killer.store(true, Ordering::Relaxed);
+            }
+            let miner = miner.safe_lock(|m| m.clone()).unwrap();
+            for i in 0..p {
+                let mut miner = miner.clone();
+                let share_send = share_send.clone();
+                let killer = Arc::new(AtomicBool::new(false));
+                // every thread tries its own nonces
+                let first = i * unit;
+                let last = if i == p - 1 {
+                    u32::MAX
+                } else {
+                    first + unit - 1
+                };
+                miner.header.as_mut().map(|h| h.nonce = first);
+                killers.push(killer.clone());
+                std::thread::spawn(move || {
+                    mine(miner, (first, last), share_send, killer);
+                });
             }
         }
+        while let Some(killer) = killers.pop() {
+            killer.store(true, Ordering::Relaxed);
+        }
     });
 }
 
-fn mine(mut miner: Miner, share_send: Sender<(u32, u32, u32, u32)>, kill: Arc<AtomicBool>) {
-    if miner.handicap != 0 {
-        loop {
-            if kill.load(Ordering::Relaxed) {
-                break;
-            }
+fn mine(
+    mut miner: Miner,
+    nonces: (u32, u32),
+    share_send: Sender<(u32, u32, u32, u32)>,
+    kill: Arc<AtomicBool>,
+) {
+    while !kill.load(Ordering::Relaxed) {
+        if miner.handicap != 0 {
             std::thread::sleep(std::time::Duration::from_micros(miner.handicap.into()));
-            if miner.next_share().is_valid() {
-                let nonce = miner.header.unwrap().nonce;
-                let time = miner.header.unwrap().time;
-                let job_id = miner.job_id.unwrap();
-                let version = miner.version;
-                share_send
-                    .try_send((nonce, job_id, version.unwrap(), time))
-                    .unwrap();
-            }
-            miner.header.as_mut().map(|h| h.nonce += 1);
         }
-    } else {
-        loop {
-            if miner.next_share().is_valid() {
-                if kill.load(Ordering::Relaxed) {
-                    break;
-                }
-                let nonce = miner.header.unwrap().nonce;
-                let time = miner.header.unwrap().time;
-                let job_id = miner.job_id.unwrap();
-                let version = miner.version;
-                share_send
-                    .try_send((nonce, job_id, version.unwrap(), time))
-                    .unwrap();
+        if miner.next_share().is_valid() {
+            let header = miner.header.unwrap();
+            let job_id = miner.job_id.unwrap();
+            if share_send
+                .try_send((header.nonce, job_id, header.version as u32, header.time))
+                .is_err()
+            {
+                break;
             }
-            miner.header.as_mut().map(|h| h.nonce += 1);
         }
+        miner.next_nonce(nonces);
     }
 }
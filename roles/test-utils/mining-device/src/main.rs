@@ -47,6 +47,11 @@ struct Args {
          \nIf empty, the CPU miner will simply advertise its real capacity."
     )]
     nominal_hashrate_multiplier: Option<f32>,
+    #[arg(
+        long,
+        help = "Stop after this number of shares are accepted, useful to test pools and proxies end to end"
+    )]
+    shares: Option<u32>,
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -61,6 +66,7 @@ async fn main() {
         args.id_user,
         args.handicap,
         args.nominal_hashrate_multiplier,
+        args.shares,
     )
     .await;
 }
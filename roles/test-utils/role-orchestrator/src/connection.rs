@@ -0,0 +1,100 @@
+//! Noise connection of the mock roles with the roles under test
+use crate::{within, AUTHORITY_PUBLIC_KEY, AUTHORITY_SECRET_KEY};
+use async_channel::{Receiver, Sender};
+use codec_sv2::{HandshakeRole, Initiator, Responder, StandardEitherFrame, StandardSv2Frame};
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use network_helpers_sv2::noise_connection_tokio::Connection;
+use roles_logic_sv2::parsers::PoolMessages;
+use std::{convert::TryInto, net::SocketAddr, time::Duration};
+use tokio::{net::TcpStream, task::AbortHandle};
+
+type Message = PoolMessages<'static>;
+type StdFrame = StandardSv2Frame<Message>;
+type EitherFrame = StandardEitherFrame<Message>;
+
+/// Cloned to send from another task than the one that receives
+#[derive(Clone)]
+pub struct Sv2Connection {
+    receiver: Receiver<EitherFrame>,
+    sender: Sender<EitherFrame>,
+    abort_handles: [AbortHandle; 2],
+}
+
+impl Sv2Connection {
+    /// Handshake as initiator with a role configured with [`AUTHORITY_PUBLIC_KEY`]
+    pub async fn connect(address: SocketAddr) -> Result<Self, String> {
+        let stream = within(&format!("a connection to {}", address), async {
+            loop {
+                match TcpStream::connect(address).await {
+                    Ok(stream) => break stream,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+                }
+            }
+        })
+        .await?;
+        // Infallible unwrap, the key is valid
+        let public_key: Secp256k1PublicKey = AUTHORITY_PUBLIC_KEY.parse().unwrap();
+        let initiator = Initiator::from_raw_k(public_key.into_bytes())
+            .map_err(|e| format!("invalid authority key: {:?}", e))?;
+        Self::handshake(stream, HandshakeRole::Initiator(initiator)).await
+    }
+
+    /// Handshake as responder with the [`AUTHORITY_SECRET_KEY`]
+    pub async fn accept(stream: TcpStream) -> Result<Self, String> {
+        // Infallible unwraps, the keys are valid
+        let public_key: Secp256k1PublicKey = AUTHORITY_PUBLIC_KEY.parse().unwrap();
+        let secret_key: Secp256k1SecretKey = AUTHORITY_SECRET_KEY.parse().unwrap();
+        let responder = Responder::from_authority_kp(
+            &public_key.into_bytes(),
+            &secret_key.into_bytes(),
+            Duration::from_secs(3600),
+        )
+        .map_err(|e| format!("invalid authority keys: {:?}", e))?;
+        Self::handshake(stream, HandshakeRole::Responder(responder)).await
+    }
+
+    async fn handshake(stream: TcpStream, role: HandshakeRole) -> Result<Self, String> {
+        let (receiver, sender, reader, writer) = Connection::new::<Message>(stream, role)
+            .await
+            .map_err(|e| format!("noise handshake failed: {:?}", e))?;
+        Ok(Self {
+            receiver,
+            sender,
+            abort_handles: [reader, writer],
+        })
+    }
+
+    pub async fn send(&self, message: Message) -> Result<(), String> {
+        let frame: StdFrame = message.try_into().map_err(|e| format!("{:?}", e))?;
+        self.sender
+            .send(frame.into())
+            .await
+            .map_err(|_| "connection closed".to_string())
+    }
+
+    pub async fn recv(&self) -> Result<Message, String> {
+        let frame = self
+            .receiver
+            .recv()
+            .await
+            .map_err(|_| "connection closed".to_string())?;
+        let mut frame: StdFrame = frame.try_into().map_err(|e| format!("{:?}", e))?;
+        let message_type = frame
+            .get_header()
+            .ok_or_else(|| "frame without header".to_string())?
+            .msg_type();
+        let message: PoolMessages = (message_type, frame.payload())
+            .try_into()
+            .map_err(|e| format!("invalid message {}: {:?}", message_type, e))?;
+        Ok(message.into_static())
+    }
+
+    /// Closes the socket, like a role that goes down
+    pub fn close(&self) {
+        for handle in &self.abort_handles {
+            handle.abort();
+        }
+        self.receiver.close();
+        self.sender.close();
+    }
+}
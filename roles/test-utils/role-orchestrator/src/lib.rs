@@ -0,0 +1,82 @@
+//! Runs the SV2 roles in process and drives them through scripted scenarios.
+//!
+//! A [`scenario::Stack`] starts a [`template_provider::MockTemplateProvider`], the pool and,
+//! depending on the [`scenario::Topology`], the translator, the JDS and the JDC as tasks of the
+//! current tokio runtime, all connected on local ports. A scripted miner is connected at the bottom
+//! of the stack: it does not hash in a loop, it only looks for the nonce of the share or of the
+//! block asked by the scenario, so the scenarios are deterministic and fast.
+//!
+//! [`scenario::run`] runs a [`scenario::Scenario`] (block found, upstream restart, stale shares)
+//! against a topology and checks the final state: the solutions received by the Template Provider,
+//! the answers to the shares and the share statistics of the pool.
+//!
+//! [`sv1_pool::MockSv1Pool`] is a SV1 pool with scripted notifications, to check the SV1 side of
+//! the roles against deterministic fixtures.
+pub mod connection;
+pub mod roles;
+pub mod scenario;
+pub mod sv1_miner;
+pub mod sv1_pool;
+pub mod sv2_miner;
+pub mod template_provider;
+pub mod work;
+
+use std::{
+    collections::HashSet,
+    future::Future,
+    net::{SocketAddr, TcpListener},
+    sync::Mutex,
+    time::Duration,
+};
+
+/// Authority keys of every role of the stack, the ones of the config examples
+pub const AUTHORITY_PUBLIC_KEY: &str = "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72";
+pub const AUTHORITY_SECRET_KEY: &str = "mkDLTBBRxdBv998612qipDYoTK3YUrqLe8uWw7gu3iXbSrn2n";
+
+/// Time given to the roles for every step of a scenario
+pub const STEP_TIMEOUT: Duration = Duration::from_secs(20);
+
+// Ports already returned by `free_address`, the roles bind them some time after
+static USED_PORTS: Mutex<Option<HashSet<u16>>> = Mutex::new(None);
+
+/// A local address that is not in use and that has never been returned before
+pub fn free_address() -> SocketAddr {
+    let mut used = USED_PORTS.lock().unwrap_or_else(|e| e.into_inner());
+    let used = used.get_or_insert_with(HashSet::new);
+    loop {
+        // Infallible unwraps, binding the port 0 of the loopback interface always succeeds
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        if used.insert(address.port()) {
+            return address;
+        }
+    }
+}
+
+/// Waits for `future`, at most [`STEP_TIMEOUT`]. `what` is in the error.
+pub async fn within<T>(what: &str, future: impl Future<Output = T>) -> Result<T, String> {
+    tokio::time::timeout(STEP_TIMEOUT, future)
+        .await
+        .map_err(|_| format!("timeout waiting for {}", what))
+}
+
+/// Polls `condition` every 50ms, at most [`STEP_TIMEOUT`]
+pub async fn wait_until(what: &str, mut condition: impl FnMut() -> bool) -> Result<(), String> {
+    within(what, async {
+        while !condition() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+}
+
+/// Waits for a role to listen on `address`. The address is not connected to, the JDC accepts a
+/// single downstream connection.
+pub async fn wait_for_listener(address: SocketAddr) -> Result<(), String> {
+    wait_until(&format!("a listener on {}", address), || {
+        TcpListener::bind(address).is_err()
+    })
+    .await
+}
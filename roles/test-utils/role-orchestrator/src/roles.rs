@@ -0,0 +1,173 @@
+//! Starts the roles under test as tasks of the current runtime, with the configs of the examples
+//! and the keys of the crate. Every role listens on a free local port and is started once it is
+//! listening.
+use crate::{free_address, wait_for_listener, AUTHORITY_PUBLIC_KEY, AUTHORITY_SECRET_KEY};
+use async_channel::{unbounded, Receiver};
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use pool_sv2::{stats::StatsSnapshot, PoolSv2};
+use std::{net::SocketAddr, time::Duration};
+use tracing::error;
+
+const COINBASE_OUTPUT_TYPE: &str = "P2WPKH";
+const COINBASE_OUTPUT_VALUE: &str =
+    "036adc3bdf21e6f9a0f0fb0066bf517e5b7909ed1563d6958a10993849a7554075";
+const CERT_VALIDITY_SEC: u64 = 3600;
+/// Hashrate of the SV1 miners as seen by the translator, low enough for any hash to be a share
+const SV1_MINER_HASHRATE: f32 = 0.01;
+
+fn keys() -> (Secp256k1PublicKey, Secp256k1SecretKey) {
+    // Infallible unwraps, the keys are valid
+    (
+        AUTHORITY_PUBLIC_KEY.parse().unwrap(),
+        AUTHORITY_SECRET_KEY.parse().unwrap(),
+    )
+}
+
+/// Starts a pool connected to the Template Provider at `tp_address`. The pool retries the
+/// Template Provider every second and sends its share statistics every second. SV1 miners are
+/// accepted on `sv1_address` if set.
+pub async fn start_pool(
+    tp_address: SocketAddr,
+    sv1_address: Option<SocketAddr>,
+) -> Result<(SocketAddr, Receiver<StatsSnapshot>), String> {
+    use pool_sv2::{
+        mining_pool::{
+            AuthorityConfig, CoinbaseOutput, Configuration, ConnectionConfig,
+            TemplateProviderConfig,
+        },
+        sv1_listener::Sv1ListenerConfig,
+    };
+    let address = free_address();
+    let (public_key, secret_key) = keys();
+    let mut config = Configuration::new(
+        ConnectionConfig::new(
+            address.to_string(),
+            CERT_VALIDITY_SEC,
+            "Stratum v2 SRI Pool".to_string(),
+        ),
+        TemplateProviderConfig::new(tp_address.to_string(), None),
+        AuthorityConfig::new(public_key, secret_key),
+        vec![CoinbaseOutput::new(
+            COINBASE_OUTPUT_TYPE.to_string(),
+            COINBASE_OUTPUT_VALUE.to_string(),
+        )],
+    );
+    config.tp_health_check_interval_sec = 1;
+    config.stats.snapshot_interval_sec = 1;
+    config.sv1_listener = sv1_address
+        .map(|sv1_address| Sv1ListenerConfig::new(sv1_address.to_string(), SV1_MINER_HASHRATE));
+    let (stats_sender, stats_receiver) = unbounded();
+    let pool = PoolSv2::new(config).with_stats_sender(stats_sender);
+    tokio::spawn(async move {
+        if let Err(e) = pool.start().await {
+            error!("Pool stopped: {:?}", e);
+        }
+    });
+    wait_for_listener(address).await?;
+    if let Some(sv1_address) = sv1_address {
+        wait_for_listener(sv1_address).await?;
+    }
+    Ok((address, stats_receiver))
+}
+
+/// Starts a JDS. Its mempool is not synchronized, the templates of the scenarios are empty.
+pub async fn start_jds(tp_address: SocketAddr) -> Result<SocketAddr, String> {
+    use jd_server::{CoinbaseOutput, Configuration, CoreRpc, JobDeclaratorServer};
+    let address = free_address();
+    let (public_key, secret_key) = keys();
+    let config = Configuration::new(
+        address.to_string(),
+        public_key,
+        secret_key,
+        CERT_VALIDITY_SEC,
+        vec![CoinbaseOutput::new(
+            COINBASE_OUTPUT_TYPE.to_string(),
+            COINBASE_OUTPUT_VALUE.to_string(),
+        )],
+        // Not an http url, the mempool is not polled
+        CoreRpc::new(
+            tp_address.ip().to_string(),
+            tp_address.port(),
+            "username".to_string(),
+            "password".to_string(),
+        ),
+        Duration::from_secs(1),
+    );
+    tokio::spawn(async move { JobDeclaratorServer::new(config).start().await });
+    wait_for_listener(address).await?;
+    Ok(address)
+}
+
+/// Starts a JDC that declares its jobs to the JDS at `jds_address` and mines on the pool at
+/// `pool_address`
+pub async fn start_jdc(
+    pool_address: SocketAddr,
+    jds_address: SocketAddr,
+    tp_address: SocketAddr,
+) -> Result<SocketAddr, String> {
+    use jd_client::{
+        proxy_config::{
+            CoinbaseOutput, PoolConfig, ProtocolConfig, ProxyConfig, TPConfig, Upstream,
+        },
+        JobDeclaratorClient,
+    };
+    let address = free_address();
+    let (public_key, secret_key) = keys();
+    let config = ProxyConfig::new(
+        address,
+        ProtocolConfig::new(
+            2,
+            2,
+            8,
+            vec![CoinbaseOutput::new(
+                COINBASE_OUTPUT_TYPE.to_string(),
+                COINBASE_OUTPUT_VALUE.to_string(),
+            )],
+        ),
+        false,
+        PoolConfig::new(public_key, secret_key),
+        TPConfig::new(1000, tp_address.to_string(), None),
+        vec![Upstream::new(
+            public_key,
+            pool_address.to_string(),
+            jds_address.to_string(),
+            "Stratum v2 SRI Pool".to_string(),
+        )],
+        Duration::from_secs(CERT_VALIDITY_SEC),
+    );
+    tokio::spawn(async move { JobDeclaratorClient::new(config).start().await });
+    wait_for_listener(address).await?;
+    Ok(address)
+}
+
+/// Starts a translator connected to the pool or the JDC at `upstream_address`
+pub async fn start_translator(upstream_address: SocketAddr) -> Result<SocketAddr, String> {
+    use translator_sv2::{
+        proxy_config::{
+            DownstreamConfig, DownstreamDifficultyConfig, ProxyConfig, UpstreamConfig,
+            UpstreamDifficultyConfig,
+        },
+        TranslatorSv2,
+    };
+    let address = free_address();
+    let (public_key, _) = keys();
+    let config = ProxyConfig::new(
+        UpstreamConfig::new(
+            upstream_address.ip().to_string(),
+            upstream_address.port(),
+            public_key,
+            UpstreamDifficultyConfig::new(60, SV1_MINER_HASHRATE, 0, false),
+        ),
+        DownstreamConfig::new(
+            address.ip().to_string(),
+            address.port(),
+            DownstreamDifficultyConfig::new(SV1_MINER_HASHRATE, 60.0, 0, 0),
+        ),
+        2,
+        2,
+        8,
+    );
+    tokio::spawn(async move { TranslatorSv2::new(config).start().await });
+    wait_for_listener(address).await?;
+    Ok(address)
+}
@@ -0,0 +1,337 @@
+//! Scenarios run against every topology of roles.
+//!
+//! Each scenario runs in its own runtime, dropped at the end with all the tasks of the roles, so
+//! nothing is left running between two scenarios. The roles keep some state in statics, the
+//! scenarios of a process are run one at a time.
+use crate::{
+    free_address, roles,
+    sv1_miner::Sv1Miner,
+    sv2_miner::Sv2Miner,
+    template_provider::MockTemplateProvider,
+    wait_until, within,
+    work::{ShareKind, Work},
+};
+use async_channel::Receiver;
+use pool_sv2::stats::{ShareStats, StatsSnapshot};
+use std::{fmt, net::SocketAddr, sync::Mutex, time::Duration};
+
+// Held while a scenario runs
+static SCENARIO_LOCK: Mutex<()> = Mutex::new(());
+
+/// Roles between the miner and the Template Provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// SV2 miner -> pool -> TP
+    Pool,
+    /// SV1 miner -> translator -> pool -> TP
+    Translator,
+    /// SV1 miner -> pool with the SV1 listener -> TP
+    PoolSv1Listener,
+    /// SV1 miner -> translator -> JDC -> pool and JDS, the JDC and the pool on the same TP
+    JobDeclaration,
+}
+
+impl Topology {
+    pub const ALL: [Topology; 4] = [
+        Topology::Pool,
+        Topology::Translator,
+        Topology::PoolSv1Listener,
+        Topology::JobDeclaration,
+    ];
+
+    /// Roles connected to the Template Provider
+    fn tp_clients(self) -> usize {
+        match self {
+            Topology::Pool | Topology::Translator | Topology::PoolSv1Listener => 1,
+            Topology::JobDeclaration => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scenario {
+    /// A share and then a block are submitted, the block reaches the Template Provider
+    BlockFound,
+    /// The Template Provider restarts, the roles reconnect and a block found after the restart
+    /// reaches it
+    UpstreamRestart,
+    /// A share on the job of the previous block is rejected, a share on the new job is accepted
+    StaleShares,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 3] = [
+        Scenario::BlockFound,
+        Scenario::UpstreamRestart,
+        Scenario::StaleShares,
+    ];
+}
+
+enum Miner {
+    Sv1(Sv1Miner),
+    Sv2(Sv2Miner),
+}
+
+impl Miner {
+    async fn next_work(&mut self, previous: Option<&Work>) -> Result<Work, String> {
+        match self {
+            Miner::Sv1(miner) => miner.next_work(previous).await,
+            Miner::Sv2(miner) => miner.next_work(previous).await,
+        }
+    }
+
+    async fn submit(&mut self, work: &Work, kind: ShareKind) -> Result<bool, String> {
+        match self {
+            Miner::Sv1(miner) => miner.submit(work, kind).await,
+            Miner::Sv2(miner) => miner.submit(work, kind).await,
+        }
+    }
+}
+
+/// The roles of a topology, started and connected
+pub struct Stack {
+    topology: Topology,
+    tp: MockTemplateProvider,
+    stats: Receiver<StatsSnapshot>,
+    miner: Miner,
+    /// Role the miner is connected to
+    miner_address: SocketAddr,
+}
+
+impl Stack {
+    pub async fn start(topology: Topology) -> Result<Self, String> {
+        let tp = MockTemplateProvider::start().await?;
+        let sv1_address = match topology {
+            Topology::PoolSv1Listener => Some(free_address()),
+            _ => None,
+        };
+        let (pool, stats) = roles::start_pool(tp.address(), sv1_address).await?;
+        let (miner, miner_address) = match topology {
+            Topology::Pool => (Miner::Sv2(Sv2Miner::connect(pool).await?), pool),
+            Topology::Translator => {
+                let translator = roles::start_translator(pool).await?;
+                (Miner::Sv1(Sv1Miner::connect(translator).await?), translator)
+            }
+            Topology::PoolSv1Listener => {
+                // Infallible unwrap, set above for this topology
+                let sv1_address = sv1_address.unwrap();
+                (
+                    Miner::Sv1(Sv1Miner::connect(sv1_address).await?),
+                    sv1_address,
+                )
+            }
+            Topology::JobDeclaration => {
+                let jds = roles::start_jds(tp.address()).await?;
+                let jdc = roles::start_jdc(pool, jds, tp.address()).await?;
+                let translator = roles::start_translator(jdc).await?;
+                (Miner::Sv1(Sv1Miner::connect(translator).await?), translator)
+            }
+        };
+        Ok(Self {
+            topology,
+            tp,
+            stats,
+            miner,
+            miner_address,
+        })
+    }
+
+    /// Connects the SV1 miner again, until the translator accepts it
+    async fn reconnect_miner(&mut self) -> Result<(), String> {
+        let address = self.miner_address;
+        let miner = within("the miner to reconnect", async {
+            loop {
+                match Sv1Miner::connect(address).await {
+                    Ok(miner) => break miner,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+                }
+            }
+        })
+        .await?;
+        self.miner = Miner::Sv1(miner);
+        Ok(())
+    }
+
+    /// Waits for the share statistics of the pool, summed over the channels, to satisfy `check`
+    async fn wait_for_shares(
+        &self,
+        what: &str,
+        check: impl Fn(&ShareStats) -> bool,
+    ) -> Result<(), String> {
+        let mut last = ShareStats::default();
+        let waited = within(what, async {
+            while let Ok(snapshot) = self.stats.recv().await {
+                last =
+                    snapshot
+                        .channels
+                        .values()
+                        .fold(ShareStats::default(), |mut total, channel| {
+                            total.accepted += channel.shares.accepted;
+                            total.rejected += channel.shares.rejected;
+                            total.stale += channel.shares.stale;
+                            total
+                        });
+                if check(&last) {
+                    return true;
+                }
+            }
+            false
+        })
+        .await;
+        match waited {
+            Ok(true) => Ok(()),
+            _ => Err(format!(
+                "{}: the pool counts {} accepted, {} rejected and {} stale shares",
+                what, last.accepted, last.rejected, last.stale
+            )),
+        }
+    }
+
+    /// Waits for a solution of the template `template_id` to be submitted to the Template
+    /// Provider, and checks it is the only one
+    async fn expect_solution(&self, template_id: u64) -> Result<(), String> {
+        wait_until("a solution", || !self.tp.solutions().is_empty()).await?;
+        // Lets a duplicated solution arrive
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let template_ids: Vec<u64> = self.tp.solutions().iter().map(|s| s.template_id).collect();
+        if template_ids != [template_id] {
+            return Err(format!(
+                "expected a solution of template {}, got solutions of {:?}",
+                template_id, template_ids
+            ));
+        }
+        Ok(())
+    }
+
+    /// Waits for a work on a prev hash different from the one of `previous`. The roles can send
+    /// new jobs on the same prev hash first, when they reconnect to their upstream.
+    async fn next_block_work(&mut self, previous: &Work) -> Result<Work, String> {
+        let mut work = self.miner.next_work(Some(previous)).await?;
+        while work.prev_hash == previous.prev_hash {
+            work = self.miner.next_work(Some(&work)).await?;
+        }
+        Ok(work)
+    }
+
+    async fn submit(&mut self, work: &Work, kind: ShareKind, accepted: bool) -> Result<(), String> {
+        if self.miner.submit(work, kind).await? != accepted {
+            return Err(format!(
+                "{:?} on job {} {}",
+                kind,
+                work.job_id,
+                if accepted { "rejected" } else { "accepted" }
+            ));
+        }
+        Ok(())
+    }
+
+    async fn block_found(&mut self) -> Result<(), String> {
+        let work = self.miner.next_work(None).await?;
+        self.submit(&work, ShareKind::Share, true).await?;
+        self.submit(&work, ShareKind::Block, true).await?;
+        self.expect_solution(self.tp.template_id()).await?;
+        self.wait_for_shares("2 accepted shares", |s| s.accepted == 2)
+            .await
+    }
+
+    async fn upstream_restart(&mut self) -> Result<(), String> {
+        let work = self.miner.next_work(None).await?;
+        self.submit(&work, ShareKind::Share, true).await?;
+        self.tp.restart(Duration::from_secs(1)).await?;
+        let reconnected = 2 * self.topology.tp_clients();
+        wait_until("the roles to reconnect to the Template Provider", || {
+            self.tp.accepted_connections() >= reconnected
+        })
+        .await?;
+        if self.topology == Topology::JobDeclaration {
+            // The JDC restarts when it loses the Template Provider, the translator drops its miners
+            // when it reconnects to the JDC
+            self.reconnect_miner().await?;
+            self.miner.next_work(None).await?;
+        }
+        let template_id = self.tp.new_block().await?;
+        let work = self.next_block_work(&work).await?;
+        self.submit(&work, ShareKind::Block, true).await?;
+        self.expect_solution(template_id).await
+    }
+
+    async fn stale_shares(&mut self) -> Result<(), String> {
+        let old = self.miner.next_work(None).await?;
+        self.tp.new_block().await?;
+        let new = self.next_block_work(&old).await?;
+        self.submit(&old, ShareKind::Share, false).await?;
+        self.submit(&new, ShareKind::Share, true).await?;
+        match self.topology {
+            // The SV1 share on the old job is rejected by the translator, the pool never sees it
+            Topology::Pool => {
+                self.wait_for_shares("1 accepted and 1 stale share", |s| {
+                    s.accepted == 1 && s.stale == 1
+                })
+                .await
+            }
+            Topology::Translator | Topology::PoolSv1Listener | Topology::JobDeclaration => {
+                self.wait_for_shares("1 accepted share", |s| s.accepted == 1 && s.stale == 0)
+                    .await
+            }
+        }
+    }
+
+    pub async fn run(&mut self, scenario: Scenario) -> Result<(), String> {
+        match scenario {
+            Scenario::BlockFound => self.block_found().await,
+            Scenario::UpstreamRestart => self.upstream_restart().await,
+            Scenario::StaleShares => self.stale_shares().await,
+        }
+    }
+}
+
+/// A scenario that failed on a topology
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub topology: Topology,
+    pub scenario: Scenario,
+    pub error: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} on {:?}: {}",
+            self.scenario, self.topology, self.error
+        )
+    }
+}
+
+/// Starts the roles of `topology` and runs `scenario` on them, in a new runtime
+pub fn run(topology: Topology, scenario: Scenario) -> Result<(), Failure> {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let failure = |error| Failure {
+        topology,
+        scenario,
+        error,
+    };
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| failure(e.to_string()))?;
+    let result = runtime.block_on(async {
+        let mut stack = Stack::start(topology).await?;
+        stack.run(scenario).await
+    });
+    runtime.shutdown_timeout(Duration::from_secs(1));
+    result.map_err(failure)
+}
+
+/// Runs every scenario on every topology, returns the failures
+pub fn run_matrix(topologies: &[Topology], scenarios: &[Scenario]) -> Vec<Failure> {
+    let mut failures = vec![];
+    for topology in topologies {
+        for scenario in scenarios {
+            if let Err(failure) = run(*topology, *scenario) {
+                failures.push(failure);
+            }
+        }
+    }
+    failures
+}
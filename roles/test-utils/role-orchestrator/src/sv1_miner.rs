@@ -0,0 +1,210 @@
+//! SV1 miner connected to the translator.
+//!
+//! Like [`crate::sv2_miner::Sv2Miner`] it keeps the jobs it receives and submits the shares the
+//! scenario asks for. The answer of the translator to a `mining.submit` only tells if the job is
+//! still valid, the shares are checked against the targets after it.
+use crate::{
+    within,
+    work::{ShareKind, Work},
+};
+use roles_logic_sv2::mining_sv2::Target;
+use serde_json::Value;
+use std::{convert::TryFrom, net::SocketAddr};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+};
+use v1::{
+    client_to_server,
+    json_rpc::{Message, Response},
+    server_to_client,
+    utils::{Extranonce, HexU32Be},
+};
+
+const USER: &str = "orchestrator";
+
+pub struct Sv1Miner {
+    reader: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+    extranonce1: Vec<u8>,
+    extranonce2_size: usize,
+    target: Target,
+    notify: Option<server_to_client::Notify<'static>>,
+}
+
+impl Sv1Miner {
+    /// Subscribes and authorizes on the translator at `address`
+    pub async fn connect(address: SocketAddr) -> Result<Self, String> {
+        let stream = within(&format!("a connection to {}", address), async {
+            loop {
+                match TcpStream::connect(address).await {
+                    Ok(stream) => break stream,
+                    Err(_) => tokio::time::sleep(std::time::Duration::from_millis(50)).await,
+                }
+            }
+        })
+        .await?;
+        let (reader, writer) = stream.into_split();
+        let mut miner = Self {
+            reader: BufReader::new(reader).lines(),
+            writer,
+            next_id: 0,
+            extranonce1: vec![],
+            extranonce2_size: 0,
+            target: Target::MAX,
+            notify: None,
+        };
+        let subscribe = client_to_server::Subscribe {
+            id: miner.id(),
+            agent_signature: "orchestrator".to_string(),
+            extranonce1: None,
+        };
+        let response = miner
+            .request(Message::try_from(subscribe).map_err(|e| format!("{:?}", e))?)
+            .await?;
+        let subscribed = server_to_client::Subscribe::try_from(&response)
+            .map_err(|e| format!("invalid subscribe response: {:?}", e))?;
+        miner.extranonce1 = subscribed.extra_nonce1.into();
+        miner.extranonce2_size = subscribed.extra_nonce2_size;
+        let authorize = client_to_server::Authorize {
+            id: miner.id(),
+            name: USER.to_string(),
+            password: "".to_string(),
+        };
+        if !is_ok(&miner.request(authorize.into()).await?) {
+            return Err("not authorized".to_string());
+        }
+        Ok(miner)
+    }
+
+    fn id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    async fn send(&mut self, message: Message) -> Result<(), String> {
+        let line = format!(
+            "{}\n",
+            serde_json::to_string(&message).map_err(|e| e.to_string())?
+        );
+        self.writer
+            .write_all(line.as_bytes())
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn recv(&mut self) -> Result<Message, String> {
+        let line = within("a message from the translator", self.reader.next_line())
+            .await?
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "connection closed".to_string())?;
+        serde_json::from_str(&line).map_err(|e| format!("invalid message {}: {}", line, e))
+    }
+
+    /// Sends `message` and waits for its response, the notifications received meanwhile are
+    /// handled
+    async fn request(&mut self, message: Message) -> Result<Response, String> {
+        let id = match &message {
+            Message::StandardRequest(m) => m.id,
+            _ => return Err(format!("not a request: {:?}", message)),
+        };
+        self.send(message).await?;
+        loop {
+            match self.recv().await? {
+                Message::OkResponse(r) | Message::ErrorResponse(r) if r.id == id => return Ok(r),
+                Message::Notification(n) => self.on_notification(n)?,
+                _ => (),
+            }
+        }
+    }
+
+    fn on_notification(&mut self, notification: v1::json_rpc::Notification) -> Result<(), String> {
+        match notification.method.as_str() {
+            "mining.notify" => {
+                let notify = server_to_client::Notify::try_from(notification)
+                    .map_err(|e| format!("invalid notify: {:?}", e))?;
+                self.notify = Some(notify);
+            }
+            "mining.set_difficulty" => {
+                let difficulty = server_to_client::SetDifficulty::try_from(notification)
+                    .map_err(|e| format!("invalid difficulty: {:?}", e))?;
+                self.target = Target::from_difficulty(difficulty.value).unwrap_or(Target::MAX);
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Current work, built from the last `mining.notify`
+    pub fn work(&self) -> Result<Option<Work>, String> {
+        let notify = match &self.notify {
+            Some(notify) => notify.clone(),
+            None => return Ok(None),
+        };
+        let job_id = notify
+            .job_id
+            .parse()
+            .map_err(|_| format!("invalid job id {}", notify.job_id))?;
+        let mut extranonce = self.extranonce1.clone();
+        extranonce.resize(self.extranonce1.len() + self.extranonce2_size, 0);
+        let prev_hash: Vec<u8> = notify.prev_hash.into();
+        let coinbase_tx_prefix: Vec<u8> = notify.coin_base1.into();
+        let coinbase_tx_suffix: Vec<u8> = notify.coin_base2.into();
+        let merkle_path: Vec<Vec<u8>> = notify
+            .merkle_branch
+            .into_iter()
+            .map(|node| node.into())
+            .collect();
+        Work::new(
+            job_id,
+            notify.version.0,
+            &prev_hash,
+            &coinbase_tx_prefix,
+            &coinbase_tx_suffix,
+            &extranonce,
+            &merkle_path,
+            notify.time.0,
+            notify.bits.0,
+            self.target.clone(),
+        )
+        .map(Some)
+    }
+
+    /// Waits for a work different from `previous`
+    pub async fn next_work(&mut self, previous: Option<&Work>) -> Result<Work, String> {
+        loop {
+            if let Some(work) = self.work()? {
+                if Some(&work) != previous {
+                    return Ok(work);
+                }
+            }
+            if let Message::Notification(n) = self.recv().await? {
+                self.on_notification(n)?;
+            }
+        }
+    }
+
+    /// Submits a share of `kind` on `work`, returns true if the translator accepts it
+    pub async fn submit(&mut self, work: &Work, kind: ShareKind) -> Result<bool, String> {
+        let nonce = work.find_nonce(kind)?;
+        let submit = client_to_server::Submit {
+            id: self.id(),
+            user_name: USER.to_string(),
+            job_id: work.job_id.to_string(),
+            extra_nonce2: Extranonce::try_from(vec![0; self.extranonce2_size])
+                .map_err(|e| format!("{:?}", e))?,
+            time: HexU32Be(work.ntime),
+            nonce: HexU32Be(nonce),
+            version_bits: None,
+        };
+        Ok(is_ok(&self.request(submit.into()).await?))
+    }
+}
+
+fn is_ok(response: &Response) -> bool {
+    response.error.is_none() && response.result == Value::Bool(true)
+}
@@ -0,0 +1,322 @@
+//! SV1 pool, to check SV1 clients against deterministic fixtures without the SV2 roles.
+//!
+//! It answers `mining.subscribe`, `mining.authorize` and `mining.submit` and sends the
+//! `mining.set_difficulty` and `mining.notify` of a script to every authorized connection, the
+//! events pushed with [`MockSv1Pool::send`] are added to the script. A submit is accepted if its
+//! job has been notified and has not been cleaned by a notify with `clean_jobs` set, the shares
+//! are not checked against the target. Every submit received is recorded.
+use async_channel::{unbounded, Receiver, Sender};
+use roles_logic_sv2::utils::Mutex;
+use std::{collections::HashSet, convert::TryFrom, net::SocketAddr, sync::Arc};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{tcp::OwnedWriteHalf, TcpListener, TcpStream},
+    task::AbortHandle,
+};
+use tracing::{debug, warn};
+use v1::{
+    client_to_server,
+    json_rpc::{Message, StandardRequest},
+    server_to_client,
+    utils::{Extranonce, HexU32Be, MerkleNode, PrevHash},
+};
+
+/// Size of the extranonce1 of the connections, it is the number of the connection
+pub const EXTRANONCE1_SIZE: usize = 4;
+pub const EXTRANONCE2_SIZE: usize = 4;
+
+/// A notification of the script
+#[derive(Debug, Clone)]
+pub enum Sv1PoolEvent {
+    SetDifficulty(f64),
+    Notify(server_to_client::Notify<'static>),
+}
+
+impl From<Sv1PoolEvent> for Message {
+    fn from(event: Sv1PoolEvent) -> Self {
+        match event {
+            Sv1PoolEvent::SetDifficulty(value) => server_to_client::SetDifficulty { value }.into(),
+            Sv1PoolEvent::Notify(notify) => notify.into(),
+        }
+    }
+}
+
+/// `mining.notify` of a job with an empty block on `prev_hash`, its coinbase pays 50 BTC to an
+/// empty script
+pub fn notify(
+    job_id: u32,
+    prev_hash: [u8; 32],
+    ntime: u32,
+    nbits: u32,
+    clean_jobs: bool,
+) -> server_to_client::Notify<'static> {
+    let extranonce_len = (EXTRANONCE1_SIZE + EXTRANONCE2_SIZE) as u8;
+    // Version, one input spending nothing and the length of its script, that is the extranonce
+    let mut coinbase_prefix = vec![1, 0, 0, 0, 1];
+    coinbase_prefix.extend_from_slice(&[0; 32]);
+    coinbase_prefix.extend_from_slice(&[0xff; 4]);
+    coinbase_prefix.push(extranonce_len);
+    // Sequence, one output with an empty script and the lock time
+    let mut coinbase_suffix = vec![0xff; 4];
+    coinbase_suffix.push(1);
+    coinbase_suffix.extend_from_slice(&5_000_000_000_u64.to_le_bytes());
+    coinbase_suffix.push(0);
+    coinbase_suffix.extend_from_slice(&[0; 4]);
+    server_to_client::Notify {
+        job_id: job_id.to_string(),
+        prev_hash: PrevHash(prev_hash.into()),
+        coin_base1: coinbase_prefix.into(),
+        coin_base2: coinbase_suffix.into(),
+        merkle_branch: Vec::<MerkleNode>::new(),
+        version: HexU32Be(0x2000_0000),
+        bits: HexU32Be(nbits),
+        time: HexU32Be(ntime),
+        clean_jobs,
+    }
+}
+
+#[derive(Default)]
+struct State {
+    script: Vec<Sv1PoolEvent>,
+    /// Authorized connections
+    connections: Vec<Sender<Message>>,
+    accepted: u32,
+    /// Jobs the submits can be for
+    jobs: HashSet<String>,
+    submits: Vec<(client_to_server::Submit<'static>, bool)>,
+}
+
+impl State {
+    fn on_event(&mut self, event: &Sv1PoolEvent) {
+        if let Sv1PoolEvent::Notify(notify) = event {
+            if notify.clean_jobs {
+                self.jobs.clear();
+            }
+            self.jobs.insert(notify.job_id.clone());
+        }
+        self.script.push(event.clone());
+    }
+}
+
+/// SV1 pool served from a task of the current runtime
+pub struct MockSv1Pool {
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+    listener: AbortHandle,
+}
+
+impl MockSv1Pool {
+    /// Listens on a free local port, `script` is sent to every connection once it is authorized
+    pub async fn start(script: Vec<Sv1PoolEvent>) -> Result<Self, String> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| e.to_string())?;
+        let address = listener.local_addr().map_err(|e| e.to_string())?;
+        let mut state = State::default();
+        for event in &script {
+            state.on_event(event);
+        }
+        let state = Arc::new(Mutex::new(state));
+        let state_ = state.clone();
+        let listener = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = state_.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = Self::serve(stream, state).await {
+                        debug!("SV1 pool connection closed: {}", e);
+                    }
+                });
+            }
+        })
+        .abort_handle();
+        Ok(Self {
+            address,
+            state,
+            listener,
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    async fn serve(stream: TcpStream, state: Arc<Mutex<State>>) -> Result<(), String> {
+        let (reader, writer) = stream.into_split();
+        let (sender, receiver) = unbounded();
+        let writer = tokio::spawn(Self::write(writer, receiver));
+        let result = Self::read(BufReader::new(reader), sender, state).await;
+        writer.abort();
+        result
+    }
+
+    async fn write(mut writer: OwnedWriteHalf, receiver: Receiver<Message>) -> Result<(), String> {
+        while let Ok(message) = receiver.recv().await {
+            let line = format!(
+                "{}\n",
+                serde_json::to_string(&message).map_err(|e| e.to_string())?
+            );
+            writer
+                .write_all(line.as_bytes())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    async fn read(
+        reader: BufReader<tokio::net::tcp::OwnedReadHalf>,
+        sender: Sender<Message>,
+        state: Arc<Mutex<State>>,
+    ) -> Result<(), String> {
+        let extranonce1 = state
+            .safe_lock(|s| {
+                s.accepted += 1;
+                s.accepted
+            })
+            .map_err(|e| e.to_string())?
+            .to_be_bytes()
+            .to_vec();
+        let mut lines = reader.lines();
+        while let Some(line) = lines.next_line().await.map_err(|e| e.to_string())? {
+            let request = match serde_json::from_str(&line) {
+                Ok(Message::StandardRequest(request)) => request,
+                _ => {
+                    warn!("SV1 pool ignores {}", line);
+                    continue;
+                }
+            };
+            Self::on_request(request, &extranonce1, &sender, &state)?;
+        }
+        Err("connection closed".to_string())
+    }
+
+    /// Queues the response to `request`, followed by the script once authorized
+    fn on_request(
+        request: StandardRequest,
+        extranonce1: &[u8],
+        sender: &Sender<Message>,
+        state: &Arc<Mutex<State>>,
+    ) -> Result<(), String> {
+        // The channel is unbounded, sending fails only once the writer has stopped
+        let send = |message: Message| {
+            sender
+                .try_send(message)
+                .map_err(|_| "writer closed".to_string())
+        };
+        match request.method.as_str() {
+            "mining.subscribe" => {
+                let subscribe = client_to_server::Subscribe::try_from(request)
+                    .map_err(|e| format!("invalid subscribe: {:?}", e))?;
+                let extranonce1 =
+                    Extranonce::try_from(extranonce1.to_vec()).map_err(|e| format!("{:?}", e))?;
+                let response = subscribe.respond(vec![], extranonce1, EXTRANONCE2_SIZE);
+                send(Message::OkResponse(response))
+            }
+            "mining.authorize" => {
+                let authorize = client_to_server::Authorize::try_from(request)
+                    .map_err(|e| format!("invalid authorize: {:?}", e))?;
+                // Queued under the lock so that the events sent meanwhile come after the script
+                state
+                    .safe_lock(|s| {
+                        send(Message::OkResponse(authorize.respond(true)))?;
+                        for event in s.script.iter().cloned() {
+                            send(event.into())?;
+                        }
+                        s.connections.push(sender.clone());
+                        Ok(())
+                    })
+                    .map_err(|e| e.to_string())?
+            }
+            "mining.submit" => {
+                let submit = client_to_server::Submit::try_from(request)
+                    .map_err(|e| format!("invalid submit: {:?}", e))?;
+                let accepted = state
+                    .safe_lock(|s| {
+                        let accepted = s.jobs.contains(&submit.job_id);
+                        s.submits.push((submit.clone(), accepted));
+                        accepted
+                    })
+                    .map_err(|e| e.to_string())?;
+                send(Message::OkResponse(submit.respond(accepted)))
+            }
+            method => {
+                warn!("SV1 pool ignores {}", method);
+                Ok(())
+            }
+        }
+    }
+
+    /// Sends `event` to every authorized connection and adds it to the script
+    pub fn send(&self, event: Sv1PoolEvent) -> Result<(), String> {
+        self.state
+            .safe_lock(|s| {
+                s.on_event(&event);
+                // The closed connections are skipped
+                s.connections
+                    .retain(|connection| connection.try_send(event.clone().into()).is_ok());
+            })
+            .map_err(|e| e.to_string())
+    }
+
+    /// Submits received, with true if they were accepted
+    pub fn submits(&self) -> Vec<(client_to_server::Submit<'static>, bool)> {
+        self.state
+            .safe_lock(|s| s.submits.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for MockSv1Pool {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sv1_miner::Sv1Miner, template_provider::NBITS, work::ShareKind};
+
+    #[tokio::test]
+    async fn test_scripted_sv1_pool() {
+        let pool = MockSv1Pool::start(vec![
+            // Wider than the bitcoin target of `NBITS`
+            Sv1PoolEvent::SetDifficulty(1.0 / (1 << 30) as f64),
+            Sv1PoolEvent::Notify(notify(1, [1; 32], 1_700_000_000, NBITS, true)),
+        ])
+        .await
+        .unwrap();
+        let mut miner = Sv1Miner::connect(pool.address()).await.unwrap();
+        let first = miner.next_work(None).await.unwrap();
+        assert_eq!(first.job_id, 1);
+        assert!(miner.submit(&first, ShareKind::Share).await.unwrap());
+
+        pool.send(Sv1PoolEvent::Notify(notify(
+            2,
+            [2; 32],
+            1_700_000_600,
+            NBITS,
+            true,
+        )))
+        .unwrap();
+        let second = miner.next_work(Some(&first)).await.unwrap();
+        assert_eq!(second.job_id, 2);
+        // The first job has been cleaned
+        assert!(!miner.submit(&first, ShareKind::Share).await.unwrap());
+        assert!(miner.submit(&second, ShareKind::Block).await.unwrap());
+
+        let submits: Vec<(String, bool)> = pool
+            .submits()
+            .into_iter()
+            .map(|(submit, accepted)| (submit.job_id, accepted))
+            .collect();
+        assert_eq!(
+            submits,
+            vec![
+                ("1".to_string(), true),
+                ("1".to_string(), false),
+                ("2".to_string(), true)
+            ]
+        );
+    }
+}
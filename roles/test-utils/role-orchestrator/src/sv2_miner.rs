@@ -0,0 +1,182 @@
+//! SV2 miner with an extended channel, connected directly to the pool.
+//!
+//! The miner keeps the jobs and the prev hashes it receives and submits the shares the scenario
+//! asks for, on the current job or on an old one.
+use crate::{
+    connection::Sv2Connection,
+    within,
+    work::{ShareKind, Work},
+};
+use binary_sv2::U256;
+use roles_logic_sv2::{
+    common_messages_sv2::{DeviceInfoBuilder, Endpoint, Protocol, SetupConnection},
+    mining_sv2::{
+        NewExtendedMiningJob, OpenExtendedMiningChannel, SetNewPrevHash, SubmitSharesExtended,
+        Target,
+    },
+    parsers::{CommonMessages, Mining, PoolMessages},
+};
+use std::{convert::TryInto, net::SocketAddr};
+
+/// Hashrate advertised for the channel, low enough for the pool to accept any hash as a share
+const NOMINAL_HASH_RATE: f32 = 0.01;
+const MIN_EXTRANONCE_SIZE: u16 = 8;
+
+pub struct Sv2Miner {
+    connection: Sv2Connection,
+    channel_id: u32,
+    target: Target,
+    extranonce_prefix: Vec<u8>,
+    /// Part of the extranonce rolled by the miner, always zero
+    extranonce_size: usize,
+    jobs: Vec<NewExtendedMiningJob<'static>>,
+    prev_hash: Option<SetNewPrevHash<'static>>,
+    sequence_number: u32,
+}
+
+impl Sv2Miner {
+    /// Opens an extended channel on the pool at `address`
+    pub async fn connect(address: SocketAddr) -> Result<Self, String> {
+        let connection = Sv2Connection::connect(address).await?;
+        let device_info = DeviceInfoBuilder::new("orchestrator")
+            .hardware_version("scripted")
+            .build()
+            .unwrap();
+        let setup_connection = SetupConnection::for_protocol(
+            Protocol::MiningProtocol,
+            device_info,
+            Endpoint::new("127.0.0.1", address.port()).unwrap(),
+        );
+        connection
+            .send(PoolMessages::Common(CommonMessages::SetupConnection(
+                setup_connection,
+            )))
+            .await?;
+        match within("SetupConnectionSuccess", connection.recv()).await?? {
+            PoolMessages::Common(CommonMessages::SetupConnectionSuccess(_)) => (),
+            m => return Err(format!("expected SetupConnectionSuccess, got {:?}", m)),
+        }
+        let max_target: U256 = Target::MAX.into();
+        let open_channel = OpenExtendedMiningChannel {
+            request_id: 1,
+            user_identity: "orchestrator".to_string().into_bytes().try_into().unwrap(),
+            nominal_hash_rate: NOMINAL_HASH_RATE,
+            max_target,
+            min_extranonce_size: MIN_EXTRANONCE_SIZE,
+        };
+        connection
+            .send(PoolMessages::Mining(Mining::OpenExtendedMiningChannel(
+                open_channel,
+            )))
+            .await?;
+        let success = match within("OpenExtendedMiningChannelSuccess", connection.recv()).await?? {
+            PoolMessages::Mining(Mining::OpenExtendedMiningChannelSuccess(m)) => m,
+            m => {
+                return Err(format!(
+                    "expected OpenExtendedMiningChannelSuccess, got {:?}",
+                    m
+                ))
+            }
+        };
+        Ok(Self {
+            connection,
+            channel_id: success.channel_id,
+            target: success.target.into(),
+            extranonce_prefix: success.extranonce_prefix.to_vec(),
+            extranonce_size: success.extranonce_size as usize,
+            jobs: vec![],
+            prev_hash: None,
+            sequence_number: 0,
+        })
+    }
+
+    fn on_message(&mut self, message: Mining<'static>) {
+        match message {
+            Mining::NewExtendedMiningJob(m) => self.jobs.push(m),
+            Mining::SetNewPrevHash(m) => {
+                self.jobs.retain(|j| j.job_id == m.job_id);
+                self.prev_hash = Some(m);
+            }
+            Mining::SetTarget(m) => self.target = m.target(),
+            _ => (),
+        }
+    }
+
+    /// Current work, built from the last prev hash and its job
+    pub fn work(&self) -> Result<Option<Work>, String> {
+        let prev_hash = match &self.prev_hash {
+            Some(prev_hash) => prev_hash,
+            None => return Ok(None),
+        };
+        let job = match self.jobs.iter().find(|j| j.job_id == prev_hash.job_id) {
+            Some(job) => job,
+            None => return Ok(None),
+        };
+        let mut extranonce = self.extranonce_prefix.clone();
+        extranonce.resize(self.extranonce_prefix.len() + self.extranonce_size, 0);
+        let merkle_path: Vec<Vec<u8>> = job.merkle_path.to_vec();
+        Work::new(
+            job.job_id,
+            job.version,
+            prev_hash.prev_hash.inner_as_ref(),
+            job.coinbase_tx_prefix.inner_as_ref(),
+            job.coinbase_tx_suffix.inner_as_ref(),
+            &extranonce,
+            &merkle_path,
+            prev_hash.min_ntime,
+            prev_hash.nbits,
+            self.target.clone(),
+        )
+        .map(Some)
+    }
+
+    /// Waits for a work different from `previous`
+    pub async fn next_work(&mut self, previous: Option<&Work>) -> Result<Work, String> {
+        loop {
+            if let Some(work) = self.work()? {
+                if Some(&work) != previous {
+                    return Ok(work);
+                }
+            }
+            if let PoolMessages::Mining(m) = within("a new job", self.connection.recv()).await?? {
+                self.on_message(m);
+            }
+        }
+    }
+
+    /// Submits a share of `kind` on `work`, returns true if it is accepted
+    pub async fn submit(&mut self, work: &Work, kind: ShareKind) -> Result<bool, String> {
+        let nonce = work.find_nonce(kind)?;
+        self.sequence_number += 1;
+        let share = SubmitSharesExtended {
+            channel_id: self.channel_id,
+            sequence_number: self.sequence_number,
+            job_id: work.job_id,
+            nonce,
+            ntime: work.ntime,
+            version: work.version,
+            extranonce: vec![0; self.extranonce_size]
+                .try_into()
+                .map_err(|e| format!("{:?}", e))?,
+        };
+        self.connection
+            .send(PoolMessages::Mining(Mining::SubmitSharesExtended(share)))
+            .await?;
+        loop {
+            match within("the answer to a share", self.connection.recv()).await?? {
+                PoolMessages::Mining(Mining::SubmitSharesSuccess(m))
+                    if m.last_sequence_number == self.sequence_number =>
+                {
+                    return Ok(true)
+                }
+                PoolMessages::Mining(Mining::SubmitSharesError(m))
+                    if m.sequence_number == self.sequence_number =>
+                {
+                    return Ok(false)
+                }
+                PoolMessages::Mining(m) => self.on_message(m),
+                _ => (),
+            }
+        }
+    }
+}
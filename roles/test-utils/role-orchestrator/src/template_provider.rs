@@ -0,0 +1,296 @@
+//! Template Provider the pool and the JDC connect to.
+//!
+//! It serves a chain of empty blocks: every connection gets the `NewTemplate` of the next block
+//! and the `SetNewPrevHash` that activates it. The scenario mines on it with
+//! [`MockTemplateProvider::new_block`], and can restart it to check how the roles recover. The
+//! solutions submitted by the roles are recorded.
+use crate::{connection::Sv2Connection, within};
+use binary_sv2::{U256Ext, U256};
+use roles_logic_sv2::{
+    common_messages_sv2::{Protocol, SetupConnectionSuccess},
+    parsers::{CommonMessages, PoolMessages, TemplateDistribution},
+    template_distribution_sv2::{
+        NewTemplate, RequestTransactionDataSuccess, SetNewPrevHash, SubmitSolution,
+    },
+    utils::Mutex,
+};
+use std::{
+    convert::TryInto,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    net::TcpListener,
+    task::{AbortHandle, JoinHandle},
+};
+use tracing::{debug, warn};
+
+/// Bits of the blocks, a block is found every few hundred hashes
+pub const NBITS: u32 = 0x2000_ffff;
+
+const BLOCK_REWARD: u64 = 5_000_000_000;
+
+#[derive(Debug, Clone)]
+struct Block {
+    template_id: u64,
+    height: u32,
+    prev_hash: [u8; 32],
+    timestamp: u32,
+}
+
+impl Block {
+    fn new_template(&self) -> NewTemplate<'static> {
+        // Height pushed as required by BIP34, followed by the extranonce push of the coinbase
+        let height = self.height.to_le_bytes();
+        let coinbase_prefix = vec![3, height[0], height[1], height[2], 0];
+        NewTemplate {
+            template_id: self.template_id,
+            future_template: true,
+            version: 0x2000_0000,
+            coinbase_tx_version: 2,
+            // Infallible unwraps, the fields are small enough
+            coinbase_prefix: coinbase_prefix.try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: BLOCK_REWARD,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: vec![].try_into().unwrap(),
+        }
+    }
+
+    fn set_new_prev_hash(&self) -> SetNewPrevHash<'static> {
+        // Infallible unwrap, `NBITS` is valid
+        let target = U256::from_compact(NBITS).unwrap();
+        SetNewPrevHash {
+            template_id: self.template_id,
+            prev_hash: self.prev_hash.into(),
+            header_timestamp: self.timestamp,
+            n_bits: NBITS,
+            target,
+        }
+    }
+
+    fn next(&self) -> Self {
+        let mut prev_hash = [0; 32];
+        prev_hash[..4].copy_from_slice(&(self.height + 1).to_le_bytes());
+        Self {
+            template_id: self.template_id + 1,
+            height: self.height + 1,
+            prev_hash,
+            timestamp: now().max(self.timestamp + 1),
+        }
+    }
+}
+
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or_default()
+}
+
+#[derive(Default)]
+struct State {
+    block: Option<Block>,
+    connections: Vec<Sv2Connection>,
+    /// Connections accepted since the start
+    accepted: usize,
+    solutions: Vec<SubmitSolution<'static>>,
+}
+
+/// Template Provider served from a task of the current runtime
+pub struct MockTemplateProvider {
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+    listener: Mutex<AbortHandle>,
+}
+
+impl MockTemplateProvider {
+    /// Listens on a free local port, the tip of the chain is at height 100
+    pub async fn start() -> Result<Self, String> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| e.to_string())?;
+        let address = listener.local_addr().map_err(|e| e.to_string())?;
+        let state = Arc::new(Mutex::new(State {
+            block: Some(Block {
+                template_id: 1,
+                height: 101,
+                prev_hash: [100; 32],
+                timestamp: now(),
+            }),
+            ..Default::default()
+        }));
+        let listener = Self::listen(listener, state.clone()).abort_handle();
+        Ok(Self {
+            address,
+            state,
+            listener: Mutex::new(listener),
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    fn listen(listener: TcpListener, state: Arc<Mutex<State>>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = Self::serve(stream, state).await {
+                        debug!("Template Provider connection closed: {}", e);
+                    }
+                });
+            }
+        })
+    }
+
+    async fn serve(stream: tokio::net::TcpStream, state: Arc<Mutex<State>>) -> Result<(), String> {
+        let connection = Sv2Connection::accept(stream).await?;
+        match connection.recv().await? {
+            PoolMessages::Common(CommonMessages::SetupConnection(m))
+                if m.protocol == Protocol::TemplateDistributionProtocol => {}
+            m => return Err(format!("expected SetupConnection, got {:?}", m)),
+        }
+        connection
+            .send(PoolMessages::Common(
+                CommonMessages::SetupConnectionSuccess(SetupConnectionSuccess {
+                    used_version: 2,
+                    flags: 0,
+                }),
+            ))
+            .await?;
+        let block = state
+            .safe_lock(|s| {
+                s.connections.push(connection.clone());
+                s.accepted += 1;
+                s.block.clone()
+            })
+            .map_err(|e| e.to_string())?;
+        if let Some(block) = block {
+            Self::send_block(&connection, &block).await?;
+        }
+        loop {
+            let message = match connection.recv().await? {
+                PoolMessages::TemplateDistribution(m) => m,
+                m => {
+                    warn!("Template Provider ignores {:?}", m);
+                    continue;
+                }
+            };
+            match message {
+                TemplateDistribution::CoinbaseOutputDataSize(_) => (),
+                TemplateDistribution::RequestTransactionData(m) => {
+                    let success = RequestTransactionDataSuccess {
+                        template_id: m.template_id,
+                        // Infallible unwraps, the blocks are empty
+                        excess_data: vec![].try_into().unwrap(),
+                        transaction_list: vec![].try_into().unwrap(),
+                    };
+                    connection
+                        .send(PoolMessages::TemplateDistribution(
+                            TemplateDistribution::RequestTransactionDataSuccess(success),
+                        ))
+                        .await?;
+                }
+                TemplateDistribution::SubmitSolution(m) => {
+                    state
+                        .safe_lock(|s| s.solutions.push(m))
+                        .map_err(|e| e.to_string())?;
+                }
+                m => warn!("Template Provider ignores {:?}", m),
+            }
+        }
+    }
+
+    async fn send_block(connection: &Sv2Connection, block: &Block) -> Result<(), String> {
+        connection
+            .send(PoolMessages::TemplateDistribution(
+                TemplateDistribution::NewTemplate(block.new_template()),
+            ))
+            .await?;
+        connection
+            .send(PoolMessages::TemplateDistribution(
+                TemplateDistribution::SetNewPrevHash(block.set_new_prev_hash()),
+            ))
+            .await
+    }
+
+    /// Extends the chain and sends the template of the next block to every connection, returns
+    /// its template id
+    pub async fn new_block(&self) -> Result<u64, String> {
+        let (block, connections) = self
+            .state
+            .safe_lock(|s| {
+                let block = s.block.as_ref().map(Block::next);
+                s.block = block.clone();
+                (block, s.connections.clone())
+            })
+            .map_err(|e| e.to_string())?;
+        let block = block.ok_or_else(|| "no chain".to_string())?;
+        for connection in connections {
+            // The connections that are closed are dropped at the next restart
+            let _ = Self::send_block(&connection, &block).await;
+        }
+        Ok(block.template_id)
+    }
+
+    /// Closes every connection and stops listening for `down_for`, then listens again on the same
+    /// address
+    pub async fn restart(&self, down_for: Duration) -> Result<(), String> {
+        self.listener
+            .safe_lock(|l| l.abort())
+            .map_err(|e| e.to_string())?;
+        let connections = self
+            .state
+            .safe_lock(|s| std::mem::take(&mut s.connections))
+            .map_err(|e| e.to_string())?;
+        for connection in connections {
+            connection.close();
+        }
+        tokio::time::sleep(down_for).await;
+        let listener = within("the Template Provider address", async {
+            loop {
+                match TcpListener::bind(self.address).await {
+                    Ok(listener) => break listener,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+                }
+            }
+        })
+        .await?;
+        let handle = Self::listen(listener, self.state.clone()).abort_handle();
+        self.listener
+            .safe_lock(|l| *l = handle)
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Template id of the tip of the chain
+    pub fn template_id(&self) -> u64 {
+        self.state
+            .safe_lock(|s| s.block.as_ref().map(|b| b.template_id))
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    }
+
+    /// Connections accepted since the start, including the ones closed by a restart
+    pub fn accepted_connections(&self) -> usize {
+        self.state.safe_lock(|s| s.accepted).unwrap_or_default()
+    }
+
+    pub fn solutions(&self) -> Vec<SubmitSolution<'static>> {
+        self.state
+            .safe_lock(|s| s.solutions.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for MockTemplateProvider {
+    fn drop(&mut self) {
+        let _ = self.listener.safe_lock(|l| l.abort());
+    }
+}
@@ -0,0 +1,124 @@
+//! Work of the scripted miners: the header of a job, and the nonces of the shares the scenarios
+//! ask for
+use binary_sv2::{U256Ext, U256};
+use roles_logic_sv2::mining_sv2::Target;
+use std::convert::TryInto;
+use stratum_common::bitcoin::{
+    blockdata::block::BlockHeader,
+    hash_types::{BlockHash, TxMerkleNode},
+    hashes::{sha256d::Hash, Hash as _},
+};
+
+/// What a share must be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareKind {
+    /// Meets the target of the channel but not the bitcoin target
+    Share,
+    /// Meets the bitcoin target
+    Block,
+}
+
+/// A job with the prev hash that activates it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Work {
+    pub job_id: u32,
+    pub version: u32,
+    pub prev_hash: [u8; 32],
+    pub merkle_root: [u8; 32],
+    pub ntime: u32,
+    pub nbits: u32,
+    /// Target of the shares of the channel
+    pub share_target: Target,
+}
+
+impl Work {
+    /// `extranonce` is the whole extranonce, the one of the channel followed by the one of the
+    /// miner
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<T: AsRef<[u8]>>(
+        job_id: u32,
+        version: u32,
+        prev_hash: &[u8],
+        coinbase_tx_prefix: &[u8],
+        coinbase_tx_suffix: &[u8],
+        extranonce: &[u8],
+        merkle_path: &[T],
+        ntime: u32,
+        nbits: u32,
+        share_target: Target,
+    ) -> Result<Self, String> {
+        let merkle_root = roles_logic_sv2::utils::merkle_root_from_path(
+            coinbase_tx_prefix,
+            coinbase_tx_suffix,
+            extranonce,
+            merkle_path,
+        )
+        .ok_or_else(|| format!("invalid coinbase for job {}", job_id))?;
+        Ok(Self {
+            job_id,
+            version,
+            prev_hash: prev_hash
+                .try_into()
+                .map_err(|_| format!("prev hash of {} bytes", prev_hash.len()))?,
+            // Infallible unwrap, a sha256 is 32 bytes
+            merkle_root: merkle_root.try_into().unwrap(),
+            ntime,
+            nbits,
+            share_target,
+        })
+    }
+
+    /// Hash of the header with `nonce`
+    pub fn hash(&self, nonce: u32) -> Target {
+        let header = BlockHeader {
+            version: self.version as i32,
+            prev_blockhash: BlockHash::from_hash(Hash::from_inner(self.prev_hash)),
+            merkle_root: TxMerkleNode::from_hash(Hash::from_inner(self.merkle_root)),
+            time: self.ntime,
+            bits: self.nbits,
+            nonce,
+        };
+        header.block_hash().as_hash().into_inner().into()
+    }
+
+    /// First nonce that makes a share of `kind`, the targets of the scenarios are wide enough for
+    /// this to take a few hundred hashes
+    pub fn find_nonce(&self, kind: ShareKind) -> Result<u32, String> {
+        let bitcoin_target: Target = U256::from_compact(self.nbits)
+            .map(Into::into)
+            .ok_or_else(|| format!("invalid nbits {:x}", self.nbits))?;
+        (0..u32::MAX)
+            .find(|nonce| {
+                let hash = self.hash(*nonce);
+                match kind {
+                    ShareKind::Share => hash > bitcoin_target && hash <= self.share_target,
+                    ShareKind::Block => hash <= bitcoin_target,
+                }
+            })
+            .ok_or_else(|| format!("no nonce for a {:?} on job {}", kind, self.job_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template_provider::NBITS;
+
+    #[test]
+    fn test_find_nonce() {
+        let work = Work {
+            job_id: 1,
+            version: 0x2000_0000,
+            prev_hash: [1; 32],
+            merkle_root: [2; 32],
+            ntime: 1_700_000_000,
+            nbits: NBITS,
+            share_target: Target::MAX,
+        };
+        let bitcoin_target: Target = U256::from_compact(NBITS).unwrap().into();
+        let share = work.find_nonce(ShareKind::Share).unwrap();
+        assert!(work.hash(share) > bitcoin_target);
+        let block = work.find_nonce(ShareKind::Block).unwrap();
+        assert!(work.hash(block) <= bitcoin_target);
+    }
+}
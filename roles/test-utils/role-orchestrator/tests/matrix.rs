@@ -0,0 +1,67 @@
+use role_orchestrator::scenario::{run, Scenario, Topology};
+
+fn check(topology: Topology, scenario: Scenario) {
+    if let Err(failure) = run(topology, scenario) {
+        panic!("{}", failure);
+    }
+}
+
+#[test]
+fn pool_block_found() {
+    check(Topology::Pool, Scenario::BlockFound);
+}
+
+#[test]
+fn pool_upstream_restart() {
+    check(Topology::Pool, Scenario::UpstreamRestart);
+}
+
+#[test]
+fn pool_stale_shares() {
+    check(Topology::Pool, Scenario::StaleShares);
+}
+
+#[test]
+fn translator_block_found() {
+    check(Topology::Translator, Scenario::BlockFound);
+}
+
+#[test]
+fn translator_upstream_restart() {
+    check(Topology::Translator, Scenario::UpstreamRestart);
+}
+
+#[test]
+fn translator_stale_shares() {
+    check(Topology::Translator, Scenario::StaleShares);
+}
+
+#[test]
+fn pool_sv1_listener_block_found() {
+    check(Topology::PoolSv1Listener, Scenario::BlockFound);
+}
+
+#[test]
+fn pool_sv1_listener_upstream_restart() {
+    check(Topology::PoolSv1Listener, Scenario::UpstreamRestart);
+}
+
+#[test]
+fn pool_sv1_listener_stale_shares() {
+    check(Topology::PoolSv1Listener, Scenario::StaleShares);
+}
+
+#[test]
+fn job_declaration_block_found() {
+    check(Topology::JobDeclaration, Scenario::BlockFound);
+}
+
+#[test]
+fn job_declaration_upstream_restart() {
+    check(Topology::JobDeclaration, Scenario::UpstreamRestart);
+}
+
+#[test]
+fn job_declaration_stale_shares() {
+    check(Topology::JobDeclaration, Scenario::StaleShares);
+}
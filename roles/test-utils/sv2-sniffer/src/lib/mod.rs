@@ -0,0 +1,290 @@
+//! Man in the middle between two SV2 roles that prints every frame they exchange.
+//!
+//! The downstream connects to the sniffer, that opens a connection to the upstream for each
+//! downstream and relays the frames untouched in both directions. With noise the sniffer is the
+//! responder of the downstream, with its own authority keys, and the initiator of the upstream: the
+//! downstream must be configured with the authority public key of the sniffer instead of the one of
+//! the upstream.
+//!
+//! The frames can also be captured in a pcap file (see [`pcap`]), and the frames sent by a
+//! downstream in a capture can be replayed against an upstream with [`replay`].
+pub mod pcap;
+pub mod trace;
+
+use async_channel::{Receiver, Sender};
+use codec_sv2::{HandshakeRole, Initiator, Responder, StandardEitherFrame, StandardSv2Frame};
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use network_helpers_sv2::{
+    noise_connection_tokio::Connection, plain_connection_tokio::PlainConnection,
+};
+use pcap::{Packet, PcapWriter};
+use roles_logic_sv2::{parsers::AnyMessage, utils::Mutex};
+use std::{io::Write, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+use trace::{Direction, Format, Record};
+use tracing::{error, info};
+
+type EitherFrame = StandardEitherFrame<AnyMessage<'static>>;
+
+#[derive(Debug, Clone)]
+pub enum Transport {
+    Plaintext,
+    Noise {
+        /// Keys used to authenticate the sniffer to the downstream, only needed to relay
+        authority: Option<AuthorityKeys>,
+        /// Key of the upstream, its certificate is not checked when `None`
+        upstream_authority_public_key: Option<Secp256k1PublicKey>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthorityKeys {
+    pub public_key: Secp256k1PublicKey,
+    pub secret_key: Secp256k1SecretKey,
+    pub cert_validity: Duration,
+}
+
+/// Where the records are written
+pub type Output = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// Where the frames are captured
+pub type Capture = Arc<Mutex<PcapWriter<Box<dyn Write + Send>>>>;
+
+/// Accepts the downstreams on `listen_address` and relays each one of them to `upstream_address`
+pub async fn run(
+    listen_address: SocketAddr,
+    upstream_address: SocketAddr,
+    transport: Transport,
+    format: Format,
+    output: Output,
+    capture: Option<Capture>,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(listen_address).await?;
+    info!(
+        "Listening on {}, relaying to {}",
+        listen_address, upstream_address
+    );
+    let mut connection_id = 0;
+    loop {
+        let (downstream, address) = listener.accept().await?;
+        info!("Connection #{} from {}", connection_id, address);
+        let transport = transport.clone();
+        let output = output.clone();
+        let capture = capture.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = relay(
+                connection_id,
+                downstream,
+                upstream_address,
+                transport,
+                format,
+                output,
+                capture,
+            )
+            .await
+            {
+                error!("Connection #{}: {}", connection_id, e);
+            }
+            info!("Connection #{} closed", connection_id);
+        });
+        connection_id += 1;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    /// Id of the connection of the capture to replay
+    pub connection: u32,
+    /// Wait between the frames as much as in the capture, otherwise they are sent at once
+    pub keep_timing: bool,
+    /// How long the frames of the upstream are printed after the last frame is sent
+    pub wait: Duration,
+}
+
+/// Connects to `upstream_address` and sends the frames that a downstream sent in `packets`. The
+/// frames received from the upstream are printed until it closes the connection or for
+/// `options.wait` after the last frame.
+pub async fn replay(
+    upstream_address: SocketAddr,
+    transport: Transport,
+    packets: Vec<Packet>,
+    options: ReplayOptions,
+    format: Format,
+    output: Output,
+) -> Result<(), String> {
+    let connection = options.connection;
+    let packets: Vec<Packet> = packets
+        .into_iter()
+        .filter(|p| p.connection == connection && p.direction == Direction::ToUpstream)
+        .collect();
+    if packets.is_empty() {
+        return Err(format!(
+            "the capture has no frame sent by the downstream of connection #{}",
+            connection
+        ));
+    }
+    let (recv_up, send_up) = connect_upstream(upstream_address, &transport).await?;
+    let received = forward(
+        connection,
+        Direction::ToDownstream,
+        recv_up,
+        None,
+        format,
+        output.clone(),
+        None,
+    );
+    let sent = async {
+        let mut previous: Option<Duration> = None;
+        for packet in packets {
+            if let (true, Some(previous)) = (options.keep_timing, previous) {
+                tokio::time::sleep(packet.timestamp.saturating_sub(previous)).await;
+            }
+            previous = Some(packet.timestamp);
+            let mut frame = frame_from_bytes(packet.frame)
+                .ok_or_else(|| "the capture has an invalid frame".to_string())?;
+            if let Some(record) = Record::from_frame(connection, Direction::ToUpstream, &mut frame)
+            {
+                write_record(&output, &record, format);
+            }
+            send_up
+                .send(frame)
+                .await
+                .map_err(|_| "the upstream closed the connection".to_string())?;
+        }
+        tokio::time::sleep(options.wait).await;
+        Ok(())
+    };
+    tokio::select! {
+        r = sent => r,
+        _ = received => Err("the upstream closed the connection".to_string()),
+    }
+}
+
+// The buffer of the frames is a `Vec` or a `Slice` depending on the `with_buffer_pool` feature of
+// codec_sv2
+#[allow(clippy::useless_conversion)]
+fn frame_from_bytes(bytes: Vec<u8>) -> Option<EitherFrame> {
+    StandardSv2Frame::from_bytes(bytes.into())
+        .ok()
+        .map(|frame| frame.into())
+}
+
+async fn connect_upstream(
+    upstream_address: SocketAddr,
+    transport: &Transport,
+) -> Result<(Receiver<EitherFrame>, Sender<EitherFrame>), String> {
+    let upstream = TcpStream::connect(upstream_address)
+        .await
+        .map_err(|e| format!("failed to connect to {}: {}", upstream_address, e))?;
+    match transport {
+        Transport::Plaintext => Ok(PlainConnection::new(upstream).await),
+        Transport::Noise {
+            upstream_authority_public_key,
+            ..
+        } => {
+            let initiator = Initiator::new(upstream_authority_public_key.map(|k| k.0));
+            let (recv_up, send_up, _, _) =
+                Connection::new(upstream, HandshakeRole::Initiator(initiator))
+                    .await
+                    .map_err(|e| format!("upstream handshake failed: {:?}", e))?;
+            Ok((recv_up, send_up))
+        }
+    }
+}
+
+async fn relay(
+    connection_id: u32,
+    downstream: TcpStream,
+    upstream_address: SocketAddr,
+    transport: Transport,
+    format: Format,
+    output: Output,
+    capture: Option<Capture>,
+) -> Result<(), String> {
+    let (recv_down, send_down) = match &transport {
+        Transport::Plaintext => PlainConnection::new(downstream).await,
+        Transport::Noise {
+            authority: Some(authority),
+            ..
+        } => {
+            let responder = Responder::from_authority_kp(
+                &authority.public_key.into_bytes(),
+                &authority.secret_key.into_bytes(),
+                authority.cert_validity,
+            )
+            .map_err(|e| format!("invalid authority keys: {:?}", e))?;
+            let (recv_down, send_down, _, _) =
+                Connection::new(downstream, HandshakeRole::Responder(responder))
+                    .await
+                    .map_err(|e| format!("downstream handshake failed: {:?}", e))?;
+            (recv_down, send_down)
+        }
+        Transport::Noise {
+            authority: None, ..
+        } => return Err("the authority keys are needed to relay with noise".to_string()),
+    };
+    let (recv_up, send_up) = connect_upstream(upstream_address, &transport).await?;
+    let to_upstream = forward(
+        connection_id,
+        Direction::ToUpstream,
+        recv_down,
+        Some(send_up),
+        format,
+        output.clone(),
+        capture.clone(),
+    );
+    let to_downstream = forward(
+        connection_id,
+        Direction::ToDownstream,
+        recv_up,
+        Some(send_down),
+        format,
+        output,
+        capture,
+    );
+    // When one side closes the other one is dropped, and closed, with it
+    tokio::select! {
+        _ = to_upstream => Ok(()),
+        _ = to_downstream => Ok(()),
+    }
+}
+
+// Prints and captures the frames received from `recv` and relays them to `send`, if any
+async fn forward(
+    connection_id: u32,
+    direction: Direction,
+    recv: Receiver<EitherFrame>,
+    send: Option<Sender<EitherFrame>>,
+    format: Format,
+    output: Output,
+    capture: Option<Capture>,
+) {
+    while let Ok(mut frame) = recv.recv().await {
+        if let Some(record) = Record::from_frame(connection_id, direction, &mut frame) {
+            write_record(&output, &record, format);
+        }
+        if let Some(capture) = &capture {
+            if let Some(packet) = Packet::from_frame(connection_id, direction, &mut frame) {
+                capture.super_safe_lock(|c| {
+                    if let Err(e) = c.write_packet(&packet) {
+                        error!("Failed to write the capture: {}", e);
+                    }
+                });
+            }
+        }
+        if let Some(send) = &send {
+            if send.send(frame).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+fn write_record(output: &Output, record: &Record, format: Format) {
+    let line = record.to_line(format);
+    output.super_safe_lock(|o| {
+        if let Err(e) = writeln!(o, "{}", line).and_then(|_| o.flush()) {
+            error!("Failed to write the trace: {}", e);
+        }
+    });
+}
@@ -0,0 +1,219 @@
+//! Captures of the relayed frames in the pcap format, to be analyzed in Wireshark or replayed
+//! against a role.
+//!
+//! The frames are captured decrypted, with the link type [`LINKTYPE_SV2`] (`LINKTYPE_USER0`) that
+//! Wireshark can decode with a custom dissector. Each packet starts with 1 byte for the
+//! [`Direction`] (0 to the upstream, 1 to the downstream) and the id of the connection as a little
+//! endian u32, followed by the frame: the SV2 header and the payload, without the CRC.
+use crate::trace::Direction;
+use codec_sv2::{framing_sv2::framing::Frame, StandardEitherFrame};
+use roles_logic_sv2::parsers::AnyMessage;
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// `LINKTYPE_USER0`, reserved for private use
+pub const LINKTYPE_SV2: u32 = 147;
+
+const MAGIC: u32 = 0xa1b2_c3d4;
+const PACKET_HEADER_SIZE: usize = 5;
+// The biggest SV2 frame with the packet header
+const SNAPLEN: u32 = PACKET_HEADER_SIZE as u32 + 6 + 0x00ff_ffff;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    /// Time since the unix epoch
+    pub timestamp: Duration,
+    pub connection: u32,
+    pub direction: Direction,
+    /// The SV2 frame, decrypted
+    pub frame: Vec<u8>,
+}
+
+impl Packet {
+    /// Returns `None` for the handshake frames, that are not captured
+    pub fn from_frame(
+        connection: u32,
+        direction: Direction,
+        frame: &mut StandardEitherFrame<AnyMessage<'static>>,
+    ) -> Option<Self> {
+        let frame = match frame {
+            Frame::Sv2(frame) => frame,
+            Frame::HandShake(_) => return None,
+        };
+        let header = frame.get_header()?;
+        let payload = frame.payload();
+        let extension_type = header.ext_type() & !const_sv2::EXTENSION_TYPE_FRAME_CRC;
+        let mut bytes = Vec::with_capacity(6 + payload.len());
+        bytes.extend_from_slice(&extension_type.to_le_bytes());
+        bytes.push(header.msg_type());
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes()[..3]);
+        bytes.extend_from_slice(payload);
+        Some(Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            connection,
+            direction,
+            frame: bytes,
+        })
+    }
+}
+
+pub struct PcapWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes the header of the capture
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(&MAGIC.to_le_bytes())?;
+        // version 2.4
+        inner.write_all(&2_u16.to_le_bytes())?;
+        inner.write_all(&4_u16.to_le_bytes())?;
+        // time zone and accuracy of the timestamps, always 0
+        inner.write_all(&[0; 8])?;
+        inner.write_all(&SNAPLEN.to_le_bytes())?;
+        inner.write_all(&LINKTYPE_SV2.to_le_bytes())?;
+        Ok(Self { inner })
+    }
+
+    pub fn write_packet(&mut self, packet: &Packet) -> io::Result<()> {
+        let len = (PACKET_HEADER_SIZE + packet.frame.len()) as u32;
+        self.inner
+            .write_all(&(packet.timestamp.as_secs() as u32).to_le_bytes())?;
+        self.inner
+            .write_all(&packet.timestamp.subsec_micros().to_le_bytes())?;
+        self.inner.write_all(&len.to_le_bytes())?;
+        self.inner.write_all(&len.to_le_bytes())?;
+        let direction = match packet.direction {
+            Direction::ToUpstream => 0,
+            Direction::ToDownstream => 1,
+        };
+        self.inner.write_all(&[direction])?;
+        self.inner.write_all(&packet.connection.to_le_bytes())?;
+        self.inner.write_all(&packet.frame)?;
+        self.inner.flush()
+    }
+}
+
+/// Iterates over the packets of a capture written by [`PcapWriter`]
+pub struct PcapReader<R: Read> {
+    inner: R,
+    big_endian: bool,
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Reads the header of the capture, fails if it is not a capture of SV2 frames
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut header = [0; 24];
+        inner.read_exact(&mut header)?;
+        let big_endian = match header[..4] {
+            [0xd4, 0xc3, 0xb2, 0xa1] => false,
+            [0xa1, 0xb2, 0xc3, 0xd4] => true,
+            _ => return Err(invalid_data("not a pcap file")),
+        };
+        let reader = Self { inner, big_endian };
+        let link_type = reader.u32(&header[20..24]);
+        if link_type != LINKTYPE_SV2 {
+            return Err(invalid_data(&format!(
+                "link type {} is not the one of the SV2 captures ({})",
+                link_type, LINKTYPE_SV2
+            )));
+        }
+        Ok(reader)
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        }
+    }
+
+    fn next_packet(&mut self) -> io::Result<Option<Packet>> {
+        let mut header = [0; 16];
+        match self.inner.read_exact(&mut header) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            r => r?,
+        }
+        let secs = self.u32(&header[..4]);
+        let micros = self.u32(&header[4..8]);
+        let len = self.u32(&header[8..12]) as usize;
+        if len < PACKET_HEADER_SIZE || len > SNAPLEN as usize {
+            return Err(invalid_data("invalid packet length"));
+        }
+        let mut data = vec![0; len];
+        self.inner.read_exact(&mut data)?;
+        let direction = match data[0] {
+            0 => Direction::ToUpstream,
+            1 => Direction::ToDownstream,
+            _ => return Err(invalid_data("invalid direction")),
+        };
+        let connection = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+        Ok(Some(Packet {
+            timestamp: Duration::from_secs(secs as u64) + Duration::from_micros(micros as u64),
+            connection,
+            direction,
+            frame: data.split_off(PACKET_HEADER_SIZE),
+        }))
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = io::Result<Packet>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet().transpose()
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read_a_capture() {
+        let packets = vec![
+            Packet {
+                timestamp: Duration::from_micros(1_700_000_000_123_456),
+                connection: 7,
+                direction: Direction::ToUpstream,
+                frame: vec![0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0xaa, 0xbb],
+            },
+            Packet {
+                timestamp: Duration::from_secs(1_700_000_001),
+                connection: 7,
+                direction: Direction::ToDownstream,
+                frame: vec![0x00, 0x80, 0x01, 0x00, 0x00, 0x00],
+            },
+        ];
+        let mut writer = PcapWriter::new(vec![]).unwrap();
+        for packet in &packets {
+            writer.write_packet(packet).unwrap();
+        }
+        let capture = writer.inner;
+        assert_eq!(capture.len(), 24 + 16 + 5 + 8 + 16 + 5 + 6);
+
+        let read: Vec<Packet> = PcapReader::new(capture.as_slice())
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(read, packets);
+    }
+
+    #[test]
+    fn test_other_link_types_are_refused() {
+        let mut capture = PcapWriter::new(vec![]).unwrap().inner;
+        // LINKTYPE_ETHERNET
+        capture[20..24].copy_from_slice(&1_u32.to_le_bytes());
+        assert!(PcapReader::new(capture.as_slice()).is_err());
+        assert!(PcapReader::new(&b"not a capture at all...."[..]).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! One record per frame relayed by the sniffer, printed as a line of text or as a JSON object.
+use codec_sv2::{framing_sv2::framing::Frame, StandardEitherFrame};
+use roles_logic_sv2::parsers::{message_type_name, AnyMessage};
+use serde::Serialize;
+use std::{
+    convert::TryInto,
+    fmt::Write as _,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One line per frame, for humans
+    Text,
+    /// One JSON object per line, for scripts
+    Jsonl,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Format::Text),
+            "jsonl" | "json" => Ok(Format::Jsonl),
+            _ => Err(format!(
+                "unknown trace format {}, expected text or jsonl",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    ToUpstream,
+    ToDownstream,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Record {
+    /// Milliseconds since the unix epoch
+    pub timestamp: u128,
+    /// Id of the downstream connection, in the order they were accepted
+    pub connection: u32,
+    pub direction: Direction,
+    pub message_type: u8,
+    pub message_name: Option<&'static str>,
+    pub extension_type: u16,
+    pub channel_msg: bool,
+    /// Length of the payload
+    pub length: usize,
+    /// The decoded message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Why the payload could not be decoded, it is then dumped in `payload`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Hex of the payload of the frames that can not be decoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+}
+
+impl Record {
+    /// Decodes `frame`, that is left untouched so that it can be relayed. Returns `None` for the
+    /// handshake frames, that are never relayed.
+    pub fn from_frame(
+        connection: u32,
+        direction: Direction,
+        frame: &mut StandardEitherFrame<AnyMessage<'static>>,
+    ) -> Option<Self> {
+        let frame = match frame {
+            Frame::Sv2(frame) => frame,
+            Frame::HandShake(_) => return None,
+        };
+        let header = frame.get_header()?;
+        let payload = frame.payload();
+        let length = payload.len();
+        let mut copy = payload.to_vec();
+        let decoded: Result<AnyMessage<'_>, _> =
+            (header.msg_type(), copy.as_mut_slice()).try_into();
+        let (message, error, payload) = match decoded {
+            Ok(message) => (Some(format!("{:?}", message)), None, None),
+            Err(e) => (None, Some(format!("{:?}", e)), Some(hex(payload))),
+        };
+        Some(Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis())
+                .unwrap_or_default(),
+            connection,
+            direction,
+            message_type: header.msg_type(),
+            message_name: message_type_name(header.msg_type()),
+            extension_type: header.ext_type(),
+            channel_msg: header.channel_msg(),
+            length,
+            message,
+            error,
+            payload,
+        })
+    }
+
+    /// The record as a line, without the line break
+    pub fn to_line(&self, format: Format) -> String {
+        match format {
+            Format::Jsonl => serde_json::to_string(self).unwrap_or_else(|e| {
+                format!("{{\"error\":\"the record can not be serialized: {}\"}}", e)
+            }),
+            Format::Text => {
+                let arrow = match self.direction {
+                    Direction::ToUpstream => "downstream -> upstream",
+                    Direction::ToDownstream => "upstream -> downstream",
+                };
+                let mut line = format!(
+                    "{} #{} {} {}(0x{:02x}) ext=0x{:04x} len={}",
+                    self.timestamp,
+                    self.connection,
+                    arrow,
+                    self.message_name.unwrap_or("Unknown"),
+                    self.message_type,
+                    self.extension_type,
+                    self.length,
+                );
+                if let Some(message) = &self.message {
+                    let _ = write!(line, " {}", message);
+                }
+                if let Some(error) = &self.error {
+                    let _ = write!(line, " error={}", error);
+                }
+                if let Some(payload) = &self.payload {
+                    let _ = write!(line, " payload={}", payload);
+                }
+                line
+            }
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codec_sv2::StandardSv2Frame;
+    use roles_logic_sv2::{
+        common_messages_sv2::SetupConnectionSuccess,
+        parsers::{CommonMessages, PoolMessages},
+    };
+
+    // Frames are received serialized, as the decoder of a connection outputs them. The buffer is
+    // a `Vec` or a `Slice` depending on the `with_buffer_pool` feature of codec_sv2.
+    #[allow(clippy::useless_conversion)]
+    fn from_bytes(bytes: Vec<u8>) -> StandardEitherFrame<AnyMessage<'static>> {
+        Frame::Sv2(StandardSv2Frame::from_bytes(bytes.into()).unwrap())
+    }
+
+    #[test]
+    fn test_decoded_frame() {
+        let message = PoolMessages::Common(CommonMessages::SetupConnectionSuccess(
+            SetupConnectionSuccess {
+                used_version: 2,
+                flags: 0,
+            },
+        ));
+        let frame = StandardSv2Frame::from_message(message, 0x01, 0, false).unwrap();
+        let mut bytes = vec![0; frame.encoded_length()];
+        frame.serialize(&mut bytes).unwrap();
+        let mut frame = from_bytes(bytes);
+        let record = Record::from_frame(3, Direction::ToDownstream, &mut frame).unwrap();
+        assert_eq!(record.message_name, Some("SetupConnectionSuccess"));
+        assert_eq!(record.length, 6);
+        assert!(record.error.is_none());
+
+        let line = record.to_line(Format::Text);
+        assert!(line.contains("#3 upstream -> downstream SetupConnectionSuccess(0x01)"));
+        let json: serde_json::Value = serde_json::from_str(&record.to_line(Format::Jsonl)).unwrap();
+        assert_eq!(json["direction"], "to_downstream");
+        assert_eq!(json["message_type"], 1);
+        assert!(json.get("payload").is_none());
+    }
+
+    #[test]
+    fn test_undecodable_frame_is_dumped() {
+        let mut bytes = vec![0x00, 0x00, 0xfe, 0x02, 0x00, 0x00, 0xaa, 0xbb];
+        let mut frame = from_bytes(bytes.clone());
+        let record = Record::from_frame(0, Direction::ToUpstream, &mut frame).unwrap();
+        assert_eq!(record.message_name, None);
+        assert!(record.error.is_some());
+        assert_eq!(record.payload.as_deref(), Some("aabb"));
+        // the frame is relayed as it was received
+        if let Frame::Sv2(frame) = &mut frame {
+            assert_eq!(frame.payload(), &mut bytes[6..]);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use clap::Parser;
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use roles_logic_sv2::utils::Mutex;
+use std::{fs::File, io::Write, net::SocketAddr, sync::Arc, time::Duration};
+use sv2_sniffer::{
+    pcap::{Packet, PcapReader, PcapWriter},
+    trace::Format,
+    AuthorityKeys, ReplayOptions, Transport,
+};
+use tracing::error;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[arg(
+        short,
+        long,
+        help = "Address where the downstream connects, ip:port",
+        required_unless_present = "replay"
+    )]
+    listen: Option<SocketAddr>,
+    #[arg(short, long, help = "Address of the upstream, ip:port")]
+    upstream: SocketAddr,
+    #[arg(
+        long,
+        help = "Relay plaintext connections, without noise on both sides"
+    )]
+    plaintext: bool,
+    #[arg(
+        long,
+        help = "Authority public key of the sniffer, to be configured in the downstream",
+        required_unless_present_any = ["plaintext", "replay"]
+    )]
+    authority_public_key: Option<Secp256k1PublicKey>,
+    #[arg(
+        long,
+        help = "Authority secret key of the sniffer",
+        required_unless_present_any = ["plaintext", "replay"]
+    )]
+    authority_secret_key: Option<Secp256k1SecretKey>,
+    #[arg(
+        long,
+        help = "Validity in seconds of the certificate sent to the downstream",
+        default_value = "3600"
+    )]
+    cert_validity_sec: u64,
+    #[arg(
+        long,
+        help = "Authority public key of the upstream, when left empty the upstream certificate is not checked"
+    )]
+    upstream_authority_public_key: Option<Secp256k1PublicKey>,
+    #[arg(
+        short,
+        long,
+        help = "Format of the trace: text or jsonl",
+        default_value = "text"
+    )]
+    format: Format,
+    #[arg(
+        short,
+        long,
+        help = "File where the trace is appended, when left empty it is printed to stdout"
+    )]
+    output: Option<String>,
+    #[arg(long, help = "Also write the decrypted frames to this pcap file")]
+    pcap: Option<String>,
+    #[arg(
+        long,
+        help = "Instead of relaying, send to the upstream the frames that a downstream sent in this pcap file"
+    )]
+    replay: Option<String>,
+    #[arg(
+        long,
+        help = "Id of the connection of the capture to replay",
+        default_value = "0"
+    )]
+    replay_connection: u32,
+    #[arg(
+        long,
+        help = "Wait between the replayed frames as much as in the capture"
+    )]
+    replay_timing: bool,
+    #[arg(
+        long,
+        help = "Seconds the frames of the upstream are printed after the last frame is replayed",
+        default_value = "5"
+    )]
+    replay_wait_sec: u64,
+}
+
+fn exit_with(message: String) -> ! {
+    error!("{}", message);
+    std::process::exit(1)
+}
+
+fn create_file(path: &str) -> File {
+    File::options()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap_or_else(|e| exit_with(format!("Failed to open {}: {}", path, e)))
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    logging_sv2::init();
+    let transport = match args.plaintext {
+        true => Transport::Plaintext,
+        false => Transport::Noise {
+            authority: args
+                .authority_public_key
+                .zip(args.authority_secret_key)
+                .map(|(public_key, secret_key)| AuthorityKeys {
+                    public_key,
+                    secret_key,
+                    cert_validity: Duration::from_secs(args.cert_validity_sec),
+                }),
+            upstream_authority_public_key: args.upstream_authority_public_key,
+        },
+    };
+    let output: Box<dyn Write + Send> = match &args.output {
+        Some(path) => Box::new(create_file(path)),
+        None => Box::new(std::io::stdout()),
+    };
+    let output = Arc::new(Mutex::new(output));
+
+    if let Some(path) = &args.replay {
+        let file = File::open(path)
+            .unwrap_or_else(|e| exit_with(format!("Failed to open {}: {}", path, e)));
+        let packets: Vec<Packet> = PcapReader::new(std::io::BufReader::new(file))
+            .and_then(|reader| reader.collect())
+            .unwrap_or_else(|e| exit_with(format!("Failed to read {}: {}", path, e)));
+        let options = ReplayOptions {
+            connection: args.replay_connection,
+            keep_timing: args.replay_timing,
+            wait: Duration::from_secs(args.replay_wait_sec),
+        };
+        if let Err(e) = sv2_sniffer::replay(
+            args.upstream,
+            transport,
+            packets,
+            options,
+            args.format,
+            output,
+        )
+        .await
+        {
+            exit_with(e);
+        }
+        return;
+    }
+
+    // A new capture is started every time, appending to an old one would repeat its header
+    let capture = args.pcap.as_ref().map(|path| {
+        let file: Box<dyn Write + Send> = Box::new(
+            File::create(path)
+                .unwrap_or_else(|e| exit_with(format!("Failed to create {}: {}", path, e))),
+        );
+        let writer = PcapWriter::new(file)
+            .unwrap_or_else(|e| exit_with(format!("Failed to write {}: {}", path, e)));
+        Arc::new(Mutex::new(writer))
+    });
+    // Is safe to unwrap, clap requires listen when there is nothing to replay
+    let listen = args.listen.unwrap();
+    if let Err(e) = sv2_sniffer::run(
+        listen,
+        args.upstream,
+        transport,
+        args.format,
+        output,
+        capture,
+    )
+    .await
+    {
+        exit_with(e.to_string());
+    }
+}
@@ -487,3 +487,18 @@ pub async fn start_mining_device_sv1(upstream_addr: SocketAddr) {
     });
     tokio::time::sleep(std::time::Duration::from_secs(3)).await;
 }
+
+/// Mines on `upstream_addr` with the sv2 CPU miner until `shares` shares are accepted
+pub async fn start_mining_device(upstream_addr: SocketAddr, shares: u32) {
+    mining_device::connect(
+        upstream_addr.to_string(),
+        None,
+        None,
+        None,
+        0,
+        // advertises a low hashrate so that the upstream sets a low difficulty
+        Some(0.01),
+        Some(shares),
+    )
+    .await;
+}
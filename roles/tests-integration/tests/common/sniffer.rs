@@ -5,17 +5,7 @@ use codec_sv2::{
 use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
 use network_helpers_sv2::noise_connection_tokio::Connection;
 use roles_logic_sv2::{
-    parsers::{
-        AnyMessage, CommonMessages,
-        JobDeclaration::{
-            AllocateMiningJobToken, AllocateMiningJobTokenSuccess, DeclareMiningJob,
-            DeclareMiningJobError, DeclareMiningJobSuccess, IdentifyTransactions,
-            IdentifyTransactionsSuccess, ProvideMissingTransactions,
-            ProvideMissingTransactionsSuccess, SubmitSolution,
-        },
-        PoolMessages,
-        TemplateDistribution::{self, CoinbaseOutputDataSize},
-    },
+    parsers::{AnyMessage, PoolMessages},
     utils::Mutex,
 };
 use std::{collections::VecDeque, convert::TryInto, net::SocketAddr, sync::Arc};
@@ -307,7 +297,7 @@ impl Sniffer {
                         (message_type, payload.as_mut_slice()).try_into();
                     match message {
                         Ok(message) => {
-                            let message = Self::into_static(message);
+                            let message = message.into_static();
                             (message_type, message)
                         }
                         _ => {
@@ -329,85 +319,6 @@ impl Sniffer {
         }
     }
 
-    fn into_static(m: AnyMessage<'_>) -> AnyMessage<'static> {
-        match m {
-            AnyMessage::Mining(m) => AnyMessage::Mining(m.into_static()),
-            AnyMessage::Common(m) => match m {
-                CommonMessages::ChannelEndpointChanged(m) => {
-                    AnyMessage::Common(CommonMessages::ChannelEndpointChanged(m.into_static()))
-                }
-                CommonMessages::SetupConnection(m) => {
-                    AnyMessage::Common(CommonMessages::SetupConnection(m.into_static()))
-                }
-                CommonMessages::SetupConnectionError(m) => {
-                    AnyMessage::Common(CommonMessages::SetupConnectionError(m.into_static()))
-                }
-                CommonMessages::SetupConnectionSuccess(m) => {
-                    AnyMessage::Common(CommonMessages::SetupConnectionSuccess(m.into_static()))
-                }
-            },
-            AnyMessage::JobDeclaration(m) => match m {
-                AllocateMiningJobToken(m) => {
-                    AnyMessage::JobDeclaration(AllocateMiningJobToken(m.into_static()))
-                }
-                AllocateMiningJobTokenSuccess(m) => {
-                    AnyMessage::JobDeclaration(AllocateMiningJobTokenSuccess(m.into_static()))
-                }
-                DeclareMiningJob(m) => {
-                    AnyMessage::JobDeclaration(DeclareMiningJob(m.into_static()))
-                }
-                DeclareMiningJobError(m) => {
-                    AnyMessage::JobDeclaration(DeclareMiningJobError(m.into_static()))
-                }
-                DeclareMiningJobSuccess(m) => {
-                    AnyMessage::JobDeclaration(DeclareMiningJobSuccess(m.into_static()))
-                }
-                IdentifyTransactions(m) => {
-                    AnyMessage::JobDeclaration(IdentifyTransactions(m.into_static()))
-                }
-                IdentifyTransactionsSuccess(m) => {
-                    AnyMessage::JobDeclaration(IdentifyTransactionsSuccess(m.into_static()))
-                }
-                ProvideMissingTransactions(m) => {
-                    AnyMessage::JobDeclaration(ProvideMissingTransactions(m.into_static()))
-                }
-                ProvideMissingTransactionsSuccess(m) => {
-                    AnyMessage::JobDeclaration(ProvideMissingTransactionsSuccess(m.into_static()))
-                }
-                SubmitSolution(m) => AnyMessage::JobDeclaration(SubmitSolution(m.into_static())),
-            },
-            AnyMessage::TemplateDistribution(m) => match m {
-                CoinbaseOutputDataSize(m) => {
-                    AnyMessage::TemplateDistribution(CoinbaseOutputDataSize(m.into_static()))
-                }
-                TemplateDistribution::NewTemplate(m) => AnyMessage::TemplateDistribution(
-                    TemplateDistribution::NewTemplate(m.into_static()),
-                ),
-                TemplateDistribution::RequestTransactionData(m) => {
-                    AnyMessage::TemplateDistribution(TemplateDistribution::RequestTransactionData(
-                        m.into_static(),
-                    ))
-                }
-                TemplateDistribution::RequestTransactionDataError(m) => {
-                    AnyMessage::TemplateDistribution(
-                        TemplateDistribution::RequestTransactionDataError(m.into_static()),
-                    )
-                }
-                TemplateDistribution::RequestTransactionDataSuccess(m) => {
-                    AnyMessage::TemplateDistribution(
-                        TemplateDistribution::RequestTransactionDataSuccess(m.into_static()),
-                    )
-                }
-                TemplateDistribution::SetNewPrevHash(m) => AnyMessage::TemplateDistribution(
-                    TemplateDistribution::SetNewPrevHash(m.into_static()),
-                ),
-                TemplateDistribution::SubmitSolution(m) => AnyMessage::TemplateDistribution(
-                    TemplateDistribution::SubmitSolution(m.into_static()),
-                ),
-            },
-        }
-    }
-
     async fn wait_for_client(client: SocketAddr) -> TcpStream {
         let listner = TcpListener::bind(client)
             .await
@@ -94,3 +94,19 @@ async fn test_sniffer_interrupter() {
     assert_common_message!(&sniffer.next_message_from_downstream(), SetupConnection);
     assert_common_message!(&sniffer.next_message_from_upstream(), SetupConnectionError);
 }
+
+// This test starts a Template Provider, a Pool and a sv2 CPU miner, and checks that the shares
+// mined by the CPU miner are accepted by the Pool.
+#[tokio::test]
+async fn mining_device_shares_are_accepted() {
+    let tp_addr = common::get_available_address();
+    let pool_addr = common::get_available_address();
+    let _tp = common::start_template_provider(tp_addr.port()).await;
+    let _ = common::start_pool(Some(pool_addr), Some(tp_addr)).await;
+    tokio::time::timeout(
+        std::time::Duration::from_secs(60),
+        common::start_mining_device(pool_addr, 3),
+    )
+    .await
+    .expect("the shares of the mining device were not accepted in time");
+}
@@ -25,8 +25,8 @@ impl Args {
         let cli_args = std::env::args();
 
         if cli_args.len() == 1 {
-            println!("Using default config path: {}", Self::DEFAULT_CONFIG_PATH);
-            println!("{}\n", Self::HELP_MSG);
+            tracing::info!("Using default config path: {}", Self::DEFAULT_CONFIG_PATH);
+            tracing::info!("{}", Self::HELP_MSG);
         }
 
         let config_path = cli_args
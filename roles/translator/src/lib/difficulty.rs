@@ -0,0 +1,175 @@
+//! Difficulty of the SV1 downstreams.
+//!
+//! SV2 carries targets while SV1 carries difficulties. [`difficulty_from_target`] and
+//! [`set_difficulty`] convert the target of a channel for `mining.set_difficulty`,
+//! [`update_miner_hashrate`] estimates the hashrate of a miner from the shares it submitted so that
+//! its difficulty converges to `shares_per_minute`. These functions do not read the clock nor do
+//! any IO, the `Downstream` of the proxy calls them on every share.
+use super::{error::ProxyResult, proxy_config::DownstreamDifficultyConfig};
+use std::ops::Div;
+use stratum_common::bitcoin::util::uint::Uint256;
+use v1::json_rpc;
+
+/// Converts a little endian SV2 target into the difficulty sent via the SV1
+/// `mining.set_difficulty` message.
+#[allow(clippy::result_large_err)]
+pub fn difficulty_from_target(mut target: Vec<u8>) -> ProxyResult<'static, f64> {
+    // reverse because target is LE and this function relies on BE
+    target.reverse();
+    let target = target.as_slice();
+    tracing::debug!("Target: {:?}", target);
+
+    // If received target is 0, return 0
+    if is_zero(target) {
+        return Ok(0.0);
+    }
+    let target = Uint256::from_be_slice(target)?;
+    let pdiff: [u8; 32] = [
+        0, 0, 0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
+        255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
+    ];
+    let pdiff = Uint256::from_be_bytes(pdiff);
+
+    if pdiff > target {
+        let diff = pdiff.div(target);
+        Ok(diff.low_u64() as f64)
+    } else {
+        let diff = target.div(pdiff);
+        let diff = diff.low_u64() as f64;
+        // TODO still results in a difficulty that is too low
+        Ok(1.0 / diff)
+    }
+}
+
+/// Creates the SV1 `mining.set_difficulty` message for a little endian SV2 target.
+#[allow(clippy::result_large_err)]
+pub fn set_difficulty(target: Vec<u8>) -> ProxyResult<'static, json_rpc::Message> {
+    let value = difficulty_from_target(target)?;
+    tracing::debug!("Difficulty from target: {:?}", value);
+    let set_target = v1::methods::server_to_client::SetDifficulty { value };
+    let message: json_rpc::Message = set_target.into();
+    Ok(message)
+}
+
+/// Updates the hashrate of a miner and resets the difficulty management params. The realized
+/// shares per minute are calculated from the number of shares submitted and the delta time since
+/// the last update, the hashrate is then estimated from them and the target those shares were
+/// mined on with [`roles_logic_sv2::utils::hash_rate_from_target`].
+///
+/// Returns the new hashrate and its difference with the previous one, used to adjust the
+/// `channel_nominal_hashrate`, or None if the hashrate is not updated.
+#[allow(clippy::result_large_err)]
+pub fn update_miner_hashrate(
+    diff_mgmt: &mut DownstreamDifficultyConfig,
+    miner_target: Vec<u8>,
+    now_secs: u64,
+) -> ProxyResult<'static, Option<(f32, f32)>> {
+    // reset if timestamp is at 0
+    if diff_mgmt.timestamp_of_last_update == 0 {
+        diff_mgmt.timestamp_of_last_update = now_secs;
+        diff_mgmt.submits_since_last_update = 0;
+        return Ok(None);
+    }
+
+    let delta_time = now_secs - diff_mgmt.timestamp_of_last_update;
+    #[cfg(test)]
+    if delta_time == 0 {
+        return Ok(None);
+    }
+    #[cfg(not(test))]
+    if delta_time <= 15 {
+        return Ok(None);
+    }
+    tracing::debug!("\nDELTA TIME: {:?}", delta_time);
+    let realized_share_per_min =
+        diff_mgmt.submits_since_last_update as f64 / (delta_time as f64 / 60.0);
+    tracing::debug!("\nREALIZED SHARES PER MINUTE {:?}", realized_share_per_min);
+    let mut new_miner_hashrate = match roles_logic_sv2::utils::hash_rate_from_target(
+        miner_target.try_into()?,
+        realized_share_per_min,
+    ) {
+        Ok(hashrate) => hashrate as f32,
+        Err(e) => {
+            tracing::debug!("{:?} -> Probably min_individual_miner_hashrate parameter was not set properly in config file. New hashrate will be automatically adjusted to match the real one.", e);
+            diff_mgmt.min_individual_miner_hashrate * realized_share_per_min as f32
+                / diff_mgmt.shares_per_minute
+        }
+    };
+
+    let mut hashrate_delta = new_miner_hashrate - diff_mgmt.min_individual_miner_hashrate;
+    let hashrate_delta_percentage =
+        (hashrate_delta.abs() / diff_mgmt.min_individual_miner_hashrate) * 100.0;
+    tracing::debug!("\nMINER HASHRATE: {:?}", new_miner_hashrate);
+
+    if (hashrate_delta_percentage >= 100.0)
+        || (hashrate_delta_percentage >= 60.0) && (delta_time >= 60)
+        || (hashrate_delta_percentage >= 50.0) && (delta_time >= 120)
+        || (hashrate_delta_percentage >= 45.0) && (delta_time >= 180)
+        || (hashrate_delta_percentage >= 30.0) && (delta_time >= 240)
+        || (hashrate_delta_percentage >= 15.0) && (delta_time >= 300)
+    {
+        // realized_share_per_min is 0.0 when submits_since_last_update is 0 so it's safe to
+        // compare realized_share_per_min with == 0.0
+        if realized_share_per_min == 0.0 {
+            new_miner_hashrate = match delta_time {
+                dt if dt <= 30 => diff_mgmt.min_individual_miner_hashrate / 1.5,
+                dt if dt < 60 => diff_mgmt.min_individual_miner_hashrate / 2.0,
+                _ => diff_mgmt.min_individual_miner_hashrate / 3.0,
+            };
+            hashrate_delta = new_miner_hashrate - diff_mgmt.min_individual_miner_hashrate;
+        }
+        if (realized_share_per_min > 0.0) && (hashrate_delta_percentage > 1000.0) {
+            new_miner_hashrate = match delta_time {
+                dt if dt <= 30 => diff_mgmt.min_individual_miner_hashrate * 10.0,
+                dt if dt < 60 => diff_mgmt.min_individual_miner_hashrate * 5.0,
+                _ => diff_mgmt.min_individual_miner_hashrate * 3.0,
+            };
+            hashrate_delta = new_miner_hashrate - diff_mgmt.min_individual_miner_hashrate;
+        }
+        diff_mgmt.min_individual_miner_hashrate = new_miner_hashrate;
+        diff_mgmt.timestamp_of_last_update = now_secs;
+        diff_mgmt.submits_since_last_update = 0;
+        Ok(Some((new_miner_hashrate, hashrate_delta)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Helper function to check if target is set to zero for some reason (typically happens when
+/// Downstream role first connects).
+/// https://stackoverflow.com/questions/65367552/checking-a-vecu8-to-see-if-its-all-zero
+fn is_zero(buf: &[u8]) -> bool {
+    let (prefix, aligned, suffix) = unsafe { buf.align_to::<u128>() };
+
+    prefix.iter().all(|&x| x == 0)
+        && suffix.iter().all(|&x| x == 0)
+        && aligned.iter().all(|&x| x == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_miner_hashrate() {
+        let mut diff_mgmt = DownstreamDifficultyConfig::new(1_000.0, 6.0, 0, 0);
+        let target = roles_logic_sv2::utils::hash_rate_to_target(1_000.0, 6.0)
+            .unwrap()
+            .to_vec();
+        // The first call only starts the measurement
+        assert!(update_miner_hashrate(&mut diff_mgmt, target.clone(), 1_000)
+            .unwrap()
+            .is_none());
+        assert_eq!(diff_mgmt.timestamp_of_last_update, 1_000);
+
+        // No share in 60 seconds, the hashrate is divided by 3
+        let (hashrate, delta) = update_miner_hashrate(&mut diff_mgmt, target, 1_060)
+            .unwrap()
+            .unwrap();
+        assert!((hashrate - 1_000.0 / 3.0).abs() < 0.01);
+        assert!((delta - (hashrate - 1_000.0)).abs() < 0.01);
+        assert_eq!(diff_mgmt.min_individual_miner_hashrate, hashrate);
+        assert_eq!(diff_mgmt.timestamp_of_last_update, 1_060);
+        assert_eq!(diff_mgmt.submits_since_last_update, 0);
+    }
+}
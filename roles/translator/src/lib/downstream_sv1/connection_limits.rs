@@ -0,0 +1,186 @@
+//! Limits of the SV1 downstream connections.
+//!
+//! A connection is refused when the proxy already has `max_connections` connections, when its ip
+//! already has `max_connections_per_ip` connections or when its ip opened more than
+//! `max_new_connections_per_ip_per_min` connections in the last minute. The refused connections
+//! count towards the rate, so an ip can not get around it by retrying.
+use crate::proxy_config::ConnectionLimitsConfig;
+use roles_logic_sv2::utils::Mutex;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// Limit that made a connection be refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    MaxConnections,
+    MaxConnectionsPerIp,
+    MaxNewConnectionsPerIpPerMin,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::MaxConnections => write!(f, "max_connections"),
+            Limit::MaxConnectionsPerIp => write!(f, "max_connections_per_ip"),
+            Limit::MaxNewConnectionsPerIpPerMin => write!(f, "max_new_connections_per_ip_per_min"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    config: ConnectionLimitsConfig,
+    connections: usize,
+    connections_per_ip: HashMap<IpAddr, usize>,
+    // ip -> times of the connections opened in the last minute
+    new_connections: HashMap<IpAddr, VecDeque<Instant>>,
+}
+
+impl ConnectionLimiter {
+    pub fn new(config: ConnectionLimitsConfig) -> Self {
+        Self {
+            config,
+            connections: 0,
+            connections_per_ip: HashMap::new(),
+            new_connections: HashMap::new(),
+        }
+    }
+
+    pub fn unauthorized_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.unauthorized_timeout_sec)
+    }
+
+    /// Counts a new connection from `ip`, it is released when the returned guard is dropped
+    pub fn on_connection(self_: &Arc<Mutex<Self>>, ip: IpAddr) -> Result<ConnectionGuard, Limit> {
+        self_
+            .safe_lock(|s| s.on_connection_at(ip, Instant::now()))
+            .unwrap_or(Ok(()))?;
+        Ok(ConnectionGuard {
+            limiter: self_.clone(),
+            ip,
+        })
+    }
+
+    fn on_connection_at(&mut self, ip: IpAddr, now: Instant) -> Result<(), Limit> {
+        self.new_connections.retain(|_, times| {
+            while let Some(time) = times.front() {
+                if now.saturating_duration_since(*time) < MINUTE {
+                    break;
+                }
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        let recent = self.new_connections.entry(ip).or_default();
+        recent.push_back(now);
+        if let Some(max) = self.config.max_new_connections_per_ip_per_min {
+            if recent.len() > max as usize {
+                return Err(Limit::MaxNewConnectionsPerIpPerMin);
+            }
+        }
+        if let Some(max) = self.config.max_connections {
+            if self.connections >= max {
+                return Err(Limit::MaxConnections);
+            }
+        }
+        let per_ip = self.connections_per_ip.entry(ip).or_insert(0);
+        if let Some(max) = self.config.max_connections_per_ip {
+            if *per_ip >= max {
+                return Err(Limit::MaxConnectionsPerIp);
+            }
+        }
+        *per_ip += 1;
+        self.connections += 1;
+        Ok(())
+    }
+
+    fn release(&mut self, ip: IpAddr) {
+        self.connections = self.connections.saturating_sub(1);
+        if let Some(per_ip) = self.connections_per_ip.get_mut(&ip) {
+            *per_ip = per_ip.saturating_sub(1);
+            if *per_ip == 0 {
+                self.connections_per_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+/// Held by a downstream for as long as it is connected
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    limiter: Arc<Mutex<ConnectionLimiter>>,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let ip = self.ip;
+        let _ = self.limiter.safe_lock(|l| l.release(ip));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ConnectionLimitsConfig {
+        ConnectionLimitsConfig {
+            max_connections: Some(3),
+            max_connections_per_ip: Some(2),
+            max_new_connections_per_ip_per_min: Some(4),
+            unauthorized_timeout_sec: 10,
+        }
+    }
+
+    #[test]
+    fn test_connection_caps() {
+        let limiter = Arc::new(Mutex::new(ConnectionLimiter::new(config())));
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+        let c: IpAddr = "10.0.0.3".parse().unwrap();
+
+        let a_1 = ConnectionLimiter::on_connection(&limiter, a).unwrap();
+        let _a_2 = ConnectionLimiter::on_connection(&limiter, a).unwrap();
+        assert_eq!(
+            ConnectionLimiter::on_connection(&limiter, a).unwrap_err(),
+            Limit::MaxConnectionsPerIp
+        );
+        let _b_1 = ConnectionLimiter::on_connection(&limiter, b).unwrap();
+        assert_eq!(
+            ConnectionLimiter::on_connection(&limiter, c).unwrap_err(),
+            Limit::MaxConnections
+        );
+
+        // a connection that is closed frees its slot
+        drop(a_1);
+        let _c_1 = ConnectionLimiter::on_connection(&limiter, c).unwrap();
+    }
+
+    #[test]
+    fn test_new_connections_rate() {
+        let mut limiter = ConnectionLimiter::new(ConnectionLimitsConfig {
+            max_connections_per_ip: None,
+            ..config()
+        });
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+        for _ in 0..3 {
+            limiter.on_connection_at(a, now).unwrap();
+            limiter.release(a);
+        }
+        limiter.on_connection_at(a, now).unwrap();
+        assert_eq!(
+            limiter.on_connection_at(a, now + Duration::from_secs(30)),
+            Err(Limit::MaxNewConnectionsPerIpPerMin)
+        );
+        // the first connections are out of the window
+        assert_eq!(limiter.on_connection_at(a, now + MINUTE), Ok(()));
+    }
+}
@@ -1,12 +1,13 @@
 use super::{Downstream, DownstreamMessages, SetDownstreamTarget};
 
-use super::super::error::{Error, ProxyResult};
+use super::super::{
+    difficulty,
+    error::{Error, ProxyResult},
+};
 use roles_logic_sv2::utils::Mutex;
-use std::{ops::Div, sync::Arc};
+use std::sync::Arc;
 use v1::json_rpc;
 
-use stratum_common::bitcoin::util::uint::Uint256;
-
 impl Downstream {
     /// initializes the timestamp and resets the number of submits for a connection.
     /// Should only be called once for the lifetime of a connection since
@@ -155,51 +156,11 @@ impl Downstream {
     /// be sent to the Downstream role.
     #[allow(clippy::result_large_err)]
     pub(super) fn get_set_difficulty(target: Vec<u8>) -> ProxyResult<'static, json_rpc::Message> {
-        let value = Downstream::difficulty_from_target(target)?;
-        tracing::debug!("Difficulty from target: {:?}", value);
-        let set_target = v1::methods::server_to_client::SetDifficulty { value };
-        let message: json_rpc::Message = set_target.into();
-        Ok(message)
+        difficulty::set_difficulty(target)
     }
 
-    /// Converts target received by the `SetTarget` SV2 message from the Upstream role into the
-    /// difficulty for the Downstream role sent via the SV1 `mining.set_difficulty` message.
-    #[allow(clippy::result_large_err)]
-    pub(super) fn difficulty_from_target(mut target: Vec<u8>) -> ProxyResult<'static, f64> {
-        // reverse because target is LE and this function relies on BE
-        target.reverse();
-        let target = target.as_slice();
-        tracing::debug!("Target: {:?}", target);
-
-        // If received target is 0, return 0
-        if Downstream::is_zero(target) {
-            return Ok(0.0);
-        }
-        let target = Uint256::from_be_slice(target)?;
-        let pdiff: [u8; 32] = [
-            0, 0, 0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-            255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-        ];
-        let pdiff = Uint256::from_be_bytes(pdiff);
-
-        if pdiff > target {
-            let diff = pdiff.div(target);
-            Ok(diff.low_u64() as f64)
-        } else {
-            let diff = target.div(pdiff);
-            let diff = diff.low_u64() as f64;
-            // TODO still results in a difficulty that is too low
-            Ok(1.0 / diff)
-        }
-    }
-
-    /// This function updates the miner hashrate and resets difficulty management params. To
-    /// calculate hashrate it calculates the realized shares per minute from the number of shares
-    /// submitted and the delta time since last update. It then uses the realized shares per
-    /// minute and the target those shares where mined on to calculate an estimated hashrate during
-    /// that period with the function [`roles_logic_sv2::utils::hash_rate_from_target`]. Lastly,
-    /// it adjusts the `channel_nominal_hashrate` according to the change in estimated miner
-    /// hashrate
+    /// Updates the miner hashrate with [`difficulty::update_miner_hashrate`] and adjusts the
+    /// `channel_nominal_hashrate` according to the change in estimated miner hashrate
     #[allow(clippy::result_large_err)]
     pub fn update_miner_hashrate(
         self_: Arc<Mutex<Self>>,
@@ -211,100 +172,24 @@ impl Downstream {
                     .duration_since(std::time::UNIX_EPOCH)
                     .expect("time went backwards")
                     .as_secs();
-
-                // reset if timestamp is at 0
-                if d.difficulty_mgmt.timestamp_of_last_update == 0 {
-                    d.difficulty_mgmt.timestamp_of_last_update = timestamp_secs;
-                    d.difficulty_mgmt.submits_since_last_update = 0;
-                    return Ok(None);
-                }
-
-                let delta_time = timestamp_secs - d.difficulty_mgmt.timestamp_of_last_update;
-                #[cfg(test)]
-                if delta_time == 0 {
-                    return Ok(None);
-                }
-                #[cfg(not(test))]
-                if delta_time <= 15 {
-                    return Ok(None);
-                }
-                tracing::debug!("\nDELTA TIME: {:?}", delta_time);
-                let realized_share_per_min =
-                    d.difficulty_mgmt.submits_since_last_update as f64 / (delta_time as f64 / 60.0);
-                tracing::debug!("\nREALIZED SHARES PER MINUTE {:?}", realized_share_per_min);
-                let mut new_miner_hashrate = match roles_logic_sv2::utils::hash_rate_from_target(
-                    miner_target.clone().try_into()?,
-                    realized_share_per_min,
-                ) {
-                    Ok(hashrate) => hashrate as f32,
-                    Err(e) => {
-                        tracing::debug!("{:?} -> Probably min_individual_miner_hashrate parameter was not set properly in config file. New hashrate will be automatically adjusted to match the real one.", e);
-                        d.difficulty_mgmt.min_individual_miner_hashrate * realized_share_per_min as f32 / d.difficulty_mgmt.shares_per_minute
-                    }
-                };
-
-                let mut hashrate_delta =
-                    new_miner_hashrate - d.difficulty_mgmt.min_individual_miner_hashrate;
-                let hashrate_delta_percentage = (hashrate_delta.abs()
-                    / d.difficulty_mgmt.min_individual_miner_hashrate)
-                    * 100.0;
-                tracing::debug!("\nMINER HASHRATE: {:?}", new_miner_hashrate);
-
-                if (hashrate_delta_percentage >= 100.0)
-                    || (hashrate_delta_percentage >= 60.0) && (delta_time >= 60)
-                    || (hashrate_delta_percentage >= 50.0) && (delta_time >= 120)
-                    || (hashrate_delta_percentage >= 45.0) && (delta_time >= 180)
-                    || (hashrate_delta_percentage >= 30.0) && (delta_time >= 240)
-                    || (hashrate_delta_percentage >= 15.0) && (delta_time >= 300)
-                {
-                // realized_share_per_min is 0.0 when d.difficulty_mgmt.submits_since_last_update is 0
-                // so it's safe to compare realized_share_per_min with == 0.0
-                if realized_share_per_min == 0.0 {
-                    new_miner_hashrate = match delta_time {
-                        dt if dt <= 30 => d.difficulty_mgmt.min_individual_miner_hashrate / 1.5,
-                        dt if dt < 60 => d.difficulty_mgmt.min_individual_miner_hashrate / 2.0,
-                        _ => d.difficulty_mgmt.min_individual_miner_hashrate / 3.0,
-                    };
-                    hashrate_delta =
-                        new_miner_hashrate - d.difficulty_mgmt.min_individual_miner_hashrate;
-                }
-                if (realized_share_per_min > 0.0) && (hashrate_delta_percentage > 1000.0) {
-                    new_miner_hashrate = match delta_time {
-                        dt if dt <= 30 => d.difficulty_mgmt.min_individual_miner_hashrate * 10.0,
-                        dt if dt < 60 => d.difficulty_mgmt.min_individual_miner_hashrate * 5.0,
-                        _ => d.difficulty_mgmt.min_individual_miner_hashrate * 3.0,
-                    };
-                    hashrate_delta =
-                        new_miner_hashrate - d.difficulty_mgmt.min_individual_miner_hashrate;
-                }
-                d.difficulty_mgmt.min_individual_miner_hashrate = new_miner_hashrate;
-                d.difficulty_mgmt.timestamp_of_last_update = timestamp_secs;
-                d.difficulty_mgmt.submits_since_last_update = 0;
-                d.upstream_difficulty_config.super_safe_lock(|c| {
-                    if c.channel_nominal_hashrate + hashrate_delta > 0.0 {
-                        c.channel_nominal_hashrate += hashrate_delta;
-                    } else {
-                        c.channel_nominal_hashrate = 0.0;
-                    }
-                });
-                Ok(Some(new_miner_hashrate))
-                } else {
-                    Ok(None)
-                }
+                let update = difficulty::update_miner_hashrate(
+                    &mut d.difficulty_mgmt,
+                    miner_target,
+                    timestamp_secs,
+                )?;
+                Ok(update.map(|(new_miner_hashrate, hashrate_delta)| {
+                    d.upstream_difficulty_config.super_safe_lock(|c| {
+                        if c.channel_nominal_hashrate + hashrate_delta > 0.0 {
+                            c.channel_nominal_hashrate += hashrate_delta;
+                        } else {
+                            c.channel_nominal_hashrate = 0.0;
+                        }
+                    });
+                    new_miner_hashrate
+                }))
             })
             .map_err(|_e| Error::PoisonLock)?
     }
-
-    /// Helper function to check if target is set to zero for some reason (typically happens when
-    /// Downstream role first connects).
-    /// https://stackoverflow.com/questions/65367552/checking-a-vecu8-to-see-if-its-all-zero
-    fn is_zero(buf: &[u8]) -> bool {
-        let (prefix, aligned, suffix) = unsafe { buf.align_to::<u128>() };
-
-        prefix.iter().all(|&x| x == 0)
-            && suffix.iter().all(|&x| x == 0)
-            && aligned.iter().all(|&x| x == 0)
-    }
 }
 
 #[cfg(test)]
@@ -321,6 +206,7 @@ mod test {
     };
 
     use crate::downstream_sv1::Downstream;
+    use v1::Sv1ServerSession;
 
     #[ignore] // as described in issue #988
     #[test]
@@ -433,17 +319,12 @@ mod test {
         let (tx_outgoing, _rx_outgoing) = unbounded();
         let mut downstream = Downstream::new(
             1,
-            vec![],
-            vec![],
-            None,
-            None,
+            Sv1ServerSession::default(),
             tx_sv1_submit,
             tx_outgoing,
             false,
-            0,
             downstream_conf.clone(),
             Arc::new(Mutex::new(upstream_config)),
-            "0".to_string(),
         );
         downstream.difficulty_mgmt.min_individual_miner_hashrate = start_hashrate as f32;
 
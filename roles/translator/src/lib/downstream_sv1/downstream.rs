@@ -1,7 +1,7 @@
 use crate::{
     downstream_sv1,
     error::ProxyResult,
-    proxy_config::{DownstreamDifficultyConfig, UpstreamDifficultyConfig},
+    proxy_config::{ConnectionLimitsConfig, DownstreamDifficultyConfig, UpstreamDifficultyConfig},
     status,
 };
 use async_channel::{bounded, Receiver, Sender};
@@ -15,10 +15,14 @@ use error_handling::handle_result;
 use futures::FutureExt;
 use tokio::{sync::broadcast, task::AbortHandle};
 
-use super::{kill, DownstreamMessages, SubmitShareWithChannelId, SUBSCRIBE_TIMEOUT_SECS};
+use super::{
+    connection_limits::{ConnectionGuard, ConnectionLimiter},
+    kill, DownstreamMessages, SubmitShareWithChannelId,
+};
 
 use roles_logic_sv2::{
     common_properties::{IsDownstream, IsMiningDownstream},
+    mining_sv2::version_rolling,
     utils::Mutex,
 };
 
@@ -26,13 +30,13 @@ use crate::error::Error;
 use futures::select;
 use tokio_util::codec::{FramedRead, LinesCodec};
 
-use std::{net::SocketAddr, sync::Arc};
-use tracing::{debug, info, warn};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tracing::{debug, info, info_span, warn, Instrument};
 use v1::{
     client_to_server::{self, Submit},
     json_rpc, server_to_client,
     utils::{Extranonce, HexU32Be},
-    IsServer,
+    IsServer, PolicyTable, Sv1ServerSession,
 };
 
 const MAX_LINE_LENGTH: usize = 2_usize.pow(16);
@@ -41,17 +45,10 @@ const MAX_LINE_LENGTH: usize = 2_usize.pow(16);
 /// a SV2 Pool server).
 #[derive(Debug)]
 pub struct Downstream {
-    /// List of authorized Downstream Mining Devices.
     pub(super) connection_id: u32,
-    authorized_names: Vec<String>,
-    extranonce1: Vec<u8>,
-    /// `extranonce1` to be sent to the Downstream in the SV1 `mining.subscribe` message response.
-    //extranonce1: Vec<u8>,
-    //extranonce2_size: usize,
-    /// Version rolling mask bits
-    version_rolling_mask: Option<HexU32Be>,
-    /// Minimum version rolling mask bits size
-    version_rolling_min_bit: Option<HexU32Be>,
+    /// Authorized workers, extranonces sent in the `mining.subscribe` response, version rolling
+    /// and last job sent to the Downstream.
+    session: Sv1ServerSession<'static>,
     /// Sends a SV1 `mining.submit` message received from the Downstream role to the `Bridge` for
     /// translation into a SV2 `SubmitSharesExtended`.
     tx_sv1_bridge: Sender<DownstreamMessages>,
@@ -59,41 +56,35 @@ pub struct Downstream {
     tx_outgoing: Sender<json_rpc::Message>,
     /// True if this is the first job received from `Upstream`.
     first_job_received: bool,
-    extranonce2_len: usize,
     pub(super) difficulty_mgmt: DownstreamDifficultyConfig,
     pub(super) upstream_difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
-    last_job_id: String, // we usually receive a String on SV1 messages, no need to cast to u32
+    /// Checks of the requests received from the Downstream
+    policy_table: PolicyTable,
+    /// The connection and its channel in the admin interface
+    diagnostics: admin_sv2::Connection,
 }
 
 impl Downstream {
     #[cfg(test)]
     pub fn new(
         connection_id: u32,
-        authorized_names: Vec<String>,
-        extranonce1: Vec<u8>,
-        version_rolling_mask: Option<HexU32Be>,
-        version_rolling_min_bit: Option<HexU32Be>,
+        session: Sv1ServerSession<'static>,
         tx_sv1_bridge: Sender<DownstreamMessages>,
         tx_outgoing: Sender<json_rpc::Message>,
         first_job_received: bool,
-        extranonce2_len: usize,
         difficulty_mgmt: DownstreamDifficultyConfig,
         upstream_difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
-        last_job_id: String,
     ) -> Self {
         Downstream {
             connection_id,
-            authorized_names,
-            extranonce1,
-            version_rolling_mask,
-            version_rolling_min_bit,
+            session,
             tx_sv1_bridge,
             tx_outgoing,
             first_job_received,
-            extranonce2_len,
             difficulty_mgmt,
             upstream_difficulty_config,
-            last_job_id,
+            policy_table: PolicyTable::default(),
+            diagnostics: admin_sv2::diagnostics().open_connection("test", "sv1 downstream"),
         }
     }
     /// Instantiate a new `Downstream`.
@@ -111,6 +102,9 @@ impl Downstream {
         difficulty_config: DownstreamDifficultyConfig,
         upstream_difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
         task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+        connection: ConnectionGuard,
+        unauthorized_timeout: Duration,
+        policy_table: PolicyTable,
     ) {
         let stream = std::sync::Arc::new(stream);
 
@@ -122,23 +116,36 @@ impl Downstream {
         // Used to send SV1 `mining.notify` messages to the Downstreams
         let _socket_writer_notify = socket_writer;
 
+        let extranonce1: Extranonce<'static> = match extranonce1.try_into() {
+            Ok(extranonce1) => extranonce1,
+            Err(e) => {
+                tracing::error!("Invalid extranonce1 for downstream {}: {:?}", host, e);
+                return;
+            }
+        };
+        let diagnostics = admin_sv2::diagnostics().open_connection(&host, "sv1 downstream");
+        diagnostics.open_channel(connection_id, None);
         let downstream = Arc::new(Mutex::new(Downstream {
             connection_id,
-            authorized_names: vec![],
-            extranonce1,
-            //extranonce1: extranonce1.to_vec(),
-            version_rolling_mask: None,
-            version_rolling_min_bit: None,
+            session: Sv1ServerSession::new(extranonce1, extranonce2_len),
             tx_sv1_bridge,
             tx_outgoing,
             first_job_received: false,
-            extranonce2_len,
             difficulty_mgmt: difficulty_config,
             upstream_difficulty_config,
-            last_job_id: "".to_string(),
+            policy_table,
+            diagnostics,
         }));
         let self_ = downstream.clone();
 
+        // the user is recorded when the downstream authorizes
+        let span = info_span!(
+            "sv1_downstream",
+            channel_id = connection_id,
+            addr = %host,
+            user = tracing::field::Empty,
+        );
+
         let host_ = host.clone();
         // The shutdown channel is used local to the `Downstream::new_downstream()` function.
         // Each task is set broadcast a shutdown message at the end of their lifecycle with
@@ -159,7 +166,7 @@ impl Downstream {
         // SV1 message received, a message response is sent directly back to the SV1 Downstream
         // role, or the message is sent upwards to the Bridge for translation into a SV2 message
         // and then sent to the SV2 Upstream role.
-        let socket_reader_task = tokio::task::spawn(async move {
+        let socket_reader = async move {
             let reader = BufReader::new(&*socket_reader);
             let mut messages = FramedRead::new(
                 async_compat::Compat::new(reader),
@@ -209,7 +216,8 @@ impl Downstream {
             }
             kill(&tx_shutdown_clone).await;
             warn!("Downstream: Shutting down sv1 downstream reader");
-        });
+        };
+        let socket_reader_task = tokio::task::spawn(socket_reader.instrument(span.clone()));
         let _ = task_collector_mining_device.safe_lock(|a| {
             a.push((
                 socket_reader_task.abort_handle(),
@@ -225,7 +233,7 @@ impl Downstream {
         let task_collector_new_sv1_message_no_transl = task_collector.clone();
         // Task to receive SV1 message responses to SV1 messages that do NOT need translation.
         // These response messages are sent directly to the SV1 Downstream role.
-        let socket_writer_task = tokio::task::spawn(async move {
+        let socket_writer = async move {
             loop {
                 select! {
                     res = receiver_outgoing.recv().fuse() => {
@@ -253,7 +261,8 @@ impl Downstream {
                 "Downstream: Shutting down sv1 downstream writer: {}",
                 &host_
             );
-        });
+        };
+        let socket_writer_task = tokio::task::spawn(socket_writer.instrument(span.clone()));
         let _ = task_collector_new_sv1_message_no_transl.safe_lock(|a| {
             a.push((
                 socket_writer_task.abort_handle(),
@@ -265,17 +274,18 @@ impl Downstream {
         let self_ = downstream.clone();
 
         let task_collector_notify_task = task_collector.clone();
-        let notify_task = tokio::task::spawn(async move {
+        let notify = async move {
             let timeout_timer = std::time::Instant::now();
             let mut first_sent = false;
             loop {
-                let is_a = match downstream.safe_lock(|d| !d.authorized_names.is_empty()) {
-                    Ok(is_a) => is_a,
-                    Err(_e) => {
-                        debug!("\nDownstream: Poison Lock - authorized_names\n");
-                        break;
-                    }
-                };
+                let is_a =
+                    match downstream.safe_lock(|d| !d.session.authorized_workers().is_empty()) {
+                        Ok(is_a) => is_a,
+                        Err(_e) => {
+                            debug!("\nDownstream: Poison Lock - authorized_names\n");
+                            break;
+                        }
+                    };
                 if is_a && !first_sent && last_notify.is_some() {
                     let target = handle_result!(
                         tx_status_notify,
@@ -297,7 +307,7 @@ impl Downstream {
                     let sv1_mining_notify_msg = last_notify.clone().unwrap();
 
                     self_
-                        .safe_lock(|s| s.last_job_id = sv1_mining_notify_msg.clone().job_id)
+                        .safe_lock(|s| s.session.notify(&sv1_mining_notify_msg))
                         .unwrap();
 
                     let message: json_rpc::Message = sv1_mining_notify_msg.into();
@@ -323,7 +333,7 @@ impl Downstream {
                             let sv1_mining_notify_msg = handle_result!(tx_status_notify, res);
                             let message: json_rpc::Message = sv1_mining_notify_msg.clone().into();
 
-                            self_.safe_lock(|s| s.last_job_id = sv1_mining_notify_msg.job_id).unwrap();
+                            self_.safe_lock(|s| s.session.notify(&sv1_mining_notify_msg)).unwrap();
 
                             handle_result!(tx_status_notify, Downstream::send_message_downstream(downstream.clone(), message).await);
                         },
@@ -334,10 +344,11 @@ impl Downstream {
                 } else {
                     // timeout connection if miner does not send the authorize message after sending
                     // a subscribe
-                    if timeout_timer.elapsed().as_secs() > SUBSCRIBE_TIMEOUT_SECS {
-                        debug!(
-                            "Downstream: miner.subscribe/miner.authorize TIMOUT for {}",
-                            &host
+                    if timeout_timer.elapsed() > unauthorized_timeout {
+                        warn!(
+                            host = %host,
+                            timeout_sec = unauthorized_timeout.as_secs(),
+                            "Downstream: closing connection not authorized in time"
                         );
                         break;
                     }
@@ -345,12 +356,15 @@ impl Downstream {
                 }
             }
             let _ = Self::remove_miner_hashrate_from_channel(self_);
+            // frees the slot of the connection in the limits
+            drop(connection);
             kill(&tx_shutdown).await;
             warn!(
                 "Downstream: Shutting down sv1 downstream job notifier for {}",
                 &host
             );
-        });
+        };
+        let notify_task = tokio::task::spawn(notify.instrument(span));
 
         let _ = task_collector_notify_task
             .safe_lock(|a| a.push((notify_task.abort_handle(), "notify_task".to_string())));
@@ -368,8 +382,11 @@ impl Downstream {
         downstream_difficulty_config: DownstreamDifficultyConfig,
         upstream_difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
         task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+        connection_limits: ConnectionLimitsConfig,
+        policy_table: PolicyTable,
     ) {
         let task_collector_downstream = task_collector.clone();
+        let limiter = Arc::new(Mutex::new(ConnectionLimiter::new(connection_limits)));
 
         let accept_connections = tokio::task::spawn(async move {
             let downstream_listener = TcpListener::bind(downstream_addr).await.unwrap();
@@ -377,12 +394,30 @@ impl Downstream {
 
             while let Some(stream) = downstream_incoming.next().await {
                 let stream = stream.expect("Err on SV1 Downstream connection stream");
+                let peer = match stream.peer_addr() {
+                    Ok(peer) => peer,
+                    Err(_) => continue,
+                };
+                let connection = match ConnectionLimiter::on_connection(&limiter, peer.ip()) {
+                    Ok(connection) => connection,
+                    Err(limit) => {
+                        warn!(
+                            ip = %peer.ip(),
+                            limit = %limit,
+                            "PROXY SERVER - REFUSING DOWNSTREAM CONNECTION: limit reached"
+                        );
+                        continue;
+                    }
+                };
+                let unauthorized_timeout = limiter
+                    .safe_lock(|l| l.unauthorized_timeout())
+                    .unwrap_or(Duration::from_secs(super::SUBSCRIBE_TIMEOUT_SECS));
                 let expected_hash_rate = downstream_difficulty_config.min_individual_miner_hashrate;
                 let open_sv1_downstream = bridge
                     .safe_lock(|s| s.on_new_sv1_connection(expected_hash_rate))
                     .unwrap();
 
-                let host = stream.peer_addr().unwrap().to_string();
+                let host = peer.to_string();
                 match open_sv1_downstream {
                     Ok(opened) => {
                         info!("PROXY SERVER - ACCEPTING FROM DOWNSTREAM: {}", host);
@@ -399,6 +434,9 @@ impl Downstream {
                             downstream_difficulty_config.clone(),
                             upstream_difficulty_config.clone(),
                             task_collector_downstream.clone(),
+                            connection,
+                            unauthorized_timeout,
+                            policy_table.clone(),
                         )
                         .await;
                     }
@@ -489,19 +527,22 @@ impl IsServer<'static> for Downstream {
         // If the tproxy/pool needs to use some version bits this needs to be configurable
         // so upstreams can negotiate with downstreams. When that happens this should consider
         // the min_bit_count in the mining.configure message
-        self.version_rolling_mask = request
-            .version_rolling_mask()
-            .map(|mask| HexU32Be(mask & 0x1FFFE000));
-        self.version_rolling_min_bit = request.version_rolling_min_bit_count();
+        self.session.set_version_rolling_mask(
+            request
+                .version_rolling_mask()
+                .map(|mask| HexU32Be(version_rolling::negotiate_version_mask(mask.0))),
+        );
+        self.session
+            .set_version_rolling_min_bit(request.version_rolling_min_bit_count());
 
         debug!(
             "Negotiated version_rolling_mask is {:?}",
-            self.version_rolling_mask
+            self.session.version_rolling_mask()
         );
         (
             Some(server_to_client::VersionRollingParams::new(
-                self.version_rolling_mask.clone().unwrap_or(HexU32Be(0)),
-                self.version_rolling_min_bit.clone().unwrap_or(HexU32Be(0)),
+                self.session.version_rolling_mask().unwrap_or(HexU32Be(0)),
+                self.session.version_rolling_min_bit().unwrap_or(HexU32Be(0)),
             ).expect("Version mask invalid, automatic version mask selection not supported, please change it in carte::downstream_sv1::mod.rs")),
             Some(false),
         )
@@ -531,6 +572,9 @@ impl IsServer<'static> for Downstream {
     /// https://bitcoin.stackexchange.com/questions/29416/how-do-pool-servers-handle-multiple-workers-sharing-one-connection-with-stratum
     fn handle_authorize(&self, request: &client_to_server::Authorize) -> bool {
         info!("Down: Authorizing");
+        tracing::Span::current().record("user", request.name.as_str());
+        self.diagnostics
+            .open_channel(self.connection_id, Some(&request.name));
         debug!("Down: Handling mining.authorize: {:?}", &request);
         true
     }
@@ -543,13 +587,17 @@ impl IsServer<'static> for Downstream {
 
         // TODO: Check if receiving valid shares by adding diff field to Downstream
 
-        if request.job_id == self.last_job_id {
+        if self.session.last_job_id() == Some(request.job_id.as_str()) {
             let to_send = SubmitShareWithChannelId {
                 channel_id: self.connection_id,
                 share: request.clone(),
-                extranonce: self.extranonce1.clone(),
-                extranonce2_len: self.extranonce2_len,
-                version_rolling_mask: self.version_rolling_mask.clone(),
+                extranonce: self
+                    .session
+                    .extranonce1()
+                    .map(|e| e.as_ref().to_vec())
+                    .unwrap_or_default(),
+                extranonce2_len: self.session.extranonce2_size(),
+                version_rolling_mask: self.session.version_rolling_mask(),
             };
 
             self.tx_sv1_bridge
@@ -567,12 +615,12 @@ impl IsServer<'static> for Downstream {
 
     /// Checks if a Downstream role is authorized.
     fn is_authorized(&self, name: &str) -> bool {
-        self.authorized_names.contains(&name.to_string())
+        self.session.is_authorized(name)
     }
 
     /// Authorizes a Downstream role.
     fn authorize(&mut self, name: &str) {
-        self.authorized_names.push(name.to_string());
+        self.session.authorize(name);
     }
 
     /// Sets the `extranonce1` field sent in the SV1 `mining.notify` message to the value specified
@@ -581,38 +629,44 @@ impl IsServer<'static> for Downstream {
         &mut self,
         _extranonce1: Option<Extranonce<'static>>,
     ) -> Extranonce<'static> {
-        self.extranonce1.clone().try_into().unwrap()
+        self.extranonce1()
     }
 
     /// Returns the `Downstream`'s `extranonce1` value.
     fn extranonce1(&self) -> Extranonce<'static> {
-        self.extranonce1.clone().try_into().unwrap()
+        // Is safe to unwrap, the session is created with an extranonce1
+        self.session.extranonce1().cloned().unwrap()
     }
 
     /// Sets the `extranonce2_size` field sent in the SV1 `mining.notify` message to the value
     /// specified by the SV2 `OpenExtendedMiningChannelSuccess` message sent from the Upstream role.
     fn set_extranonce2_size(&mut self, _extra_nonce2_size: Option<usize>) -> usize {
-        self.extranonce2_len
+        self.session.extranonce2_size()
     }
 
     /// Returns the `Downstream`'s `extranonce2_size` value.
     fn extranonce2_size(&self) -> usize {
-        self.extranonce2_len
+        self.session.extranonce2_size()
     }
 
     /// Returns the version rolling mask.
     fn version_rolling_mask(&self) -> Option<HexU32Be> {
-        self.version_rolling_mask.clone()
+        self.session.version_rolling_mask()
     }
 
     /// Sets the version rolling mask.
     fn set_version_rolling_mask(&mut self, mask: Option<HexU32Be>) {
-        self.version_rolling_mask = mask;
+        self.session.set_version_rolling_mask(mask);
+    }
+
+    /// Returns the checks of the requests received from the Downstream.
+    fn policy_table(&self) -> Option<&PolicyTable> {
+        Some(&self.policy_table)
     }
 
     /// Sets the minimum version rolling bit.
     fn set_version_rolling_min_bit(&mut self, mask: Option<HexU32Be>) {
-        self.version_rolling_min_bit = mask
+        self.session.set_version_rolling_min_bit(mask);
     }
 
     fn notify(&mut self) -> Result<json_rpc::Message, v1::error::Error> {
@@ -632,7 +686,7 @@ impl IsDownstream for Downstream {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use crate::difficulty::difficulty_from_target;
 
     #[test]
     fn gets_difficulty_from_target() {
@@ -640,7 +694,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 128, 255, 127,
             0, 0, 0, 0, 0,
         ];
-        let actual = Downstream::difficulty_from_target(target).unwrap();
+        let actual = difficulty_from_target(target).unwrap();
         let expect = 512.0;
         assert_eq!(actual, expect);
     }
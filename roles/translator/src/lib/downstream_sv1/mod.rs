@@ -1,5 +1,6 @@
 use roles_logic_sv2::mining_sv2::Target;
 use v1::{client_to_server::Submit, utils::HexU32Be};
+pub mod connection_limits;
 pub mod diff_management;
 pub mod downstream;
 pub use downstream::Downstream;
@@ -8,8 +9,9 @@ pub use downstream::Downstream;
 /// do not send a mining.subscribe and never a mining.authorize
 /// since they will take up a tcp connection but never be allowed to
 /// receive jobs. Without the timeout the TProxy can be exploited by incoming
-/// `mining.subscribe` messages that init connections and take up compute
-const SUBSCRIBE_TIMEOUT_SECS: u64 = 10;
+/// `mining.subscribe` messages that init connections and take up compute.
+/// It is the default of `unauthorized_timeout_sec` in the connection limits.
+pub const SUBSCRIBE_TIMEOUT_SECS: u64 = 10;
 
 /// enum of messages sent to the Bridge
 #[derive(Debug)]
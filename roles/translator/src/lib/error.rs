@@ -41,6 +41,8 @@ pub enum Error<'a> {
     BadSerdeJson(serde_json::Error),
     /// Errors on bad `config` TOML deserialize.
     BadConfigDeserialize(ConfigError),
+    /// Errors on a config that can not be loaded or has invalid values.
+    InvalidConfig(config_helpers_sv2::ConfigError),
     /// Errors from `binary_sv2` crate.
     BinarySv2(binary_sv2::Error),
     /// Errors on bad noise handshake.
@@ -85,6 +87,7 @@ impl<'a> fmt::Display for Error<'a> {
             BadCliArgs => write!(f, "Bad CLI arg input"),
             BadSerdeJson(ref e) => write!(f, "Bad serde json: `{:?}`", e),
             BadConfigDeserialize(ref e) => write!(f, "Bad `config` TOML deserialize: `{:?}`", e),
+            InvalidConfig(ref e) => write!(f, "{}", e),
             BinarySv2(ref e) => write!(f, "Binary SV2 error: `{:?}`", e),
             CodecNoise(ref e) => write!(f, "Noise error: `{:?}", e),
             FramingSv2(ref e) => write!(f, "Framing SV2 error: `{:?}`", e),
@@ -166,6 +169,12 @@ impl<'a> From<ConfigError> for Error<'a> {
     }
 }
 
+impl<'a> From<config_helpers_sv2::ConfigError> for Error<'a> {
+    fn from(e: config_helpers_sv2::ConfigError) -> Self {
+        Error::InvalidConfig(e)
+    }
+}
+
 impl<'a> From<v1::error::Error<'a>> for Error<'a> {
     fn from(e: v1::error::Error<'a>) -> Self {
         Error::V1Protocol(e)
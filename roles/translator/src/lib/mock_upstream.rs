@@ -0,0 +1,388 @@
+//! In process SV2 upstream the translator can connect to in tests, enabled by the
+//! `mock_upstream` feature.
+//!
+//! The test drives the upstream: it accepts the connection of the translator, answers its
+//! `SetupConnection` and `OpenExtendedMiningChannel`, sends the jobs and prev hashes, and checks
+//! the messages the translator sends, e.g. the shares translated from the SV1 submits. The test
+//! module runs a SV1 miner against a translator connected to it.
+use crate::upstream_sv2::{EitherFrame, Message, StdFrame};
+use async_channel::{Receiver, Sender};
+use async_std::net::TcpListener;
+use codec_sv2::{HandshakeRole, Responder};
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use network_helpers_sv2::Connection;
+use roles_logic_sv2::{
+    common_messages_sv2::{Protocol, SetupConnectionSuccess},
+    mining_sv2::{
+        NewExtendedMiningJob, OpenExtendedMiningChannel, OpenExtendedMiningChannelSuccess,
+        SetNewPrevHash, SubmitSharesExtended,
+    },
+    parsers::{CommonMessages, Mining, PoolMessages},
+};
+use std::{convert::TryInto, net::SocketAddr, time::Duration};
+
+/// Keys of the mock upstream, the ones of the pool config examples
+pub const AUTHORITY_PUBLIC_KEY: &str = "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72";
+pub const AUTHORITY_SECRET_KEY: &str = "mkDLTBBRxdBv998612qipDYoTK3YUrqLe8uWw7gu3iXbSrn2n";
+
+/// Len of the extranonce prefix of the channels opened by the mock upstream, the rest of the 32
+/// bytes of extranonce is left to the translator
+pub const EXTRANONCE_PREFIX_LEN: usize = 16;
+const EXTRANONCE_LEN: usize = 32;
+
+/// Listener of the mock upstream
+pub struct MockUpstream {
+    listener: TcpListener,
+    authority_public_key: Secp256k1PublicKey,
+    authority_secret_key: Secp256k1SecretKey,
+}
+
+impl MockUpstream {
+    /// Listens on a free local port
+    pub async fn bind() -> Result<Self, String> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            listener,
+            // Infallible unwraps, the keys are valid
+            authority_public_key: AUTHORITY_PUBLIC_KEY.parse().unwrap(),
+            authority_secret_key: AUTHORITY_SECRET_KEY.parse().unwrap(),
+        })
+    }
+
+    pub fn address(&self) -> Result<SocketAddr, String> {
+        self.listener.local_addr().map_err(|e| e.to_string())
+    }
+
+    /// Key the translator must be configured with as `upstream_authority_pubkey`
+    pub fn authority_public_key(&self) -> Secp256k1PublicKey {
+        self.authority_public_key
+    }
+
+    /// Accepts the connection of the translator and answers its `SetupConnection`
+    pub async fn accept(&self) -> Result<MockConnection, String> {
+        let (stream, _) = self.listener.accept().await.map_err(|e| e.to_string())?;
+        let responder = Responder::from_authority_kp(
+            &self.authority_public_key.into_bytes(),
+            &self.authority_secret_key.into_bytes(),
+            Duration::from_secs(3600),
+        )
+        .map_err(|e| format!("invalid authority keys: {:?}", e))?;
+        let (receiver, sender) =
+            Connection::new::<Message>(stream, HandshakeRole::Responder(responder), 10)
+                .await
+                .map_err(|e| format!("noise handshake failed: {:?}", e))?;
+        let connection = MockConnection { receiver, sender };
+        connection
+            .expect(|message| match message {
+                PoolMessages::Common(CommonMessages::SetupConnection(m)) => {
+                    Some(match m.protocol {
+                        Protocol::MiningProtocol => Ok(()),
+                        protocol => Err(format!("SetupConnection for {:?}", protocol)),
+                    })
+                }
+                _ => None,
+            })
+            .await?;
+        connection
+            .send(PoolMessages::Common(
+                CommonMessages::SetupConnectionSuccess(SetupConnectionSuccess {
+                    used_version: 2,
+                    flags: 0,
+                }),
+            ))
+            .await?;
+        Ok(connection)
+    }
+}
+
+/// Noise connection of the mock upstream with the translator
+pub struct MockConnection {
+    receiver: Receiver<EitherFrame>,
+    sender: Sender<EitherFrame>,
+}
+
+impl MockConnection {
+    pub async fn send(&self, message: PoolMessages<'static>) -> Result<(), String> {
+        let frame: StdFrame = message.try_into().map_err(|e| format!("{:?}", e))?;
+        self.sender
+            .send(frame.into())
+            .await
+            .map_err(|_| "connection closed".to_string())
+    }
+
+    /// Waits for the first message for which `select` returns a result, the other messages are
+    /// ignored
+    pub async fn expect<T>(
+        &self,
+        mut select: impl FnMut(PoolMessages<'_>) -> Option<Result<T, String>>,
+    ) -> Result<T, String> {
+        loop {
+            let frame = self
+                .receiver
+                .recv()
+                .await
+                .map_err(|_| "connection closed".to_string())?;
+            let mut frame: StdFrame = frame.try_into().map_err(|e| format!("{:?}", e))?;
+            let message_type = frame
+                .get_header()
+                .ok_or_else(|| "frame without header".to_string())?
+                .msg_type();
+            let message: PoolMessages = (message_type, frame.payload())
+                .try_into()
+                .map_err(|e| format!("invalid message {}: {:?}", message_type, e))?;
+            if let Some(result) = select(message) {
+                return result;
+            }
+        }
+    }
+
+    /// Answers the `OpenExtendedMiningChannel` of the translator with a channel on which every
+    /// share meets the target, and returns the request
+    pub async fn open_extended_channel(
+        &self,
+        channel_id: u32,
+    ) -> Result<OpenExtendedMiningChannel<'static>, String> {
+        let request = self
+            .expect(|message| match message {
+                PoolMessages::Mining(Mining::OpenExtendedMiningChannel(m)) => {
+                    Some(Ok(m.into_static()))
+                }
+                _ => None,
+            })
+            .await?;
+        let extranonce_prefix = vec![1; EXTRANONCE_PREFIX_LEN]
+            .try_into()
+            .map_err(|e| format!("{:?}", e))?;
+        self.send(PoolMessages::Mining(
+            Mining::OpenExtendedMiningChannelSuccess(OpenExtendedMiningChannelSuccess {
+                request_id: request.request_id,
+                channel_id,
+                target: [255; 32].into(),
+                extranonce_size: (EXTRANONCE_LEN - EXTRANONCE_PREFIX_LEN) as u16,
+                extranonce_prefix,
+            }),
+        ))
+        .await?;
+        Ok(request)
+    }
+
+    /// Sends a future job and the prev hash that activates it
+    pub async fn send_job(
+        &self,
+        channel_id: u32,
+        job_id: u32,
+        prev_hash: [u8; 32],
+        min_ntime: u32,
+    ) -> Result<(), String> {
+        let (coinbase_tx_prefix, coinbase_tx_suffix) = coinbase();
+        self.send(PoolMessages::Mining(Mining::NewExtendedMiningJob(
+            NewExtendedMiningJob {
+                channel_id,
+                job_id,
+                min_ntime: binary_sv2::Sv2Option::new(None),
+                version: 0x2000_0000,
+                version_rolling_allowed: true,
+                merkle_path: vec![].into(),
+                coinbase_tx_prefix: coinbase_tx_prefix
+                    .try_into()
+                    .map_err(|e| format!("{:?}", e))?,
+                coinbase_tx_suffix: coinbase_tx_suffix
+                    .try_into()
+                    .map_err(|e| format!("{:?}", e))?,
+            },
+        )))
+        .await?;
+        self.send(PoolMessages::Mining(Mining::SetNewPrevHash(
+            SetNewPrevHash {
+                channel_id,
+                job_id,
+                prev_hash: prev_hash.into(),
+                min_ntime,
+                // Regtest difficulty
+                nbits: 0x207fffff,
+            },
+        )))
+        .await
+    }
+
+    /// Waits for the next share sent by the translator
+    pub async fn expect_share(&self) -> Result<SubmitSharesExtended<'static>, String> {
+        self.expect(|message| match message {
+            PoolMessages::Mining(Mining::SubmitSharesExtended(m)) => Some(Ok(m.into_static())),
+            _ => None,
+        })
+        .await
+    }
+}
+
+/// Coinbase prefix and suffix around the 32 bytes of extranonce of the scriptSig
+fn coinbase() -> (Vec<u8>, Vec<u8>) {
+    // version, 1 input, null outpoint
+    let mut prefix = vec![2, 0, 0, 0, 1];
+    prefix.extend_from_slice(&[0; 32]);
+    prefix.extend_from_slice(&[0xff; 4]);
+    // scriptSig: BIP34 height 1 then the extranonce
+    prefix.push(4 + EXTRANONCE_LEN as u8);
+    prefix.extend_from_slice(&[3, 1, 0, 0]);
+    // sequence, no outputs, locktime
+    let mut suffix = vec![0xff; 4];
+    suffix.push(0);
+    suffix.extend_from_slice(&[0; 4]);
+    (prefix, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{
+            proxy_config::{
+                DownstreamConfig, DownstreamDifficultyConfig, ProxyConfig, UpstreamConfig,
+                UpstreamDifficultyConfig,
+            },
+            TranslatorSv2,
+        },
+        *,
+    };
+    use serde_json::{json, Value};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+        net::{
+            tcp::{OwnedReadHalf, OwnedWriteHalf},
+            TcpStream,
+        },
+        time::timeout,
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(20);
+
+    /// SV1 miner speaking JSON-RPC over TCP
+    struct Sv1Miner {
+        lines: Lines<BufReader<OwnedReadHalf>>,
+        writer: OwnedWriteHalf,
+    }
+
+    impl Sv1Miner {
+        /// Connects to the translator, retrying until it listens
+        async fn connect(address: SocketAddr) -> Self {
+            let stream = loop {
+                match TcpStream::connect(address).await {
+                    Ok(stream) => break stream,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+                }
+            };
+            let (reader, writer) = stream.into_split();
+            Self {
+                lines: BufReader::new(reader).lines(),
+                writer,
+            }
+        }
+
+        async fn send(&mut self, message: Value) {
+            let line = format!("{}\n", message);
+            self.writer.write_all(line.as_bytes()).await.unwrap();
+        }
+
+        /// Waits for the first message for which `select` returns something
+        async fn expect<T>(&mut self, select: impl Fn(&Value) -> Option<T>) -> T {
+            loop {
+                let line = self.lines.next_line().await.unwrap().unwrap();
+                let message: Value = serde_json::from_str(&line).unwrap();
+                if let Some(result) = select(&message) {
+                    return result;
+                }
+            }
+        }
+
+        async fn response(&mut self, id: u64) -> Value {
+            self.expect(|m| match m["id"].as_u64() {
+                Some(i) if i == id => Some(m["result"].clone()),
+                _ => None,
+            })
+            .await
+        }
+    }
+
+    fn free_local_address() -> SocketAddr {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sv1_submit_is_translated_to_sv2_share() {
+        let upstream = MockUpstream::bind().await.unwrap();
+        let upstream_address = upstream.address().unwrap();
+        let downstream_address = free_local_address();
+        let config = ProxyConfig::new(
+            UpstreamConfig::new(
+                upstream_address.ip().to_string(),
+                upstream_address.port(),
+                upstream.authority_public_key(),
+                UpstreamDifficultyConfig::new(60, 1_000_000.0, 0, false),
+            ),
+            DownstreamConfig::new(
+                downstream_address.ip().to_string(),
+                downstream_address.port(),
+                // a hashrate this low gives the highest target, every hash is a share
+                DownstreamDifficultyConfig::new(0.001, 6.0, 0, 0),
+            ),
+            2,
+            2,
+            8,
+        );
+        let translator = tokio::spawn(TranslatorSv2::new(config).start());
+
+        let run = async {
+            let connection = upstream.accept().await.unwrap();
+            let request = connection.open_extended_channel(1).await.unwrap();
+            assert_eq!(request.min_extranonce_size, 8);
+            connection
+                .send_job(1, 1, [7; 32], 1_700_000_000)
+                .await
+                .unwrap();
+
+            let mut miner = Sv1Miner::connect(downstream_address).await;
+            miner
+                .send(json!({"id": 1, "method": "mining.subscribe", "params": ["test"]}))
+                .await;
+            let subscribe = miner.response(1).await;
+            let extranonce2_size = subscribe[2].as_u64().unwrap() as usize;
+            miner
+                .send(json!({"id": 2, "method": "mining.authorize", "params": ["user", "x"]}))
+                .await;
+            assert_eq!(miner.response(2).await, json!(true));
+            let notify = miner
+                .expect(|m| match m["method"].as_str() {
+                    Some("mining.notify") => Some(m["params"].clone()),
+                    _ => None,
+                })
+                .await;
+            assert_eq!(notify[1], "07".repeat(32));
+            assert_eq!(notify[7], format!("{:08x}", 1_700_000_000));
+
+            let extranonce2 = "ab".repeat(extranonce2_size);
+            miner
+                .send(json!({
+                    "id": 3,
+                    "method": "mining.submit",
+                    "params": ["user", notify[0], extranonce2, notify[7], "0000002a"],
+                }))
+                .await;
+            let share = connection.expect_share().await.unwrap();
+            assert_eq!(share.channel_id, 1);
+            assert_eq!(share.job_id, 1);
+            assert_eq!(share.nonce, 0x2a);
+            assert_eq!(share.ntime, 1_700_000_000);
+            assert_eq!(share.version, 0x2000_0000);
+            // the part of the extranonce after the prefix of the channel
+            let extranonce = share.extranonce.to_vec();
+            assert_eq!(extranonce.len(), EXTRANONCE_LEN - EXTRANONCE_PREFIX_LEN);
+            assert!(extranonce.ends_with(&vec![0xab; extranonce2_size]));
+        };
+        timeout(TIMEOUT, run).await.unwrap();
+        translator.abort();
+    }
+}
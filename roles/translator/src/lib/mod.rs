@@ -1,12 +1,31 @@
+//! SV1 <-> SV2 translator proxy.
+//!
+//! [`TranslatorSv2`] runs the whole proxy: it listens for SV1 miners and opens an extended channel
+//! with the SV2 upstream. The translation can also be embedded without the proxy:
+//! - [`proxy::Translator`] turns SV2 `SetNewPrevHash` and `NewExtendedMiningJob` messages into SV1
+//!   `mining.notify` messages and SV1 `mining.submit` messages into SV2 `SubmitSharesExtended`
+//!   messages, without doing any IO.
+//! - [`difficulty`] converts SV2 targets into SV1 difficulties and updates the hashrate of the
+//!   miners from the shares they submit.
 use async_channel::{bounded, unbounded};
 use futures::FutureExt;
+use key_utils::Secp256k1PublicKey;
 use rand::Rng;
 pub use roles_logic_sv2::utils::Mutex;
+use roles_logic_sv2::{
+    handlers::interceptor::Interceptor,
+    upstream_selection::{
+        LatencySamples, LowestLatency, RoundRobin, SelectionStrategy, StaticPriority,
+        UpstreamCandidate,
+    },
+};
 use status::Status;
 use std::{
+    collections::HashMap,
     net::{IpAddr, SocketAddr},
     str::FromStr,
     sync::Arc,
+    time::Duration,
 };
 
 use tokio::{
@@ -16,35 +35,78 @@ use tokio::{
 use tracing::{debug, error, info, warn};
 pub use v1::server_to_client;
 
-use proxy_config::ProxyConfig;
+use proxy_config::{ProxyConfig, UpstreamSelection};
 
 use crate::status::State;
 
+pub mod difficulty;
 pub mod downstream_sv1;
 pub mod error;
+#[cfg(feature = "mock_upstream")]
+pub mod mock_upstream;
 pub mod proxy;
 pub mod proxy_config;
 pub mod status;
 pub mod upstream_sv2;
 pub mod utils;
 
+/// Time given to an upstream to accept the connection and complete the noise handshake when there
+/// are other upstreams to fall back to
+const UPSTREAM_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Clone, Debug)]
 pub struct TranslatorSv2 {
     config: ProxyConfig,
     reconnect_wait_time: u64,
+    interceptor: Option<Arc<dyn Interceptor>>,
+    // Kept across the reconnections so that `RoundRobin` moves to the next upstream
+    upstream_strategy: Arc<dyn SelectionStrategy>,
+    // Round trips of the `SetupConnection` of the upstreams the proxy connected to, by position
+    // in `ProxyConfig::upstreams`
+    upstream_latencies: Arc<Mutex<HashMap<u32, LatencySamples>>>,
 }
 
 impl TranslatorSv2 {
     pub fn new(config: ProxyConfig) -> Self {
         let mut rng = rand::thread_rng();
         let wait_time = rng.gen_range(0..=3000);
+        let upstream_strategy: Arc<dyn SelectionStrategy> = match config.upstream_selection {
+            // The ids of the upstreams are their positions in `ProxyConfig::upstreams`
+            UpstreamSelection::Priority => Arc::new(StaticPriority::default()),
+            UpstreamSelection::RoundRobin => Arc::new(RoundRobin::new()),
+            UpstreamSelection::LowestLatency => Arc::new(LowestLatency),
+        };
         Self {
             config,
             reconnect_wait_time: wait_time,
+            interceptor: None,
+            upstream_strategy,
+            upstream_latencies: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
+    /// Calls `interceptor` with the messages received from the upstream before they are
+    /// translated
+    #[allow(dead_code)]
+    pub fn with_interceptor(mut self, interceptor: Arc<dyn Interceptor>) -> Self {
+        self.interceptor = Some(interceptor);
+        self
+    }
+
     pub async fn start(self) {
+        if let Some(metrics_address) = &self.config.metrics_address {
+            if let Err(e) = monitoring_sv2::serve(metrics_address, "translator") {
+                error!("Failed to serve the metrics on {}: {}", metrics_address, e);
+            }
+        }
+        if let Some(admin_address) = &self.config.admin_address {
+            if let Err(e) = admin_sv2::serve(admin_address) {
+                error!(
+                    "Failed to serve the admin interface on {}: {}",
+                    admin_address, e
+                );
+            }
+        }
         let (tx_status, rx_status) = unbounded();
 
         let target = Arc::new(Mutex::new(vec![0; 32]));
@@ -132,6 +194,54 @@ impl TranslatorSv2 {
         }
     }
 
+    /// Position in `ProxyConfig::upstreams`, address and authority key of the upstream to connect
+    /// to, picked with the `upstream_selection` strategy among the main and the backup upstreams
+    /// that are not `unreachable`. When all of them are, the upstream is picked among all of them.
+    /// The latency of an upstream is the average round trip of the `SetupConnection` of the last
+    /// connections to it, the upstreams the proxy never connected to (or that failed the last
+    /// connection) have none.
+    fn select_upstream(&self, unreachable: &[u32]) -> (u32, SocketAddr, Secp256k1PublicKey) {
+        let upstreams = self.config.upstreams();
+        if upstreams.len() == 1 {
+            return (
+                0,
+                upstreams[0].socket_address(),
+                upstreams[0].authority_pubkey,
+            );
+        }
+        let latencies = self
+            .upstream_latencies
+            .safe_lock(|latencies| latencies.clone())
+            .unwrap_or_default();
+        let mut ids: Vec<u32> = (0..upstreams.len() as u32)
+            .filter(|id| !unreachable.contains(id))
+            .collect();
+        if ids.is_empty() {
+            warn!("No upstream is reachable");
+            ids = (0..upstreams.len() as u32).collect();
+        }
+        let candidates: Vec<UpstreamCandidate> = ids
+            .into_iter()
+            .map(|id| UpstreamCandidate {
+                id,
+                weight: 1.0,
+                total_hash_rate: 0,
+                latency: latencies.get(&id).and_then(LatencySamples::average),
+            })
+            .collect();
+        let id = self
+            .upstream_strategy
+            .select(&candidates)
+            .map(|index| candidates[index].id)
+            .unwrap_or(0);
+        let upstream = &upstreams[id as usize];
+        info!(
+            "Selected upstream {}:{} ({:?})",
+            upstream.address, upstream.port, self.config.upstream_selection
+        );
+        (id, upstream.socket_address(), upstream.authority_pubkey)
+    }
+
     async fn internal_start(
         &self,
         tx_sv1_notify: broadcast::Sender<server_to_client::Notify<'static>>,
@@ -164,35 +274,49 @@ impl TranslatorSv2 {
         // `Bridge` (Sender<SetNewPrevHash<'static>>, Receiver<SetNewPrevHash<'static>>)
         let (tx_sv2_set_new_prev_hash, rx_sv2_set_new_prev_hash) = bounded(10);
 
-        // Format `Upstream` connection address
-        let upstream_addr = SocketAddr::new(
-            IpAddr::from_str(&proxy_config.upstream_address)
-                .expect("Failed to parse upstream address!"),
-            proxy_config.upstream_port,
-        );
-
+        let upstream_latencies = self.upstream_latencies.clone();
         let diff_config = Arc::new(Mutex::new(proxy_config.upstream_difficulty_config.clone()));
-        let task_collector_upstream = task_collector.clone();
-        // Instantiate a new `Upstream` (SV2 Pool)
-        let upstream = match upstream_sv2::Upstream::new(
-            upstream_addr,
-            proxy_config.upstream_authority_pubkey,
-            rx_sv2_submit_shares_ext,
-            tx_sv2_set_new_prev_hash,
-            tx_sv2_new_ext_mining_job,
-            proxy_config.min_extranonce2_size,
-            tx_sv2_extranonce,
-            status::Sender::Upstream(tx_status.clone()),
-            target.clone(),
-            diff_config.clone(),
-            task_collector_upstream,
-        )
-        .await
-        {
-            Ok(upstream) => upstream,
-            Err(e) => {
-                error!("Failed to create upstream: {}", e);
-                return;
+        // Upstreams that did not accept the connection, the next one is tried as long as there
+        // is one left
+        let mut unreachable = vec![];
+        let (upstream_id, upstream) = loop {
+            let (upstream_id, upstream_addr, upstream_authority_pubkey) =
+                self.select_upstream(&unreachable);
+            // Instantiate a new `Upstream` (SV2 Pool)
+            let new_upstream = upstream_sv2::Upstream::new(
+                upstream_addr,
+                upstream_authority_pubkey,
+                rx_sv2_submit_shares_ext.clone(),
+                tx_sv2_set_new_prev_hash.clone(),
+                tx_sv2_new_ext_mining_job.clone(),
+                proxy_config.min_extranonce2_size,
+                tx_sv2_extranonce.clone(),
+                status::Sender::Upstream(tx_status.clone()),
+                target.clone(),
+                diff_config.clone(),
+                task_collector.clone(),
+                self.interceptor.clone(),
+            );
+            let new_upstream = if unreachable.len() + 1 < proxy_config.upstreams().len() {
+                match tokio::time::timeout(UPSTREAM_CONNECT_TIMEOUT, new_upstream).await {
+                    Ok(new_upstream) => new_upstream,
+                    Err(_) => {
+                        warn!("Upstream {} did not accept the connection", upstream_addr);
+                        let _ = upstream_latencies
+                            .safe_lock(|latencies| latencies.remove(&upstream_id));
+                        unreachable.push(upstream_id);
+                        continue;
+                    }
+                }
+            } else {
+                new_upstream.await
+            };
+            match new_upstream {
+                Ok(upstream) => break (upstream_id, upstream),
+                Err(e) => {
+                    error!("Failed to create upstream: {}", e);
+                    return;
+                }
             }
         };
         let task_collector_init_task = task_collector.clone();
@@ -209,9 +333,16 @@ impl TranslatorSv2 {
             )
             .await
             {
-                Ok(_) => info!("Connected to Upstream!"),
+                Ok(round_trip) => {
+                    info!("Connected to Upstream!");
+                    let _ = upstream_latencies.safe_lock(|latencies| {
+                        latencies.entry(upstream_id).or_default().record(round_trip)
+                    });
+                }
                 Err(e) => {
                     error!("Failed to connect to Upstream EXITING! : {}", e);
+                    let _ =
+                        upstream_latencies.safe_lock(|latencies| latencies.remove(&upstream_id));
                     return;
                 }
             }
@@ -253,6 +384,8 @@ impl TranslatorSv2 {
                 target,
                 up_id,
                 task_collector_bridge,
+                proxy_config.ntime_limits(),
+                proxy_config.cache_future_jobs,
             );
             proxy::Bridge::start(b.clone());
 
@@ -273,6 +406,8 @@ impl TranslatorSv2 {
                 proxy_config.downstream_difficulty_config,
                 diff_config,
                 task_collector_downstream,
+                proxy_config.connection_limits,
+                proxy_config.sv1_validation,
             );
         }); // End of init task
         let _ =
@@ -1,32 +1,36 @@
 use async_channel::{Receiver, Sender};
 use roles_logic_sv2::{
-    channel_logic::channel_factory::{ExtendedChannelKind, ProxyExtendedChannelFactory, Share},
     mining_sv2::{
         ExtendedExtranonce, NewExtendedMiningJob, SetNewPrevHash, SubmitSharesExtended, Target,
     },
-    parsers::Mining,
-    utils::{GroupId, Mutex},
+    share_validation::NtimeLimits,
+    utils::Mutex,
 };
 use std::sync::Arc;
 use tokio::{sync::broadcast, task::AbortHandle};
-use v1::{client_to_server::Submit, server_to_client, utils::HexU32Be};
-
-use super::super::{
-    downstream_sv1::{DownstreamMessages, SetDownstreamTarget, SubmitShareWithChannelId},
-    error::{
-        Error::{self, PoisonLock},
-        ProxyResult,
+use v1::server_to_client;
+
+use super::{
+    super::{
+        downstream_sv1::{DownstreamMessages, SetDownstreamTarget, SubmitShareWithChannelId},
+        error::{
+            Error::{self, PoisonLock},
+            ProxyResult,
+        },
+        status,
     },
-    status,
+    translator::{SubmitOutcome, Translator},
 };
 use error_handling::handle_result;
-use roles_logic_sv2::{channel_logic::channel_factory::OnNewShare, Error as RolesLogicError};
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
 /// Bridge between the SV2 `Upstream` and SV1 `Downstream` responsible for the following messaging
 /// translation:
 /// 1. SV1 `mining.submit` -> SV2 `SubmitSharesExtended`
 /// 2. SV2 `SetNewPrevHash` + `NewExtendedMiningJob` -> SV1 `mining.notify`
+///
+/// The translation itself is done by a [`Translator`], the bridge runs it on the channels of the
+/// proxy.
 #[derive(Debug)]
 pub struct Bridge {
     /// Receives a SV1 `mining.submit` message from the Downstream role.
@@ -47,23 +51,14 @@ pub struct Bridge {
     /// Allows the bridge the ability to communicate back to the main thread any status updates
     /// that would interest the main thread for error handling
     tx_status: status::Sender,
-    /// Stores the most recent SV1 `mining.notify` values to be sent to the `Downstream` upon
-    /// receiving a new SV2 `SetNewPrevHash` and `NewExtendedMiningJob` messages **before** any
-    /// Downstream role connects to the proxy.
-    ///
-    /// Once the proxy establishes a connection with the SV2 Upstream role, it immediately receives
-    /// a SV2 `SetNewPrevHash` and `NewExtendedMiningJob` message. This happens before the
-    /// connection to the Downstream role(s) occur. The `last_notify` member fields allows these
-    /// first notify values to be relayed to the `Downstream` once a Downstream role connects. Once
-    /// a Downstream role connects and receives the first notify values, this member field is no
-    /// longer used.
-    last_notify: Option<server_to_client::Notify<'static>>,
-    pub(self) channel_factory: ProxyExtendedChannelFactory,
-    future_jobs: Vec<NewExtendedMiningJob<'static>>,
-    last_p_hash: Option<SetNewPrevHash<'static>>,
+    /// Extended channel with the Upstream and the SV1 downstreams opened on it, translates the
+    /// messages received on the channels above.
+    pub(self) translator: Translator,
     target: Arc<Mutex<Vec<u8>>>,
-    last_job_id: u32,
     task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+    /// When the future job of a `SetNewPrevHash` has already been received the `mining.notify`
+    /// is sent right away, without waiting for the job being handled by the other task.
+    cache_future_jobs: bool,
 }
 
 impl Bridge {
@@ -80,12 +75,13 @@ impl Bridge {
         target: Arc<Mutex<Vec<u8>>>,
         up_id: u32,
         task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+        ntime_limits: Option<NtimeLimits>,
+        cache_future_jobs: bool,
     ) -> Arc<Mutex<Self>> {
-        let ids = Arc::new(Mutex::new(GroupId::new()));
-        let share_per_min = 1.0;
         let upstream_target: [u8; 32] =
             target.safe_lock(|t| t.clone()).unwrap().try_into().unwrap();
         let upstream_target: Target = upstream_target.into();
+        let translator = Translator::new(extranonces, upstream_target, up_id, ntime_limits);
         Arc::new(Mutex::new(Self {
             rx_sv1_downstream,
             tx_sv2_submit_shares_ext,
@@ -93,22 +89,10 @@ impl Bridge {
             rx_sv2_new_ext_mining_job,
             tx_sv1_notify,
             tx_status,
-            last_notify: None,
-            channel_factory: ProxyExtendedChannelFactory::new(
-                ids,
-                extranonces,
-                None,
-                share_per_min,
-                ExtendedChannelKind::Proxy { upstream_target },
-                None,
-                String::from(""),
-                up_id,
-            ),
-            future_jobs: vec![],
-            last_p_hash: None,
+            translator,
             target,
-            last_job_id: 0,
             task_collector,
+            cache_future_jobs,
         }))
     }
 
@@ -117,40 +101,17 @@ impl Bridge {
         &mut self,
         hash_rate: f32,
     ) -> ProxyResult<'static, OpenSv1Downstream> {
-        match self.channel_factory.new_extended_channel(0, hash_rate, 0) {
-            Ok(messages) => {
-                for message in messages {
-                    match message {
-                        Mining::OpenExtendedMiningChannelSuccess(success) => {
-                            let extranonce = success.extranonce_prefix.to_vec();
-                            let extranonce2_len = success.extranonce_size;
-                            self.target
-                                .safe_lock(|t| *t = success.target.to_vec())
-                                .map_err(|_e| PoisonLock)?;
-                            return Ok(OpenSv1Downstream {
-                                channel_id: success.channel_id,
-                                last_notify: self.last_notify.clone(),
-                                extranonce,
-                                target: self.target.clone(),
-                                extranonce2_len,
-                            });
-                        }
-                        Mining::OpenMiningChannelError(_) => todo!(),
-                        Mining::SetNewPrevHash(_) => (),
-                        Mining::NewExtendedMiningJob(_) => (),
-                        _ => unreachable!(),
-                    }
-                }
-            }
-            Err(_) => {
-                return Err(Error::SubprotocolMining(
-                    "Bridge: failed to open new extended channel".to_string(),
-                ))
-            }
-        };
-        Err(Error::SubprotocolMining(
-            "Bridge: Invalid mining message when opening downstream connection".to_string(),
-        ))
+        let channel = self.translator.open_sv1_downstream(hash_rate)?;
+        self.target
+            .safe_lock(|t| *t = channel.target)
+            .map_err(|_e| PoisonLock)?;
+        Ok(OpenSv1Downstream {
+            channel_id: channel.channel_id,
+            last_notify: channel.last_notify,
+            extranonce: channel.extranonce,
+            target: self.target.clone(),
+            extranonce2_len: channel.extranonce2_len,
+        })
     }
 
     /// Starts the tasks that receive SV1 and SV2 messages to be translated and sent to their
@@ -175,9 +136,17 @@ impl Bridge {
 
                 match msg {
                     DownstreamMessages::SubmitShares(share) => {
+                        let span = info_span!(
+                            "share",
+                            channel_id = share.channel_id,
+                            user = %share.share.user_name,
+                            job_id = %share.share.job_id,
+                        );
                         handle_result!(
                             tx_status,
-                            Self::handle_submit_shares(self_.clone(), share).await
+                            Self::handle_submit_shares(self_.clone(), share)
+                                .instrument(span)
+                                .await
                         );
                     }
                     DownstreamMessages::SetDownstreamTarget(new_target) => {
@@ -204,8 +173,8 @@ impl Bridge {
     ) -> ProxyResult<'static, ()> {
         self_
             .safe_lock(|b| {
-                b.channel_factory
-                    .update_target_for_channel(new_target.channel_id, new_target.new_target);
+                b.translator
+                    .update_downstream_target(new_target.channel_id, new_target.new_target);
             })
             .map_err(|_| PoisonLock)?;
         Ok(())
@@ -229,44 +198,35 @@ impl Bridge {
             .safe_lock(|t| t.clone())
             .map_err(|_| PoisonLock)?
             .try_into()?;
-        let mut upstream_target: Target = upstream_target.into();
+        let upstream_target: Target = upstream_target.into();
         self_
-            .safe_lock(|s| s.channel_factory.set_target(&mut upstream_target))
+            .safe_lock(|s| s.translator.set_upstream_target(upstream_target))
             .map_err(|_| PoisonLock)?;
 
         let sv2_submit = self_
             .safe_lock(|s| {
-                s.translate_submit(share.channel_id, share.share, share.version_rolling_mask)
+                s.translator.translate_submit(
+                    share.channel_id,
+                    share.share,
+                    share.version_rolling_mask,
+                )
             })
             .map_err(|_| PoisonLock)??;
         let res = self_
-            .safe_lock(|s| s.channel_factory.on_submit_shares_extended(sv2_submit))
+            .safe_lock(|s| s.translator.on_submit_shares_extended(sv2_submit))
             .map_err(|_| PoisonLock);
 
         match res {
-            Ok(Ok(OnNewShare::SendErrorDownstream(e))) => {
-                warn!(
-                    "Submit share error {:?}",
-                    std::str::from_utf8(&e.error_code.to_vec()[..])
-                );
+            Ok(Ok(SubmitOutcome::Rejected(error_code))) => {
+                warn!("Submit share error {:?}", error_code);
             }
-            Ok(Ok(OnNewShare::SendSubmitShareUpstream((share, _)))) => {
+            Ok(Ok(SubmitOutcome::SendUpstream(share))) => {
                 info!("SHARE MEETS UPSTREAM TARGET");
-                match share {
-                    Share::Extended(share) => {
-                        tx_sv2_submit_shares_ext.send(share).await?;
-                    }
-                    // We are in an extended channel shares are extended
-                    Share::Standard(_) => unreachable!(),
-                }
+                tx_sv2_submit_shares_ext.send(share).await?;
             }
-            // We are in an extended channel this variant is group channle only
-            Ok(Ok(OnNewShare::RelaySubmitShareUpstream)) => unreachable!(),
-            Ok(Ok(OnNewShare::ShareMeetDownstreamTarget)) => {
+            Ok(Ok(SubmitOutcome::MeetsDownstreamTarget)) => {
                 debug!("SHARE MEETS DOWNSTREAM TARGET");
             }
-            // Proxy do not have JD capabilities
-            Ok(Ok(OnNewShare::ShareMeetBitcoinTarget(..))) => unreachable!(),
             Ok(Err(e)) => error!("Error: {:?}", e),
             Err(e) => {
                 let _ = tx_status
@@ -279,93 +239,36 @@ impl Bridge {
         Ok(())
     }
 
-    /// Translates a SV1 `mining.submit` message to a SV2 `SubmitSharesExtended` message.
-    #[allow(clippy::result_large_err)]
-    fn translate_submit(
-        &self,
-        channel_id: u32,
-        sv1_submit: Submit,
-        version_rolling_mask: Option<HexU32Be>,
-    ) -> ProxyResult<'static, SubmitSharesExtended<'static>> {
-        let last_version = self
-            .channel_factory
-            .last_valid_job_version()
-            .ok_or(Error::RolesSv2Logic(RolesLogicError::NoValidJob))?;
-        let version = match (sv1_submit.version_bits, version_rolling_mask) {
-            // regarding version masking see https://github.com/slushpool/stratumprotocol/blob/master/stratum-extensions.mediawiki#changes-in-request-miningsubmit
-            (Some(vb), Some(mask)) => (last_version & !mask.0) | (vb.0 & mask.0),
-            (None, None) => last_version,
-            _ => return Err(Error::V1Protocol(v1::error::Error::InvalidSubmission)),
-        };
-        let mining_device_extranonce: Vec<u8> = sv1_submit.extra_nonce2.into();
-        let extranonce2 = mining_device_extranonce;
-        Ok(SubmitSharesExtended {
-            channel_id,
-            // I put 0 below cause sequence_number is not what should be TODO
-            sequence_number: 0,
-            job_id: sv1_submit.job_id.parse::<u32>()?,
-            nonce: sv1_submit.nonce.0,
-            ntime: sv1_submit.time.0,
-            version,
-            extranonce: extranonce2.try_into()?,
-        })
-    }
-
     async fn handle_new_prev_hash_(
         self_: Arc<Mutex<Self>>,
         sv2_set_new_prev_hash: SetNewPrevHash<'static>,
         tx_sv1_notify: broadcast::Sender<server_to_client::Notify<'static>>,
     ) -> Result<(), Error<'static>> {
-        while !crate::upstream_sv2::upstream::IS_NEW_JOB_HANDLED
-            .load(std::sync::atomic::Ordering::SeqCst)
-        {
-            tokio::task::yield_now().await;
-        }
-        self_
-            .safe_lock(|s| s.last_p_hash = Some(sv2_set_new_prev_hash.clone()))
-            .map_err(|_| PoisonLock)?;
-
-        let on_new_prev_hash_res = self_
+        let job_cached = self_
             .safe_lock(|s| {
-                s.channel_factory
-                    .on_new_prev_hash(sv2_set_new_prev_hash.clone())
+                s.cache_future_jobs && s.translator.has_future_job(sv2_set_new_prev_hash.job_id)
             })
             .map_err(|_| PoisonLock)?;
-        on_new_prev_hash_res?;
-
-        let mut future_jobs = self_
+        // Otherwise the future job could still be in the channel of the jobs
+        if !job_cached {
+            while !crate::upstream_sv2::upstream::IS_NEW_JOB_HANDLED
+                .load(std::sync::atomic::Ordering::SeqCst)
+            {
+                tokio::task::yield_now().await;
+            }
+        }
+        let notify = self_
             .safe_lock(|s| {
-                let future_jobs = s.future_jobs.clone();
-                s.future_jobs = vec![];
-                future_jobs
+                s.translator
+                    .on_set_new_prev_hash(sv2_set_new_prev_hash.clone())
             })
-            .map_err(|_| PoisonLock)?;
-
-        let mut match_a_future_job = false;
-        while let Some(job) = future_jobs.pop() {
-            if job.job_id == sv2_set_new_prev_hash.job_id {
-                let j_id = job.job_id;
-                // Create the mining.notify to be sent to the Downstream.
-                let notify = crate::proxy::next_mining_notify::create_notify(
-                    sv2_set_new_prev_hash.clone(),
-                    job,
-                    true,
-                );
-
-                // Get the sender to send the mining.notify to the Downstream
-                tx_sv1_notify.send(notify.clone())?;
-                match_a_future_job = true;
-                self_
-                    .safe_lock(|s| {
-                        s.last_notify = Some(notify);
-                        s.last_job_id = j_id;
-                    })
-                    .map_err(|_| PoisonLock)?;
-                break;
+            .map_err(|_| PoisonLock)??;
+        match notify {
+            // Send the mining.notify to the Downstream
+            Some(notify) => {
+                tx_sv1_notify.send(notify)?;
             }
-        }
-        if !match_a_future_job {
-            debug!("No future jobs for {:?}", sv2_set_new_prev_hash);
+            None => debug!("No future jobs for {:?}", sv2_set_new_prev_hash),
         }
         Ok(())
     }
@@ -422,52 +325,19 @@ impl Bridge {
         sv2_new_extended_mining_job: NewExtendedMiningJob<'static>,
         tx_sv1_notify: broadcast::Sender<server_to_client::Notify<'static>>,
     ) -> Result<(), Error<'static>> {
-        // convert to non segwit jobs so we dont have to depend if miner's support segwit or not
-        self_
+        // If future_job=true, this job is meant for a future SetNewPrevHash that the proxy
+        // has yet to receive, the translator keeps it until then.
+        let notify = self_
             .safe_lock(|s| {
-                s.channel_factory
-                    .on_new_extended_mining_job(sv2_new_extended_mining_job.as_static().clone())
+                s.translator
+                    .on_new_extended_mining_job(sv2_new_extended_mining_job)
             })
             .map_err(|_| PoisonLock)??;
-
-        // If future_job=true, this job is meant for a future SetNewPrevHash that the proxy
-        // has yet to receive. Insert this new job into the job_mapper .
-        if sv2_new_extended_mining_job.is_future() {
-            self_
-                .safe_lock(|s| s.future_jobs.push(sv2_new_extended_mining_job.clone()))
-                .map_err(|_| PoisonLock)?;
-            Ok(())
-
         // If future_job=false, this job is meant for the current SetNewPrevHash.
-        } else {
-            let last_p_hash_option = self_
-                .safe_lock(|s| s.last_p_hash.clone())
-                .map_err(|_| PoisonLock)?;
-
-            // last_p_hash is an Option<SetNewPrevHash> so we need to map to the correct error type
-            // to be handled
-            let last_p_hash = last_p_hash_option.ok_or(Error::RolesSv2Logic(
-                RolesLogicError::JobIsNotFutureButPrevHashNotPresent,
-            ))?;
-
-            let j_id = sv2_new_extended_mining_job.job_id;
-            // Create the mining.notify to be sent to the Downstream.
-            // clean_jobs must be false because it's not a NewPrevHash template
-            let notify = crate::proxy::next_mining_notify::create_notify(
-                last_p_hash,
-                sv2_new_extended_mining_job.clone(),
-                false,
-            );
-            // Get the sender to send the mining.notify to the Downstream
-            tx_sv1_notify.send(notify.clone())?;
-            self_
-                .safe_lock(|s| {
-                    s.last_notify = Some(notify);
-                    s.last_job_id = j_id;
-                })
-                .map_err(|_| PoisonLock)?;
-            Ok(())
+        if let Some(notify) = notify {
+            tx_sv1_notify.send(notify)?;
         }
+        Ok(())
     }
 
     /// Receives a SV2 `NewExtendedMiningJob` message from the `Upstream`. If `future_job=true`,
@@ -535,6 +405,7 @@ pub struct OpenSv1Downstream {
 mod test {
     use super::*;
     use async_channel::bounded;
+    use v1::client_to_server::Submit;
 
     use stratum_common::bitcoin::util::psbt::serialize::Serialize;
 
@@ -583,6 +454,8 @@ mod test {
                 Arc::new(Mutex::new(upstream_target)),
                 1,
                 task_collector,
+                None,
+                true,
             );
             (b, interface)
         }
@@ -598,43 +471,45 @@ mod test {
                 id: 0,
             }
         }
+
+        /// Coinbase whose extranonce is the `script_sig`, between the bytes 42 and
+        /// `42 + script_sig_len`
+        pub fn coinbase(script_sig_len: usize) -> Vec<u8> {
+            use stratum_common::{
+                bitcoin,
+                bitcoin::{blockdata::witness::Witness, hashes::Hash},
+            };
+            let out_id = bitcoin::hashes::sha256d::Hash::from_slice(&[0_u8; 32]).unwrap();
+            let p_out = bitcoin::OutPoint {
+                txid: bitcoin::Txid::from_hash(out_id),
+                vout: 0xffff_ffff,
+            };
+            let in_ = bitcoin::TxIn {
+                previous_output: p_out,
+                script_sig: vec![89_u8; script_sig_len].into(),
+                sequence: bitcoin::Sequence(0),
+                witness: Witness::from_vec(vec![]),
+            };
+            let tx = bitcoin::Transaction {
+                version: 1,
+                lock_time: bitcoin::PackedLockTime(0),
+                input: vec![in_],
+                output: vec![],
+            };
+            tx.serialize()
+        }
     }
 
     #[test]
     fn test_version_bits_insert() {
-        use stratum_common::{
-            bitcoin,
-            bitcoin::{blockdata::witness::Witness, hashes::Hash},
-        };
-
         let extranonces = ExtendedExtranonce::new(0..6, 6..8, 8..16);
         let (bridge, _) = test_utils::create_bridge(extranonces);
         bridge
             .safe_lock(|bridge| {
                 let channel_id = 1;
-                let out_id = bitcoin::hashes::sha256d::Hash::from_slice(&[
-                    0_u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0,
-                ])
-                .unwrap();
-                let p_out = bitcoin::OutPoint {
-                    txid: bitcoin::Txid::from_hash(out_id),
-                    vout: 0xffff_ffff,
-                };
-                let in_ = bitcoin::TxIn {
-                    previous_output: p_out,
-                    script_sig: vec![89_u8; 16].into(),
-                    sequence: bitcoin::Sequence(0),
-                    witness: Witness::from_vec(vec![]),
-                };
-                let tx = bitcoin::Transaction {
-                    version: 1,
-                    lock_time: bitcoin::PackedLockTime(0),
-                    input: vec![in_],
-                    output: vec![],
-                };
-                let tx = tx.serialize();
+                let tx = test_utils::coinbase(16);
                 let _down = bridge
+                    .translator
                     .channel_factory
                     .add_standard_channel(0, 10_000_000_000.0, true, 1)
                     .unwrap();
@@ -649,7 +524,11 @@ mod test {
                     min_ntime: 989898,
                     nbits: 9,
                 };
-                bridge.channel_factory.on_new_prev_hash(prev_hash).unwrap();
+                bridge
+                    .translator
+                    .channel_factory
+                    .on_new_prev_hash(prev_hash)
+                    .unwrap();
                 let now = std::time::SystemTime::now()
                     .duration_since(std::time::UNIX_EPOCH)
                     .unwrap()
@@ -665,6 +544,7 @@ mod test {
                     coinbase_tx_suffix: tx[58..].to_vec().try_into().unwrap(),
                 };
                 bridge
+                    .translator
                     .channel_factory
                     .on_new_extended_mining_job(new_mining_job.clone())
                     .unwrap();
@@ -672,6 +552,7 @@ mod test {
                 // pass sv1_submit into Bridge::translate_submit
                 let sv1_submit = test_utils::create_sv1_submit(0);
                 let sv2_message = bridge
+                    .translator
                     .translate_submit(channel_id, sv1_submit, None)
                     .unwrap();
                 // assert sv2 message equals sv1 with version bits added
@@ -682,4 +563,138 @@ mod test {
             })
             .unwrap();
     }
+
+    #[test]
+    fn test_version_rolling_checks() {
+        let extranonces = ExtendedExtranonce::new(0..6, 6..8, 8..16);
+        let (bridge, _) = test_utils::create_bridge(extranonces);
+        bridge
+            .safe_lock(|bridge| {
+                let channel_id = 1;
+                let tx = test_utils::coinbase(16);
+                let prev_hash = SetNewPrevHash {
+                    channel_id,
+                    job_id: 0,
+                    prev_hash: [3; 32].into(),
+                    min_ntime: 989898,
+                    nbits: 9,
+                };
+                bridge
+                    .translator
+                    .channel_factory
+                    .on_new_prev_hash(prev_hash)
+                    .unwrap();
+                let new_mining_job = NewExtendedMiningJob {
+                    channel_id,
+                    job_id: 0,
+                    min_ntime: binary_sv2::Sv2Option::new(Some(989898)),
+                    version: 0x2000_0000,
+                    version_rolling_allowed: false,
+                    merkle_path: vec![].into(),
+                    coinbase_tx_prefix: tx[0..42].to_vec().try_into().unwrap(),
+                    coinbase_tx_suffix: tx[58..].to_vec().try_into().unwrap(),
+                };
+                bridge
+                    .translator
+                    .channel_factory
+                    .on_new_extended_mining_job(new_mining_job)
+                    .unwrap();
+                let mask = Some(v1::utils::HexU32Be(0x1fff_e000));
+
+                // The version bits are outside the negotiated mask
+                let mut sv1_submit = test_utils::create_sv1_submit(0);
+                sv1_submit.version_bits = Some(v1::utils::HexU32Be(0x0000_0001));
+                assert!(bridge
+                    .translator
+                    .translate_submit(channel_id, sv1_submit, mask.clone())
+                    .is_err());
+
+                // The version bits are in the mask but the job does not allow to roll them
+                let mut sv1_submit = test_utils::create_sv1_submit(0);
+                sv1_submit.version_bits = Some(v1::utils::HexU32Be(0x0000_e000));
+                let sv2_submit = bridge
+                    .translator
+                    .translate_submit(channel_id, sv1_submit, mask)
+                    .unwrap();
+                assert_eq!(sv2_submit.version, 0x2000_e000);
+                match bridge.translator.on_submit_shares_extended(sv2_submit) {
+                    Ok(SubmitOutcome::Rejected(error_code)) => {
+                        assert_eq!(
+                            error_code,
+                            roles_logic_sv2::share_validation::INVALID_VERSION
+                        )
+                    }
+                    _ => panic!(),
+                }
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_open_sv1_downstream_without_extranonces() {
+        // One byte to tell the SV1 downstreams apart
+        let extranonces = ExtendedExtranonce::new(0..6, 6..7, 7..16);
+        let (bridge, _) = test_utils::create_bridge(extranonces);
+        bridge
+            .safe_lock(|bridge| {
+                for _ in 0..255 {
+                    let channel = bridge.translator.open_sv1_downstream(10_000_000.0).unwrap();
+                    assert_eq!(channel.extranonce2_len, 9);
+                }
+                assert!(matches!(
+                    bridge.translator.open_sv1_downstream(10_000_000.0),
+                    Err(Error::SubprotocolMining(_))
+                ));
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_future_job_notify_on_prev_hash() {
+        let extranonces = ExtendedExtranonce::new(0..6, 6..8, 8..16);
+        let (bridge, _) = test_utils::create_bridge(extranonces);
+        bridge
+            .safe_lock(|bridge| {
+                // The notify is created with an extranonce of 32 bytes
+                let tx = test_utils::coinbase(32);
+                let future_job = |job_id| NewExtendedMiningJob {
+                    channel_id: 1,
+                    job_id,
+                    min_ntime: binary_sv2::Sv2Option::new(None),
+                    version: 0x2000_0000,
+                    version_rolling_allowed: false,
+                    merkle_path: vec![].into(),
+                    coinbase_tx_prefix: tx[0..42].to_vec().try_into().unwrap(),
+                    coinbase_tx_suffix: tx[74..].to_vec().try_into().unwrap(),
+                };
+                for job_id in [1, 2] {
+                    let notify = bridge
+                        .translator
+                        .on_new_extended_mining_job(future_job(job_id))
+                        .unwrap();
+                    assert!(notify.is_none());
+                }
+                assert!(bridge.translator.has_future_job(2));
+
+                let prev_hash = SetNewPrevHash {
+                    channel_id: 1,
+                    job_id: 2,
+                    prev_hash: [3; 32].into(),
+                    min_ntime: 989898,
+                    nbits: 9,
+                };
+                let notify = bridge
+                    .translator
+                    .on_set_new_prev_hash(prev_hash)
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(notify.job_id, "2");
+                assert!(notify.clean_jobs);
+                assert_eq!(notify.time.0, 989898);
+                // The job built on the old prev hash is dropped
+                assert!(!bridge.translator.has_future_job(1));
+                assert!(!bridge.translator.has_future_job(2));
+            })
+            .unwrap();
+    }
 }
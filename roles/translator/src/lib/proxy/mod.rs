@@ -1,3 +1,5 @@
 pub mod bridge;
 pub mod next_mining_notify;
+pub mod translator;
 pub use bridge::Bridge;
+pub use translator::{SubmitOutcome, Sv1Channel, Translator};
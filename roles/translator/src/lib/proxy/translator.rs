@@ -0,0 +1,262 @@
+//! SV1 <-> SV2 translation without IO.
+//!
+//! [`Translator`] holds the extended channel opened with the SV2 upstream and the SV1 downstreams
+//! opened on it. SV2 `SetNewPrevHash` and `NewExtendedMiningJob` messages are fed in and turned
+//! into SV1 `mining.notify` messages, SV1 `mining.submit` messages are fed in and turned into SV2
+//! `SubmitSharesExtended` messages when they meet the upstream target. The caller moves the
+//! messages between the connections, [`super::Bridge`] does it with the channels of the proxy.
+use super::{super::error::Error, next_mining_notify::create_notify};
+use crate::error::ProxyResult;
+use roles_logic_sv2::{
+    channel_logic::channel_factory::{
+        ExtendedChannelKind, OnNewShare, ProxyExtendedChannelFactory, Share,
+    },
+    mining_sv2::{
+        version_rolling, ExtendedExtranonce, NewExtendedMiningJob, SetNewPrevHash,
+        SubmitSharesExtended, Target,
+    },
+    parsers::Mining,
+    share_validation::NtimeLimits,
+    utils::{GroupId, Mutex},
+    Error as RolesLogicError,
+};
+use std::{collections::HashMap, sync::Arc};
+use v1::{client_to_server::Submit, server_to_client, utils::HexU32Be};
+
+/// SV1 downstream opened on the extended channel
+#[derive(Debug, Clone)]
+pub struct Sv1Channel {
+    pub channel_id: u32,
+    /// Sent with `mining.subscribe`
+    pub extranonce: Vec<u8>,
+    pub extranonce2_len: u16,
+    pub target: Vec<u8>,
+    /// Job to send after `mining.authorize`, None if no job has been received yet
+    pub last_notify: Option<server_to_client::Notify<'static>>,
+}
+
+/// What to do with a translated share
+#[derive(Debug)]
+pub enum SubmitOutcome {
+    /// The share meets the upstream target, send it upstream
+    SendUpstream(SubmitSharesExtended<'static>),
+    /// The share only meets the downstream target
+    MeetsDownstreamTarget,
+    /// The share is invalid, with the SV2 error code
+    Rejected(String),
+}
+
+#[derive(Debug)]
+pub struct Translator {
+    pub(crate) channel_factory: ProxyExtendedChannelFactory,
+    /// Jobs waiting for their `SetNewPrevHash`, by upstream job id
+    future_jobs: HashMap<u32, NewExtendedMiningJob<'static>>,
+    last_p_hash: Option<SetNewPrevHash<'static>>,
+    /// Sent to the downstreams that connect after the job has been received
+    last_notify: Option<server_to_client::Notify<'static>>,
+    last_job_id: u32,
+}
+
+impl Translator {
+    /// `extranonces` and `up_id` come from the `OpenExtendedMiningChannelSuccess` of the
+    /// upstream, the shares below `upstream_target` are sent upstream
+    pub fn new(
+        extranonces: ExtendedExtranonce,
+        upstream_target: Target,
+        up_id: u32,
+        ntime_limits: Option<NtimeLimits>,
+    ) -> Self {
+        let ids = Arc::new(Mutex::new(GroupId::new()));
+        let share_per_min = 1.0;
+        let mut channel_factory = ProxyExtendedChannelFactory::new(
+            ids,
+            extranonces,
+            None,
+            share_per_min,
+            ExtendedChannelKind::Proxy { upstream_target },
+            None,
+            String::from(""),
+            up_id,
+        );
+        channel_factory.set_ntime_limits(ntime_limits);
+        Self {
+            channel_factory,
+            future_jobs: HashMap::new(),
+            last_p_hash: None,
+            last_notify: None,
+            last_job_id: 0,
+        }
+    }
+
+    /// Opens a SV1 downstream with `hash_rate` on the extended channel
+    #[allow(clippy::result_large_err)]
+    pub fn open_sv1_downstream(&mut self, hash_rate: f32) -> ProxyResult<'static, Sv1Channel> {
+        // The SV1 downstream gets all the extranonce bytes reserved for the downstreams
+        let extranonce2_len = self.channel_factory.downstream_extranonce_size() as u16;
+        let messages = self
+            .channel_factory
+            .new_extended_channel(0, hash_rate, extranonce2_len)
+            .map_err(|_| {
+                Error::SubprotocolMining("Bridge: failed to open new extended channel".to_string())
+            })?;
+        for message in messages {
+            match message {
+                Mining::OpenExtendedMiningChannelSuccess(success) => {
+                    return Ok(Sv1Channel {
+                        channel_id: success.channel_id,
+                        extranonce: success.extranonce_prefix.to_vec(),
+                        extranonce2_len: success.extranonce_size,
+                        target: success.target.to_vec(),
+                        last_notify: self.last_notify.clone(),
+                    });
+                }
+                Mining::OpenMiningChannelError(e) => {
+                    return Err(Error::SubprotocolMining(format!(
+                        "Bridge: failed to open new extended channel: {}",
+                        String::from_utf8_lossy(e.error_code.as_ref())
+                    )))
+                }
+                Mining::SetNewPrevHash(_) => (),
+                Mining::NewExtendedMiningJob(_) => (),
+                _ => unreachable!(),
+            }
+        }
+        Err(Error::SubprotocolMining(
+            "Bridge: Invalid mining message when opening downstream connection".to_string(),
+        ))
+    }
+
+    /// Returns the `mining.notify` with `clean_jobs` set for the future job of `prev_hash`, None
+    /// if the job has not been received. The other future jobs are dropped, they were built on
+    /// the previous prev hash.
+    #[allow(clippy::result_large_err)]
+    pub fn on_set_new_prev_hash(
+        &mut self,
+        prev_hash: SetNewPrevHash<'static>,
+    ) -> ProxyResult<'static, Option<server_to_client::Notify<'static>>> {
+        self.last_p_hash = Some(prev_hash.clone());
+        self.channel_factory.on_new_prev_hash(prev_hash.clone())?;
+
+        let job = self.future_jobs.remove(&prev_hash.job_id);
+        self.future_jobs.clear();
+        match job {
+            Some(job) => {
+                let job_id = job.job_id;
+                let notify = create_notify(prev_hash, job, true);
+                self.last_notify = Some(notify.clone());
+                self.last_job_id = job_id;
+                Ok(Some(notify))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// True if the future job `job_id` has been received and is waiting for its
+    /// `SetNewPrevHash`
+    pub fn has_future_job(&self, job_id: u32) -> bool {
+        self.future_jobs.contains_key(&job_id)
+    }
+
+    /// Returns the `mining.notify` of a job for the current prev hash, None for a future job that
+    /// is kept until its `SetNewPrevHash`
+    #[allow(clippy::result_large_err)]
+    pub fn on_new_extended_mining_job(
+        &mut self,
+        job: NewExtendedMiningJob<'static>,
+    ) -> ProxyResult<'static, Option<server_to_client::Notify<'static>>> {
+        self.channel_factory
+            .on_new_extended_mining_job(job.as_static().clone())?;
+        if job.is_future() {
+            self.future_jobs.insert(job.job_id, job);
+            return Ok(None);
+        }
+        let last_p_hash = self.last_p_hash.clone().ok_or(Error::RolesSv2Logic(
+            RolesLogicError::JobIsNotFutureButPrevHashNotPresent,
+        ))?;
+        let job_id = job.job_id;
+        // clean_jobs must be false because it's not a NewPrevHash template
+        let notify = create_notify(last_p_hash, job, false);
+        self.last_notify = Some(notify.clone());
+        self.last_job_id = job_id;
+        Ok(Some(notify))
+    }
+
+    /// Translates a SV1 `mining.submit` message to a SV2 `SubmitSharesExtended` message.
+    #[allow(clippy::result_large_err)]
+    pub fn translate_submit(
+        &self,
+        channel_id: u32,
+        sv1_submit: Submit,
+        version_rolling_mask: Option<HexU32Be>,
+    ) -> ProxyResult<'static, SubmitSharesExtended<'static>> {
+        let last_version = self
+            .channel_factory
+            .last_valid_job_version()
+            .ok_or(Error::RolesSv2Logic(RolesLogicError::NoValidJob))?;
+        let version = match (sv1_submit.version_bits, version_rolling_mask) {
+            // regarding version masking see https://github.com/slushpool/stratumprotocol/blob/master/stratum-extensions.mediawiki#changes-in-request-miningsubmit
+            // version_bits only carries the rolled bits, that must be in the negotiated mask. If
+            // the job does not allow version rolling the share is rejected by the channel factory.
+            (Some(vb), Some(mask)) => {
+                version_rolling::check_version_rolling(0, vb.0, mask.0)
+                    .map_err(|_| Error::V1Protocol(v1::error::Error::InvalidSubmission))?;
+                version_rolling::apply_rolled_bits(last_version, vb.0, mask.0)
+            }
+            (None, None) => last_version,
+            _ => return Err(Error::V1Protocol(v1::error::Error::InvalidSubmission)),
+        };
+        let mining_device_extranonce: Vec<u8> = sv1_submit.extra_nonce2.into();
+        let extranonce2 = mining_device_extranonce;
+        Ok(SubmitSharesExtended {
+            channel_id,
+            // I put 0 below cause sequence_number is not what should be TODO
+            sequence_number: 0,
+            job_id: sv1_submit.job_id.parse::<u32>()?,
+            nonce: sv1_submit.nonce.0,
+            ntime: sv1_submit.time.0,
+            version,
+            extranonce: extranonce2.try_into()?,
+        })
+    }
+
+    /// Validates a share translated with [`Translator::translate_submit`]
+    pub fn on_submit_shares_extended(
+        &mut self,
+        share: SubmitSharesExtended<'static>,
+    ) -> Result<SubmitOutcome, RolesLogicError> {
+        match self.channel_factory.on_submit_shares_extended(share)? {
+            OnNewShare::SendErrorDownstream(e) => Ok(SubmitOutcome::Rejected(
+                String::from_utf8_lossy(&e.error_code.to_vec()).into_owned(),
+            )),
+            OnNewShare::SendSubmitShareUpstream((Share::Extended(share), _)) => {
+                Ok(SubmitOutcome::SendUpstream(share))
+            }
+            // We are in an extended channel shares are extended
+            OnNewShare::SendSubmitShareUpstream((Share::Standard(_), _)) => unreachable!(),
+            // We are in an extended channel this variant is group channle only
+            OnNewShare::RelaySubmitShareUpstream => unreachable!(),
+            OnNewShare::ShareMeetDownstreamTarget => Ok(SubmitOutcome::MeetsDownstreamTarget),
+            // Proxy do not have JD capabilities
+            OnNewShare::ShareMeetBitcoinTarget(..) => unreachable!(),
+        }
+    }
+
+    /// Called when the upstream changes the target of the extended channel
+    pub fn set_upstream_target(&mut self, mut target: Target) {
+        self.channel_factory.set_target(&mut target);
+    }
+
+    /// Called when the difficulty of a SV1 downstream is updated, see [`crate::difficulty`]
+    pub fn update_downstream_target(&mut self, channel_id: u32, target: Target) {
+        self.channel_factory
+            .update_target_for_channel(channel_id, target);
+    }
+
+    pub fn last_notify(&self) -> Option<&server_to_client::Notify<'static>> {
+        self.last_notify.as_ref()
+    }
+
+    pub fn last_job_id(&self) -> u32 {
+        self.last_job_id
+    }
+}
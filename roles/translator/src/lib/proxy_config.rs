@@ -1,5 +1,12 @@
+use config_helpers_sv2::{Validate, Validator};
 use key_utils::Secp256k1PublicKey;
+use roles_logic_sv2::share_validation::{NtimeLimits, DEFAULT_NTIME_FUTURE_TOLERANCE};
 use serde::Deserialize;
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+use v1::PolicyTable;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ProxyConfig {
@@ -13,6 +20,69 @@ pub struct ProxyConfig {
     pub min_extranonce2_size: u16,
     pub downstream_difficulty_config: DownstreamDifficultyConfig,
     pub upstream_difficulty_config: UpstreamDifficultyConfig,
+    #[serde(default)]
+    pub connection_limits: ConnectionLimitsConfig,
+    /// Checks of the requests received from the downstreams, off by default
+    #[serde(default)]
+    pub sv1_validation: PolicyTable,
+    /// Shares whose ntime is rolled more than this many seconds ahead of the min_ntime of the
+    /// last prev hash plus the time since it was received are rejected, the ntime of the shares
+    /// is not checked if not set
+    pub max_ntime_drift_sec: Option<u32>,
+    /// Shares whose ntime is more than this many seconds ahead of the local clock are rejected,
+    /// only used when `max_ntime_drift_sec` is set
+    #[serde(default = "default_ntime_future_tolerance_sec")]
+    pub ntime_future_tolerance_sec: u32,
+    /// Sends the `mining.notify` of a `SetNewPrevHash` as soon as it is received when its future
+    /// job is already known, instead of waiting for the last job received to be handled
+    #[serde(default)]
+    pub cache_future_jobs: bool,
+    /// Address where the metrics are served in the OpenMetrics text format, not served if not set
+    pub metrics_address: Option<String>,
+    /// Localhost address of the admin interface (log level, connections, channels and state
+    /// snapshot), not served if not set
+    pub admin_address: Option<String>,
+    /// Upstreams that can be connected to instead of the main one, see `upstream_selection`
+    #[serde(default)]
+    pub backup_upstreams: Vec<UpstreamAddress>,
+    /// How the upstream is picked among the main and the backup ones at every (re)connection,
+    /// `Priority` if not set
+    #[serde(default)]
+    pub upstream_selection: UpstreamSelection,
+}
+
+/// Upstream the proxy can connect to
+#[derive(Debug, Deserialize, Clone)]
+pub struct UpstreamAddress {
+    pub address: String,
+    pub port: u16,
+    pub authority_pubkey: Secp256k1PublicKey,
+}
+
+impl UpstreamAddress {
+    pub fn socket_address(&self) -> SocketAddr {
+        SocketAddr::new(
+            IpAddr::from_str(&self.address).expect("Failed to parse upstream address!"),
+            self.port,
+        )
+    }
+}
+
+/// How the upstream is picked among the reachable ones
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UpstreamSelection {
+    /// The main upstream, then the backup ones in the order of `backup_upstreams`
+    #[default]
+    Priority,
+    /// The upstreams in turn
+    RoundRobin,
+    /// The upstream with the lowest average round trip of the `SetupConnection` of the previous
+    /// connections
+    LowestLatency,
+}
+
+fn default_ntime_future_tolerance_sec() -> u32 {
+    DEFAULT_NTIME_FUTURE_TOLERANCE
 }
 
 pub struct UpstreamConfig {
@@ -73,6 +143,80 @@ impl ProxyConfig {
             min_extranonce2_size,
             downstream_difficulty_config: downstream.difficulty_config,
             upstream_difficulty_config: upstream.difficulty_config,
+            connection_limits: ConnectionLimitsConfig::default(),
+            sv1_validation: PolicyTable::default(),
+            max_ntime_drift_sec: None,
+            ntime_future_tolerance_sec: default_ntime_future_tolerance_sec(),
+            cache_future_jobs: false,
+            metrics_address: None,
+            admin_address: None,
+            backup_upstreams: Vec::new(),
+            upstream_selection: UpstreamSelection::default(),
+        }
+    }
+
+    /// The main upstream followed by the backup ones
+    pub fn upstreams(&self) -> Vec<UpstreamAddress> {
+        let main = UpstreamAddress {
+            address: self.upstream_address.clone(),
+            port: self.upstream_port,
+            authority_pubkey: self.upstream_authority_pubkey,
+        };
+        std::iter::once(main)
+            .chain(self.backup_upstreams.iter().cloned())
+            .collect()
+    }
+
+    /// Limits of the ntime of the shares, None if it is not checked
+    pub fn ntime_limits(&self) -> Option<NtimeLimits> {
+        self.max_ntime_drift_sec
+            .map(|drift| NtimeLimits::new(drift, self.ntime_future_tolerance_sec))
+    }
+}
+
+impl Validate for ProxyConfig {
+    fn validate(&self, v: &mut Validator) {
+        v.socket_address(
+            "upstream_address",
+            &format!("{}:{}", self.upstream_address, self.upstream_port),
+        );
+        for (index, backup) in self.backup_upstreams.iter().enumerate() {
+            v.socket_address(
+                &format!("backup_upstreams[{}]", index),
+                &format!("{}:{}", backup.address, backup.port),
+            );
+        }
+        v.ip_address("downstream_address", &self.downstream_address);
+        v.ordered(
+            ("min_supported_version", self.min_supported_version),
+            ("max_supported_version", self.max_supported_version),
+        );
+        v.range("min_extranonce2_size", self.min_extranonce2_size, 1, 32);
+        let downstream = &self.downstream_difficulty_config;
+        v.check(
+            downstream.shares_per_minute > 0.0,
+            "downstream_difficulty_config.shares_per_minute must be greater than 0",
+        );
+        v.check(
+            downstream.min_individual_miner_hashrate > 0.0,
+            "downstream_difficulty_config.min_individual_miner_hashrate must be greater than 0",
+        );
+        v.check(
+            self.upstream_difficulty_config.channel_diff_update_interval > 0,
+            "upstream_difficulty_config.channel_diff_update_interval must be greater than 0",
+        );
+        let limits = &self.connection_limits;
+        if let (Some(per_ip), Some(max)) = (limits.max_connections_per_ip, limits.max_connections) {
+            v.ordered(
+                ("connection_limits.max_connections_per_ip", per_ip),
+                ("connection_limits.max_connections", max),
+            );
+        }
+        if let Some(metrics_address) = &self.metrics_address {
+            v.socket_address("metrics_address", metrics_address);
+        }
+        if let Some(admin_address) = &self.admin_address {
+            v.socket_address("admin_address", admin_address);
         }
     }
 }
@@ -134,3 +278,65 @@ impl UpstreamDifficultyConfig {
         }
     }
 }
+
+/// Limits of the SV1 downstream connections, every limit is disabled if not set
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConnectionLimitsConfig {
+    pub max_connections: Option<usize>,
+    pub max_connections_per_ip: Option<usize>,
+    /// New connections accepted per minute from the same ip
+    pub max_new_connections_per_ip_per_min: Option<u32>,
+    /// Time given to a new connection to authorize before it is closed
+    #[serde(default = "default_unauthorized_timeout_sec")]
+    pub unauthorized_timeout_sec: u64,
+}
+
+impl Default for ConnectionLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: None,
+            max_connections_per_ip: None,
+            max_new_connections_per_ip_per_min: None,
+            unauthorized_timeout_sec: default_unauthorized_timeout_sec(),
+        }
+    }
+}
+
+fn default_unauthorized_timeout_sec() -> u64 {
+    crate::downstream_sv1::SUBSCRIBE_TIMEOUT_SECS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_config_validation() {
+        for path in [
+            "./config-examples/tproxy-config-hosted-pool-example.toml",
+            "./config-examples/tproxy-config-local-jdc-example.toml",
+            "./config-examples/tproxy-config-local-pool-example.toml",
+        ] {
+            config_helpers_sv2::load::<ProxyConfig>(Path::new(path), "TPROXY").unwrap();
+        }
+
+        let mut config: ProxyConfig = config_helpers_sv2::load(
+            Path::new("./config-examples/tproxy-config-local-pool-example.toml"),
+            "TPROXY",
+        )
+        .unwrap();
+        config.downstream_address = "localhost".to_string();
+        config.min_supported_version = 3;
+        config.connection_limits.max_connections = Some(10);
+        config.connection_limits.max_connections_per_ip = Some(20);
+        let mut v = Validator::default();
+        config.validate(&mut v);
+        match v.finish() {
+            Err(config_helpers_sv2::ConfigError::Invalid(errors)) => {
+                assert_eq!(errors.len(), 3, "{:?}", errors)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
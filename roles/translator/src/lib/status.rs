@@ -127,6 +127,8 @@ pub async fn handle_error(
         Error::BadConfigDeserialize(_) => {
             send_status(sender, e, error_handling::ErrorBranch::Break).await
         }
+        // Errors on a config with invalid values.
+        Error::InvalidConfig(_) => send_status(sender, e, error_handling::ErrorBranch::Break).await,
         // Errors from `binary_sv2` crate.
         Error::BinarySv2(_) => send_status(sender, e, error_handling::ErrorBranch::Break).await,
         // Errors on bad noise handshake.
@@ -16,10 +16,11 @@ use error_handling::handle_result;
 use key_utils::Secp256k1PublicKey;
 use network_helpers_sv2::Connection;
 use roles_logic_sv2::{
-    common_messages_sv2::{Protocol, SetupConnection},
+    common_messages_sv2::{DeviceInfo, Endpoint, Protocol, SetupConnection},
     common_properties::{IsMiningUpstream, IsUpstream},
     handlers::{
         common::{ParseUpstreamCommonMessages, SendTo as SendToCommon},
+        interceptor::Interceptor,
         mining::{ParseUpstreamMiningMessages, SendTo},
     },
     mining_sv2::{
@@ -36,6 +37,7 @@ use roles_logic_sv2::{
 use std::{
     net::SocketAddr,
     sync::{atomic::AtomicBool, Arc},
+    time::Instant,
 };
 use tokio::{
     task::AbortHandle,
@@ -102,6 +104,8 @@ pub struct Upstream {
     // than the configured percentage
     pub(super) difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
     task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+    /// Called with the messages received from the SV2 Upstream role before they are handled.
+    interceptor: Option<Arc<dyn Interceptor>>,
 }
 
 impl PartialEq for Upstream {
@@ -129,6 +133,7 @@ impl Upstream {
         target: Arc<Mutex<Vec<u8>>>,
         difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
         task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+        interceptor: Option<Arc<dyn Interceptor>>,
     ) -> ProxyResult<'static, Arc<Mutex<Self>>> {
         // Connect to the SV2 Upstream role retry connection every 5 seconds.
         let socket = loop {
@@ -178,17 +183,19 @@ impl Upstream {
             target,
             difficulty_config,
             task_collector,
+            interceptor,
         })))
     }
 
-    /// Setups the connection with the SV2 Upstream role (most typically a SV2 Pool).
+    /// Setups the connection with the SV2 Upstream role (most typically a SV2 Pool). Returns the
+    /// round trip of the `SetupConnection`, that is the latency of the upstream.
     pub async fn connect(
         self_: Arc<Mutex<Self>>,
         min_version: u16,
         max_version: u16,
-    ) -> ProxyResult<'static, ()> {
+    ) -> ProxyResult<'static, Duration> {
         // Get the `SetupConnection` message with Mining Device information (currently hard coded)
-        let setup_connection = Self::get_setup_connection_message(min_version, max_version, false)?;
+        let setup_connection = Self::get_setup_connection_message(min_version, max_version, false);
         let mut connection = self_
             .safe_lock(|s| s.connection.clone())
             .map_err(|_e| PoisonLock)?;
@@ -197,6 +204,7 @@ impl Upstream {
         let sv2_frame: StdFrame = Message::Common(setup_connection.into()).try_into()?;
         // Send the `SetupConnection` frame to the SV2 Upstream role
         // Only one Upstream role is supported, panics if multiple connections are encountered
+        let sent_at = Instant::now();
         connection.send(sv2_frame).await?;
 
         // Wait for the SV2 Upstream to respond with either a `SetupConnectionSuccess` or a
@@ -210,6 +218,7 @@ impl Upstream {
                 ));
             }
         };
+        let round_trip = sent_at.elapsed();
 
         // Gets the binary frame message type from the message header
         let message_type = if let Some(header) = incoming.get_header() {
@@ -259,7 +268,7 @@ impl Upstream {
         let sv2_frame: StdFrame = Message::Mining(open_channel).try_into()?;
         connection.send(sv2_frame).await?;
 
-        Ok(())
+        Ok(round_trip)
     }
 
     /// Parses the incoming SV2 message from the Upstream role and routes the message to the
@@ -539,33 +548,25 @@ impl Upstream {
     /// Creates the `SetupConnection` message to setup the connection with the SV2 Upstream role.
     /// TODO: The Mining Device information is hard coded here, need to receive from Downstream
     /// instead.
-    #[allow(clippy::result_large_err)]
     fn get_setup_connection_message(
         min_version: u16,
         max_version: u16,
         is_work_selection_enabled: bool,
-    ) -> ProxyResult<'static, SetupConnection<'static>> {
-        let endpoint_host = "0.0.0.0".to_string().into_bytes().try_into()?;
-        let vendor = String::new().try_into()?;
-        let hardware_version = String::new().try_into()?;
-        let firmware = String::new().try_into()?;
-        let device_id = String::new().try_into()?;
-        let flags = match is_work_selection_enabled {
+    ) -> SetupConnection<'static> {
+        // Infallible unwrap the host is valid
+        let endpoint = Endpoint::new("0.0.0.0", 50).unwrap();
+        let mut setup_connection = SetupConnection::for_protocol(
+            Protocol::MiningProtocol,
+            DeviceInfo::default(),
+            endpoint,
+        );
+        setup_connection.min_version = min_version;
+        setup_connection.max_version = max_version;
+        setup_connection.flags = match is_work_selection_enabled {
             false => 0b0000_0000_0000_0000_0000_0000_0000_0100,
             true => 0b0000_0000_0000_0000_0000_0000_0000_0110,
         };
-        Ok(SetupConnection {
-            protocol: Protocol::MiningProtocol,
-            min_version,
-            max_version,
-            flags,
-            endpoint_host,
-            endpoint_port: 50,
-            vendor,
-            hardware_version,
-            firmware,
-            device_id,
-        })
+        setup_connection
     }
 }
 
@@ -647,6 +648,10 @@ impl ParseUpstreamMiningMessages<Downstream, NullDownstreamMiningSelector, NoRou
         roles_logic_sv2::handlers::mining::SupportedChannelTypes::Extended
     }
 
+    fn get_interceptor(&self) -> Option<Arc<dyn Interceptor>> {
+        self.interceptor.clone()
+    }
+
     /// Work selection is disabled for SV1/SV2 Translator Proxy and all work selection is performed
     /// by the SV2 Upstream role.
     fn is_work_selection_enabled(&self) -> bool {
@@ -687,6 +692,8 @@ impl ParseUpstreamMiningMessages<Downstream, NullDownstreamMiningSelector, NoRou
             .map_err(|e| RolesLogicError::PoisonLock(e.to_string()))?;
 
         info!("Up: Successfully Opened Extended Mining Channel");
+        // The translator has a single channel, the one of a previous upstream is gone
+        monitoring_sv2::metrics().channels_open.set(1);
         self.channel_id = Some(m.channel_id);
         self.extranonce_prefix = Some(m.extranonce_prefix.to_vec());
         let m = Mining::OpenExtendedMiningChannelSuccess(m.into_static());
@@ -718,6 +725,7 @@ impl ParseUpstreamMiningMessages<Downstream, NullDownstreamMiningSelector, NoRou
         &mut self,
         m: roles_logic_sv2::mining_sv2::CloseChannel,
     ) -> Result<roles_logic_sv2::handlers::mining::SendTo<Downstream>, RolesLogicError> {
+        monitoring_sv2::metrics().channels_open.set(0);
         Ok(SendTo::None(Some(Mining::CloseChannel(m.as_static()))))
     }
 
@@ -732,16 +740,20 @@ impl ParseUpstreamMiningMessages<Downstream, NullDownstreamMiningSelector, NoRou
     /// Handles the SV2 `SubmitSharesSuccess` message.
     fn handle_submit_shares_success(
         &mut self,
-        _m: roles_logic_sv2::mining_sv2::SubmitSharesSuccess,
+        m: roles_logic_sv2::mining_sv2::SubmitSharesSuccess,
     ) -> Result<roles_logic_sv2::handlers::mining::SendTo<Downstream>, RolesLogicError> {
+        monitoring_sv2::metrics()
+            .shares_accepted
+            .inc_by(m.new_submits_accepted_count.into());
         Ok(SendTo::None(None))
     }
 
     /// Handles the SV2 `SubmitSharesError` message.
     fn handle_submit_shares_error(
         &mut self,
-        _m: roles_logic_sv2::mining_sv2::SubmitSharesError,
+        m: roles_logic_sv2::mining_sv2::SubmitSharesError,
     ) -> Result<roles_logic_sv2::handlers::mining::SendTo<Downstream>, RolesLogicError> {
+        monitoring_sv2::metrics().on_share_error(m.error_code.as_ref());
         Ok(SendTo::None(None))
     }
 
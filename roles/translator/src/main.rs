@@ -4,11 +4,11 @@ mod lib;
 
 use args::Args;
 use error::{Error, ProxyResult};
-pub use lib::{downstream_sv1, error, proxy, proxy_config, status, upstream_sv2};
+#[cfg(feature = "mock_upstream")]
+pub use lib::mock_upstream;
+pub use lib::{difficulty, downstream_sv1, error, proxy, proxy_config, status, upstream_sv2};
 use proxy_config::ProxyConfig;
 
-use ext_config::{Config, File, FileFormat};
-
 use tracing::{error, info};
 
 /// Process CLI args, if any.
@@ -20,28 +20,21 @@ fn process_cli_args<'a>() -> ProxyResult<'a, ProxyConfig> {
         Error::BadCliArgs
     })?;
 
-    // Build configuration from the provided file path
-    let config_path = args.config_path.to_str().ok_or_else(|| {
-        error!("Invalid configuration path.");
-        Error::BadCliArgs
-    })?;
-
-    let settings = Config::builder()
-        .add_source(File::new(config_path, FileFormat::Toml))
-        .build()?;
-
-    // Deserialize settings into ProxyConfig
-    let config = settings.try_deserialize::<ProxyConfig>()?;
+    // Load the configuration from the provided file path
+    let config = config_helpers_sv2::load::<ProxyConfig>(&args.config_path, "TPROXY")?;
     Ok(config)
 }
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt::init();
+    logging_sv2::init();
 
     let proxy_config = match process_cli_args() {
         Ok(p) => p,
-        Err(e) => panic!("failed to load config: {}", e),
+        Err(e) => {
+            error!("failed to load config: {}", e);
+            return;
+        }
     };
     info!("Proxy Config: {:?}", &proxy_config);
 
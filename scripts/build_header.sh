@@ -12,5 +12,6 @@ cd "$dir"
   cbindgen --crate common_messages_sv2 >> ../scripts/sv2.h
   cbindgen --crate template_distribution_sv2 >> ../scripts/sv2.h
   cbindgen --crate codec_sv2 >> ../scripts/sv2.h
+  cbindgen --crate noise_sv2 >> ../scripts/sv2.h
   cbindgen --crate sv2_ffi >> ../scripts/sv2.h
 cd ..
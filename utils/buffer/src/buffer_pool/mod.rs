@@ -321,6 +321,25 @@ impl InnerMemory {
     }
 }
 
+/// Counters of the slices returned by a [`BufferPool`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Slices taken from the pool
+    pub hits: u64,
+    /// Slices allocated in system memory because the pool was full
+    pub misses: u64,
+}
+
+impl PoolStats {
+    /// Share of the slices taken from the pool, 1 when no slice has been returned yet
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 1.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct BufferPool<T: Buffer> {
     pool_back: PoolBack,
@@ -331,6 +350,7 @@ pub struct BufferPool<T: Buffer> {
     // Used only when we need as_ref or as_mut, set the first element to the one with index equal
     // to start
     start: usize,
+    stats: PoolStats,
 }
 
 impl BufferPool<BufferFromSystemMemory> {
@@ -342,6 +362,7 @@ impl BufferPool<BufferFromSystemMemory> {
             inner_memory: InnerMemory::new(capacity),
             system_memory: BufferFromSystemMemory::default(),
             start: 0,
+            stats: PoolStats::default(),
         }
     }
 }
@@ -357,6 +378,7 @@ impl BufferPool<TestBufferFromMemory> {
             inner_memory: InnerMemory::new(capacity),
             system_memory: TestBufferFromMemory(Vec::new()),
             start: 0,
+            stats: PoolStats::default(),
         }
     }
 }
@@ -386,6 +408,11 @@ impl<T: Buffer> BufferPool<T> {
         }
     }
 
+    /// Counters of the slices returned until now
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
     #[inline(always)]
     fn reset(&mut self) {
         #[cfg(feature = "debug")]
@@ -590,6 +617,10 @@ impl<T: Buffer> Buffer for BufferPool<T> {
 
     #[inline(always)]
     fn get_data_owned(&mut self) -> Self::Slice {
+        match self.mode {
+            PoolMode::Alloc => self.stats.misses += 1,
+            _ => self.stats.hits += 1,
+        }
         let shared_state = &mut self.shared_state;
 
         #[cfg(feature = "debug")]
@@ -678,6 +709,10 @@ impl<T: Buffer> Buffer for BufferPool<T> {
     fn is_droppable(&self) -> bool {
         self.shared_state.load(Ordering::Relaxed) == 0
     }
+
+    fn is_exhausted(&self) -> bool {
+        self.is_alloc_mode()
+    }
 }
 
 #[cfg(not(test))]
@@ -12,7 +12,7 @@ use alloc::vec::Vec;
 
 pub use crate::buffer::BufferFromSystemMemory;
 pub use aes_gcm::aead::Buffer as AeadBuffer;
-pub use buffer_pool::BufferPool;
+pub use buffer_pool::{BufferPool, PoolStats};
 pub use slice::Slice;
 
 pub enum WriteError {
@@ -86,4 +86,10 @@ pub trait Buffer {
         self.len() == 0
     }
     fn is_droppable(&self) -> bool;
+
+    // Return true when the written data is held in system memory because the buffer is full, only
+    // a pool can be exhausted
+    fn is_exhausted(&self) -> bool {
+        false
+    }
 }
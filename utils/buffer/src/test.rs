@@ -36,6 +36,24 @@ fn pool_capicity_without_alloc() {
     }
 }
 
+#[test]
+fn pool_stats_count_the_slices_allocated_out_of_the_pool() {
+    let mut pool = Pool::new(8 * 5);
+    let mut slices: Vec<Slice> = Vec::new();
+
+    for i in 0..9 {
+        let writable = pool.get_writable(5);
+        writable.copy_from_slice(&[i; 5]);
+        // the pool has room for 8 slices of 5 bytes
+        assert_eq!(pool.is_exhausted(), i == 8);
+        slices.push(pool.get_data_owned());
+    }
+    let stats = pool.stats();
+    assert_eq!((stats.hits, stats.misses), (8, 1));
+    assert_eq!(stats.hit_rate(), 8.0 / 9.0);
+    assert_eq!(slices[8].as_mut(), &[8; 5]);
+}
+
 #[test]
 fn it_drop() {
     let mut rng = rand::thread_rng();
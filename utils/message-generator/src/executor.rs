@@ -182,7 +182,13 @@ impl Executor {
                 } else {
                     message
                 };
-                let frame = EitherFrame::Sv2(message.clone().try_into().unwrap());
+                // A frame without fields to replace is sent as built, so that the manual and the
+                // malformed frames keep their header and payload
+                let frame = if message_.2.is_empty() {
+                    message_.0
+                } else {
+                    EitherFrame::Sv2(message.clone().try_into().unwrap())
+                };
                 debug!("SEND {:#?}", message);
                 match sender.send(frame).await {
                     Ok(_) => (),
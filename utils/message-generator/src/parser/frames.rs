@@ -1,5 +1,6 @@
 use super::sv2_messages::{message_from_path, ReplaceField};
 use codec_sv2::{buffer_sv2::Slice, Sv2Frame};
+use const_sv2::SV2_FRAME_HEADER_SIZE;
 use roles_logic_sv2::parsers::AnyMessage;
 use serde_json::{Map, Value};
 use std::{collections::HashMap, convert::TryInto};
@@ -78,9 +79,50 @@ impl<'a> Frames<'a> {
                             .unwrap();
                     result.insert(id, frame);
                 }
+                "malformed" => {
+                    result.insert(id, malformed_frame(message, frame));
+                }
                 _ => panic!("Unrecognized frames parsing type {}", type_),
             }
         }
         (Frames { frames: result }, messages)
     }
 }
+
+/// Encodes `message` then replaces or truncates its payload and overrides the payload length in
+/// the header, as set in the frame builder
+fn malformed_frame<'a>(
+    message: AnyMessage<'a>,
+    builder: &Value,
+) -> Sv2Frame<AnyMessage<'a>, Slice> {
+    let frame: Sv2Frame<AnyMessage<'a>, Slice> = message.try_into().unwrap();
+    let mut bytes = vec![0; frame.encoded_length()];
+    frame.serialize(&mut bytes).unwrap();
+    let mut payload = bytes.split_off(SV2_FRAME_HEADER_SIZE);
+    if let Some(hex) = builder.get("payload") {
+        payload = from_hex(hex.as_str().unwrap());
+    }
+    if let Some(truncate) = builder.get("truncate") {
+        let truncate = truncate.as_u64().unwrap() as usize;
+        payload.truncate(payload.len().saturating_sub(truncate));
+    }
+    let msg_length = match builder.get("msg_length") {
+        Some(msg_length) => msg_length.as_u64().unwrap() as u32,
+        None => payload.len() as u32,
+    };
+    bytes[3..].copy_from_slice(&msg_length.to_le_bytes()[..3]);
+    bytes.extend(payload);
+    Sv2Frame::from_bytes_unchecked(bytes.into())
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    let hex = hex
+        .strip_prefix("0x")
+        .expect("Frame payload should be an hex value starting with 0x");
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16).expect("Frame payload should be an hex value")
+        })
+        .collect()
+}
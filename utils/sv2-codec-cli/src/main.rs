@@ -0,0 +1,160 @@
+//! Encodes SV2 messages written as JSON into hex payloads and decodes hex payloads back into
+//! JSON, e.g. to write test vectors or to compare the output of another SV2 implementation.
+//!
+//! The JSON of a message is the one used by the message generator tests.
+use clap::{Parser, Subcommand};
+use roles_logic_sv2::parsers::{AnyMessage, IsSv2Message};
+use serde_json::Value;
+use std::{convert::TryInto, process::exit};
+
+/// Tried in this order when the name of a message is not prefixed by its subprotocol
+const SUBPROTOCOLS: [&str; 4] = ["Common", "Mining", "JobDeclaration", "TemplateDistribution"];
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Prints the message type and the hex payload of a message
+    Encode {
+        #[arg(
+            help = "Name of the message, e.g. SetupConnection, prefix it with the subprotocol when it is not unique, e.g. TemplateDistribution::SetNewPrevHash"
+        )]
+        name: String,
+        #[arg(help = "Fields of the message as a JSON object")]
+        fields: String,
+    },
+    /// Prints the name and the fields as JSON of a message
+    Decode {
+        #[arg(help = "Message type, decimal or hex with the 0x prefix")]
+        message_type: String,
+        #[arg(help = "Hex payload of the message, without the frame header")]
+        payload: String,
+    },
+}
+
+fn encode(name: &str, fields: &str) -> Result<(u8, Vec<u8>), String> {
+    let (subprotocols, name) = match name.split_once("::") {
+        Some((subprotocol, name)) => (vec![subprotocol], name),
+        None => (SUBPROTOCOLS.to_vec(), name),
+    };
+    let mut error = format!("Unknown message {}", name);
+    for subprotocol in subprotocols {
+        let json = format!(r#"{{"{}":{{"{}":{}}}}}"#, subprotocol, name, fields);
+        match serde_json::from_str::<AnyMessage>(&json) {
+            Ok(message) => {
+                let payload = binary_sv2::to_bytes(&message).map_err(|e| format!("{:?}", e))?;
+                return Ok((message.message_type(), payload));
+            }
+            // The subprotocol has a message with this name but the fields are not valid
+            Err(e) if !e.to_string().contains("unknown variant") => error = e.to_string(),
+            Err(_) => (),
+        }
+    }
+    Err(error)
+}
+
+/// Returns the key and the value of an object with a single entry
+fn single_entry(value: Value) -> Option<(String, Value)> {
+    match value {
+        Value::Object(map) if map.len() == 1 => map.into_iter().next(),
+        _ => None,
+    }
+}
+
+fn decode(message_type: u8, payload: &mut [u8]) -> Result<(String, Value), String> {
+    let message: AnyMessage = (message_type, payload)
+        .try_into()
+        .map_err(|e: roles_logic_sv2::Error| e.to_string())?;
+    let value = serde_json::to_value(&message).map_err(|e| e.to_string())?;
+    // e.g. {"Mining": {"SetTarget": {..fields..}}}
+    let (subprotocol, message) = single_entry(value).ok_or("Unexpected JSON message")?;
+    let (name, fields) = single_entry(message).ok_or("Unexpected JSON message")?;
+    Ok((format!("{}::{}", subprotocol, name), fields))
+}
+
+fn parse_message_type(message_type: &str) -> Result<u8, String> {
+    match message_type.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => message_type.parse(),
+    }
+    .map_err(|e| format!("Invalid message type {}: {}", message_type, e))
+}
+
+fn run(command: Command) -> Result<String, String> {
+    match command {
+        Command::Encode { name, fields } => {
+            let (message_type, payload) = encode(&name, &fields)?;
+            Ok(format!("0x{:02x} {}", message_type, hex::encode(payload)))
+        }
+        Command::Decode {
+            message_type,
+            payload,
+        } => {
+            let message_type = parse_message_type(&message_type)?;
+            let mut payload = hex::decode(payload.trim_start_matches("0x"))
+                .map_err(|e| format!("Invalid hex payload: {}", e))?;
+            let (name, fields) = decode(message_type, &mut payload)?;
+            Ok(format!("{} {}", name, fields))
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    match run(args.command) {
+        Ok(output) => println!("{}", output),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let fields =
+            r#"{"channel_id":1,"sequence_number":2,"job_id":3,"nonce":4,"ntime":5,"version":6}"#;
+        let (message_type, mut payload) = encode("SubmitSharesStandard", fields).unwrap();
+        assert_eq!(message_type, 0x1a);
+        assert_eq!(
+            hex::encode(&payload),
+            "010000000200000003000000040000000500000006000000"
+        );
+        let (name, decoded) = decode(message_type, &mut payload).unwrap();
+        assert_eq!(name, "Mining::SubmitSharesStandard");
+        assert_eq!(decoded, serde_json::from_str::<Value>(fields).unwrap());
+    }
+
+    #[test]
+    fn test_subprotocol_prefix() {
+        let fields = r#"{"template_id":1,"prev_hash":[1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1],"header_timestamp":2,"n_bits":3,"target":[2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2]}"#;
+        let (message_type, mut payload) =
+            encode("TemplateDistribution::SetNewPrevHash", fields).unwrap();
+        assert_eq!(message_type, 0x72);
+        let (name, _) = decode(message_type, &mut payload).unwrap();
+        assert_eq!(name, "TemplateDistribution::SetNewPrevHash");
+
+        assert!(encode("Mining::SetupConnection", "{}")
+            .unwrap_err()
+            .contains("Unknown message"));
+        assert!(encode("SubmitSharesStandard", r#"{"channel_id":1}"#)
+            .unwrap_err()
+            .contains("missing field"));
+    }
+
+    #[test]
+    fn test_parse_message_type() {
+        assert_eq!(parse_message_type("0x1a"), Ok(0x1a));
+        assert_eq!(parse_message_type("26"), Ok(0x1a));
+        assert!(parse_message_type("0x100").is_err());
+    }
+}